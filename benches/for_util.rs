@@ -0,0 +1,78 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timings of the block encoding of the postings.
+//!
+//! Run with `cargo bench --bench for_util`.
+
+extern crate rucene;
+extern crate tempfile;
+
+use rucene::core::codec::postings::{ForUtil, BLOCK_SIZE, MAX_DATA_SIZE, MAX_ENCODED_SIZE};
+use rucene::core::store::directory::{Directory, FSDirectory};
+use rucene::core::store::IOContext;
+use rucene::core::util::packed::COMPACT;
+
+use std::time::Instant;
+
+fn random_blocks(count: usize) -> Vec<Vec<i32>> {
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    let mut next = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+    (0..count)
+        .map(|i| {
+            let bpv = i % 31 + 1;
+            let mut block = vec![0i32; MAX_DATA_SIZE];
+            for v in &mut block[..BLOCK_SIZE as usize] {
+                *v = (next() % (1u64 << bpv)) as i32;
+            }
+            block
+        })
+        .collect()
+}
+
+/// Compares writing blocks of random values with blocks of a single value, which are
+/// written as the value alone.
+fn bench_write_block() {
+    const ROUNDS: usize = 20_000;
+
+    let dir = tempfile::tempdir().unwrap();
+    let directory = FSDirectory::with_path(dir.path()).unwrap();
+    let mut encoded = [0u8; MAX_ENCODED_SIZE];
+    let random = random_blocks(64);
+    let constant = vec![vec![3i32; MAX_DATA_SIZE]; 64];
+    for &(name, ref blocks) in &[("random", &random), ("constant", &constant)] {
+        let mut output = directory.create_output(name, &IOContext::Default).unwrap();
+        let for_util = ForUtil::with_output(COMPACT, &mut output).unwrap();
+        let start = Instant::now();
+        for i in 0..ROUNDS {
+            let block = &blocks[i % blocks.len()];
+            for_util
+                .write_block(block, &mut encoded, &mut output, None, false)
+                .unwrap();
+        }
+        println!(
+            "write_block over {} blocks: {} ns per block",
+            name,
+            start.elapsed().as_nanos() / ROUNDS as u128
+        );
+    }
+}
+
+fn main() {
+    bench_write_block();
+}
@@ -0,0 +1,272 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timings of the searcher over on-disk indexes.
+//!
+//! Run with `cargo bench --bench search`, the indexes are built under `TMPDIR`.
+
+extern crate libc;
+extern crate num_cpus;
+extern crate rucene;
+extern crate tempfile;
+
+use rucene::core::analysis::WhitespaceTokenizer;
+use rucene::core::codec::{Codec, CodecEnum};
+use rucene::core::doc::{Field, FieldType, Fieldable, IndexOptions, Term};
+use rucene::core::index::merge::{SerialMergeScheduler, TieredMergePolicy};
+use rucene::core::index::reader::{IndexReader, LeafReaderContext, StandardDirectoryReader};
+use rucene::core::index::writer::{IndexWriter, IndexWriterConfig};
+use rucene::core::search::collector::TopDocsCollector;
+use rucene::core::search::query::{Query, TermQuery, Weight};
+use rucene::core::search::scorer::Scorer;
+use rucene::core::search::{DefaultIndexSearcher, Explanation, IndexSearcher, SearchPlanBuilder};
+use rucene::core::store::directory::{FSDirectory, MmapDirectory};
+use rucene::core::util::external::ThreadPoolBuilder;
+use rucene::core::util::DocId;
+use rucene::error::Result;
+
+use std::any::Any;
+use std::fmt;
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+type MmapReader =
+    StandardDirectoryReader<MmapDirectory, CodecEnum, SerialMergeScheduler, TieredMergePolicy>;
+
+fn text_doc(text: &str) -> Vec<Box<dyn Fieldable>> {
+    let mut field_type = FieldType::default();
+    field_type.index_options = IndexOptions::DocsAndFreqs;
+    let tokens = WhitespaceTokenizer::new(Box::new(Cursor::new(text.as_bytes().to_vec())));
+    vec![Box::new(Field::new(
+        "body".into(),
+        field_type,
+        None,
+        Some(Box::new(tokens)),
+    ))]
+}
+
+fn build_text_index(path: &Path, docs: impl Iterator<Item = String>) {
+    let config = Arc::new(IndexWriterConfig::default());
+    let directory = Arc::new(MmapDirectory::new(&path).unwrap());
+    let writer = IndexWriter::new(directory, config).unwrap();
+    for text in docs {
+        writer.add_document(text_doc(&text)).unwrap();
+    }
+    writer.commit().unwrap();
+}
+
+fn build_segmented_index(path: &Path, num_segments: usize, docs_per_segment: usize) {
+    let mut config = IndexWriterConfig::default();
+    // keep all the segments apart
+    config.merge_policy.set_segs_per_tier(1000.0).unwrap();
+    let directory = Arc::new(FSDirectory::with_path(path).unwrap());
+    let writer = IndexWriter::new(directory, Arc::new(config)).unwrap();
+    for segment in 0..num_segments {
+        for i in 0..docs_per_segment {
+            let text = format!("common w{} s{}", i % 3, segment % 4);
+            writer.add_document(text_doc(&text)).unwrap();
+        }
+        writer.commit().unwrap();
+    }
+}
+
+/// Compares `search_parallel` with `search_leaves_parallel` over many small segments.
+fn bench_search_leaves_parallel() {
+    const ROUNDS: u128 = 20;
+
+    let dir = tempfile::tempdir().unwrap();
+    build_segmented_index(dir.path(), 100, 2_000);
+    let reader = MmapReader::open(Arc::new(MmapDirectory::new(&dir.path()).unwrap())).unwrap();
+    assert_eq!(reader.leaves().len(), 100);
+    let mut searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+    searcher.with_thread_pool(num_cpus::get());
+    let pool = ThreadPoolBuilder::with_default_factory("search".into())
+        .thread_count(num_cpus::get())
+        .build();
+    let query = TermQuery::new(Term::new("body".into(), b"common".to_vec()), 1.0, None);
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search_parallel(&query, &mut collector).unwrap();
+        assert_eq!(collector.top_docs().total_hits(), 200_000);
+    }
+    println!(
+        "search_parallel: {} us per search",
+        start.elapsed().as_micros() / ROUNDS
+    );
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let top_docs = searcher
+            .search_leaves_parallel(&query, || TopDocsCollector::new(10), &pool)
+            .unwrap();
+        assert_eq!(top_docs.total_hits(), 200_000);
+    }
+    println!(
+        "search_leaves_parallel: {} us per search",
+        start.elapsed().as_micros() / ROUNDS
+    );
+}
+
+// hides the `TermWeight` of a `TermQuery`, so that it is scored through a boxed scorer
+struct BoxedScorerQuery(TermQuery);
+
+impl<C: Codec> Query<C> for BoxedScorerQuery {
+    fn create_weight(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        let weight = self.0.create_weight(searcher, needs_scores)?;
+        Ok(Box::new(BoxedScorerWeight(weight)))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        Query::<C>::extract_terms(&self.0)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl fmt::Display for BoxedScorerQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BoxedScorerQuery({})", self.0)
+    }
+}
+
+struct BoxedScorerWeight<C: Codec>(Box<dyn Weight<C>>);
+
+impl<C: Codec> Weight<C> for BoxedScorerWeight<C> {
+    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        self.0.create_scorer(reader)
+    }
+
+    fn query_type(&self) -> &'static str {
+        self.0.query_type()
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.0.normalize(norm, boost)
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.0.value_for_normalization()
+    }
+
+    fn needs_scores(&self) -> bool {
+        self.0.needs_scores()
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        self.0.explain(reader, doc)
+    }
+}
+
+impl<C: Codec> fmt::Display for BoxedScorerWeight<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BoxedScorerWeight({})", self.0)
+    }
+}
+
+/// Compares a single term query scored through the concrete `TermScorer` with the same
+/// query scored through a boxed scorer.
+fn bench_term_scorer_fast_path() {
+    const ROUNDS: u128 = 20;
+
+    let dir = tempfile::tempdir().unwrap();
+    build_segmented_index(dir.path(), 4, 100_000);
+    let reader = MmapReader::open(Arc::new(MmapDirectory::new(&dir.path()).unwrap())).unwrap();
+    let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+    let term = Term::new("body".into(), b"common".to_vec());
+    let term_query = TermQuery::new(term.clone(), 1.0, None);
+    let boxed_query = BoxedScorerQuery(TermQuery::new(term, 1.0, None));
+
+    for &(name, query) in &[
+        ("term scorer", &term_query as &dyn Query<CodecEnum>),
+        ("boxed scorer", &boxed_query as &dyn Query<CodecEnum>),
+    ] {
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            let mut collector = TopDocsCollector::new(10);
+            searcher.search(query, &mut collector).unwrap();
+            assert_eq!(collector.top_docs().total_hits(), 400_000);
+        }
+        println!(
+            "{}: {} ns per doc",
+            name,
+            start.elapsed().as_nanos() / (ROUNDS * 400_000)
+        );
+    }
+}
+
+// drop the clean pages of the index files, so that the next search reads from disk.
+#[cfg(unix)]
+fn evict_page_cache(path: &Path) {
+    use std::os::unix::io::AsRawFd;
+
+    for entry in ::std::fs::read_dir(path).unwrap() {
+        let file = ::std::fs::File::open(entry.unwrap().path()).unwrap();
+        unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+    }
+}
+
+/// Compares cold-cache searches with and without postings prefetch on a mmap directory,
+/// preferably run against a spinning disk or network volume by setting `TMPDIR`, where
+/// the read-ahead hint matters the most.
+#[cfg(unix)]
+fn bench_prefetch_postings_cold_cache() {
+    const NUM_DOCS: usize = 500_000;
+    const ROUNDS: usize = 5;
+
+    let dir = tempfile::tempdir().unwrap();
+    let docs = (0..NUM_DOCS).map(|i| format!("common w{} w{}", i % 7, i % 1_000));
+    build_text_index(dir.path(), docs);
+
+    let query = TermQuery::new(Term::new("body".into(), b"common".to_vec()), 1.0, None);
+    for &prefetch in &[false, true] {
+        let mut total = 0u128;
+        for _ in 0..ROUNDS {
+            // a fresh directory, so nothing stays mapped from the previous round
+            let directory = Arc::new(MmapDirectory::new(&dir.path()).unwrap());
+            let reader = MmapReader::open(directory).unwrap();
+            let mut searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+            searcher.set_prefetch_postings(prefetch);
+            evict_page_cache(dir.path());
+
+            let start = Instant::now();
+            let mut collector = TopDocsCollector::new(10);
+            searcher.search(&query, &mut collector).unwrap();
+            total += start.elapsed().as_micros();
+            assert_eq!(collector.top_docs().total_hits() as usize, NUM_DOCS);
+        }
+        println!(
+            "prefetch_postings={}: {} us per cold search",
+            prefetch,
+            total / ROUNDS as u128
+        );
+    }
+}
+
+fn main() {
+    bench_search_leaves_parallel();
+    bench_term_scorer_fast_path();
+    #[cfg(unix)]
+    bench_prefetch_postings_cold_cache();
+}
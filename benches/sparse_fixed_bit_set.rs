@@ -0,0 +1,104 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Timings and memory of `SparseFixedBitSet` against `FixedBitSet` at several densities.
+//!
+//! Run with `cargo bench --bench sparse_fixed_bit_set`.
+//!
+//! The roaring doc id set of the query cache is private and only built once from sorted
+//! docs, and the crate doesn't depend on a roaring bitmap crate, so there is no roaring
+//! bitmap to compare with.
+
+extern crate rucene;
+
+use rucene::core::search::NO_MORE_DOCS;
+use rucene::core::util::{bits2words, BitSet, FixedBitSet, SparseFixedBitSet};
+
+use std::time::Instant;
+
+const NUM_BITS: usize = 1 << 22;
+
+fn random_bits(count: usize) -> Vec<usize> {
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    (0..count)
+        .map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % NUM_BITS as u64) as usize
+        })
+        .collect()
+}
+
+fn bench_bit_set<B: BitSet>(name: &str, mut bit_set: B, bits: &[usize], probes: &[usize]) -> B {
+    let start = Instant::now();
+    for &bit in bits {
+        bit_set.set(bit);
+    }
+    let set_nanos = start.elapsed().as_nanos() / bits.len() as u128;
+
+    let start = Instant::now();
+    let mut found = 0;
+    for &probe in probes {
+        if bit_set.get(probe).unwrap() {
+            found += 1;
+        }
+    }
+    let get_nanos = start.elapsed().as_nanos() / probes.len() as u128;
+
+    let start = Instant::now();
+    let mut count = 0;
+    let mut doc = bit_set.next_set_bit(0);
+    while doc != NO_MORE_DOCS {
+        count += 1;
+        if doc as usize + 1 >= NUM_BITS {
+            break;
+        }
+        doc = bit_set.next_set_bit(doc as usize + 1);
+    }
+    let iterate_nanos = start.elapsed().as_nanos() / count.max(1) as u128;
+
+    assert_eq!(count, bit_set.cardinality());
+    println!(
+        "  {:<18} set: {:>4} ns, get: {:>4} ns ({} found), next_set_bit: {:>4} ns",
+        name, set_nanos, get_nanos, found, iterate_nanos
+    );
+    bit_set
+}
+
+/// Sets random bits, probes random bits and iterates the set bits at densities from one
+/// bit in 4096 to one in 2.
+fn bench_densities() {
+    let probes = random_bits(1 << 20);
+    for &density in &[4096, 512, 64, 8, 2] {
+        let bits = random_bits(NUM_BITS / density);
+        println!("density 1/{}, {} bits set", density, bits.len());
+
+        let sparse = bench_bit_set(
+            "SparseFixedBitSet",
+            SparseFixedBitSet::new(NUM_BITS),
+            &bits,
+            &probes,
+        );
+        bench_bit_set("FixedBitSet", FixedBitSet::new(NUM_BITS), &bits, &probes);
+        println!(
+            "  memory: SparseFixedBitSet {} bytes, FixedBitSet {} bytes",
+            sparse.approximate_ram_bytes_used(),
+            bits2words(NUM_BITS) * 8
+        );
+    }
+}
+
+fn main() {
+    bench_densities();
+}
@@ -0,0 +1,180 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::analysis::{TokenStream, WhitespaceTokenizer};
+
+use error::Result;
+
+use std::io::Read;
+
+/// An Analyzer builds TokenStreams, which analyze text.
+pub trait Analyzer: Send + Sync {
+    /// Returns a `TokenStream` of the text read from `reader` for `field`
+    fn token_stream(&self, field: &str, reader: Box<dyn Read>) -> Result<Box<dyn TokenStream>>;
+
+    /// Normalizes a single term of a multi-term query (wildcard, prefix or range bound)
+    /// without tokenizing it.
+    ///
+    /// Only the filters which transform each token on its own, see `MultiTermAware`,
+    /// are applied. The default returns the text unchanged.
+    fn normalize(&self, _field: &str, text: &str) -> Result<Vec<u8>> {
+        Ok(text.as_bytes().to_vec())
+    }
+}
+
+/// A filter whose transformation only depends on the token itself (e.g. case folding),
+/// so it can also be applied to query text that must not be tokenized.
+pub trait MultiTermAware {
+    fn normalize(&self, term: &str) -> String;
+}
+
+/// Creates the `Tokenizer` of an analysis chain
+pub trait TokenizerFactory: Send + Sync {
+    fn create(&self, reader: Box<dyn Read>) -> Box<dyn TokenStream>;
+}
+
+/// Creates a `TokenFilter` of an analysis chain
+pub trait TokenFilterFactory: Send + Sync {
+    fn create(&self, input: Box<dyn TokenStream>) -> Box<dyn TokenStream>;
+
+    /// Returns `Some` if the filter is multi-term aware
+    fn as_multi_term_aware(&self) -> Option<&dyn MultiTermAware> {
+        None
+    }
+}
+
+pub struct WhitespaceTokenizerFactory;
+
+impl TokenizerFactory for WhitespaceTokenizerFactory {
+    fn create(&self, reader: Box<dyn Read>) -> Box<dyn TokenStream> {
+        Box::new(WhitespaceTokenizer::new(reader))
+    }
+}
+
+/// An `Analyzer` built from a tokenizer followed by a chain of token filters
+pub struct CustomAnalyzer {
+    tokenizer: Box<dyn TokenizerFactory>,
+    filters: Vec<Box<dyn TokenFilterFactory>>,
+}
+
+impl CustomAnalyzer {
+    pub fn new(
+        tokenizer: Box<dyn TokenizerFactory>,
+        filters: Vec<Box<dyn TokenFilterFactory>>,
+    ) -> CustomAnalyzer {
+        CustomAnalyzer { tokenizer, filters }
+    }
+}
+
+impl Analyzer for CustomAnalyzer {
+    fn token_stream(&self, _field: &str, reader: Box<dyn Read>) -> Result<Box<dyn TokenStream>> {
+        let mut stream = self.tokenizer.create(reader);
+        for filter in &self.filters {
+            stream = filter.create(stream);
+        }
+        Ok(stream)
+    }
+
+    fn normalize(&self, _field: &str, text: &str) -> Result<Vec<u8>> {
+        let mut term = text.to_string();
+        for filter in &self.filters {
+            if let Some(aware) = filter.as_multi_term_aware() {
+                term = aware.normalize(&term);
+            }
+        }
+        Ok(term.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::analysis::{ASCIIFoldingFilterFactory, LowerCaseFilterFactory};
+    use core::analysis::{StringTokenStream, Tokenizer};
+    use std::io::Cursor;
+
+    fn analyzer() -> CustomAnalyzer {
+        CustomAnalyzer::new(
+            Box::new(WhitespaceTokenizerFactory),
+            vec![
+                Box::new(LowerCaseFilterFactory),
+                Box::new(ASCIIFoldingFilterFactory),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_normalize_skips_tokenizer() {
+        let analyzer = analyzer();
+        assert_eq!(
+            analyzer.normalize("body", "CAFÉ").unwrap(),
+            b"cafe".to_vec()
+        );
+        // not split on whitespace, the text is a single term
+        assert_eq!(
+            analyzer.normalize("body", "Crème Brûlée*").unwrap(),
+            b"creme brulee*".to_vec()
+        );
+
+        let mut stream = analyzer
+            .token_stream(
+                "body",
+                Box::new(Cursor::new("Crème Brûlée*".as_bytes().to_vec())),
+            )
+            .unwrap();
+        let mut terms = vec![];
+        while stream.next_token().unwrap() {
+            terms.push(String::from_utf8(stream.token().term.clone()).unwrap());
+        }
+        assert_eq!(terms, vec!["creme", "brulee*"]);
+    }
+
+    fn consume(stream: &mut dyn TokenStream) -> Vec<(String, usize, usize)> {
+        stream.reset().unwrap();
+        let mut tokens = vec![];
+        while stream.next_token().unwrap() {
+            let token = stream.token();
+            let term = String::from_utf8(token.term.clone()).unwrap();
+            tokens.push((term, token.start_offset, token.end_offset));
+        }
+        stream.end().unwrap();
+        tokens
+    }
+
+    #[test]
+    fn test_reuse_token_stream() {
+        let analyzer = analyzer();
+        let mut stream = analyzer
+            .token_stream(
+                "body",
+                Box::new(Cursor::new("Hello Wörld  ".as_bytes().to_vec())),
+            )
+            .unwrap();
+        assert_eq!(
+            consume(stream.as_mut()),
+            vec![("hello".to_string(), 0, 5), ("world".to_string(), 6, 11)]
+        );
+
+        // the filters pass the new reader on to the tokenizer of the chain
+        stream
+            .tokenizer_mut()
+            .unwrap()
+            .set_reader(Box::new(Cursor::new(" Déjà".as_bytes().to_vec())));
+        assert_eq!(consume(stream.as_mut()), vec![("deja".to_string(), 1, 5)]);
+
+        let mut keyword = StringTokenStream::new("id".to_string());
+        assert!(keyword.tokenizer_mut().is_none());
+        keyword.set_value("other id");
+        assert_eq!(consume(&mut keyword), vec![("other id".to_string(), 0, 8)]);
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::analysis::{MultiTermAware, Token, TokenFilterFactory, TokenStream, Tokenizer};
+
+use error::Result;
+
+use std::str;
+
+/// Converts alphabetic characters of the Latin-1 Supplement and Latin Extended-A
+/// blocks into their ASCII equivalents, if one exists (e.g. `é` => `e`, `ß` => `ss`).
+///
+/// Characters without an equivalent are left unchanged.
+#[derive(Debug)]
+pub struct ASCIIFoldingFilter {
+    input: Box<dyn TokenStream>,
+}
+
+impl ASCIIFoldingFilter {
+    pub fn new(input: Box<dyn TokenStream>) -> Self {
+        ASCIIFoldingFilter { input }
+    }
+
+    /// Folds `text` into ASCII, see the struct doc.
+    pub fn fold_to_ascii(text: &str) -> String {
+        let mut folded = String::with_capacity(text.len());
+        for c in text.chars() {
+            match fold_char(c) {
+                Some(s) => folded.push_str(s),
+                None => folded.push(c),
+            }
+        }
+        folded
+    }
+}
+
+fn fold_char(c: char) -> Option<&'static str> {
+    if (c as u32) < 0x80 {
+        return None;
+    }
+    let folded = match c {
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' | 'Ā' | 'Ă' | 'Ą' => "A",
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'Æ' => "AE",
+        'æ' => "ae",
+        'Ç' | 'Ć' | 'Ĉ' | 'Ċ' | 'Č' => "C",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'Ð' | 'Ď' | 'Đ' => "D",
+        'ð' | 'ď' | 'đ' => "d",
+        'È' | 'É' | 'Ê' | 'Ë' | 'Ē' | 'Ĕ' | 'Ė' | 'Ę' | 'Ě' => "E",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'Ĝ' | 'Ğ' | 'Ġ' | 'Ģ' => "G",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'Ĥ' | 'Ħ' => "H",
+        'ĥ' | 'ħ' => "h",
+        'Ì' | 'Í' | 'Î' | 'Ï' | 'Ĩ' | 'Ī' | 'Ĭ' | 'Į' | 'İ' => "I",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'Ĳ' => "IJ",
+        'ĳ' => "ij",
+        'Ĵ' => "J",
+        'ĵ' => "j",
+        'Ķ' => "K",
+        'ķ' | 'ĸ' => "k",
+        'Ĺ' | 'Ļ' | 'Ľ' | 'Ŀ' | 'Ł' => "L",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'Ñ' | 'Ń' | 'Ņ' | 'Ň' | 'Ŋ' => "N",
+        'ñ' | 'ń' | 'ņ' | 'ň' | 'ŉ' | 'ŋ' => "n",
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' | 'Ø' | 'Ō' | 'Ŏ' | 'Ő' => "O",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'Œ' => "OE",
+        'œ' => "oe",
+        'Ŕ' | 'Ŗ' | 'Ř' => "R",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'Ś' | 'Ŝ' | 'Ş' | 'Š' => "S",
+        'ś' | 'ŝ' | 'ş' | 'š' | 'ſ' => "s",
+        'ß' => "ss",
+        'Ţ' | 'Ť' | 'Ŧ' => "T",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'Þ' => "TH",
+        'þ' => "th",
+        'Ù' | 'Ú' | 'Û' | 'Ü' | 'Ũ' | 'Ū' | 'Ŭ' | 'Ů' | 'Ű' | 'Ų' => "U",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'Ŵ' => "W",
+        'ŵ' => "w",
+        'Ý' | 'Ŷ' | 'Ÿ' => "Y",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'Ź' | 'Ż' | 'Ž' => "Z",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    };
+    Some(folded)
+}
+
+impl TokenStream for ASCIIFoldingFilter {
+    fn next_token(&mut self) -> Result<bool> {
+        if !self.input.next_token()? {
+            return Ok(false);
+        }
+        let token = self.input.token_mut();
+        // fast path, pure ASCII terms are left as is
+        if token.term.iter().any(|b| *b >= 0x80) {
+            if let Ok(term) = str::from_utf8(&token.term) {
+                let folded = ASCIIFoldingFilter::fold_to_ascii(term);
+                token.term = folded.into_bytes();
+            }
+        }
+        Ok(true)
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.input.end()
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.input.reset()
+    }
+
+    fn token(&self) -> &Token {
+        self.input.token()
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.input.token_mut()
+    }
+
+    fn tokenizer_mut(&mut self) -> Option<&mut dyn Tokenizer> {
+        self.input.tokenizer_mut()
+    }
+}
+
+pub struct ASCIIFoldingFilterFactory;
+
+impl TokenFilterFactory for ASCIIFoldingFilterFactory {
+    fn create(&self, input: Box<dyn TokenStream>) -> Box<dyn TokenStream> {
+        Box::new(ASCIIFoldingFilter::new(input))
+    }
+
+    fn as_multi_term_aware(&self) -> Option<&dyn MultiTermAware> {
+        Some(self)
+    }
+}
+
+impl MultiTermAware for ASCIIFoldingFilterFactory {
+    fn normalize(&self, term: &str) -> String {
+        ASCIIFoldingFilter::fold_to_ascii(term)
+    }
+}
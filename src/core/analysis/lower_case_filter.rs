@@ -0,0 +1,83 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::analysis::{MultiTermAware, Token, TokenFilterFactory, TokenStream, Tokenizer};
+
+use error::Result;
+
+use std::str;
+
+/// Normalizes token text to lower case.
+#[derive(Debug)]
+pub struct LowerCaseFilter {
+    input: Box<dyn TokenStream>,
+}
+
+impl LowerCaseFilter {
+    pub fn new(input: Box<dyn TokenStream>) -> Self {
+        LowerCaseFilter { input }
+    }
+}
+
+impl TokenStream for LowerCaseFilter {
+    fn next_token(&mut self) -> Result<bool> {
+        if !self.input.next_token()? {
+            return Ok(false);
+        }
+        let token = self.input.token_mut();
+        let lowered = match str::from_utf8(&token.term) {
+            Ok(term) => term.to_lowercase(),
+            Err(_) => return Ok(true),
+        };
+        token.term = lowered.into_bytes();
+        Ok(true)
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.input.end()
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.input.reset()
+    }
+
+    fn token(&self) -> &Token {
+        self.input.token()
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.input.token_mut()
+    }
+
+    fn tokenizer_mut(&mut self) -> Option<&mut dyn Tokenizer> {
+        self.input.tokenizer_mut()
+    }
+}
+
+pub struct LowerCaseFilterFactory;
+
+impl TokenFilterFactory for LowerCaseFilterFactory {
+    fn create(&self, input: Box<dyn TokenStream>) -> Box<dyn TokenStream> {
+        Box::new(LowerCaseFilter::new(input))
+    }
+
+    fn as_multi_term_aware(&self) -> Option<&dyn MultiTermAware> {
+        Some(self)
+    }
+}
+
+impl MultiTermAware for LowerCaseFilterFactory {
+    fn normalize(&self, term: &str) -> String {
+        term.to_lowercase()
+    }
+}
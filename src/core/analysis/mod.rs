@@ -11,6 +11,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod analyzer;
+
+pub use self::analyzer::*;
+
+mod ascii_folding_filter;
+
+pub use self::ascii_folding_filter::*;
+
+mod lower_case_filter;
+
+pub use self::lower_case_filter::*;
+
+mod synonym_graph_filter;
+
+pub use self::synonym_graph_filter::*;
+
 mod token_stream;
 
 pub use self::token_stream::*;
@@ -22,6 +38,7 @@ pub use self::whitespace_tokenizer::*;
 use error::Result;
 
 use std::fmt::Debug;
+use std::io::Read;
 
 pub const MIN_BUFFER_SIZE: usize = 10;
 pub const MAX_WORD_LEN: usize = 128;
@@ -50,6 +67,10 @@ pub struct Token {
     /// non-stop word.  Then exact phrase queries will only match when the terms
     /// occur with no intervening stop words.
     pub position: usize,
+    /// The number of positions this token spans, one unless the stream is a token graph,
+    /// e.g. a synonym of several terms emitted next to the term it stands for spans all
+    /// the positions of the synonym.
+    pub position_length: usize,
     /// The start and end character offset of a Token.
     pub start_offset: usize,
     pub end_offset: usize,
@@ -69,6 +90,7 @@ impl Token {
         Token {
             term: Vec::with_capacity(MIN_BUFFER_SIZE),
             position: 1,
+            position_length: 1,
             start_offset: 0,
             end_offset: 0,
             payload: Vec::with_capacity(0),
@@ -77,6 +99,7 @@ impl Token {
 
     pub fn clear(&mut self) {
         self.position = 1;
+        self.position_length = 1;
         self.start_offset = 0;
         self.end_offset = 0;
         self.payload.clear();
@@ -170,8 +193,13 @@ pub trait TokenStream: Debug {
     /// can be applied to the position increment, or any adjustment of other
     /// attributes where the end-of-stream value may be important.
     ///
-    /// If you override this method, always call {@code super.end()}.
-    fn end(&mut self) -> Result<()>;
+    /// The default ends the token (`end_token`), so the consumer doesn't add the position
+    /// and offsets of the last token once more. If you override this method, always call
+    /// `end_token`.
+    fn end(&mut self) -> Result<()> {
+        self.end_token();
+        Ok(())
+    }
 
     /// This method is called by a consumer before it begins consumption using
     /// {@link #incrementToken()}.
@@ -201,4 +229,19 @@ pub trait TokenStream: Debug {
     fn end_token(&mut self) {
         self.token_mut().end();
     }
+
+    /// Returns the `Tokenizer` this stream reads its tokens from, if any.
+    ///
+    /// Token filters return the tokenizer of their input, so a whole analysis chain can
+    /// be pointed to a new reader and reused instead of being built again.
+    fn tokenizer_mut(&mut self) -> Option<&mut dyn Tokenizer> {
+        None
+    }
+}
+
+/// A `TokenStream` whose input is a reader, the source of an analysis chain.
+pub trait Tokenizer: TokenStream {
+    /// Replaces the reader of this tokenizer. As for a new stream, the consumer must
+    /// call `reset` before the tokens of `reader` are consumed.
+    fn set_reader(&mut self, reader: Box<dyn Read>);
 }
@@ -0,0 +1,229 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::analysis::{Token, TokenFilterFactory, TokenStream, Tokenizer};
+
+use error::Result;
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// The synonyms of single terms, a synonym may be made of several terms.
+#[derive(Debug, Default)]
+pub struct SynonymMap {
+    synonyms: HashMap<Vec<u8>, Vec<Vec<Vec<u8>>>>,
+}
+
+impl SynonymMap {
+    pub fn new() -> SynonymMap {
+        SynonymMap::default()
+    }
+
+    /// Adds `synonym` as a synonym of `term`, the terms of `synonym` are separated by
+    /// whitespace.
+    pub fn add(&mut self, term: &str, synonym: &str) {
+        let terms: Vec<Vec<u8>> = synonym
+            .split_whitespace()
+            .map(|t| t.as_bytes().to_vec())
+            .collect();
+        if !terms.is_empty() {
+            self.synonyms
+                .entry(term.as_bytes().to_vec())
+                .or_insert_with(Vec::new)
+                .push(terms);
+        }
+    }
+
+    pub fn get(&self, term: &[u8]) -> Option<&[Vec<Vec<u8>>]> {
+        self.synonyms.get(term).map(Vec::as_slice)
+    }
+}
+
+/// Emits the synonyms of every token next to it as a token graph, meant for query time
+/// analysis.
+///
+/// All the synonyms of a token start at its position and end at the same position, each
+/// multi-term synonym getting positions of its own in between. The original token is
+/// given the position length of the whole graph, so `ny` with the synonym `new york`
+/// gives `ny` spanning the positions of `new` and `york`.
+#[derive(Debug)]
+pub struct SynonymGraphFilter {
+    input: Box<dyn TokenStream>,
+    synonyms: Arc<SynonymMap>,
+    token: Token,
+    pending: VecDeque<Token>,
+}
+
+impl SynonymGraphFilter {
+    pub fn new(input: Box<dyn TokenStream>, synonyms: Arc<SynonymMap>) -> Self {
+        SynonymGraphFilter {
+            input,
+            synonyms,
+            token: Token::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn push_graph(&mut self, synonyms: &[Vec<Vec<u8>>]) {
+        let inner_positions: usize = synonyms.iter().map(|s| s.len() - 1).sum();
+        let end = inner_positions + 1;
+        // the start and end position of the tokens, relative to the original token
+        let mut tokens = vec![];
+        let mut next_position = 1;
+        for synonym in synonyms {
+            let mut start = 0;
+            for (i, term) in synonym.iter().enumerate() {
+                let stop = if i + 1 == synonym.len() {
+                    end
+                } else {
+                    next_position += 1;
+                    next_position - 1
+                };
+                tokens.push((term, start, stop));
+                start = stop;
+            }
+        }
+        // tokens are emitted in the order of their start position
+        tokens.sort_by_key(|&(_, start, _)| start);
+
+        self.token.position_length = end;
+        let mut last_start = 0;
+        for (term, start, stop) in tokens {
+            let mut token = self.token.clone();
+            token.term.clear();
+            token.term.extend_from_slice(term);
+            token.position = start - last_start;
+            token.position_length = stop - start;
+            self.pending.push_back(token);
+            last_start = start;
+        }
+    }
+}
+
+impl TokenStream for SynonymGraphFilter {
+    fn next_token(&mut self) -> Result<bool> {
+        if let Some(token) = self.pending.pop_front() {
+            self.token = token;
+            return Ok(true);
+        }
+        if !self.input.next_token()? {
+            return Ok(false);
+        }
+        self.token.clone_from(self.input.token());
+        let synonyms = Arc::clone(&self.synonyms);
+        if let Some(synonyms) = synonyms.get(&self.token.term) {
+            self.push_graph(synonyms);
+        }
+        Ok(true)
+    }
+
+    fn end(&mut self) -> Result<()> {
+        self.input.end()?;
+        self.token.clone_from(self.input.token());
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.pending.clear();
+        self.input.reset()
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+
+    fn tokenizer_mut(&mut self) -> Option<&mut dyn Tokenizer> {
+        self.input.tokenizer_mut()
+    }
+}
+
+pub struct SynonymGraphFilterFactory {
+    synonyms: Arc<SynonymMap>,
+}
+
+impl SynonymGraphFilterFactory {
+    pub fn new(synonyms: Arc<SynonymMap>) -> Self {
+        SynonymGraphFilterFactory { synonyms }
+    }
+}
+
+impl TokenFilterFactory for SynonymGraphFilterFactory {
+    fn create(&self, input: Box<dyn TokenStream>) -> Box<dyn TokenStream> {
+        Box::new(SynonymGraphFilter::new(input, Arc::clone(&self.synonyms)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::analysis::WhitespaceTokenizer;
+    use std::io::Cursor;
+
+    fn graph(text: &str, synonyms: SynonymMap) -> Vec<(String, usize, usize)> {
+        let tokenizer = WhitespaceTokenizer::new(Box::new(Cursor::new(text.as_bytes().to_vec())));
+        let mut stream = SynonymGraphFilter::new(Box::new(tokenizer), Arc::new(synonyms));
+        stream.reset().unwrap();
+        let mut tokens = vec![];
+        while stream.next_token().unwrap() {
+            let token = stream.token();
+            let term = String::from_utf8(token.term.clone()).unwrap();
+            tokens.push((term, token.position, token.position_length));
+        }
+        tokens
+    }
+
+    fn token(term: &str, position: usize, position_length: usize) -> (String, usize, usize) {
+        (term.to_string(), position, position_length)
+    }
+
+    #[test]
+    fn test_synonym_graph() {
+        let mut synonyms = SynonymMap::new();
+        synonyms.add("ny", "new york");
+        synonyms.add("car", "automobile");
+        assert_eq!(
+            graph("the ny times", synonyms),
+            vec![
+                token("the", 1, 1),
+                token("ny", 1, 2),
+                token("new", 0, 1),
+                token("york", 1, 1),
+                token("times", 1, 1),
+            ]
+        );
+
+        // every multi-term synonym gets positions of its own between the start and the
+        // end of the graph
+        let mut synonyms = SynonymMap::new();
+        synonyms.add("ny", "new york");
+        synonyms.add("ny", "big apple city");
+        synonyms.add("ny", "nyc");
+        assert_eq!(
+            graph("ny times", synonyms),
+            vec![
+                token("ny", 1, 4),
+                token("new", 0, 1),
+                token("big", 0, 2),
+                token("nyc", 0, 4),
+                token("york", 1, 3),
+                token("apple", 1, 1),
+                token("city", 1, 1),
+                token("times", 1, 1),
+            ]
+        );
+    }
+}
@@ -33,6 +33,14 @@ impl StringTokenStream {
             value,
         }
     }
+
+    /// Replaces the value of the stream, reusing its buffers. As for a new stream, the
+    /// stream must be `reset` before the token is consumed.
+    pub fn set_value(&mut self, value: &str) {
+        self.value.clear();
+        self.value.push_str(value);
+        self.used = true;
+    }
 }
 
 impl TokenStream for StringTokenStream {
@@ -42,7 +50,7 @@ impl TokenStream for StringTokenStream {
         }
 
         self.clear_token();
-        self.token.term = self.value.as_bytes().to_vec();
+        self.token.term.extend_from_slice(self.value.as_bytes());
         self.token.set_offset(0, self.value.len())?;
 
         self.used = true;
@@ -11,7 +11,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::analysis::{Token, TokenStream};
+use core::analysis::{Token, TokenStream, Tokenizer};
 
 use error::Result;
 
@@ -132,8 +132,10 @@ impl TokenStream for WhitespaceTokenizer {
 
     fn end(&mut self) -> Result<()> {
         self.end_token();
-
-        Ok(())
+        // the final offset includes the trailing whitespaces, so the offsets of the next
+        // value of a multi-valued field start after them
+        let final_offset = self.final_offset;
+        self.token.set_offset(final_offset, final_offset)
     }
 
     fn reset(&mut self) -> Result<()> {
@@ -152,6 +154,16 @@ impl TokenStream for WhitespaceTokenizer {
     fn token_mut(&mut self) -> &mut Token {
         &mut self.token
     }
+
+    fn tokenizer_mut(&mut self) -> Option<&mut dyn Tokenizer> {
+        Some(self)
+    }
+}
+
+impl Tokenizer for WhitespaceTokenizer {
+    fn set_reader(&mut self, reader: Box<dyn Read>) {
+        self.reader = reader;
+    }
 }
 
 /// a simple IO buffer to use
@@ -243,4 +255,17 @@ mod tests {
             assert_eq!(tokenizer.token().term.as_slice(), words[i].as_bytes());
         }
     }
+
+    #[test]
+    fn test_end_sets_final_offset() {
+        let reader = Box::new(BufReader::new("  quick fox  ".as_bytes()));
+        let mut tokenizer = WhitespaceTokenizer::new(reader);
+        tokenizer.reset().unwrap();
+        while tokenizer.next_token().unwrap() {}
+        tokenizer.end().unwrap();
+        assert_eq!(tokenizer.token().start_offset, 13);
+        assert_eq!(tokenizer.token().end_offset, 13);
+        assert_eq!(tokenizer.token().position, 0);
+        assert!(tokenizer.token().term.is_empty());
+    }
 }
@@ -1852,3 +1852,65 @@ impl NumericDocValues for PackedLongValuesWrapper {
         self.get64(i64::from(doc_id))
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use core::doc::{Fieldable, NumericDocValuesField};
+    use core::index::reader::{IndexReader, LeafReader};
+    use core::test_util::new_fs_writer;
+
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::Path;
+
+    fn dvd_files(path: &Path) -> HashSet<(String, u64)> {
+        fs::read_dir(path)
+            .unwrap()
+            .map(|e| e.unwrap())
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".dvd"))
+            .map(|e| {
+                let name = e.file_name().to_string_lossy().into_owned();
+                (name, e.metadata().unwrap().len())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_recomputes_numeric_encoding() {
+        let (dir, writer) = new_fs_writer();
+
+        // 150 distinct multiples of 1000 per segment, so each flushed segment is small
+        // enough for table compression, while the 300 merged values are not
+        let value = |i: i64| 7 + i * 1000;
+        for range in &[0..150, 150..300] {
+            for i in range.clone() {
+                let field: Box<dyn Fieldable> = Box::new(NumericDocValuesField::new("v", value(i)));
+                writer.add_document(vec![field]).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+        let flushed = dvd_files(dir.path());
+        assert_eq!(flushed.len(), 2);
+
+        writer.force_merge(1, true).unwrap();
+        writer.commit().unwrap();
+
+        let merged: Vec<_> = dvd_files(dir.path())
+            .into_iter()
+            .filter(|f| !flushed.contains(f))
+            .collect();
+        assert_eq!(merged.len(), 1);
+        // GCD compression needs 9 bits (rounded up to 12) per value, while delta
+        // compression of the same values would need 19 bits (rounded up to 20)
+        assert!(merged[0].1 < 300 * 20 / 8);
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 1);
+        let dv = leaves[0].reader.get_numeric_doc_values("v").unwrap();
+        let mut values: Vec<i64> = (0..300).map(|doc| dv.get(doc).unwrap()).collect();
+        values.sort();
+        assert_eq!(values, (0..300).map(value).collect::<Vec<_>>());
+    }
+}
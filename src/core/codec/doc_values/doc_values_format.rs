@@ -17,8 +17,8 @@ use core::codec::doc_values::{
     SortedSetDocValues,
 };
 use core::codec::doc_values::{
-    BinaryDocValuesProvider, DocValuesProducer, NumericDocValuesProvider, SortedDocValuesProvider,
-    SortedNumericDocValuesProvider, SortedSetDocValuesProvider,
+    BinaryDocValuesProvider, DocValuesProducer, NumericDocValuesProvider, NumericEncoding,
+    SortedDocValuesProvider, SortedNumericDocValuesProvider, SortedSetDocValuesProvider,
 };
 use core::codec::field_infos::FieldInfo;
 use core::codec::segment_infos::{SegmentReadState, SegmentWriteState};
@@ -438,6 +438,12 @@ impl DocValuesProducer for DocValuesFieldsReader {
     fn get_merge_instance(&self) -> Result<Box<dyn DocValuesProducer>> {
         Ok(Box::new(DocValuesFieldsReader::copy_for_merge(self)?))
     }
+
+    fn numeric_encoding(&self, field: &FieldInfo) -> Option<NumericEncoding> {
+        self.fields
+            .get(&field.name)
+            .and_then(|producer| producer.numeric_encoding(field))
+    }
 }
 
 struct ConsumerAndSuffix<D: Directory, DW: Directory, C: Codec> {
@@ -19,8 +19,11 @@ use core::doc::DocValuesType;
 use core::index::reader::LeafReaderContext;
 use core::search::DocIterator;
 use core::search::NO_MORE_DOCS;
-use core::util::DocId;
-use error::Result;
+use core::util::{BitsMut, DocId};
+use error::{
+    ErrorKind::{IllegalArgument, IllegalState},
+    Result,
+};
 
 pub struct DocValuesIterator {
     cost: i32,
@@ -154,3 +157,126 @@ impl DocIterator for DocValuesIterator {
         self.cost as usize
     }
 }
+
+/// Forward-only access to a numeric doc values field, for fields that only a few
+/// documents have a value for.
+///
+/// `advance_exact` positions the iterator on a doc and tells whether it has a value,
+/// which `long_value` then returns. Targets must not go backwards.
+pub struct NumericDocValuesIterator {
+    values: Box<dyn NumericDocValues>,
+    docs_with_field: Box<dyn BitsMut>,
+    max_doc: DocId,
+    doc: DocId,
+    has_value: bool,
+}
+
+impl NumericDocValuesIterator {
+    pub fn new(
+        values: Box<dyn NumericDocValues>,
+        docs_with_field: Box<dyn BitsMut>,
+        max_doc: DocId,
+    ) -> Self {
+        NumericDocValuesIterator {
+            values,
+            docs_with_field,
+            max_doc,
+            doc: -1,
+            has_value: false,
+        }
+    }
+
+    /// Creates an iterator over the numeric doc values of `field` in the given leaf.
+    pub fn from_leaf<C: Codec>(
+        leaf_reader: &LeafReaderContext<'_, C>,
+        field: &str,
+    ) -> Result<Self> {
+        let values = leaf_reader.reader.get_numeric_doc_values(field)?;
+        let docs_with_field = leaf_reader.reader.get_docs_with_field(field)?;
+        Ok(Self::new(
+            values,
+            docs_with_field,
+            leaf_reader.reader.max_doc(),
+        ))
+    }
+
+    /// The doc the iterator is positioned on, -1 before the first `advance_exact`.
+    pub fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    /// Moves to `target` and returns whether it has a value.
+    ///
+    /// `target` must be in `[doc_id(), max_doc)`.
+    pub fn advance_exact(&mut self, target: DocId) -> Result<bool> {
+        if target < self.doc {
+            bail!(IllegalArgument(format!(
+                "cannot advance backward: current doc is {}, target is {}",
+                self.doc, target
+            )));
+        }
+        if target >= self.max_doc {
+            bail!(IllegalArgument(format!(
+                "target {} is out of bounds, max_doc is {}",
+                target, self.max_doc
+            )));
+        }
+        self.doc = target;
+        self.has_value = self.docs_with_field.get(target as usize)?;
+        Ok(self.has_value)
+    }
+
+    /// The value of the current doc, only valid after `advance_exact` returned true.
+    pub fn long_value(&mut self) -> Result<i64> {
+        if !self.has_value {
+            bail!(IllegalState(format!("doc {} has no value", self.doc)));
+        }
+        self.values.get_mut(self.doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use core::doc::{Fieldable, NumericDocValuesField};
+    use core::index::reader::IndexReader;
+    use core::test_util::new_fs_writer;
+
+    #[test]
+    fn test_advance_exact_over_sparse_field() {
+        let (_dir, writer) = new_fs_writer();
+        // only every third doc has a value for "v", the others just have "other"
+        for i in 0..30 {
+            let field: Box<dyn Fieldable> = if i % 3 == 0 {
+                Box::new(NumericDocValuesField::new("v", i * 10))
+            } else {
+                Box::new(NumericDocValuesField::new("other", i))
+            };
+            writer.add_document(vec![field]).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 1);
+        let mut iter = NumericDocValuesIterator::from_leaf(&leaves[0], "v").unwrap();
+        assert_eq!(iter.doc_id(), -1);
+        for doc in 0..30 {
+            let has_value = iter.advance_exact(doc).unwrap();
+            assert_eq!(iter.doc_id(), doc);
+            if doc % 3 == 0 {
+                assert!(has_value);
+                assert_eq!(iter.long_value().unwrap(), i64::from(doc) * 10);
+            } else {
+                assert!(!has_value);
+                assert!(iter.long_value().is_err());
+            }
+        }
+
+        // advancing to the current doc is fine, going backward or past max_doc is not
+        assert!(iter.advance_exact(29).is_ok());
+        assert!(iter.advance_exact(28).is_err());
+        assert!(iter.advance_exact(30).is_err());
+    }
+}
@@ -55,6 +55,39 @@ pub trait DocValuesProducer: Send + Sync {
 
     /// Returns an instance optimized for merging.
     fn get_merge_instance(&self) -> Result<Box<dyn DocValuesProducer>>;
+
+    /// Returns how the numeric values, or the ords, of this field are encoded, `None` if
+    /// the field has neither or the format doesn't tell.
+    fn numeric_encoding(&self, _field: &FieldInfo) -> Option<NumericEncoding> {
+        None
+    }
+}
+
+/// The strategy used to encode the numeric values, or the ords, of a doc values field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NumericEncodingType {
+    /// packed deltas from the minimum value
+    Delta,
+    /// packed deltas from the minimum value, divided by their greatest common divisor
+    Gcd,
+    /// packed indexes into the table of the unique values
+    Table,
+    /// increasing values, packed with `DirectMonotonicWriter`
+    Monotonic,
+    /// a single value shared by all the docs that have one
+    Const,
+    /// only the values of the docs that have one are stored
+    Sparse,
+}
+
+/// How the numeric values, or the ords, of a doc values field are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NumericEncoding {
+    pub encoding: NumericEncodingType,
+    /// The number of bits of the packed values, 0 when they aren't packed with a fixed
+    /// width, e.g. for `Const` and `Monotonic`. For `Sparse` these are the bits of the
+    /// values of the docs that have one.
+    pub bits_per_value: i32,
 }
 
 pub type DocValuesProducerRef = Arc<dyn DocValuesProducer>;
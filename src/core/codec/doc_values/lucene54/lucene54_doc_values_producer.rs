@@ -13,8 +13,9 @@
 
 use core::codec::doc_values::lucene54::*;
 use core::codec::doc_values::{
-    BinaryDocValuesProvider, DocValuesProducer, NumericDocValuesProvider, SortedDocValuesProvider,
-    SortedNumericDocValuesProvider, SortedSetDocValuesProvider,
+    BinaryDocValuesProvider, DocValuesProducer, NumericDocValuesProvider, NumericEncoding,
+    NumericEncodingType, SortedDocValuesProvider, SortedNumericDocValuesProvider,
+    SortedSetDocValuesProvider,
 };
 use core::codec::field_infos::{FieldInfo, FieldInfos};
 use core::codec::segment_infos::{segment_file_name, SegmentInfo, SegmentReadState};
@@ -1371,4 +1372,28 @@ impl DocValuesProducer for Lucene54DocValuesProducer {
     fn get_merge_instance(&self) -> Result<Box<dyn DocValuesProducer>> {
         Ok(Box::new(Lucene54DocValuesProducer::copy_from(self)?))
     }
+
+    fn numeric_encoding(&self, field: &FieldInfo) -> Option<NumericEncoding> {
+        let entry = self
+            .numerics
+            .get(&field.name)
+            .or_else(|| self.ords.get(&field.name))?;
+        let encoding = match entry.format {
+            Lucene54DocValuesFormat::DELTA_COMPRESSED => NumericEncodingType::Delta,
+            Lucene54DocValuesFormat::GCD_COMPRESSED => NumericEncodingType::Gcd,
+            Lucene54DocValuesFormat::TABLE_COMPRESSED => NumericEncodingType::Table,
+            Lucene54DocValuesFormat::MONOTONIC_COMPRESSED => NumericEncodingType::Monotonic,
+            Lucene54DocValuesFormat::CONST_COMPRESSED => NumericEncodingType::Const,
+            Lucene54DocValuesFormat::SPARSE_COMPRESSED => NumericEncodingType::Sparse,
+            _ => return None,
+        };
+        let bits_per_value = match entry.non_missing_values {
+            Some(ref values) => values.bits_per_value,
+            None => entry.bits_per_value,
+        };
+        Some(NumericEncoding {
+            encoding,
+            bits_per_value,
+        })
+    }
 }
@@ -13,6 +13,7 @@
 
 use core::codec::field_infos::{FieldInfo, FieldInfos};
 use core::codec::segment_infos::{segment_file_name, SegmentInfo};
+use core::codec::vectors::KnnVectorSimilarityFunction;
 use core::codec::{codec_util, Codec};
 use core::doc::{DocValuesType, IndexOptions};
 use core::store::directory::Directory;
@@ -20,6 +21,8 @@ use core::store::io::{BufferedChecksumIndexInput, ChecksumIndexInput, DataOutput
 use core::store::IOContext;
 use error::{ErrorKind::CorruptIndex, Result};
 
+use std::collections::HashMap;
+
 /// Encodes/decodes `FieldInfos`
 pub trait FieldInfosFormat {
     /// Read the `FieldInfos` previously written with {@link #write}. */
@@ -50,6 +53,11 @@ const CODEC_NAME: &str = "Lucene60FieldInfos";
 const FORMAT_START: i32 = 0;
 const FORMAT_CURRENT: i32 = FORMAT_START;
 
+// The vector dimension and similarity function are kept as attributes, the format
+// stays the one of Lucene 6.2.1
+const VECTOR_DIMENSION_KEY: &str = "KnnVectorFormat.dimension";
+const VECTOR_SIMILARITY_KEY: &str = "KnnVectorFormat.similarity";
+
 // Field flags
 const STORE_TERM_VECTOR: u8 = 0x1;
 const OMIT_NORMS: u8 = 0x2;
@@ -87,7 +95,7 @@ fn read_field_infos_from_index<T: IndexInput + ?Sized, D: Directory, C: Codec>(
         let index_options = read_index_options(input)?;
         let doc_values_type = read_doc_values_type(input)?;
         let dv_gen = input.read_long()?;
-        let attributes = input.read_map_of_strings()?;
+        let mut attributes = input.read_map_of_strings()?;
         let point_dimension_count = input.read_vint()?;
         let point_num_bytes = if point_dimension_count != 0 {
             input.read_vint()?
@@ -95,7 +103,9 @@ fn read_field_infos_from_index<T: IndexInput + ?Sized, D: Directory, C: Codec>(
             0
         };
 
-        let info = FieldInfo::new(
+        let (vector_dimension, vector_similarity) = read_vector_attributes(&name, &mut attributes)?;
+
+        let mut info = FieldInfo::new(
             name,
             field_number as u32,
             store_term_vector,
@@ -108,11 +118,35 @@ fn read_field_infos_from_index<T: IndexInput + ?Sized, D: Directory, C: Codec>(
             point_dimension_count as u32,
             point_num_bytes as u32,
         )?;
+        info.set_vector_dimension(vector_dimension, vector_similarity)?;
         infos.push(info);
     }
     Ok(infos)
 }
 
+// removes the vector attributes of field `name` from `attributes`
+fn read_vector_attributes(
+    name: &str,
+    attributes: &mut HashMap<String, String>,
+) -> Result<(u32, KnnVectorSimilarityFunction)> {
+    let dimension = match attributes.remove(VECTOR_DIMENSION_KEY) {
+        Some(dimension) => dimension,
+        None => return Ok((0, KnnVectorSimilarityFunction::default())),
+    };
+    let similarity = attributes
+        .remove(VECTOR_SIMILARITY_KEY)
+        .and_then(|s| s.parse::<u8>().ok());
+    match (dimension.parse::<u32>(), similarity) {
+        (Ok(value), Some(similarity)) if value > 0 => {
+            Ok((value, KnnVectorSimilarityFunction::from_byte(similarity)?))
+        }
+        _ => bail!(CorruptIndex(format!(
+            "invalid vector attributes for field: {}, dimension={}",
+            name, dimension
+        ))),
+    }
+}
+
 pub fn read_field_infos<D: Directory, C: Codec>(
     directory: &mut D,
     segment_info: &SegmentInfo<D, C>,
@@ -247,7 +281,18 @@ impl FieldInfosFormat for Lucene60FieldInfosFormat {
             // pack the DV type and has_norms in one byte
             output.write_byte(doc_values_byte(fi.doc_values_type))?;
             output.write_long(fi.dv_gen)?;
-            output.write_map_of_strings(&fi.attributes.read().unwrap())?;
+            let mut attributes = fi.attributes.read().unwrap().clone();
+            if fi.vector_dimension > 0 {
+                attributes.insert(
+                    VECTOR_DIMENSION_KEY.to_string(),
+                    fi.vector_dimension.to_string(),
+                );
+                attributes.insert(
+                    VECTOR_SIMILARITY_KEY.to_string(),
+                    fi.vector_similarity.as_byte().to_string(),
+                );
+            }
+            output.write_map_of_strings(&attributes)?;
             let point_dimension_count = fi.point_dimension_count;
             output.write_vint(point_dimension_count as i32)?;
             if point_dimension_count > 0 {
@@ -26,12 +26,14 @@ use std::iter::Iterator;
 use std::result;
 use std::sync::{Arc, Mutex, RwLock};
 
+use serde::de;
 use serde::ser::SerializeStruct;
-use serde::{Serialize, Serializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 // use core::attribute::{OffsetAttribute, PayloadAttribute, PositionIncrementAttribute};
 use core::codec::points::{MAX_DIMENSIONS, MAX_NUM_BYTES};
 use core::codec::postings::{PER_FIELD_POSTING_FORMAT_KEY, PER_FIELD_POSTING_SUFFIX_KEY};
+use core::codec::vectors::{KnnVectorSimilarityFunction, MAX_VECTOR_DIMENSIONS};
 use core::doc::{DocValuesType, IndexOptions};
 
 /// Access to the Field Info file that describes document fields and whether or
@@ -52,6 +54,9 @@ pub struct FieldInfo {
     pub dv_gen: i64,
     pub point_dimension_count: u32,
     pub point_num_bytes: u32,
+    /// The dimension of the dense vectors of this field, 0 if it has none.
+    pub vector_dimension: u32,
+    pub vector_similarity: KnnVectorSimilarityFunction,
 }
 
 impl Serialize for FieldInfo {
@@ -59,7 +64,7 @@ impl Serialize for FieldInfo {
     where
         S: Serializer,
     {
-        let mut s = serializer.serialize_struct("FieldInfo", 11)?;
+        let mut s = serializer.serialize_struct("FieldInfo", 13)?;
         s.serialize_field("name", &self.name)?;
         s.serialize_field("number", &self.number)?;
         s.serialize_field("doc_values_type", &self.doc_values_type)?;
@@ -71,10 +76,59 @@ impl Serialize for FieldInfo {
         s.serialize_field("dv_gen", &self.dv_gen)?;
         s.serialize_field("point_dimension_count", &self.point_dimension_count)?;
         s.serialize_field("point_num_bytes", &self.point_num_bytes)?;
+        s.serialize_field("vector_dimension", &self.vector_dimension)?;
+        s.serialize_field("vector_similarity", &self.vector_similarity)?;
         s.end()
     }
 }
 
+impl<'de> Deserialize<'de> for FieldInfo {
+    fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct FieldInfoData {
+            name: String,
+            number: u32,
+            doc_values_type: DocValuesType,
+            has_store_term_vector: bool,
+            omit_norms: bool,
+            index_options: IndexOptions,
+            has_store_payloads: bool,
+            attributes: HashMap<String, String>,
+            dv_gen: i64,
+            point_dimension_count: u32,
+            point_num_bytes: u32,
+            #[serde(default)]
+            vector_dimension: u32,
+            #[serde(default)]
+            vector_similarity: KnnVectorSimilarityFunction,
+        }
+
+        let data = FieldInfoData::deserialize(deserializer)?;
+        let mut info = FieldInfo::new(
+            data.name,
+            data.number,
+            data.has_store_term_vector,
+            data.omit_norms,
+            data.has_store_payloads,
+            data.index_options,
+            data.doc_values_type,
+            data.dv_gen,
+            data.attributes,
+            data.point_dimension_count,
+            data.point_num_bytes,
+        )
+        .map_err(|e| de::Error::custom(e.to_string()))?;
+        info.vector_dimension = data.vector_dimension;
+        info.vector_similarity = data.vector_similarity;
+        info.check_consistency()
+            .map_err(|e| de::Error::custom(e.to_string()))?;
+        Ok(info)
+    }
+}
+
 impl FieldInfo {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -102,6 +156,8 @@ impl FieldInfo {
             dv_gen,
             point_dimension_count,
             point_num_bytes,
+            vector_dimension: 0,
+            vector_similarity: KnnVectorSimilarityFunction::default(),
         };
 
         info.check_consistency()?;
@@ -158,6 +214,13 @@ impl FieldInfo {
             )));
         }
 
+        if self.vector_dimension > MAX_VECTOR_DIMENSIONS {
+            bail!(IllegalState(format!(
+                "Illegal State: vector dimension of field '{}' must be <= {}, got {}",
+                &self.name, MAX_VECTOR_DIMENSIONS, self.vector_dimension
+            )));
+        }
+
         if self.dv_gen != -1
             && match self.doc_values_type {
                 DocValuesType::Null => true,
@@ -235,6 +298,34 @@ impl FieldInfo {
         Ok(())
     }
 
+    /// Records the dimension and similarity function of the dense vectors of this field,
+    /// which cannot be changed once set.
+    pub fn set_vector_dimension(
+        &mut self,
+        dimension: u32,
+        similarity: KnnVectorSimilarityFunction,
+    ) -> Result<()> {
+        if dimension == 0 {
+            return Ok(());
+        }
+        if dimension > MAX_VECTOR_DIMENSIONS {
+            bail!(IllegalArgument(format!(
+                "vector dimension of field '{}' must be <= {}, got {}",
+                self.name, MAX_VECTOR_DIMENSIONS, dimension
+            )));
+        }
+        if self.vector_dimension == 0 {
+            self.vector_dimension = dimension;
+            self.vector_similarity = similarity;
+        } else if self.vector_dimension != dimension || self.vector_similarity != similarity {
+            bail!(IllegalArgument(format!(
+                "cannot change field '{}' vector dimension or similarity function",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
     pub fn has_norms(&self) -> bool {
         match self.index_options {
             IndexOptions::Null => false,
@@ -320,6 +411,8 @@ pub struct FieldInvertState {
     pub max_term_frequency: u32,
     pub unique_term_count: u32,
     pub boost: f32,
+    /// whether overlap tokens (position increment 0) are excluded from `field_length`
+    pub discount_overlaps: bool,
 
     // we must track these across field instances (multi-valued case)
     pub last_start_offset: i32,
@@ -352,11 +445,22 @@ impl FieldInvertState {
             max_term_frequency: 0,
             unique_term_count: 0,
             boost,
+            discount_overlaps: true,
             last_start_offset: 0,
             last_position: 0,
         }
     }
 
+    /// Returns the field length fed to the norm encoder: the number of tokens
+    /// indexed, minus the overlap tokens if `discount_overlaps` is set.
+    pub fn field_length(&self) -> i32 {
+        if self.discount_overlaps {
+            self.length - self.num_overlap
+        } else {
+            self.length
+        }
+    }
+
     pub fn reset(&mut self) {
         self.position = -1;
         self.length = 0;
@@ -381,6 +485,7 @@ pub struct FieldInfos {
     pub has_norms: bool,
     pub has_doc_values: bool,
     pub has_point_values: bool,
+    pub has_vector_values: bool,
 
     pub by_number: BTreeMap<u32, Arc<FieldInfo>>,
     pub by_name: HashMap<String, Arc<FieldInfo>>,
@@ -391,7 +496,7 @@ impl Serialize for FieldInfos {
     where
         S: Serializer,
     {
-        let mut s = serializer.serialize_struct("FieldInfos", 9)?;
+        let mut s = serializer.serialize_struct("FieldInfos", 10)?;
         s.serialize_field("has_freq", &self.has_freq)?;
         s.serialize_field("has_prox", &self.has_prox)?;
         s.serialize_field("has_payloads", &self.has_payloads)?;
@@ -400,6 +505,7 @@ impl Serialize for FieldInfos {
         s.serialize_field("has_norms", &self.has_norms)?;
         s.serialize_field("has_doc_values", &self.has_doc_values)?;
         s.serialize_field("has_point_values", &self.has_point_values)?;
+        s.serialize_field("has_vector_values", &self.has_vector_values)?;
 
         let fields: HashMap<&String, &FieldInfo> = self
             .by_name
@@ -431,6 +537,7 @@ impl FieldInfos {
         let mut has_norms = false;
         let mut has_doc_values = false;
         let mut has_point_values = false;
+        let mut has_vector_values = false;
 
         let mut by_number: BTreeMap<u32, Arc<FieldInfo>> = BTreeMap::new();
         let mut by_name: HashMap<String, Arc<FieldInfo>> = HashMap::new();
@@ -438,8 +545,10 @@ impl FieldInfos {
 
         let mut infos = infos;
         for info in &mut infos {
-            if info.index_options != IndexOptions::Null {
-                // force put attr...
+            // fields read from a segment keep the format recorded by its postings writer
+            if info.index_options != IndexOptions::Null
+                && info.attribute(PER_FIELD_POSTING_FORMAT_KEY).is_none()
+            {
                 info.put_attribute(
                     PER_FIELD_POSTING_FORMAT_KEY.to_string(),
                     "Lucene50".to_string(),
@@ -464,6 +573,7 @@ impl FieldInfos {
                 has_doc_values |= !info.doc_values_type.null();
                 has_payloads |= info.has_store_payloads;
                 has_point_values |= info.point_dimension_count != 0;
+                has_vector_values |= info.vector_dimension != 0;
             }
 
             if let Some(previous) = by_number.insert(number, info.clone()) {
@@ -491,6 +601,7 @@ impl FieldInfos {
             has_norms,
             has_doc_values,
             has_point_values,
+            has_vector_values,
             by_number,
             by_name,
         })
@@ -590,7 +701,11 @@ impl<T: AsRef<FieldNumbers>> FieldInfosBuilder<T> {
             fi.doc_values_type,
             fi.point_dimension_count,
             fi.point_num_bytes,
-        )
+        )?;
+        self.by_name
+            .get_mut(&fi.name)
+            .unwrap()
+            .set_vector_dimension(fi.vector_dimension, fi.vector_similarity)
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -1036,3 +1151,109 @@ impl FieldDimensions {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_info_serde_round_trip() {
+        let mut attributes = HashMap::new();
+        attributes.insert(
+            PER_FIELD_POSTING_FORMAT_KEY.to_string(),
+            "Lucene50".to_string(),
+        );
+        let info = FieldInfo::new(
+            "body".to_string(),
+            3,
+            true,
+            false,
+            true,
+            IndexOptions::DocsAndFreqsAndPositionsAndOffsets,
+            DocValuesType::SortedNumeric,
+            2,
+            attributes,
+            1,
+            8,
+        )
+        .unwrap();
+
+        let json = ::serde_json::to_string(&info).unwrap();
+        let decoded: FieldInfo = ::serde_json::from_str(&json).unwrap();
+
+        assert_eq!(decoded.name, info.name);
+        assert_eq!(decoded.number, info.number);
+        assert_eq!(decoded.doc_values_type, info.doc_values_type);
+        assert_eq!(decoded.has_store_term_vector, info.has_store_term_vector);
+        assert_eq!(decoded.omit_norms, info.omit_norms);
+        assert_eq!(decoded.index_options, info.index_options);
+        assert_eq!(decoded.has_store_payloads, info.has_store_payloads);
+        assert_eq!(
+            *decoded.attributes.read().unwrap(),
+            *info.attributes.read().unwrap()
+        );
+        assert_eq!(decoded.dv_gen, info.dv_gen);
+        assert_eq!(decoded.point_dimension_count, info.point_dimension_count);
+        assert_eq!(decoded.point_num_bytes, info.point_num_bytes);
+    }
+
+    #[test]
+    fn test_field_info_vector_dimension() {
+        let mut info = FieldInfo::new(
+            "vec".to_string(),
+            0,
+            false,
+            false,
+            false,
+            IndexOptions::Null,
+            DocValuesType::Null,
+            -1,
+            HashMap::new(),
+            0,
+            0,
+        )
+        .unwrap();
+        let cosine = KnnVectorSimilarityFunction::Cosine;
+        info.set_vector_dimension(4, cosine).unwrap();
+        // setting the same values again is fine, changing them is not
+        info.set_vector_dimension(4, cosine).unwrap();
+        assert!(info.set_vector_dimension(8, cosine).is_err());
+        assert!(info
+            .set_vector_dimension(4, KnnVectorSimilarityFunction::L2)
+            .is_err());
+        assert!(info
+            .set_vector_dimension(MAX_VECTOR_DIMENSIONS + 1, cosine)
+            .is_err());
+
+        let json = ::serde_json::to_string(&info).unwrap();
+        let decoded: FieldInfo = ::serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.vector_dimension, 4);
+        assert_eq!(decoded.vector_similarity, cosine);
+
+        let infos = FieldInfos::new(vec![info]).unwrap();
+        assert!(infos.has_vector_values);
+        assert!(!infos.has_point_values);
+    }
+
+    #[test]
+    fn test_field_info_deserialize_checks_consistency() {
+        // non-indexed fields cannot store payloads
+        let mut info = FieldInfo::new(
+            "id".to_string(),
+            0,
+            false,
+            true,
+            false,
+            IndexOptions::Null,
+            DocValuesType::Null,
+            -1,
+            HashMap::new(),
+            0,
+            0,
+        )
+        .unwrap();
+        info.has_store_payloads = true;
+        let json = ::serde_json::to_string(&info).unwrap();
+        assert!(::serde_json::from_str::<FieldInfo>(&json).is_err());
+    }
+}
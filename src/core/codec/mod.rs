@@ -19,6 +19,7 @@ pub mod postings;
 pub mod segment_infos;
 pub mod stored_fields;
 pub mod term_vectors;
+pub mod vectors;
 
 mod codec_util;
 
@@ -73,10 +74,12 @@ use core::codec::stored_fields::{
 use core::codec::term_vectors::{
     term_vectors_format, CompressingTermVectorsFormat, TermVectorsFormat, TermVectorsReader,
 };
+use core::codec::vectors::{QuantizedVectorsFormat, VectorsFormat, VectorsFormatEnum};
 
 use core::codec::segment_infos::{Lucene62SegmentInfoFormat, SegmentInfoFormat};
 use error::ErrorKind::{CorruptIndex, IllegalArgument};
 use error::{Error, Result};
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::sync::Arc;
 
@@ -102,6 +105,7 @@ pub trait Codec: TryFrom<String, Error = Error> + 'static {
     type LiveDocFmt: LiveDocsFormat;
     type CompoundFmt: CompoundFormat;
     type PointFmt: PointsFormat;
+    type VectorFmt: VectorsFormat;
 
     fn name(&self) -> &str;
     fn postings_format(&self) -> Self::PostingFmt;
@@ -114,6 +118,7 @@ pub trait Codec: TryFrom<String, Error = Error> + 'static {
     fn live_docs_format(&self) -> Self::LiveDocFmt;
     fn compound_format(&self) -> Self::CompoundFmt;
     fn points_format(&self) -> Self::PointFmt;
+    fn vectors_format(&self) -> Self::VectorFmt;
 }
 
 pub type CodecFieldsProducer<C> = <<C as Codec>::PostingFmt as PostingsFormat>::FieldsProducer;
@@ -145,6 +150,7 @@ impl Codec for CodecEnum {
     type LiveDocFmt = Lucene50LiveDocsFormat;
     type CompoundFmt = Lucene50CompoundFormat;
     type PointFmt = Lucene60PointsFormat;
+    type VectorFmt = VectorsFormatEnum;
 
     fn name(&self) -> &str {
         match self {
@@ -203,6 +209,13 @@ impl Codec for CodecEnum {
             CodecEnum::Lucene62(c) => c.points_format(),
         }
     }
+
+    /// Encodes/decodes dense vectors
+    fn vectors_format(&self) -> Self::VectorFmt {
+        match self {
+            CodecEnum::Lucene62(c) => c.vectors_format(),
+        }
+    }
 }
 
 impl TryFrom<String> for CodecEnum {
@@ -239,6 +252,7 @@ pub struct Lucene62Codec {
     stored_fields_format: Lucene50StoredFieldsFormat,
     norms_format: Lucene53NormsFormat,
     points_format: Lucene60PointsFormat,
+    vectors_format: VectorsFormatEnum,
 }
 
 impl Default for Lucene62Codec {
@@ -256,6 +270,27 @@ impl Default for Lucene62Codec {
             doc_values_format: PerFieldDocValuesFormat::default(),
             norms_format: Lucene53NormsFormat::default(),
             points_format: Lucene60PointsFormat {},
+            vectors_format: VectorsFormatEnum::default(),
+        }
+    }
+}
+
+impl Lucene62Codec {
+    /// Creates the codec that keeps a bloom filter of the terms of `fields` in each
+    /// segment, so that looking up an absent term skips the segment's terms dict.
+    pub fn with_bloom_filter_fields(fields: HashSet<String>) -> Lucene62Codec {
+        Lucene62Codec {
+            postings_format: PerFieldPostingsFormat::with_bloom_filter_fields(fields),
+            ..Default::default()
+        }
+    }
+
+    /// Creates the codec that also writes the dense vectors quantized to `i8`s, see
+    /// `QuantizedVectorsFormat`.
+    pub fn with_quantized_vectors() -> Lucene62Codec {
+        Lucene62Codec {
+            vectors_format: VectorsFormatEnum::Quantized(QuantizedVectorsFormat),
+            ..Default::default()
         }
     }
 }
@@ -272,13 +307,14 @@ impl Codec for Lucene62Codec {
     type LiveDocFmt = Lucene50LiveDocsFormat;
     type CompoundFmt = Lucene50CompoundFormat;
     type PointFmt = Lucene60PointsFormat;
+    type VectorFmt = VectorsFormatEnum;
 
     fn name(&self) -> &str {
         "Lucene62"
     }
 
     fn postings_format(&self) -> Self::PostingFmt {
-        self.postings_format
+        self.postings_format.clone()
     }
 
     fn doc_values_format(&self) -> Self::DVFmt {
@@ -316,6 +352,10 @@ impl Codec for Lucene62Codec {
     fn points_format(&self) -> Self::PointFmt {
         self.points_format
     }
+
+    fn vectors_format(&self) -> Self::VectorFmt {
+        self.vectors_format
+    }
 }
 
 impl TryFrom<String> for Lucene62Codec {
@@ -86,6 +86,11 @@ where
             }
         }
 
+        if subs2.is_empty() {
+            // field is not indexed in any of the sub readers
+            return Ok(None);
+        }
+
         let terms = Arc::new(MultiTerms::new(subs2, slices2)?);
         self.terms
             .borrow_mut()
@@ -121,7 +126,7 @@ fn fields<C: Codec, IR: IndexReader<Codec = C> + ?Sized>(
             fields.push(leaf.reader.fields()?);
             slices.push(ReaderSlice::new(
                 leaf.doc_base(),
-                reader.max_doc(),
+                leaf.reader.max_doc(),
                 fields.len() - 1,
             ));
         }
@@ -129,6 +134,12 @@ fn fields<C: Codec, IR: IndexReader<Codec = C> + ?Sized>(
     }
 }
 
+/// Returns a single `Terms` view of `field` across all leaves of `reader`.
+///
+/// When the reader has more than one leaf the per-segment term iterators are
+/// merge sorted by term text, so the returned terms enumerate every distinct term
+/// exactly once with statistics aggregated over all segments. Returns `None` if
+/// no segment has indexed the field.
 pub fn get_terms<C: Codec, IR: IndexReader<Codec = C> + ?Sized>(
     reader: &IR,
     field: &str,
@@ -574,6 +585,10 @@ impl<C: Codec, T: TermIterator> TermIterator for MappedMultiTermsIterator<C, T>
         self.terms.next()
     }
 
+    fn seek_exact(&mut self, text: &[u8]) -> Result<bool> {
+        self.terms.seek_exact(text)
+    }
+
     fn seek_ceil(&mut self, text: &[u8]) -> Result<SeekStatus> {
         self.terms.seek_ceil(text)
     }
@@ -716,6 +731,9 @@ impl<C: Codec, T: TermIterator> TermIterator for MappedMultiTermsIterEnum<C, T>
     }
 }
 
+/// The postings of a term in all the segments being merged, with the docs of each segment
+/// mapped to the merged segment, skipping the deleted ones, and iterated in increasing
+/// order. The postings writer of the merged segment encodes the deltas of the mapped docs.
 pub struct MappingMultiPostingsIter<T: PostingIterator> {
     _field: String,
     doc_id_merger: DocIdMergerEnum<MappingPostingsSub<T>>,
@@ -985,3 +1003,184 @@ impl<T: PostingIterator> DocIterator for MappedMultiPostingIterEnum<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::analysis::StringTokenStream;
+    use core::codec::PostingIteratorFlags;
+    use core::doc::{Field, FieldType, Fieldable, IndexOptions, Term};
+    use core::index::reader::LeafReader;
+    use core::search::query::TermQuery;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::{keyword_field, new_fs_writer};
+
+    fn keyword_field_with_options(
+        name: &str,
+        value: &str,
+        index_options: IndexOptions,
+    ) -> Box<dyn Fieldable> {
+        let mut field_type = FieldType::default();
+        field_type.index_options = index_options;
+        let tokens = StringTokenStream::new(value.to_string());
+        Box::new(Field::new(
+            name.to_string(),
+            field_type,
+            None,
+            Some(Box::new(tokens)),
+        ))
+    }
+
+    #[test]
+    fn test_get_terms_merges_segments() {
+        let (_dir, writer) = new_fs_writer();
+
+        // each commit flushes a new segment
+        for values in &[vec!["b", "d"], vec!["a", "d"], vec!["c"]] {
+            for v in values {
+                writer.add_document(vec![keyword_field("tag", v)]).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+
+        let reader = writer.get_reader(true, false).unwrap();
+        assert!(reader.leaves().len() > 1);
+
+        let terms = get_terms(&reader, "tag").unwrap().unwrap();
+        assert_eq!(terms.doc_count().unwrap(), 5);
+        assert_eq!(terms.min().unwrap(), Some(b"a".to_vec()));
+        assert_eq!(terms.max().unwrap(), Some(b"d".to_vec()));
+
+        let mut iter = terms.iterator().unwrap();
+        let mut merged = vec![];
+        while let Some(term) = iter.next().unwrap() {
+            merged.push((term, iter.doc_freq().unwrap()));
+        }
+        assert_eq!(
+            merged,
+            vec![
+                (b"a".to_vec(), 1),
+                (b"b".to_vec(), 1),
+                (b"c".to_vec(), 1),
+                (b"d".to_vec(), 2),
+            ]
+        );
+
+        assert!(get_terms(&reader, "missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_dump_terms_with_statistics() {
+        let (_dir, writer) = new_fs_writer();
+
+        let segments = vec![vec![vec!["x", "x", "y"], vec!["y"]], vec![vec!["x", "z"]]];
+        for docs in &segments {
+            for values in docs {
+                let doc = values
+                    .iter()
+                    .map(|v| keyword_field_with_options("tag", v, IndexOptions::DocsAndFreqs))
+                    .collect();
+                writer.add_document(doc).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 2);
+        // the per-segment view knows its exact number of terms
+        let leaf_terms = leaves[0].reader.terms("tag").unwrap().unwrap();
+        assert_eq!(leaf_terms.size().unwrap(), 2);
+        assert_eq!(leaf_terms.sum_total_term_freq().unwrap(), 4);
+
+        let terms = get_terms(&reader, "tag").unwrap().unwrap();
+        // unknown at the composite level, as terms may be shared between segments
+        assert_eq!(terms.size().unwrap(), -1);
+        assert_eq!(terms.doc_count().unwrap(), 3);
+        assert_eq!(terms.sum_doc_freq().unwrap(), 5);
+        assert_eq!(terms.sum_total_term_freq().unwrap(), 6);
+        assert!(!terms.stats().unwrap().is_empty());
+
+        let mut iter = terms.iterator().unwrap();
+        let mut dump = vec![];
+        while let Some(term) = iter.next().unwrap() {
+            let doc_freq = iter.doc_freq().unwrap();
+            let total_term_freq = iter.total_term_freq().unwrap();
+            dump.push((String::from_utf8(term).unwrap(), doc_freq, total_term_freq));
+        }
+        assert_eq!(
+            dump,
+            vec![
+                ("x".to_string(), 2, 3),
+                ("y".to_string(), 2, 2),
+                ("z".to_string(), 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_remaps_and_reencodes_postings() {
+        let (_dir, writer) = new_fs_writer();
+        // two segments of 200 docs, all of them holding the term "x"
+        for i in 0..400 {
+            let doc = vec![
+                keyword_field("tag", "x"),
+                keyword_field("id", &i.to_string()),
+            ];
+            writer.add_document(doc).unwrap();
+            if i % 200 == 199 {
+                writer.commit().unwrap();
+            }
+        }
+        writer
+            .delete_documents_by_terms(vec![Term::new("id".into(), b"3".to_vec())])
+            .unwrap();
+        writer.commit().unwrap();
+        writer.force_merge(1, true).unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        {
+            let leaves = reader.leaves();
+            assert_eq!(leaves.len(), 1);
+            assert_eq!(leaves[0].reader.max_doc(), 399);
+
+            // the docs of both segments are remapped past the deleted doc, into one sorted list
+            let postings = |term: Term| {
+                let mut postings = leaves[0]
+                    .reader
+                    .postings(&term, i32::from(PostingIteratorFlags::NONE))
+                    .unwrap()
+                    .unwrap();
+                let mut docs = vec![];
+                loop {
+                    let doc = postings.next().unwrap();
+                    if doc == NO_MORE_DOCS {
+                        break;
+                    }
+                    docs.push(doc);
+                }
+                docs
+            };
+            assert_eq!(
+                postings(Term::new("tag".into(), b"x".to_vec())),
+                (0..399).collect::<Vec<DocId>>()
+            );
+            assert_eq!(postings(Term::new("id".into(), b"250".to_vec())), vec![249]);
+        }
+
+        // the deltas are computed again from the merged docs: the first doc of the second
+        // segment is 1 after the last doc of the first one, in the middle of a packed block
+        let reports = reader.codec_reports().unwrap();
+        let tag = &reports[0].fields["tag"];
+        assert_eq!(tag.doc_blocks.num_blocks(), 3);
+        assert_eq!(tag.doc_blocks.all_values_equal(), 2);
+        assert_eq!(tag.doc_blocks.max_bits_per_value(), Some(1));
+
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let query = TermQuery::new(Term::new("tag".into(), b"x".to_vec()), 1.0, None);
+        assert_eq!(searcher.count(&query).unwrap(), 399);
+    }
+}
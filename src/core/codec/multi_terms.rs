@@ -144,15 +144,36 @@ impl<T: Terms> Terms for MultiTerms<T> {
     }
 
     fn min(&self) -> Result<Option<Vec<u8>>> {
-        unimplemented!();
+        let mut min_term: Option<Vec<u8>> = None;
+        for terms in &self.subs {
+            if let Some(term) = terms.min()? {
+                if min_term.as_ref().map_or(true, |m| term < *m) {
+                    min_term = Some(term);
+                }
+            }
+        }
+        Ok(min_term)
     }
 
     fn max(&self) -> Result<Option<Vec<u8>>> {
-        unimplemented!();
+        let mut max_term: Option<Vec<u8>> = None;
+        for terms in &self.subs {
+            if let Some(term) = terms.max()? {
+                if max_term.as_ref().map_or(true, |m| term > *m) {
+                    max_term = Some(term);
+                }
+            }
+        }
+        Ok(max_term)
     }
 
+    /// The stats of each sub `Terms`, one segment after the other.
     fn stats(&self) -> Result<String> {
-        unimplemented!();
+        let mut stats = Vec::with_capacity(self.subs.len());
+        for terms in &self.subs {
+            stats.push(terms.stats()?);
+        }
+        Ok(stats.join("\n"))
     }
 }
 
@@ -732,6 +753,13 @@ impl<T: TermIterator> TermIterator for MultiTermIteratorEnum<T> {
         }
     }
 
+    fn prefetch_postings(&mut self) -> Result<()> {
+        match self {
+            MultiTermIteratorEnum::Raw(t) => t.prefetch_postings(),
+            _ => Ok(()),
+        }
+    }
+
     fn term_state(&mut self) -> Result<Self::TermState> {
         match self {
             MultiTermIteratorEnum::Multi(t) => t.term_state(),
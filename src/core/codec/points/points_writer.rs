@@ -24,6 +24,7 @@ use core::codec::points::{
 use core::codec::segment_infos::{segment_file_name, SegmentWriteState};
 use core::codec::{codec_util, Codec};
 use core::index::merge::{LiveDocsDocMap, MergeState};
+use core::index::writer::TrackingTmpOutputDirectoryWrapper;
 use core::store::directory::Directory;
 use core::store::io::DataOutput;
 use core::util::bkd::{BKDWriter, DEFAULT_MAX_MB_SORT_IN_HEAP, DEFAULT_MAX_POINTS_IN_LEAF_NODE};
@@ -76,6 +77,14 @@ impl<D: Directory, DW: Directory, C: Codec> Lucene60PointsWriter<D, DW, C> {
             finished: false,
         })
     }
+
+    // the points spilled to disk while sorting them go to the temp directory
+    fn temp_dir(&self) -> Arc<TrackingTmpOutputDirectoryWrapper<DW>> {
+        Arc::new(TrackingTmpOutputDirectoryWrapper::new(
+            Arc::clone(&self.write_state.directory),
+            self.write_state.temp_directory.clone(),
+        ))
+    }
 }
 
 impl<D: Directory, DW: Directory, C: Codec> PointsWriter for Lucene60PointsWriter<D, DW, C> {
@@ -92,7 +101,7 @@ impl<D: Directory, DW: Directory, C: Codec> PointsWriter for Lucene60PointsWrite
             values.size(&field_info.name)? == values.doc_count(&field_info.name)? as i64;
         let mut writer = BKDWriter::new(
             self.write_state.segment_info.max_doc(),
-            Arc::clone(&self.write_state.directory),
+            self.temp_dir(),
             &self.write_state.segment_info.name,
             field_info.point_dimension_count as usize,
             field_info.point_num_bytes as usize,
@@ -172,7 +181,7 @@ impl<D: Directory, DW: Directory, C: Codec> PointsWriter for Lucene60PointsWrite
                     // reindexing them:
                     let mut writer = BKDWriter::new(
                         self.write_state.segment_info.max_doc,
-                        Arc::clone(&self.write_state.directory),
+                        self.temp_dir(),
                         &self.write_state.segment_info.name,
                         field_info.point_dimension_count as usize,
                         field_info.point_num_bytes as usize,
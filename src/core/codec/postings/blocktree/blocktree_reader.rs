@@ -12,9 +12,10 @@
 // limitations under the License.
 
 use std::borrow::Cow;
+#[cfg(test)]
 use std::cmp::Ordering;
 use std::collections::btree_map::Keys;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Write;
 use std::io::Read;
 use std::ops::DerefMut;
@@ -24,7 +25,8 @@ use std::sync::Arc;
 use core::codec::field_infos::FieldInfo;
 use core::codec::postings::blocktree::{BlockTermState, SegmentTermsIterFrame, MAX_LONGS_SIZE};
 use core::codec::postings::{
-    FieldsProducer, Lucene50PostingIterator, Lucene50PostingsReader, Lucene50PostingsReaderRef,
+    FieldsProducer, FormatSummary, FuzzySet, Lucene50PostingIterator, Lucene50PostingsReader,
+    Lucene50PostingsReaderRef,
 };
 use core::codec::segment_infos::{segment_file_name, SegmentReadState};
 use core::codec::{codec_util, Codec};
@@ -73,6 +75,22 @@ pub const TERMS_INDEX_CODEC_NAME: &str = "BlockTreeTermsIndex";
 
 type IndexInputRef = Arc<dyn IndexInput>;
 
+/// How the terms index FST of each field is loaded when a segment is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FSTLoadMode {
+    /// Copy the FST onto the heap.
+    OnHeap,
+    /// Leave the FST in the `.tip` file and read its arcs on demand, which keeps
+    /// the terms index out of the heap. Meant for mmapped directories.
+    OffHeap,
+}
+
+impl Default for FSTLoadMode {
+    fn default() -> Self {
+        FSTLoadMode::OnHeap
+    }
+}
+
 /// A block-based terms index and dictionary that assigns
 /// terms to variable length blocks according to how they
 /// share prefixes.
@@ -133,6 +151,16 @@ impl BlockTreeTermsReader {
     pub fn new<D: Directory, DW: Directory, C: Codec>(
         postings_reader: Lucene50PostingsReader,
         state: &SegmentReadState<'_, D, DW, C>,
+    ) -> Result<BlockTreeTermsReader> {
+        Self::with_bloom_filters(postings_reader, state, HashMap::new())
+    }
+
+    /// Opens the terms dict, where `bloom_filters` holds the filters of the terms of
+    /// some fields by field number.
+    pub fn with_bloom_filters<D: Directory, DW: Directory, C: Codec>(
+        postings_reader: Lucene50PostingsReader,
+        state: &SegmentReadState<'_, D, DW, C>,
+        mut bloom_filters: HashMap<u32, Arc<FuzzySet>>,
     ) -> Result<BlockTreeTermsReader> {
         let segment = Arc::new(state.segment_info.name.clone());
         let terms_name = segment_file_name(&segment, &state.segment_suffix, TERMS_EXTENSION);
@@ -293,6 +321,8 @@ impl BlockTreeTermsReader {
                     max_term,
                     terms_in,
                     postings_reader.clone(),
+                    bloom_filters.remove(&field_info.number),
+                    state.fst_load_mode,
                 )?);
                 fields.insert(field_info.name.clone(), reader);
             }
@@ -403,6 +433,7 @@ pub struct FieldReader {
     terms_in: IndexInputRef,
     postings_reader: Lucene50PostingsReaderRef,
     parent: BlockTreeTermsReader,
+    bloom_filter: Option<Arc<FuzzySet>>,
 }
 
 pub type FieldReaderRef = Arc<FieldReader>;
@@ -424,6 +455,8 @@ impl FieldReader {
         max_term: Vec<u8>,
         terms_in: IndexInputRef,
         postings_reader: Lucene50PostingsReaderRef,
+        bloom_filter: Option<Arc<FuzzySet>>,
+        fst_load_mode: FSTLoadMode,
     ) -> Result<FieldReader> {
         debug_assert!(longs_size <= MAX_LONGS_SIZE);
         let mut root_block_fp = root_code.as_slice().read_vlong()? as usize;
@@ -432,10 +465,15 @@ impl FieldReader {
         let index = if index_in.is_some() {
             let mut clone = index_in.unwrap().clone()?;
             clone.seek(index_start_fp)?;
-            Some(Arc::new(FST::from_input(
-                clone.as_mut(),
-                ByteSequenceOutputFactory {},
-            )?))
+            let index = match fst_load_mode {
+                FSTLoadMode::OnHeap => {
+                    FST::from_input(clone.as_mut(), ByteSequenceOutputFactory {})?
+                }
+                FSTLoadMode::OffHeap => {
+                    FST::from_input_off_heap(clone.as_mut(), ByteSequenceOutputFactory {})?
+                }
+            };
+            Some(Arc::new(index))
         } else {
             None
         };
@@ -455,6 +493,7 @@ impl FieldReader {
             terms_in,
             postings_reader,
             parent,
+            bloom_filter,
         })
     }
 
@@ -480,6 +519,28 @@ impl FieldReader {
     pub fn parent(&self) -> &BlockTreeTermsReader {
         &self.parent
     }
+
+    /// The bloom filter of this field's terms, if it was written with
+    /// `BloomFilteringPostingsFormat`.
+    pub fn bloom_filter(&self) -> Option<&FuzzySet> {
+        self.bloom_filter.as_ref().map(|f| f.as_ref())
+    }
+
+    /// Counts how the doc and freq blocks of the postings of all the terms of this field
+    /// were encoded, see `Lucene50PostingsReader::format_summary`.
+    pub fn format_summary(
+        &self,
+        docs: &mut FormatSummary,
+        freqs: &mut FormatSummary,
+    ) -> Result<()> {
+        let mut iter = self.iterator()?;
+        while iter.next()?.is_some() {
+            let state = iter.term_state()?;
+            self.postings_reader
+                .format_summary(&self.field_info, &state, docs, freqs)?;
+        }
+        Ok(())
+    }
 }
 
 impl<'a> Terms for FieldReader {
@@ -906,6 +967,7 @@ impl ToString for Stats {
 /// This implementation skips any auto-prefix terms it encounters.
 pub struct SegmentTermIterator {
     iter: Box<SegmentTermIteratorInner>,
+    bloom_filter: Option<Arc<FuzzySet>>,
 }
 
 impl SegmentTermIterator {
@@ -921,7 +983,8 @@ impl SegmentTermIterator {
             postings_reader,
             field_info,
         ));
-        Self { iter }
+        let bloom_filter = field_reader.bloom_filter.clone();
+        Self { iter, bloom_filter }
     }
 }
 
@@ -936,6 +999,11 @@ impl TermIterator for SegmentTermIterator {
 
     #[inline]
     fn seek_exact(&mut self, text: &[u8]) -> Result<bool> {
+        if let Some(ref filter) = self.bloom_filter {
+            if !filter.may_contain(text) {
+                return Ok(false);
+            }
+        }
         self.iter.seek_exact(text)
     }
 
@@ -984,6 +1052,20 @@ impl TermIterator for SegmentTermIterator {
         self.iter.postings_with_flags(flags)
     }
 
+    #[inline]
+    fn postings_with_reuse(
+        &mut self,
+        reuse: Option<Self::Postings>,
+        flags: u16,
+    ) -> Result<Self::Postings> {
+        self.iter.postings_with_reuse(reuse, flags)
+    }
+
+    #[inline]
+    fn prefetch_postings(&mut self) -> Result<()> {
+        self.iter.prefetch_postings()
+    }
+
     #[inline]
     fn term_state(&mut self) -> Result<Self::TermState> {
         self.iter.term_state()
@@ -1793,17 +1875,244 @@ impl TermIterator for SegmentTermIteratorInner {
     }
 
     fn postings_with_flags(&mut self, flags: u16) -> Result<Self::Postings> {
+        self.postings_with_reuse(None, flags)
+    }
+
+    fn postings_with_reuse(
+        &mut self,
+        reuse: Option<Self::Postings>,
+        flags: u16,
+    ) -> Result<Self::Postings> {
         debug_assert!(!self.eof);
         self.stack[self.current_frame_ord].decode_metadata()?;
-        self.postings_reader.postings(
+        self.postings_reader.postings_with_reuse(
             self.field_info.as_ref(),
             &self.stack[self.current_frame_ord].state,
+            reuse,
             flags,
         )
     }
 
+    fn prefetch_postings(&mut self) -> Result<()> {
+        debug_assert!(!self.eof);
+        self.stack[self.current_frame_ord].decode_metadata()?;
+        self.postings_reader
+            .prefetch(&self.stack[self.current_frame_ord].state)
+    }
+
     fn term_state(&mut self) -> Result<Self::TermState> {
         self.stack[self.current_frame_ord].decode_metadata()?;
         Ok(self.stack[self.current_frame_ord].state.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::analysis::WhitespaceTokenizer;
+    use core::codec::{CodecEnum, PostingIterator, PostingIteratorFlags};
+    use core::doc::{Field, FieldType, Fieldable};
+    use core::index::merge::{SerialMergeScheduler, TieredMergePolicy};
+    use core::index::reader::{IndexReader, LeafReader, StandardDirectoryReader};
+    use core::index::writer::{IndexWriter, IndexWriterConfig};
+    use core::search::{DocIterator, NO_MORE_DOCS};
+    use core::store::directory::MmapDirectory;
+    use core::test_util::keyword_field;
+    use core::util::DocId;
+
+    use std::collections::HashSet;
+    use std::io::Cursor;
+
+    type MmapReader =
+        StandardDirectoryReader<MmapDirectory, CodecEnum, SerialMergeScheduler, TieredMergePolicy>;
+
+    fn all_terms(terms: &FieldReader) -> Vec<Vec<u8>> {
+        let mut iter = terms.iterator().unwrap();
+        let mut all = Vec::new();
+        while let Some(term) = iter.next().unwrap() {
+            all.push(term);
+        }
+        all
+    }
+
+    #[test]
+    fn test_off_heap_terms_index_matches_on_heap() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let config = Arc::new(IndexWriterConfig::default());
+            let directory = Arc::new(MmapDirectory::new(&dir.path()).unwrap());
+            let writer = IndexWriter::new(directory, config).unwrap();
+            // enough terms sharing prefixes to get a multi block terms index
+            for i in 0..5000 {
+                let id = format!("{}-{:05}", ["apple", "apricot", "banana"][i % 3], i);
+                writer.add_document(vec![keyword_field("id", &id)]).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+
+        let directory = Arc::new(MmapDirectory::new(&dir.path()).unwrap());
+        let on_heap =
+            MmapReader::open_with_fst_load_mode(Arc::clone(&directory), FSTLoadMode::OnHeap)
+                .unwrap();
+        let off_heap =
+            MmapReader::open_with_fst_load_mode(directory, FSTLoadMode::OffHeap).unwrap();
+        let on_heap_leaves = on_heap.leaves();
+        let off_heap_leaves = off_heap.leaves();
+        assert_eq!(on_heap_leaves.len(), off_heap_leaves.len());
+
+        for (on, off) in on_heap_leaves.iter().zip(off_heap_leaves.iter()) {
+            let on_terms = on.reader.terms("id").unwrap().unwrap();
+            let off_terms = off.reader.terms("id").unwrap().unwrap();
+            let expected = all_terms(&on_terms);
+            assert!(!expected.is_empty());
+            assert_eq!(all_terms(&off_terms), expected);
+
+            let mut on_iter = on_terms.iterator().unwrap();
+            let mut off_iter = off_terms.iterator().unwrap();
+            let probes = expected.iter().step_by(7).cloned().chain(vec![
+                b"apple".to_vec(),
+                b"avocado".to_vec(),
+                b"zzz".to_vec(),
+            ]);
+            for probe in probes {
+                let found = on_iter.seek_exact(&probe).unwrap();
+                assert_eq!(off_iter.seek_exact(&probe).unwrap(), found);
+                assert_eq!(found, expected.contains(&probe));
+                if found {
+                    assert_eq!(off_iter.term().unwrap(), on_iter.term().unwrap());
+                    assert_eq!(off_iter.next().unwrap(), on_iter.next().unwrap());
+                }
+
+                let status = on_iter.seek_ceil(&probe).unwrap();
+                assert_eq!(off_iter.seek_ceil(&probe).unwrap(), status);
+                if status != SeekStatus::End {
+                    assert_eq!(off_iter.term().unwrap(), on_iter.term().unwrap());
+                }
+            }
+        }
+    }
+
+    fn all_docs(postings: &mut impl DocIterator) -> Vec<DocId> {
+        let mut docs = Vec::new();
+        loop {
+            let doc = postings.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                return docs;
+            }
+            docs.push(doc);
+        }
+    }
+
+    #[test]
+    fn test_postings_with_reuse() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let config = Arc::new(IndexWriterConfig::default());
+            let directory = Arc::new(MmapDirectory::new(&dir.path()).unwrap());
+            let writer = IndexWriter::new(directory, config).unwrap();
+            // more docs per term than a postings block, so skipping is reset as well
+            for i in 0..1000 {
+                let id = ["red", "green", "blue", "black"][i % 4];
+                let id = if i % 250 == 0 { "white" } else { id };
+                writer.add_document(vec![keyword_field("id", id)]).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+
+        let directory = Arc::new(MmapDirectory::new(&dir.path()).unwrap());
+        let reader = MmapReader::open(directory).unwrap();
+        for leaf in reader.leaves() {
+            let terms = leaf.reader.terms("id").unwrap().unwrap();
+            let mut iter = terms.iterator().unwrap();
+            let mut reuse = None;
+            while iter.next().unwrap().is_some() {
+                let expected = all_docs(&mut iter.postings_with_flags(0).unwrap());
+                assert_eq!(expected.len() as i32, iter.doc_freq().unwrap());
+
+                let mut postings = iter.postings_with_reuse(reuse.take(), 0).unwrap();
+                assert_eq!(all_docs(&mut postings), expected);
+
+                // a half consumed iterator starts over from the new term
+                let mut postings = iter
+                    .postings_with_reuse(Some(postings), PostingIteratorFlags::FREQS)
+                    .unwrap();
+                let target = expected[expected.len() / 2];
+                assert_eq!(postings.advance(target).unwrap(), target);
+                let mut postings = iter.postings_with_reuse(Some(postings), 0).unwrap();
+                assert_eq!(all_docs(&mut postings), expected);
+                reuse = Some(postings);
+            }
+        }
+    }
+
+    #[test]
+    fn test_field_stats_match_postings() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let config = Arc::new(IndexWriterConfig::default());
+            let directory = Arc::new(MmapDirectory::new(&dir.path()).unwrap());
+            let writer = IndexWriter::new(directory, config).unwrap();
+            let words = ["quick", "brown", "fox", "lazy", "dog", "jumps"];
+            for i in 0..300 {
+                let text: Vec<&str> = (0..(i % 7)).map(|j| words[(i * j + j) % 6]).collect();
+                let mut field_type = FieldType::default();
+                field_type.index_options = IndexOptions::DocsAndFreqs;
+                let text = text.join(" ").into_bytes();
+                let tokens = WhitespaceTokenizer::new(Box::new(Cursor::new(text)));
+                let field: Box<dyn Fieldable> = Box::new(Field::new(
+                    "body".to_string(),
+                    field_type,
+                    None,
+                    Some(Box::new(tokens)),
+                ));
+                writer.add_document(vec![field]).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+
+        // the stats read back from the terms dict
+        let open = || {
+            let directory = Arc::new(MmapDirectory::new(&dir.path()).unwrap());
+            MmapReader::open(directory).unwrap()
+        };
+        let stats: Vec<(i32, i64, i64)> = open()
+            .leaves()
+            .iter()
+            .map(|leaf| {
+                let terms = leaf.reader.terms("body").unwrap().unwrap();
+                (
+                    terms.doc_count().unwrap(),
+                    terms.sum_doc_freq().unwrap(),
+                    terms.sum_total_term_freq().unwrap(),
+                )
+            })
+            .collect();
+
+        // and a full scan of the postings with a reader of its own
+        let reader = open();
+        let mut scanned = vec![];
+        for leaf in reader.leaves() {
+            let terms = leaf.reader.terms("body").unwrap().unwrap();
+            let mut iter = terms.iterator().unwrap();
+            let mut docs = HashSet::new();
+            let mut sum_doc_freq = 0i64;
+            let mut sum_total_term_freq = 0i64;
+            while iter.next().unwrap().is_some() {
+                let mut postings = iter
+                    .postings_with_flags(PostingIteratorFlags::FREQS)
+                    .unwrap();
+                while postings.next().unwrap() != NO_MORE_DOCS {
+                    docs.insert(postings.doc_id());
+                    sum_doc_freq += 1;
+                    sum_total_term_freq += i64::from(postings.freq().unwrap());
+                }
+            }
+            assert!(sum_total_term_freq > sum_doc_freq);
+            scanned.push((docs.len() as i32, sum_doc_freq, sum_total_term_freq));
+        }
+        assert!(!stats.is_empty());
+        assert_eq!(stats, scanned);
+    }
+}
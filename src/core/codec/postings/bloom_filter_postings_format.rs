@@ -0,0 +1,410 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use fasthash::murmur3;
+
+use core::codec::codec_util::{check_footer, check_index_header};
+use core::codec::codec_util::{write_footer, write_index_header};
+use core::codec::postings::blocktree::BlockTreeTermsReader;
+use core::codec::postings::{
+    FieldsConsumer, FieldsConsumerEnum, Lucene50PostingsFormat, Lucene50PostingsReader,
+    PostingsFormat,
+};
+use core::codec::segment_infos::{segment_file_name, SegmentReadState, SegmentWriteState};
+use core::codec::{Codec, Fields, TermIterator, Terms};
+use core::store::directory::Directory;
+use core::store::io::{DataInput, DataOutput};
+use error::ErrorKind::{CorruptIndex, IllegalState};
+use error::Result;
+
+const BLOOM_EXTENSION: &str = "blm";
+const BLOOM_CODEC_NAME: &str = "BloomFilter";
+const VERSION_START: i32 = 0;
+const VERSION_CURRENT: i32 = VERSION_START;
+
+// ~1% false positive rate for the expected number of terms
+const BITS_PER_TERM: usize = 10;
+const NUM_HASHES: u32 = 7;
+const SECOND_HASH_SEED: u32 = 0x9747_b28c;
+
+/// A bloom filter over the terms of a field.
+///
+/// `may_contain` never returns false for an added term, and returns true for an absent
+/// term with a small probability only.
+pub struct FuzzySet {
+    bits: Vec<u64>,
+    num_hashes: u32,
+}
+
+impl FuzzySet {
+    /// Creates an empty filter sized for about `expected_terms` terms.
+    pub fn with_expected_terms(expected_terms: usize) -> FuzzySet {
+        let num_bits = (expected_terms.max(1) * BITS_PER_TERM)
+            .next_power_of_two()
+            .max(64);
+        FuzzySet {
+            bits: vec![0u64; num_bits / 64],
+            num_hashes: NUM_HASHES,
+        }
+    }
+
+    // double hashing, probe `i` is `h1 + i * h2` modulo the number of bits
+    fn probes(&self, term: &[u8]) -> (u64, u64, u64) {
+        let h1 = u64::from(murmur3::hash32(&term));
+        let h2 = u64::from(murmur3::hash32_with_seed(&term, SECOND_HASH_SEED)) | 1;
+        let mask = (self.bits.len() * 64) as u64 - 1;
+        (h1, h2, mask)
+    }
+
+    pub fn add(&mut self, term: &[u8]) {
+        let (h1, h2, mask) = self.probes(term);
+        for i in 0..u64::from(self.num_hashes) {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) & mask;
+            self.bits[(bit >> 6) as usize] |= 1u64 << (bit & 63);
+        }
+    }
+
+    /// Returns false if `term` was definitely not added.
+    pub fn may_contain(&self, term: &[u8]) -> bool {
+        let (h1, h2, mask) = self.probes(term);
+        (0..u64::from(self.num_hashes)).all(|i| {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) & mask;
+            self.bits[(bit >> 6) as usize] & (1u64 << (bit & 63)) != 0
+        })
+    }
+
+    fn write(&self, out: &mut impl DataOutput) -> Result<()> {
+        out.write_vint(self.num_hashes as i32)?;
+        out.write_vint(self.bits.len() as i32)?;
+        for word in &self.bits {
+            out.write_long(*word as i64)?;
+        }
+        Ok(())
+    }
+
+    fn read(input: &mut impl DataInput) -> Result<FuzzySet> {
+        let num_hashes = input.read_vint()?;
+        let num_words = input.read_vint()?;
+        if num_hashes <= 0 || num_words <= 0 || !(num_words as usize).is_power_of_two() {
+            bail!(CorruptIndex(format!(
+                "invalid bloom filter: num_hashes={}, num_words={}",
+                num_hashes, num_words
+            )));
+        }
+        let mut bits = Vec::with_capacity(num_words as usize);
+        for _ in 0..num_words {
+            bits.push(input.read_long()? as u64);
+        }
+        Ok(FuzzySet {
+            bits,
+            num_hashes: num_hashes as u32,
+        })
+    }
+}
+
+/// A postings format that keeps a `FuzzySet` per field next to the `Lucene50` terms
+/// dictionary, so that `TermIterator::seek_exact` for an absent term can return false
+/// without seeking the terms dictionary.
+///
+/// This is mostly useful for primary-key-like fields, whose point lookups would otherwise
+/// seek the terms dictionary of every segment. The filters are written to a side file of
+/// the segment and loaded when the segment is opened.
+pub struct BloomFilteringPostingsFormat {
+    delegate: Lucene50PostingsFormat,
+    bloom_filter_fields: Arc<HashSet<String>>,
+}
+
+impl Default for BloomFilteringPostingsFormat {
+    fn default() -> Self {
+        Self::new(Arc::new(HashSet::new()))
+    }
+}
+
+impl BloomFilteringPostingsFormat {
+    /// Creates a format filtering the terms of `bloom_filter_fields`.
+    pub fn new(bloom_filter_fields: Arc<HashSet<String>>) -> Self {
+        BloomFilteringPostingsFormat {
+            delegate: Lucene50PostingsFormat::default(),
+            bloom_filter_fields,
+        }
+    }
+
+    fn read_bloom_filters<D: Directory, DW: Directory, C: Codec>(
+        state: &SegmentReadState<'_, D, DW, C>,
+    ) -> Result<HashMap<u32, Arc<FuzzySet>>> {
+        let name = bloom_file_name(&state.segment_info.name, &state.segment_suffix);
+        let mut input = state.directory.open_checksum_input(&name, state.context)?;
+        check_index_header(
+            &mut input,
+            BLOOM_CODEC_NAME,
+            VERSION_START,
+            VERSION_CURRENT,
+            &state.segment_info.id,
+            &state.segment_suffix,
+        )?;
+        let num_fields = input.read_vint()?;
+        let mut filters = HashMap::with_capacity(num_fields.max(0) as usize);
+        for _ in 0..num_fields {
+            let field_number = input.read_vint()? as u32;
+            if state
+                .field_infos
+                .field_info_by_number(field_number)
+                .is_none()
+            {
+                bail!(CorruptIndex(format!(
+                    "invalid field number: {}",
+                    field_number
+                )));
+            }
+            filters.insert(field_number, Arc::new(FuzzySet::read(&mut input)?));
+        }
+        check_footer(&mut input)?;
+        Ok(filters)
+    }
+}
+
+impl PostingsFormat for BloomFilteringPostingsFormat {
+    type FieldsProducer = BlockTreeTermsReader;
+
+    fn fields_producer<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentReadState<'_, D, DW, C>,
+    ) -> Result<Self::FieldsProducer> {
+        let filters = Self::read_bloom_filters(state)?;
+        let reader = Lucene50PostingsReader::open(&state)?;
+        BlockTreeTermsReader::with_bloom_filters(reader, state, filters)
+    }
+
+    fn fields_consumer<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentWriteState<D, DW, C>,
+    ) -> Result<FieldsConsumerEnum<D, DW, C>> {
+        let delegate = self.delegate.fields_consumer(state)?;
+        Ok(FieldsConsumerEnum::BloomFilter(Box::new(
+            BloomFilteredFieldsWriter {
+                delegate,
+                bloom_filter_fields: Arc::clone(&self.bloom_filter_fields),
+                state: state.clone(),
+            },
+        )))
+    }
+
+    fn name(&self) -> &str {
+        BLOOM_CODEC_NAME
+    }
+}
+
+fn bloom_file_name(segment: &str, segment_suffix: &str) -> String {
+    segment_file_name(segment, segment_suffix, BLOOM_EXTENSION)
+}
+
+/// Writes the terms with the delegate format, and a bloom filter of the selected fields.
+pub struct BloomFilteredFieldsWriter<D: Directory, DW: Directory, C: Codec> {
+    delegate: FieldsConsumerEnum<D, DW, C>,
+    bloom_filter_fields: Arc<HashSet<String>>,
+    state: SegmentWriteState<D, DW, C>,
+}
+
+impl<D: Directory, DW: Directory, C: Codec> FieldsConsumer for BloomFilteredFieldsWriter<D, DW, C> {
+    fn write(&mut self, fields: &impl Fields) -> Result<()> {
+        let mut filters = Vec::new();
+        for field in fields.fields() {
+            if !self.bloom_filter_fields.contains(&field) {
+                continue;
+            }
+            if let Some(terms) = fields.terms(&field)? {
+                let field_number = match self.state.field_infos.field_info_by_name(&field) {
+                    Some(info) => info.number,
+                    None => bail!(IllegalState(format!("unknown field: {}", field))),
+                };
+                // a primary key has about one term per doc
+                let mut filter =
+                    FuzzySet::with_expected_terms(self.state.segment_info.max_doc as usize);
+                let mut iter = terms.iterator()?;
+                while let Some(term) = iter.next()? {
+                    filter.add(&term);
+                }
+                filters.push((field_number, filter));
+            }
+        }
+
+        self.delegate.write(fields)?;
+
+        let name = bloom_file_name(&self.state.segment_info.name, &self.state.segment_suffix);
+        let mut output = self
+            .state
+            .directory
+            .create_output(&name, &self.state.context)?;
+        write_index_header(
+            &mut output,
+            BLOOM_CODEC_NAME,
+            VERSION_CURRENT,
+            &self.state.segment_info.id,
+            &self.state.segment_suffix,
+        )?;
+        output.write_vint(filters.len() as i32)?;
+        for (field_number, filter) in &filters {
+            output.write_vint(*field_number as i32)?;
+            filter.write(&mut output)?;
+        }
+        write_footer(&mut output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::codec::postings::PER_FIELD_POSTING_FORMAT_KEY;
+    use core::codec::{CodecEnum, Lucene62Codec};
+    use core::index::merge::{SerialMergeScheduler, TieredMergePolicy};
+    use core::index::reader::{IndexReader, LeafReader, StandardDirectoryReader};
+    use core::index::writer::{IndexWriter, IndexWriterConfig};
+    use core::store::directory::{FSDirectory, MockDirectory};
+    use core::test_util::{keyword_field, new_fs_writer};
+
+    #[test]
+    fn test_fuzzy_set_has_no_false_negatives() {
+        let mut filter = FuzzySet::with_expected_terms(1000);
+        for i in 0..1000 {
+            filter.add(format!("id{}", i).as_bytes());
+        }
+        assert!((0..1000).all(|i| filter.may_contain(format!("id{}", i).as_bytes())));
+        let false_positives = (0..1000)
+            .filter(|i| filter.may_contain(format!("absent{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 50);
+
+        let mut bytes = Vec::new();
+        filter.write(&mut bytes).unwrap();
+        let read = FuzzySet::read(&mut bytes.as_slice()).unwrap();
+        assert_eq!(read.bits, filter.bits);
+        assert_eq!(read.num_hashes, filter.num_hashes);
+    }
+
+    #[test]
+    fn test_seek_absent_term_skips_terms_dict() {
+        let dir = tempfile::tempdir().unwrap();
+        let fields = vec!["id".to_string()].into_iter().collect();
+        let codec = CodecEnum::Lucene62(Lucene62Codec::with_bloom_filter_fields(fields));
+        let config = Arc::new(IndexWriterConfig::new(
+            Arc::new(codec),
+            SerialMergeScheduler {},
+            TieredMergePolicy::default(),
+        ));
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let writer = IndexWriter::new(Arc::clone(&directory), config).unwrap();
+        // each commit flushes a segment with its own ids
+        for prefix in &["a", "b", "c"] {
+            for i in 0..20 {
+                let id = format!("{}{}", prefix, i);
+                let doc = vec![keyword_field("id", &id), keyword_field("tag", &id)];
+                writer.add_document(doc).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+
+        // the reader's codec has no bloom filter fields, the format is found by the name
+        // recorded in the field infos
+        type MockReader = StandardDirectoryReader<
+            MockDirectory<FSDirectory>,
+            CodecEnum,
+            SerialMergeScheduler,
+            TieredMergePolicy,
+        >;
+        let mock_dir = Arc::new(MockDirectory::new(Arc::clone(&directory)));
+        let reader = MockReader::open(Arc::clone(&mock_dir)).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 3);
+        let mut skipped = 0;
+        for (leaf, own_prefix) in leaves.iter().zip(&["a", "b", "c"]) {
+            for field in &["id", "tag"] {
+                let info = leaf.reader.field_info(field).unwrap();
+                assert_eq!(
+                    info.attribute(PER_FIELD_POSTING_FORMAT_KEY).unwrap(),
+                    BLOOM_CODEC_NAME
+                );
+            }
+            let terms = leaf.reader.terms("id").unwrap().unwrap();
+            let filter = terms.bloom_filter().unwrap();
+            let tag_terms = leaf.reader.terms("tag").unwrap().unwrap();
+            assert!(tag_terms.bloom_filter().is_none());
+
+            for prefix in &["a", "b", "c", "x"] {
+                for i in 0..20 {
+                    let id = format!("{}{}", prefix, i);
+                    // a fresh iterator has no block of the terms dict loaded yet
+                    let mut iter = terms.iterator().unwrap();
+                    let before = mock_dir.bytes_read("tim");
+                    let found = iter.seek_exact(id.as_bytes()).unwrap();
+                    let read = mock_dir.bytes_read("tim") - before;
+                    if prefix == own_prefix {
+                        assert!(found);
+                        assert!(read > 0);
+                    }
+                    if !filter.may_contain(id.as_bytes()) {
+                        assert!(!found);
+                        assert_eq!(read, 0);
+                        skipped += 1;
+                    }
+                }
+            }
+        }
+        // 60 absent ids per segment, all but a few false positives are skipped
+        assert!(skipped > 150);
+
+        // merged segments rebuild the filter from the merged terms
+        drop(leaves);
+        drop(reader);
+        writer.force_merge(1, true).unwrap();
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 1);
+        let terms = leaves[0].reader.terms("id").unwrap().unwrap();
+        assert!(terms.bloom_filter().is_some());
+        let mut iter = terms.iterator().unwrap();
+        for prefix in &["a", "b", "c"] {
+            for i in 0..20 {
+                assert!(iter
+                    .seek_exact(format!("{}{}", prefix, i).as_bytes())
+                    .unwrap());
+            }
+        }
+        assert!(!iter.seek_exact(b"x0").unwrap());
+    }
+
+    #[test]
+    fn test_segments_without_bloom_filters_record_lucene50() {
+        let (_dir, writer) = new_fs_writer();
+        writer
+            .add_document(vec![keyword_field("id", "a0")])
+            .unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        let info = leaves[0].reader.field_info("id").unwrap();
+        assert_eq!(
+            info.attribute(PER_FIELD_POSTING_FORMAT_KEY).unwrap(),
+            "Lucene50"
+        );
+        let terms = leaves[0].reader.terms("id").unwrap().unwrap();
+        assert!(terms.bloom_filter().is_none());
+        assert!(terms.iterator().unwrap().seek_exact(b"a0").unwrap());
+    }
+}
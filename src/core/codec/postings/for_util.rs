@@ -12,6 +12,7 @@
 // limitations under the License.
 
 use std::cmp::max;
+use std::collections::BTreeMap;
 use std::sync::{Arc, Once};
 
 use core::codec::postings::posting_format::BLOCK_SIZE;
@@ -20,7 +21,7 @@ use core::util::packed::*;
 use core::util::{BitSet, BitsRequired, DocId, FixedBitSet};
 
 use core::codec::postings::{EfWriterMeta, EncodeType, PartialBlockDecoder, SIMDBlockDecoder};
-use error::Result;
+use error::{ErrorKind::IllegalArgument, Result};
 use std::mem::MaybeUninit;
 use std::ptr;
 use std::slice::{from_raw_parts, from_raw_parts_mut};
@@ -229,7 +230,7 @@ impl ForUtilInstance {
             };
             p.parse_from(input, encoded_size as usize, num_bits, format)?;
         } else if by_simd {
-            let encoded = unsafe { input.get_and_advance(SIMD_ENCODE_SIZE[num_bits]) };
+            let encoded = unsafe { input.get_and_advance(SIMD_ENCODE_SIZE[num_bits])? };
             let decoded = unsafe {
                 from_raw_parts_mut(decoded.as_mut_ptr() as *mut u32, BLOCK_SIZE as usize)
             };
@@ -308,6 +309,25 @@ impl ForUtil {
             .read_block(input, encoded, decoded, encode_type, None, by_simd)
     }
 
+    /// Reads a block written by `write_partial_block` into `decoded` and returns its
+    /// length, only the first `len` values of `decoded` are meaningful.
+    pub fn read_partial_block(
+        &self,
+        input: &mut dyn IndexInput,
+        encoded: &mut [u8],
+        decoded: &mut [i32],
+    ) -> Result<usize> {
+        let len = input.read_vint()?;
+        if len < 0 || len > BLOCK_SIZE {
+            bail!(IllegalArgument(format!(
+                "invalid partial block length {}, expected at most {}",
+                len, BLOCK_SIZE
+            )));
+        }
+        self.read_block(input, encoded, decoded, None, false)?;
+        Ok(len as usize)
+    }
+
     pub fn read_block_only(
         &self,
         input: &mut dyn IndexInput,
@@ -371,26 +391,36 @@ impl ForUtil {
         Ok(())
     }
 
-    fn is_all_equal(data: &[i32]) -> bool {
-        assert!(!data.is_empty());
-        let v = data[0];
-        for i in &data[1..BLOCK_SIZE as usize] {
-            if *i != v {
-                return false;
+    /// Returns the number of bits needed to pack the values of a block, or
+    /// `ALL_VALUES_EQUAL` if they are all the same, in a single pass over the block.
+    ///
+    /// Blocks only hold non-negative values, e.g. doc deltas and freqs. A negative value
+    /// would take its sign bit into the count and get the whole block encoded with 32 bits
+    /// per value, decoding to different values, so it is rejected unless all the values
+    /// are equal and the block is stored as a single vint.
+    fn bits_required(data: &[i32]) -> Result<i32> {
+        let block = &data[..BLOCK_SIZE as usize];
+        let first = block[0];
+        let mut or = 0;
+        let mut all_equal = true;
+        let mut negative = None;
+        for (i, &v) in block.iter().enumerate() {
+            or |= v;
+            all_equal &= v == first;
+            if v < 0 && negative.is_none() {
+                negative = Some((i, v));
             }
         }
-        true
-    }
-
-    fn bits_required(data: &[i32]) -> i32 {
-        let mut or = 0;
-        for i in &data[..BLOCK_SIZE as usize] {
-            debug_assert!(*i >= 0);
-            or |= *i;
+        if all_equal {
+            return Ok(ALL_VALUES_EQUAL);
         }
-
-        debug_assert!(or >= 0);
-        or.bits_required() as i32
+        if let Some((i, v)) = negative {
+            bail!(IllegalArgument(format!(
+                "can't encode negative value {} at {} of a block",
+                v, i
+            )));
+        }
+        Ok(or.bits_required() as i32)
     }
 
     pub fn write_block(
@@ -401,12 +431,13 @@ impl ForUtil {
         ef_writer_meta: Option<&mut EfWriterMeta>,
         by_simd: bool,
     ) -> Result<()> {
-        if Self::is_all_equal(data) {
+        let num_bits = Self::bits_required(data)?;
+        if num_bits == ALL_VALUES_EQUAL {
             out.write_byte(0)?;
             return out.write_vint(data[0]);
         }
 
-        let num_bits = Self::bits_required(data) as usize;
+        let num_bits = num_bits as usize;
         assert!(num_bits > 0 && num_bits <= 32);
 
         let iters = self.instance.iterations[num_bits - 1];
@@ -483,12 +514,13 @@ impl ForUtil {
         encoded: &mut [u8],
         out: &mut impl IndexOutput,
     ) -> Result<()> {
-        if Self::is_all_equal(data) {
+        let num_bits = Self::bits_required(data)?;
+        if num_bits == ALL_VALUES_EQUAL {
             out.write_byte(0)?;
             return out.write_vint(data[0]);
         }
 
-        let num_bits = Self::bits_required(data) as usize;
+        let num_bits = num_bits as usize;
         assert!(num_bits > 0 && num_bits <= 32);
 
         out.write_byte(num_bits as u8)?;
@@ -497,10 +529,113 @@ impl ForUtil {
         out.write_bytes(encoded, 0, num_bits * BLOCK_SIZE as usize / 8)
     }
 
+    /// Writes the first `len` values of `data`, e.g. the last block of a postings list,
+    /// which are less than `BLOCK_SIZE`.
+    ///
+    /// The values are zero-padded to a full block, prefixed by the vint of `len` so that
+    /// `read_partial_block` only returns the real values.
+    pub fn write_partial_block(
+        &self,
+        data: &[i32],
+        len: usize,
+        encoded: &mut [u8],
+        out: &mut impl IndexOutput,
+    ) -> Result<()> {
+        if len > BLOCK_SIZE as usize || len > data.len() {
+            bail!(IllegalArgument(format!(
+                "can't write a partial block of {} values from {} values, at most {} allowed",
+                len,
+                data.len(),
+                BLOCK_SIZE
+            )));
+        }
+        let mut block = [0i32; MAX_DATA_SIZE];
+        block[..len].copy_from_slice(&data[..len]);
+        // check the values before anything of the block is written
+        Self::bits_required(&block)?;
+        out.write_vint(len as i32)?;
+        self.write_block(&block, encoded, out, None, false)
+    }
+
     pub fn skip_block(&self, input: &mut dyn IndexInput) -> Result<()> {
         self.instance.skip_block(input)
     }
 
+    /// Returns the number of bits per value of the block `input` is positioned at, read
+    /// from its header byte without moving `input`. `ALL_VALUES_EQUAL` (0) means the block
+    /// only holds the vint of its single value.
+    pub fn peek_num_bits(input: &mut dyn IndexInput) -> Result<u8> {
+        let fp = input.file_pointer();
+        let code = input.read_byte()?;
+        input.seek(fp)?;
+        Ok(code & 0x3F)
+    }
+
+    /// Returns the on-disk size, header byte included, of a block written by `write_block`
+    /// with `num_bits` bits per value, without decoding it.
+    ///
+    /// The size of an `ALL_VALUES_EQUAL` block depends on the length of the vint of its
+    /// value, only the header byte is counted for it, use `peek_block_byte_size` to read
+    /// it. Blocks written by `write_block_by_simd` take `num_bits * BLOCK_SIZE / 8` bytes
+    /// after the header instead.
+    pub fn block_byte_size(&self, num_bits: usize) -> usize {
+        debug_assert!(num_bits <= 32);
+        if num_bits as i32 == ALL_VALUES_EQUAL {
+            1
+        } else {
+            1 + self.instance.encoded_sizes[num_bits - 1] as usize
+        }
+    }
+
+    /// Returns the on-disk size of the block `input` is positioned at, without moving
+    /// `input`. Only the vint of `ALL_VALUES_EQUAL` blocks is read.
+    pub fn peek_block_byte_size(&self, input: &mut dyn IndexInput) -> Result<usize> {
+        let fp = input.file_pointer();
+        let num_bits = input.read_byte()? & 0x3F;
+        let size = if i32::from(num_bits) == ALL_VALUES_EQUAL {
+            input.read_vint()?;
+            (input.file_pointer() - fp) as usize
+        } else {
+            self.block_byte_size(num_bits as usize)
+        };
+        input.seek(fp)?;
+        Ok(size)
+    }
+
+    /// Reads over the block `input` is positioned at, as written by `write_block` with the
+    /// same `by_simd`, and counts how it was encoded into `summary`.
+    pub fn summarize_block(
+        &self,
+        input: &mut dyn IndexInput,
+        by_simd: bool,
+        summary: &mut FormatSummary,
+    ) -> Result<()> {
+        let mut encoded = [0u8; MAX_ENCODED_SIZE];
+        let mut decoded = [0i32; MAX_DATA_SIZE];
+        let mut encode_type = EncodeType::PF;
+        let num_bits = Self::peek_num_bits(input)?;
+        self.read_block(
+            input,
+            &mut encoded,
+            &mut decoded,
+            Some(&mut encode_type),
+            by_simd,
+        )?;
+        match encode_type {
+            EncodeType::PF => *summary.bits_per_value.entry(num_bits).or_insert(0) += 1,
+            EncodeType::EF => summary.elias_fano += 1,
+            EncodeType::BITSET => summary.bitset += 1,
+            EncodeType::FULL => summary.full += 1,
+        }
+        ForUtil::read_other_encode_block(
+            input,
+            &mut None,
+            &encode_type,
+            &mut FixedBitSet::default(),
+            &mut 0,
+        )
+    }
+
     #[inline]
     pub fn encode_type_from_code(code: u8) -> EncodeType {
         match code >> 6 {
@@ -523,3 +658,267 @@ impl ForUtil {
         code << 6
     }
 }
+
+/// How a set of blocks written by `ForUtil` were encoded, as counted by
+/// `ForUtil::summarize_block`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormatSummary {
+    /// The number of packed blocks by bits per value, the `ALL_VALUES_EQUAL` blocks are
+    /// counted under 0.
+    pub bits_per_value: BTreeMap<u8, u64>,
+    /// The number of doc blocks encoded with Elias-Fano.
+    pub elias_fano: u64,
+    /// The number of doc blocks encoded as a bitset.
+    pub bitset: u64,
+    /// The number of doc blocks flagged as `EncodeType::FULL`.
+    pub full: u64,
+}
+
+impl FormatSummary {
+    pub fn num_blocks(&self) -> u64 {
+        self.bits_per_value.values().sum::<u64>() + self.elias_fano + self.bitset + self.full
+    }
+
+    /// The number of blocks stored as their single value.
+    pub fn all_values_equal(&self) -> u64 {
+        self.bits_per_value
+            .get(&(ALL_VALUES_EQUAL as u8))
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// The largest number of bits per value of the packed blocks, if any.
+    pub fn max_bits_per_value(&self) -> Option<u8> {
+        self.bits_per_value.keys().next_back().cloned()
+    }
+
+    pub fn merge(&mut self, other: &FormatSummary) {
+        for (&bits, &count) in &other.bits_per_value {
+            *self.bits_per_value.entry(bits).or_insert(0) += count;
+        }
+        self.elias_fano += other.elias_fano;
+        self.bitset += other.bitset;
+        self.full += other.full;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::store::directory::{Directory, FSDirectory};
+    use core::store::io::DataInput;
+    use core::store::IOContext;
+    use core::test_util::xorshift;
+
+    #[test]
+    fn test_block_byte_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = FSDirectory::with_path(dir.path()).unwrap();
+        let mut encoded = [0u8; MAX_ENCODED_SIZE];
+        let mut blocks = vec![];
+        for bpv in 1..32 {
+            let max = 1i64 << bpv;
+            let mut block = vec![0i32; MAX_DATA_SIZE];
+            for (i, v) in block[..BLOCK_SIZE as usize].iter_mut().enumerate() {
+                *v = (i as i64 * 7919 % max) as i32;
+            }
+            block[1] = (max - 1) as i32;
+            blocks.push(block);
+        }
+        // single value blocks, with one and two bytes vints
+        blocks.push(vec![5; MAX_DATA_SIZE]);
+        blocks.push(vec![300; MAX_DATA_SIZE]);
+
+        for &ratio in &[COMPACT, FASTEST] {
+            let name = format!("blocks_{}", ratio);
+            {
+                let mut output = directory.create_output(&name, &IOContext::Default).unwrap();
+                let for_util = ForUtil::with_output(ratio, &mut output).unwrap();
+                for block in &blocks {
+                    for_util
+                        .write_block(block, &mut encoded, &mut output, None, false)
+                        .unwrap();
+                }
+            }
+
+            let mut input = directory.open_input(&name, &IOContext::READ).unwrap();
+            let for_util = ForUtil::with_input(input.as_mut()).unwrap();
+            let mut decoded = [0i32; MAX_DATA_SIZE];
+            for (i, block) in blocks.iter().enumerate() {
+                let start = input.file_pointer();
+                let num_bits = ForUtil::peek_num_bits(input.as_mut()).unwrap();
+                let size = for_util.peek_block_byte_size(input.as_mut()).unwrap();
+                assert_eq!(input.file_pointer(), start);
+                if i < 31 {
+                    assert_eq!(num_bits as usize, i + 1);
+                    assert_eq!(for_util.block_byte_size(num_bits as usize), size);
+                } else {
+                    assert_eq!(i32::from(num_bits), ALL_VALUES_EQUAL);
+                    assert_eq!(size, i - 29);
+                }
+
+                for_util
+                    .read_block(input.as_mut(), &mut encoded, &mut decoded, None, false)
+                    .unwrap();
+                assert_eq!((input.file_pointer() - start) as usize, size);
+                let n = BLOCK_SIZE as usize;
+                assert_eq!(&decoded[..n], &block[..n]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reject_negative_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = FSDirectory::with_path(dir.path()).unwrap();
+        let mut output = directory
+            .create_output("blocks", &IOContext::Default)
+            .unwrap();
+        let for_util = ForUtil::with_output(COMPACT, &mut output).unwrap();
+        let mut encoded = [0u8; MAX_ENCODED_SIZE];
+
+        let mut block = vec![1i32; MAX_DATA_SIZE];
+        block[0] = 3;
+        assert_eq!(ForUtil::bits_required(&block).unwrap(), 2);
+        block[BLOCK_SIZE as usize - 1] = -1;
+        assert!(ForUtil::bits_required(&block).is_err());
+
+        let fp = output.file_pointer();
+        assert!(for_util
+            .write_block(&block, &mut encoded, &mut output, None, false)
+            .is_err());
+        assert!(for_util
+            .write_block_by_simd(&block, &mut encoded, &mut output)
+            .is_err());
+        // nothing of the rejected block was written
+        assert_eq!(output.file_pointer(), fp);
+
+        // a block of one negative value is stored as is
+        let block = vec![-1i32; MAX_DATA_SIZE];
+        for_util
+            .write_block(&block, &mut encoded, &mut output, None, false)
+            .unwrap();
+    }
+
+    // the encoder before the all-equal check and the bit count shared their scan
+    fn write_block_reference(
+        for_util: &ForUtil,
+        data: &[i32],
+        encoded: &mut [u8],
+        out: &mut impl IndexOutput,
+    ) {
+        let block = &data[..BLOCK_SIZE as usize];
+        if block.iter().all(|&v| v == block[0]) {
+            out.write_byte(0).unwrap();
+            out.write_vint(data[0]).unwrap();
+            return;
+        }
+        let num_bits = block.iter().fold(0, |or, &v| or | v).bits_required() as usize;
+        let iters = for_util.instance.iterations[num_bits - 1];
+        let encoder = unsafe { &for_util.instance.encoders.get_ref()[num_bits - 1] };
+        let encoded_size = for_util.instance.encoded_sizes[num_bits - 1];
+        out.write_byte(num_bits as u8).unwrap();
+        encoder.encode_int_to_byte(data, encoded, iters as usize);
+        out.write_bytes(encoded, 0, encoded_size as usize).unwrap();
+    }
+
+    fn random_blocks(count: usize) -> Vec<Vec<i32>> {
+        let mut next = xorshift(0x2545_f491_4f6c_dd1d);
+        (0..count)
+            .map(|i| {
+                let bpv = i % 31 + 1;
+                let mut block = vec![0i32; MAX_DATA_SIZE];
+                for v in &mut block[..BLOCK_SIZE as usize] {
+                    *v = (next() % (1u64 << bpv)) as i32;
+                }
+                block
+            })
+            .collect()
+    }
+
+    fn read_file(directory: &FSDirectory, name: &str) -> Vec<u8> {
+        let mut input = directory.open_input(name, &IOContext::READ).unwrap();
+        let mut bytes = vec![0u8; input.len() as usize];
+        input.read_bytes(&mut bytes, 0, bytes.len()).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_single_pass_scan_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = FSDirectory::with_path(dir.path()).unwrap();
+        let mut encoded = [0u8; MAX_ENCODED_SIZE];
+        let mut blocks = random_blocks(100);
+        blocks.push(vec![0; MAX_DATA_SIZE]);
+        blocks.push(vec![7; MAX_DATA_SIZE]);
+        // only the last value differs
+        let mut block = vec![7; MAX_DATA_SIZE];
+        block[BLOCK_SIZE as usize - 1] = 8;
+        blocks.push(block);
+
+        for &ratio in &[COMPACT, FASTEST] {
+            for &(name, reference) in &[("actual", false), ("expected", true)] {
+                let mut output = directory.create_output(name, &IOContext::Default).unwrap();
+                let for_util = ForUtil::with_output(ratio, &mut output).unwrap();
+                for block in &blocks {
+                    if reference {
+                        write_block_reference(&for_util, block, &mut encoded, &mut output);
+                    } else {
+                        for_util
+                            .write_block(block, &mut encoded, &mut output, None, false)
+                            .unwrap();
+                    }
+                }
+            }
+            assert_eq!(
+                read_file(&directory, "actual"),
+                read_file(&directory, "expected")
+            );
+            directory.delete_file("actual").unwrap();
+            directory.delete_file("expected").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_partial_block_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = FSDirectory::with_path(dir.path()).unwrap();
+        let mut encoded = [0u8; MAX_ENCODED_SIZE];
+        let len = 37;
+        let data: Vec<i32> = (0..len as i32).map(|i| i * 31 % 97 + 1).collect();
+
+        {
+            let mut output = directory
+                .create_output("partial", &IOContext::Default)
+                .unwrap();
+            let for_util = ForUtil::with_output(COMPACT, &mut output).unwrap();
+            for_util
+                .write_partial_block(&data, len, &mut encoded, &mut output)
+                .unwrap();
+            // a partial block of a single value
+            for_util
+                .write_partial_block(&[9; 3], 3, &mut encoded, &mut output)
+                .unwrap();
+            let too_long = vec![1; BLOCK_SIZE as usize + 1];
+            assert!(for_util
+                .write_partial_block(&too_long, too_long.len(), &mut encoded, &mut output)
+                .is_err());
+        }
+
+        let mut input = directory.open_input("partial", &IOContext::READ).unwrap();
+        let for_util = ForUtil::with_input(input.as_mut()).unwrap();
+        let mut decoded = [-1i32; MAX_DATA_SIZE];
+        let n = for_util
+            .read_partial_block(input.as_mut(), &mut encoded, &mut decoded)
+            .unwrap();
+        assert_eq!(n, len);
+        assert_eq!(&decoded[..n], &data[..]);
+        let n = for_util
+            .read_partial_block(input.as_mut(), &mut encoded, &mut decoded)
+            .unwrap();
+        assert_eq!(&decoded[..n], &[9, 9, 9]);
+        assert_eq!(input.file_pointer() as u64, input.len());
+    }
+}
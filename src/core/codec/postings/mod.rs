@@ -25,6 +25,10 @@ mod per_field_postings_format;
 
 pub use self::per_field_postings_format::*;
 
+mod bloom_filter_postings_format;
+
+pub use self::bloom_filter_postings_format::*;
+
 mod posting_reader;
 
 pub use self::posting_reader::*;
@@ -121,6 +125,7 @@ pub trait PostingsFormat {
 /// composite `PostingsFormat` use for `CodecEnum`
 pub enum PostingsFormatEnum {
     Lucene50(Lucene50PostingsFormat),
+    BloomFilter(BloomFilteringPostingsFormat),
 }
 
 impl PostingsFormat for PostingsFormatEnum {
@@ -134,6 +139,9 @@ impl PostingsFormat for PostingsFormatEnum {
             PostingsFormatEnum::Lucene50(f) => {
                 Ok(FieldsProducerEnum::Lucene50(f.fields_producer(state)?))
             }
+            PostingsFormatEnum::BloomFilter(f) => {
+                Ok(FieldsProducerEnum::Lucene50(f.fields_producer(state)?))
+            }
         }
     }
 
@@ -143,12 +151,14 @@ impl PostingsFormat for PostingsFormatEnum {
     ) -> Result<FieldsConsumerEnum<D, DW, C>> {
         match self {
             PostingsFormatEnum::Lucene50(f) => f.fields_consumer(state),
+            PostingsFormatEnum::BloomFilter(f) => f.fields_consumer(state),
         }
     }
 
     fn name(&self) -> &str {
         match self {
             PostingsFormatEnum::Lucene50(f) => f.name(),
+            PostingsFormatEnum::BloomFilter(f) => f.name(),
         }
     }
 }
@@ -158,6 +168,9 @@ pub fn postings_format_for_name(name: &str) -> Result<PostingsFormatEnum> {
         "Lucene50" => Ok(PostingsFormatEnum::Lucene50(
             Lucene50PostingsFormat::default(),
         )),
+        "BloomFilter" => Ok(PostingsFormatEnum::BloomFilter(
+            BloomFilteringPostingsFormat::default(),
+        )),
         _ => bail!(IllegalArgument(format!(
             "Invalid postings format: {}",
             name
@@ -224,6 +237,7 @@ pub trait FieldsConsumer {
 pub enum FieldsConsumerEnum<D: Directory, DW: Directory, C: Codec> {
     Lucene50(BlockTreeTermsWriter<Lucene50PostingsWriter<DW::IndexOutput>, DW::IndexOutput>),
     PerField(PerFieldFieldsWriter<D, DW, C>),
+    BloomFilter(Box<BloomFilteredFieldsWriter<D, DW, C>>),
 }
 
 impl<D: Directory, DW: Directory, C: Codec> FieldsConsumer for FieldsConsumerEnum<D, DW, C> {
@@ -231,6 +245,7 @@ impl<D: Directory, DW: Directory, C: Codec> FieldsConsumer for FieldsConsumerEnu
         match self {
             FieldsConsumerEnum::Lucene50(w) => w.write(fields),
             FieldsConsumerEnum::PerField(w) => w.write(fields),
+            FieldsConsumerEnum::BloomFilter(w) => w.write(fields),
         }
     }
 
@@ -241,6 +256,7 @@ impl<D: Directory, DW: Directory, C: Codec> FieldsConsumer for FieldsConsumerEnu
         match self {
             FieldsConsumerEnum::Lucene50(w) => w.merge(merge_state),
             FieldsConsumerEnum::PerField(w) => w.merge(merge_state),
+            FieldsConsumerEnum::BloomFilter(w) => w.merge(merge_state),
         }
     }
 }
@@ -11,16 +11,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
 use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::mem;
 use std::sync::Arc;
 
 use core::codec::postings::blocktree::FieldReaderRef;
 use core::codec::postings::{
-    postings_format_for_name, FieldsConsumer, FieldsConsumerEnum, FieldsProducer,
-    FieldsProducerEnum, Lucene50PostingsFormat, PostingsFormat,
+    postings_format_for_name, BloomFilteringPostingsFormat, FieldsConsumer, FieldsConsumerEnum,
+    FieldsProducer, FieldsProducerEnum, Lucene50PostingsFormat, PostingsFormat, PostingsFormatEnum,
 };
 use core::codec::segment_infos::{SegmentReadState, SegmentWriteState};
 use core::codec::{Codec, Fields};
@@ -54,12 +54,26 @@ fn get_suffix(format: &str, suffix: &str) -> String {
 /// filenames would look like <tt>_1_Lucene40_0.prx</tt>.
 /// @see ServiceLoader
 /// @lucene.experimental
-#[derive(Copy, Clone)]
-pub struct PerFieldPostingsFormat;
+#[derive(Clone)]
+pub struct PerFieldPostingsFormat {
+    bloom_filter_fields: Arc<HashSet<String>>,
+}
 
 impl Default for PerFieldPostingsFormat {
     fn default() -> PerFieldPostingsFormat {
-        PerFieldPostingsFormat {}
+        PerFieldPostingsFormat {
+            bloom_filter_fields: Arc::new(HashSet::new()),
+        }
+    }
+}
+
+impl PerFieldPostingsFormat {
+    /// Keeps a bloom filter of the terms of `fields` in each segment, see
+    /// `BloomFilteringPostingsFormat`.
+    pub fn with_bloom_filter_fields(fields: HashSet<String>) -> PerFieldPostingsFormat {
+        PerFieldPostingsFormat {
+            bloom_filter_fields: Arc::new(fields),
+        }
     }
 }
 
@@ -78,6 +92,7 @@ impl PostingsFormat for PerFieldPostingsFormat {
     ) -> Result<FieldsConsumerEnum<D, DW, C>> {
         Ok(FieldsConsumerEnum::PerField(PerFieldFieldsWriter::new(
             state,
+            Arc::clone(&self.bloom_filter_fields),
         )))
     }
 
@@ -181,12 +196,17 @@ struct FieldsGroup<D: Directory, DW: Directory, C: Codec> {
 
 pub struct PerFieldFieldsWriter<D: Directory, DW: Directory, C: Codec> {
     write_state: SegmentWriteState<D, DW, C>,
+    bloom_filter_fields: Arc<HashSet<String>>,
 }
 
 impl<D: Directory, DW: Directory, C: Codec> PerFieldFieldsWriter<D, DW, C> {
-    pub fn new(write_state: &SegmentWriteState<D, DW, C>) -> Self {
+    pub fn new(
+        write_state: &SegmentWriteState<D, DW, C>,
+        bloom_filter_fields: Arc<HashSet<String>>,
+    ) -> Self {
         PerFieldFieldsWriter {
             write_state: write_state.clone(),
+            bloom_filter_fields,
         }
     }
 
@@ -197,17 +217,34 @@ impl<D: Directory, DW: Directory, C: Codec> PerFieldFieldsWriter<D, DW, C> {
 }
 
 impl<D: Directory, DW: Directory, C: Codec> FieldsConsumer for PerFieldFieldsWriter<D, DW, C> {
-    // TODO this is only one postings format currently (lucene50).
-    // And we assume that we always use one format all the time.
+    // TODO there are only two postings formats currently (lucene50 and bloom filter).
+    // And we assume that a segment uses one format for all its fields.
     // so we won't implement this like lucene
-    // when the format changes, it's easy to change the hard code then.
     fn write(&mut self, fields: &impl Fields) -> Result<()> {
-        // this is only one format, so suffix is always "0"
-        let segment_suffix =
-            self.get_full_segment_suffix(&self.write_state.segment_suffix, "Lucene50_0".into());
-        // always use lucene50, so just hard code it.
-        let format = Lucene50PostingsFormat::default();
-
+        // bloom filters are only a side file of the lucene50 terms dict
+        let format = if self.bloom_filter_fields.is_empty() {
+            PostingsFormatEnum::Lucene50(Lucene50PostingsFormat::default())
+        } else {
+            PostingsFormatEnum::BloomFilter(BloomFilteringPostingsFormat::new(Arc::clone(
+                &self.bloom_filter_fields,
+            )))
+        };
+        // the format is recorded for each field, and read back by `PerFieldFieldsReader`.
+        // there is only one format per segment, so suffix is always "0"
+        let suffix = "0";
+        for info in self.write_state.field_infos.by_number.values() {
+            if info.index_options != IndexOptions::Null {
+                info.put_attribute(
+                    PER_FIELD_POSTING_FORMAT_KEY.to_string(),
+                    format.name().to_string(),
+                );
+                info.put_attribute(PER_FIELD_POSTING_SUFFIX_KEY.to_string(), suffix.to_string());
+            }
+        }
+        let segment_suffix = self.get_full_segment_suffix(
+            &self.write_state.segment_suffix,
+            get_suffix(format.name(), suffix),
+        );
         let old_suffix = mem::replace(&mut self.write_state.segment_suffix, segment_suffix);
 
         let mut consumer = format.fields_consumer(&self.write_state)?;
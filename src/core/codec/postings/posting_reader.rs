@@ -30,6 +30,7 @@ use error::{ErrorKind::IllegalState, Result};
 use core::codec::postings::{PartialBlockDecoder, SIMDBlockDecoder};
 use core::util::packed::{EliasFanoDecoder, SIMD128Packer, SIMDPacker, NO_MORE_VALUES};
 use std::intrinsics::{likely, unlikely};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 /// Filename extension for document number, frequencies, and skip data.
@@ -73,8 +74,14 @@ pub struct Lucene50PostingsReader {
     pub version: i32,
     pub for_util: ForUtil,
     use_simd: bool,
+    // identifies the reader to its iterators, unique over the life of the process
+    id: usize,
 }
 
+// the ids of the opened postings readers, unlike the addresses of the readers they are
+// never reused once a reader is dropped
+static NEXT_READER_ID: AtomicUsize = AtomicUsize::new(0);
+
 impl Lucene50PostingsReader {
     fn clone_pos_in(&self) -> Result<Box<dyn IndexInput>> {
         clone_option_index_input(&self.pos_in)
@@ -154,6 +161,7 @@ impl Lucene50PostingsReader {
             version,
             for_util,
             use_simd,
+            id: NEXT_READER_ID.fetch_add(1, Ordering::Relaxed),
         })
     }
 
@@ -191,59 +199,150 @@ impl Lucene50PostingsReader {
         field_info: &FieldInfo,
         state: &BlockTermState,
         flags: u16,
+    ) -> Result<Lucene50PostingIterator> {
+        self.postings_with_reuse(field_info, state, None, flags)
+    }
+
+    /// Like `postings`, but resets `reuse` in place when it was created by this reader for
+    /// the same field and with the same kind of iterator the flags call for, instead of
+    /// allocating a new one.
+    pub fn postings_with_reuse(
+        &self,
+        field_info: &FieldInfo,
+        state: &BlockTermState,
+        reuse: Option<Lucene50PostingIterator>,
+        flags: u16,
     ) -> Result<Lucene50PostingIterator> {
         let options = &field_info.index_options;
         let index_has_positions = options.has_positions();
         let index_has_offsets = options.has_offsets();
         let index_has_payloads = field_info.has_store_payloads;
+        let reader_id = self.reader_id();
+        let reuse = reuse.filter(|r| r.reader == reader_id && r.field == field_info.number);
 
         if !index_has_positions
             || !PostingIteratorFlags::feature_requested(flags, PostingIteratorFlags::POSITIONS)
         {
-            Ok(Lucene50PostingIterator(Lucene50PostingIterEnum::Doc(
-                BlockDocIterator::new(
-                    self.doc_in.clone()?,
-                    field_info,
-                    state,
-                    flags,
-                    self.for_util.clone(),
-                    self.use_simd,
-                )?,
-            )))
+            if let Some(mut reuse) = reuse {
+                if let Lucene50PostingIterEnum::Doc(ref mut i) = reuse.iter {
+                    i.reset(state, flags)?;
+                    return Ok(reuse);
+                }
+            }
+            let iter = BlockDocIterator::new(
+                self.doc_in.clone()?,
+                field_info,
+                state,
+                flags,
+                self.for_util.clone(),
+                self.use_simd,
+            )?;
+            Ok(self.new_iterator(field_info, Lucene50PostingIterEnum::Doc(iter)))
         } else if (!index_has_offsets
             || !PostingIteratorFlags::feature_requested(flags, PostingIteratorFlags::OFFSETS))
             && (!index_has_payloads
                 || !PostingIteratorFlags::feature_requested(flags, PostingIteratorFlags::PAYLOADS))
         {
-            Ok(Lucene50PostingIterator(Lucene50PostingIterEnum::Posting(
-                BlockPostingIterator::new(
-                    self.doc_in.clone()?,
-                    self.clone_pos_in()?,
-                    field_info,
-                    state,
-                    flags,
-                    self.for_util.clone(),
-                    self.use_simd,
-                )?,
-            )))
+            if let Some(mut reuse) = reuse {
+                if let Lucene50PostingIterEnum::Posting(ref mut i) = reuse.iter {
+                    i.reset(state)?;
+                    return Ok(reuse);
+                }
+            }
+            let iter = BlockPostingIterator::new(
+                self.doc_in.clone()?,
+                self.clone_pos_in()?,
+                field_info,
+                state,
+                flags,
+                self.for_util.clone(),
+                self.use_simd,
+            )?;
+            Ok(self.new_iterator(field_info, Lucene50PostingIterEnum::Posting(iter)))
         } else {
             debug_assert!(self.pos_in.is_some());
             debug_assert!(self.pay_in.is_some());
-            Ok(Lucene50PostingIterator(
-                Lucene50PostingIterEnum::Everything(EverythingIterator::new(
-                    self.doc_in.clone()?,
-                    self.clone_pos_in()?,
-                    self.clone_pay_in()?,
-                    field_info,
-                    state,
-                    flags,
-                    self.for_util.clone(),
-                    self.use_simd,
-                )?),
-            ))
+            if let Some(mut reuse) = reuse {
+                if let Lucene50PostingIterEnum::Everything(ref mut i) = reuse.iter {
+                    i.reset(state, flags)?;
+                    return Ok(reuse);
+                }
+            }
+            let iter = EverythingIterator::new(
+                self.doc_in.clone()?,
+                self.clone_pos_in()?,
+                self.clone_pay_in()?,
+                field_info,
+                state,
+                flags,
+                self.for_util.clone(),
+                self.use_simd,
+            )?;
+            Ok(self.new_iterator(field_info, Lucene50PostingIterEnum::Everything(iter)))
         }
     }
 
+    // identifies the files an iterator reads from
+    fn reader_id(&self) -> usize {
+        self.id
+    }
+
+    fn new_iterator(
+        &self,
+        field_info: &FieldInfo,
+        iter: Lucene50PostingIterEnum,
+    ) -> Lucene50PostingIterator {
+        Lucene50PostingIterator {
+            iter,
+            reader: self.reader_id(),
+            field: field_info.number,
+        }
+    }
+
+    /// Hints the doc file that the postings of the term `state` will be read soon.
+    ///
+    /// Terms inlined into the dictionary (singleton docs) have nothing to prefetch; otherwise
+    /// the length is an upper-bound estimate of the encoded docs and freqs.
+    pub fn prefetch(&self, state: &BlockTermState) -> Result<()> {
+        if state.singleton_doc_id != -1 {
+            return Ok(());
+        }
+        let remaining = self.doc_in.len() as i64 - state.doc_start_fp;
+        let length = (i64::from(state.doc_freq) * 8).min(remaining);
+        if length > 0 {
+            self.doc_in.prefetch(state.doc_start_fp, length)?;
+        }
+        Ok(())
+    }
+
+    /// Counts how the full blocks of docs and freqs of the term `state` were encoded into
+    /// `docs` and `freqs`. The docs of the last, partial, block are vint encoded and
+    /// not counted.
+    pub fn format_summary(
+        &self,
+        field_info: &FieldInfo,
+        state: &BlockTermState,
+        docs: &mut FormatSummary,
+        freqs: &mut FormatSummary,
+    ) -> Result<()> {
+        let num_blocks = state.doc_freq / BLOCK_SIZE;
+        if state.singleton_doc_id != -1 || num_blocks == 0 {
+            return Ok(());
+        }
+        let has_freqs = field_info.index_options.has_freqs();
+        let mut doc_in = self.doc_in.clone()?;
+        doc_in.seek(state.doc_start_fp)?;
+        for _ in 0..num_blocks {
+            self.for_util
+                .summarize_block(doc_in.as_mut(), self.use_simd, docs)?;
+            if has_freqs {
+                self.for_util
+                    .summarize_block(doc_in.as_mut(), self.use_simd, freqs)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn check_integrity(&self) -> Result<()> {
         //        codec_util::checksum_entire_file(self.doc_in.as_ref())?;
         //
@@ -2337,7 +2436,12 @@ impl DocIterator for EverythingIterator {
 }
 
 /// `PostingIterator` impl for `Lucene50PostingsReader`
-pub struct Lucene50PostingIterator(Lucene50PostingIterEnum);
+pub struct Lucene50PostingIterator {
+    iter: Lucene50PostingIterEnum,
+    // the reader and field this iterator was created for, checked before reusing it
+    reader: usize,
+    field: u32,
+}
 
 enum Lucene50PostingIterEnum {
     Doc(BlockDocIterator),
@@ -2349,7 +2453,7 @@ enum Lucene50PostingIterEnum {
 
 impl PostingIterator for Lucene50PostingIterator {
     fn freq(&self) -> Result<i32> {
-        match &self.0 {
+        match &self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.freq(),
             Lucene50PostingIterEnum::SDoc(i) => i.freq(),
             Lucene50PostingIterEnum::Posting(i) => i.freq(),
@@ -2358,7 +2462,7 @@ impl PostingIterator for Lucene50PostingIterator {
     }
 
     fn next_position(&mut self) -> Result<i32> {
-        match &mut self.0 {
+        match &mut self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.next_position(),
             Lucene50PostingIterEnum::SDoc(i) => i.next_position(),
             Lucene50PostingIterEnum::Posting(i) => i.next_position(),
@@ -2367,7 +2471,7 @@ impl PostingIterator for Lucene50PostingIterator {
     }
 
     fn start_offset(&self) -> Result<i32> {
-        match &self.0 {
+        match &self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.start_offset(),
             Lucene50PostingIterEnum::SDoc(i) => i.start_offset(),
             Lucene50PostingIterEnum::Posting(i) => i.start_offset(),
@@ -2376,7 +2480,7 @@ impl PostingIterator for Lucene50PostingIterator {
     }
 
     fn end_offset(&self) -> Result<i32> {
-        match &self.0 {
+        match &self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.end_offset(),
             Lucene50PostingIterEnum::SDoc(i) => i.end_offset(),
             Lucene50PostingIterEnum::Posting(i) => i.end_offset(),
@@ -2385,7 +2489,7 @@ impl PostingIterator for Lucene50PostingIterator {
     }
 
     fn payload(&self) -> Result<Payload> {
-        match &self.0 {
+        match &self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.payload(),
             Lucene50PostingIterEnum::SDoc(i) => i.payload(),
             Lucene50PostingIterEnum::Posting(i) => i.payload(),
@@ -2396,7 +2500,7 @@ impl PostingIterator for Lucene50PostingIterator {
 
 impl DocIterator for Lucene50PostingIterator {
     fn doc_id(&self) -> DocId {
-        match &self.0 {
+        match &self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.doc_id(),
             Lucene50PostingIterEnum::SDoc(i) => i.doc_id(),
             Lucene50PostingIterEnum::Posting(i) => i.doc_id(),
@@ -2405,7 +2509,7 @@ impl DocIterator for Lucene50PostingIterator {
     }
 
     fn next(&mut self) -> Result<DocId> {
-        match &mut self.0 {
+        match &mut self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.next(),
             Lucene50PostingIterEnum::SDoc(i) => i.next(),
             Lucene50PostingIterEnum::Posting(i) => i.next(),
@@ -2414,7 +2518,7 @@ impl DocIterator for Lucene50PostingIterator {
     }
 
     fn advance(&mut self, target: i32) -> Result<DocId> {
-        match &mut self.0 {
+        match &mut self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.advance(target),
             Lucene50PostingIterEnum::SDoc(i) => i.advance(target),
             Lucene50PostingIterEnum::Posting(i) => i.advance(target),
@@ -2423,7 +2527,7 @@ impl DocIterator for Lucene50PostingIterator {
     }
 
     fn slow_advance(&mut self, target: i32) -> Result<DocId> {
-        match &mut self.0 {
+        match &mut self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.slow_advance(target),
             Lucene50PostingIterEnum::SDoc(i) => i.slow_advance(target),
             Lucene50PostingIterEnum::Posting(i) => i.slow_advance(target),
@@ -2432,7 +2536,7 @@ impl DocIterator for Lucene50PostingIterator {
     }
 
     fn cost(&self) -> usize {
-        match &self.0 {
+        match &self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.cost(),
             Lucene50PostingIterEnum::SDoc(i) => i.cost(),
             Lucene50PostingIterEnum::Posting(i) => i.cost(),
@@ -2441,7 +2545,7 @@ impl DocIterator for Lucene50PostingIterator {
     }
 
     fn matches(&mut self) -> Result<bool> {
-        match &mut self.0 {
+        match &mut self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.matches(),
             Lucene50PostingIterEnum::SDoc(i) => i.matches(),
             Lucene50PostingIterEnum::Posting(i) => i.matches(),
@@ -2450,7 +2554,7 @@ impl DocIterator for Lucene50PostingIterator {
     }
 
     fn match_cost(&self) -> f32 {
-        match &self.0 {
+        match &self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.match_cost(),
             Lucene50PostingIterEnum::SDoc(i) => i.match_cost(),
             Lucene50PostingIterEnum::Posting(i) => i.match_cost(),
@@ -2459,7 +2563,7 @@ impl DocIterator for Lucene50PostingIterator {
     }
 
     fn approximate_next(&mut self) -> Result<DocId> {
-        match &mut self.0 {
+        match &mut self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.approximate_next(),
             Lucene50PostingIterEnum::SDoc(i) => i.approximate_next(),
             Lucene50PostingIterEnum::Posting(i) => i.approximate_next(),
@@ -2468,7 +2572,7 @@ impl DocIterator for Lucene50PostingIterator {
     }
 
     fn approximate_advance(&mut self, target: i32) -> Result<DocId> {
-        match &mut self.0 {
+        match &mut self.iter {
             Lucene50PostingIterEnum::Doc(i) => i.approximate_advance(target),
             Lucene50PostingIterEnum::SDoc(i) => i.approximate_advance(target),
             Lucene50PostingIterEnum::Posting(i) => i.approximate_advance(target),
@@ -2476,3 +2580,54 @@ impl DocIterator for Lucene50PostingIterator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use core::analysis::WhitespaceTokenizer;
+    use core::doc::{Field, FieldType, Fieldable, IndexOptions, Term};
+    use core::index::reader::{IndexReader, LeafReader};
+    use core::test_util::new_fs_writer;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_postings_without_freqs_skip_freq_blocks() {
+        let (_dir, writer) = new_fs_writer();
+        // two full packed blocks, so no doc is read from the vint encoded tail
+        for _ in 0..BLOCK_SIZE * 2 {
+            let mut field_type = FieldType::default();
+            field_type.index_options = IndexOptions::DocsAndFreqs;
+            let tokens = WhitespaceTokenizer::new(Box::new(Cursor::new(b"a a a".to_vec())));
+            let doc: Vec<Box<dyn Fieldable>> = vec![Box::new(Field::new(
+                "body".into(),
+                field_type,
+                None,
+                Some(Box::new(tokens)),
+            ))];
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 1);
+        let term = Term::new("body".into(), b"a".to_vec());
+        for &flags in &[PostingIteratorFlags::NONE, PostingIteratorFlags::FREQS] {
+            let mut postings = leaves[0].reader.postings(&term, i32::from(flags)).unwrap();
+            let postings = postings.as_mut().unwrap();
+            // freqs are only decoded when asked for, otherwise they stay at 1
+            let expected_freq = if flags == PostingIteratorFlags::NONE {
+                1
+            } else {
+                3
+            };
+            for doc in 0..BLOCK_SIZE * 2 {
+                assert_eq!(postings.next().unwrap(), doc);
+                assert_eq!(postings.freq().unwrap(), expected_freq);
+            }
+            assert_eq!(postings.next().unwrap(), NO_MORE_DOCS);
+        }
+    }
+}
@@ -78,7 +78,7 @@ impl SIMDBlockDecoder {
         num: usize,
         bits_num: usize,
     ) -> Result<()> {
-        let encoded = unsafe { input.get_and_advance(num) };
+        let encoded = unsafe { input.get_and_advance(num)? };
         self.next_index = 0;
         self.packer.delta_unpack(
             encoded,
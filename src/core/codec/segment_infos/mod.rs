@@ -31,8 +31,9 @@ use std::sync::Arc;
 use regex::Regex;
 
 use core::codec::field_infos::FieldInfos;
+use core::codec::postings::blocktree::FSTLoadMode;
 use core::codec::{Codec, LiveDocsFormat};
-use core::index::writer::BufferedUpdates;
+use core::index::writer::{BufferedUpdates, TempDirectory};
 use core::search::sort_field::Sort;
 use core::store::directory::Directory;
 use core::store::IOContext;
@@ -718,6 +719,10 @@ pub struct SegmentWriteState<D: Directory, DW: Directory, C: Codec> {
     /// {@link IOContext} for all writes; you should pass this
     /// to {@link Directory#createOutput(String,IOContext)}. */
     pub context: IOContext,
+
+    /// Directory of the transient files of the writer, `None` to write them into
+    /// `directory`, see `IndexWriterConfig#set_temp_directory`.
+    pub temp_directory: Option<Arc<dyn TempDirectory>>,
 }
 
 impl<D: Directory, DW: Directory, C: Codec> SegmentWriteState<D, DW, C> {
@@ -739,6 +744,7 @@ impl<D: Directory, DW: Directory, C: Codec> SegmentWriteState<D, DW, C> {
             live_docs: FixedBitSet::default(),
             segment_suffix,
             context,
+            temp_directory: None,
         }
     }
 
@@ -780,6 +786,7 @@ impl<D: Directory, DW: Directory, C: Codec> Clone for SegmentWriteState<D, DW, C
             // TODO, fake clone
             segment_suffix: self.segment_suffix.clone(),
             context: self.context,
+            temp_directory: self.temp_directory.clone(),
         }
     }
 }
@@ -808,6 +815,9 @@ pub struct SegmentReadState<'a, D: Directory, DW: Directory, C: Codec> {
     /// write/read must be derived using this suffix (use
     /// {@link IndexFileNames#segmentFileName(String,String,String)}).
     pub segment_suffix: String,
+
+    /// How the terms index of each field is loaded.
+    pub fst_load_mode: FSTLoadMode,
 }
 
 impl<'a, D: Directory, DW: Directory, C: Codec> SegmentReadState<'a, D, DW, C> {
@@ -824,6 +834,7 @@ impl<'a, D: Directory, DW: Directory, C: Codec> SegmentReadState<'a, D, DW, C> {
             field_infos,
             context,
             segment_suffix,
+            fst_load_mode: FSTLoadMode::default(),
         }
     }
 
@@ -831,12 +842,14 @@ impl<'a, D: Directory, DW: Directory, C: Codec> SegmentReadState<'a, D, DW, C> {
         state: &'a SegmentReadState<D, DW, C>,
         suffix: &str,
     ) -> SegmentReadState<'a, D, DW, C> {
-        Self::new(
+        let mut new_state = Self::new(
             state.directory.clone(),
             state.segment_info,
             state.field_infos.clone(),
             state.context,
             String::from(suffix),
-        )
+        );
+        new_state.fst_load_mode = state.fst_load_mode;
+        new_state
     }
 }
@@ -28,7 +28,7 @@ use error::{Error as CoreError, ErrorKind::IllegalState, Result};
 
 const MODE_KEY: &str = "Lucene50StoredFieldsFormat.mode";
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum StoredFieldCompressMode {
     BestSpeed,
     BestCompression,
@@ -73,6 +73,17 @@ impl Lucene50StoredFieldsFormat {
         }
     }
 
+    /// Returns the mode the stored fields of `si` were written with, `None` if they
+    /// weren't written by this format.
+    pub fn mode_of<D: Directory, C: Codec>(
+        si: &SegmentInfo<D, C>,
+    ) -> Result<Option<StoredFieldCompressMode>> {
+        match si.attributes.get(MODE_KEY) {
+            Some(value) => Ok(Some(StoredFieldCompressMode::from_str(value)?)),
+            None => Ok(None),
+        }
+    }
+
     pub fn format(self, mode: StoredFieldCompressMode) -> CompressingStoredFieldsFormat {
         match mode {
             StoredFieldCompressMode::BestSpeed => CompressingStoredFieldsFormat::new(
@@ -156,9 +156,10 @@ where
 {
     pub fn new(doc_writer: *mut DocumentsWriterPerThread<D, C, MS, MP>) -> Self {
         let dwpt = unsafe { &*doc_writer };
-        let dir = Arc::new(TrackingTmpOutputDirectoryWrapper::new(Arc::clone(
-            &dwpt.directory,
-        )));
+        let dir = Arc::new(TrackingTmpOutputDirectoryWrapper::new(
+            Arc::clone(&dwpt.directory),
+            dwpt.index_writer_config.temp_directory.clone(),
+        ));
         let consumer = StoredFieldsConsumerImpl::new(doc_writer, Arc::clone(&dir));
         Self {
             consumer,
@@ -200,10 +201,7 @@ where
             res
         } else {
             // we're lucky the index is already sorted, just rename the temporary file and return.
-            for (k, v) in &*self.tmp_directory.file_names.lock().unwrap() {
-                self.tmp_directory.rename(v, k)?;
-            }
-            Ok(())
+            self.tmp_directory.rename_temp_files()
         }
     }
 
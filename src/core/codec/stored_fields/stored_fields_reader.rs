@@ -32,12 +32,15 @@ use core::codec::stored_fields::{
 use core::codec::Codec;
 use core::doc::{Status as VisitStatus, StoredFieldVisitor};
 use core::store::directory::Directory;
-use core::store::io::{ByteArrayDataInput, DataInput, IndexInput};
+use core::store::io::{ByteArrayDataInput, DataInput, IndexInput, RandomAccessInput};
 use core::store::IOContext;
 use core::util::packed::{get_reader_iterator_no_header, get_reader_no_header};
+use core::util::packed::{DirectMonotonicReader, MixinMonotonicLongValues};
 use core::util::packed::{Format, OffsetAndLength, Reader, ReaderEnum, ReaderIterator};
+use core::util::packed::{MAX_BLOCK_SHIFT, MIN_BLOCK_SHIFT};
 use core::util::BytesRef;
 use core::util::DocId;
+use core::util::LongValues;
 use core::util::{CompressionMode, Decompress, Decompressor};
 use core::util::{UnsignedShift, ZigZagEncoding};
 
@@ -52,6 +55,11 @@ pub const CODEC_SFX_DAT: &str = "Data";
 pub const VERSION_START: i32 = 0;
 pub const VERSION_CHUNK_STATS: i32 = 1;
 pub const VERSION_CURRENT: i32 = VERSION_CHUNK_STATS;
+/// The index stores the doc bases and start pointers of the chunks with `DirectMonotonic`.
+///
+/// Lucene 6.2.1 doesn't read this version, so it is only written by formats that opt in
+/// with `with_monotonic_index`, under a format name of their own.
+pub const VERSION_MONOTONIC_INDEX: i32 = 2;
 
 pub const STRING: i32 = 0x00;
 pub const BYTE_ARR: i32 = 0x01;
@@ -92,6 +100,7 @@ pub struct CompressingStoredFieldsFormat {
     chunk_size: i32,
     max_docs_per_chunk: i32,
     block_size: i32,
+    monotonic_index: bool,
 }
 
 impl CompressingStoredFieldsFormat {
@@ -112,8 +121,15 @@ impl CompressingStoredFieldsFormat {
             chunk_size,
             max_docs_per_chunk,
             block_size,
+            monotonic_index: false,
         }
     }
+
+    /// Writes the index as `DirectMonotonic` sequences, see `VERSION_MONOTONIC_INDEX`.
+    pub fn with_monotonic_index(mut self) -> CompressingStoredFieldsFormat {
+        self.monotonic_index = true;
+        self
+    }
 }
 
 impl StoredFieldsFormat for CompressingStoredFieldsFormat {
@@ -159,13 +175,121 @@ impl StoredFieldsFormat for CompressingStoredFieldsFormat {
                 self.chunk_size as usize,
                 self.max_docs_per_chunk as usize,
                 self.block_size as usize,
+                self.monotonic_index,
             )?,
         ))
     }
 }
 
+/// The in-memory index of the chunks of the stored fields or term vectors data file,
+/// mapping a doc to the start pointer of its chunk.
 pub struct CompressingStoredFieldsIndexReader {
     max_doc: i32,
+    index: FieldsIndex,
+}
+
+enum FieldsIndex {
+    // blocks of chunks with the deltas from their average doc base and start pointer,
+    // written before `VERSION_MONOTONIC_INDEX`
+    Legacy(LegacyFieldsIndex),
+    Monotonic(MonotonicFieldsIndex),
+}
+
+impl CompressingStoredFieldsIndexReader {
+    // It is the responsibility of the caller to close fieldsIndexIn after this constructor
+    // has been called
+    //
+    // `monotonic` tells whether the index was written as `DirectMonotonic` sequences of the
+    // doc bases and start pointers of the chunks, see `VERSION_MONOTONIC_INDEX`.
+    pub fn new<T: IndexInput + ?Sized, D: Directory, C: Codec>(
+        fields_index_in: &mut T,
+        si: &SegmentInfo<D, C>,
+        monotonic: bool,
+    ) -> Result<CompressingStoredFieldsIndexReader> {
+        let index = if monotonic {
+            FieldsIndex::Monotonic(MonotonicFieldsIndex::new(fields_index_in)?)
+        } else {
+            FieldsIndex::Legacy(LegacyFieldsIndex::new(fields_index_in)?)
+        };
+        Ok(CompressingStoredFieldsIndexReader {
+            max_doc: si.max_doc,
+            index,
+        })
+    }
+
+    pub fn start_pointer(&self, doc_id: DocId) -> Result<i64> {
+        debug_assert!(doc_id >= 0 && doc_id < self.max_doc);
+        match self.index {
+            FieldsIndex::Legacy(ref index) => index.start_pointer(doc_id),
+            FieldsIndex::Monotonic(ref index) => index.start_pointer(doc_id),
+        }
+    }
+}
+
+/// The doc bases and start pointers of the chunks, `block_shift` chunks at a time stored
+/// as their min, average increment and packed residuals.
+struct MonotonicFieldsIndex {
+    num_chunks: i64,
+    doc_bases: MixinMonotonicLongValues,
+    start_pointers: MixinMonotonicLongValues,
+}
+
+impl MonotonicFieldsIndex {
+    fn new<T: IndexInput + ?Sized>(fields_index_in: &mut T) -> Result<MonotonicFieldsIndex> {
+        let _packed_ints_version = fields_index_in.read_vint()?;
+        let num_chunks = i64::from(fields_index_in.read_vint()?);
+        let block_shift = fields_index_in.read_vint()?;
+        if block_shift < MIN_BLOCK_SHIFT || block_shift > MAX_BLOCK_SHIFT {
+            bail!(CorruptIndex(format!("block_shift: {}", block_shift)));
+        }
+        let doc_bases_meta =
+            DirectMonotonicReader::load_meta(fields_index_in, num_chunks, block_shift)?;
+        let start_pointers_meta =
+            DirectMonotonicReader::load_meta(fields_index_in, num_chunks, block_shift)?;
+        let doc_bases_data = Self::read_data(fields_index_in)?;
+        let start_pointers_data = Self::read_data(fields_index_in)?;
+        Ok(MonotonicFieldsIndex {
+            num_chunks,
+            doc_bases: DirectMonotonicReader::get_instance(&doc_bases_meta, &doc_bases_data)?,
+            start_pointers: DirectMonotonicReader::get_instance(
+                &start_pointers_meta,
+                &start_pointers_data,
+            )?,
+        })
+    }
+
+    // the packed residuals are loaded into memory, just like the legacy index
+    fn read_data<T: IndexInput + ?Sized>(
+        fields_index_in: &mut T,
+    ) -> Result<Arc<dyn RandomAccessInput>> {
+        let length = fields_index_in.read_vlong()?;
+        if length < 0 {
+            bail!(CorruptIndex(format!("data length: {}", length)));
+        }
+        let mut bytes = vec![0u8; length as usize];
+        fields_index_in.read_bytes(&mut bytes, 0, length as usize)?;
+        let input = ByteArrayDataInput::new(bytes);
+        Ok(Arc::from(input.random_access_slice(0, length)?))
+    }
+
+    fn start_pointer(&self, doc_id: DocId) -> Result<i64> {
+        // the last chunk whose doc base is not after `doc_id`
+        let doc_id = i64::from(doc_id);
+        let mut lo = 0i64;
+        let mut hi = self.num_chunks - 1;
+        while lo < hi {
+            let mid = (lo + hi + 1) >> 1;
+            if self.doc_bases.get64(mid)? <= doc_id {
+                lo = mid;
+            } else {
+                hi = mid - 1;
+            }
+        }
+        self.start_pointers.get64(lo)
+    }
+}
+
+struct LegacyFieldsIndex {
     doc_bases: Vec<i32>,
     start_pointers: Vec<i64>,
     avg_chunk_docs: Vec<i32>,
@@ -176,13 +300,8 @@ pub struct CompressingStoredFieldsIndexReader {
     // delta from the avg
 }
 
-impl CompressingStoredFieldsIndexReader {
-    // It is the responsibility of the caller to close fieldsIndexIn after this constructor
-    // has been called
-    pub fn new<T: IndexInput + ?Sized, D: Directory, C: Codec>(
-        fields_index_in: &mut T,
-        si: &SegmentInfo<D, C>,
-    ) -> Result<CompressingStoredFieldsIndexReader> {
+impl LegacyFieldsIndex {
+    fn new<T: IndexInput + ?Sized>(fields_index_in: &mut T) -> Result<LegacyFieldsIndex> {
         let mut doc_bases = Vec::new();
         let mut start_pointers = Vec::new();
         let mut avg_chunk_docs = Vec::new();
@@ -237,8 +356,7 @@ impl CompressingStoredFieldsIndexReader {
             // block_count += 1;
         }
 
-        Ok(CompressingStoredFieldsIndexReader {
-            max_doc: si.max_doc,
+        Ok(LegacyFieldsIndex {
             doc_bases,
             start_pointers,
             avg_chunk_docs,
@@ -296,8 +414,7 @@ impl CompressingStoredFieldsIndexReader {
         Ok(hi)
     }
 
-    pub fn start_pointer(&self, doc_id: DocId) -> Result<i64> {
-        debug_assert!(doc_id >= 0 && doc_id < self.max_doc);
+    fn start_pointer(&self, doc_id: DocId) -> Result<i64> {
         let block = self.block(doc_id);
         let relative_chunk = self.relative_chunk(block, doc_id - self.doc_bases[block])?;
         Ok(self.start_pointers[block] + self.relative_start_pointer(block, relative_chunk))
@@ -359,7 +476,7 @@ impl CompressingStoredFieldsReader {
             &mut index_stream,
             &codec_name_idx,
             VERSION_START,
-            VERSION_CURRENT,
+            VERSION_MONOTONIC_INDEX,
             &si.id,
             segment_suffix,
         )?;
@@ -367,7 +484,11 @@ impl CompressingStoredFieldsReader {
             index_header_length(&codec_name_idx, segment_suffix),
             index_stream.file_pointer() as usize
         );
-        let index_reader = CompressingStoredFieldsIndexReader::new(&mut index_stream, si)?;
+        let index_reader = CompressingStoredFieldsIndexReader::new(
+            &mut index_stream,
+            si,
+            version >= VERSION_MONOTONIC_INDEX,
+        )?;
         let max_pointer = index_stream.read_vlong()?;
         check_footer(&mut index_stream)?;
 
@@ -378,7 +499,7 @@ impl CompressingStoredFieldsReader {
             fields_stream.as_mut(),
             &codec_name_dat,
             VERSION_START,
-            VERSION_CURRENT,
+            VERSION_MONOTONIC_INDEX,
             &si.id,
             segment_suffix,
         )?;
@@ -496,6 +617,8 @@ impl CompressingStoredFieldsReader {
     }
 
     fn copy_for_merge(&self) -> Result<CompressingStoredFieldsReader> {
+        // merging reads all chunks sequentially, let the OS read them ahead.
+        self.fields_stream.prefetch(0, self.max_pointer)?;
         Ok(CompressingStoredFieldsReader {
             version: self.version,
             field_infos: self.field_infos.clone(),
@@ -1093,3 +1216,123 @@ pub struct SerializedDocument {
     pub input: DocumentInput,
     // decompressed bytes in `bytes` in store field reader
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::codec::stored_fields::CompressingStoredFieldsIndexWriter;
+    use core::doc::{Fieldable, StoredField};
+    use core::index::reader::IndexReader;
+    use core::store::directory::FSDirectory;
+    use core::test_util::{new_fs_writer, xorshift};
+    use core::util::VariantValue;
+
+    #[test]
+    fn test_index_start_pointers() {
+        const NUM_DOCS: usize = 100_000;
+
+        let mut next = xorshift(0x9e37_79b9_7f4a_7c15);
+
+        // (doc base, start pointer) of the chunks, a few of them far apart in size
+        let mut chunks = vec![];
+        let (mut doc_base, mut start_pointer) = (0usize, 57i64);
+        while doc_base < NUM_DOCS {
+            chunks.push((doc_base, start_pointer));
+            doc_base += ((next() % 128) as usize + 1).min(NUM_DOCS - doc_base);
+            start_pointer += if next() % 50 == 0 {
+                (next() % (1 << 30)) as i64 + 1
+            } else {
+                (next() % 20_000) as i64 + 1
+            };
+        }
+        let max_pointer = start_pointer;
+
+        let dir = tempfile::tempdir().unwrap();
+        let directory = FSDirectory::with_path(dir.path()).unwrap();
+        // the legacy blocks written by default, and the `DirectMonotonic` sequences
+        let mut indexes = vec![];
+        for &monotonic in &[false, true] {
+            let name = format!("index_{}", monotonic);
+            {
+                let output = directory.create_output(&name, &IOContext::Default).unwrap();
+                let mut writer =
+                    CompressingStoredFieldsIndexWriter::new(output, 1024, monotonic).unwrap();
+                for (i, &(doc_base, start_pointer)) in chunks.iter().enumerate() {
+                    let end = chunks.get(i + 1).map_or(NUM_DOCS, |c| c.0);
+                    writer.write_index(end - doc_base, start_pointer).unwrap();
+                }
+                writer.finish(NUM_DOCS, max_pointer).unwrap();
+            }
+
+            let mut input = directory
+                .open_checksum_input(&name, &IOContext::READ)
+                .unwrap();
+            let index = if monotonic {
+                FieldsIndex::Monotonic(MonotonicFieldsIndex::new(&mut input).unwrap())
+            } else {
+                FieldsIndex::Legacy(LegacyFieldsIndex::new(&mut input).unwrap())
+            };
+            assert_eq!(input.read_vlong().unwrap(), max_pointer);
+            check_footer(&mut input).unwrap();
+            indexes.push(CompressingStoredFieldsIndexReader {
+                max_doc: NUM_DOCS as i32,
+                index,
+            });
+        }
+
+        let brute_force = |doc: usize| chunks.iter().take_while(|c| c.0 <= doc).last().unwrap().1;
+        let mut docs: Vec<usize> = (0..10_000)
+            .map(|_| (next() % NUM_DOCS as u64) as usize)
+            .collect();
+        for &(doc_base, _) in &chunks {
+            docs.push(doc_base);
+            docs.push(doc_base.max(1) - 1);
+        }
+        docs.push(NUM_DOCS - 1);
+        for doc in docs {
+            for index in &indexes {
+                assert_eq!(
+                    index.start_pointer(doc as DocId).unwrap(),
+                    brute_force(doc),
+                    "doc {}",
+                    doc
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_read_back_stored_fields() {
+        const NUM_DOCS: usize = 100_000;
+        // values of varying lengths, so the chunks hold different numbers of docs
+        let value = |doc: usize| format!("doc{}-{}", doc, "x".repeat(doc * 7 % 97));
+
+        let (_dir, writer) = new_fs_writer();
+        for doc in 0..NUM_DOCS {
+            let field = StoredField::new("value", None, VariantValue::VString(value(doc)));
+            let fields: Vec<Box<dyn Fieldable>> = vec![Box::new(field.field)];
+            writer.add_document(fields).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        assert_eq!(reader.max_doc() as usize, NUM_DOCS);
+        let mut next = xorshift(0x2545_f491_4f6c_dd1d);
+        let mut docs: Vec<usize> = (0..5_000)
+            .map(|_| (next() % NUM_DOCS as u64) as usize)
+            .collect();
+        docs.extend_from_slice(&[0, 1, NUM_DOCS - 2, NUM_DOCS - 1]);
+        for doc in docs {
+            let document = reader.document(doc as DocId, &[]).unwrap();
+            let stored = document.get_field("value").unwrap();
+            assert_eq!(
+                stored.string_value(),
+                Some(value(doc).as_str()),
+                "doc {}",
+                doc
+            );
+        }
+    }
+}
@@ -26,11 +26,12 @@ use core::index::merge::{
 };
 use core::search::NO_MORE_DOCS;
 use core::store::directory::Directory;
-use core::store::io::{DataOutput, GrowableByteArrayDataOutput, IndexOutput};
+use core::store::io::{DataOutput, GrowableByteArrayDataOutput, IndexOutput, RAMOutputStream};
 use core::store::IOContext;
 use core::util::packed::Format;
 use core::util::packed::VERSION_CURRENT as PACKED_VERSION_CURRENT;
 use core::util::packed::{get_writer_no_header, Writer};
+use core::util::packed::{DirectMonotonicWriter, MAX_BLOCK_SHIFT, MIN_BLOCK_SHIFT};
 use core::util::DocId;
 use core::util::Numeric;
 use core::util::{BitsRequired, UnsignedShift, ZigZagEncoding};
@@ -43,6 +44,12 @@ use error::{
 
 use std::sync::Arc;
 
+/// Writes the index of the chunks of a stored fields or term vectors data file.
+///
+/// By default the index is written in blocks of `block_size` chunks, storing the doc bases
+/// and start pointers as deltas from their averages, the format Lucene 6.2.1 reads. With
+/// `monotonic` set, the doc bases and start pointers of all the chunks are buffered until
+/// `finish` and written as two `DirectMonotonic` sequences, see `VERSION_MONOTONIC_INDEX`.
 pub struct CompressingStoredFieldsIndexWriter<O: IndexOutput> {
     fields_index_output: O,
     block_size: usize,
@@ -53,14 +60,25 @@ pub struct CompressingStoredFieldsIndexWriter<O: IndexOutput> {
     max_start_pointer: i64,
     doc_base_deltas: Vec<i32>,
     start_pointer_deltas: Vec<i64>,
+    monotonic: Option<MonotonicChunks>,
+}
+
+struct MonotonicChunks {
+    doc_bases: Vec<i64>,
+    start_pointers: Vec<i64>,
 }
 
 impl<O: IndexOutput> CompressingStoredFieldsIndexWriter<O> {
-    pub fn new(output: O, block_size: usize) -> Result<CompressingStoredFieldsIndexWriter<O>> {
+    pub fn new(
+        output: O,
+        block_size: usize,
+        monotonic: bool,
+    ) -> Result<CompressingStoredFieldsIndexWriter<O>> {
         if block_size == 0 {
             bail!(IllegalArgument("block size must be 0".into()));
         }
 
+        let block_deltas = if monotonic { 0 } else { block_size };
         let mut writer = CompressingStoredFieldsIndexWriter {
             fields_index_output: output,
             block_size,
@@ -69,8 +87,16 @@ impl<O: IndexOutput> CompressingStoredFieldsIndexWriter<O> {
             block_chunks: 0,
             first_start_pointer: -1,
             max_start_pointer: -1,
-            doc_base_deltas: vec![0i32; block_size],
-            start_pointer_deltas: vec![0i64; block_size],
+            doc_base_deltas: vec![0i32; block_deltas],
+            start_pointer_deltas: vec![0i64; block_deltas],
+            monotonic: if monotonic {
+                Some(MonotonicChunks {
+                    doc_bases: Vec::new(),
+                    start_pointers: Vec::new(),
+                })
+            } else {
+                None
+            },
         };
 
         writer
@@ -164,6 +190,18 @@ impl<O: IndexOutput> CompressingStoredFieldsIndexWriter<O> {
     }
 
     pub fn write_index(&mut self, num_docs: usize, start_pointer: i64) -> Result<()> {
+        if let Some(ref mut chunks) = self.monotonic {
+            debug_assert!(start_pointer > 0);
+            debug_assert!(chunks
+                .start_pointers
+                .last()
+                .map_or(true, |&p| start_pointer >= p));
+            chunks.doc_bases.push(self.total_docs as i64);
+            chunks.start_pointers.push(start_pointer);
+            self.total_docs += num_docs;
+            return Ok(());
+        }
+
         if self.block_chunks == self.block_size {
             self.write_block()?;
             self.reset();
@@ -195,19 +233,66 @@ impl<O: IndexOutput> CompressingStoredFieldsIndexWriter<O> {
             )));
         }
 
-        if self.block_chunks > 0 {
-            self.write_block()?;
+        if let Some(ref chunks) = self.monotonic {
+            // `block_size` chunks share the same average increment, rounded up to a power
+            // of two
+            let block_shift = (self.block_size.next_power_of_two().trailing_zeros() as i32)
+                .max(MIN_BLOCK_SHIFT)
+                .min(MAX_BLOCK_SHIFT);
+            let num_chunks = chunks.doc_bases.len();
+            self.fields_index_output.write_vint(num_chunks as i32)?;
+            self.fields_index_output.write_vint(block_shift)?;
+            let mut meta = RAMOutputStream::new(false);
+            let mut doc_bases_data = RAMOutputStream::new(false);
+            let mut start_pointers_data = RAMOutputStream::new(false);
+            Self::write_monotonic(
+                &chunks.doc_bases,
+                block_shift,
+                &mut meta,
+                &mut doc_bases_data,
+            )?;
+            Self::write_monotonic(
+                &chunks.start_pointers,
+                block_shift,
+                &mut meta,
+                &mut start_pointers_data,
+            )?;
+            meta.write_to(&mut self.fields_index_output)?;
+            for data in &[doc_bases_data, start_pointers_data] {
+                self.fields_index_output.write_vlong(data.file_pointer())?;
+                data.write_to(&mut self.fields_index_output)?;
+            }
+        } else {
+            if self.block_chunks > 0 {
+                self.write_block()?;
+            }
+
+            self.fields_index_output.write_vint(0)?; // end marker
         }
 
-        self.fields_index_output.write_vint(0)?; // end marker
         self.fields_index_output.write_vlong(max_pointer)?;
         write_footer(&mut self.fields_index_output)
     }
+
+    fn write_monotonic(
+        values: &[i64],
+        block_shift: i32,
+        meta: &mut RAMOutputStream,
+        data: &mut RAMOutputStream,
+    ) -> Result<()> {
+        let mut writer = DirectMonotonicWriter::new(meta, data, values.len() as i64, block_shift)?;
+        for &v in values {
+            writer.add(v)?;
+        }
+        writer.finish()
+    }
 }
 
 /// `StoredFieldsWriter` impl for `CompressingStoredFieldsFormat`
 pub struct CompressingStoredFieldsWriter<O: IndexOutput> {
     index_writer: CompressingStoredFieldsIndexWriter<O>,
+    // `VERSION_CURRENT`, or `VERSION_MONOTONIC_INDEX` when writing a monotonic index
+    version: i32,
     fields_stream: O,
     compress_mode: CompressionMode,
     compressor: Compressor,
@@ -241,7 +326,13 @@ impl<O: IndexOutput + 'static> CompressingStoredFieldsWriter<O> {
         chunk_size: usize,
         max_docs_per_chunk: usize,
         block_size: usize,
+        monotonic_index: bool,
     ) -> Result<CompressingStoredFieldsWriter<DW::IndexOutput>> {
+        let version = if monotonic_index {
+            VERSION_MONOTONIC_INDEX
+        } else {
+            VERSION_CURRENT
+        };
         let mut index_stream = directory.create_output(
             &segment_file_name(&si.name, segment_suffix, STORED_FIELDS_INDEX_EXTENSION),
             context,
@@ -256,14 +347,14 @@ impl<O: IndexOutput + 'static> CompressingStoredFieldsWriter<O> {
         write_index_header(
             &mut index_stream,
             &codec_name_idx,
-            VERSION_CURRENT,
+            version,
             si.get_id(),
             segment_suffix,
         )?;
         write_index_header(
             &mut fields_stream,
             &codec_name_dat,
-            VERSION_CURRENT,
+            version,
             si.get_id(),
             segment_suffix,
         )?;
@@ -276,12 +367,14 @@ impl<O: IndexOutput + 'static> CompressingStoredFieldsWriter<O> {
                 == fields_stream.file_pointer() as usize
         );
 
-        let index_writer = CompressingStoredFieldsIndexWriter::new(index_stream, block_size)?;
+        let index_writer =
+            CompressingStoredFieldsIndexWriter::new(index_stream, block_size, monotonic_index)?;
         fields_stream.write_vint(chunk_size as i32)?;
         fields_stream.write_vint(PACKED_VERSION_CURRENT as i32)?;
 
         Ok(CompressingStoredFieldsWriter {
             index_writer,
+            version,
             fields_stream,
             compress_mode,
             compressor: compress_mode.new_compressor(),
@@ -734,7 +827,7 @@ impl<O: IndexOutput + 'static> StoredFieldsWriter for CompressingStoredFieldsWri
             let live_docs = merge_state.live_docs[i].as_ref();
             // if its some other format, or an older version of this format, or safety switch:
             if let Some(ref mut fields_reader) = matching_fields_reader {
-                if fields_reader.version() != VERSION_CURRENT {
+                if fields_reader.version() != self.version {
                     // naive merge:
                     for doc_id in 0..max_doc {
                         if !live_docs.get(doc_id as usize)? {
@@ -755,7 +848,7 @@ impl<O: IndexOutput + 'static> StoredFieldsWriter for CompressingStoredFieldsWri
                     // its not worth fine-graining this if there are deletions.
 
                     // if the format is older, its always handled by the naive merge case above
-                    debug_assert_eq!(fields_reader.version(), VERSION_CURRENT);
+                    debug_assert_eq!(fields_reader.version(), self.version);
 
                     // flush any pending chunks
                     if self.num_buffered_docs > 0 {
@@ -840,7 +933,7 @@ impl<O: IndexOutput + 'static> StoredFieldsWriter for CompressingStoredFieldsWri
                     // even on simple docs (1 stored field), it seems to help by about 20%
 
                     // if the format is older, its always handled by the naive merge case above
-                    debug_assert_eq!(fields_reader.version(), VERSION_CURRENT);
+                    debug_assert_eq!(fields_reader.version(), self.version);
 
                     for doc_id in 0..max_doc {
                         if !live_docs.get(doc_id as usize)? {
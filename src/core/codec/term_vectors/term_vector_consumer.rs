@@ -267,9 +267,10 @@ where
     MP: MergePolicy,
 {
     fn new(doc_writer: &mut DocumentsWriterPerThread<D, C, MS, MP>) -> Self {
-        let dir = Arc::new(TrackingTmpOutputDirectoryWrapper::new(Arc::clone(
-            &doc_writer.directory,
-        )));
+        let dir = Arc::new(TrackingTmpOutputDirectoryWrapper::new(
+            Arc::clone(&doc_writer.directory),
+            doc_writer.index_writer_config.temp_directory.clone(),
+        ));
         let consumer = TermVectorsConsumerImpl::new(doc_writer, Arc::clone(&dir));
         Self {
             consumer,
@@ -304,10 +305,7 @@ where
             res
         } else {
             // we're lucky the index is already sorted, just rename the temporary file and return
-            for (k, v) in &*self.tmp_directory.file_names.lock().unwrap() {
-                self.tmp_directory.rename(v, k)?;
-            }
-            Ok(())
+            self.tmp_directory.rename_temp_files()
         }
     }
 
@@ -57,6 +57,9 @@ pub const CODEC_SFX_DAT: &str = "Data";
 pub const VERSION_START: i32 = 0;
 pub const VERSION_CHUNK_STATS: i32 = 1;
 pub const VERSION_CURRENT: i32 = VERSION_CHUNK_STATS;
+/// The index stores the doc bases and start pointers of the chunks with `DirectMonotonic`,
+/// only written by formats that opt in with `with_monotonic_index`.
+pub const VERSION_MONOTONIC_INDEX: i32 = 2;
 pub const PACKED_BLOCK_SIZE: i32 = 64;
 
 pub const POSITIONS: i32 = 0x01;
@@ -74,6 +77,7 @@ pub struct CompressingTermVectorsFormat {
     compression_mode: CompressionMode,
     chunk_size: i32,
     block_size: i32,
+    monotonic_index: bool,
 }
 
 impl CompressingTermVectorsFormat {
@@ -91,8 +95,16 @@ impl CompressingTermVectorsFormat {
             compression_mode,
             chunk_size,
             block_size,
+            monotonic_index: false,
         }
     }
+
+    /// Writes the index as `DirectMonotonic` sequences, which Lucene 6.2.1 doesn't read,
+    /// so the format should have a name of its own.
+    pub fn with_monotonic_index(mut self) -> CompressingTermVectorsFormat {
+        self.monotonic_index = true;
+        self
+    }
 }
 
 impl Default for CompressingTermVectorsFormat {
@@ -143,6 +155,7 @@ impl TermVectorsFormat for CompressingTermVectorsFormat {
                 self.compression_mode,
                 self.chunk_size as usize,
                 self.block_size as usize,
+                self.monotonic_index,
             )?,
         ))
     }
@@ -185,7 +198,7 @@ impl CompressingTermVectorsReader {
             &mut input,
             &codec_name_idx,
             VERSION_START,
-            VERSION_CURRENT,
+            VERSION_MONOTONIC_INDEX,
             &si.id,
             segment_suffix,
         )?;
@@ -193,7 +206,11 @@ impl CompressingTermVectorsReader {
             index_header_length(&codec_name_idx, segment_suffix),
             input.file_pointer() as usize
         );
-        let index_reader = CompressingStoredFieldsIndexReader::new(&mut input, si)?;
+        let index_reader = CompressingStoredFieldsIndexReader::new(
+            &mut input,
+            si,
+            version >= VERSION_MONOTONIC_INDEX,
+        )?;
         let max_pointer = input.read_vlong()?;
         // TODO once the input is a `ChecksumIndexInput`, we should check the footer
         check_footer(&mut input)?;
@@ -207,7 +224,7 @@ impl CompressingTermVectorsReader {
             vectors_stream.as_mut(),
             &codec_name_dat,
             VERSION_START,
-            VERSION_CURRENT,
+            VERSION_MONOTONIC_INDEX,
             &si.id,
             segment_suffix,
         )?;
@@ -254,6 +254,8 @@ impl DocData {
 /// `TermVectorsWriter` for `CompressingTermVectorsFormat`
 pub struct CompressingTermVectorsWriter<O: IndexOutput> {
     index_writer: CompressingStoredFieldsIndexWriter<O>,
+    // `VERSION_CURRENT`, or `VERSION_MONOTONIC_INDEX` when writing a monotonic index
+    version: i32,
     vectors_stream: O,
     compress_mode: CompressionMode,
     compressor: Compressor,
@@ -287,7 +289,13 @@ impl<O: IndexOutput> CompressingTermVectorsWriter<O> {
         compress_mode: CompressionMode,
         chunk_size: usize,
         block_size: usize,
+        monotonic_index: bool,
     ) -> Result<CompressingTermVectorsWriter<O>> {
+        let version = if monotonic_index {
+            VERSION_MONOTONIC_INDEX
+        } else {
+            VERSION_CURRENT
+        };
         let segment = &si.name;
         let mut index_stream = directory.create_output(
             &segment_file_name(segment, segment_suffix, VECTORS_INDEX_EXTENSION),
@@ -303,14 +311,14 @@ impl<O: IndexOutput> CompressingTermVectorsWriter<O> {
         write_index_header(
             &mut index_stream,
             &codec_name_idx,
-            VERSION_CURRENT,
+            version,
             si.get_id(),
             segment_suffix,
         )?;
         write_index_header(
             &mut vectors_stream,
             &codec_name_dat,
-            VERSION_CURRENT,
+            version,
             si.get_id(),
             segment_suffix,
         )?;
@@ -324,13 +332,15 @@ impl<O: IndexOutput> CompressingTermVectorsWriter<O> {
                 == vectors_stream.file_pointer() as usize
         );
 
-        let index_writer = CompressingStoredFieldsIndexWriter::new(index_stream, block_size)?;
+        let index_writer =
+            CompressingStoredFieldsIndexWriter::new(index_stream, block_size, monotonic_index)?;
 
         vectors_stream.write_vint(PACKED_VERSION_CURRENT as i32)?;
         vectors_stream.write_vint(chunk_size as i32)?;
 
         Ok(CompressingTermVectorsWriter {
             index_writer,
+            version,
             vectors_stream,
             compress_mode,
             compressor: compress_mode.new_compressor(),
@@ -1000,7 +1010,7 @@ impl<O: IndexOutput> TermVectorsWriter for CompressingTermVectorsWriter<O> {
                 Some(ref mut vectors_reader)
                     if vectors_reader.compression_mode() == self.compress_mode
                         && vectors_reader.chunk_size() == self.chunk_size as i32
-                        && vectors_reader.version() == VERSION_CURRENT
+                        && vectors_reader.version() == self.version
                         && vectors_reader.packed_ints_version() == PACKED_VERSION_CURRENT
                         && live_docs.is_empty()
                         && !Self::too_dirty(vectors_reader) =>
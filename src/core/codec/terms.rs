@@ -40,6 +40,20 @@ impl OrdTermState {
 
 impl TermState for OrdTermState {}
 
+/// Aggregate statistics of a field, as stored by the codec for each segment.
+///
+/// Like the single measures of `Terms`, a value is -1 if the codec doesn't store it, and
+/// deleted documents are not taken into account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldStatistics {
+    /// number of documents that have at least one term for the field
+    pub doc_count: i64,
+    /// sum of the total term freq of all the terms of the field
+    pub sum_total_term_freq: i64,
+    /// sum of the doc freq of all the terms of the field
+    pub sum_doc_freq: i64,
+}
+
 /// Access to the terms in a specific field.  See `Fields`.
 pub trait Terms {
     type Iterator: TermIterator;
@@ -209,6 +223,15 @@ pub trait Terms {
         }
     }
 
+    /// Returns `doc_count`, `sum_total_term_freq` and `sum_doc_freq` at once.
+    fn field_statistics(&self) -> Result<FieldStatistics> {
+        Ok(FieldStatistics {
+            doc_count: i64::from(self.doc_count()?),
+            sum_total_term_freq: self.sum_total_term_freq()?,
+            sum_doc_freq: self.sum_doc_freq()?,
+        })
+    }
+
     /// Expert: returns additional information about this Terms instance
     /// for debugging purposes.
     fn stats(&self) -> Result<String> {
@@ -375,6 +398,23 @@ pub trait TermIterator: 'static {
     }
     fn postings_with_flags(&mut self, flags: u16) -> Result<Self::Postings>;
 
+    /// Like `postings_with_flags`, but `reuse` may be a previously returned iterator
+    /// which the implementation resets and hands back instead of allocating a new one.
+    /// Implementations that can't reuse it simply drop it.
+    fn postings_with_reuse(
+        &mut self,
+        _reuse: Option<Self::Postings>,
+        flags: u16,
+    ) -> Result<Self::Postings> {
+        self.postings_with_flags(flags)
+    }
+
+    /// Hints that the postings of the current term will be read soon, so the
+    /// underlying storage may start loading them. Default is a no-op.
+    fn prefetch_postings(&mut self) -> Result<()> {
+        Ok(())
+    }
+
     /// Expert: Returns the TermsEnums internal state to position the TermsEnum
     /// without re-seeking the term dictionary.
     /// <p>
@@ -580,4 +620,61 @@ where
     fn postings_with_flags(&mut self, flags: u16) -> Result<Self::Postings> {
         self.base_mut().terms.postings_with_flags(flags)
     }
+
+    fn postings_with_reuse(
+        &mut self,
+        reuse: Option<Self::Postings>,
+        flags: u16,
+    ) -> Result<Self::Postings> {
+        self.base_mut().terms.postings_with_reuse(reuse, flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use core::doc::IndexOptions;
+    use core::index::reader::{IndexReader, LeafReader};
+    use core::test_util::{new_fs_writer, text_doc};
+
+    #[test]
+    fn test_field_statistics_per_segment() {
+        let (_dir, writer) = new_fs_writer();
+        // each commit flushes a new segment
+        for docs in &[vec!["a b", "a"], vec!["a a c", ""]] {
+            for text in docs {
+                writer
+                    .add_document(text_doc("body", text, IndexOptions::DocsAndFreqs))
+                    .unwrap();
+            }
+            writer.commit().unwrap();
+        }
+        writer
+            .add_document(text_doc("title", "x y", IndexOptions::Docs))
+            .unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 3);
+        let stats: Vec<FieldStatistics> = leaves
+            .iter()
+            .zip(&["body", "body", "title"])
+            .map(|(leaf, field)| {
+                let terms = leaf.reader.terms(field).unwrap().unwrap();
+                terms.field_statistics().unwrap()
+            })
+            .collect();
+        let expected = |doc_count, sum_total_term_freq, sum_doc_freq| FieldStatistics {
+            doc_count,
+            sum_total_term_freq,
+            sum_doc_freq,
+        };
+        assert_eq!(stats[0], expected(2, 3, 3));
+        // the empty doc doesn't count as having the field
+        assert_eq!(stats[1], expected(1, 3, 2));
+        // freqs are not indexed, so the total term freq is unknown
+        assert_eq!(stats[2], expected(1, -1, 2));
+    }
 }
@@ -0,0 +1,496 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod vectors_reader;
+
+pub use self::vectors_reader::*;
+
+mod vectors_writer;
+
+pub use self::vectors_writer::*;
+
+mod vector_values_writer;
+
+pub use self::vector_values_writer::*;
+
+mod scalar_quantizer;
+
+pub use self::scalar_quantizer::*;
+
+use core::codec::segment_infos::{SegmentReadState, SegmentWriteState};
+use core::codec::Codec;
+use core::store::directory::Directory;
+
+use error::ErrorKind::CorruptIndex;
+use error::Result;
+
+/// Maximum number of dimensions of a dense vector
+pub const MAX_VECTOR_DIMENSIONS: u32 = 1024;
+
+/// Filename extension for the vector data
+pub const VECTOR_DATA_EXTENSION: &str = "vec";
+/// Filename extension for the per field offset index of the vector data
+pub const VECTOR_INDEX_EXTENSION: &str = "vmi";
+
+pub const VECTOR_DATA_CODEC_NAME: &str = "DenseVectorsFormatData";
+pub const VECTOR_INDEX_CODEC_NAME: &str = "DenseVectorsFormatIndex";
+
+pub const VECTOR_VERSION_START: i32 = 0;
+// the index entries record whether the vectors are followed by their quantized values
+pub const VECTOR_VERSION_QUANTIZED: i32 = 1;
+// the index entries record the offset of the HNSW graph of the vectors
+pub const VECTOR_VERSION_HNSW: i32 = 2;
+pub const VECTOR_VERSION_CURRENT: i32 = VECTOR_VERSION_HNSW;
+
+/// The vectors are only stored as `f32`s
+pub const VECTOR_ENCODING_RAW: u8 = 0;
+/// The `f32` vectors are followed by their `i8` quantized values, see `ScalarQuantizer`
+pub const VECTOR_ENCODING_INT8: u8 = 1;
+
+/// Field info attributes holding the calibration of the quantized vectors of a segment
+pub const QUANTIZED_VECTOR_OFFSET_KEY: &str = "QuantizedVectorsFormat.offset";
+pub const QUANTIZED_VECTOR_SCALE_KEY: &str = "QuantizedVectorsFormat.scale";
+
+/// The similarity function used to compare the dense vectors of a field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KnnVectorSimilarityFunction {
+    /// Euclidean distance
+    L2,
+    /// Dot product, the vectors are expected to be unit length
+    DotProduct,
+    /// Cosine of the angle between the vectors
+    Cosine,
+    /// Number of differing bits, the values of the vectors are bits: any value other
+    /// than `0.0` is a 1. The vectors are stored packed in 64 bits words, see
+    /// `BinaryVectorField`.
+    Hamming,
+}
+
+impl Default for KnnVectorSimilarityFunction {
+    fn default() -> Self {
+        KnnVectorSimilarityFunction::L2
+    }
+}
+
+impl KnnVectorSimilarityFunction {
+    /// Compares two vectors of the same dimension: the squared distance for `L2`, the
+    /// dot product, the cosine or the Hamming distance otherwise.
+    pub fn compare(self, a: &[f32], b: &[f32]) -> f32 {
+        debug_assert_eq!(a.len(), b.len());
+        match self {
+            KnnVectorSimilarityFunction::L2 => {
+                a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum::<f32>()
+            }
+            KnnVectorSimilarityFunction::DotProduct => {
+                a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>()
+            }
+            KnnVectorSimilarityFunction::Cosine => {
+                let mut dot = 0f32;
+                let mut norm_a = 0f32;
+                let mut norm_b = 0f32;
+                for (x, y) in a.iter().zip(b) {
+                    dot += x * y;
+                    norm_a += x * x;
+                    norm_b += y * y;
+                }
+                if norm_a == 0f32 || norm_b == 0f32 {
+                    0f32
+                } else {
+                    dot / (norm_a * norm_b).sqrt()
+                }
+            }
+            KnnVectorSimilarityFunction::Hamming => {
+                hamming_distance(&pack_bits(a), &pack_bits(b)) as f32
+            }
+        }
+    }
+
+    /// Maps a value of `compare` to a score in `[0, 1]`, the higher the nearer the
+    /// vectors: `1 / (1 + d)` of the squared or Hamming distance `d`, `(1 + x) / 2` of
+    /// the dot product or cosine `x`.
+    pub fn score_of(self, compared: f32) -> f32 {
+        match self {
+            KnnVectorSimilarityFunction::L2 | KnnVectorSimilarityFunction::Hamming => {
+                1.0 / (1.0 + compared)
+            }
+            KnnVectorSimilarityFunction::DotProduct | KnnVectorSimilarityFunction::Cosine => {
+                ((1.0 + compared) / 2.0).max(0.0)
+            }
+        }
+    }
+
+    /// The score of `b` against `a`, see `score_of`.
+    pub fn score(self, a: &[f32], b: &[f32]) -> f32 {
+        self.score_of(self.compare(a, b))
+    }
+
+    pub(crate) fn as_byte(self) -> u8 {
+        match self {
+            KnnVectorSimilarityFunction::L2 => 0,
+            KnnVectorSimilarityFunction::DotProduct => 1,
+            KnnVectorSimilarityFunction::Cosine => 2,
+            KnnVectorSimilarityFunction::Hamming => 3,
+        }
+    }
+
+    pub(crate) fn from_byte(byte: u8) -> Result<Self> {
+        Ok(match byte {
+            0 => KnnVectorSimilarityFunction::L2,
+            1 => KnnVectorSimilarityFunction::DotProduct,
+            2 => KnnVectorSimilarityFunction::Cosine,
+            3 => KnnVectorSimilarityFunction::Hamming,
+            _ => bail!(CorruptIndex(format!(
+                "invalid vector similarity function byte: {}",
+                byte
+            ))),
+        })
+    }
+}
+
+/// The number of 64 bits words holding the bits of a vector of `dimension` bits.
+pub fn binary_words(dimension: usize) -> usize {
+    (dimension + 63) / 64
+}
+
+/// Packs the bits of a vector of a `Hamming` field into 64 bits words, the first bit
+/// into the lowest bit of the first word and the last word padded with 0s.
+pub fn pack_bits(bits: &[f32]) -> Vec<u64> {
+    bits.chunks(64).map(pack_word).collect()
+}
+
+/// The number of differing bits of two packed vectors of the same dimension.
+pub fn hamming_distance(a: &[u64], b: &[u64]) -> u32 {
+    debug_assert_eq!(a.len(), b.len());
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+// packs up to 64 bits, the first one into the lowest bit of the word
+fn pack_word(bits: &[f32]) -> u64 {
+    debug_assert!(bits.len() <= 64);
+    let mut word = 0u64;
+    for (i, &b) in bits.iter().enumerate() {
+        if b != 0.0 {
+            word |= 1 << i;
+        }
+    }
+    word
+}
+
+/// Encodes/decodes per-document dense vectors.
+pub trait VectorsFormat {
+    /// Writes a new segment
+    // TODO we need GAT to make the writer an associated type
+    fn fields_writer<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentWriteState<D, DW, C>,
+    ) -> Result<DenseVectorsWriter<DW::IndexOutput>>;
+
+    /// Reads a segment, the returned reader holds open the files it needs.
+    fn fields_reader<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentReadState<'_, D, DW, C>,
+    ) -> Result<DenseVectorsReader>;
+}
+
+/// Stores the `f32` vectors of every field in a `.vec` data file, in little endian
+/// and 4 bytes aligned so that they can be used in place when the file is memory
+/// mapped, followed by their `HnswGraph`, with the per field docs and data offsets in a
+/// `.vmi` index file. The vectors of a `Hamming` field are stored as their packed 64
+/// bits words instead, 8 bytes aligned.
+#[derive(Copy, Clone, Default)]
+pub struct DenseVectorsFormat;
+
+impl VectorsFormat for DenseVectorsFormat {
+    fn fields_writer<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentWriteState<D, DW, C>,
+    ) -> Result<DenseVectorsWriter<DW::IndexOutput>> {
+        DenseVectorsWriter::new(state)
+    }
+
+    fn fields_reader<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentReadState<'_, D, DW, C>,
+    ) -> Result<DenseVectorsReader> {
+        DenseVectorsReader::new(state)
+    }
+}
+
+/// Writes the vectors like `DenseVectorsFormat`, followed by their values quantized to
+/// `i8`s with a `ScalarQuantizer` calibrated on the vectors of each field of the
+/// segment, whose offset and scale are stored as attributes of the field info.
+///
+/// The quantized values are written in addition to the `f32` vectors, which are kept
+/// to requantize the vectors when segments are merged and for the exact searches, so
+/// the data file grows by a quarter. The graph searches compare the quantized values
+/// with `VectorReader::scorer`. Both are used in place from a memory mapped input, and
+/// are copied to the heap otherwise.
+#[derive(Copy, Clone, Default)]
+pub struct QuantizedVectorsFormat;
+
+impl VectorsFormat for QuantizedVectorsFormat {
+    fn fields_writer<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentWriteState<D, DW, C>,
+    ) -> Result<DenseVectorsWriter<DW::IndexOutput>> {
+        DenseVectorsWriter::with_quantization(state)
+    }
+
+    fn fields_reader<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentReadState<'_, D, DW, C>,
+    ) -> Result<DenseVectorsReader> {
+        DenseVectorsReader::new(state)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum VectorsFormatEnum {
+    Dense(DenseVectorsFormat),
+    Quantized(QuantizedVectorsFormat),
+}
+
+impl Default for VectorsFormatEnum {
+    fn default() -> Self {
+        VectorsFormatEnum::Dense(DenseVectorsFormat)
+    }
+}
+
+impl VectorsFormat for VectorsFormatEnum {
+    fn fields_writer<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentWriteState<D, DW, C>,
+    ) -> Result<DenseVectorsWriter<DW::IndexOutput>> {
+        match self {
+            VectorsFormatEnum::Dense(f) => f.fields_writer(state),
+            VectorsFormatEnum::Quantized(f) => f.fields_writer(state),
+        }
+    }
+
+    fn fields_reader<D: Directory, DW: Directory, C: Codec>(
+        &self,
+        state: &SegmentReadState<'_, D, DW, C>,
+    ) -> Result<DenseVectorsReader> {
+        match self {
+            VectorsFormatEnum::Dense(f) => f.fields_reader(state),
+            VectorsFormatEnum::Quantized(f) => f.fields_reader(state),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+
+    use core::codec::{CodecEnum, Lucene62Codec};
+    use core::doc::{Fieldable, NumericDocValuesField, Term};
+    use core::index::merge::{SerialMergeScheduler, TieredMergePolicy};
+    use core::index::reader::IndexReader;
+    use core::index::writer::{IndexWriter, IndexWriterConfig};
+    use core::search::sort_field::{SimpleSortField, Sort, SortField, SortFieldType};
+    use core::store::directory::MmapDirectory;
+    use core::test_util::{fs_writer, keyword_field, vector_field};
+    use core::util::DocId;
+
+    use std::sync::Arc;
+
+    fn vector_doc(id: &str, rank: i64, vector: Option<&[f32]>) -> Vec<Box<dyn Fieldable>> {
+        let mut doc: Vec<Box<dyn Fieldable>> = vec![
+            keyword_field("id", id),
+            Box::new(NumericDocValuesField::new("rank", rank)),
+        ];
+        if let Some(vector) = vector {
+            let similarity = KnnVectorSimilarityFunction::Cosine;
+            doc.push(vector_field("vec", vector, similarity));
+        }
+        doc
+    }
+
+    #[test]
+    fn test_dense_vectors_sorted_flush_and_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = IndexWriterConfig::default();
+        config.index_sort = Some(Sort::new(vec![SortField::Simple(SimpleSortField::new(
+            "rank".into(),
+            SortFieldType::Long,
+            false,
+        ))]));
+        let writer = fs_writer(dir.path(), Arc::new(config));
+        writer
+            .add_document(vector_doc("a", 3, Some(&[3.0, 0.0, 0.0])))
+            .unwrap();
+        writer.add_document(vector_doc("b", 1, None)).unwrap();
+        writer
+            .add_document(vector_doc("c", 2, Some(&[2.0, 1.0, 0.0])))
+            .unwrap();
+        writer.commit().unwrap();
+
+        // the flushed vectors follow the docs sorted by rank: b, c, a
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        let field_info = leaves[0].reader.field_info("vec").unwrap();
+        assert_eq!(field_info.vector_dimension, 3);
+        assert_eq!(
+            field_info.vector_similarity,
+            KnnVectorSimilarityFunction::Cosine
+        );
+        let vectors = leaves[0].reader.vector_values("vec").unwrap();
+        assert_eq!(vectors.dimension(), 3);
+        assert_eq!(vectors.docs(), &[1, 2]);
+        assert_eq!(vectors.get_vector(1).unwrap(), &[2.0, 1.0, 0.0]);
+        assert_eq!(vectors.get_vector(2).unwrap(), &[3.0, 0.0, 0.0]);
+        assert!(vectors.get_vector(0).is_err());
+        // the graph links the vectors by ord
+        assert_eq!(vectors.graph().unwrap().len(), 2);
+        assert!(leaves[0].reader.vector_values("id").is_none());
+        drop(leaves);
+        drop(reader);
+
+        writer
+            .add_document(vector_doc("d", 0, Some(&[0.0, 0.0, 1.0])))
+            .unwrap();
+        writer
+            .add_document(vector_doc("e", 4, Some(&[4.0, 0.0, 0.0])))
+            .unwrap();
+        writer.commit().unwrap();
+        writer
+            .delete_documents_by_terms(vec![Term::new("id".into(), b"a".to_vec())])
+            .unwrap();
+        writer.force_merge(1, true).unwrap();
+
+        // the deleted vector is dropped, the others follow the merged order: d, b, c, e
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 1);
+        let vectors = leaves[0].reader.vector_values("vec").unwrap();
+        assert_eq!(vectors.docs(), &[0, 2, 3]);
+        assert_eq!(vectors.get_vector(0).unwrap(), &[0.0, 0.0, 1.0]);
+        assert_eq!(vectors.get_vector(2).unwrap(), &[2.0, 1.0, 0.0]);
+        assert_eq!(vectors.get_vector(3).unwrap(), &[4.0, 0.0, 0.0]);
+        assert_eq!(vectors.similarity(), KnnVectorSimilarityFunction::Cosine);
+        // the graph is rebuilt over the merged vectors
+        assert_eq!(vectors.graph().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_dense_vectors_mmap() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = IndexWriterConfig::default();
+        config.use_compound_file = false;
+        let directory = Arc::new(MmapDirectory::new(&dir.path()).unwrap());
+        let writer = IndexWriter::new(directory, Arc::new(config)).unwrap();
+        for i in 0..10 {
+            let vector: Vec<f32> = (0..5).map(|j| (i * 5 + j) as f32 * 0.5).collect();
+            writer
+                .add_document(vector_doc(&i.to_string(), i, Some(&vector)))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        let vectors = leaves[0].reader.vector_values("vec").unwrap();
+        assert_eq!(vectors.len(), 10);
+        for i in 0..10 {
+            let expected: Vec<f32> = (0..5).map(|j| (i * 5 + j) as f32 * 0.5).collect();
+            assert_eq!(vectors.get_vector(i).unwrap(), &expected[..]);
+        }
+    }
+
+    #[test]
+    fn test_quantized_vectors_flush_and_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let codec = CodecEnum::Lucene62(Lucene62Codec::with_quantized_vectors());
+        let config = Arc::new(IndexWriterConfig::new(
+            Arc::new(codec),
+            SerialMergeScheduler {},
+            TieredMergePolicy::default(),
+        ));
+        let writer = fs_writer(dir.path(), config);
+        let vector = |i: i64| -> Vec<f32> { (0..4).map(|j| ((i * 4 + j) % 7) as f32).collect() };
+        for i in 0..20 {
+            writer
+                .add_document(vector_doc(&i.to_string(), i, Some(&vector(i))))
+                .unwrap();
+            if i == 9 {
+                writer.commit().unwrap();
+            }
+        }
+        writer.commit().unwrap();
+        writer.force_merge(1, true).unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 1);
+        let field_info = leaves[0].reader.field_info("vec").unwrap();
+        assert!(field_info.attribute(QUANTIZED_VECTOR_SCALE_KEY).is_some());
+        let vectors = leaves[0].reader.vector_values("vec").unwrap();
+        // the f32 vectors are kept as is
+        for i in 0..20 {
+            assert_eq!(vectors.get_vector(i as DocId).unwrap(), &vector(i)[..]);
+        }
+        let quantizer = vectors.quantizer().unwrap();
+        assert_eq!(quantizer.similarity, KnnVectorSimilarityFunction::Cosine);
+        let (quantized, _) = vectors.quantized_vector_at(3).unwrap();
+        assert_eq!(quantized.len(), 4);
+
+        let query = vector(5);
+        let scorer = vectors.scorer(&query).unwrap();
+        for ord in 0..vectors.len() {
+            let exact = KnnVectorSimilarityFunction::Cosine.compare(&query, vectors.vector_at(ord));
+            assert!((scorer.compare(ord) - exact).abs() < 0.02);
+        }
+        assert!(vectors.scorer(&[1.0]).is_err());
+    }
+
+    #[test]
+    fn test_similarity_functions() {
+        let a = [1.0, 2.0, 2.0];
+        let b = [2.0, 0.0, 0.0];
+        assert_eq!(KnnVectorSimilarityFunction::L2.compare(&a, &b), 9.0);
+        assert_eq!(KnnVectorSimilarityFunction::DotProduct.compare(&a, &b), 2.0);
+        let cosine = KnnVectorSimilarityFunction::Cosine.compare(&a, &b);
+        assert!((cosine - 1.0 / 3.0).abs() < 1e-6);
+        assert_eq!(KnnVectorSimilarityFunction::L2.score(&a, &b), 0.1);
+        assert_eq!(KnnVectorSimilarityFunction::DotProduct.score(&a, &b), 1.5);
+        assert_eq!(KnnVectorSimilarityFunction::L2.score(&a, &a), 1.0);
+
+        // the differing bits are counted across the 64 bits words
+        let x = vec![0f32; 70];
+        let mut y = x.clone();
+        y[3] = 1.0;
+        y[65] = 1.0;
+        let hamming = KnnVectorSimilarityFunction::Hamming;
+        assert_eq!(hamming.compare(&x, &y), 2.0);
+        assert_eq!(pack_bits(&y), vec![1 << 3, 1 << 1]);
+        assert_eq!(binary_words(70), 2);
+        assert_eq!(hamming_distance(&pack_bits(&x), &pack_bits(&y)), 2);
+        assert_eq!(hamming.score(&x, &y), 1.0 / 3.0);
+        assert_eq!(hamming.score(&y, &y), 1.0);
+        for &similarity in &[
+            KnnVectorSimilarityFunction::L2,
+            KnnVectorSimilarityFunction::DotProduct,
+            KnnVectorSimilarityFunction::Cosine,
+            KnnVectorSimilarityFunction::Hamming,
+        ] {
+            let byte = similarity.as_byte();
+            assert_eq!(
+                KnnVectorSimilarityFunction::from_byte(byte).unwrap(),
+                similarity
+            );
+        }
+        assert!(KnnVectorSimilarityFunction::from_byte(4).is_err());
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::vectors::KnnVectorSimilarityFunction;
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64 as simd;
+
+/// The largest quantized value, the values are quantized to 7 bits so that the
+/// products of two of them and their sums over 1024 dimensions fit in an `i32`.
+const MAX_QUANTIZED: f32 = 127.0;
+
+/// The number of vectors sampled to calibrate a quantizer.
+pub const MAX_CALIBRATION_VECTORS: usize = 25_000;
+
+/// Quantizes the `f32` values of the vectors of a field to `i8`s in `[0, 127]`, a
+/// value `x` being approximated by `offset + scale * q`.
+///
+/// The quantizer is calibrated on the vectors of a segment: `offset` and `offset + 127 *
+/// scale` are the quantiles of their values bounding a confidence interval, so that a
+/// few outliers don't stretch the steps of all the other values; the values out of the
+/// interval are clamped to its bounds. The vectors of a `Cosine` field are normalized
+/// before being quantized, so that their dot product is their cosine.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScalarQuantizer {
+    pub similarity: KnnVectorSimilarityFunction,
+    pub offset: f32,
+    pub scale: f32,
+}
+
+impl ScalarQuantizer {
+    pub fn new(similarity: KnnVectorSimilarityFunction, offset: f32, scale: f32) -> Self {
+        ScalarQuantizer {
+            similarity,
+            offset,
+            scale,
+        }
+    }
+
+    /// Calibrates the quantizer on `values`, the vectors of `dimension` values one after
+    /// the other.
+    ///
+    /// The confidence interval holds `max(0.99, 1 - 1 / (dimension + 1))` of the values,
+    /// sampled from at most `MAX_CALIBRATION_VECTORS` vectors.
+    pub fn calibrate(
+        similarity: KnnVectorSimilarityFunction,
+        dimension: usize,
+        values: &[f32],
+    ) -> Self {
+        let num_vectors = values.len() / dimension.max(1);
+        let step = (num_vectors / MAX_CALIBRATION_VECTORS).max(1);
+        let mut sample = Vec::with_capacity(num_vectors.min(MAX_CALIBRATION_VECTORS) * dimension);
+        for vector in values.chunks(dimension).step_by(step) {
+            let normalized = Self::normalized(similarity, vector);
+            let vector = normalized.as_ref().map_or(vector, |n| &n[..]);
+            sample.extend(vector.iter().filter(|v| !v.is_nan()));
+        }
+        if sample.is_empty() {
+            return ScalarQuantizer::new(similarity, 0.0, 1.0);
+        }
+        sample.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let confidence = (1.0 - 1.0 / (dimension as f64 + 1.0)).max(0.99);
+        let last = (sample.len() - 1) as f64;
+        let min = sample[((1.0 - confidence) / 2.0 * last).round() as usize];
+        let max = sample[((1.0 + confidence) / 2.0 * last).round() as usize];
+        let scale = if max > min {
+            (max - min) / MAX_QUANTIZED
+        } else {
+            1.0
+        };
+        ScalarQuantizer::new(similarity, min, scale)
+    }
+
+    /// Appends the quantized values of `vector` to `dest`, returning the correction to
+    /// add to their dot products, see `compare`.
+    pub fn quantize(&self, vector: &[f32], dest: &mut Vec<i8>) -> f32 {
+        let normalized = Self::normalized(self.similarity, vector);
+        let vector = normalized.as_ref().map_or(vector, |n| &n[..]);
+        let mut sum = 0i32;
+        for &v in vector {
+            let q = ((v - self.offset) / self.scale)
+                .round()
+                .max(0.0)
+                .min(MAX_QUANTIZED) as i8;
+            sum += i32::from(q);
+            dest.push(q);
+        }
+        // (o + s * a) . (o + s * b) = s^2 * a . b + (o * s * sum(a) + d * o^2 / 2)
+        //                                         + (o * s * sum(b) + d * o^2 / 2)
+        self.offset * self.scale * sum as f32
+            + vector.len() as f32 * self.offset * self.offset / 2.0
+    }
+
+    /// Returns the approximation of the values quantized to `quantized`.
+    pub fn dequantize(&self, quantized: &[i8]) -> Vec<f32> {
+        quantized
+            .iter()
+            .map(|&q| self.offset + self.scale * f32::from(q))
+            .collect()
+    }
+
+    /// Approximates `KnnVectorSimilarityFunction::compare` of two vectors from their
+    /// quantized values and corrections.
+    pub fn compare(&self, a: &[i8], a_correction: f32, b: &[i8], b_correction: f32) -> f32 {
+        let scale = self.scale * self.scale;
+        match self.similarity {
+            KnnVectorSimilarityFunction::L2 => scale * squared_distance_i8(a, b) as f32,
+            KnnVectorSimilarityFunction::DotProduct | KnnVectorSimilarityFunction::Cosine => {
+                scale * dot_product_i8(a, b) as f32 + a_correction + b_correction
+            }
+            KnnVectorSimilarityFunction::Hamming => {
+                unreachable!("the vectors of a Hamming field are not quantized")
+            }
+        }
+    }
+
+    fn normalized(similarity: KnnVectorSimilarityFunction, vector: &[f32]) -> Option<Vec<f32>> {
+        if similarity != KnnVectorSimilarityFunction::Cosine {
+            return None;
+        }
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm == 0.0 {
+            return None;
+        }
+        Some(vector.iter().map(|v| v / norm).collect())
+    }
+}
+
+/// The dot product of two `i8` vectors of the same length.
+pub fn dot_product_i8(a: &[i8], b: &[i8]) -> i32 {
+    debug_assert_eq!(a.len(), b.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if std::is_x86_feature_detected!("avx2") {
+            return unsafe { dot_product_i8_avx2(a, b) };
+        }
+    }
+    a.iter()
+        .zip(b)
+        .map(|(&x, &y)| i32::from(x) * i32::from(y))
+        .sum()
+}
+
+/// The squared L2 distance of two `i8` vectors of the same length.
+pub fn squared_distance_i8(a: &[i8], b: &[i8]) -> i32 {
+    debug_assert_eq!(a.len(), b.len());
+    #[cfg(target_arch = "x86_64")]
+    {
+        if std::is_x86_feature_detected!("avx2") {
+            return unsafe { squared_distance_i8_avx2(a, b) };
+        }
+    }
+    a.iter()
+        .zip(b)
+        .map(|(&x, &y)| {
+            let d = i32::from(x) - i32::from(y);
+            d * d
+        })
+        .sum()
+}
+
+// the products of 16 values sign extended to i16 are added pairwise into 8 i32 lanes
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn dot_product_i8_avx2(a: &[i8], b: &[i8]) -> i32 {
+    let len = a.len() / 16 * 16;
+    let mut sum = simd::_mm256_setzero_si256();
+    let mut i = 0;
+    while i < len {
+        let va = simd::_mm256_cvtepi8_epi16(simd::_mm_loadu_si128(
+            a.as_ptr().add(i) as *const simd::__m128i
+        ));
+        let vb = simd::_mm256_cvtepi8_epi16(simd::_mm_loadu_si128(
+            b.as_ptr().add(i) as *const simd::__m128i
+        ));
+        sum = simd::_mm256_add_epi32(sum, simd::_mm256_madd_epi16(va, vb));
+        i += 16;
+    }
+    let mut lanes = [0i32; 8];
+    simd::_mm256_storeu_si256(lanes.as_mut_ptr() as *mut simd::__m256i, sum);
+    let tail: i32 = a[len..]
+        .iter()
+        .zip(&b[len..])
+        .map(|(&x, &y)| i32::from(x) * i32::from(y))
+        .sum();
+    lanes.iter().sum::<i32>() + tail
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn squared_distance_i8_avx2(a: &[i8], b: &[i8]) -> i32 {
+    let len = a.len() / 16 * 16;
+    let mut sum = simd::_mm256_setzero_si256();
+    let mut i = 0;
+    while i < len {
+        let va = simd::_mm256_cvtepi8_epi16(simd::_mm_loadu_si128(
+            a.as_ptr().add(i) as *const simd::__m128i
+        ));
+        let vb = simd::_mm256_cvtepi8_epi16(simd::_mm_loadu_si128(
+            b.as_ptr().add(i) as *const simd::__m128i
+        ));
+        let diff = simd::_mm256_sub_epi16(va, vb);
+        sum = simd::_mm256_add_epi32(sum, simd::_mm256_madd_epi16(diff, diff));
+        i += 16;
+    }
+    let mut lanes = [0i32; 8];
+    simd::_mm256_storeu_si256(lanes.as_mut_ptr() as *mut simd::__m256i, sum);
+    let tail: i32 = a[len..]
+        .iter()
+        .zip(&b[len..])
+        .map(|(&x, &y)| {
+            let d = i32::from(x) - i32::from(y);
+            d * d
+        })
+        .sum();
+    lanes.iter().sum::<i32>() + tail
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::test_util::random_vectors;
+
+    #[test]
+    fn test_i8_kernels() {
+        // lengths around the 16 values of a SIMD step
+        for &len in &[0usize, 1, 15, 16, 17, 40, 1024] {
+            let a: Vec<i8> = (0..len).map(|i| (i * 37 % 128) as i8).collect();
+            let b: Vec<i8> = (0..len).map(|i| (i * 91 % 128) as i8).collect();
+            let dot: i32 = a
+                .iter()
+                .zip(&b)
+                .map(|(&x, &y)| i32::from(x) * i32::from(y))
+                .sum();
+            let distance: i32 = a
+                .iter()
+                .zip(&b)
+                .map(|(&x, &y)| (i32::from(x) - i32::from(y)).pow(2))
+                .sum();
+            assert_eq!(dot_product_i8(&a, &b), dot);
+            assert_eq!(squared_distance_i8(&a, &b), distance);
+        }
+    }
+
+    #[test]
+    fn test_quantized_compare() {
+        let dimension = 16;
+        let vectors = random_vectors(1000, dimension, 0x2545_f491_4f6c_dd1d).concat();
+        let queries = random_vectors(20, dimension, 0x1234_5678_9abc_def1).concat();
+        for &similarity in &[
+            KnnVectorSimilarityFunction::L2,
+            KnnVectorSimilarityFunction::DotProduct,
+            KnnVectorSimilarityFunction::Cosine,
+        ] {
+            let quantizer = ScalarQuantizer::calibrate(similarity, dimension, &vectors);
+            let mut quantized = Vec::with_capacity(vectors.len());
+            let corrections: Vec<f32> = vectors
+                .chunks(dimension)
+                .map(|v| quantizer.quantize(v, &mut quantized))
+                .collect();
+            assert!(quantized.iter().all(|&q| q >= 0));
+
+            let mut found = 0;
+            // the loss is measured on the scores, the products themselves being about 0
+            let mut error = 0f32;
+            let mut total = 0f32;
+            for query in queries.chunks(dimension) {
+                let mut quantized_query = vec![];
+                let query_correction = quantizer.quantize(query, &mut quantized_query);
+                let mut exact: Vec<(usize, f32)> = vectors
+                    .chunks(dimension)
+                    .map(|v| similarity.compare(query, v))
+                    .enumerate()
+                    .collect();
+                let mut approximate: Vec<(usize, f32)> = quantized
+                    .chunks(dimension)
+                    .zip(&corrections)
+                    .map(|(v, &c)| quantizer.compare(&quantized_query, query_correction, v, c))
+                    .enumerate()
+                    .collect();
+                for (e, a) in exact.iter().zip(&approximate) {
+                    error += (similarity.score_of(e.1) - similarity.score_of(a.1)).abs();
+                    total += similarity.score_of(e.1);
+                }
+                // the nearest vectors have the lowest distance, or the highest product
+                let order = |a: &(usize, f32), b: &(usize, f32)| a.1.partial_cmp(&b.1).unwrap();
+                if similarity == KnnVectorSimilarityFunction::L2 {
+                    exact.sort_by(order);
+                    approximate.sort_by(order);
+                } else {
+                    exact.sort_by(|a, b| order(b, a));
+                    approximate.sort_by(|a, b| order(b, a));
+                }
+                let expected: Vec<usize> = exact.iter().take(10).map(|e| e.0).collect();
+                found += approximate
+                    .iter()
+                    .take(10)
+                    .filter(|a| expected.contains(&a.0))
+                    .count();
+            }
+            let recall = found as f32 / 200.0;
+            assert!(recall >= 0.9, "recall {} of {:?}", recall, similarity);
+            let loss = error / total;
+            assert!(loss < 0.01, "loss {} of {:?}", loss, similarity);
+        }
+    }
+
+    #[test]
+    fn test_calibrate_outliers() {
+        let similarity = KnnVectorSimilarityFunction::L2;
+        let mut values: Vec<f32> = (0..1000).map(|i| i as f32 / 1000.0).collect();
+        values.push(1000.0);
+        values.push(::std::f32::NAN);
+        let quantizer = ScalarQuantizer::calibrate(similarity, 1, &values);
+        // the outlier doesn't stretch the steps of the other values
+        assert!(quantizer.offset >= 0.0 && quantizer.offset < 0.01);
+        assert!(quantizer.scale < 1.0 / MAX_QUANTIZED);
+        let mut quantized = vec![];
+        quantizer.quantize(&[1000.0], &mut quantized);
+        quantizer.quantize(&[-1000.0], &mut quantized);
+        assert_eq!(quantized, vec![127, 0]);
+    }
+
+    #[test]
+    fn test_calibrate_constant_values() {
+        let similarity = KnnVectorSimilarityFunction::DotProduct;
+        let quantizer = ScalarQuantizer::calibrate(similarity, 2, &[0.5, 0.5, 0.5, 0.5]);
+        assert_eq!(quantizer.offset, 0.5);
+        let mut quantized = vec![];
+        let correction = quantizer.quantize(&[0.5, 0.5], &mut quantized);
+        assert_eq!(quantized, vec![0, 0]);
+        assert_eq!(quantizer.dequantize(&quantized), vec![0.5, 0.5]);
+        let product = quantizer.compare(&quantized, correction, &quantized, correction);
+        assert!((product - 0.5).abs() < 1e-6);
+
+        let empty = ScalarQuantizer::calibrate(similarity, 2, &[]);
+        assert_eq!(empty.scale, 1.0);
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::field_infos::FieldInfo;
+use core::codec::vectors::{binary_words, DenseVectorsWriter, KnnVectorSimilarityFunction};
+use core::codec::SorterDocMap;
+use core::store::io::IndexOutput;
+use core::util::DocId;
+
+use error::Result;
+
+/// Buffers the dense vectors of one field in RAM until the segment is flushed.
+pub struct VectorValuesWriter {
+    field_info: FieldInfo,
+    docs: Vec<DocId>,
+    values: Vec<f32>,
+    // the packed bits of a Hamming field, instead of `values`
+    words: Vec<u64>,
+}
+
+impl VectorValuesWriter {
+    pub fn new(field_info: &FieldInfo) -> VectorValuesWriter {
+        VectorValuesWriter {
+            field_info: field_info.clone(),
+            docs: vec![],
+            values: vec![],
+            words: vec![],
+        }
+    }
+
+    fn is_binary(&self) -> bool {
+        self.field_info.vector_similarity == KnnVectorSimilarityFunction::Hamming
+    }
+
+    /// Adds the vector of `doc_id`, encoded as little endian `f32`s, or as the little
+    /// endian packed words of a `BinaryVectorField`.
+    pub fn add_value(&mut self, doc_id: DocId, value: &[u8]) -> Result<()> {
+        let dimension = self.field_info.vector_dimension as usize;
+        let length = if self.is_binary() {
+            binary_words(dimension) * 8
+        } else {
+            dimension * 4
+        };
+        if value.len() != length {
+            bail!(
+                "field={}: this field's vector has {} bytes but should have dimension={}",
+                self.field_info.name,
+                value.len(),
+                dimension
+            );
+        }
+        if let Some(&last_doc_id) = self.docs.last() {
+            if last_doc_id >= doc_id {
+                bail!(
+                    "field={}: a document can only have one vector",
+                    self.field_info.name
+                );
+            }
+        }
+        self.docs.push(doc_id);
+        if self.is_binary() {
+            self.words.extend(
+                value
+                    .chunks_exact(8)
+                    .map(|b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])),
+            );
+        } else {
+            self.values.extend(
+                value
+                    .chunks_exact(4)
+                    .map(|b| f32::from_bits(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))),
+            );
+        }
+        Ok(())
+    }
+
+    pub fn flush<O: IndexOutput>(
+        &self,
+        sort_map: Option<&impl SorterDocMap>,
+        writer: &mut DenseVectorsWriter<O>,
+    ) -> Result<()> {
+        let field_info = &self.field_info;
+        if let Some(sort_map) = sort_map {
+            let mut ords: Vec<usize> = (0..self.docs.len()).collect();
+            ords.sort_by_key(|&ord| sort_map.old_to_new(self.docs[ord]));
+            let docs: Vec<DocId> = ords
+                .iter()
+                .map(|&ord| sort_map.old_to_new(self.docs[ord]))
+                .collect();
+            if self.is_binary() {
+                let words = sorted(&self.words, &ords, binary_words(self.dimension()));
+                writer.write_binary_field(field_info, &docs, &words)
+            } else {
+                let values = sorted(&self.values, &ords, self.dimension());
+                writer.write_field(field_info, &docs, &values)
+            }
+        } else if self.is_binary() {
+            writer.write_binary_field(field_info, &self.docs, &self.words)
+        } else {
+            writer.write_field(field_info, &self.docs, &self.values)
+        }
+    }
+
+    fn dimension(&self) -> usize {
+        self.field_info.vector_dimension as usize
+    }
+}
+
+// the vectors of `length` values of `values`, in the order of `ords`
+fn sorted<T: Copy>(values: &[T], ords: &[usize], length: usize) -> Vec<T> {
+    let mut sorted = Vec::with_capacity(values.len());
+    for &ord in ords {
+        sorted.extend_from_slice(&values[ord * length..(ord + 1) * length]);
+    }
+    sorted
+}
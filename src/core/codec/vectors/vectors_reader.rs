@@ -0,0 +1,559 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::field_infos::FieldInfo;
+use core::codec::segment_infos::{segment_file_name, SegmentReadState};
+use core::codec::vectors::{
+    binary_words, hamming_distance, pack_bits, KnnVectorSimilarityFunction, ScalarQuantizer,
+    QUANTIZED_VECTOR_OFFSET_KEY, QUANTIZED_VECTOR_SCALE_KEY, VECTOR_DATA_CODEC_NAME,
+    VECTOR_DATA_EXTENSION, VECTOR_ENCODING_INT8, VECTOR_ENCODING_RAW, VECTOR_INDEX_CODEC_NAME,
+    VECTOR_INDEX_EXTENSION, VECTOR_VERSION_CURRENT, VECTOR_VERSION_HNSW, VECTOR_VERSION_QUANTIZED,
+    VECTOR_VERSION_START,
+};
+use core::codec::{codec_util, Codec};
+use core::index::HnswGraph;
+use core::store::directory::Directory;
+use core::store::io::{DataInput, IndexInput};
+use core::util::DocId;
+
+use error::ErrorKind::{CorruptIndex, IllegalArgument};
+use error::Result;
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::slice;
+
+/// Reads the dense vectors written by `DenseVectorsWriter`.
+pub struct DenseVectorsReader {
+    fields: HashMap<String, VectorReader>,
+    data_in: Box<dyn IndexInput>,
+}
+
+impl DenseVectorsReader {
+    pub fn new<D: Directory, DW: Directory, C: Codec>(
+        state: &SegmentReadState<'_, D, DW, C>,
+    ) -> Result<DenseVectorsReader> {
+        let index_file_name = segment_file_name(
+            &state.segment_info.name,
+            &state.segment_suffix,
+            VECTOR_INDEX_EXTENSION,
+        );
+        let mut index_in = state
+            .directory
+            .open_checksum_input(&index_file_name, state.context)?;
+        let version = codec_util::check_index_header(
+            &mut index_in,
+            VECTOR_INDEX_CODEC_NAME,
+            VECTOR_VERSION_START,
+            VECTOR_VERSION_CURRENT,
+            &state.segment_info.id,
+            &state.segment_suffix,
+        )?;
+        let max_doc = state.segment_info.max_doc() as usize;
+        // (field number, similarity, dimension, offset, quantized offset, graph offset, docs)
+        let mut entries = vec![];
+        loop {
+            let field_number = index_in.read_vint()?;
+            if field_number == -1 {
+                break;
+            }
+            let similarity = KnnVectorSimilarityFunction::from_byte(index_in.read_byte()?)?;
+            let dimension = index_in.read_vint()? as usize;
+            let count = index_in.read_vint()?;
+            // checked before allocating the docs, the footer is only checked at the end
+            if count < 0 || count as usize > max_doc {
+                bail!(CorruptIndex(format!(
+                    "invalid vector count {} of field {} in a segment of {} docs",
+                    count, field_number, max_doc
+                )));
+            }
+            let count = count as usize;
+            let offset = index_in.read_vlong()?;
+            let quantized_offset = if version >= VECTOR_VERSION_QUANTIZED {
+                match index_in.read_byte()? {
+                    VECTOR_ENCODING_RAW => None,
+                    VECTOR_ENCODING_INT8 => Some(index_in.read_vlong()?),
+                    encoding => bail!(CorruptIndex(format!(
+                        "invalid vector encoding: {}",
+                        encoding
+                    ))),
+                }
+            } else {
+                None
+            };
+            let graph_offset = if version >= VECTOR_VERSION_HNSW {
+                Some(index_in.read_vlong()?)
+            } else {
+                None
+            };
+            let mut docs = Vec::with_capacity(count);
+            let mut doc = -1;
+            for _ in 0..count {
+                doc += index_in.read_vint()? + 1;
+                docs.push(doc);
+            }
+            entries.push((
+                field_number,
+                similarity,
+                dimension,
+                offset,
+                quantized_offset,
+                graph_offset,
+                docs,
+            ));
+        }
+        codec_util::check_footer(&mut index_in)?;
+
+        let data_file_name = segment_file_name(
+            &state.segment_info.name,
+            &state.segment_suffix,
+            VECTOR_DATA_EXTENSION,
+        );
+        let mut data_in = state.directory.open_input(&data_file_name, state.context)?;
+        codec_util::check_index_header(
+            data_in.as_mut(),
+            VECTOR_DATA_CODEC_NAME,
+            VECTOR_VERSION_START,
+            VECTOR_VERSION_CURRENT,
+            &state.segment_info.id,
+            &state.segment_suffix,
+        )?;
+        // the data file is too costly to checksum on open, only check the footer structure
+        codec_util::retrieve_checksum(data_in.as_mut())?;
+        let data_len = data_in.len() as i64 - codec_util::footer_length() as i64;
+
+        let mut fields = HashMap::with_capacity(entries.len());
+        for entry in entries {
+            let (field_number, similarity, dimension, offset, quantized_offset, graph_offset, docs) =
+                entry;
+            let field_info = match state.field_infos.field_info_by_number(field_number as u32) {
+                Some(field_info) => field_info,
+                None => bail!(CorruptIndex(format!(
+                    "invalid vector field number: {}",
+                    field_number
+                ))),
+            };
+            if field_info.vector_dimension as usize != dimension {
+                bail!(CorruptIndex(format!(
+                    "vector dimension mismatch for field '{}': {} != {}",
+                    field_info.name, field_info.vector_dimension, dimension
+                )));
+            }
+            let binary = similarity == KnnVectorSimilarityFunction::Hamming;
+            let (length, alignment) = if binary {
+                ((docs.len() * binary_words(dimension) * 8) as i64, 8)
+            } else {
+                ((docs.len() * dimension * 4) as i64, 4)
+            };
+            if offset % alignment != 0 || offset + length > data_len {
+                bail!(CorruptIndex(format!(
+                    "invalid vector data offset {} and length {} for field '{}'",
+                    offset, length, field_info.name
+                )));
+            }
+            let values = if binary {
+                FieldValues::Binary(VectorValues::load(data_in.as_mut(), offset, length)?)
+            } else {
+                FieldValues::Float(VectorValues::load(data_in.as_mut(), offset, length)?)
+            };
+            let quantized = match quantized_offset {
+                Some(quantized_offset) => Some(QuantizedValues::load(
+                    data_in.as_mut(),
+                    field_info,
+                    similarity,
+                    quantized_offset,
+                    docs.len(),
+                    data_len,
+                )?),
+                None => None,
+            };
+            let graph = match graph_offset {
+                Some(graph_offset) => {
+                    if graph_offset < 0 || graph_offset >= data_len {
+                        bail!(CorruptIndex(format!(
+                            "invalid graph offset {} for field '{}'",
+                            graph_offset, field_info.name
+                        )));
+                    }
+                    data_in.seek(graph_offset)?;
+                    let graph = HnswGraph::read(data_in.as_mut(), docs.len())?;
+                    if data_in.file_pointer() > data_len {
+                        bail!(CorruptIndex(format!(
+                            "the graph of field '{}' overlaps the footer",
+                            field_info.name
+                        )));
+                    }
+                    Some(graph)
+                }
+                None => None,
+            };
+            let reader = VectorReader {
+                dimension,
+                similarity,
+                docs,
+                values,
+                quantized,
+                graph,
+            };
+            fields.insert(field_info.name.clone(), reader);
+        }
+
+        Ok(DenseVectorsReader { fields, data_in })
+    }
+
+    /// Returns the vectors of `field`, or `None` if no document of this segment has one.
+    pub fn field(&self, field: &str) -> Option<&VectorReader> {
+        self.fields.get(field)
+    }
+
+    pub fn check_integrity(&self) -> Result<()> {
+        codec_util::checksum_entire_file(self.data_in.as_ref())?;
+        Ok(())
+    }
+}
+
+// a value of the vectors, stored in little endian
+trait VectorValue: Copy {
+    const SIZE: usize;
+
+    fn decode(bytes: &[u8]) -> Self;
+}
+
+impl VectorValue for f32 {
+    const SIZE: usize = 4;
+
+    fn decode(b: &[u8]) -> f32 {
+        f32::from_bits(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+impl VectorValue for u64 {
+    const SIZE: usize = 8;
+
+    fn decode(b: &[u8]) -> u64 {
+        u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])
+    }
+}
+
+enum VectorValues<T: VectorValue> {
+    // the memory mapped little endian vectors, used in place
+    Mapped(Box<dyn IndexInput>),
+    Heap(Vec<T>),
+}
+
+impl<T: VectorValue> VectorValues<T> {
+    fn load(data_in: &mut dyn IndexInput, offset: i64, length: i64) -> Result<VectorValues<T>> {
+        let slice = data_in.slice("DenseVectors", offset, length)?;
+        let mapped = match slice.mapped_bytes() {
+            Some(bytes) => cfg!(target_endian = "little") && bytes.as_ptr() as usize % T::SIZE == 0,
+            None => false,
+        };
+        if mapped {
+            return Ok(VectorValues::Mapped(slice));
+        }
+
+        let mut bytes = vec![0u8; length as usize];
+        data_in.seek(offset)?;
+        data_in.read_exact(&mut bytes)?;
+        let values = bytes.chunks_exact(T::SIZE).map(T::decode).collect();
+        Ok(VectorValues::Heap(values))
+    }
+
+    fn as_slice(&self) -> &[T] {
+        match self {
+            VectorValues::Mapped(input) => {
+                let bytes = input.mapped_bytes().unwrap();
+                // checked to be aligned and little endian on load
+                unsafe { slice::from_raw_parts(bytes.as_ptr() as *const T, bytes.len() / T::SIZE) }
+            }
+            VectorValues::Heap(values) => values,
+        }
+    }
+}
+
+// the `f32` vectors of a field, or the packed bits of a Hamming field
+enum FieldValues {
+    Float(VectorValues<f32>),
+    Binary(VectorValues<u64>),
+}
+
+// the quantized vectors of a field, with the correction of each vector
+struct QuantizedValues {
+    quantizer: ScalarQuantizer,
+    values: QuantizedBytes,
+    corrections: Vec<f32>,
+}
+
+enum QuantizedBytes {
+    // the memory mapped values, used in place
+    Mapped(Box<dyn IndexInput>),
+    Heap(Vec<i8>),
+}
+
+impl QuantizedBytes {
+    fn as_slice(&self) -> &[i8] {
+        match self {
+            QuantizedBytes::Mapped(input) => {
+                let bytes = input.mapped_bytes().unwrap();
+                unsafe { slice::from_raw_parts(bytes.as_ptr() as *const i8, bytes.len()) }
+            }
+            QuantizedBytes::Heap(values) => values,
+        }
+    }
+}
+
+impl QuantizedValues {
+    fn load(
+        data_in: &mut dyn IndexInput,
+        field_info: &FieldInfo,
+        similarity: KnnVectorSimilarityFunction,
+        offset: i64,
+        count: usize,
+        data_len: i64,
+    ) -> Result<QuantizedValues> {
+        if similarity == KnnVectorSimilarityFunction::Hamming {
+            bail!(CorruptIndex(format!(
+                "the vectors of Hamming field '{}' can't be quantized",
+                field_info.name
+            )));
+        }
+        let attribute = |key: &str| -> Result<f32> {
+            match field_info.attribute(key).map(|v| v.parse::<f32>()) {
+                Some(Ok(v)) => Ok(v),
+                _ => bail!(CorruptIndex(format!(
+                    "missing or invalid attribute {} of quantized vector field '{}'",
+                    key, field_info.name
+                ))),
+            }
+        };
+        let quantizer = ScalarQuantizer::new(
+            similarity,
+            attribute(QUANTIZED_VECTOR_OFFSET_KEY)?,
+            attribute(QUANTIZED_VECTOR_SCALE_KEY)?,
+        );
+
+        let length = count * field_info.vector_dimension as usize;
+        let corrections_offset = (offset + length as i64 + 3) / 4 * 4;
+        let corrections_length = (count * 4) as i64;
+        if offset < 0 || corrections_offset + corrections_length > data_len {
+            bail!(CorruptIndex(format!(
+                "invalid quantized vector offset {} for field '{}'",
+                offset, field_info.name
+            )));
+        }
+        let slice = data_in.slice("QuantizedVectors", offset, length as i64)?;
+        let values = if slice.mapped_bytes().is_some() {
+            QuantizedBytes::Mapped(slice)
+        } else {
+            let mut bytes = vec![0u8; length];
+            data_in.seek(offset)?;
+            data_in.read_exact(&mut bytes)?;
+            QuantizedBytes::Heap(bytes.iter().map(|&b| b as i8).collect())
+        };
+        let mut bytes = vec![0u8; corrections_length as usize];
+        data_in.seek(corrections_offset)?;
+        data_in.read_exact(&mut bytes)?;
+        let corrections = bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_bits(u32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+            .collect();
+        Ok(QuantizedValues {
+            quantizer,
+            values,
+            corrections,
+        })
+    }
+}
+
+/// The dense vectors of one field of a segment.
+pub struct VectorReader {
+    dimension: usize,
+    similarity: KnnVectorSimilarityFunction,
+    docs: Vec<DocId>,
+    values: FieldValues,
+    quantized: Option<QuantizedValues>,
+    graph: Option<HnswGraph>,
+}
+
+impl VectorReader {
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    pub fn similarity(&self) -> KnnVectorSimilarityFunction {
+        self.similarity
+    }
+
+    /// The docs having a vector, in increasing order.
+    pub fn docs(&self) -> &[DocId] {
+        &self.docs
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Returns the vector of `doc_id`, without copying if the data file is memory mapped.
+    ///
+    /// The packed bits of a `Hamming` field are read with `binary_vector_at`.
+    pub fn get_vector(&self, doc_id: DocId) -> Result<&[f32]> {
+        if self.similarity == KnnVectorSimilarityFunction::Hamming {
+            bail!(IllegalArgument(
+                "the vectors of a Hamming field are packed bits".into()
+            ));
+        }
+        match self.docs.binary_search(&doc_id) {
+            Ok(ord) => Ok(self.vector_at(ord)),
+            Err(_) => bail!(IllegalArgument(format!("doc {} has no vector", doc_id))),
+        }
+    }
+
+    /// Returns the `ord`-th vector, i.e. the vector of `docs()[ord]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a `Hamming` field, see `binary_vector_at`.
+    pub fn vector_at(&self, ord: usize) -> &[f32] {
+        match &self.values {
+            FieldValues::Float(values) => {
+                &values.as_slice()[ord * self.dimension..(ord + 1) * self.dimension]
+            }
+            FieldValues::Binary(_) => panic!("the vectors of a Hamming field are packed bits"),
+        }
+    }
+
+    /// Returns the packed bits of the `ord`-th vector of a `Hamming` field, see
+    /// `pack_bits`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this isn't a `Hamming` field, see `vector_at`.
+    pub fn binary_vector_at(&self, ord: usize) -> &[u64] {
+        match &self.values {
+            FieldValues::Binary(words) => {
+                let length = binary_words(self.dimension);
+                &words.as_slice()[ord * length..(ord + 1) * length]
+            }
+            FieldValues::Float(_) => panic!("only the vectors of a Hamming field are packed bits"),
+        }
+    }
+
+    /// The graph linking the vectors by ord, `None` for the segments written before the
+    /// graphs were part of the format.
+    pub fn graph(&self) -> Option<&HnswGraph> {
+        self.graph.as_ref()
+    }
+
+    /// The quantizer of the vectors, `None` if they weren't written quantized.
+    pub fn quantizer(&self) -> Option<&ScalarQuantizer> {
+        self.quantized.as_ref().map(|q| &q.quantizer)
+    }
+
+    /// Returns the quantized values of the `ord`-th vector with their correction,
+    /// `None` if the vectors weren't written quantized.
+    pub fn quantized_vector_at(&self, ord: usize) -> Option<(&[i8], f32)> {
+        self.quantized.as_ref().map(|q| {
+            let values = &q.values.as_slice()[ord * self.dimension..(ord + 1) * self.dimension];
+            (values, q.corrections[ord])
+        })
+    }
+
+    /// Returns a scorer comparing `query` to the vectors of this field, with their
+    /// quantized values if they were written quantized.
+    pub fn scorer(&self, query: &[f32]) -> Result<VectorScorer<'_>> {
+        self.check_query(query)?;
+        let query = match (&self.quantized, &self.values) {
+            (Some(q), _) => {
+                let mut values = Vec::with_capacity(query.len());
+                let correction = q.quantizer.quantize(query, &mut values);
+                ScorerQuery::Quantized(values, correction)
+            }
+            (None, FieldValues::Binary(_)) => ScorerQuery::Binary(pack_bits(query)),
+            (None, FieldValues::Float(_)) => ScorerQuery::Float(query.to_vec()),
+        };
+        Ok(VectorScorer {
+            reader: self,
+            query,
+        })
+    }
+
+    /// Like `scorer`, but always comparing `query` to the `f32` vectors, or the packed
+    /// bits of a `Hamming` field.
+    pub fn exact_scorer(&self, query: &[f32]) -> Result<VectorScorer<'_>> {
+        self.check_query(query)?;
+        let query = match &self.values {
+            FieldValues::Binary(_) => ScorerQuery::Binary(pack_bits(query)),
+            FieldValues::Float(_) => ScorerQuery::Float(query.to_vec()),
+        };
+        Ok(VectorScorer {
+            reader: self,
+            query,
+        })
+    }
+
+    fn check_query(&self, query: &[f32]) -> Result<()> {
+        if query.len() != self.dimension {
+            bail!(IllegalArgument(format!(
+                "query vector dimension {} differs from the field dimension {}",
+                query.len(),
+                self.dimension
+            )));
+        }
+        Ok(())
+    }
+}
+
+enum ScorerQuery {
+    Float(Vec<f32>),
+    // the quantized values of the query with their correction
+    Quantized(Vec<i8>, f32),
+    Binary(Vec<u64>),
+}
+
+/// Compares a query vector to the vectors of a `VectorReader`.
+pub struct VectorScorer<'a> {
+    reader: &'a VectorReader,
+    query: ScorerQuery,
+}
+
+impl<'a> VectorScorer<'a> {
+    /// Compares the query to the `ord`-th vector like
+    /// `KnnVectorSimilarityFunction::compare`, approximated with `i8` products when the
+    /// vectors are quantized, or counting the differing bits of the packed words of a
+    /// `Hamming` field.
+    pub fn compare(&self, ord: usize) -> f32 {
+        match &self.query {
+            ScorerQuery::Float(query) => self
+                .reader
+                .similarity
+                .compare(query, self.reader.vector_at(ord)),
+            ScorerQuery::Quantized(query, query_correction) => {
+                let quantizer = self.reader.quantizer().unwrap();
+                let (values, correction) = self.reader.quantized_vector_at(ord).unwrap();
+                quantizer.compare(query, *query_correction, values, correction)
+            }
+            ScorerQuery::Binary(query) => {
+                hamming_distance(query, self.reader.binary_vector_at(ord)) as f32
+            }
+        }
+    }
+
+    /// The score of the `ord`-th vector, see `KnnVectorSimilarityFunction::score_of`.
+    pub fn score(&self, ord: usize) -> f32 {
+        self.reader.similarity.score_of(self.compare(ord))
+    }
+}
@@ -0,0 +1,363 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::field_infos::FieldInfo;
+use core::codec::segment_infos::{segment_file_name, SegmentWriteState};
+use core::codec::vectors::{
+    binary_words, hamming_distance, KnnVectorSimilarityFunction, ScalarQuantizer,
+    QUANTIZED_VECTOR_OFFSET_KEY, QUANTIZED_VECTOR_SCALE_KEY, VECTOR_DATA_CODEC_NAME,
+    VECTOR_DATA_EXTENSION, VECTOR_ENCODING_INT8, VECTOR_ENCODING_RAW, VECTOR_INDEX_CODEC_NAME,
+    VECTOR_INDEX_EXTENSION, VECTOR_VERSION_CURRENT,
+};
+use core::codec::{codec_util, Codec};
+use core::index::merge::{DocMap, MergeState};
+use core::index::{HnswGraph, HnswGraphBuilder};
+use core::store::directory::Directory;
+use core::store::io::{DataOutput, IndexOutput};
+use core::util::DocId;
+
+use error::ErrorKind::{IllegalArgument, IllegalState};
+use error::Result;
+
+/// Writes the dense vectors of a segment.
+///
+/// The vectors of each field are written to the `.vec` file as consecutive little
+/// endian `f32`s, starting at a 4 bytes aligned offset. For each field the `.vmi` file
+/// records the field number, the similarity function, the dimension, the number of
+/// vectors, the offset of the vectors in the `.vec` file, their encoding and the delta
+/// encoded docs; it is terminated by a field number of `-1`.
+///
+/// When quantizing, the `f32` vectors of a field are followed by their quantized `i8`
+/// values and, 4 bytes aligned, the little endian `f32` correction of each vector, whose
+/// offset in the `.vec` file is recorded after the encoding.
+///
+/// The packed bits of a `Hamming` field are written instead as consecutive little endian
+/// `u64`s, starting at a 8 bytes aligned offset, and are never quantized.
+///
+/// The vectors of a field are then followed by their `HnswGraph`, built with
+/// `HnswGraphBuilder` whenever a segment is flushed or merged, whose offset in the
+/// `.vec` file ends the entry of the field.
+pub struct DenseVectorsWriter<O: IndexOutput> {
+    data_out: O,
+    index_out: O,
+    quantize: bool,
+    finished: bool,
+}
+
+impl<O: IndexOutput> DenseVectorsWriter<O> {
+    pub fn new<D: Directory, DW: Directory<IndexOutput = O>, C: Codec>(
+        state: &SegmentWriteState<D, DW, C>,
+    ) -> Result<DenseVectorsWriter<O>> {
+        Self::create(state, false)
+    }
+
+    /// Creates a writer that also writes the vectors quantized by a `ScalarQuantizer`.
+    pub fn with_quantization<D: Directory, DW: Directory<IndexOutput = O>, C: Codec>(
+        state: &SegmentWriteState<D, DW, C>,
+    ) -> Result<DenseVectorsWriter<O>> {
+        Self::create(state, true)
+    }
+
+    fn create<D: Directory, DW: Directory<IndexOutput = O>, C: Codec>(
+        state: &SegmentWriteState<D, DW, C>,
+        quantize: bool,
+    ) -> Result<DenseVectorsWriter<O>> {
+        let data_file_name = segment_file_name(
+            &state.segment_info.name,
+            &state.segment_suffix,
+            VECTOR_DATA_EXTENSION,
+        );
+        let mut data_out = state
+            .directory
+            .create_output(&data_file_name, &state.context)?;
+        codec_util::write_index_header(
+            &mut data_out,
+            VECTOR_DATA_CODEC_NAME,
+            VECTOR_VERSION_CURRENT,
+            state.segment_info.get_id(),
+            &state.segment_suffix,
+        )?;
+
+        let index_file_name = segment_file_name(
+            &state.segment_info.name,
+            &state.segment_suffix,
+            VECTOR_INDEX_EXTENSION,
+        );
+        let mut index_out = state
+            .directory
+            .create_output(&index_file_name, &state.context)?;
+        codec_util::write_index_header(
+            &mut index_out,
+            VECTOR_INDEX_CODEC_NAME,
+            VECTOR_VERSION_CURRENT,
+            state.segment_info.get_id(),
+            &state.segment_suffix,
+        )?;
+
+        Ok(DenseVectorsWriter {
+            data_out,
+            index_out,
+            quantize,
+            finished: false,
+        })
+    }
+
+    /// Writes the vectors of one field, `values` holds the vectors of `docs`, which
+    /// must be increasing, one after the other.
+    pub fn write_field(
+        &mut self,
+        field_info: &FieldInfo,
+        docs: &[DocId],
+        values: &[f32],
+    ) -> Result<()> {
+        let dimension = field_info.vector_dimension as usize;
+        Self::check_values(field_info, docs, values.len(), dimension)?;
+        if field_info.vector_similarity == KnnVectorSimilarityFunction::Hamming {
+            bail!(IllegalArgument(format!(
+                "the vectors of Hamming field '{}' are written as packed words",
+                field_info.name
+            )));
+        }
+
+        let offset = self.align(4)?;
+        let mut bytes = Vec::with_capacity(values.len() * 4);
+        for v in values {
+            bytes.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        self.data_out.write_bytes(&bytes, 0, bytes.len())?;
+        let quantized_offset = if self.quantize {
+            Some(self.write_quantized(field_info, values)?)
+        } else {
+            None
+        };
+        let graph_offset = self.data_out.file_pointer();
+        let similarity = field_info.vector_similarity;
+        let vector = |ord: usize| &values[ord * dimension..(ord + 1) * dimension];
+        self.write_graph(docs.len(), |a, b| similarity.score(vector(a), vector(b)))?;
+
+        self.write_entry(field_info, docs, offset, quantized_offset, graph_offset)
+    }
+
+    /// Writes the vectors of one `Hamming` field, `words` holds the packed bits of the
+    /// vectors of `docs`, which must be increasing, one after the other.
+    pub fn write_binary_field(
+        &mut self,
+        field_info: &FieldInfo,
+        docs: &[DocId],
+        words: &[u64],
+    ) -> Result<()> {
+        let dimension = field_info.vector_dimension as usize;
+        let length = binary_words(dimension);
+        Self::check_values(field_info, docs, words.len(), length)?;
+        if field_info.vector_similarity != KnnVectorSimilarityFunction::Hamming {
+            bail!(IllegalArgument(format!(
+                "the vectors of field '{}' aren't bits",
+                field_info.name
+            )));
+        }
+
+        let offset = self.align(8)?;
+        let mut bytes = Vec::with_capacity(words.len() * 8);
+        for w in words {
+            bytes.extend_from_slice(&w.to_le_bytes());
+        }
+        self.data_out.write_bytes(&bytes, 0, bytes.len())?;
+        // the bits have nothing to quantize
+        let graph_offset = self.data_out.file_pointer();
+        let similarity = field_info.vector_similarity;
+        let vector = |ord: usize| &words[ord * length..(ord + 1) * length];
+        self.write_graph(docs.len(), |a, b| {
+            similarity.score_of(hamming_distance(vector(a), vector(b)) as f32)
+        })?;
+
+        self.write_entry(field_info, docs, offset, None, graph_offset)
+    }
+
+    fn check_values(
+        field_info: &FieldInfo,
+        docs: &[DocId],
+        num_values: usize,
+        length: usize,
+    ) -> Result<()> {
+        if field_info.vector_dimension == 0 {
+            bail!(IllegalArgument(format!(
+                "field '{}' has no vector dimension",
+                field_info.name
+            )));
+        }
+        if num_values != docs.len() * length {
+            bail!(IllegalArgument(format!(
+                "field '{}': got {} values for {} vectors of dimension {}",
+                field_info.name,
+                num_values,
+                docs.len(),
+                field_info.vector_dimension
+            )));
+        }
+        Ok(())
+    }
+
+    // pads the data file to a multiple of `alignment`, returning its file pointer
+    fn align(&mut self, alignment: i64) -> Result<i64> {
+        while self.data_out.file_pointer() % alignment != 0 {
+            self.data_out.write_byte(0)?;
+        }
+        Ok(self.data_out.file_pointer())
+    }
+
+    fn write_entry(
+        &mut self,
+        field_info: &FieldInfo,
+        docs: &[DocId],
+        offset: i64,
+        quantized_offset: Option<i64>,
+        graph_offset: i64,
+    ) -> Result<()> {
+        self.index_out.write_vint(field_info.number as i32)?;
+        self.index_out
+            .write_byte(field_info.vector_similarity.as_byte())?;
+        self.index_out
+            .write_vint(field_info.vector_dimension as i32)?;
+        self.index_out.write_vint(docs.len() as i32)?;
+        self.index_out.write_vlong(offset)?;
+        if let Some(quantized_offset) = quantized_offset {
+            self.index_out.write_byte(VECTOR_ENCODING_INT8)?;
+            self.index_out.write_vlong(quantized_offset)?;
+        } else {
+            self.index_out.write_byte(VECTOR_ENCODING_RAW)?;
+        }
+        self.index_out.write_vlong(graph_offset)?;
+        let mut last_doc = -1;
+        for &doc in docs {
+            if doc <= last_doc {
+                bail!(IllegalArgument(format!(
+                    "field '{}': docs must be increasing, got {} after {}",
+                    field_info.name, doc, last_doc
+                )));
+            }
+            self.index_out.write_vint(doc - last_doc - 1)?;
+            last_doc = doc;
+        }
+        Ok(())
+    }
+
+    // writes the quantized vectors and their corrections, returning their offset
+    fn write_quantized(&mut self, field_info: &FieldInfo, values: &[f32]) -> Result<i64> {
+        let dimension = field_info.vector_dimension as usize;
+        let quantizer = ScalarQuantizer::calibrate(field_info.vector_similarity, dimension, values);
+        let mut quantized = Vec::with_capacity(values.len());
+        let mut corrections = Vec::with_capacity(values.len() / dimension * 4);
+        for vector in values.chunks(dimension) {
+            let correction = quantizer.quantize(vector, &mut quantized);
+            corrections.extend_from_slice(&correction.to_bits().to_le_bytes());
+        }
+
+        let offset = self.data_out.file_pointer();
+        let bytes: Vec<u8> = quantized.iter().map(|&q| q as u8).collect();
+        self.data_out.write_bytes(&bytes, 0, bytes.len())?;
+        while self.data_out.file_pointer() % 4 != 0 {
+            self.data_out.write_byte(0)?;
+        }
+        self.data_out
+            .write_bytes(&corrections, 0, corrections.len())?;
+
+        // the field infos are written after the vectors, by flush or merge
+        field_info.put_attribute(
+            QUANTIZED_VECTOR_OFFSET_KEY.to_string(),
+            quantizer.offset.to_string(),
+        );
+        field_info.put_attribute(
+            QUANTIZED_VECTOR_SCALE_KEY.to_string(),
+            quantizer.scale.to_string(),
+        );
+        Ok(offset)
+    }
+
+    // builds the graph of `count` vectors scored against each other by `score`
+    fn write_graph<S: Fn(usize, usize) -> f32>(&mut self, count: usize, score: S) -> Result<()> {
+        let mut builder = HnswGraphBuilder::new(
+            HnswGraph::DEFAULT_MAX_CONNECTIONS,
+            HnswGraph::DEFAULT_BEAM_WIDTH,
+        )?;
+        for _ in 0..count {
+            builder.add_graph_node(&score);
+        }
+        builder.build().write(&mut self.data_out)
+    }
+
+    /// Merges the vectors of the merging segments, in the order of the merged docs.
+    pub fn merge<D: Directory, C: Codec>(&mut self, merge_state: &MergeState<D, C>) -> Result<()> {
+        let merge_field_infos = merge_state.merge_field_infos.as_ref().unwrap();
+        for field_info in merge_field_infos.by_number.values() {
+            if field_info.vector_dimension == 0 {
+                continue;
+            }
+            // (merged doc, reader, ord) of every live vector
+            let mut merged = vec![];
+            for (i, reader) in merge_state.vectors_readers.iter().enumerate() {
+                if let Some(field) = reader.as_ref().and_then(|r| r.field(&field_info.name)) {
+                    if field.dimension() != field_info.vector_dimension as usize {
+                        bail!(IllegalArgument(format!(
+                            "cannot change vector dimension of field '{}'",
+                            field_info.name
+                        )));
+                    }
+                    for (ord, &doc) in field.docs().iter().enumerate() {
+                        let doc = merge_state.leaf_doc_maps[i].get(doc)?;
+                        let new_doc = merge_state.doc_maps[i].get(doc)?;
+                        if new_doc != -1 {
+                            merged.push((new_doc, i, ord));
+                        }
+                    }
+                }
+            }
+            if merged.is_empty() {
+                continue;
+            }
+            merged.sort_by_key(|&(doc, _, _)| doc);
+
+            let mut docs = Vec::with_capacity(merged.len());
+            let mut values = vec![];
+            let mut words = vec![];
+            let binary = field_info.vector_similarity == KnnVectorSimilarityFunction::Hamming;
+            for (doc, i, ord) in merged {
+                let field = merge_state.vectors_readers[i]
+                    .as_ref()
+                    .and_then(|r| r.field(&field_info.name))
+                    .unwrap();
+                docs.push(doc);
+                if binary {
+                    words.extend_from_slice(field.binary_vector_at(ord));
+                } else {
+                    values.extend_from_slice(field.vector_at(ord));
+                }
+            }
+            if binary {
+                self.write_binary_field(field_info, &docs, &words)?;
+            } else {
+                self.write_field(field_info, &docs, &values)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        if self.finished {
+            bail!(IllegalState("already finished".into()));
+        }
+        self.finished = true;
+        self.index_out.write_vint(-1)?;
+        codec_util::write_footer(&mut self.index_out)?;
+        codec_util::write_footer(&mut self.data_out)
+    }
+}
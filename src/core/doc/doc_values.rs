@@ -20,7 +20,7 @@ use core::util::{BytesRef, Numeric, VariantValue};
 
 use error::Result;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DocValuesType {
     /// No doc values for this field.
     Null,
@@ -150,6 +150,42 @@ impl FloatDocValuesField {
     }
 }
 
+impl Fieldable for FloatDocValuesField {
+    fn name(&self) -> &str {
+        self.field.name()
+    }
+
+    fn field_type(&self) -> &FieldType {
+        self.field.field_type()
+    }
+
+    fn boost(&self) -> f32 {
+        self.field.boost()
+    }
+
+    fn field_data(&self) -> Option<&VariantValue> {
+        self.field.field_data()
+    }
+
+    fn token_stream(&mut self) -> Result<Box<dyn TokenStream>> {
+        unreachable!()
+    }
+
+    fn binary_value(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn string_value(&self) -> Option<&str> {
+        None
+    }
+
+    fn numeric_value(&self) -> Option<Numeric> {
+        // indexed as the bits of the float, see `Numeric::long_value`
+        self.field_data()
+            .map(|v| Numeric::Float(v.get_float().unwrap()))
+    }
+}
+
 pub struct DoubleDocValuesField {
     field: Field,
 }
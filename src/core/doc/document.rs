@@ -13,10 +13,14 @@
 
 use core::codec::field_infos::FieldInfo;
 use core::doc::{DocValuesType, Field, FieldType, Fieldable, IndexOptions};
-use core::util::VariantValue;
+use core::util::{tagged_variant_value_option, Numeric, TaggedVariantValue, VariantValue};
 
 use error::Result;
 
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::result;
+
 pub enum Status {
     Yes,
     No,
@@ -78,9 +82,49 @@ impl StoredField {
             }
         }
     }
+
+    /// The value of a numeric stored field, in the type it was stored with.
+    pub fn numeric_value(&self) -> Option<Numeric> {
+        self.field.numeric_value()
+    }
+}
+
+/// Serialized as its name, field type and tagged value, so that deserializing restores
+/// the exact value variant.
+impl Serialize for StoredField {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("StoredField", 3)?;
+        s.serialize_field("name", self.field.name())?;
+        s.serialize_field("field_type", self.field.field_type())?;
+        s.serialize_field("value", &self.field.field_data().map(TaggedVariantValue))?;
+        s.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for StoredField {
+    fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct StoredFieldData {
+            name: String,
+            field_type: FieldType,
+            #[serde(with = "tagged_variant_value_option")]
+            value: Option<VariantValue>,
+        }
+
+        let data = StoredFieldData::deserialize(deserializer)?;
+        Ok(StoredField {
+            field: Field::new(data.name, data.field_type, data.value, None),
+        })
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Document {
     pub fields: Vec<StoredField>,
 }
@@ -98,6 +142,11 @@ impl Document {
     pub fn remove_field(&mut self, name: &str) {
         self.fields.retain(|ref v| v.field.name() != name);
     }
+
+    /// Returns the first field with the given name, if any.
+    pub fn get_field(&self, name: &str) -> Option<&StoredField> {
+        self.fields.iter().find(|f| f.field.name() == name)
+    }
 }
 
 pub struct DocumentStoredFieldVisitor {
@@ -202,3 +251,91 @@ impl StoredFieldVisitor for DocumentStoredFieldVisitor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::index::reader::IndexReader;
+    use core::test_util::new_fs_writer;
+
+    #[test]
+    fn test_numeric_stored_fields_round_trip() {
+        let (_dir, writer) = new_fs_writer();
+
+        let values = vec![
+            ("int", VariantValue::Int(-7)),
+            ("long", VariantValue::Long(-1_234_567_890_123)),
+            ("float", VariantValue::Float(0.25)),
+            ("double", VariantValue::Double(-2.718_281_828)),
+        ];
+        let doc: Vec<Box<dyn Fieldable>> = values
+            .iter()
+            .map(|(name, value)| {
+                Box::new(StoredField::new(name, None, value.clone()).field) as Box<dyn Fieldable>
+            })
+            .collect();
+        writer.add_document(doc).unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let doc = reader.document(0, &[]).unwrap();
+        assert_eq!(doc.fields.len(), 4);
+
+        // each value comes back in the numeric type it was written with
+        match doc.get_field("int").unwrap().numeric_value() {
+            Some(Numeric::Int(v)) => assert_eq!(v, -7),
+            v => panic!("unexpected int value {:?}", v),
+        }
+        match doc.get_field("long").unwrap().numeric_value() {
+            Some(Numeric::Long(v)) => assert_eq!(v, -1_234_567_890_123),
+            v => panic!("unexpected long value {:?}", v),
+        }
+        match doc.get_field("float").unwrap().numeric_value() {
+            Some(Numeric::Float(v)) => assert_eq!(v.to_bits(), 0.25f32.to_bits()),
+            v => panic!("unexpected float value {:?}", v),
+        }
+        match doc.get_field("double").unwrap().numeric_value() {
+            Some(Numeric::Double(v)) => assert_eq!(v.to_bits(), (-2.718_281_828f64).to_bits()),
+            v => panic!("unexpected double value {:?}", v),
+        }
+        assert!(doc.get_field("missing").is_none());
+    }
+
+    #[test]
+    fn test_document_serde_round_trip() {
+        let mut doc = Document::new(vec![]);
+        doc.add(StoredField::new(
+            "title",
+            None,
+            VariantValue::VString("rucene".into()),
+        ));
+        doc.add(StoredField::new("count", None, VariantValue::Int(7)));
+        doc.add(StoredField::new("size", None, VariantValue::Long(7)));
+        doc.add(StoredField::new(
+            "tags",
+            None,
+            VariantValue::Vec(vec![VariantValue::Short(1), VariantValue::Double(0.5)]),
+        ));
+
+        let json = ::serde_json::to_string(&doc).unwrap();
+        let decoded: Document = ::serde_json::from_str(&json).unwrap();
+
+        assert_eq!(decoded.fields.len(), doc.fields.len());
+        for (d, f) in decoded.fields.iter().zip(doc.fields.iter()) {
+            assert_eq!(d.field.name(), f.field.name());
+            assert_eq!(d.field.field_type(), f.field.field_type());
+            // `VariantValue` equality does not cover nested values
+            assert_eq!(
+                format!("{:?}", d.field.field_data()),
+                format!("{:?}", f.field.field_data())
+            );
+        }
+        // the exact numeric variant survives the round trip
+        assert_eq!(
+            decoded.fields[2].field.field_data(),
+            Some(&VariantValue::Long(7))
+        );
+    }
+}
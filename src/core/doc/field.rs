@@ -12,6 +12,7 @@
 // limitations under the License.
 
 use core::analysis::{BinaryTokenStream, StringTokenStream, TokenStream};
+use core::codec::vectors::{KnnVectorSimilarityFunction, MAX_VECTOR_DIMENSIONS};
 use core::doc::{DocValuesType, IndexOptions};
 use core::util::{ByteBlockPool, BytesRef, Numeric, VariantValue};
 
@@ -130,6 +131,26 @@ impl Fieldable for Field {
         ))
     }
 
+    fn reuse_token_stream(&self, stream: &mut StringTokenStream) -> bool {
+        if self.token_stream.is_some() || self.field_type.tokenized {
+            return false;
+        }
+        match self.field_data {
+            Some(VariantValue::VString(ref s)) => {
+                stream.set_value(s);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn analyzed_value(&self) -> Option<&str> {
+        if self.token_stream.is_some() || !self.field_type.tokenized {
+            return None;
+        }
+        self.field_data.as_ref().and_then(|f| f.get_string())
+    }
+
     fn binary_value(&self) -> Option<&[u8]> {
         self.field_data.as_ref().and_then(|f| f.get_binary())
     }
@@ -166,6 +187,18 @@ pub trait Fieldable {
     fn boost(&self) -> f32;
     fn field_data(&self) -> Option<&VariantValue>;
     fn token_stream(&mut self) -> Result<Box<dyn TokenStream>>;
+    /// Sets the value of `stream`, a stream reused across fields, to the single token of
+    /// this field and returns true if the field is indexed as its untokenized string
+    /// value, so indexing doesn't create a stream per value. Otherwise the tokens of the
+    /// field must be read from `token_stream`.
+    fn reuse_token_stream(&self, _stream: &mut StringTokenStream) -> bool {
+        false
+    }
+    /// Returns the text to tokenize with the analyzer of the writer, the string value of
+    /// a tokenized field that has no token stream of its own.
+    fn analyzed_value(&self) -> Option<&str> {
+        None
+    }
     fn binary_value(&self) -> Option<&[u8]>;
     fn string_value(&self) -> Option<&str>;
     fn numeric_value(&self) -> Option<Numeric>;
@@ -187,6 +220,12 @@ impl<T: Fieldable + ?Sized> Fieldable for Box<T> {
     fn token_stream(&mut self) -> Result<Box<dyn TokenStream>> {
         (**self).token_stream()
     }
+    fn reuse_token_stream(&self, stream: &mut StringTokenStream) -> bool {
+        (**self).reuse_token_stream(stream)
+    }
+    fn analyzed_value(&self) -> Option<&str> {
+        (**self).analyzed_value()
+    }
     fn binary_value(&self) -> Option<&[u8]> {
         (**self).binary_value()
     }
@@ -198,7 +237,7 @@ impl<T: Fieldable + ?Sized> Fieldable for Box<T> {
     }
 }
 
-#[derive(Clone, PartialEq, Hash, Serialize, Debug)]
+#[derive(Clone, PartialEq, Hash, Serialize, Deserialize, Debug)]
 pub struct FieldType {
     pub stored: bool,
     pub tokenized: bool,
@@ -211,6 +250,12 @@ pub struct FieldType {
     pub doc_values_type: DocValuesType,
     pub dimension_count: u32,
     pub dimension_num_bytes: u32,
+    /// The dimension of the dense vectors of this field, 0 if it has none.
+    pub vector_dimension: u32,
+    pub vector_similarity: KnnVectorSimilarityFunction,
+    /// Whether zero position-increment (overlap) tokens, e.g. injected synonyms,
+    /// are excluded from the field length used to compute norms.
+    pub discount_overlaps: bool,
 }
 
 impl Default for FieldType {
@@ -227,6 +272,9 @@ impl Default for FieldType {
             doc_values_type: DocValuesType::Null,
             dimension_count: 0,
             dimension_num_bytes: 0,
+            vector_dimension: 0,
+            vector_similarity: KnnVectorSimilarityFunction::L2,
+            discount_overlaps: true,
         }
     }
 }
@@ -263,6 +311,9 @@ impl FieldType {
             doc_values_type,
             dimension_count,
             dimension_num_bytes,
+            vector_dimension: 0,
+            vector_similarity: KnnVectorSimilarityFunction::L2,
+            discount_overlaps: true,
         }
     }
 
@@ -331,6 +382,14 @@ impl FieldType {
         self.tokenized
     }
 
+    pub fn discount_overlaps(&self) -> bool {
+        self.discount_overlaps
+    }
+
+    pub fn set_discount_overlaps(&mut self, v: bool) {
+        self.discount_overlaps = v;
+    }
+
     pub fn set_dimensions(&mut self, dimension_count: u32, dimension_num_bytes: u32) -> Result<()> {
         if dimension_count > POINT_MAX_DIMENSIONS {
             bail!(IllegalArgument(format!(
@@ -363,6 +422,24 @@ impl FieldType {
 
         Ok(())
     }
+
+    pub fn set_vector_dimension(
+        &mut self,
+        dimension: u32,
+        similarity: KnnVectorSimilarityFunction,
+    ) -> Result<()> {
+        if dimension == 0 || dimension > MAX_VECTOR_DIMENSIONS {
+            bail!(IllegalArgument(format!(
+                "vector dimension must be in [1, {}], got {}",
+                MAX_VECTOR_DIMENSIONS, dimension
+            )));
+        }
+
+        self.vector_dimension = dimension;
+        self.vector_similarity = similarity;
+
+        Ok(())
+    }
 }
 
 impl fmt::Display for FieldType {
@@ -387,6 +464,9 @@ pub const NUMERIC_DOC_VALUES_FIELD_TYPE: FieldType = FieldType {
     doc_values_type: DocValuesType::Numeric,
     dimension_count: 0,
     dimension_num_bytes: 0,
+    vector_dimension: 0,
+    vector_similarity: KnnVectorSimilarityFunction::L2,
+    discount_overlaps: true,
 };
 
 pub const SORTED_NUMERIC_DOC_VALUES_FIELD_TYPE: FieldType = FieldType {
@@ -401,6 +481,9 @@ pub const SORTED_NUMERIC_DOC_VALUES_FIELD_TYPE: FieldType = FieldType {
     doc_values_type: DocValuesType::SortedNumeric,
     dimension_count: 0,
     dimension_num_bytes: 0,
+    vector_dimension: 0,
+    vector_similarity: KnnVectorSimilarityFunction::L2,
+    discount_overlaps: true,
 };
 
 pub const BINARY_DOC_VALUES_FIELD_TYPE: FieldType = FieldType {
@@ -415,6 +498,9 @@ pub const BINARY_DOC_VALUES_FIELD_TYPE: FieldType = FieldType {
     doc_values_type: DocValuesType::Binary,
     dimension_count: 0,
     dimension_num_bytes: 0,
+    vector_dimension: 0,
+    vector_similarity: KnnVectorSimilarityFunction::L2,
+    discount_overlaps: true,
 };
 
 pub const SORTED_DOC_VALUES_FIELD_TYPE: FieldType = FieldType {
@@ -429,6 +515,9 @@ pub const SORTED_DOC_VALUES_FIELD_TYPE: FieldType = FieldType {
     doc_values_type: DocValuesType::Sorted,
     dimension_count: 0,
     dimension_num_bytes: 0,
+    vector_dimension: 0,
+    vector_similarity: KnnVectorSimilarityFunction::L2,
+    discount_overlaps: true,
 };
 
 pub const SORTED_SET_DOC_VALUES_FIELD_TYPE: FieldType = FieldType {
@@ -443,6 +532,9 @@ pub const SORTED_SET_DOC_VALUES_FIELD_TYPE: FieldType = FieldType {
     doc_values_type: DocValuesType::SortedSet,
     dimension_count: 0,
     dimension_num_bytes: 0,
+    vector_dimension: 0,
+    vector_similarity: KnnVectorSimilarityFunction::L2,
+    discount_overlaps: true,
 };
 
 pub const STORE_FIELD_TYPE: FieldType = FieldType {
@@ -457,4 +549,7 @@ pub const STORE_FIELD_TYPE: FieldType = FieldType {
     doc_values_type: DocValuesType::Null,
     dimension_count: 0,
     dimension_num_bytes: 0,
+    vector_dimension: 0,
+    vector_similarity: KnnVectorSimilarityFunction::L2,
+    discount_overlaps: true,
 };
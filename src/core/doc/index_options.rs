@@ -14,7 +14,7 @@
 use error::{ErrorKind::IllegalArgument, Result};
 use std::cmp::Ordering;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum IndexOptions {
     Null,
     Docs,
@@ -0,0 +1,139 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::analysis::TokenStream;
+use core::codec::vectors::{binary_words, pack_bits, KnnVectorSimilarityFunction};
+use core::doc::{Field, FieldType, Fieldable};
+use core::util::{Numeric, VariantValue};
+
+use error::Result;
+
+/// A field holding one dense `f32` vector per document, written by the vectors format
+/// of the codec and read back with `VectorReader::get_vector`, searched by
+/// `IndexSearcher::knn_search` or an `ExactKnnQuery`.
+///
+/// All the vectors of a field must have the same dimension and similarity function.
+pub struct DenseVectorField {
+    field: Field,
+}
+
+impl DenseVectorField {
+    pub fn new(
+        name: &str,
+        vector: &[f32],
+        similarity: KnnVectorSimilarityFunction,
+    ) -> Result<DenseVectorField> {
+        let mut field_type = FieldType::default();
+        field_type.tokenized = false;
+        field_type.set_vector_dimension(vector.len() as u32, similarity)?;
+        let mut bytes = Vec::with_capacity(vector.len() * 4);
+        for v in vector {
+            bytes.extend_from_slice(&v.to_bits().to_le_bytes());
+        }
+        Ok(DenseVectorField {
+            field: Field::new_bytes(String::from(name), bytes, field_type),
+        })
+    }
+}
+
+impl Fieldable for DenseVectorField {
+    fn name(&self) -> &str {
+        self.field.name()
+    }
+
+    fn field_type(&self) -> &FieldType {
+        self.field.field_type()
+    }
+
+    fn boost(&self) -> f32 {
+        self.field.boost()
+    }
+
+    fn field_data(&self) -> Option<&VariantValue> {
+        self.field.field_data()
+    }
+
+    fn token_stream(&mut self) -> Result<Box<dyn TokenStream>> {
+        unreachable!()
+    }
+
+    fn binary_value(&self) -> Option<&[u8]> {
+        self.field.binary_value()
+    }
+
+    fn string_value(&self) -> Option<&str> {
+        None
+    }
+
+    fn numeric_value(&self) -> Option<Numeric> {
+        None
+    }
+}
+
+/// A vector of bits of a document, given as `0.0` and `1.0` values and compared with
+/// `KnnVectorSimilarityFunction::Hamming`.
+///
+/// The bits are packed in little endian 64 bits words, the dimension of the field is
+/// the number of bits, whatever the padding of the last word.
+pub struct BinaryVectorField {
+    field: Field,
+}
+
+impl BinaryVectorField {
+    pub fn new(name: &str, bits: &[f32]) -> Result<BinaryVectorField> {
+        let mut field_type = FieldType::default();
+        field_type.tokenized = false;
+        field_type.set_vector_dimension(bits.len() as u32, KnnVectorSimilarityFunction::Hamming)?;
+        let mut bytes = Vec::with_capacity(binary_words(bits.len()) * 8);
+        for word in pack_bits(bits) {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        Ok(BinaryVectorField {
+            field: Field::new_bytes(String::from(name), bytes, field_type),
+        })
+    }
+}
+
+impl Fieldable for BinaryVectorField {
+    fn name(&self) -> &str {
+        self.field.name()
+    }
+
+    fn field_type(&self) -> &FieldType {
+        self.field.field_type()
+    }
+
+    fn boost(&self) -> f32 {
+        self.field.boost()
+    }
+
+    fn field_data(&self) -> Option<&VariantValue> {
+        self.field.field_data()
+    }
+
+    fn token_stream(&mut self) -> Result<Box<dyn TokenStream>> {
+        unreachable!()
+    }
+
+    fn binary_value(&self) -> Option<&[u8]> {
+        self.field.binary_value()
+    }
+
+    fn string_value(&self) -> Option<&str> {
+        None
+    }
+
+    fn numeric_value(&self) -> Option<Numeric> {
+        None
+    }
+}
@@ -11,6 +11,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::analysis::Analyzer;
 use core::codec::Codec;
 use core::highlight::frag_list_builder::SimpleFragListBuilder;
 use core::highlight::fragments_builder::BaseFragmentsBuilder;
@@ -25,6 +26,7 @@ use core::util::DocId;
 use error::Result;
 
 use std::i32;
+use std::sync::Arc;
 
 const DEFAULT_PHRASE_HIGHLIGHT: bool = true;
 const DEFAULT_FIELD_MATCH: bool = true;
@@ -36,6 +38,7 @@ pub struct FastVectorHighlighter {
     frag_list_builder: Box<dyn FragListBuilder>,
     fragments_builder: BaseFragmentsBuilder,
     pub phrase_limit: i32,
+    analyzer: Option<Arc<dyn Analyzer>>,
 }
 
 impl Default for FastVectorHighlighter {
@@ -73,9 +76,16 @@ impl FastVectorHighlighter {
                 Some(x) => x,
                 None => DEFAULT_PHRASE_LIMIT,
             },
+            analyzer: None,
         }
     }
 
+    /// Sets the analyzer used to re-analyze the stored values of fields which have no
+    /// term vector with positions and offsets. Without one such fields get no fragments.
+    pub fn set_analyzer(&mut self, analyzer: Arc<dyn Analyzer>) {
+        self.analyzer = Some(analyzer);
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn get_best_fragments<C: Codec>(
         &mut self,
@@ -181,7 +191,13 @@ impl FastVectorHighlighter {
         field: &str,
         frag_char_size: i32,
     ) -> Result<Box<dyn FieldFragList>> {
-        let mut field_term_stack = FieldTermStack::new(reader, doc_id, field, field_query)?;
+        let mut field_term_stack = FieldTermStack::with_analyzer(
+            reader,
+            doc_id,
+            field,
+            field_query,
+            self.analyzer.as_ref().map(|a| a.as_ref()),
+        )?;
         let field_phrase_list =
             FieldPhraseList::new(&mut field_term_stack, field_query, self.phrase_limit);
 
@@ -201,7 +217,13 @@ impl FastVectorHighlighter {
 
         let mut to_merge: Vec<FieldPhraseList> = Vec::with_capacity(matched_fields.len());
         for field in matched_fields {
-            let mut stack = FieldTermStack::new(reader, doc_id, field, field_query)?;
+            let mut stack = FieldTermStack::with_analyzer(
+                reader,
+                doc_id,
+                field,
+                field_query,
+                self.analyzer.as_ref().map(|a| a.as_ref()),
+            )?;
             to_merge.push(FieldPhraseList::new(
                 &mut stack,
                 field_query,
@@ -214,3 +236,91 @@ impl FastVectorHighlighter {
         frag_list_builder.create_field_frag_list(&field_phrase_list, frag_char_size)
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use core::analysis::{CustomAnalyzer, WhitespaceTokenizer, WhitespaceTokenizerFactory};
+    use core::codec::CodecEnum;
+    use core::doc::{Field, FieldType, Fieldable, IndexOptions, Term};
+    use core::search::query::TermQuery;
+    use core::test_util::new_fs_writer;
+    use core::util::VariantValue;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_highlight_without_term_vectors() {
+        let (_dir, writer) = new_fs_writer();
+
+        // stored and indexed with positions, but without term vectors
+        let text = "the quick brown fox jumps";
+        let mut field_type = FieldType::default();
+        field_type.stored = true;
+        field_type.index_options = IndexOptions::DocsAndFreqsAndPositions;
+        let tokens = WhitespaceTokenizer::new(Box::new(Cursor::new(text.to_string())));
+        let doc: Vec<Box<dyn Fieldable>> = vec![Box::new(Field::new(
+            "body".into(),
+            field_type,
+            Some(VariantValue::VString(text.to_string())),
+            Some(Box::new(tokens)),
+        ))];
+        writer.add_document(doc).unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        let query = TermQuery::new(Term::new("body".into(), b"fox".to_vec()), 1.0, None);
+
+        let mut highlighter = FastVectorHighlighter::default();
+        let index_reader: &dyn IndexReader<Codec = CodecEnum> = &reader;
+        let mut field_query = highlighter
+            .get_field_query(&query, Some(index_reader))
+            .unwrap();
+        let fragments = highlighter
+            .get_best_fragments(
+                &mut field_query,
+                &leaves[0],
+                0,
+                "body",
+                "body",
+                100,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        assert!(fragments.is_empty());
+
+        // re-analyzing the stored value finds the term and its offsets
+        highlighter.set_analyzer(Arc::new(CustomAnalyzer::new(
+            Box::new(WhitespaceTokenizerFactory),
+            vec![],
+        )));
+        let fragments = highlighter
+            .get_best_fragments(
+                &mut field_query,
+                &leaves[0],
+                0,
+                "body",
+                "body",
+                100,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(fragments.len(), 1);
+        assert!(fragments[0].contains("<b>fox</b>"));
+        assert!(!fragments[0].contains("<b>quick</b>"));
+    }
+}
@@ -23,6 +23,7 @@ mod fvh_highlighter;
 
 pub use self::fvh_highlighter::*;
 
+use core::analysis::Analyzer;
 use core::codec::{Codec, Fields, PostingIterator, PostingIteratorFlags, TermIterator, Terms};
 use core::doc::Term;
 use core::index::reader::{IndexReader, LeafReaderContext};
@@ -36,6 +37,7 @@ use std::borrow::Cow;
 use std::cmp::{self, Ordering};
 use std::collections::HashMap;
 use std::f32::EPSILON;
+use std::io::Cursor;
 
 ///
 // Encodes original text. The Encoder works with the {@link Formatter} to generate output.
@@ -681,6 +683,19 @@ impl FieldTermStack {
         doc_id: DocId,
         field_name: &str,
         field_query: &FieldQuery,
+    ) -> Result<FieldTermStack> {
+        Self::with_analyzer(ctx, doc_id, field_name, field_query, None)
+    }
+
+    /// Like `new`, but when the field has no term vector with positions and offsets
+    /// for this doc, its stored values are re-analyzed with `analyzer` instead of
+    /// giving an empty stack.
+    pub fn with_analyzer<C: Codec>(
+        ctx: &LeafReaderContext<'_, C>,
+        doc_id: DocId,
+        field_name: &str,
+        field_query: &FieldQuery,
+        analyzer: Option<&dyn Analyzer>,
     ) -> Result<FieldTermStack> {
         // just return to make null snippet if un-matched fieldName specified when fieldMatch ==
         let term_set = match field_query.get_term_set_by_field(field_name) {
@@ -693,117 +708,186 @@ impl FieldTermStack {
             }
         };
 
-        let reader = ctx.reader;
-
-        if let Some(vectors) = reader.term_vector(doc_id - ctx.doc_base)? {
-            if let Some(vector) = vectors.terms(field_name)? {
-                // true null snippet
-                if vectors.fields().is_empty() || !vector.has_positions()? {
-                    return Ok(FieldTermStack {
-                        field_name: field_name.to_string(),
-                        term_list: vec![],
-                    });
+        let term_list = match Self::term_vector_terms(ctx, doc_id, field_name, term_set)? {
+            Some(term_list) => term_list,
+            None => match analyzer {
+                Some(analyzer) => {
+                    Self::analyzed_terms(ctx, doc_id, field_name, term_set, analyzer)?
                 }
+                // true null snippet
+                None => vec![],
+            },
+        };
+        Ok(Self::from_term_list(field_name, term_list))
+    }
 
-                let mut terms_iter = vector.iterator()?;
-                let max_docs = reader.max_doc();
-                let mut term_list: Vec<TermInfo> = vec![];
+    /// Collects the query terms from the term vector of the field, or returns `None`
+    /// if the doc has no term vector with positions and offsets for it.
+    fn term_vector_terms<C: Codec>(
+        ctx: &LeafReaderContext<'_, C>,
+        doc_id: DocId,
+        field_name: &str,
+        term_set: &[String],
+    ) -> Result<Option<Vec<TermInfo>>> {
+        let reader = ctx.reader;
 
-                while let Some(text) = terms_iter.next()? {
-                    let term = String::from_utf8(text)?;
-                    if !term_set.contains(&term) {
-                        continue;
-                    }
+        let vectors = match reader.term_vector(doc_id - ctx.doc_base)? {
+            Some(vectors) => vectors,
+            None => return Ok(None),
+        };
+        let vector = match vectors.terms(field_name)? {
+            Some(vector) => vector,
+            None => return Ok(None),
+        };
+        if vectors.fields().is_empty() || !vector.has_positions()? || !vector.has_offsets()? {
+            return Ok(None);
+        }
 
-                    let mut postings =
-                        terms_iter.postings_with_flags(PostingIteratorFlags::POSITIONS)?;
-                    postings.next()?;
-
-                    // For weight look here: http://lucene.apache.org/core/3_6_0/api/core/org/apache/lucene/search/DefaultSimilarity.html
-                    let weight = (f64::from(max_docs) / (terms_iter.total_term_freq()? + 1) as f64
-                        + 1.0)
-                        .log(10.0f64) as f32;
-                    let freq = postings.freq()?;
-
-                    for _ in 0..freq {
-                        let pos = postings.next_position()?;
-
-                        if postings.start_offset()? < 0 {
-                            // no offsets, null snippet
-                            return Ok(FieldTermStack {
-                                field_name: field_name.to_string(),
-                                term_list: vec![],
-                            });
-                        }
+        let mut terms_iter = vector.iterator()?;
+        let max_docs = reader.max_doc();
+        let mut term_list: Vec<TermInfo> = vec![];
 
-                        term_list.push(TermInfo::new(
-                            term.clone(),
-                            postings.start_offset()?,
-                            postings.end_offset()?,
-                            pos,
-                            weight,
-                        ));
-                    }
-                }
+        while let Some(text) = terms_iter.next()? {
+            let term = String::from_utf8(text)?;
+            if !term_set.contains(&term) {
+                continue;
+            }
 
-                // now look for dups at the same position, linking them together
-                term_list.sort_by(|o1, o2| {
-                    if o1.end_offset != o2.end_offset {
-                        o1.end_offset.cmp(&o2.end_offset).reverse()
-                    } else {
-                        o1.start_offset.cmp(&o2.start_offset)
-                    }
-                });
+            let mut postings = terms_iter.postings_with_flags(PostingIteratorFlags::POSITIONS)?;
+            postings.next()?;
 
-                let mut start_offset = -1;
-                let mut end_offset = -1;
-                let mut terms_count = HashMap::new();
-                let mut total_count = 0;
-                for term_info in term_list.iter_mut() {
-                    if !(term_info.start_offset >= start_offset
-                        && term_info.end_offset <= end_offset)
-                    {
-                        if !terms_count.contains_key(&term_info.text) {
-                            terms_count.insert(term_info.text.clone(), 1);
-                        }
-                        *(terms_count.get_mut(&term_info.text).unwrap()) += 1;
+            let weight = Self::term_weight(max_docs, terms_iter.total_term_freq()?);
+            let freq = postings.freq()?;
 
-                        total_count += 1;
-                    } else {
-                        term_info.position = -1;
-                        continue;
-                    }
+            for _ in 0..freq {
+                let pos = postings.next_position()?;
 
-                    start_offset = term_info.start_offset;
-                    end_offset = term_info.end_offset;
+                if postings.start_offset()? < 0 {
+                    // no offsets
+                    return Ok(None);
                 }
 
-                let mut i = term_list.len();
-                while i > 0 {
-                    if term_list[i - 1].position == -1 {
-                        term_list.remove(i - 1);
-                    }
+                term_list.push(TermInfo::new(
+                    term.clone(),
+                    postings.start_offset()?,
+                    postings.end_offset()?,
+                    pos,
+                    weight,
+                ));
+            }
+        }
+        Ok(Some(term_list))
+    }
 
-                    i -= 1;
+    /// Collects the query terms by running the stored values of the field through
+    /// `analyzer`. Offsets of each value follow those of the previous one, plus one
+    /// for the separator, as the fragments builder expects.
+    fn analyzed_terms<C: Codec>(
+        ctx: &LeafReaderContext<'_, C>,
+        doc_id: DocId,
+        field_name: &str,
+        term_set: &[String],
+        analyzer: &dyn Analyzer,
+    ) -> Result<Vec<TermInfo>> {
+        let document = ctx.parent.document(doc_id, &[field_name.to_string()])?;
+
+        let mut matches: Vec<(String, i32, i32, i32)> = vec![];
+        let mut freqs: HashMap<String, i64> = HashMap::new();
+        let mut offset_base = 0i32;
+        let mut position = -1i32;
+        for field in &document.fields {
+            let value = match field.field.field_data() {
+                Some(data) => format!("{}", data),
+                None => continue,
+            };
+            let reader = Box::new(Cursor::new(value.clone()));
+            let mut tokens = analyzer.token_stream(field_name, reader)?;
+            tokens.reset()?;
+            while tokens.next_token()? {
+                let token = tokens.token();
+                position += token.position as i32;
+                let term = String::from_utf8(token.term.clone())?;
+                if !term_set.contains(&term) {
+                    continue;
                 }
+                *freqs.entry(term.clone()).or_insert(0) += 1;
+                matches.push((
+                    term,
+                    offset_base + token.start_offset as i32,
+                    offset_base + token.end_offset as i32,
+                    position,
+                ));
+            }
+            tokens.end()?;
+            offset_base += value.chars().count() as i32 + 1;
+        }
 
-                let avg_count = (total_count as f32) / (terms_count.len() as f32 + 1.0);
-                for term_info in term_list.iter_mut() {
-                    if let Some(count) = terms_count.get(&term_info.text) {
-                        term_info.weight *= 1.0 + avg_count / (*count as f32 + 1.0);
-                    }
+        let max_docs = ctx.reader.max_doc();
+        Ok(matches
+            .into_iter()
+            .map(|(term, start_offset, end_offset, position)| {
+                let weight = Self::term_weight(max_docs, freqs[&term]);
+                TermInfo::new(term, start_offset, end_offset, position, weight)
+            })
+            .collect())
+    }
+
+    // For weight look here: http://lucene.apache.org/core/3_6_0/api/core/org/apache/lucene/search/DefaultSimilarity.html
+    fn term_weight(max_docs: i32, total_term_freq: i64) -> f32 {
+        (f64::from(max_docs) / (total_term_freq + 1) as f64 + 1.0).log(10.0f64) as f32
+    }
+
+    fn from_term_list(field_name: &str, mut term_list: Vec<TermInfo>) -> FieldTermStack {
+        // now look for dups at the same position, linking them together
+        term_list.sort_by(|o1, o2| {
+            if o1.end_offset != o2.end_offset {
+                o1.end_offset.cmp(&o2.end_offset).reverse()
+            } else {
+                o1.start_offset.cmp(&o2.start_offset)
+            }
+        });
+
+        let mut start_offset = -1;
+        let mut end_offset = -1;
+        let mut terms_count = HashMap::new();
+        let mut total_count = 0;
+        for term_info in term_list.iter_mut() {
+            if !(term_info.start_offset >= start_offset && term_info.end_offset <= end_offset) {
+                if !terms_count.contains_key(&term_info.text) {
+                    terms_count.insert(term_info.text.clone(), 1);
                 }
+                *(terms_count.get_mut(&term_info.text).unwrap()) += 1;
 
-                return Ok(FieldTermStack {
-                    field_name: field_name.to_string(),
-                    term_list,
-                });
+                total_count += 1;
+            } else {
+                term_info.position = -1;
+                continue;
             }
+
+            start_offset = term_info.start_offset;
+            end_offset = term_info.end_offset;
         }
-        Ok(FieldTermStack {
+
+        let mut i = term_list.len();
+        while i > 0 {
+            if term_list[i - 1].position == -1 {
+                term_list.remove(i - 1);
+            }
+
+            i -= 1;
+        }
+
+        let avg_count = (total_count as f32) / (terms_count.len() as f32 + 1.0);
+        for term_info in term_list.iter_mut() {
+            if let Some(count) = terms_count.get(&term_info.text) {
+                term_info.weight *= 1.0 + avg_count / (*count as f32 + 1.0);
+            }
+        }
+
+        FieldTermStack {
             field_name: field_name.to_string(),
-            term_list: vec![],
-        })
+            term_list,
+        }
     }
 
     pub fn pop(&mut self) -> Option<TermInfo> {
@@ -0,0 +1,1128 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashSet};
+
+use core::codec::vectors::VectorReader;
+use core::codec::Codec;
+use core::index::reader::LeafReaderContext;
+use core::search::query::Weight;
+use core::search::sort_field::ScoreDoc;
+use core::search::DocIterator;
+use core::store::io::{DataInput, DataOutput};
+use core::util::{BitSet, Bits, DocId, FixedBitSet};
+
+use error::{
+    ErrorKind::{CorruptIndex, IllegalArgument},
+    Result,
+};
+
+/// Orders two scores of a kNN search, a NaN score being lower than any other so that a
+/// vector compared to NaN values never ranks first, nor panics the sort of the hits.
+pub(crate) fn compare_scores(a: f32, b: f32) -> Ordering {
+    match a.partial_cmp(&b) {
+        Some(ordering) => ordering,
+        None => b.is_nan().cmp(&a.is_nan()),
+    }
+}
+
+// a node and its score against the searched vector, ordered by score
+#[derive(Clone, Copy, Debug)]
+struct ScoredNode {
+    score: f32,
+    node: usize,
+}
+
+impl PartialEq for ScoredNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScoredNode {}
+
+impl PartialOrd for ScoredNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScoredNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_scores(self.score, other.score).then_with(|| other.node.cmp(&self.node))
+    }
+}
+
+/// Keeps the `k` nearest nodes of a kNN search, the ones with the highest scores.
+///
+/// The nodes are kept in a heap of at most `k` entries whose top is the farthest one, so a
+/// node scoring below the `k`-th score is discarded at once, without sorting the nodes
+/// collected so far. On equal scores the smaller nodes are kept.
+pub struct GraphBasedKnnCollector {
+    k: usize,
+    // the nearest nodes collected, farthest first
+    heap: BinaryHeap<Reverse<ScoredNode>>,
+}
+
+impl GraphBasedKnnCollector {
+    pub fn new(k: usize) -> GraphBasedKnnCollector {
+        GraphBasedKnnCollector {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    /// Collects `node` scoring `score`, returns whether it is among the `k` nearest nodes
+    /// collected so far.
+    pub fn collect(&mut self, node: u32, score: f32) -> bool {
+        self.collect_scored(ScoredNode {
+            score,
+            node: node as usize,
+        })
+    }
+
+    fn collect_scored(&mut self, scored: ScoredNode) -> bool {
+        if !self.is_competitive(scored) {
+            return false;
+        }
+        if self.heap.len() == self.k {
+            self.heap.pop();
+        }
+        self.heap.push(Reverse(scored));
+        true
+    }
+
+    // whether `scored` would be kept if collected
+    fn is_competitive(&self, scored: ScoredNode) -> bool {
+        if self.heap.len() < self.k {
+            true
+        } else {
+            self.heap
+                .peek()
+                .map_or(false, |farthest| scored > farthest.0)
+        }
+    }
+
+    /// The score of the `k`-th nearest node, `None` until `k` nodes are collected. The
+    /// nodes scoring below it are discarded.
+    pub fn min_competitive_score(&self) -> Option<f32> {
+        if self.heap.len() < self.k {
+            None
+        } else {
+            self.heap.peek().map(|farthest| farthest.0.score)
+        }
+    }
+
+    /// The number of nodes kept, at most `k`.
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    fn into_sorted(self) -> Vec<ScoredNode> {
+        // the heap is reversed, so its ascending order is the nearest node first
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|r| r.0)
+            .collect()
+    }
+
+    /// The nodes kept as docs, the nearest first.
+    pub fn top_docs(self) -> Vec<ScoreDoc> {
+        self.into_sorted()
+            .iter()
+            .map(|r| ScoreDoc::new(r.node as DocId, r.score))
+            .collect()
+    }
+}
+
+/// A Hierarchical Navigable Small World graph of vectors, for approximate nearest
+/// neighbor search.
+///
+/// Every vector is a node of the bottom level, and of each level above with a
+/// probability decreasing exponentially. A search walks greedily from the single node of
+/// the top level down to the bottom one, where it explores the neighborhood of the
+/// nearest nodes found so far.
+///
+/// The nodes are the ords of the vectors of a field in a segment, the graph only holds
+/// their links: it is written with the vectors by the vectors format of the codec and
+/// searched with `HnswGraphSearcher`.
+pub struct HnswGraph {
+    max_connections: usize,
+    // the neighbors of each node on each of its levels, the bottom level first
+    nodes: Vec<Vec<Vec<u32>>>,
+    entry_point: Option<usize>,
+}
+
+impl HnswGraph {
+    pub const DEFAULT_MAX_CONNECTIONS: usize = 16;
+    pub const DEFAULT_BEAM_WIDTH: usize = 100;
+    const MAX_LEVEL: usize = 16;
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The number of neighbors kept per node on the upper levels, twice that on the
+    /// bottom level.
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    fn max_neighbors(&self, level: usize) -> usize {
+        if level == 0 {
+            self.max_connections * 2
+        } else {
+            self.max_connections
+        }
+    }
+
+    /// Returns the `k` nodes scored the highest by `score` found with a beam of `ef`
+    /// candidates on the bottom level, with their scores in descending order. The larger
+    /// `ef` the better the recall, and the slower the search.
+    pub fn search<S: Fn(usize) -> f32>(&self, score: S, k: usize, ef: usize) -> Vec<(usize, f32)> {
+        self.search_accepting(score, k, ef, |_| true)
+    }
+
+    /// Like `search`, among the nodes accepted by `accept`; the other nodes are still
+    /// walked through, since the accepted ones may only be reachable via them.
+    pub fn search_accepting<S, A>(
+        &self,
+        score: S,
+        k: usize,
+        ef: usize,
+        accept: A,
+    ) -> Vec<(usize, f32)>
+    where
+        S: Fn(usize) -> f32,
+        A: Fn(usize) -> bool,
+    {
+        let entry_point = match self.entry_point {
+            Some(entry_point) => entry_point,
+            None => return vec![],
+        };
+        let top_level = self.nodes[entry_point].len() - 1;
+        let mut entry_points = vec![entry_point];
+        for l in (1..=top_level).rev() {
+            entry_points = vec![self.search_level(&score, &entry_points, 1, l, &|_| true)[0].node];
+        }
+        let results = self.search_level(&score, &entry_points, ef.max(k), 0, &accept);
+        results.iter().take(k).map(|r| (r.node, r.score)).collect()
+    }
+
+    // the `ef` nearest nodes of `level` reachable from `entry_points` among the nodes
+    // accepted by `accept`, nearest first
+    fn search_level<S, A>(
+        &self,
+        score: &S,
+        entry_points: &[usize],
+        ef: usize,
+        level: usize,
+        accept: &A,
+    ) -> Vec<ScoredNode>
+    where
+        S: Fn(usize) -> f32,
+        A: Fn(usize) -> bool,
+    {
+        let mut visited = HashSet::new();
+        // the nodes to explore, nearest first
+        let mut candidates = BinaryHeap::new();
+        // the nearest accepted nodes found
+        let mut results = GraphBasedKnnCollector::new(ef);
+        for &node in entry_points {
+            if visited.insert(node) {
+                let scored = ScoredNode {
+                    score: score(node),
+                    node,
+                };
+                candidates.push(scored);
+                if accept(node) {
+                    results.collect_scored(scored);
+                }
+            }
+        }
+
+        while let Some(candidate) = candidates.pop() {
+            if let Some(min_score) = results.min_competitive_score() {
+                if compare_scores(candidate.score, min_score) == Ordering::Less {
+                    break;
+                }
+            }
+            for &neighbor in &self.nodes[candidate.node][level] {
+                let neighbor = neighbor as usize;
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let scored = ScoredNode {
+                    score: score(neighbor),
+                    node: neighbor,
+                };
+                if results.is_competitive(scored) {
+                    candidates.push(scored);
+                    if accept(neighbor) {
+                        results.collect_scored(scored);
+                    }
+                }
+            }
+        }
+
+        results.into_sorted()
+    }
+
+    /// Writes the graph after the vectors of its field, to be read back by `read`.
+    ///
+    /// The graph is written as `max_connections`, the number of nodes and the entry
+    /// point, followed for each node by its number of levels and, for each level, its
+    /// number of neighbors followed by their ords.
+    pub(crate) fn write<O: DataOutput + ?Sized>(&self, out: &mut O) -> Result<()> {
+        out.write_vint(self.max_connections as i32)?;
+        out.write_vint(self.nodes.len() as i32)?;
+        // 0 when the graph is empty
+        out.write_vint(self.entry_point.map_or(0, |e| e + 1) as i32)?;
+        for levels in &self.nodes {
+            out.write_vint(levels.len() as i32)?;
+            for neighbors in levels {
+                out.write_vint(neighbors.len() as i32)?;
+                for &n in neighbors {
+                    out.write_vint(n as i32)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the graph of the `num_nodes` vectors of a field written by `write`.
+    ///
+    /// The sizes read are checked before allocating anything for them: the data file
+    /// of the vectors is not checksummed when opened.
+    pub(crate) fn read<I: DataInput + ?Sized>(
+        input: &mut I,
+        num_nodes: usize,
+    ) -> Result<HnswGraph> {
+        let max_connections = input.read_vint()?;
+        let count = input.read_vint()?;
+        let entry_point = input.read_vint()?;
+        if max_connections < 2
+            || count < 0
+            || count as usize != num_nodes
+            || entry_point < 0
+            || entry_point > count
+            || (count > 0) != (entry_point > 0)
+        {
+            bail!(CorruptIndex(format!(
+                "invalid max_connections {}, node count {} or entry point {} of a graph of {} \
+                 vectors",
+                max_connections, count, entry_point, num_nodes
+            )));
+        }
+        let mut graph = HnswGraph {
+            max_connections: max_connections as usize,
+            nodes: Vec::with_capacity(num_nodes),
+            entry_point: None,
+        };
+        for node in 0..num_nodes {
+            let num_levels = input.read_vint()?;
+            if num_levels <= 0 || num_levels as usize > HnswGraph::MAX_LEVEL + 1 {
+                bail!(CorruptIndex(format!(
+                    "invalid level count {} of node {}",
+                    num_levels, node
+                )));
+            }
+            let mut levels = Vec::with_capacity(num_levels as usize);
+            for level in 0..num_levels as usize {
+                let count = input.read_vint()?;
+                if count < 0 || count as usize > graph.max_neighbors(level).min(num_nodes) {
+                    bail!(CorruptIndex(format!(
+                        "invalid neighbor count {} of node {} on level {}",
+                        count, node, level
+                    )));
+                }
+                let mut neighbors = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    let n = input.read_vint()?;
+                    if n < 0 || n as usize >= num_nodes {
+                        bail!(CorruptIndex(format!(
+                            "invalid neighbor {} of node {}",
+                            n, node
+                        )));
+                    }
+                    neighbors.push(n as u32);
+                }
+                levels.push(neighbors);
+            }
+            graph.nodes.push(levels);
+        }
+        // the neighbors on a level must be nodes of that level
+        for (node, levels) in graph.nodes.iter().enumerate() {
+            for (level, neighbors) in levels.iter().enumerate() {
+                if neighbors
+                    .iter()
+                    .any(|&n| graph.nodes[n as usize].len() <= level)
+                {
+                    bail!(CorruptIndex(format!(
+                        "node {} has a neighbor without level {}",
+                        node, level
+                    )));
+                }
+            }
+        }
+        if entry_point > 0 {
+            graph.entry_point = Some(entry_point as usize - 1);
+        }
+        Ok(graph)
+    }
+}
+
+/// Builds an `HnswGraph` as vectors are inserted: each node is drawn a top level and
+/// linked, from that level down to the bottom one, to its `m` nearest nodes among the
+/// `ef_construction` nearest ones found by searching the graph built so far.
+///
+/// The larger `m` and `ef_construction` the better the recall of the graph, and the
+/// slower it is to build.
+pub struct HnswGraphBuilder {
+    graph: HnswGraph,
+    ef_construction: usize,
+    level_multiplier: f64,
+    seed: u64,
+}
+
+impl HnswGraphBuilder {
+    pub fn new(m: usize, ef_construction: usize) -> Result<HnswGraphBuilder> {
+        if m < 2 || ef_construction == 0 {
+            bail!(IllegalArgument(format!(
+                "m must be >= 2 and ef_construction > 0, got {} and {}",
+                m, ef_construction
+            )));
+        }
+        Ok(HnswGraphBuilder {
+            graph: HnswGraph {
+                max_connections: m,
+                nodes: vec![],
+                entry_point: None,
+            },
+            ef_construction,
+            level_multiplier: 1.0 / (m as f64).ln(),
+            seed: 0x9e37_79b9_7f4a_7c15,
+        })
+    }
+
+    /// Inserts the next node, whose ord is the number of nodes inserted so far.
+    /// `score(a, b)` scores two nodes among the inserted ones and the new one, the higher
+    /// the nearer.
+    pub fn add_graph_node<F: Fn(usize, usize) -> f32>(&mut self, score: F) {
+        let level = self.random_level();
+        let id = self.graph.nodes.len();
+        self.graph.nodes.push(vec![vec![]; level + 1]);
+        let entry_point = match self.graph.entry_point {
+            Some(entry_point) => entry_point,
+            None => {
+                self.graph.entry_point = Some(id);
+                return;
+            }
+        };
+
+        let top_level = self.graph.nodes[entry_point].len() - 1;
+        let score_new = |n: usize| score(id, n);
+        let mut entry_points = vec![entry_point];
+        for l in (level + 1..=top_level).rev() {
+            let nearest = self
+                .graph
+                .search_level(&score_new, &entry_points, 1, l, &|_| true);
+            entry_points = vec![nearest[0].node];
+        }
+        for l in (0..=level.min(top_level)).rev() {
+            let candidates = self.graph.search_level(
+                &score_new,
+                &entry_points,
+                self.ef_construction,
+                l,
+                &|_| true,
+            );
+            let neighbors: Vec<u32> = candidates
+                .iter()
+                .take(self.graph.max_connections)
+                .map(|c| c.node as u32)
+                .collect();
+            for &n in &neighbors {
+                self.graph.nodes[n as usize][l].push(id as u32);
+                self.prune(n as usize, l, &score);
+            }
+            self.graph.nodes[id][l] = neighbors;
+            entry_points = candidates.iter().map(|c| c.node).collect();
+        }
+        if level > top_level {
+            self.graph.entry_point = Some(id);
+        }
+    }
+
+    pub fn build(self) -> HnswGraph {
+        self.graph
+    }
+
+    fn random_level(&mut self) -> usize {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        // uniform in (0, 1]
+        let uniform = ((self.seed >> 11) + 1) as f64 / (1u64 << 53) as f64;
+        ((-uniform.ln() * self.level_multiplier) as usize).min(HnswGraph::MAX_LEVEL)
+    }
+
+    // keeps the nearest neighbors of `node` on `level` once it has too many of them
+    fn prune<F: Fn(usize, usize) -> f32>(&mut self, node: usize, level: usize, score: &F) {
+        let max_neighbors = self.graph.max_neighbors(level);
+        let neighbors = &mut self.graph.nodes[node][level];
+        if neighbors.len() <= max_neighbors {
+            return;
+        }
+        let mut scored: Vec<ScoredNode> = neighbors
+            .iter()
+            .map(|&n| ScoredNode {
+                score: score(node, n as usize),
+                node: n as usize,
+            })
+            .collect();
+        scored.sort_by(|a, b| b.cmp(a));
+        *neighbors = scored
+            .iter()
+            .take(max_neighbors)
+            .map(|s| s.node as u32)
+            .collect();
+    }
+}
+
+/// Searches the nearest neighbors of a query vector in the `HnswGraph` of the vectors
+/// of a field in a segment, among all its docs or only those of a filter.
+pub struct HnswGraphSearcher<'a> {
+    vectors: &'a VectorReader,
+    graph: &'a HnswGraph,
+}
+
+impl<'a> HnswGraphSearcher<'a> {
+    /// Returns `None` if the vectors were written without a graph.
+    pub fn new(vectors: &'a VectorReader) -> Option<HnswGraphSearcher<'a>> {
+        vectors
+            .graph()
+            .map(|graph| HnswGraphSearcher { vectors, graph })
+    }
+
+    /// Returns the docs whose vectors are the `k` nearest to `query_vector` found with a
+    /// beam of `ef` candidates, with their scores in descending order. The larger `ef`
+    /// the better the recall, and the slower the search.
+    pub fn search(&self, query_vector: &[f32], k: usize, ef: usize) -> Result<Vec<(DocId, f32)>> {
+        self.search_accepting(query_vector, k, ef, |_| true)
+    }
+
+    /// Returns the docs of `filter` whose vectors are the `k` nearest to `query_vector`
+    /// found with a beam of `ef` candidates, with their scores in descending order.
+    ///
+    /// `filter` is read from its current position to its end. The walk goes through the
+    /// nodes of the docs out of `filter` to reach the others but skips them as hits,
+    /// so the more selective the filter the more of the graph is explored.
+    pub fn search_with_filter(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        ef: usize,
+        filter: &mut dyn DocIterator,
+    ) -> Result<Vec<(DocId, f32)>> {
+        let accepted = accepted_docs(self.vectors, filter)?;
+        self.search_accepting(query_vector, k, ef, |doc| {
+            accepted.get(doc as usize).unwrap_or(false)
+        })
+    }
+
+    // like `search`, among the docs accepted by `accept`
+    fn search_accepting<A: Fn(DocId) -> bool>(
+        &self,
+        query_vector: &[f32],
+        k: usize,
+        ef: usize,
+        accept: A,
+    ) -> Result<Vec<(DocId, f32)>> {
+        check_dimension(self.vectors, query_vector)?;
+        // compares the quantized values of the vectors if they were written quantized
+        let scorer = self.vectors.scorer(query_vector)?;
+        let docs = self.vectors.docs();
+        let hits =
+            self.graph
+                .search_accepting(|ord| scorer.score(ord), k, ef, |ord| accept(docs[ord]));
+        Ok(hits
+            .into_iter()
+            .map(|(ord, score)| (docs[ord], score))
+            .collect())
+    }
+}
+
+fn check_dimension(vectors: &VectorReader, query: &[f32]) -> Result<()> {
+    if query.len() != vectors.dimension() {
+        bail!(IllegalArgument(format!(
+            "vector dimension {} differs from the dimension {} of the vectors of the field",
+            query.len(),
+            vectors.dimension()
+        )));
+    }
+    Ok(())
+}
+
+// the docs with a vector in `vectors` matched by `filter`, read from its current position
+fn accepted_docs(vectors: &VectorReader, filter: &mut dyn DocIterator) -> Result<FixedBitSet> {
+    let num_bits = vectors.docs().last().map_or(0, |&doc| doc as usize + 1);
+    let mut accepted = FixedBitSet::new(num_bits);
+    let mut doc = filter.next()?;
+    while doc < num_bits as DocId {
+        accepted.set(doc as usize);
+        doc = filter.next()?;
+    }
+    Ok(accepted)
+}
+
+/// Returns the docs of the `k` nearest vectors to `query` among the live docs of a leaf
+/// indexed in `field` as `DenseVectorField`s, with their scores in descending order.
+/// With a `filter`, only the live docs it matches are hits.
+///
+/// The HNSW graph of the field is searched with a beam of `ef` candidates, unless the
+/// leaf has no more than `ef` docs, where the beam would visit about every vector, or
+/// its vectors were written without a graph: the leaf is then searched exactly.
+pub(crate) fn leaf_knn_search<C: Codec>(
+    reader: &LeafReaderContext<'_, C>,
+    field: &str,
+    query: &[f32],
+    k: usize,
+    ef: usize,
+    filter: Option<&dyn Weight<C>>,
+) -> Result<Vec<(DocId, f32)>> {
+    let vectors = match reader.reader.vector_values(field) {
+        Some(vectors) => vectors,
+        None => return Ok(vec![]),
+    };
+    let accepted = match filter {
+        Some(weight) => match weight.create_scorer(reader)? {
+            Some(mut scorer) => Some(accepted_docs(vectors, &mut scorer)?),
+            None => return Ok(vec![]),
+        },
+        None => None,
+    };
+    let live_docs = reader.reader.live_docs();
+    let accept = |doc: DocId| {
+        live_docs.get(doc as usize).unwrap_or(false)
+            && accepted
+                .as_ref()
+                .map_or(true, |a| a.get(doc as usize).unwrap_or(false))
+    };
+    match HnswGraphSearcher::new(vectors) {
+        Some(searcher) if reader.reader.max_doc() as usize > ef => {
+            searcher.search_accepting(query, k, ef, accept)
+        }
+        _ => exact_search_accepting(vectors, query, k, accept),
+    }
+}
+
+/// Returns the docs of the `k` nearest vectors to `query` among the live docs of a leaf
+/// indexed in `field` as `DenseVectorField`s, with their scores in descending order.
+///
+/// Unlike `HnswGraphSearcher::search` every vector is compared to `query`, so the hits
+/// are the true nearest neighbors.
+pub(crate) fn exact_leaf_search<C: Codec>(
+    reader: &LeafReaderContext<'_, C>,
+    field: &str,
+    query: &[f32],
+    k: usize,
+) -> Result<Vec<(DocId, f32)>> {
+    let vectors = match reader.reader.vector_values(field) {
+        Some(vectors) => vectors,
+        None => return Ok(vec![]),
+    };
+    let live_docs = reader.reader.live_docs();
+    exact_search_accepting(vectors, query, k, |doc| {
+        live_docs.get(doc as usize).unwrap_or(false)
+    })
+}
+
+// like `exact_leaf_search`, among the docs accepted by `accept`
+fn exact_search_accepting<A: Fn(DocId) -> bool>(
+    vectors: &VectorReader,
+    query: &[f32],
+    k: usize,
+    accept: A,
+) -> Result<Vec<(DocId, f32)>> {
+    check_dimension(vectors, query)?;
+    let scorer = vectors.exact_scorer(query)?;
+    let mut results = GraphBasedKnnCollector::new(k);
+    for (ord, &doc) in vectors.docs().iter().enumerate() {
+        if accept(doc) {
+            results.collect(doc as u32, scorer.score(ord));
+        }
+    }
+    Ok(results
+        .into_sorted()
+        .iter()
+        .map(|r| (r.node as DocId, r.score))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::analysis::StringTokenStream;
+    use core::codec::vectors::{pack_bits, KnnVectorSimilarityFunction};
+    use core::doc::{BinaryVectorField, Field, FieldType, Fieldable, IndexOptions, Term};
+    use core::index::reader::IndexReader;
+    use core::search::collector::TopDocsCollector;
+    use core::search::query::{ExactKnnQuery, TermQuery};
+    use core::search::sort_field::TopDocs;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::store::io::ByteArrayDataInput;
+    use core::test_util::{new_fs_writer, random_vectors, vector_field};
+    use core::util::VariantValue;
+    use error::{Error, ErrorKind};
+
+    use std::sync::Arc;
+
+    fn build_graph(vectors: &[Vec<f32>], similarity: KnnVectorSimilarityFunction) -> HnswGraph {
+        let mut builder = HnswGraphBuilder::new(16, 100).unwrap();
+        for _ in vectors {
+            builder.add_graph_node(|a, b| similarity.score(&vectors[a], &vectors[b]));
+        }
+        builder.build()
+    }
+
+    fn brute_force(
+        vectors: &[Vec<f32>],
+        query: &[f32],
+        k: usize,
+        similarity: KnnVectorSimilarityFunction,
+    ) -> Vec<DocId> {
+        let mut scored: Vec<(DocId, f32)> = vectors
+            .iter()
+            .enumerate()
+            .map(|(doc, v)| (doc as DocId, similarity.score(query, v)))
+            .collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        scored.iter().take(k).map(|s| s.0).collect()
+    }
+
+    #[test]
+    fn test_graph_based_knn_collector() {
+        let scores = random_vectors(1, 1000, 0x2545_f491_4f6c_dd1d).remove(0);
+        let mut collector = GraphBasedKnnCollector::new(10);
+        assert!(collector.min_competitive_score().is_none());
+        for (node, &score) in scores.iter().enumerate() {
+            let min_score = collector.min_competitive_score();
+            let kept = collector.collect(node as u32, score);
+            // a node scoring below the k-th score is discarded
+            if let Some(min_score) = min_score {
+                assert_eq!(kept, score > min_score);
+            } else {
+                assert!(kept);
+            }
+            assert!(collector.len() <= 10);
+        }
+
+        let mut expected: Vec<(DocId, f32)> = scores
+            .iter()
+            .enumerate()
+            .map(|(node, &score)| (node as DocId, score))
+            .collect();
+        expected.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        expected.truncate(10);
+        assert_eq!(collector.min_competitive_score(), Some(expected[9].1));
+        let top_docs: Vec<(DocId, f32)> = collector
+            .top_docs()
+            .iter()
+            .map(|d| (d.doc, d.score))
+            .collect();
+        assert_eq!(top_docs, expected);
+
+        // on equal scores the smaller nodes are kept
+        let mut collector = GraphBasedKnnCollector::new(2);
+        for node in &[5, 3, 8, 1] {
+            collector.collect(*node, 1.0);
+        }
+        let nodes: Vec<DocId> = collector.top_docs().iter().map(|d| d.doc).collect();
+        assert_eq!(nodes, vec![1, 3]);
+        let mut collector = GraphBasedKnnCollector::new(0);
+        assert!(!collector.collect(0, 1.0));
+        assert!(collector.top_docs().is_empty());
+
+        // a NaN score is lower than any other
+        let nan = ::std::f32::NAN;
+        assert_eq!(compare_scores(nan, -1.0), Ordering::Less);
+        assert_eq!(compare_scores(1.0, nan), Ordering::Greater);
+        assert_eq!(compare_scores(nan, nan), Ordering::Equal);
+        let mut collector = GraphBasedKnnCollector::new(2);
+        assert!(collector.collect(0, nan));
+        assert!(collector.collect(1, 0.5));
+        assert!(collector.collect(2, 0.1));
+        assert!(!collector.collect(3, nan));
+        let nodes: Vec<DocId> = collector.top_docs().iter().map(|d| d.doc).collect();
+        assert_eq!(nodes, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_binary_vector_field() {
+        let bits = [1.0, 0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        let field = BinaryVectorField::new("bits", &bits).unwrap();
+        assert_eq!(field.field_type().vector_dimension, 9);
+        let hamming = KnnVectorSimilarityFunction::Hamming;
+        assert_eq!(field.field_type().vector_similarity, hamming);
+        // the 9 bits are packed into one word
+        let word = 0b1_0000_1101u64.to_le_bytes();
+        assert_eq!(field.binary_value().unwrap(), &word[..]);
+
+        let (_dir, writer) = new_fs_writer();
+        let vectors: Vec<Vec<f32>> = random_vectors(100, 9, 0x2545_f491_4f6c_dd1d)
+            .into_iter()
+            .map(|v| v.iter().map(|&x| if x > 0.0 { 1.0 } else { 0.0 }).collect())
+            .collect();
+        for (i, v) in vectors.iter().enumerate() {
+            let field = BinaryVectorField::new("bits", v).unwrap();
+            let doc: Vec<Box<dyn Fieldable>> = vec![Box::new(field)];
+            writer.add_document(doc).unwrap();
+            if i == 49 {
+                writer.commit().unwrap();
+            }
+        }
+        writer.commit().unwrap();
+        writer.force_merge(1, true).unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        {
+            let leaves = reader.leaves();
+            assert_eq!(leaves.len(), 1);
+            let values = leaves[0].reader.vector_values("bits").unwrap();
+            assert_eq!(values.dimension(), 9);
+            for (ord, v) in vectors.iter().enumerate() {
+                assert_eq!(values.binary_vector_at(ord), &pack_bits(v)[..]);
+            }
+            assert!(values.get_vector(0).is_err());
+            assert_eq!(values.graph().unwrap().len(), 100);
+        }
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        // the leaf is smaller than the beam, so it is searched exactly
+        let top_docs = searcher.knn_search("bits", &vectors[7], 5, 100).unwrap();
+        let hits = top_docs.score_docs();
+        assert_eq!(hits[0].score(), 1.0);
+        let mut expected: Vec<f32> = vectors
+            .iter()
+            .map(|v| hamming.score(&vectors[7], v))
+            .collect();
+        expected.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        let scores: Vec<f32> = hits.iter().map(|h| h.score()).collect();
+        assert_eq!(scores, &expected[..5]);
+
+        // the graph search counts the differing bits of the words as well
+        let top_docs = searcher.knn_search("bits", &vectors[7], 5, 20).unwrap();
+        assert_eq!(top_docs.score_docs()[0].score(), 1.0);
+        assert!(searcher
+            .knn_search("bits", &vectors[7][..8], 5, 20)
+            .is_err());
+    }
+
+    #[test]
+    fn test_vector_field_without_value() {
+        let (_dir, writer) = new_fs_writer();
+        let mut field_type = FieldType::default();
+        field_type
+            .set_vector_dimension(4, KnnVectorSimilarityFunction::L2)
+            .unwrap();
+        let value = VariantValue::VString("not a vector".into());
+        let doc: Vec<Box<dyn Fieldable>> = vec![Box::new(Field::new(
+            "v".into(),
+            field_type,
+            Some(value),
+            None,
+        ))];
+        match writer.add_document(doc) {
+            Err(Error(ErrorKind::IllegalArgument(msg), _)) => assert!(msg.contains("'v'")),
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("expected an illegal argument"),
+        }
+    }
+
+    #[test]
+    fn test_hnsw_recall() {
+        let vectors = random_vectors(1000, 16, 0x2545_f491_4f6c_dd1d);
+        let queries = random_vectors(20, 16, 0x1234_5678_9abc_def1);
+        for &similarity in &[
+            KnnVectorSimilarityFunction::L2,
+            KnnVectorSimilarityFunction::Cosine,
+        ] {
+            let graph = build_graph(&vectors, similarity);
+            assert_eq!(graph.len(), 1000);
+
+            let mut found = 0;
+            for query in &queries {
+                let hits = graph.search(|n| similarity.score(query, &vectors[n]), 10, 50);
+                assert_eq!(hits.len(), 10);
+                assert!(hits.windows(2).all(|w| w[0].1 >= w[1].1));
+                let expected = brute_force(&vectors, query, 10, similarity);
+                found += hits
+                    .iter()
+                    .filter(|h| expected.contains(&(h.0 as DocId)))
+                    .count();
+            }
+            let recall = found as f32 / (queries.len() * 10) as f32;
+            assert!(recall >= 0.9, "recall {} of {:?}", recall, similarity);
+        }
+
+        let graph = HnswGraphBuilder::new(16, 100).unwrap().build();
+        assert!(graph.search(|_| 1.0, 3, 10).is_empty());
+        assert!(HnswGraphBuilder::new(1, 100).is_err());
+        assert!(HnswGraphBuilder::new(16, 0).is_err());
+    }
+
+    #[test]
+    fn test_hnsw_graph_write_and_read() {
+        let vectors = random_vectors(1000, 16, 0x2545_f491_4f6c_dd1d);
+        let queries = random_vectors(20, 16, 0x1234_5678_9abc_def1);
+        let similarity = KnnVectorSimilarityFunction::L2;
+        let graph = build_graph(&vectors, similarity);
+        let mut bytes = vec![];
+        graph.write(&mut bytes).unwrap();
+        let read = HnswGraph::read(&mut ByteArrayDataInput::new(&bytes), 1000).unwrap();
+        assert_eq!(read.len(), 1000);
+        assert_eq!(read.max_connections(), 16);
+        for query in &queries {
+            // the read graph has the same nodes and links, so the same hits
+            let score = |n: usize| similarity.score(query, &vectors[n]);
+            assert_eq!(read.search(score, 10, 50), graph.search(score, 10, 50));
+        }
+
+        // the graph must have a node per vector
+        assert!(HnswGraph::read(&mut ByteArrayDataInput::new(&bytes), 999).is_err());
+        // corrupt sizes are rejected before anything is allocated for them
+        let mut corrupt = vec![];
+        for &v in &[16, 2, 1, 1, i32::max_value()] {
+            corrupt.write_vint(v).unwrap();
+        }
+        assert!(HnswGraph::read(&mut ByteArrayDataInput::new(&corrupt), 2).is_err());
+        let mut corrupt = vec![];
+        for &v in &[16, 2, 1, i32::max_value()] {
+            corrupt.write_vint(v).unwrap();
+        }
+        assert!(HnswGraph::read(&mut ByteArrayDataInput::new(&corrupt), 2).is_err());
+
+        // an empty graph round trips too
+        let empty = HnswGraphBuilder::new(4, 10).unwrap().build();
+        let mut bytes = vec![];
+        empty.write(&mut bytes).unwrap();
+        let read = HnswGraph::read(&mut ByteArrayDataInput::new(&bytes), 0).unwrap();
+        assert!(read.is_empty());
+        assert!(read.search(|_| 1.0, 3, 10).is_empty());
+    }
+
+    #[test]
+    fn test_knn_search() {
+        let (_dir, writer) = new_fs_writer();
+        let vectors = random_vectors(200, 8, 0x2545_f491_4f6c_dd1d);
+        for (i, v) in vectors.iter().enumerate() {
+            let doc: Vec<Box<dyn Fieldable>> = vec![
+                vector_field("l2", v, KnnVectorSimilarityFunction::L2),
+                vector_field("cosine", v, KnnVectorSimilarityFunction::Cosine),
+            ];
+            writer.add_document(doc).unwrap();
+            // spread the docs over several segments
+            if i % 70 == 69 {
+                writer.commit().unwrap();
+            }
+        }
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        for &(field, similarity) in &[
+            ("l2", KnnVectorSimilarityFunction::L2),
+            ("cosine", KnnVectorSimilarityFunction::Cosine),
+        ] {
+            // a vector of the index is its own nearest neighbor
+            let top_docs = searcher.knn_search(field, &vectors[42], 5, 50).unwrap();
+            let hits = top_docs.score_docs();
+            assert_eq!(hits.len(), 5);
+            assert_eq!(hits[0].doc_id(), 42);
+            let expected = brute_force(&vectors, &vectors[42], 5, similarity);
+            let found = hits
+                .iter()
+                .filter(|h| expected.contains(&h.doc_id()))
+                .count();
+            assert!(found >= 4);
+        }
+        let top_docs = searcher.knn_search("missing", &vectors[0], 5, 50).unwrap();
+        assert_eq!(top_docs.total_hits(), 0);
+
+        // the NaN scores of a NaN query are ranked, not compared with a panic
+        let nan_query = vec![::std::f32::NAN; 8];
+        let top_docs = searcher.knn_search("l2", &nan_query, 5, 50).unwrap();
+        assert_eq!(top_docs.score_docs().len(), 5);
+    }
+
+    #[test]
+    fn test_exact_knn_query() {
+        let (_dir, writer) = new_fs_writer();
+        let vectors = random_vectors(300, 16, 0x2545_f491_4f6c_dd1d);
+        let queries = random_vectors(20, 16, 0x1234_5678_9abc_def1);
+        for (i, v) in vectors.iter().enumerate() {
+            let doc: Vec<Box<dyn Fieldable>> = vec![
+                vector_field("l2", v, KnnVectorSimilarityFunction::L2),
+                vector_field("cosine", v, KnnVectorSimilarityFunction::Cosine),
+            ];
+            writer.add_document(doc).unwrap();
+            if i % 100 == 99 {
+                writer.commit().unwrap();
+            }
+        }
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        for &(field, similarity) in &[
+            ("l2", KnnVectorSimilarityFunction::L2),
+            ("cosine", KnnVectorSimilarityFunction::Cosine),
+        ] {
+            let mut found = 0;
+            for query in &queries {
+                let knn_query = ExactKnnQuery::new(field.into(), query.clone(), 10);
+                let mut collector = TopDocsCollector::new(10);
+                searcher.search(&knn_query, &mut collector).unwrap();
+                let exact: Vec<DocId> = collector
+                    .top_docs()
+                    .score_docs()
+                    .iter()
+                    .map(|d| d.doc_id())
+                    .collect();
+                assert_eq!(exact, brute_force(&vectors, query, 10, similarity));
+                // the leaves match only their share of the nearest docs of the index
+                let mut collector = TopDocsCollector::new(100);
+                searcher.search(&knn_query, &mut collector).unwrap();
+                assert_eq!(collector.top_docs().total_hits(), 10);
+                assert!(searcher.explain(&knn_query, exact[9]).unwrap().is_match());
+                let other = (0..300).find(|d| !exact.contains(d)).unwrap();
+                assert!(!searcher.explain(&knn_query, other).unwrap().is_match());
+
+                let top_docs = searcher.knn_search(field, query, 10, 50).unwrap();
+                found += top_docs
+                    .score_docs()
+                    .iter()
+                    .filter(|h| exact.contains(&h.doc_id()))
+                    .count();
+
+                // no leaf has more docs than the beam, all of them are searched exactly
+                let top_docs = searcher.knn_search(field, query, 10, 300).unwrap();
+                let hits: Vec<DocId> = top_docs.score_docs().iter().map(|d| d.doc_id()).collect();
+                assert_eq!(hits, exact);
+            }
+            let recall = found as f32 / (queries.len() * 10) as f32;
+            assert!(recall > 0.9, "recall {} of {:?}", recall, similarity);
+        }
+
+        let query = ExactKnnQuery::new("l2".into(), vec![1.0, 2.0], 10);
+        let mut collector = TopDocsCollector::new(10);
+        assert!(searcher.search(&query, &mut collector).is_err());
+    }
+
+    #[test]
+    fn test_hnsw_search_with_term_filter() {
+        let (_dir, writer) = new_fs_writer();
+        let vectors = random_vectors(500, 16, 0x2545_f491_4f6c_dd1d);
+        let queries = random_vectors(20, 16, 0x1234_5678_9abc_def1);
+        let category = |doc: usize| format!("c{}", doc % 4);
+        for (i, v) in vectors.iter().enumerate() {
+            let mut field_type = FieldType::default();
+            field_type.index_options = IndexOptions::Docs;
+            let tokens = StringTokenStream::new(category(i));
+            let doc: Vec<Box<dyn Fieldable>> = vec![
+                vector_field("v", v, KnnVectorSimilarityFunction::L2),
+                Box::new(Field::new(
+                    "category".into(),
+                    field_type,
+                    None,
+                    Some(Box::new(tokens)),
+                )),
+            ];
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+        let reader = Arc::new(writer.get_reader(true, false).unwrap());
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 1);
+        let vectors_reader = leaves[0].reader.vector_values("v").unwrap();
+        // the graph is written with the vectors when the segment is flushed
+        assert_eq!(vectors_reader.graph().unwrap().len(), 500);
+        drop(leaves);
+        let searcher = DefaultIndexSearcher::new(reader, None);
+        let filter = TermQuery::new(Term::new("category".into(), b"c1".to_vec()), 1.0, None);
+        let hit_docs = |top_docs: TopDocs| -> Vec<DocId> {
+            top_docs.score_docs().iter().map(|d| d.doc_id()).collect()
+        };
+
+        let mut found = 0;
+        for query in &queries {
+            let top_docs = searcher
+                .knn_search_with_filter("v", query, 5, 50, &filter)
+                .unwrap();
+            let scores: Vec<f32> = top_docs.score_docs().iter().map(|d| d.score()).collect();
+            assert!(scores.windows(2).all(|w| w[0] >= w[1]));
+            let hits = hit_docs(top_docs);
+            assert_eq!(hits.len(), 5);
+            assert!(hits.iter().all(|&doc| category(doc as usize) == "c1"));
+
+            let in_category: Vec<Vec<f32>> = vectors
+                .iter()
+                .enumerate()
+                .map(|(doc, v)| {
+                    // the other docs can't be nearer than any vector of the category
+                    if category(doc) == "c1" {
+                        v.clone()
+                    } else {
+                        vec![1e6; v.len()]
+                    }
+                })
+                .collect();
+            let expected = brute_force(&in_category, query, 5, KnnVectorSimilarityFunction::L2);
+            found += hits.iter().filter(|doc| expected.contains(doc)).count();
+
+            // a beam as large as the leaf searches it exactly
+            let top_docs = searcher
+                .knn_search_with_filter("v", query, 5, 500, &filter)
+                .unwrap();
+            assert_eq!(hit_docs(top_docs), expected);
+        }
+        let recall = found as f32 / (queries.len() * 5) as f32;
+        assert!(recall >= 0.9, "recall {}", recall);
+
+        // doc 42 is its own nearest neighbor, unless filtered out
+        let hits = hit_docs(searcher.knn_search("v", &vectors[42], 5, 50).unwrap());
+        assert_eq!(hits[0], 42);
+        let top_docs = searcher
+            .knn_search_with_filter("v", &vectors[42], 5, 50, &filter)
+            .unwrap();
+        assert!(hit_docs(top_docs).iter().all(|&doc| doc != 42));
+
+        // no doc matches the filter
+        let none = TermQuery::new(Term::new("category".into(), b"c9".to_vec()), 1.0, None);
+        let top_docs = searcher
+            .knn_search_with_filter("v", &vectors[42], 5, 50, &none)
+            .unwrap();
+        assert!(top_docs.score_docs().is_empty());
+    }
+}
@@ -205,6 +205,17 @@ pub trait MergePolicy: 'static {
         }
     }
 
+    /// Returns true if the segment of `info`, all of whose docs have been deleted,
+    /// should be kept in the index instead of being dropped once its deletes are
+    /// applied. Segments chosen for a merge are still merged away.
+    fn keep_fully_deleted_segment<D, C>(&self, _info: &SegmentCommitInfo<D, C>) -> bool
+    where
+        D: Directory + Send + Sync + 'static,
+        C: Codec,
+    {
+        false
+    }
+
     /// Returns true if this single info is already fully merged (has no
     /// pending deletes, is in the same dir as the writer, and matches the
     /// current compound file setting
@@ -243,7 +254,7 @@ impl<D: Directory + Send + Sync + 'static, C: Codec> Default for MergeSpecificat
 }
 
 impl<D: Directory + Send + Sync + 'static, C: Codec> MergeSpecification<D, C> {
-    fn add(&mut self, merge: OneMerge<D, C>) {
+    pub fn add(&mut self, merge: OneMerge<D, C>) {
         self.merges.push(merge);
     }
 }
@@ -271,7 +282,7 @@ pub struct OneMerge<D: Directory, C: Codec> {
     pub rate_limiter: Arc<MergeRateLimiter>,
     pub merge_start_time: Arc<Volatile<Option<SystemTime>>>,
     /// Total number of documents in segments to be merged, not accounting for deletions.
-    pub total_max_doc: u32,
+    pub total_max_doc: u64,
     // error: Result<()>
 }
 
@@ -285,7 +296,7 @@ impl<D: Directory, C: Codec> OneMerge<D, C> {
             bail!(RuntimeError("segments must not be empty!".into()));
         }
 
-        let count: i32 = segments.iter().map(|s| s.info.max_doc).sum();
+        let count: u64 = segments.iter().map(|s| s.info.max_doc as u64).sum();
         let rate_limiter = Arc::new(MergeRateLimiter::new());
         Ok(OneMerge {
             id,
@@ -300,7 +311,7 @@ impl<D: Directory, C: Codec> OneMerge<D, C> {
             segments,
             rate_limiter,
             merge_start_time: Arc::new(Volatile::new(None)),
-            total_max_doc: count as u32,
+            total_max_doc: count,
         })
     }
 
@@ -877,14 +888,16 @@ impl MergePolicy for TieredMergePolicy {
                     for info in &merge.segments {
                         to_be_merged.insert(Arc::clone(info));
                     }
-                    debug!(
-                        "add merge={:?} size={} MB, score={} {}, {}",
-                        &merge.segments,
-                        (best_merge_bytes as f64) / 1024.0 / 1024.0,
-                        best_score.score(),
-                        best_score.explanation(),
-                        if best_too_large { "[max merge]" } else { "" }
-                    );
+                    writer.config().info_stream.log("MP", || {
+                        format!(
+                            "add merge={:?} size={} MB, score={} {}, {}",
+                            &merge.segments,
+                            (best_merge_bytes as f64) / 1024.0 / 1024.0,
+                            best_score.score(),
+                            best_score.explanation(),
+                            if best_too_large { "[max merge]" } else { "" }
+                        )
+                    });
                     spec.add(merge);
                 } else if spec.merges.is_empty() {
                     return Ok(None);
@@ -1113,3 +1126,176 @@ impl MergeScore {
         format!("skew {} non_del_ratio: {}", self.skew, self.non_del_ratio)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::codec::{CodecEnum, TermIterator, Terms};
+    use core::doc::Term;
+    use core::index::merge::SerialMergeScheduler;
+    use core::index::reader::{IndexReader, LeafReader};
+    use core::index::writer::IndexWriterConfig;
+    use core::store::directory::FSDirectory;
+    use core::test_util::{fs_writer, keyword_doc};
+
+    /// Never merges on its own, and leaves the segment named `kept_apart` out of
+    /// forced merges.
+    struct KeepApartMergePolicy {
+        kept_apart: String,
+        keep_fully_deleted: bool,
+    }
+
+    impl MergePolicy for KeepApartMergePolicy {
+        fn find_merges<D, C, MS, MP>(
+            &self,
+            _merge_trigger: MergerTrigger,
+            _segment_infos: &SegmentInfos<D, C>,
+            _writer: &IndexWriter<D, C, MS, MP>,
+        ) -> Result<Option<MergeSpecification<D, C>>>
+        where
+            D: Directory + Send + Sync + 'static,
+            C: Codec,
+            MS: MergeScheduler,
+            MP: MergePolicy,
+        {
+            Ok(None)
+        }
+
+        fn find_forced_merges<D, C, MS, MP>(
+            &self,
+            segment_infos: &SegmentInfos<D, C>,
+            _max_segment_count: u32,
+            segments_to_merge: &HashMap<Arc<SegmentCommitInfo<D, C>>, bool>,
+            writer: &IndexWriter<D, C, MS, MP>,
+        ) -> Result<Option<MergeSpecification<D, C>>>
+        where
+            D: Directory + Send + Sync + 'static,
+            C: Codec,
+            MS: MergeScheduler,
+            MP: MergePolicy,
+        {
+            let segments: Vec<_> = segment_infos
+                .segments
+                .iter()
+                .filter(|info| {
+                    info.info.name != self.kept_apart
+                        && segments_to_merge.contains_key(*info)
+                        && !writer.merging_segments().contains(&info.info.name)
+                })
+                .cloned()
+                .collect();
+            if segments.len() < 2 {
+                return Ok(None);
+            }
+            let mut spec = MergeSpecification::default();
+            spec.add(OneMerge::new(segments, writer.next_merge_id())?);
+            Ok(Some(spec))
+        }
+
+        fn find_forced_deletes_mergers<D, C, MS, MP>(
+            &self,
+            _segments_infos: &SegmentInfos<D, C>,
+            _writer: &IndexWriter<D, C, MS, MP>,
+        ) -> Result<Option<MergeSpecification<D, C>>>
+        where
+            D: Directory + Send + Sync + 'static,
+            C: Codec,
+            MS: MergeScheduler,
+            MP: MergePolicy,
+        {
+            Ok(None)
+        }
+
+        fn max_cfs_segment_size(&self) -> u64 {
+            DEFAULT_MAX_CFS_SEGMENT_SIZE
+        }
+
+        fn no_cfs_ratio(&self) -> f64 {
+            DEFAULT_NO_CFS_RATIO
+        }
+
+        fn keep_fully_deleted_segment<D, C>(&self, _info: &SegmentCommitInfo<D, C>) -> bool
+        where
+            D: Directory + Send + Sync + 'static,
+            C: Codec,
+        {
+            self.keep_fully_deleted
+        }
+    }
+
+    fn new_writer(
+        path: &::std::path::Path,
+        policy: KeepApartMergePolicy,
+    ) -> IndexWriter<FSDirectory, CodecEnum, SerialMergeScheduler, KeepApartMergePolicy> {
+        let config = Arc::new(IndexWriterConfig::new(
+            Arc::new(CodecEnum::default()),
+            SerialMergeScheduler {},
+            policy,
+        ));
+        fs_writer(path, config)
+    }
+
+    #[test]
+    fn test_custom_policy_keeps_segment_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let policy = KeepApartMergePolicy {
+            kept_apart: "_1".into(),
+            keep_fully_deleted: false,
+        };
+        let writer = new_writer(dir.path(), policy);
+        // segments _0 to _3, the docs of _1 are tagged "apart"
+        for tag in &["a", "apart", "b", "c"] {
+            writer.add_document(keyword_doc(tag)).unwrap();
+            writer.add_document(keyword_doc(tag)).unwrap();
+            writer.commit().unwrap();
+        }
+
+        writer.force_merge(1, true).unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 2);
+        let mut max_docs: Vec<i32> = leaves.iter().map(|l| l.reader.max_doc()).collect();
+        max_docs.sort();
+        assert_eq!(max_docs, vec![2, 6]);
+        for leaf in &leaves {
+            let terms = leaf.reader.terms("tag").unwrap().unwrap();
+            let mut iter = terms.iterator().unwrap();
+            let has_apart = iter.seek_exact(b"apart").unwrap();
+            assert_eq!(has_apart, leaf.reader.max_doc() == 2);
+        }
+    }
+
+    #[test]
+    fn test_keep_fully_deleted_segment() {
+        for &keep in &[false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            let policy = KeepApartMergePolicy {
+                kept_apart: String::new(),
+                keep_fully_deleted: keep,
+            };
+            let writer = new_writer(dir.path(), policy);
+            writer.add_document(keyword_doc("a")).unwrap();
+            writer.add_document(keyword_doc("b")).unwrap();
+            writer.commit().unwrap();
+            writer.add_document(keyword_doc("c")).unwrap();
+            writer.commit().unwrap();
+
+            writer
+                .delete_documents_by_terms(vec![Term::new("tag".into(), b"c".to_vec())])
+                .unwrap();
+            let reader = writer.get_reader(true, false).unwrap();
+            assert_eq!(reader.num_docs(), 2);
+            if keep {
+                assert_eq!(reader.leaves().len(), 2);
+                assert_eq!(reader.max_doc(), 3);
+            } else {
+                assert_eq!(reader.leaves().len(), 1);
+                assert_eq!(reader.max_doc(), 2);
+            }
+        }
+    }
+}
@@ -419,6 +419,13 @@ impl MergeScheduler for ConcurrentMergeScheduler {
             if let Some(merge) = writer.next_merge() {
                 scheduler.update_io_throttle(&merge);
 
+                writer.config().info_stream.log("CMS", || {
+                    format!(
+                        "launch merge thread #{} for {:?}",
+                        scheduler.merge_thread_count, &merge.segments
+                    )
+                });
+
                 let sentinel = Arc::new(ThreadSentinel);
                 let live_sentinel = Arc::downgrade(&sentinel);
                 let merge_thread = MergeThread {
@@ -22,6 +22,7 @@ use core::codec::postings::FieldsProducer;
 use core::codec::segment_infos::SegmentInfo;
 use core::codec::stored_fields::StoredFieldsReader;
 use core::codec::term_vectors::TermVectorsReader;
+use core::codec::vectors::DenseVectorsReader;
 use core::codec::{
     Codec, CodecFieldsProducer, CodecNormsProducer, CodecPointsReader, CodecStoredFieldsReader,
     CodecTVFields, CodecTVReader,
@@ -79,6 +80,9 @@ pub struct MergeState<D: Directory + 'static, C: Codec> {
     pub live_docs: Vec<BitsRef>,
     pub fields_producers: Vec<MergeFieldsProducer<CodecFieldsProducer<C>>>,
     pub points_readers: Vec<Option<MergePointValuesEnum<Arc<CodecPointsReader<C>>>>>,
+    /// Dense vectors of the segments, in their original doc order: docs must be mapped
+    /// with `leaf_doc_maps` before `doc_maps`
+    pub vectors_readers: Vec<Option<Arc<DenseVectorsReader>>>,
     pub max_docs: Vec<i32>,
     /// Indicates if the index needs to be sorted
     pub needs_index_sort: bool,
@@ -95,6 +99,10 @@ impl<D: Directory + 'static, C: Codec> MergeState<D, C> {
         for _ in 0..seg_readers.len() {
             leaf_doc_maps.push(MergeDocMap(DocMapEnum::Default(DefaultDocMap::default())));
         }
+        let vectors_readers = seg_readers
+            .iter()
+            .map(|reader| reader.core.vectors_reader.clone())
+            .collect();
         let mut needs_index_sort = false;
         let readers: Vec<ReaderWrapperEnum<D, C>> = Self::maybe_sort_readers(
             seg_readers,
@@ -154,6 +162,7 @@ impl<D: Directory + 'static, C: Codec> MergeState<D, C> {
             live_docs,
             fields_producers,
             points_readers,
+            vectors_readers,
             max_docs,
             needs_index_sort,
         })
@@ -19,9 +19,11 @@ use core::codec::postings::{FieldsConsumer, PostingsFormat};
 use core::codec::segment_infos::{SegmentInfo, SegmentWriteState};
 use core::codec::stored_fields::{StoredFieldsFormat, StoredFieldsWriter};
 use core::codec::term_vectors::{TermVectorsFormat, TermVectorsWriter};
+use core::codec::vectors::VectorsFormat;
 use core::codec::Codec;
 use core::index::merge::MergeState;
 use core::index::reader::SegmentReader;
+use core::index::writer::TempDirectory;
 use core::store::directory::Directory;
 use core::store::IOContext;
 use error::ErrorKind::{IllegalArgument, IllegalState};
@@ -39,6 +41,8 @@ pub struct SegmentMerger<D: Directory + 'static, DW: Directory, C: Codec> {
     context: IOContext,
     pub merge_state: MergeState<D, C>,
     field_infos_builder: FieldInfosBuilder<FieldNumbersRef>,
+    // where the transient files of the merge are written, `None` for `directory`
+    temp_directory: Option<Arc<dyn TempDirectory>>,
 }
 
 impl<D, DW, C> SegmentMerger<D, DW, C>
@@ -54,6 +58,7 @@ where
         directory: Arc<DW>,
         field_numbers: FieldNumbersRef,
         context: IOContext,
+        temp_directory: Option<Arc<dyn TempDirectory>>,
     ) -> Result<Self> {
         if !context.is_merge() {
             bail!(IllegalArgument("IOContext should be merge!".into()));
@@ -67,6 +72,7 @@ where
             context,
             merge_state,
             field_infos_builder,
+            temp_directory,
         })
     }
 
@@ -87,7 +93,7 @@ where
         let num_merged = self.merge_fields()?;
         assert_eq!(num_merged, self.merge_state.segment_info().max_doc);
 
-        let segment_write_state = SegmentWriteState::new(
+        let mut segment_write_state = SegmentWriteState::new(
             Arc::clone(&self.directory),
             self.merge_state.segment_info().clone(),
             self.merge_state
@@ -100,6 +106,7 @@ where
             self.context,
             "".into(),
         );
+        segment_write_state.temp_directory = self.temp_directory.clone();
         self.merge_terms(&segment_write_state)?;
 
         if self
@@ -121,6 +128,16 @@ where
             self.merge_points(&segment_write_state)?;
         }
 
+        if self
+            .merge_state
+            .merge_field_infos
+            .as_ref()
+            .unwrap()
+            .has_vector_values
+        {
+            self.merge_vector_values(&segment_write_state)?;
+        }
+
         if self
             .merge_state
             .merge_field_infos
@@ -175,6 +192,18 @@ where
         writer.merge(&self.merge_state)
     }
 
+    fn merge_vector_values(
+        &mut self,
+        segment_write_state: &SegmentWriteState<D, DW, C>,
+    ) -> Result<()> {
+        let mut writer = self
+            .codec
+            .vectors_format()
+            .fields_writer(segment_write_state)?;
+        writer.merge(&self.merge_state)?;
+        writer.finish()
+    }
+
     fn merge_norms(&mut self, segment_write_state: &SegmentWriteState<D, DW, C>) -> Result<()> {
         let mut consumer = self
             .codec
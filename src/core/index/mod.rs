@@ -15,6 +15,10 @@ pub mod merge;
 pub mod reader;
 pub mod writer;
 
+mod knn_vector;
+
+pub use self::knn_vector::*;
+
 error_chain! {
     types {
         Error, ErrorKind, ResultExt;
@@ -25,6 +29,11 @@ error_chain! {
             description(desc)
             display("merge is aborted: {}", desc)
         }
+
+        DocCountExceeded(count: i64, max: i32) {
+            description("Too many documents in a segment")
+            display("a segment cannot hold {} documents, the limit is {}", count, max)
+        }
     }
 }
 
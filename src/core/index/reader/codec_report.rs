@@ -0,0 +1,154 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::doc_values::NumericEncoding;
+use core::codec::postings::{FormatSummary, PerFieldFieldsReader, PER_FIELD_POSTING_FORMAT_KEY};
+use core::codec::stored_fields::{Lucene50StoredFieldsFormat, StoredFieldCompressMode};
+use core::codec::{Codec, Fields};
+use core::doc::IndexOptions;
+use core::index::reader::SegmentReader;
+use core::store::directory::Directory;
+
+use error::Result;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// The encodings the codec chose when writing a segment, to audit how well its fields
+/// compress.
+#[derive(Debug)]
+pub struct CodecReport {
+    pub segment: String,
+    /// The compression mode of the stored fields, `None` if the segment wasn't written
+    /// by `Lucene50StoredFieldsFormat`.
+    pub stored_fields_mode: Option<StoredFieldCompressMode>,
+    pub fields: BTreeMap<String, FieldCodecReport>,
+}
+
+/// The encodings of one field of a segment, see `CodecReport`.
+#[derive(Debug, Default)]
+pub struct FieldCodecReport {
+    /// The name of the postings format of the field, `None` if it isn't indexed.
+    pub postings_format: Option<String>,
+    /// Whether a bloom filter of the terms is kept next to the terms dict.
+    pub bloom_filter: bool,
+    /// How the full blocks of doc deltas of the postings were packed.
+    pub doc_blocks: FormatSummary,
+    /// How the full blocks of freqs of the postings were packed.
+    pub freq_blocks: FormatSummary,
+    /// How the numeric doc values, or the ords, of the field are encoded.
+    pub doc_values: Option<NumericEncoding>,
+}
+
+impl CodecReport {
+    /// Builds the report of the segment of `reader`. The postings of every term are
+    /// walked, so this costs about as much as reading all the postings once.
+    pub fn new<D, C>(reader: &SegmentReader<D, C>) -> Result<CodecReport>
+    where
+        D: Directory + 'static,
+        C: Codec<FieldsProducer = Arc<PerFieldFieldsReader>>,
+    {
+        let dv_producer = reader.doc_values_producer()?;
+        let mut fields = BTreeMap::new();
+        for (name, field_info) in &reader.field_infos.by_name {
+            let mut report = FieldCodecReport::default();
+            if field_info.index_options != IndexOptions::Null {
+                report.postings_format = field_info.attribute(PER_FIELD_POSTING_FORMAT_KEY);
+                if let Some(terms) = reader.core.fields().terms(name)? {
+                    report.bloom_filter = terms.bloom_filter().is_some();
+                    terms.format_summary(&mut report.doc_blocks, &mut report.freq_blocks)?;
+                }
+            }
+            if let Some(dv_producer) = dv_producer {
+                report.doc_values = dv_producer.numeric_encoding(field_info);
+            }
+            fields.insert(name.clone(), report);
+        }
+
+        Ok(CodecReport {
+            segment: reader.si.info.name.clone(),
+            stored_fields_mode: Lucene50StoredFieldsFormat::mode_of(&reader.si.info)?,
+            fields,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::analysis::StringTokenStream;
+    use core::codec::doc_values::NumericEncodingType;
+    use core::doc::{Field, FieldType, Fieldable, NumericDocValuesField};
+    use core::test_util::new_fs_writer;
+
+    fn tag_field(name: &str, options: IndexOptions) -> Box<dyn Fieldable> {
+        let mut field_type = FieldType::default();
+        field_type.index_options = options;
+        let tokens = StringTokenStream::new("x".to_string());
+        Box::new(Field::new(
+            name.into(),
+            field_type,
+            None,
+            Some(Box::new(tokens)),
+        ))
+    }
+
+    #[test]
+    fn test_codec_report_of_constant_field() {
+        let (_dir, writer) = new_fs_writer();
+        // every doc has the same term, so the postings are 4 blocks of consecutive docs
+        for i in 0..512 {
+            let doc: Vec<Box<dyn Fieldable>> = vec![
+                tag_field("tag", IndexOptions::Docs),
+                tag_field("body", IndexOptions::DocsAndFreqs),
+                Box::new(NumericDocValuesField::new("small", 1000 + i % 4)),
+                Box::new(NumericDocValuesField::new("const", 7)),
+            ];
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let reports = reader.codec_reports().unwrap();
+        assert_eq!(reports.len(), 1);
+        let report = &reports[0];
+        assert_eq!(
+            report.stored_fields_mode,
+            Some(StoredFieldCompressMode::BestSpeed)
+        );
+
+        let tag = &report.fields["tag"];
+        assert_eq!(tag.postings_format.as_ref().unwrap(), "Lucene50");
+        assert!(!tag.bloom_filter);
+        // the first block starts with a delta of 0 from doc 0, the others are all 1s
+        assert_eq!(tag.doc_blocks.num_blocks(), 4);
+        assert_eq!(tag.doc_blocks.all_values_equal(), 3);
+        assert_eq!(tag.doc_blocks.max_bits_per_value(), Some(1));
+        assert_eq!(tag.freq_blocks.num_blocks(), 0);
+        assert!(tag.doc_values.is_none());
+
+        let body = &report.fields["body"];
+        assert_eq!(body.doc_blocks, tag.doc_blocks);
+        assert_eq!(body.freq_blocks.num_blocks(), 4);
+        assert_eq!(body.freq_blocks.all_values_equal(), 4);
+
+        let small = report.fields["small"].doc_values.unwrap();
+        assert_eq!(small.encoding, NumericEncodingType::Delta);
+        assert_eq!(small.bits_per_value, 2);
+        assert!(report.fields["small"].postings_format.is_none());
+        let constant = report.fields["const"].doc_values.unwrap();
+        assert_eq!(constant.encoding, NumericEncodingType::Const);
+    }
+}
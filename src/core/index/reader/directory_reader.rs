@@ -11,26 +11,31 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::codec::postings::blocktree::FSTLoadMode;
+use core::codec::postings::PerFieldFieldsReader;
 use core::codec::segment_infos::INDEX_FILE_SEGMENTS;
 use core::codec::segment_infos::{get_segment_file_name, run_with_find_segment_file, SegmentInfos};
-use core::codec::{Codec, CodecTVFields};
+use core::codec::{checksum_entire_file, retrieve_checksum, Codec, CodecTVFields};
 use core::doc::{Document, DocumentStoredFieldVisitor};
 use core::index::merge::MergePolicy;
 use core::index::merge::MergeScheduler;
 use core::index::reader::{
-    IndexReader, LeafReader, LeafReaderContext, SearchLeafReader, SegmentReader,
+    CodecReport, IndexReader, LeafReader, LeafReaderContext, SearchLeafReader, SegmentReader,
 };
 use core::index::writer::{CommitPoint, IndexWriter};
 use core::store::directory::Directory;
 use core::store::IOContext;
+use core::util::external::Deferred;
 use core::util::DocId;
 
 use error::{
-    ErrorKind::{IllegalArgument, IllegalState},
+    ErrorKind::{CorruptIndex, IllegalArgument, IllegalState},
     Result,
 };
 
-use std::{collections::HashMap, fmt, sync::Arc};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
 
 ///
 // Returns <code>true</code> if an index likely exists at
@@ -59,6 +64,88 @@ pub fn index_exist<D: Directory>(directory: &D) -> Result<bool> {
         .any(|f| f.starts_with(&prefix)))
 }
 
+/// Name of the lock file held by an `IndexWriter`, never part of a commit.
+pub const WRITE_LOCK_NAME: &str = "write.lock";
+
+/// Copies all files referenced by the commit `snapshot` of `src` into `dest`.
+///
+/// This is meant for backups: `snapshot` must stay referenced (e.g. by holding a
+/// reader or a snapshot deletion policy) while it is copied. The lock file and temp
+/// files are never copied. Every copied file is read back from `dest` and its checksum
+/// is verified against the source footer. The `segments_N` file is copied last, so an
+/// interrupted copy never leaves a readable commit behind.
+pub fn copy_index<S, T, C>(src: &Arc<S>, dest: &T, snapshot: &SegmentInfos<S, C>) -> Result<()>
+where
+    S: Directory,
+    T: Directory,
+    C: Codec,
+{
+    let mut files: Vec<String> = snapshot
+        .files(false)
+        .into_iter()
+        .filter(|f| f != WRITE_LOCK_NAME && !f.to_lowercase().ends_with(".tmp"))
+        .collect();
+    files.sort();
+    if let Some(segment_file_name) = snapshot.segment_file_name() {
+        files.push(segment_file_name);
+    }
+
+    for file in &files {
+        dest.copy_from(Arc::clone(src), file, file, &IOContext::READ_ONCE)?;
+
+        let mut src_input = src.open_input(file, &IOContext::READ_ONCE)?;
+        let expected = retrieve_checksum(src_input.as_mut())?;
+        let dest_input = dest.open_input(file, &IOContext::READ_ONCE)?;
+        let actual = checksum_entire_file(dest_input.as_ref())?;
+        if actual != expected {
+            bail!(CorruptIndex(format!(
+                "checksum mismatch for copied file {}: expected=0x{:X}, actual=0x{:X}",
+                file, expected, actual
+            )));
+        }
+    }
+
+    dest.sync(&files.into_iter().collect())?;
+    dest.sync_meta_data()
+}
+
+/// Hooks called when a `StandardDirectoryReader` opens the core of a segment or the core
+/// is closed, e.g. to pre-warm per segment caches.
+///
+/// Segment cores are shared between a reader and the readers reopened from it, so
+/// `on_segment_opened` is only called for the first reader of each core, and
+/// `on_segment_closed` once the core itself is dropped, that is after every segment reader
+/// sharing it is gone.
+pub trait PerSegmentReaderFactory<D: Directory, C: Codec>: Send + Sync {
+    fn on_segment_opened(&self, reader: &SegmentReader<D, C>);
+
+    /// Called with the name of the segment whose core was dropped.
+    fn on_segment_closed(&self, _segment: &str) {}
+}
+
+/// Calls the hooks of a `PerSegmentReaderFactory` once per segment core, keyed by
+/// the core cache key.
+struct SegmentFactoryHooks<D: Directory, C: Codec> {
+    factory: Arc<dyn PerSegmentReaderFactory<D, C>>,
+    open_cores: Mutex<HashSet<String>>,
+}
+
+impl<D: Directory + 'static, C: Codec> SegmentFactoryHooks<D, C> {
+    fn register(hooks: &Arc<Self>, reader: &SegmentReader<D, C>) {
+        let key = reader.core_cache_key().to_string();
+        if !hooks.open_cores.lock().unwrap().insert(key.clone()) {
+            return;
+        }
+        hooks.factory.on_segment_opened(reader);
+        let segment = reader.name().to_string();
+        let hooks = Arc::clone(hooks);
+        reader.add_core_drop_listener(Deferred::new(move || {
+            hooks.open_cores.lock().unwrap().remove(&key);
+            hooks.factory.on_segment_closed(&segment);
+        }));
+    }
+}
+
 pub struct StandardDirectoryReader<
     D: Directory + Send + Sync + 'static,
     C: Codec,
@@ -74,6 +161,8 @@ pub struct StandardDirectoryReader<
     apply_all_deletes: bool,
     write_all_deletes: bool,
     writer: Option<IndexWriter<D, C, MS, MP>>,
+    fst_load_mode: FSTLoadMode,
+    segment_factory: Option<Arc<SegmentFactoryHooks<D, C>>>,
 }
 
 impl<D, C, MS, MP> StandardDirectoryReader<D, C, MS, MP>
@@ -84,21 +173,57 @@ where
     MP: MergePolicy,
 {
     pub fn open(directory: Arc<D>) -> Result<Self> {
+        Self::open_with_fst_load_mode(directory, FSTLoadMode::default())
+    }
+
+    /// Opens the latest commit, loading the terms index of every segment as
+    /// `fst_load_mode` says. Readers reopened from this one keep the same mode.
+    pub fn open_with_fst_load_mode(directory: Arc<D>, fst_load_mode: FSTLoadMode) -> Result<Self> {
         let segment_file_name = get_segment_file_name(directory.as_ref())?;
         let segment_infos = SegmentInfos::read_commit(&directory, &segment_file_name)?;
         let mut readers = Vec::with_capacity(segment_infos.segments.len());
         for seg_info in &segment_infos.segments {
-            let s = SegmentReader::open(seg_info, &IOContext::READ)?;
+            let s =
+                SegmentReader::open_with_fst_load_mode(seg_info, &IOContext::READ, fst_load_mode)?;
             readers.push(Arc::new(s));
         }
-        Ok(Self::new(
-            directory,
-            readers,
-            segment_infos,
-            None,
-            false,
-            false,
-        ))
+        let mut reader = Self::new(directory, readers, segment_infos, None, false, false);
+        reader.fst_load_mode = fst_load_mode;
+        Ok(reader)
+    }
+
+    /// Opens the latest commit, calling the hooks of `factory` for every segment core
+    /// this reader and the readers reopened from it open or close.
+    pub fn open_with_segment_factory(
+        directory: Arc<D>,
+        factory: Arc<dyn PerSegmentReaderFactory<D, C>>,
+    ) -> Result<Self> {
+        let mut reader = Self::open(directory)?;
+        reader.set_segment_factory(factory);
+        Ok(reader)
+    }
+
+    /// Calls the hooks of `factory` for the segment cores of this reader and of the
+    /// readers reopened from it, including near real-time readers reopened through
+    /// the writer.
+    pub fn set_segment_factory(&mut self, factory: Arc<dyn PerSegmentReaderFactory<D, C>>) {
+        let hooks = Arc::new(SegmentFactoryHooks {
+            factory,
+            open_cores: Mutex::new(HashSet::new()),
+        });
+        for r in &self.readers {
+            SegmentFactoryHooks::register(&hooks, r);
+        }
+        self.segment_factory = Some(hooks);
+    }
+
+    fn inherit_segment_factory(&self, reader: &mut Self) {
+        if let Some(ref hooks) = self.segment_factory {
+            for r in &reader.readers {
+                SegmentFactoryHooks::register(hooks, r);
+            }
+            reader.segment_factory = Some(Arc::clone(hooks));
+        }
     }
 
     /// Used by near real-time searcher
@@ -122,10 +247,11 @@ where
             // actual instance of SegmentInfoPerCommit in
             // IndexWriter's segmentInfos:
             let rld = writer.reader_pool().get_or_create(&infos.segments[i])?;
-            let reader = rld.get_readonly_clone(&IOContext::READ)?;
+            // readers of unchanged segments are shared with the previous NRT reader
+            let reader = rld.get_readonly_reader(&IOContext::READ)?;
             if reader.num_docs() > 0 {
                 // Steal the ref:
-                readers.push(Arc::new(reader));
+                readers.push(reader);
                 infos_upto += 1;
             } else {
                 segment_infos.segments.remove(infos_upto);
@@ -148,6 +274,7 @@ where
         directory: Arc<D>,
         infos: SegmentInfos<D, C>,
         old_readers: &[Arc<SegmentReader<D, C>>],
+        fst_load_mode: FSTLoadMode,
     ) -> Result<Self> {
         let mut reader_indexes: HashMap<&str, usize> = HashMap::with_capacity(old_readers.len());
         for (i, r) in old_readers.iter().enumerate() {
@@ -196,17 +323,17 @@ where
                     continue;
                 }
             }
-            let new_reader = SegmentReader::open(commit_info, &IOContext::READ)?;
+            let new_reader = SegmentReader::open_with_fst_load_mode(
+                commit_info,
+                &IOContext::READ,
+                fst_load_mode,
+            )?;
             new_readers.push(Arc::new(new_reader));
         }
-        Ok(StandardDirectoryReader::new(
-            directory,
-            new_readers,
-            infos,
-            None,
-            false,
-            false,
-        ))
+        let mut reader =
+            StandardDirectoryReader::new(directory, new_readers, infos, None, false, false);
+        reader.fst_load_mode = fst_load_mode;
+        Ok(reader)
     }
 
     fn new(
@@ -238,9 +365,31 @@ where
             writer,
             apply_all_deletes,
             write_all_deletes,
+            fst_load_mode: FSTLoadMode::default(),
+            segment_factory: None,
         }
     }
 
+    /// Returns the index of the sub reader containing global `doc_id`.
+    ///
+    /// Empty segments share their start with the following reader, so this picks
+    /// the last reader whose doc base is not greater than `doc_id`.
+    fn sub_index(&self, doc_id: DocId) -> usize {
+        debug_assert!(doc_id >= 0 && doc_id < self.max_doc);
+        // `starts` has one trailing entry equal to `max_doc`
+        let mut lo = 0;
+        let mut hi = self.readers.len();
+        while hi - lo > 1 {
+            let mid = (lo + hi) >> 1;
+            if self.starts[mid] <= doc_id {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
     pub fn set_writer(&mut self, writer: Option<IndexWriter<D, C, MS, MP>>) {
         self.writer = writer;
     }
@@ -267,6 +416,13 @@ where
         self.segment_infos.version
     }
 
+    /// Opens a new reader over `commit`, or over the latest commit (or the writer's
+    /// latest changes for near real-time readers) if `commit` is `None`.
+    ///
+    /// Returns `None` if nothing changed. Otherwise the segment readers of the segments
+    /// that are unchanged, e.g. that survived a merge, are shared with this reader and
+    /// only the new segments are opened. Segments merged away stay open until this
+    /// reader is dropped.
     pub fn open_if_changed(&self, commit: Option<&CommitPoint>) -> Result<Option<Self>> {
         // If we were obtained by writer.getReader(), re-ask the
         // writer to get a new reader.
@@ -291,6 +447,7 @@ where
             if reader.version() == self.segment_infos.version {
                 return Ok(None);
             }
+            self.inherit_segment_factory(&mut reader);
             Ok(Some(reader))
         }
     }
@@ -298,7 +455,10 @@ where
     fn open_from_commit(&self, commit: Option<&CommitPoint>) -> Result<Self> {
         run_with_find_segment_file(&self.directory, commit, |(dir, file_name)| {
             let infos = SegmentInfos::read_commit(dir, file_name)?;
-            Self::open_by_readers(Arc::clone(dir), infos, &self.readers)
+            let mut reader =
+                Self::open_by_readers(Arc::clone(dir), infos, &self.readers, self.fst_load_mode)?;
+            self.inherit_segment_factory(&mut reader);
+            Ok(reader)
         })
     }
 
@@ -332,6 +492,19 @@ where
     }
 }
 
+impl<D, C, MS, MP> StandardDirectoryReader<D, C, MS, MP>
+where
+    D: Directory + Send + Sync + 'static,
+    C: Codec<FieldsProducer = Arc<PerFieldFieldsReader>>,
+    MS: MergeScheduler,
+    MP: MergePolicy,
+{
+    /// Returns the `CodecReport` of every segment of this reader, in order.
+    pub fn codec_reports(&self) -> Result<Vec<CodecReport>> {
+        self.readers.iter().map(|r| CodecReport::new(r)).collect()
+    }
+}
+
 impl<D, C, MS, MP> IndexReader for StandardDirectoryReader<D, C, MS, MP>
 where
     D: Directory + Send + Sync + 'static,
@@ -351,29 +524,22 @@ where
     }
 
     fn term_vector(&self, doc_id: DocId) -> Result<Option<CodecTVFields<C>>> {
-        if doc_id < 0 || doc_id > self.max_doc {
+        if doc_id < 0 || doc_id >= self.max_doc {
             bail!(IllegalArgument(format!("invalid doc id: {}", doc_id)));
         }
-        let i = match self.starts.binary_search_by(|&probe| probe.cmp(&doc_id)) {
-            Ok(i) => i,
-            Err(i) => i - 1,
-        };
-        debug_assert!(i < self.readers.len());
+        let i = self.sub_index(doc_id);
         LeafReader::term_vector(self.readers[i].as_ref(), doc_id - self.starts[i])
     }
 
     fn document(&self, doc_id: DocId, fields_load: &[String]) -> Result<Document> {
-        if doc_id < 0 || doc_id > self.max_doc {
+        if doc_id < 0 || doc_id >= self.max_doc {
             bail!(IllegalArgument(format!(
                 "doc_id {} invalid: [max_doc={}]",
                 doc_id, self.max_doc
             )));
         }
 
-        let pos = match self.starts.binary_search_by(|&probe| probe.cmp(&doc_id)) {
-            Ok(i) => i,
-            Err(i) => i - 1,
-        };
+        let pos = self.sub_index(doc_id);
         let mut visitor = DocumentStoredFieldVisitor::new(&fields_load);
         LeafReader::document(
             self.readers[pos].as_ref(),
@@ -452,3 +618,379 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+
+    use core::codec::CodecEnum;
+    use core::doc::{IndexOptions, Term};
+    use core::index::merge::{SerialMergeScheduler, TieredMergePolicy};
+    use core::index::writer::IndexWriterConfig;
+    use core::search::collector::TopDocsCollector;
+    use core::search::query::TermQuery;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::store::directory::FSDirectory;
+    use core::test_util::{keyword_doc, new_fs_writer, text_doc};
+
+    #[test]
+    fn test_leaves_doc_base() {
+        let (_dir, writer) = new_fs_writer();
+
+        // three segments holding docs [0, 1], [2] and [3]
+        for values in &[vec!["a", "b"], vec!["c"], vec!["b"]] {
+            for v in values {
+                writer.add_document(keyword_doc(v)).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+
+        let reader = writer.get_reader(true, false).unwrap();
+        assert_eq!(reader.max_doc(), 4);
+        {
+            let leaves = reader.leaves();
+            assert_eq!(leaves.len(), 3);
+            let doc_bases: Vec<DocId> = leaves.iter().map(|l| l.doc_base()).collect();
+            assert_eq!(doc_bases, vec![0, 2, 3]);
+            for (i, leaf) in leaves.iter().enumerate() {
+                assert_eq!(leaf.ord, i);
+            }
+            assert_eq!(reader.leaf_reader_for_doc(1).ord, 0);
+            assert_eq!(reader.leaf_reader_for_doc(2).ord, 1);
+            assert_eq!(reader.leaf_reader_for_doc(3).ord, 2);
+        }
+        assert!(reader.document(4, &[]).is_err());
+
+        // per-segment hits are remapped to global doc ids
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let query = TermQuery::new(Term::new("tag".into(), b"b".to_vec()), 1.0, None);
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(&query, &mut collector).unwrap();
+        let top_docs = collector.top_docs();
+        let mut docs: Vec<DocId> = top_docs.score_docs().iter().map(|d| d.doc_id()).collect();
+        docs.sort();
+        assert_eq!(docs, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_nrt_reopen_reuses_unchanged_segment_readers() {
+        let (_dir, writer) = new_fs_writer();
+
+        writer.add_document(keyword_doc("a")).unwrap();
+        writer.add_document(keyword_doc("b")).unwrap();
+        writer.commit().unwrap();
+
+        let first = writer.get_reader(true, false).unwrap();
+        assert_eq!(first.readers.len(), 1);
+
+        // a second NRT open without changes hands out the very same segment reader
+        let second = writer.get_reader(true, false).unwrap();
+        assert_eq!(second.readers.len(), 1);
+        assert!(Arc::ptr_eq(&first.readers[0], &second.readers[0]));
+
+        // flushing one more segment only opens a reader for the new segment
+        writer.add_document(keyword_doc("c")).unwrap();
+        let third = writer.get_reader(true, false).unwrap();
+        assert_eq!(third.readers.len(), 2);
+        assert!(Arc::ptr_eq(&first.readers[0], &third.readers[0]));
+        assert_eq!(third.num_docs(), 3);
+
+        // deleting from the old segment requires a new reader for it
+        writer
+            .delete_documents_by_terms(vec![Term::new("tag".into(), b"a".to_vec())])
+            .unwrap();
+        let fourth = writer.get_reader(true, false).unwrap();
+        assert_eq!(fourth.num_docs(), 2);
+        assert!(!Arc::ptr_eq(&third.readers[0], &fourth.readers[0]));
+        assert!(Arc::ptr_eq(&third.readers[1], &fourth.readers[1]));
+    }
+
+    #[test]
+    fn test_doc_counts_across_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(IndexWriterConfig::default());
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let writer = IndexWriter::new(Arc::clone(&directory), config).unwrap();
+
+        for values in &[vec!["a", "b", "c"], vec!["b", "d"], vec!["e"]] {
+            for v in values {
+                writer.add_document(keyword_doc(v)).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+        let reader = writer.get_reader(true, false).unwrap();
+        assert_eq!(reader.max_doc(), 6);
+        assert_eq!(reader.num_docs(), 6);
+        assert_eq!(reader.num_deleted_docs(), 0);
+        assert!(!reader.has_deletions());
+        drop(reader);
+
+        // deleting "b" hits both of the first two segments
+        writer
+            .delete_documents_by_terms(vec![Term::new("tag".into(), b"b".to_vec())])
+            .unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        assert_eq!(reader.max_doc(), 6);
+        assert_eq!(reader.num_docs(), 4);
+        assert_eq!(reader.num_deleted_docs(), 2);
+        assert!(reader.has_deletions());
+        let per_leaf: Vec<(i32, i32)> = reader
+            .leaves()
+            .iter()
+            .map(|l| (l.reader.max_doc(), l.reader.num_docs()))
+            .collect();
+        assert_eq!(per_leaf, vec![(3, 2), (2, 1), (1, 1)]);
+        drop(reader);
+
+        // a reader opened from the commit sees the same counts
+        writer.commit().unwrap();
+        type Reader = StandardDirectoryReader<
+            FSDirectory,
+            CodecEnum,
+            SerialMergeScheduler,
+            TieredMergePolicy,
+        >;
+        let committed = Reader::open(Arc::clone(&directory)).unwrap();
+        assert_eq!(committed.max_doc(), 6);
+        assert_eq!(committed.num_docs(), 4);
+        drop(committed);
+
+        // merging drops the deleted docs
+        writer.force_merge(1, true).unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        assert_eq!(reader.leaves().len(), 1);
+        assert_eq!(reader.max_doc(), 4);
+        assert_eq!(reader.num_docs(), 4);
+        assert_eq!(reader.num_deleted_docs(), 0);
+    }
+
+    fn search_tag<IR: IndexReader<Codec = CodecEnum> + 'static>(
+        reader: IR,
+        tag: &str,
+    ) -> Vec<DocId> {
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let query = TermQuery::new(Term::new("tag".into(), tag.as_bytes().to_vec()), 1.0, None);
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(&query, &mut collector).unwrap();
+        let top_docs = collector.top_docs();
+        let mut docs: Vec<DocId> = top_docs.score_docs().iter().map(|d| d.doc_id()).collect();
+        docs.sort();
+        docs
+    }
+
+    #[test]
+    fn test_copy_index() {
+        let src_dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(IndexWriterConfig::default());
+        let src = Arc::new(FSDirectory::with_path(src_dir.path()).unwrap());
+        let writer = IndexWriter::new(Arc::clone(&src), config).unwrap();
+        for values in &[vec!["a", "b"], vec!["b", "c"]] {
+            for v in values {
+                writer.add_document(keyword_doc(v)).unwrap();
+            }
+            writer.commit().unwrap();
+        }
+
+        let snapshot: SegmentInfos<FSDirectory, CodecEnum> =
+            SegmentInfos::read_latest_commit(&src).unwrap();
+        let dest_dir = tempfile::tempdir().unwrap();
+        let dest = Arc::new(FSDirectory::with_path(dest_dir.path()).unwrap());
+        copy_index(&src, dest.as_ref(), &snapshot).unwrap();
+
+        let copied = dest.list_all().unwrap();
+        assert!(!copied
+            .iter()
+            .any(|f| f == WRITE_LOCK_NAME || f.ends_with(".tmp")));
+        assert_eq!(copied.len(), snapshot.files(true).len());
+
+        type Reader = StandardDirectoryReader<
+            FSDirectory,
+            CodecEnum,
+            SerialMergeScheduler,
+            TieredMergePolicy,
+        >;
+        let dest_reader = Reader::open(Arc::clone(&dest)).unwrap();
+        assert_eq!(dest_reader.max_doc(), 4);
+        assert_eq!(dest_reader.leaves().len(), 2);
+        drop(dest_reader);
+
+        for tag in &["a", "b", "c", "d"] {
+            let expected = search_tag(Reader::open(Arc::clone(&src)).unwrap(), tag);
+            let actual = search_tag(Reader::open(Arc::clone(&dest)).unwrap(), tag);
+            assert_eq!(expected, actual);
+        }
+        assert_eq!(search_tag(Reader::open(dest).unwrap(), "b"), vec![1, 2]);
+    }
+
+    struct RecordingFactory {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl PerSegmentReaderFactory<FSDirectory, CodecEnum> for RecordingFactory {
+        fn on_segment_opened(&self, reader: &SegmentReader<FSDirectory, CodecEnum>) {
+            let event = format!("open {}", reader.name());
+            self.events.lock().unwrap().push(event);
+        }
+
+        fn on_segment_closed(&self, segment: &str) {
+            let event = format!("close {}", segment);
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    type FSReader =
+        StandardDirectoryReader<FSDirectory, CodecEnum, SerialMergeScheduler, TieredMergePolicy>;
+
+    #[test]
+    fn test_segment_factory_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(IndexWriterConfig::default());
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let writer = IndexWriter::new(Arc::clone(&directory), config).unwrap();
+        writer.add_document(keyword_doc("a")).unwrap();
+        writer.commit().unwrap();
+
+        let factory = Arc::new(RecordingFactory {
+            events: Mutex::new(Vec::new()),
+        });
+        let take_events = || -> Vec<String> { factory.events.lock().unwrap().drain(..).collect() };
+        let first = FSReader::open_with_segment_factory(
+            Arc::clone(&directory),
+            Arc::clone(&factory) as Arc<dyn PerSegmentReaderFactory<_, _>>,
+        )
+        .unwrap();
+        assert_eq!(take_events(), vec!["open _0".to_string()]);
+
+        // reopening only opens the new segment
+        writer.add_document(keyword_doc("b")).unwrap();
+        writer.commit().unwrap();
+        let second = first.open_if_changed(None).unwrap().unwrap();
+        assert_eq!(second.readers.len(), 2);
+        assert_eq!(take_events(), vec!["open _1".to_string()]);
+
+        // _0 is still used by the second reader
+        drop(first);
+        assert!(take_events().is_empty());
+        // a segment reader outliving its directory reader keeps the core open
+        let segment = Arc::clone(&second.readers[1]);
+        drop(second);
+        assert_eq!(take_events(), vec!["close _0".to_string()]);
+        drop(segment);
+        assert_eq!(take_events(), vec!["close _1".to_string()]);
+
+        // near real-time readers reopened through the writer keep the hooks
+        let mut nrt = writer.get_reader(true, false).unwrap();
+        nrt.set_segment_factory(Arc::clone(&factory) as Arc<dyn PerSegmentReaderFactory<_, _>>);
+        let mut opened = take_events();
+        opened.sort();
+        assert_eq!(opened, vec!["open _0".to_string(), "open _1".to_string()]);
+        writer.add_document(keyword_doc("c")).unwrap();
+        let reopened = nrt.open_if_changed(None).unwrap().unwrap();
+        assert_eq!(reopened.readers.len(), 3);
+        assert_eq!(take_events(), vec!["open _2".to_string()]);
+        drop(nrt);
+        assert!(take_events().is_empty());
+    }
+
+    #[test]
+    fn test_reopen_after_merge() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(IndexWriterConfig::default());
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let writer = IndexWriter::new(Arc::clone(&directory), config).unwrap();
+
+        // one large segment followed by three small ones
+        for i in 0..200 {
+            writer
+                .add_document(keyword_doc(&format!("big{}", i)))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        for v in &["a", "b", "c"] {
+            writer.add_document(keyword_doc(v)).unwrap();
+            writer.commit().unwrap();
+        }
+
+        let old = FSReader::open(Arc::clone(&directory)).unwrap();
+        assert_eq!(old.readers.len(), 4);
+        assert!(old.open_if_changed(None).unwrap().is_none());
+        let nrt = writer.get_reader(true, false).unwrap();
+
+        // the three small segments are merged into one
+        writer.force_merge(2, true).unwrap();
+        writer.commit().unwrap();
+
+        let new = old.open_if_changed(None).unwrap().unwrap();
+        assert_eq!(new.readers.len(), 2);
+        assert_eq!(new.num_docs(), 203);
+        assert!(new.open_if_changed(None).unwrap().is_none());
+
+        let (survived, merged): (Vec<_>, Vec<_>) = old
+            .readers
+            .iter()
+            .partition(|r| new.readers.iter().any(|n| n.name() == r.name()));
+        assert_eq!(survived.len(), 1);
+        assert_eq!(survived[0].max_docs(), 200);
+        assert!(new.readers.iter().any(|n| Arc::ptr_eq(n, survived[0])));
+        assert_eq!(merged.len(), 3);
+
+        // the merged away readers are only held by the old reader
+        let merged: Vec<_> = merged.into_iter().map(Arc::downgrade).collect();
+        let survived = Arc::downgrade(survived[0]);
+        drop(old);
+        assert!(merged.iter().all(|r| r.upgrade().is_none()));
+        assert!(survived.upgrade().is_some());
+        assert_eq!(search_tag(new, "b").len(), 1);
+
+        // near real-time readers share the surviving segment reader the same way
+        let new_nrt = nrt.open_if_changed(None).unwrap().unwrap();
+        assert_eq!(new_nrt.readers.len(), 2);
+        let big = nrt.readers.iter().find(|r| r.max_docs() == 200).unwrap();
+        assert!(new_nrt.readers.iter().any(|n| Arc::ptr_eq(n, big)));
+        assert!(new_nrt.open_if_changed(None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_term_stats_across_segments() {
+        let (_dir, writer) = new_fs_writer();
+
+        for texts in &[vec!["a b b", "c"], vec!["b", "b c", "a"], vec!["b b b"]] {
+            for text in texts {
+                writer
+                    .add_document(text_doc("body", text, IndexOptions::DocsAndFreqs))
+                    .unwrap();
+            }
+            writer.commit().unwrap();
+        }
+        writer.add_document(keyword_doc("b")).unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let term = Term::new("body".into(), b"b".to_vec());
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 4);
+        let per_segment: i32 = leaves
+            .iter()
+            .map(|l| l.reader.doc_freq(&term).unwrap())
+            .sum();
+        assert_eq!(per_segment, 4);
+        let stats = reader.term_statistics(&term).unwrap();
+        assert_eq!(stats.doc_freq, 4);
+        assert_eq!(stats.total_term_freq, 7);
+        assert_eq!(reader.doc_freq(&term).unwrap(), per_segment);
+        assert_eq!(reader.total_term_freq(&term).unwrap(), 7);
+
+        let missing = Term::new("body".into(), b"z".to_vec());
+        assert_eq!(reader.doc_freq(&missing).unwrap(), 0);
+        assert_eq!(reader.total_term_freq(&missing).unwrap(), 0);
+        // 2 + 1 terms per doc of the first segment, 1 + 2 + 1 and 1 in the others
+        assert_eq!(reader.sum_doc_freq("body").unwrap(), 8);
+
+        // the keyword field doesn't index freqs
+        let tag = Term::new("tag".into(), b"b".to_vec());
+        assert_eq!(reader.doc_freq(&tag).unwrap(), 1);
+        assert_eq!(reader.total_term_freq(&tag).unwrap(), -1);
+    }
+}
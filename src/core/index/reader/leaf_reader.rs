@@ -21,6 +21,7 @@ use core::codec::points::PointValues;
 use core::codec::postings::FieldsProducer;
 use core::codec::stored_fields::StoredFieldsReader;
 use core::codec::term_vectors::TermVectorsReader;
+use core::codec::vectors::VectorReader;
 use core::codec::{
     Codec, CodecFieldsProducer, CodecNormsProducer, CodecPointsReader, CodecStoredFieldsReader,
     CodecTVFields, CodecTVReader,
@@ -78,6 +79,7 @@ pub trait LeafReader {
         self.fields()?.terms(field)
     }
 
+    /// Returns the number of documents of this leaf containing `term`.
     fn doc_freq(&self, term: &Term) -> Result<i32> {
         if let Some(terms) = self.terms(&term.field)? {
             let mut terms_iter = terms.iterator()?;
@@ -152,6 +154,13 @@ pub trait LeafReader {
     /// spatial searches, or None if there are no point fields.
     fn point_values(&self) -> Option<Self::PointsReader>;
 
+    /// Returns the dense vectors of `field`, or None if no document of this leaf has one.
+    ///
+    /// Readers whose docs are remapped, e.g. sorted views, don't expose the vectors.
+    fn vector_values(&self, _field: &str) -> Option<&VectorReader> {
+        None
+    }
+
     /// Expert: Returns a key for this IndexReader, so CachingWrapperFilter can find
     // it again.
     // This key must not have equals()/hashCode() methods, so &quot;equals&quot; means
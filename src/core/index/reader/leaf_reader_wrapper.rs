@@ -19,6 +19,7 @@ use core::codec::points::{IntersectVisitor, PointValues, Relation};
 use core::codec::postings::FieldsProducer;
 use core::codec::stored_fields::StoredFieldsReader;
 use core::codec::term_vectors::TermVectorsReader;
+use core::codec::vectors::VectorReader;
 use core::codec::*;
 use core::codec::{Fields, SeekStatus, TermIterator, Terms};
 use core::codec::{PackedLongDocMap, SorterDocMap};
@@ -232,6 +233,10 @@ impl<D: Directory + 'static, C: Codec> LeafReader for MergeReaderWrapper<D, C> {
         self.reader.point_values()
     }
 
+    fn vector_values(&self, field: &str) -> Option<&VectorReader> {
+        self.reader.vector_values(field)
+    }
+
     fn core_cache_key(&self) -> &str {
         self.reader.core_cache_key()
     }
@@ -614,6 +619,10 @@ impl<T: TermIterator> TermIterator for SortingTermsIterator<T> {
         self.iter.next()
     }
 
+    fn seek_exact(&mut self, text: &[u8]) -> Result<bool> {
+        self.iter.seek_exact(text)
+    }
+
     fn seek_ceil(&mut self, text: &[u8]) -> Result<SeekStatus> {
         self.iter.seek_ceil(text)
     }
@@ -1461,6 +1470,10 @@ impl<T: LeafReader + 'static> LeafReader for SlowCodecReaderWrapper<T> {
         self.reader.point_values()
     }
 
+    fn vector_values(&self, field: &str) -> Option<&VectorReader> {
+        self.reader.vector_values(field)
+    }
+
     /// Expert: Returns a key for this IndexReader, so CachingWrapperFilter can find
     // it again.
     // This key must not have equals()/hashCode() methods, so &quot;equals&quot; means
@@ -31,13 +31,26 @@ mod index_lookup;
 
 pub use self::index_lookup::*;
 
+mod reader_manager;
+
+pub use self::reader_manager::*;
+
+mod codec_report;
+
+pub use self::codec_report::*;
+
 use core::codec::Codec;
 use core::codec::CodecTVFields;
-use core::doc::Document;
+use core::codec::{TermIterator, Terms};
+use core::doc::{Document, Term};
+use core::search::TermStatistics;
 use core::util::DocId;
 
+use error::ErrorKind::IllegalState;
 use error::Result;
 
+use std::convert::TryFrom;
+
 /// `IndexReader` providing an interface for accessing a point-in-time view of an index.
 ///
 /// Any changes made to the index via `IndexWriter` will not be visible until a new
@@ -77,6 +90,70 @@ pub trait IndexReader {
     fn has_deletions(&self) -> bool {
         self.num_deleted_docs() > 0
     }
+
+    /// Returns the statistics of `term` summed over all leaves, each leaf is only sought
+    /// once. `total_term_freq` is -1 if a leaf doesn't index term frequencies.
+    ///
+    /// Callers needing both the doc freq and the total term freq should call this instead
+    /// of `doc_freq` and `total_term_freq`, which seek the leaves again.
+    fn term_statistics(&self, term: &Term) -> Result<TermStatistics> {
+        let mut doc_freq = 0i64;
+        let mut total_term_freq = 0i64;
+        for leaf in self.leaves() {
+            if let Some(terms) = leaf.reader.terms(&term.field)? {
+                let mut terms_enum = terms.iterator()?;
+                if terms_enum.seek_exact(&term.bytes)? {
+                    doc_freq += i64::from(terms_enum.doc_freq()?);
+                    let freq = terms_enum.total_term_freq()?;
+                    if freq == -1 || total_term_freq == -1 {
+                        total_term_freq = -1;
+                    } else {
+                        total_term_freq += freq;
+                    }
+                }
+            }
+        }
+        Ok(TermStatistics::new(
+            term.bytes.clone(),
+            doc_freq,
+            total_term_freq,
+        ))
+    }
+
+    /// Returns the number of documents containing `term`.
+    fn doc_freq(&self, term: &Term) -> Result<i32> {
+        let doc_freq = self.term_statistics(term)?.doc_freq;
+        match i32::try_from(doc_freq) {
+            Ok(doc_freq) => Ok(doc_freq),
+            Err(_) => bail!(IllegalState(format!(
+                "doc freq {} of {:?} overflows i32",
+                doc_freq, term
+            ))),
+        }
+    }
+
+    /// Returns the number of occurrences of `term` across all documents, or -1 if
+    /// term frequencies are not indexed.
+    fn total_term_freq(&self, term: &Term) -> Result<i64> {
+        Ok(self.term_statistics(term)?.total_term_freq)
+    }
+
+    /// Returns the sum of the doc freqs of all terms of `field`, or -1 if a leaf doesn't
+    /// track it.
+    fn sum_doc_freq(&self, field: &str) -> Result<i64> {
+        let mut sum = 0;
+        for leaf in self.leaves() {
+            if let Some(terms) = leaf.reader.terms(field)? {
+                let freq = terms.sum_doc_freq()?;
+                if freq == -1 {
+                    return Ok(-1);
+                }
+                sum += freq;
+            }
+        }
+        Ok(sum)
+    }
+
     fn leaf_reader_for_doc(&self, doc: DocId) -> LeafReaderContext<'_, Self::Codec> {
         let leaves = self.leaves();
         let size = leaves.len();
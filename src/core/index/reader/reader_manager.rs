@@ -0,0 +1,214 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::Codec;
+use core::index::merge::{MergePolicy, MergeScheduler};
+use core::index::reader::{IndexReader, StandardDirectoryReader};
+use core::index::writer::IndexWriter;
+use core::search::{ReferenceManager, ReferenceManagerBase, RefreshListener};
+use core::store::directory::Directory;
+
+use error::ErrorKind::IllegalState;
+use error::Result;
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+/// Utility class to safely share `IndexReader` instances across multiple threads,
+/// while periodically reopening them.
+///
+/// A reader returned by `acquire` stays open until every thread acquiring it has
+/// released it, even if a later `maybe_refresh` swapped in a newer reader, so that
+/// searches running on the old point in time are not affected.
+///
+/// The manager hands out readers rather than searchers, wrap them with a searcher per
+/// request or use `SearcherManager` to share the searchers themselves. The reference
+/// counts only track the `acquire` and `release` calls, the reader itself is closed once
+/// the last `Arc` to it is dropped.
+pub struct ReaderManager<C: Codec, T> {
+    pub manager_base: ReferenceManagerBase<dyn IndexReader<Codec = C>>,
+    refresh_listener: Option<T>,
+    // references per reader address, the current reader holds one for the manager
+    ref_counts: Mutex<HashMap<usize, u32>>,
+}
+
+impl<C: Codec, T> ReaderManager<C, T> {
+    pub fn from_writer<D, MS, MP>(
+        writer: &IndexWriter<D, C, MS, MP>,
+        apply_all_deletes: bool,
+        write_all_deletes: bool,
+        refresh_listener: Option<T>,
+    ) -> Result<Self>
+    where
+        D: Directory + Send + Sync + 'static,
+        MS: MergeScheduler,
+        MP: MergePolicy,
+    {
+        let reader = writer.get_reader(apply_all_deletes, write_all_deletes)?;
+        Ok(Self::new(reader, refresh_listener))
+    }
+
+    pub fn new<D, MS, MP>(
+        reader: StandardDirectoryReader<D, C, MS, MP>,
+        refresh_listener: Option<T>,
+    ) -> Self
+    where
+        D: Directory + Send + Sync + 'static,
+        MS: MergeScheduler,
+        MP: MergePolicy,
+    {
+        let current: Arc<dyn IndexReader<Codec = C>> = Arc::new(reader);
+        let mut ref_counts = HashMap::new();
+        ref_counts.insert(reader_key(current.as_ref()), 1);
+        ReaderManager {
+            manager_base: ReferenceManagerBase::new(current),
+            refresh_listener,
+            ref_counts: Mutex::new(ref_counts),
+        }
+    }
+}
+
+fn reader_key<C: Codec>(reader: &dyn IndexReader<Codec = C>) -> usize {
+    reader as *const dyn IndexReader<Codec = C> as *const u8 as usize
+}
+
+impl<C, T, RL> ReferenceManager<dyn IndexReader<Codec = C>, RL> for ReaderManager<C, T>
+where
+    C: Codec,
+    T: Deref<Target = RL>,
+    RL: RefreshListener,
+{
+    fn base(&self) -> &ReferenceManagerBase<dyn IndexReader<Codec = C>> {
+        &self.manager_base
+    }
+
+    fn refresh_listener(&self) -> Option<&RL> {
+        self.refresh_listener.as_ref().map(|r| r.deref())
+    }
+
+    fn dec_ref(&self, reference: &dyn IndexReader<Codec = C>) -> Result<()> {
+        let key = reader_key(reference);
+        let mut ref_counts = self.ref_counts.lock()?;
+        let remaining = match ref_counts.get_mut(&key) {
+            Some(count) => {
+                *count -= 1;
+                *count
+            }
+            None => bail!(IllegalState(
+                "reader was released more often than it was acquired".into()
+            )),
+        };
+        if remaining == 0 {
+            ref_counts.remove(&key);
+        }
+        Ok(())
+    }
+
+    fn refresh_if_needed(
+        &self,
+        reference_to_refresh: &Arc<dyn IndexReader<Codec = C>>,
+    ) -> Result<Option<Arc<dyn IndexReader<Codec = C>>>> {
+        if let Some(reader) = reference_to_refresh.refresh()? {
+            let reader: Arc<dyn IndexReader<Codec = C>> = Arc::from(reader);
+            // the reference of the manager, released when the reader is swapped out
+            self.ref_counts
+                .lock()?
+                .insert(reader_key(reader.as_ref()), 1);
+            Ok(Some(reader))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn try_inc_ref(&self, reference: &Arc<dyn IndexReader<Codec = C>>) -> Result<bool> {
+        let mut ref_counts = self.ref_counts.lock()?;
+        match ref_counts.get_mut(&reader_key(reference.as_ref())) {
+            Some(count) => {
+                *count += 1;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn ref_count(&self, reference: &dyn IndexReader<Codec = C>) -> u32 {
+        self.ref_counts
+            .lock()
+            .unwrap()
+            .get(&reader_key(reference))
+            .cloned()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use core::codec::CodecEnum;
+    use core::doc::Term;
+    use core::test_util::{keyword_doc, new_fs_writer, NoListener};
+
+    fn doc_freq(reader: &dyn IndexReader<Codec = CodecEnum>, value: &str) -> i32 {
+        let term = Term::new("tag".into(), value.as_bytes().to_vec());
+        reader.doc_freq(&term).unwrap()
+    }
+
+    #[test]
+    fn test_acquire_release_refresh() {
+        let (_dir, writer) = new_fs_writer();
+        writer.add_document(keyword_doc("a")).unwrap();
+        writer.commit().unwrap();
+
+        let manager: ReaderManager<CodecEnum, Arc<NoListener>> =
+            ReaderManager::from_writer(&writer, true, false, None).unwrap();
+        let old = manager.acquire().unwrap();
+        assert_eq!(old.num_docs(), 1);
+        // the reference of the manager and the acquired one
+        assert_eq!(manager.ref_count(&*old), 2);
+
+        // nothing changed, the current reader is kept
+        assert!(manager.maybe_refresh().unwrap());
+        let same = manager.acquire().unwrap();
+        assert!(Arc::ptr_eq(&old, &same));
+        manager.release(&*same).unwrap();
+
+        writer.add_document(keyword_doc("b")).unwrap();
+        writer.commit().unwrap();
+        assert!(manager.maybe_refresh().unwrap());
+        let new = manager.acquire().unwrap();
+        assert!(!Arc::ptr_eq(&old, &new));
+        assert_eq!(manager.ref_count(&*old), 1);
+        assert_eq!(manager.ref_count(&*new), 2);
+        assert_eq!(new.num_docs(), 2);
+        assert_eq!(doc_freq(&*new, "b"), 1);
+
+        // the old reader still serves its point in time until released
+        assert_eq!(old.num_docs(), 1);
+        assert_eq!(doc_freq(&*old, "a"), 1);
+        assert_eq!(doc_freq(&*old, "b"), 0);
+        manager.release(&*old).unwrap();
+        assert_eq!(manager.ref_count(&*old), 0);
+        assert!(manager.release(&*old).is_err());
+        manager.release(&*new).unwrap();
+        assert_eq!(manager.ref_count(&*new), 1);
+
+        manager.close().unwrap();
+        assert_eq!(manager.ref_count(&*new), 0);
+        assert!(manager.acquire().is_err());
+        assert!(manager.maybe_refresh().is_err());
+        // readers acquired before closing stay usable
+        assert_eq!(new.num_docs(), 2);
+    }
+}
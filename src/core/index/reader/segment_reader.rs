@@ -23,18 +23,20 @@ use thread_local::{CachedThreadLocal, ThreadLocal};
 
 use core::codec::doc_values::{
     BinaryDocValues, BinaryDocValuesProvider, DocValuesFormat, DocValuesProducer,
-    DocValuesProviderEnum, NumericDocValues, NumericDocValuesProvider, SortedDocValues,
-    SortedDocValuesProvider, SortedNumericDocValues, SortedNumericDocValuesProvider,
-    SortedSetDocValues, SortedSetDocValuesProvider,
+    DocValuesProviderEnum, NumericDocValues, NumericDocValuesProvider, NumericEncoding,
+    SortedDocValues, SortedDocValuesProvider, SortedNumericDocValues,
+    SortedNumericDocValuesProvider, SortedSetDocValues, SortedSetDocValuesProvider,
 };
 
 use core::codec::field_infos::{FieldInfo, FieldInfos, FieldInfosFormat};
 use core::codec::norms::{NormsFormat, NormsProducer};
 use core::codec::points::PointsFormat;
+use core::codec::postings::blocktree::FSTLoadMode;
 use core::codec::postings::PostingsFormat;
 use core::codec::segment_infos::{SegmentCommitInfo, SegmentInfo, SegmentReadState};
 use core::codec::stored_fields::{StoredFieldsFormat, StoredFieldsReader};
 use core::codec::term_vectors::{TermVectorsFormat, TermVectorsReader};
+use core::codec::vectors::{DenseVectorsReader, VectorReader, VectorsFormat};
 use core::codec::{
     Codec, CodecFieldsProducer, CodecNormsProducer, CodecPointsReader, CodecStoredFieldsReader,
     CodecTVFields, CodecTVReader, CompoundFormat, LiveDocsFormat, Lucene50CompoundReader,
@@ -63,6 +65,7 @@ pub struct SegmentCoreReaders<D: Directory, C: Codec> {
     /// in the case of DV updates, SR may hold a newer version.
     pub core_field_infos: Arc<FieldInfos>,
     pub points_reader: Option<Arc<CodecPointsReader<C>>>,
+    pub vectors_reader: Option<Arc<DenseVectorsReader>>,
     pub core_dropped_listeners: Mutex<Vec<Deferred>>,
     pub core_cache_key: String,
 }
@@ -72,6 +75,7 @@ impl<D: Directory, C: Codec> SegmentCoreReaders<D, C> {
         dir: &Arc<D>,
         si: &SegmentInfo<D, C>,
         ctx: &IOContext,
+        fst_load_mode: FSTLoadMode,
     ) -> Result<SegmentCoreReaders<D, C>> {
         let codec = si.codec();
 
@@ -98,13 +102,14 @@ impl<D: Directory, C: Codec> SegmentCoreReaders<D, C> {
             "",
             &ctx,
         )?);
-        let segment_read_state = SegmentReadState::new(
+        let mut segment_read_state = SegmentReadState::new(
             cfs_dir.clone(),
             si,
             core_field_infos.clone(),
             ctx,
             String::new(),
         );
+        segment_read_state.fst_load_mode = fst_load_mode;
 
         let norms_producer = if core_field_infos.has_norms {
             Some(codec.norms_format().norms_producer(&segment_read_state)?)
@@ -139,6 +144,13 @@ impl<D: Directory, C: Codec> SegmentCoreReaders<D, C> {
         } else {
             None
         };
+        let vectors_reader = if core_field_infos.has_vector_values {
+            Some(Arc::new(
+                codec.vectors_format().fields_reader(&segment_read_state)?,
+            ))
+        } else {
+            None
+        };
         // TODO process norms_producers/store_fields_reader/term vectors
 
         Ok(SegmentCoreReaders {
@@ -151,6 +163,7 @@ impl<D: Directory, C: Codec> SegmentCoreReaders<D, C> {
             cfs_reader,
             core_field_infos,
             points_reader,
+            vectors_reader,
             core_dropped_listeners: Mutex::new(vec![]),
             core_cache_key: format!("{}@{}", si.name, id2str(&random_id())),
         })
@@ -470,6 +483,12 @@ impl DocValuesProducer for SegmentDocValues {
     fn get_merge_instance(&self) -> Result<Box<dyn DocValuesProducer>> {
         Ok(Box::new(self.clone()))
     }
+
+    fn numeric_encoding(&self, field_info: &FieldInfo) -> Option<NumericEncoding> {
+        self.dv_producers_by_field
+            .get(&field_info.name)
+            .and_then(|dv_producer| dv_producer.numeric_encoding(field_info))
+    }
 }
 
 pub type ThreadLocalDocValueProducer = ThreadLocal<Arc<dyn DocValuesProducer>>;
@@ -679,7 +698,21 @@ impl<D: Directory + 'static, C: Codec> SegmentReader<D, C> {
     /// @throws CorruptIndexException if the index is corrupt
     /// @throws IOException if there is a low-level IO error
     pub fn open(si: &Arc<SegmentCommitInfo<D, C>>, ctx: &IOContext) -> Result<SegmentReader<D, C>> {
-        let core = Arc::new(SegmentCoreReaders::new(&si.info.directory, &si.info, ctx)?);
+        Self::open_with_fst_load_mode(si, ctx, FSTLoadMode::default())
+    }
+
+    /// Like `open`, loading the terms index as `fst_load_mode` says.
+    pub fn open_with_fst_load_mode(
+        si: &Arc<SegmentCommitInfo<D, C>>,
+        ctx: &IOContext,
+        fst_load_mode: FSTLoadMode,
+    ) -> Result<SegmentReader<D, C>> {
+        let core = Arc::new(SegmentCoreReaders::new(
+            &si.info.directory,
+            &si.info,
+            ctx,
+            fst_load_mode,
+        )?);
         let codec = si.info.codec();
         let num_docs = si.info.max_doc() - si.del_count();
         let field_infos = if !si.has_field_updates() {
@@ -760,6 +793,13 @@ impl<D: Directory + 'static, C: Codec> SegmentReader<D, C> {
         }
     }
 
+    /// Returns the doc values producer of the current thread, `None` if no field of this
+    /// segment has doc values.
+    pub fn doc_values_producer(&self) -> Result<Option<&Arc<dyn DocValuesProducer>>> {
+        self.init_local_doc_values_producer()?;
+        Ok(self.doc_values_producer.get())
+    }
+
     fn init_local_doc_values_producer(&self) -> Result<()> {
         if self.field_infos.has_doc_values {
             if self.doc_values_producer.get().is_some() {
@@ -1117,6 +1157,13 @@ where
         self.core.points_reader.clone()
     }
 
+    fn vector_values(&self, field: &str) -> Option<&VectorReader> {
+        self.core
+            .vectors_reader
+            .as_ref()
+            .and_then(|reader| reader.field(field))
+    }
+
     fn core_cache_key(&self) -> &str {
         // use segment name as unique segment cache key
         &self.core.core_cache_key
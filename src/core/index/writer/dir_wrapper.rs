@@ -1,11 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::io;
 use std::sync::{Arc, Mutex};
 
 use core::store::directory::{
     Directory, FilterDirectory, LockValidatingDirectoryWrapper, TrackingDirectoryWrapper,
 };
-use core::store::io::{IndexInput, IndexOutput, RateLimitIndexOutput};
+use core::store::io::{DataOutput, IndexInput, IndexOutput, RateLimitIndexOutput};
 use core::store::{IOContext, RateLimiter};
 
 use error::{ErrorKind::IllegalState, Result};
@@ -18,24 +19,152 @@ pub type TrackingTmpDirectory<D> = TrackingTmpOutputDirectoryWrapper<
     >,
 >;
 
+/// The directory the `IndexWriter` writes its transient files into, see
+/// `IndexWriterConfig#set_temp_directory`.
+///
+/// This is the object safe part of `Directory` the temp files need, implemented for
+/// every directory.
+pub trait TempDirectory: fmt::Display + Send + Sync {
+    fn create_temp_output(
+        &self,
+        prefix: &str,
+        suffix: &str,
+        ctx: &IOContext,
+    ) -> Result<Box<dyn TempDirectoryOutput>>;
+
+    fn open_input(&self, name: &str, ctx: &IOContext) -> Result<Box<dyn IndexInput>>;
+
+    fn file_length(&self, name: &str) -> Result<i64>;
+
+    fn delete_file(&self, name: &str) -> Result<()>;
+}
+
+impl<D> TempDirectory for D
+where
+    D: Directory + Send + Sync,
+    D::TempOutput: Send + 'static,
+{
+    fn create_temp_output(
+        &self,
+        prefix: &str,
+        suffix: &str,
+        ctx: &IOContext,
+    ) -> Result<Box<dyn TempDirectoryOutput>> {
+        let output = Directory::create_temp_output(self, prefix, suffix, ctx)?;
+        Ok(Box::new(output))
+    }
+
+    fn open_input(&self, name: &str, ctx: &IOContext) -> Result<Box<dyn IndexInput>> {
+        Directory::open_input(self, name, ctx)
+    }
+
+    fn file_length(&self, name: &str) -> Result<i64> {
+        Directory::file_length(self, name)
+    }
+
+    fn delete_file(&self, name: &str) -> Result<()> {
+        Directory::delete_file(self, name)
+    }
+}
+
+/// The output of a file in a `TempDirectory`.
+pub trait TempDirectoryOutput: io::Write + Send {
+    fn name(&self) -> &str;
+
+    fn file_pointer(&self) -> i64;
+
+    fn checksum(&self) -> Result<i64>;
+}
+
+impl<O: IndexOutput + Send> TempDirectoryOutput for O {
+    fn name(&self) -> &str {
+        IndexOutput::name(self)
+    }
+
+    fn file_pointer(&self) -> i64 {
+        IndexOutput::file_pointer(self)
+    }
+
+    fn checksum(&self) -> Result<i64> {
+        IndexOutput::checksum(self)
+    }
+}
+
+/// Writes the transient files of a flush or merge to the temp directory of the
+/// `IndexWriterConfig`, or to the wrapped directory if there is none, and deletes the
+/// ones left over once it is dropped.
+///
+/// The outputs created by `create_output` are temp files standing in for the named
+/// files, they are moved to the wrapped directory by `rename_temp_files`.
 pub struct TrackingTmpOutputDirectoryWrapper<D: Directory> {
     directory: Arc<D>,
+    // where the temp files are written instead of `directory` if set
+    temp_directory: Option<Arc<dyn TempDirectory>>,
     pub file_names: Mutex<HashMap<String, String>>,
+    // the files created by `create_temp_output` and not yet deleted
+    temp_file_names: Mutex<HashSet<String>>,
 }
 
 impl<D: Directory> TrackingTmpOutputDirectoryWrapper<D> {
-    pub fn new(directory: Arc<D>) -> Self {
+    pub fn new(directory: Arc<D>, temp_directory: Option<Arc<dyn TempDirectory>>) -> Self {
         Self {
             directory,
+            temp_directory,
             file_names: Mutex::new(HashMap::new()),
+            temp_file_names: Mutex::new(HashSet::new()),
         }
     }
 
     pub fn delete_temp_files(&self) {
-        for file_name in self.file_names.lock().unwrap().values() {
-            if let Err(e) = self.delete_file(file_name.as_str()) {
-                warn!("delete file '{}' failed by '{:?}'", file_name, e);
+        if let Ok(mut file_names) = self.file_names.lock() {
+            for (_, file_name) in file_names.drain() {
+                if let Err(e) = self.delete_temp_file(&file_name) {
+                    warn!("delete file '{}' failed by '{:?}'", file_name, e);
+                }
+            }
+        }
+        if let Ok(mut temp_file_names) = self.temp_file_names.lock() {
+            for file_name in temp_file_names.drain() {
+                if let Err(e) = self.delete_temp_file(&file_name) {
+                    warn!("delete file '{}' failed by '{:?}'", file_name, e);
+                }
+            }
+        }
+    }
+
+    fn is_temp_file(&self, name: &str) -> bool {
+        self.temp_file_names.lock().unwrap().contains(name)
+    }
+
+    /// Moves the temp files to the files they were created for in the wrapped directory,
+    /// copying them over if they were written to a separate temp directory.
+    pub fn rename_temp_files(&self) -> Result<()> {
+        let file_names: Vec<(String, String)> = self
+            .file_names
+            .lock()?
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        for (name, temp_name) in file_names {
+            if let Some(ref temp_directory) = self.temp_directory {
+                let mut input = temp_directory.open_input(&temp_name, &IOContext::READ_ONCE)?;
+                let mut output = self.directory.create_output(&name, &IOContext::Default)?;
+                let length = input.len() as usize;
+                output.copy_bytes(input.as_mut(), length)?;
+                temp_directory.delete_file(&temp_name)?;
+            } else {
+                self.directory.rename(&temp_name, &name)?;
             }
+            self.file_names.lock()?.remove(&name);
+        }
+        Ok(())
+    }
+
+    fn delete_temp_file(&self, temp_name: &str) -> Result<()> {
+        if let Some(ref temp_directory) = self.temp_directory {
+            temp_directory.delete_file(temp_name)
+        } else {
+            self.directory.delete_file(temp_name)
         }
     }
 }
@@ -49,8 +178,8 @@ impl<D: Directory> FilterDirectory for TrackingTmpOutputDirectoryWrapper<D> {
 }
 
 impl<D: Directory> Directory for TrackingTmpOutputDirectoryWrapper<D> {
-    type IndexOutput = D::TempOutput;
-    type TempOutput = D::TempOutput;
+    type IndexOutput = TempIndexOutput<D::TempOutput>;
+    type TempOutput = TempIndexOutput<D::TempOutput>;
     fn create_output(&self, name: &str, ctx: &IOContext) -> Result<Self::IndexOutput> {
         let mut guard = self.file_names.lock().unwrap();
         if guard.contains_key(name) {
@@ -60,17 +189,34 @@ impl<D: Directory> Directory for TrackingTmpOutputDirectoryWrapper<D> {
             )));
         }
 
-        self.dir().create_temp_output(name, "", ctx).map(|out| {
-            guard.insert(name.to_string(), out.name().to_string());
-            out
-        })
+        let out = if let Some(ref temp_directory) = self.temp_directory {
+            TempIndexOutput::Temp(temp_directory.create_temp_output(name, "", ctx)?)
+        } else {
+            TempIndexOutput::Index(self.dir().create_temp_output(name, "", ctx)?)
+        };
+        guard.insert(name.to_string(), IndexOutput::name(&out).to_string());
+        Ok(out)
     }
 
     fn open_input(&self, name: &str, ctx: &IOContext) -> Result<Box<dyn IndexInput>> {
-        if let Some(n) = self.file_names.lock().unwrap().get(name) {
-            self.dir().open_input(n, ctx)
+        let temp_name = if let Some(n) = self.file_names.lock().unwrap().get(name) {
+            n.clone()
+        } else if self.is_temp_file(name) {
+            name.to_string()
         } else {
             bail!(IllegalState(format!("input file '{}' not found!", name)));
+        };
+        if let Some(ref temp_directory) = self.temp_directory {
+            temp_directory.open_input(&temp_name, ctx)
+        } else {
+            self.dir().open_input(&temp_name, ctx)
+        }
+    }
+
+    fn file_length(&self, name: &str) -> Result<i64> {
+        match self.temp_directory {
+            Some(ref temp_directory) if self.is_temp_file(name) => temp_directory.file_length(name),
+            _ => self.dir().file_length(name),
         }
     }
 
@@ -80,7 +226,30 @@ impl<D: Directory> Directory for TrackingTmpOutputDirectoryWrapper<D> {
         suffix: &str,
         ctx: &IOContext,
     ) -> Result<Self::TempOutput> {
-        self.directory.create_temp_output(prefix, suffix, ctx)
+        let out = if let Some(ref temp_directory) = self.temp_directory {
+            TempIndexOutput::Temp(temp_directory.create_temp_output(prefix, suffix, ctx)?)
+        } else {
+            TempIndexOutput::Index(self.dir().create_temp_output(prefix, suffix, ctx)?)
+        };
+        self.temp_file_names
+            .lock()?
+            .insert(IndexOutput::name(&out).to_string());
+        Ok(out)
+    }
+
+    fn delete_file(&self, name: &str) -> Result<()> {
+        if self.temp_file_names.lock()?.remove(name) {
+            self.delete_temp_file(name)
+        } else {
+            self.dir().delete_file(name)
+        }
+    }
+}
+
+impl<D: Directory> Drop for TrackingTmpOutputDirectoryWrapper<D> {
+    fn drop(&mut self) {
+        // the temp files are left over if the flush failed or was aborted
+        self.delete_temp_files();
     }
 }
 
@@ -94,6 +263,54 @@ impl<D: Directory> fmt::Display for TrackingTmpOutputDirectoryWrapper<D> {
     }
 }
 
+/// The output of a temp file, written to either the index directory or the temp directory
+/// of the `IndexWriterConfig`.
+pub enum TempIndexOutput<O: IndexOutput> {
+    Index(O),
+    Temp(Box<dyn TempDirectoryOutput>),
+}
+
+impl<O: IndexOutput> IndexOutput for TempIndexOutput<O> {
+    fn name(&self) -> &str {
+        match self {
+            TempIndexOutput::Index(o) => o.name(),
+            TempIndexOutput::Temp(o) => o.name(),
+        }
+    }
+
+    fn file_pointer(&self) -> i64 {
+        match self {
+            TempIndexOutput::Index(o) => o.file_pointer(),
+            TempIndexOutput::Temp(o) => o.file_pointer(),
+        }
+    }
+
+    fn checksum(&self) -> Result<i64> {
+        match self {
+            TempIndexOutput::Index(o) => o.checksum(),
+            TempIndexOutput::Temp(o) => o.checksum(),
+        }
+    }
+}
+
+impl<O: IndexOutput> DataOutput for TempIndexOutput<O> {}
+
+impl<O: IndexOutput> io::Write for TempIndexOutput<O> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            TempIndexOutput::Index(o) => o.write(buf),
+            TempIndexOutput::Temp(o) => o.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            TempIndexOutput::Index(o) => o.flush(),
+            TempIndexOutput::Temp(o) => o.flush(),
+        }
+    }
+}
+
 pub struct RateLimitFilterDirectory<D: Directory, RL: RateLimiter + ?Sized> {
     dir: Arc<D>,
     // reference to IndexWriter.rate_limiter
@@ -175,3 +392,47 @@ where
         write!(f, "RateLimitFilterDirectory({})", self.dir.as_ref())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TrackingTmpOutputDirectoryWrapper;
+    use core::store::directory::{Directory, FSDirectory, MmapDirectory};
+    use core::store::io::{DataInput, DataOutput, IndexOutput};
+    use core::store::IOContext;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_temp_outputs_go_to_temp_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let temp_directory = Arc::new(MmapDirectory::new(&temp_dir.path()).unwrap());
+        let wrapper = TrackingTmpOutputDirectoryWrapper::new(directory, Some(temp_directory));
+
+        // e.g. the points the bkd writer spills while sorting them
+        let create = |suffix: &str| -> String {
+            let mut output = wrapper
+                .create_temp_output("_0", suffix, &IOContext::Default)
+                .unwrap();
+            output.write_int(7).unwrap();
+            output.flush().unwrap();
+            output.name().to_string()
+        };
+        let name = create("bkd_dim0");
+        assert!(temp_dir.path().join(&name).exists());
+        assert!(!dir.path().join(&name).exists());
+        assert_eq!(wrapper.file_length(&name).unwrap(), 4);
+        let mut input = wrapper.open_input(&name, &IOContext::READ_ONCE).unwrap();
+        assert_eq!(input.read_int().unwrap(), 7);
+        drop(input);
+        wrapper.delete_file(&name).unwrap();
+        assert!(!temp_dir.path().join(&name).exists());
+
+        // the temp files left over are deleted with the wrapper
+        let name = create("bkd_dim1");
+        assert!(temp_dir.path().join(&name).exists());
+        drop(wrapper);
+        assert!(!temp_dir.path().join(&name).exists());
+    }
+}
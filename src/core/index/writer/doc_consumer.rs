@@ -11,7 +11,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::analysis::TokenStream;
+use core::analysis::{Analyzer, StringTokenStream, TokenStream};
 use core::codec::doc_values::*;
 use core::codec::field_infos::{
     FieldInfo, FieldInfosBuilder, FieldInfosFormat, FieldInvertState, FieldNumbersRef,
@@ -26,6 +26,7 @@ use core::codec::postings::{
 use core::codec::segment_infos::{SegmentReadState, SegmentWriteState};
 use core::codec::stored_fields::StoredFieldsConsumer;
 use core::codec::term_vectors::TermVectorsConsumer;
+use core::codec::vectors::{VectorValuesWriter, VectorsFormat};
 use core::codec::Codec;
 use core::doc::{DocValuesType, FieldType, Fieldable, IndexOptions};
 use core::index::merge::MergePolicy;
@@ -46,6 +47,7 @@ use core::index::merge::MergeScheduler;
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashSet};
+use std::io::{Cursor, Read};
 use std::sync::Arc;
 
 const MAX_FIELD_COUNT: usize = 65536;
@@ -168,6 +170,36 @@ where
         Ok(())
     }
 
+    /// Writes all buffered dense vectors.
+    fn write_vectors<DW: Directory>(
+        &mut self,
+        state: &SegmentWriteState<D, DW, C>,
+        sort_map: Option<&PackedLongDocMap>,
+    ) -> Result<()> {
+        let mut vectors_writer = None;
+        for per_field in &mut self.field_hash {
+            if let Some(ref values_writer) = per_field.vector_values_writer {
+                debug_assert!(per_field.field_info().vector_dimension > 0);
+                if vectors_writer.is_none() {
+                    // lazy init
+                    vectors_writer = Some(
+                        state
+                            .segment_info
+                            .codec()
+                            .vectors_format()
+                            .fields_writer(state)?,
+                    );
+                }
+                values_writer.flush(sort_map, vectors_writer.as_mut().unwrap())?;
+            }
+            per_field.vector_values_writer = None;
+        }
+        if let Some(ref mut writer) = vectors_writer {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+
     /// Writes all buffered doc values (called from {@link #flush}).
     fn write_doc_values<DW: Directory>(
         &mut self,
@@ -316,6 +348,13 @@ where
             self.index_point(per_field.unwrap(), field, doc_state)?;
         }
 
+        if field.field_type().vector_dimension > 0 {
+            if per_field.is_none() {
+                per_field = Some(self.get_or_add_field(field.name(), field.field_type(), false)?);
+            }
+            self.index_vector(per_field.unwrap(), field, doc_state)?;
+        }
+
         Ok(field_count)
     }
 
@@ -549,6 +588,38 @@ where
             )
     }
 
+    /// Called from process_document to index one field's dense vector
+    fn index_vector(
+        &mut self,
+        field_idx: usize,
+        field: &impl Fieldable,
+        doc_state: &DocState,
+    ) -> Result<()> {
+        let value = match field.binary_value() {
+            Some(value) => value,
+            None => bail!(IllegalArgument(format!(
+                "vector field '{}' has no binary vector value",
+                field.name()
+            ))),
+        };
+        let per_field = &mut self.field_hash[field_idx];
+        let field_type = field.field_type();
+        // this setter will throw IllegalArgExc if the dimension or similarity function
+        // were already set to something different:
+        per_field
+            .field_info_mut()
+            .set_vector_dimension(field_type.vector_dimension, field_type.vector_similarity)?;
+
+        if per_field.vector_values_writer.is_none() {
+            per_field.vector_values_writer = Some(VectorValuesWriter::new(per_field.field_info()));
+        }
+        per_field
+            .vector_values_writer
+            .as_mut()
+            .unwrap()
+            .add_value(doc_state.doc_id, value)
+    }
+
     fn get_per_field_index(&mut self, name: &str) -> Option<usize> {
         for (idx, pf) in self.field_hash.iter().enumerate() {
             if pf.field_info().name.as_str() == name {
@@ -673,6 +744,7 @@ where
 
         self.write_doc_values(state, sort_map.as_ref().map(|m| m.as_ref()))?;
         self.write_points(state, sort_map.as_ref().map(|m| m.as_ref()))?;
+        self.write_vectors(state, sort_map.as_ref().map(|m| m.as_ref()))?;
 
         // it's possible all docs hit non-aborting exceptions...
         self.stored_fields_consumer.finish(max_doc)?;
@@ -749,11 +821,36 @@ pub struct PerField<T: TermsHashPerField> {
     doc_values_writer: Option<DocValuesWriterEnum>,
     // Non-null if this field ever had points in this segment:
     point_values_writer: Option<PointValuesWriter>,
+    // Non-null if this field ever had dense vectors in this segment:
+    vector_values_writer: Option<VectorValuesWriter>,
     /// We use this to know when a PerField is seen for the
     /// first time in the current document
     field_gen: i64,
     norms: Option<NormValuesWriter>,
     invert: bool,
+    // reused to invert the untokenized string values of the field
+    stream: StringTokenStream,
+    // the analysis chain of the field, built by the analyzer of the writer on the first
+    // value analyzed and reused for the next ones
+    analyzed_stream: Option<Box<dyn TokenStream>>,
+}
+
+// points the analysis chain `stream` to `text`, building the chain with `analyzer` if
+// there is none yet or it has no tokenizer to read a new value
+fn analyzed_stream<'a>(
+    stream: &'a mut Option<Box<dyn TokenStream>>,
+    analyzer: &dyn Analyzer,
+    field: &str,
+    text: &str,
+) -> Result<&'a mut dyn TokenStream> {
+    let mut reader: Option<Box<dyn Read>> = Some(Box::new(Cursor::new(text.as_bytes().to_vec())));
+    if let Some(tokenizer) = stream.as_mut().and_then(|s| s.tokenizer_mut()) {
+        tokenizer.set_reader(reader.take().unwrap());
+    }
+    if let Some(reader) = reader {
+        *stream = Some(analyzer.token_stream(field, reader)?);
+    }
+    Ok(stream.as_mut().unwrap().as_mut())
 }
 
 impl<T: TermsHashPerField> PerField<T> {
@@ -771,9 +868,12 @@ impl<T: TermsHashPerField> PerField<T> {
             term_hash_per_field,
             doc_values_writer: None,
             point_values_writer: None,
+            vector_values_writer: None,
             field_gen: -1,
             norms: None,
             invert,
+            stream: StringTokenStream::new(String::new()),
+            analyzed_stream: None,
         };
 
         if invert {
@@ -855,6 +955,7 @@ impl<T: TermsHashPerField> PerField<T> {
             // First time we're seeing this field (indexed) in
             // this document:
             self.invert_state.reset();
+            self.invert_state.discount_overlaps = field.field_type().discount_overlaps;
         }
 
         let index_options = field.field_type().index_options;
@@ -883,7 +984,24 @@ impl<T: TermsHashPerField> PerField<T> {
         // write the field name to the infostream when we fail. We expect some caller to
         // eventually deal with the real exception, so we don't want any 'catch' clauses,
         // but rather a finally that takes note of the problem.
-        let mut token_stream: Box<dyn TokenStream> = field.token_stream()?;
+        //
+        // Untokenized string values and the values analyzed by the writer reuse the
+        // streams of the field rather than creating one per value.
+        let mut owned_stream = None;
+        let token_stream: &mut dyn TokenStream = if field.reuse_token_stream(&mut self.stream) {
+            &mut self.stream
+        } else if let (Some(analyzer), Some(text)) =
+            (doc_state.analyzer.as_ref(), field.analyzed_value())
+        {
+            analyzed_stream(
+                &mut self.analyzed_stream,
+                analyzer.as_ref(),
+                field.name(),
+                text,
+            )?
+        } else {
+            owned_stream.get_or_insert(field.token_stream()?).as_mut()
+        };
         token_stream.reset()?;
 
         self.term_hash_per_field
@@ -953,7 +1071,7 @@ impl<T: TermsHashPerField> PerField<T> {
             // new segment:
             self.term_hash_per_field.as_mut().unwrap().add(
                 &mut self.invert_state,
-                token_stream.as_ref(),
+                &*token_stream,
                 doc_state.doc_id,
             )?;
         }
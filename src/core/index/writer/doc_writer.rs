@@ -20,10 +20,11 @@ use core::index::writer::{
     DocumentsWriterFlushQueue, DocumentsWriterPerThread, DocumentsWriterPerThreadPool,
     FlushByCountsPolicy, IndexWriter, IndexWriterConfig, IndexWriterInner, ThreadState,
 };
+use core::index::ErrorKind::DocCountExceeded;
 use core::search::query::Query;
 use core::store::directory::{Directory, LockValidatingDirectoryWrapper};
 use core::util::external::Volatile;
-use error::{ErrorKind::AlreadyClosed, ErrorKind::IllegalState, Result};
+use error::{ErrorKind::AlreadyClosed, ErrorKind::IllegalState, ErrorKind::Index, Result};
 
 use crossbeam::queue::SegQueue;
 
@@ -236,6 +237,7 @@ where
         // This must happen after we've pulled the ThreadState because IW.close
         // waits for all ThreadStates to be released:
         self.ensure_open()?;
+        self.reserve_segment_docs(per_thread, docs.len())?;
         self.ensure_inited(per_thread)?;
         debug_assert!(per_thread.inited());
         let dwpt_num_docs = per_thread.dwpt().num_docs_in_ram;
@@ -326,6 +328,7 @@ where
         // This must happen after we've pulled the ThreadState because IW.close
         // waits for all ThreadStates to be released:
         self.ensure_open()?;
+        self.reserve_segment_docs(per_thread, 1)?;
         self.ensure_inited(per_thread)?;
         debug_assert!(per_thread.inited());
 
@@ -377,6 +380,26 @@ where
         Ok(has_event)
     }
 
+    // makes room for `num_docs` more docs in the segment of `per_thread`, enrolling it
+    // for flush first if they would exceed the max docs per segment, since the docs
+    // added at once must go to the same segment
+    fn reserve_segment_docs(
+        &self,
+        per_thread: &mut ThreadState<D, C, MS, MP>,
+        num_docs: usize,
+    ) -> Result<()> {
+        let max_docs = self.config.max_docs_per_segment;
+        if num_docs > max_docs as usize {
+            bail!(Index(DocCountExceeded(num_docs as i64, max_docs)));
+        }
+        if per_thread.inited()
+            && per_thread.dwpt().num_docs_in_ram as usize + num_docs > max_docs as usize
+        {
+            self.flush_control.checkout_for_flush(per_thread);
+        }
+        Ok(())
+    }
+
     fn ensure_inited(&self, state: &mut ThreadState<D, C, MS, MP>) -> Result<()> {
         let index_writer = self.index_writer();
         if state.dwpt.is_none() {
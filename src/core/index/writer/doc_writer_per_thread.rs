@@ -12,6 +12,7 @@
 // limitations under the License.
 
 use core::{
+    analysis::Analyzer,
     codec::field_infos::{FieldInfos, FieldInfosBuilder, FieldNumbers, FieldNumbersRef},
     codec::segment_infos::{SegmentCommitInfo, SegmentInfo, SegmentInfoFormat, SegmentWriteState},
     codec::{Codec, LiveDocsFormat},
@@ -35,17 +36,18 @@ use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockWriteGuard, Weak};
 use std::time::SystemTime;
 
 use core::codec::{PackedLongDocMap, SorterDocMap};
+use core::index::ErrorKind::DocCountExceeded;
 use core::util::external::Volatile;
 use core::util::Bits;
 use core::util::FixedBitSet;
-use error::ErrorKind::IllegalArgument;
+use error::ErrorKind::{IllegalArgument, Index};
 use error::Result;
 use std::mem::MaybeUninit;
 use std::ptr;
 
 #[derive(Default)]
 pub struct DocState {
-    // analyzer: Analyzer,  // TODO, current Analyzer is not implemented
+    pub analyzer: Option<Arc<dyn Analyzer>>,
     // pub similarity: Option<Box<Similarity>>,
     pub doc_id: DocId,
     // pub doc: Vec<Box<dyn Fieldable>>,
@@ -136,7 +138,8 @@ where
             writer.config.index_sort().map(|s| s.clone()),
         )?;
         let delete_slice = delete_queue.new_slice();
-        let doc_state = DocState::new();
+        let mut doc_state = DocState::new();
+        doc_state.analyzer = index_writer_config.analyzer.clone();
         // doc_state.similarity = Some(index_writer_config.similarity());
         Ok(DocumentsWriterPerThread {
             directory,
@@ -183,6 +186,15 @@ where
 
     // Anything that will add N docs to the index should reserve first to make sure it's allowed
     fn reserve_one_doc(&mut self) -> Result<()> {
+        // the doc ids of a segment must not overflow, `DocumentsWriter` flushes the
+        // segment before it gets there
+        let max_docs = self.index_writer_config.max_docs_per_segment;
+        if i64::from(self.num_docs_in_ram) >= i64::from(max_docs) {
+            bail!(Index(DocCountExceeded(
+                i64::from(self.num_docs_in_ram) + 1,
+                max_docs
+            )));
+        }
         self.pending_num_docs.fetch_add(1, Ordering::AcqRel);
         if self.pending_num_docs.load(Ordering::Acquire) > INDEX_MAX_DOCS as i64 {
             // Reserve failed: put the one doc back and throw exc:
@@ -203,7 +215,6 @@ where
         self.reserve_one_doc()?;
         // self.doc_state.doc = doc;
         self.doc_state.doc_id = self.num_docs_in_ram as i32;
-        // self.doc_state.analyzer = analyzer;
 
         // Even on exception, the document is still added (but marked
         // deleted), so we don't need to un-reserve at that point.
@@ -393,6 +404,7 @@ where
             ctx,
             "".into(),
         );
+        flush_state.temp_directory = self.index_writer_config.temp_directory.clone();
 
         // Apply delete-by-docID now (delete-byDocID only
         // happens when an exception is hit processing that
@@ -414,10 +426,12 @@ where
             return Ok(None);
         }
 
-        debug!(
-            "DWPT: flush postings as segment '{}' num_docs={}",
-            &flush_state.segment_info.name, self.num_docs_in_ram
-        );
+        self.index_writer_config.info_stream.log("DWPT", || {
+            format!(
+                "flush postings as segment {} num_docs={}",
+                &flush_state.segment_info.name, self.num_docs_in_ram
+            )
+        });
         let res = self.do_flush(flush_state);
         if res.is_err() {
             self.abort();
@@ -475,10 +489,13 @@ where
         };
         self.seal_flushed_segment(&mut fs, sort_map)?;
 
-        debug!(
-            "DWPT: flush time {:?}",
-            SystemTime::now().duration_since(t0).unwrap()
-        );
+        self.index_writer_config.info_stream.log("DWPT", || {
+            format!(
+                "flushed segment {} in {:?}",
+                &self.segment_info.name,
+                SystemTime::now().duration_since(t0).unwrap()
+            )
+        });
         Ok(Some(fs))
     }
 
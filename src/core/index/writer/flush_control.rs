@@ -391,6 +391,24 @@ impl<D: Directory + Send + Sync + 'static, C: Codec, MS: MergeScheduler, MP: Mer
         }
     }
 
+    /// Enrolls the DWPT of `per_thread` for flush, so that the next docs indexed with
+    /// `per_thread` go to a new segment. The caller holds the lock of `per_thread`.
+    pub fn checkout_for_flush(&self, per_thread: &mut ThreadState<D, C, MS, MP>) {
+        debug_assert!(per_thread.inited());
+        let l = self.lock.lock().unwrap();
+        let flush_control_mut = unsafe { self.flush_control_mut(&l) };
+        if !per_thread.flush_pending() {
+            flush_control_mut.set_flush_pending(per_thread, &l);
+        }
+        if per_thread.flush_pending() {
+            if let Some(dwpt) =
+                flush_control_mut.internal_try_checkout_for_flush_no_lock(per_thread)
+            {
+                flush_control_mut.flush_queue.push_back(dwpt);
+            }
+        }
+    }
+
     fn assert_active_delete_queue(&self) -> bool {
         let thread_pool = self.per_thread_pool();
         let limit = thread_pool.active_thread_state_count();
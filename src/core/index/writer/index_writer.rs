@@ -28,21 +28,23 @@ use core::index::merge::SegmentMerger;
 use core::index::merge::{DocMap, MergeState};
 use core::index::merge::{MergePolicy, MergeSpecification, MergerTrigger};
 use core::index::merge::{OneMerge, OneMergeRunningInfo};
-use core::index::reader::index_exist;
+use core::index::reader::{index_exist, WRITE_LOCK_NAME};
 use core::index::reader::{LeafReader, SegmentReader, StandardDirectoryReader};
 use core::index::writer::{
     BufferedUpdatesStream, DocumentsWriter, Event, FlushedSegment, FrozenBufferedUpdates,
     IndexFileDeleter, IndexWriterConfig, MergedDocValuesUpdatesIterator, NewDocValuesIterator,
-    NumericDocValuesUpdate, OpenMode,
+    NumericDocValuesUpdate, OpenMode, ReplayOp, WalOp, WriteAheadLog,
 };
 use core::search::query::{MatchAllDocsQuery, Query};
-use core::store::directory::{Directory, LockValidatingDirectoryWrapper, TrackingDirectoryWrapper};
+use core::store::directory::{
+    Directory, Lock, LockValidatingDirectoryWrapper, TrackingDirectoryWrapper,
+};
 use core::store::{FlushInfo, IOContext};
 use core::util::random_id;
 use core::util::to_base36;
 use core::util::{BitsRef, DerefWrapper, DocId, VERSION_LATEST};
 
-use core::index::ErrorKind::MergeAborted;
+use core::index::ErrorKind::{DocCountExceeded, MergeAborted};
 use error::ErrorKind::{AlreadyClosed, IllegalArgument, IllegalState, Index, RuntimeError};
 use error::{Error, Result};
 
@@ -374,7 +376,7 @@ where
     /// @return The <a href="#sequence_number">sequence number</a>
     /// for this operation
     pub fn add_document<F: Fieldable>(&self, doc: Vec<F>) -> Result<u64> {
-        IndexWriterInner::update_document(self, doc, None)
+        self.update_document(doc, None)
     }
 
     /// Updates a document by first deleting the document(s)
@@ -392,6 +394,10 @@ where
     /// @throws CorruptIndexException if the index is corrupt
     /// @throws IOException if there is a low-level IO error
     pub fn update_document<F: Fieldable>(&self, doc: Vec<F>, term: Option<Term>) -> Result<u64> {
+        if let Some(ref wal) = self.writer.wal {
+            let op = WalOp::update_document(&doc, term.as_ref())?;
+            return wal.log(op, || IndexWriterInner::update_document(self, doc, term));
+        }
         IndexWriterInner::update_document(self, doc, term)
     }
 
@@ -434,7 +440,7 @@ where
     ///
     /// @lucene.experimental
     pub fn add_documents<F: Fieldable>(&self, docs: Vec<Vec<F>>) -> Result<u64> {
-        IndexWriterInner::update_documents(self, docs, None)
+        self.update_documents(docs, None)
     }
 
     /// Atomically deletes documents matching the provided
@@ -454,6 +460,10 @@ where
         docs: Vec<Vec<F>>,
         term: Option<Term>,
     ) -> Result<u64> {
+        if let Some(ref wal) = self.writer.wal {
+            let op = WalOp::update_documents(&docs, term.as_ref())?;
+            return wal.log(op, || IndexWriterInner::update_documents(self, docs, term));
+        }
         IndexWriterInner::update_documents(self, docs, term)
     }
 
@@ -469,6 +479,12 @@ where
     /// @throws CorruptIndexException if the index is corrupt
     /// @throws IOException if there is a low-level IO error
     pub fn delete_documents_by_terms(&self, terms: Vec<Term>) -> Result<u64> {
+        if let Some(ref wal) = self.writer.wal {
+            let op = WalOp::delete_terms(&terms);
+            return wal.log(op, || {
+                IndexWriterInner::delete_documents_by_terms(self, terms)
+            });
+        }
         IndexWriterInner::delete_documents_by_terms(self, terms)
     }
 
@@ -482,7 +498,16 @@ where
     /// to be deleted
     /// @throws CorruptIndexException if the index is corrupt
     /// @throws IOException if there is a low-level IO error
+    ///
+    /// When the write-ahead log is enabled, the queries must be `TermQuery`s,
+    /// `MatchAllDocsQuery`s or `BooleanQuery`s of them, which can be logged and replayed.
     pub fn delete_documents_by_queries(&self, queries: Vec<Arc<dyn Query<C>>>) -> Result<u64> {
+        if let Some(ref wal) = self.writer.wal {
+            let op = WalOp::delete_queries(&queries)?;
+            return wal.log(op, || {
+                IndexWriterInner::delete_documents_by_queries(self, queries)
+            });
+        }
         IndexWriterInner::delete_documents_by_queries(self, queries)
     }
 
@@ -510,6 +535,9 @@ where
     /// @return The <a href="#sequence_number">sequence number</a>
     /// for this operation
     pub fn delete_all(&self) -> Result<u64> {
+        if let Some(ref wal) = self.writer.wal {
+            return wal.log(WalOp::DeleteAll, || IndexWriterInner::delete_all(self));
+        }
         IndexWriterInner::delete_all(self)
     }
 
@@ -518,6 +546,12 @@ where
     }
 
     pub fn update_numeric_doc_value(&self, term: Term, field: &str, value: i64) -> Result<u64> {
+        if let Some(ref wal) = self.writer.wal {
+            let op = WalOp::update_numeric_doc_value(&term, field, value);
+            return wal.log(op, || {
+                IndexWriterInner::update_numeric_doc_value(self, term, field, value)
+            });
+        }
         IndexWriterInner::update_numeric_doc_value(self, term, field, value)
     }
 
@@ -648,6 +682,40 @@ where
     pub fn explicit_merge(&self) -> Result<()> {
         IndexWriterInner::maybe_merge(self, MergerTrigger::Explicit, None)
     }
+
+    /// Replays the operations a previous writer logged to the write-ahead log after
+    /// its last commit, so a crash only loses what was never logged. This must be
+    /// called right after the writer is opened, before any new operation is logged.
+    ///
+    /// @return the number of replayed operations
+    pub fn recover_from_wal(&self) -> Result<usize> {
+        let wal = match self.writer.wal {
+            Some(ref wal) => wal,
+            None => bail!(IllegalState("the write-ahead log is not enabled".into())),
+        };
+        wal.recover(|op| match op {
+            ReplayOp::UpdateDocument(doc, term) => {
+                IndexWriterInner::update_document(self, doc, term)
+            }
+            ReplayOp::UpdateDocuments(docs, term) => {
+                IndexWriterInner::update_documents(self, docs, term)
+            }
+            ReplayOp::DeleteTerms(terms) => {
+                IndexWriterInner::delete_documents_by_terms(self, terms)
+            }
+            ReplayOp::DeleteQueries(queries) => {
+                let queries = queries
+                    .into_iter()
+                    .map(|q| q.into_query().map(Arc::from))
+                    .collect::<Result<_>>()?;
+                IndexWriterInner::delete_documents_by_queries(self, queries)
+            }
+            ReplayOp::DeleteAll => IndexWriterInner::delete_all(self),
+            ReplayOp::UpdateNumericDocValue(term, field, value) => {
+                IndexWriterInner::update_numeric_doc_value(self, term, &field, value)
+            }
+        })
+    }
 }
 
 impl<D, C, MS, MP> IndexWriter<D, C, MS, MP>
@@ -796,6 +864,8 @@ pub struct IndexWriterInner<
     directory_orig: Arc<D>,
     // wrapped with additional checks
     directory: Arc<LockValidatingDirectoryWrapper<D>>,
+    // held from open until close or rollback, so only one writer modifies the index
+    write_lock: Arc<dyn Lock>,
 
     lock: Arc<Mutex<()>>,
     closed: AtomicBool,
@@ -862,6 +932,9 @@ pub struct IndexWriterInner<
     // deletes, doing merges, and reopening near real-time
     // readers.
     pool_readers: AtomicBool,
+
+    // logs the operations since the last commit, if enabled in the config
+    wal: Option<WriteAheadLog>,
 }
 
 unsafe impl<D, C, MS, MP> Send for IndexWriterInner<D, C, MS, MP>
@@ -906,7 +979,11 @@ where
     ///           <code>OpenMode.APPEND</code> or if there is any other low-level
     ///           IO error
     fn new(d: Arc<D>, conf: Arc<IndexWriterConfig<C, MS, MP>>) -> Result<Self> {
-        let directory = Arc::new(LockValidatingDirectoryWrapper::new(Arc::clone(&d)));
+        let write_lock: Arc<dyn Lock> = Arc::from(d.obtain_lock(WRITE_LOCK_NAME)?);
+        let directory = Arc::new(LockValidatingDirectoryWrapper::new(
+            Arc::clone(&d),
+            Arc::clone(&write_lock),
+        ));
 
         let rate_limiters = Arc::new(ThreadLocal::default());
 
@@ -917,6 +994,10 @@ where
 
         let buffered_updates_stream = BufferedUpdatesStream::default();
         let pool_readers = conf.reader_pooling;
+        let wal = match conf.wal_dir {
+            Some(ref dir) => Some(WriteAheadLog::open(dir)?),
+            None => None,
+        };
 
         let create = match conf.open_mode {
             OpenMode::Create => true,
@@ -1052,6 +1133,8 @@ where
             updates_stream_lock: Mutex::new(()),
             buffered_updates_stream,
             pool_readers: AtomicBool::new(pool_readers),
+            write_lock,
+            wal,
             config: conf,
             pending_num_docs: Arc::new(pending_num_docs),
             full_flush_lock: Arc::new(Mutex::new(())),
@@ -1381,6 +1464,13 @@ where
     }
 
     fn rollback(&self) -> Result<()> {
+        if let Some(ref wal) = self.wal {
+            return wal.truncate_after(|| self.do_rollback());
+        }
+        self.do_rollback()
+    }
+
+    fn do_rollback(&self) -> Result<()> {
         // don't call ensureOpen here: this acts like "close()" in closeable.
 
         // Ensure that only one thread actually gets to do the
@@ -1446,6 +1536,9 @@ where
             }
             self.closed.store(true, Ordering::Release);
             self.closing.store(false, Ordering::Release);
+            if let Err(e) = self.write_lock.close() {
+                warn!("IW - release write lock failed by '{:?}'", e);
+            }
 
             // so any "concurrently closing" threads wake up and see that the close has now
             // completed:
@@ -1696,7 +1789,18 @@ where
     }
 
     fn commit(index_writer: &IndexWriter<D, C, MS, MP>) -> Result<i64> {
-        debug!("IW - commit: start");
+        if let Some(ref wal) = index_writer.writer.wal {
+            return wal.truncate_after(|| Self::do_commit(index_writer));
+        }
+        Self::do_commit(index_writer)
+    }
+
+    fn do_commit(index_writer: &IndexWriter<D, C, MS, MP>) -> Result<i64> {
+        index_writer
+            .writer
+            .config
+            .info_stream
+            .log("IW", || "commit: start".to_string());
 
         let mut do_maybe_merge = false;
         let seq_no: i64;
@@ -1877,7 +1981,9 @@ where
             )));
         }
 
-        debug!("IW - start_commit(): start");
+        self.config
+            .info_stream
+            .log("IW", || "start_commit(): start".to_string());
         {
             let lock = Arc::clone(&self.lock);
             let _l = lock.lock()?;
@@ -2000,7 +2106,9 @@ where
             }
         }
 
-        debug!("IW - commit: done");
+        self.config
+            .info_stream
+            .log("IW", || "commit: done".to_string());
         Ok(())
     }
 
@@ -2120,7 +2228,9 @@ where
         }
         index_writer.writer.do_before_flush();
 
-        debug!("IW - start flush: apply_all_deletes={}", apply_deletes);
+        index_writer.writer.config.info_stream.log("IW", || {
+            format!("start flush: apply_all_deletes={}", apply_deletes)
+        });
         // debug!("IW - index before flush");
 
         let mut any_changes = false;
@@ -2199,7 +2309,12 @@ where
                 // merge will skip merging it and will then drop
                 // it once it's done:
 
-                if !self.merging_segments.contains(&info.info.name) {
+                if !self.merging_segments.contains(&info.info.name)
+                    && !self
+                        .config
+                        .merge_policy()
+                        .keep_fully_deleted_segment(info.as_ref())
+                {
                     writer_mut.segment_infos.remove(&info);
                     self.pending_num_docs
                         .fetch_sub(info.info.max_doc() as i64, Ordering::AcqRel);
@@ -2711,6 +2826,20 @@ where
         if let Some(ref mut spec) = spec {
             let merges = mem::replace(&mut spec.merges, vec![]);
             for merge in merges {
+                // the doc ids of the merged segment must not overflow
+                let merged_docs: i64 = merge
+                    .segments
+                    .iter()
+                    .map(|s| i64::from(s.info.max_doc) - i64::from(self.num_deleted_docs(s)))
+                    .sum();
+                let max_docs = self.config.max_docs_per_segment;
+                if merged_docs > i64::from(max_docs) {
+                    if trigger == MergerTrigger::Explicit {
+                        bail!(Index(DocCountExceeded(merged_docs, max_docs)));
+                    }
+                    // the segments are left as they are, this is no reason to stop merging
+                    continue;
+                }
                 self.register_merge(merge, &l)?;
             }
         }
@@ -2779,7 +2908,11 @@ where
 
         index_writer.writer.merge_init(merge)?;
 
-        trace!("IW - now merge");
+        index_writer
+            .writer
+            .config
+            .info_stream
+            .log("IW", || format!("now merge {:?}", &merge.segments));
 
         Self::merge_middle(index_writer, merge)?;
         // self.merge_success();
@@ -2985,6 +3118,7 @@ where
             Arc::clone(&dir_wrapper),
             FieldNumbersRef::new(Arc::clone(&index_writer.writer.global_field_numbers)),
             context,
+            index_writer.writer.config.temp_directory.clone(),
         )?;
         merge.rate_limiter.check_abort()?;
         merge.merge_start_time.write(Some(SystemTime::now()));
@@ -3475,7 +3609,9 @@ where
             ));
         }
 
-        trace!("IW - commit_merge");
+        self.config
+            .info_stream
+            .log("IW", || format!("commit_merge {:?}", &merge.segments));
 
         debug_assert!(merge.register_done);
 
@@ -4056,6 +4192,7 @@ where
     pub fn drop_readers(&self) -> Result<()> {
         let mut guard = self.inner.lock()?;
         guard.reader = None;
+        guard.nrt_reader = None;
         self.dec_ref();
         Ok(())
     }
@@ -4065,6 +4202,13 @@ where
         guard.get_readonly_clone(&self.info, context)
     }
 
+    /// Like `get_readonly_clone`, but returns the previously handed out reader
+    /// if this segment has not changed since.
+    pub fn get_readonly_reader(&self, context: &IOContext) -> Result<Arc<SegmentReader<D, C>>> {
+        let mut guard = self.inner.lock()?;
+        guard.get_readonly_reader(&self.info, context)
+    }
+
     pub fn test_doc_id(&self, doc_id: usize) -> Result<bool> {
         let guard = self.inner.lock().unwrap();
         debug_assert!(guard.live_docs.is_some());
@@ -4151,6 +4295,11 @@ where
 
     // for dv updates
     sort_map: Option<Arc<PackedLongDocMap>>,
+
+    // The last reader handed out to a near real-time reader, with the field infos gen
+    // it was opened with; reused by later NRT opens as long as neither the live docs nor
+    // the doc values of this segment changed.
+    nrt_reader: Option<(Arc<SegmentReader<D, C>>, i64)>,
 }
 
 impl<D, C, MS, MP> ReadersAndUpdatesInner<D, C, MS, MP>
@@ -4171,6 +4320,7 @@ where
             pending_dv_updates: HashMap::new(),
             merging_dv_updates: HashMap::new(),
             sort_map: None,
+            nrt_reader: None,
         }
     }
 
@@ -4192,6 +4342,7 @@ where
             pending_dv_updates: HashMap::new(),
             merging_dv_updates: HashMap::new(),
             sort_map: None,
+            nrt_reader: None,
         }
     }
 
@@ -4304,6 +4455,38 @@ where
         )
     }
 
+    pub fn get_readonly_reader(
+        &mut self,
+        info: &Arc<SegmentCommitInfo<D, C>>,
+        context: &IOContext,
+    ) -> Result<Arc<SegmentReader<D, C>>> {
+        if let Some((ref cached, field_infos_gen)) = self.nrt_reader {
+            let num_docs = info.info.max_doc - info.del_count() - self.pending_delete_count as i32;
+            // a delete always copies the shared live docs first, so an unchanged
+            // live docs instance means no new deletes
+            let same_live_docs = match self.live_docs {
+                Some(ref live_docs) => Arc::ptr_eq(live_docs, &cached.live_docs),
+                None => false,
+            };
+            let same_core = match self.reader {
+                Some(ref reader) => Arc::ptr_eq(&reader.core, &cached.core),
+                None => false,
+            };
+            if same_live_docs
+                && same_core
+                && cached.num_docs() == num_docs
+                && field_infos_gen == info.field_infos_gen()
+            {
+                self.live_docs_shared = true;
+                return Ok(Arc::clone(cached));
+            }
+        }
+
+        let reader = Arc::new(self.get_readonly_clone(info, context)?);
+        self.nrt_reader = Some((Arc::clone(&reader), info.field_infos_gen()));
+        Ok(reader)
+    }
+
     pub fn init_writable_live_docs(&mut self, info: &Arc<SegmentCommitInfo<D, C>>) -> Result<()> {
         debug_assert!(info.info.max_doc > 0);
         if self.live_docs_shared {
@@ -4507,6 +4690,7 @@ where
 
             // reopen segment reader for updates
             me.reader = Some(Arc::new(me.get_readonly_clone(info, &IOContext::Default)?));
+            me.nrt_reader = None;
         }
 
         me.pending_dv_updates.clear();
@@ -4670,3 +4854,476 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+
+    use core::analysis::{
+        Analyzer, CustomAnalyzer, LowerCaseFilterFactory, TokenStream, WhitespaceTokenizer,
+        WhitespaceTokenizerFactory,
+    };
+    use core::codec::CodecEnum;
+    use core::doc::{Field, FieldType, IndexOptions, NumericDocValuesField, StoredField};
+    use core::index::reader::IndexReader;
+    use core::search::collector::TopDocsCollector;
+    use core::search::query::{BooleanQuery, TermQuery};
+    use core::search::sort_field::{SimpleSortField, Sort, SortField, SortFieldType};
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::store::directory::{FSDirectory, MmapDirectory};
+    use core::test_util::{fs_writer, keyword_field, new_fs_writer, text_field};
+    use core::util::{InfoStream, VariantValue};
+
+    use std::fs;
+    use std::io::{Cursor, Read};
+    use std::path::Path;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    fn string_field(name: &str, value: &str) -> Box<dyn Fieldable> {
+        let mut field_type = FieldType::default();
+        field_type.index_options = IndexOptions::Docs;
+        field_type.tokenized = false;
+        let value = VariantValue::VString(value.to_string());
+        Box::new(Field::new(name.to_string(), field_type, Some(value), None))
+    }
+
+    fn id_term(id: &str) -> Term {
+        Term::new("id".into(), id.as_bytes().to_vec())
+    }
+
+    fn copy_dir(from: &Path, to: &Path) {
+        for entry in fs::read_dir(from).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, to.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_concurrent_add_and_delete() {
+        const THREADS: usize = 8;
+        const DOCS_PER_THREAD: usize = 200;
+
+        let (_dir, writer) = new_fs_writer();
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let writer = writer.clone();
+                thread::spawn(move || {
+                    for i in 0..DOCS_PER_THREAD {
+                        let id = format!("{}-{}", t, i);
+                        let group = format!("group{}", t);
+                        writer
+                            .add_document(vec![
+                                keyword_field("id", &id),
+                                keyword_field("group", &group),
+                            ])
+                            .unwrap();
+                    }
+                    // each thread deletes a doc it has just added, the delete must
+                    // apply regardless of which per-thread buffer holds the doc
+                    let term = Term::new("id".into(), format!("{}-0", t).into_bytes());
+                    writer.delete_documents_by_terms(vec![term]).unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        assert_eq!(reader.num_docs() as usize, THREADS * (DOCS_PER_THREAD - 1));
+
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        for t in 0..THREADS {
+            let term = Term::new("group".into(), format!("group{}", t).into_bytes());
+            let query = TermQuery::new(term, 1.0, None);
+            let mut collector = TopDocsCollector::new(10);
+            searcher.search(&query, &mut collector).unwrap();
+            assert_eq!(
+                collector.top_docs().total_hits() as usize,
+                DOCS_PER_THREAD - 1
+            );
+
+            let term = Term::new("id".into(), format!("{}-0", t).into_bytes());
+            let query = TermQuery::new(term, 1.0, None);
+            let mut collector = TopDocsCollector::new(10);
+            searcher.search(&query, &mut collector).unwrap();
+            assert_eq!(collector.top_docs().total_hits(), 0);
+        }
+    }
+
+    #[test]
+    fn test_multi_valued_field_offsets() {
+        let (_dir, writer) = new_fs_writer();
+        let body = |text: &str| -> Box<dyn Fieldable> {
+            let mut field_type = FieldType::default();
+            field_type.index_options = IndexOptions::DocsAndFreqsAndPositionsAndOffsets;
+            let reader = Box::new(Cursor::new(text.as_bytes().to_vec()));
+            let tokens = WhitespaceTokenizer::new(reader);
+            Box::new(Field::new(
+                "body".into(),
+                field_type,
+                None,
+                Some(Box::new(tokens)),
+            ))
+        };
+        // the offsets of the second value must start after the trailing whitespaces of
+        // the first, otherwise they would go backwards
+        writer
+            .add_document(vec![body("quick  fox  "), body("lazy dog")])
+            .unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let query = TermQuery::new(Term::new("body".into(), b"dog".to_vec()), 1.0, None);
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(&query, &mut collector).unwrap();
+        assert_eq!(collector.top_docs().total_hits(), 1);
+    }
+
+    #[test]
+    fn test_recover_from_wal_after_crash() {
+        let index_dir = tempfile::tempdir().unwrap();
+        let wal_dir = tempfile::tempdir().unwrap();
+        let open_writer = |index_dir: &Path, wal_dir: &Path| {
+            let mut config = IndexWriterConfig::default();
+            config.enable_wal(wal_dir);
+            config.set_analyzer(CustomAnalyzer::new(
+                Box::new(WhitespaceTokenizerFactory),
+                vec![Box::new(LowerCaseFilterFactory)],
+            ));
+            let directory = Arc::new(FSDirectory::with_path(index_dir).unwrap());
+            IndexWriter::new(directory, Arc::new(config)).unwrap()
+        };
+        // the body is analyzed again by the analyzer of the writer on replay
+        let doc = |id: &str, body: &str| -> Vec<Box<dyn Fieldable>> {
+            let mut field_type = FieldType::default();
+            field_type.index_options = IndexOptions::DocsAndFreqsAndPositions;
+            let body = VariantValue::VString(body.to_string());
+            let body = Field::new("body".into(), field_type, Some(body), None);
+            vec![string_field("id", id), Box::new(body)]
+        };
+        let body_term = |text: &str| Term::new("body".into(), text.as_bytes().to_vec());
+
+        let writer = open_writer(index_dir.path(), wal_dir.path());
+        for id in &["a", "b", "c"] {
+            writer.add_document(vec![string_field("id", id)]).unwrap();
+        }
+        writer.commit().unwrap();
+        writer.add_document(doc("d", "Quick Fox")).unwrap();
+        writer.add_document(doc("e", "Lazy Dog")).unwrap();
+        writer
+            .update_document(vec![string_field("id", "f")], Some(id_term("a")))
+            .unwrap();
+        writer
+            .delete_documents_by_terms(vec![id_term("b")])
+            .unwrap();
+        let dog: Box<dyn Query<CodecEnum>> = Box::new(TermQuery::new(body_term("dog"), 1.0, None));
+        let query = BooleanQuery::build(vec![], vec![dog], vec![], vec![], 0).unwrap();
+        writer
+            .delete_documents_by_queries(vec![Arc::from(query)])
+            .unwrap();
+        // a token stream of the caller's can't be replayed
+        let tokens = text_field("body", "slow fox", IndexOptions::Docs);
+        assert!(writer.add_document(vec![tokens]).is_err());
+        // crash: what is on disk now is all a restarted process would see
+        let crashed_index_dir = tempfile::tempdir().unwrap();
+        let crashed_wal_dir = tempfile::tempdir().unwrap();
+        copy_dir(index_dir.path(), crashed_index_dir.path());
+        copy_dir(wal_dir.path(), crashed_wal_dir.path());
+        drop(writer);
+        let open_crashed = || open_writer(crashed_index_dir.path(), crashed_wal_dir.path());
+
+        let writer = open_crashed();
+        assert_eq!(writer.num_docs(), 3);
+        assert!(writer.add_document(vec![string_field("id", "g")]).is_err());
+        assert_eq!(writer.recover_from_wal().unwrap(), 5);
+
+        let reader = writer.get_reader(true, false).unwrap();
+        assert_eq!(reader.num_docs(), 3);
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let hits = |term: Term| {
+            let mut collector = TopDocsCollector::new(10);
+            searcher
+                .search(&TermQuery::new(term, 1.0, None), &mut collector)
+                .unwrap();
+            collector.top_docs().total_hits()
+        };
+        for (id, count) in &[("a", 0), ("b", 0), ("c", 1), ("d", 1), ("e", 0), ("f", 1)] {
+            assert_eq!(hits(id_term(id)), *count, "id {}", id);
+        }
+        assert_eq!(hits(body_term("quick")), 1);
+        assert_eq!(hits(body_term("fox")), 1);
+        writer.commit().unwrap();
+        drop(searcher);
+        drop(writer);
+
+        // the commit truncated the log, nothing is replayed twice
+        let writer = open_crashed();
+        assert_eq!(writer.recover_from_wal().unwrap(), 0);
+        assert_eq!(writer.num_docs(), 3);
+    }
+
+    #[test]
+    fn test_write_lock_excludes_second_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let config = Arc::new(IndexWriterConfig::default());
+
+        let writer = IndexWriter::new(Arc::clone(&directory), Arc::clone(&config)).unwrap();
+        assert!(IndexWriter::new(Arc::clone(&directory), Arc::clone(&config)).is_err());
+
+        writer.add_document(vec![string_field("id", "a")]).unwrap();
+        writer.close().unwrap();
+        let writer = IndexWriter::new(directory, config).unwrap();
+        assert_eq!(writer.num_docs(), 1);
+    }
+
+    #[test]
+    fn test_lock_validating_directory_rejects_released_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let lock: Arc<dyn Lock> = Arc::from(directory.obtain_lock(WRITE_LOCK_NAME).unwrap());
+        let wrapper = LockValidatingDirectoryWrapper::new(directory, Arc::clone(&lock));
+        assert!(wrapper.create_output("a", &IOContext::Default).is_ok());
+
+        lock.close().unwrap();
+        assert!(lock.ensure_valid().is_err());
+        assert!(wrapper.create_output("b", &IOContext::Default).is_err());
+        assert!(wrapper.delete_file("a").is_err());
+    }
+
+    #[test]
+    fn test_temp_directory() {
+        let file_names = |path: &Path| -> Vec<String> {
+            fs::read_dir(path)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect()
+        };
+        let has_temp_files =
+            |path: &Path| -> bool { file_names(path).iter().any(|f| f.ends_with(".tmp")) };
+        let doc = |rank: i64| -> Vec<Box<dyn Fieldable>> {
+            let title = VariantValue::VString(format!("doc{}", rank));
+            vec![
+                Box::new(StoredField::new("title", None, title).field),
+                Box::new(NumericDocValuesField::new("rank", rank)),
+            ]
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut config = IndexWriterConfig::default();
+        config.index_sort = Some(Sort::new(vec![SortField::Simple(SimpleSortField::new(
+            "rank".into(),
+            SortFieldType::Long,
+            false,
+        ))]));
+        // the temp files may go to any kind of directory
+        config.set_temp_directory(Arc::new(MmapDirectory::new(&temp_dir.path()).unwrap()));
+        let config = Arc::new(config);
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let writer = IndexWriter::new(Arc::clone(&directory), Arc::clone(&config)).unwrap();
+        for &rank in &[3, 1, 2] {
+            writer.add_document(doc(rank)).unwrap();
+        }
+
+        // the unsorted stored fields are buffered in the temp directory until the flush
+        assert!(has_temp_files(temp_dir.path()));
+        assert!(!has_temp_files(dir.path()));
+        writer.commit().unwrap();
+        assert!(file_names(temp_dir.path()).is_empty());
+        assert!(!has_temp_files(dir.path()));
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let titles: Vec<String> = (0..3)
+            .map(|doc| {
+                let doc = reader.document(doc, &[]).unwrap();
+                let title = doc.get_field("title").unwrap();
+                title.string_value().unwrap().to_string()
+            })
+            .collect();
+        assert_eq!(titles, vec!["doc1", "doc2", "doc3"]);
+        drop(reader);
+
+        // the temp files of the docs never flushed are removed as well
+        writer.add_document(doc(4)).unwrap();
+        assert!(!file_names(temp_dir.path()).is_empty());
+        writer.rollback().unwrap();
+        assert!(file_names(temp_dir.path()).is_empty());
+    }
+
+    #[test]
+    fn test_max_docs_per_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = IndexWriterConfig::default();
+        config.max_docs_per_segment = 10;
+        let writer = fs_writer(dir.path(), Arc::new(config));
+        let doc = |i: usize| vec![keyword_field("id", &i.to_string())];
+        let segment_docs = |writer: &IndexWriter<_, _, _, _>| -> Vec<i32> {
+            let reader = writer.get_reader(true, false).unwrap();
+            reader.leaves().iter().map(|l| l.reader.max_doc()).collect()
+        };
+
+        // a full segment is flushed before the next doc
+        for i in 0..25 {
+            writer.add_document(doc(i)).unwrap();
+        }
+        writer.commit().unwrap();
+        let docs = segment_docs(&writer);
+        assert!(docs.len() >= 3, "{:?}", docs);
+        assert!(docs.iter().all(|&d| d <= 10), "{:?}", docs);
+        assert_eq!(docs.iter().sum::<i32>(), 25);
+
+        // the docs of a block share a segment, so a block fitting no segment fails
+        match writer.add_documents((0..11).map(doc).collect()) {
+            Err(Error(Index(DocCountExceeded(11, 10)), _)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+        for i in 0..5 {
+            writer.add_document(doc(i)).unwrap();
+        }
+        writer.add_documents((0..6).map(doc).collect()).unwrap();
+        writer.commit().unwrap();
+        let docs = segment_docs(&writer);
+        assert!(docs.iter().all(|&d| d <= 10), "{:?}", docs);
+        assert_eq!(docs.iter().sum::<i32>(), 36);
+
+        // the segments can't be merged without exceeding the limit, they are kept
+        match writer.force_merge(1, true) {
+            Err(Error(Index(DocCountExceeded(36, 10)), _)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+        writer.commit().unwrap();
+        let merged = segment_docs(&writer);
+        assert!(merged.iter().all(|&d| d <= 10), "{:?}", merged);
+        assert_eq!(merged.iter().sum::<i32>(), 36);
+        assert!(writer.tragedy().is_none());
+    }
+
+    struct CapturingInfoStream {
+        messages: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    impl InfoStream for CapturingInfoStream {
+        fn message(&self, component: &str, message: &str) {
+            self.messages
+                .lock()
+                .unwrap()
+                .push((component.to_string(), message.to_string()));
+        }
+
+        fn is_enabled(&self, _component: &str) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_info_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let messages = Arc::new(Mutex::new(vec![]));
+        let mut config = IndexWriterConfig::default();
+        config.set_info_stream(CapturingInfoStream {
+            messages: Arc::clone(&messages),
+        });
+        let writer = fs_writer(dir.path(), Arc::new(config));
+        for i in 0..10 {
+            writer
+                .add_document(vec![keyword_field("id", &i.to_string())])
+                .unwrap();
+        }
+        assert!(messages.lock().unwrap().is_empty());
+        writer.commit().unwrap();
+        {
+            let messages = messages.lock().unwrap();
+            assert!(messages.iter().any(|(c, _)| c == "DWPT"), "{:?}", messages);
+            assert!(messages
+                .iter()
+                .any(|(c, m)| c == "IW" && m == "commit: done"));
+            assert!(messages.iter().all(|(c, _)| c != "MP"));
+        }
+
+        // enough segments for the merge policy to pick a merge
+        for i in 0..15 {
+            writer
+                .add_document(vec![keyword_field("id", &i.to_string())])
+                .unwrap();
+            writer.commit().unwrap();
+        }
+        let messages = messages.lock().unwrap();
+        assert!(
+            messages
+                .iter()
+                .any(|(c, m)| c == "MP" && m.starts_with("add merge=")),
+            "{:?}",
+            messages
+        );
+        assert!(messages
+            .iter()
+            .any(|(c, m)| c == "IW" && m.starts_with("commit_merge")));
+    }
+    struct CountingAnalyzer {
+        analyzer: CustomAnalyzer,
+        chains: Arc<AtomicUsize>,
+    }
+
+    impl Analyzer for CountingAnalyzer {
+        fn token_stream(&self, field: &str, reader: Box<dyn Read>) -> Result<Box<dyn TokenStream>> {
+            self.chains.fetch_add(1, Ordering::SeqCst);
+            self.analyzer.token_stream(field, reader)
+        }
+    }
+
+    #[test]
+    fn test_analyzer_chains_are_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let chains = Arc::new(AtomicUsize::new(0));
+        let mut config = IndexWriterConfig::default();
+        config.set_analyzer(CountingAnalyzer {
+            analyzer: CustomAnalyzer::new(
+                Box::new(WhitespaceTokenizerFactory),
+                vec![Box::new(LowerCaseFilterFactory)],
+            ),
+            chains: Arc::clone(&chains),
+        });
+        let writer = fs_writer(dir.path(), Arc::new(config));
+        let text = |name: &str, value: String| -> Box<dyn Fieldable> {
+            let mut field_type = FieldType::default();
+            field_type.index_options = IndexOptions::DocsAndFreqsAndPositions;
+            let value = VariantValue::VString(value);
+            Box::new(Field::new(name.to_string(), field_type, Some(value), None))
+        };
+        for i in 0..10 {
+            writer
+                .add_document(vec![
+                    text("title", format!("Title {}", i)),
+                    text("body", format!("The BODY of doc{}", i)),
+                ])
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        // one chain per field, pointed to the value of every doc
+        assert_eq!(chains.load(Ordering::SeqCst), 2);
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let hits = |field: &str, term: &str| {
+            let term = Term::new(field.into(), term.as_bytes().to_vec());
+            let mut collector = TopDocsCollector::new(20);
+            searcher
+                .search(&TermQuery::new(term, 1.0, None), &mut collector)
+                .unwrap();
+            collector.top_docs().total_hits()
+        };
+        assert_eq!(hits("title", "title"), 10);
+        assert_eq!(hits("title", "Title"), 0);
+        assert_eq!(hits("body", "body"), 10);
+        assert_eq!(hits("body", "doc3"), 1);
+        assert_eq!(hits("title", "3"), 1);
+    }
+}
@@ -11,13 +11,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::analysis::Analyzer;
 use core::codec::{Codec, CodecEnum, Lucene62Codec};
 use core::index::merge::MergeScheduler;
 use core::index::merge::SerialMergeScheduler;
 use core::index::merge::{MergePolicy, TieredMergePolicy};
-use core::index::writer::KeepOnlyLastCommitDeletionPolicy;
+use core::index::writer::{KeepOnlyLastCommitDeletionPolicy, TempDirectory, INDEX_MAX_DOCS};
 use core::search::sort_field::Sort;
+use core::store::directory::Directory;
+use core::util::{InfoStream, NoOutputInfoStream};
 
+use std::path::PathBuf;
 use std::sync::Arc;
 
 /// Denotes a flush trigger is disabled.
@@ -70,6 +74,19 @@ pub struct IndexWriterConfig<C: Codec, MS: MergeScheduler, MP: MergePolicy> {
     pub open_mode: OpenMode,
     pub codec: Arc<C>,
     pub commit_on_close: bool,
+    /// Directory of the write-ahead log, `None` if the log is disabled.
+    pub wal_dir: Option<PathBuf>,
+    /// Directory of the transient files, `None` to write them into the index directory.
+    pub temp_directory: Option<Arc<dyn TempDirectory>>,
+    /// The most docs a segment may hold, `INDEX_MAX_DOCS` by default. A segment being
+    /// written is flushed before it would exceed it, and segments whose live docs exceed
+    /// it together are not merged, `force_merge` fails with `DocCountExceeded` for them.
+    pub max_docs_per_segment: i32,
+    /// Receives the diagnostic messages of the writer, drops them by default.
+    pub info_stream: Arc<dyn InfoStream>,
+    /// Analyzes the text of the tokenized fields that have no token stream of their own,
+    /// `None` if every tokenized field brings its stream.
+    pub analyzer: Option<Arc<dyn Analyzer>>,
 }
 
 impl Default for IndexWriterConfig<CodecEnum, SerialMergeScheduler, TieredMergePolicy> {
@@ -95,9 +112,49 @@ impl<C: Codec, MS: MergeScheduler, MP: MergePolicy> IndexWriterConfig<C, MS, MP>
             open_mode: OpenMode::CreateOrAppend,
             codec,
             commit_on_close: true,
+            wal_dir: None,
+            temp_directory: None,
+            max_docs_per_segment: INDEX_MAX_DOCS,
+            info_stream: Arc::new(NoOutputInfoStream),
+            analyzer: None,
         }
     }
 
+    /// Logs every add, update and delete to a write-ahead log in `dir`, so that
+    /// operations buffered since the last commit can be restored after a crash
+    /// with `IndexWriter#recover_from_wal`. The log is truncated on each commit.
+    ///
+    /// Tokenized fields are logged with their string value and analyzed again by the
+    /// analyzer on replay, so fields that bring a token stream of their own are rejected.
+    pub fn enable_wal<P: Into<PathBuf>>(&mut self, dir: P) {
+        self.wal_dir = Some(dir.into());
+    }
+
+    /// Writes the transient files, e.g. the unsorted stored fields and term vectors of a
+    /// sorted index before they are sorted on flush, into `dir` instead of the index
+    /// directory, so that the scratch space can live on a separate, faster disk. The points
+    /// spilled to disk while sorting them on flush and merge go there as well. They are
+    /// deleted once the flush or merge is done, or as soon as it fails or the writer is
+    /// closed.
+    pub fn set_temp_directory<D>(&mut self, dir: Arc<D>)
+    where
+        D: Directory + Send + Sync + 'static,
+        D::TempOutput: Send + 'static,
+    {
+        self.temp_directory = Some(dir);
+    }
+
+    /// Sends the diagnostic messages of the flushes, merges and commits to `info_stream`.
+    pub fn set_info_stream<I: InfoStream + 'static>(&mut self, info_stream: I) {
+        self.info_stream = Arc::new(info_stream);
+    }
+
+    /// Analyzes the string values of the tokenized fields with `analyzer`. Each indexing
+    /// thread builds one analysis chain per field and points it to every new value.
+    pub fn set_analyzer<A: Analyzer + 'static>(&mut self, analyzer: A) {
+        self.analyzer = Some(Arc::new(analyzer));
+    }
+
     pub fn max_buffered_delete_terms(&self) -> u32 {
         self.max_buffered_delete_terms.unwrap_or(0)
     }
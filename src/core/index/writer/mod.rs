@@ -67,6 +67,10 @@ mod prefix_code_terms;
 
 pub use self::prefix_code_terms::*;
 
+mod write_ahead_log;
+
+pub use self::write_ahead_log::*;
+
 pub mod doc_values_update;
 
 pub use self::doc_values_update::*;
@@ -0,0 +1,786 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::Codec;
+use core::doc::{Field, FieldType, Fieldable, IndexOptions, Term};
+use core::search::query::{BooleanQuery, MatchAllDocsQuery, Query, TermQuery};
+use core::util::VariantValue;
+
+use error::ErrorKind::{CorruptIndex, IllegalArgument, IllegalState};
+use error::Result;
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use crc::crc32;
+use serde_json;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Name of the log file inside the configured write-ahead log directory.
+pub const WAL_FILE_NAME: &str = "write_ahead.log";
+
+// every frame starts with the payload length and its crc32, both big endian u32
+const FRAME_HEADER_LEN: usize = 8;
+
+/// A field value as recorded in the log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum WalValue {
+    Bool(bool),
+    Char(char),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    VString(String),
+    Binary(Vec<u8>),
+}
+
+impl WalValue {
+    fn new(value: &VariantValue) -> Option<WalValue> {
+        let v = match value {
+            VariantValue::Bool(v) => WalValue::Bool(*v),
+            VariantValue::Char(v) => WalValue::Char(*v),
+            VariantValue::Short(v) => WalValue::Short(*v),
+            VariantValue::Int(v) => WalValue::Int(*v),
+            VariantValue::Long(v) => WalValue::Long(*v),
+            VariantValue::Float(v) => WalValue::Float(*v),
+            VariantValue::Double(v) => WalValue::Double(*v),
+            VariantValue::VString(v) => WalValue::VString(v.clone()),
+            VariantValue::Binary(v) => WalValue::Binary(v.clone()),
+            VariantValue::Vec(_) | VariantValue::Map(_) => {
+                return None;
+            }
+        };
+        Some(v)
+    }
+
+    fn into_variant(self) -> VariantValue {
+        match self {
+            WalValue::Bool(v) => VariantValue::Bool(v),
+            WalValue::Char(v) => VariantValue::Char(v),
+            WalValue::Short(v) => VariantValue::Short(v),
+            WalValue::Int(v) => VariantValue::Int(v),
+            WalValue::Long(v) => VariantValue::Long(v),
+            WalValue::Float(v) => VariantValue::Float(v),
+            WalValue::Double(v) => VariantValue::Double(v),
+            WalValue::VString(v) => VariantValue::VString(v),
+            WalValue::Binary(v) => VariantValue::Binary(v),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct WalField {
+    name: String,
+    field_type: FieldType,
+    boost: f32,
+    value: WalValue,
+}
+
+impl WalField {
+    /// The indexed tokens of a field must be derivable from its logged value: untokenized
+    /// fields index the value as is, tokenized ones are analyzed again by the analyzer of
+    /// the writer on replay. A token stream supplied with the field is consumed by
+    /// indexing and can't be logged.
+    fn new<F: Fieldable>(field: &F) -> Result<WalField> {
+        let field_type = field.field_type();
+        let replayable = field_type.index_options == IndexOptions::Null
+            || !field_type.tokenized
+            || field.analyzed_value().is_some();
+        match field.field_data().and_then(WalValue::new) {
+            Some(value) if replayable => Ok(WalField {
+                name: field.name().to_string(),
+                field_type: field_type.clone(),
+                boost: field.boost(),
+                value,
+            }),
+            _ => bail!(IllegalArgument(format!(
+                "field '{}' can not be written to the write-ahead log, only fields with a scalar \
+                 value and no token stream of their own can be replayed",
+                field.name()
+            ))),
+        }
+    }
+
+    fn into_field(self) -> Field {
+        let mut field = Field::new(
+            self.name,
+            self.field_type,
+            Some(self.value.into_variant()),
+            None,
+        );
+        field.set_boost(self.boost);
+        field
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct WalTerm {
+    field: String,
+    bytes: Vec<u8>,
+}
+
+impl WalTerm {
+    fn new(term: &Term) -> WalTerm {
+        WalTerm {
+            field: term.field.clone(),
+            bytes: term.bytes.clone(),
+        }
+    }
+
+    pub fn into_term(self) -> Term {
+        Term::new(self.field, self.bytes)
+    }
+}
+
+/// A delete query as recorded in the log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum WalQuery {
+    MatchAll,
+    Term(WalTerm),
+    Boolean {
+        musts: Vec<WalQuery>,
+        shoulds: Vec<WalQuery>,
+        filters: Vec<WalQuery>,
+        must_nots: Vec<WalQuery>,
+        min_should_match: i32,
+    },
+}
+
+impl WalQuery {
+    fn new<C: Codec>(query: &dyn Query<C>) -> Result<WalQuery> {
+        let query = query.as_any();
+        if query.is::<MatchAllDocsQuery>() {
+            Ok(WalQuery::MatchAll)
+        } else if let Some(q) = query.downcast_ref::<TermQuery>() {
+            Ok(WalQuery::Term(WalTerm::new(q.term())))
+        } else if let Some(q) = query.downcast_ref::<BooleanQuery<C>>() {
+            let log = |queries: &[Box<dyn Query<C>>]| {
+                queries
+                    .iter()
+                    .map(|q| WalQuery::new(q.as_ref()))
+                    .collect::<Result<Vec<_>>>()
+            };
+            Ok(WalQuery::Boolean {
+                musts: log(q.must_queries())?,
+                shoulds: log(q.should_queries())?,
+                filters: log(q.filter_queries())?,
+                must_nots: log(q.must_not_queries())?,
+                min_should_match: q.min_should_match(),
+            })
+        } else {
+            bail!(IllegalArgument(
+                "only term, match all and boolean queries of them can be written to the \
+                 write-ahead log"
+                    .into()
+            ))
+        }
+    }
+
+    pub fn into_query<C: Codec>(self) -> Result<Box<dyn Query<C>>> {
+        match self {
+            WalQuery::MatchAll => Ok(Box::new(MatchAllDocsQuery)),
+            WalQuery::Term(term) => Ok(Box::new(TermQuery::new(term.into_term(), 1.0, None))),
+            WalQuery::Boolean {
+                musts,
+                shoulds,
+                filters,
+                must_nots,
+                min_should_match,
+            } => {
+                let into = |queries: Vec<WalQuery>| {
+                    queries
+                        .into_iter()
+                        .map(WalQuery::into_query)
+                        .collect::<Result<Vec<_>>>()
+                };
+                Ok(Box::new(BooleanQuery::new(
+                    into(musts)?,
+                    into(shoulds)?,
+                    into(filters)?,
+                    into(must_nots)?,
+                    min_should_match,
+                )?))
+            }
+        }
+    }
+}
+
+fn log_doc<F: Fieldable>(doc: &[F]) -> Result<Vec<WalField>> {
+    doc.iter().map(WalField::new).collect()
+}
+
+fn into_doc(doc: Vec<WalField>) -> Vec<Field> {
+    doc.into_iter().map(WalField::into_field).collect()
+}
+
+/// An indexing operation recorded in the write-ahead log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum WalOp {
+    UpdateDocument {
+        doc: Vec<WalField>,
+        term: Option<WalTerm>,
+    },
+    UpdateDocuments {
+        docs: Vec<Vec<WalField>>,
+        term: Option<WalTerm>,
+    },
+    DeleteTerms(Vec<WalTerm>),
+    DeleteQueries(Vec<WalQuery>),
+    DeleteAll,
+    UpdateNumericDocValue {
+        term: WalTerm,
+        field: String,
+        value: i64,
+    },
+}
+
+impl WalOp {
+    pub fn update_document<F: Fieldable>(doc: &[F], term: Option<&Term>) -> Result<WalOp> {
+        Ok(WalOp::UpdateDocument {
+            doc: log_doc(doc)?,
+            term: term.map(WalTerm::new),
+        })
+    }
+
+    pub fn update_documents<F: Fieldable>(docs: &[Vec<F>], term: Option<&Term>) -> Result<WalOp> {
+        let docs = docs.iter().map(|d| log_doc(d)).collect::<Result<_>>()?;
+        Ok(WalOp::UpdateDocuments {
+            docs,
+            term: term.map(WalTerm::new),
+        })
+    }
+
+    pub fn delete_terms(terms: &[Term]) -> WalOp {
+        WalOp::DeleteTerms(terms.iter().map(WalTerm::new).collect())
+    }
+
+    pub fn delete_queries<C: Codec>(queries: &[Arc<dyn Query<C>>]) -> Result<WalOp> {
+        let queries = queries
+            .iter()
+            .map(|q| WalQuery::new(q.as_ref()))
+            .collect::<Result<_>>()?;
+        Ok(WalOp::DeleteQueries(queries))
+    }
+
+    pub fn update_numeric_doc_value(term: &Term, field: &str, value: i64) -> WalOp {
+        WalOp::UpdateNumericDocValue {
+            term: WalTerm::new(term),
+            field: field.to_string(),
+            value,
+        }
+    }
+}
+
+/// The documents of a replayed op, rebuilt as plain `Field`s.
+pub(crate) enum ReplayOp {
+    UpdateDocument(Vec<Field>, Option<Term>),
+    UpdateDocuments(Vec<Vec<Field>>, Option<Term>),
+    DeleteTerms(Vec<Term>),
+    DeleteQueries(Vec<WalQuery>),
+    DeleteAll,
+    UpdateNumericDocValue(Term, String, i64),
+}
+
+impl From<WalOp> for ReplayOp {
+    fn from(op: WalOp) -> ReplayOp {
+        match op {
+            WalOp::UpdateDocument { doc, term } => {
+                ReplayOp::UpdateDocument(into_doc(doc), term.map(WalTerm::into_term))
+            }
+            WalOp::UpdateDocuments { docs, term } => ReplayOp::UpdateDocuments(
+                docs.into_iter().map(into_doc).collect(),
+                term.map(WalTerm::into_term),
+            ),
+            WalOp::DeleteTerms(terms) => {
+                ReplayOp::DeleteTerms(terms.into_iter().map(WalTerm::into_term).collect())
+            }
+            WalOp::DeleteQueries(queries) => ReplayOp::DeleteQueries(queries),
+            WalOp::DeleteAll => ReplayOp::DeleteAll,
+            WalOp::UpdateNumericDocValue { term, field, value } => {
+                ReplayOp::UpdateNumericDocValue(term.into_term(), field, value)
+            }
+        }
+    }
+}
+
+/// A frame of the log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum WalFrame {
+    /// An op, written before it is applied.
+    Op { id: u64, op: WalOp },
+    /// The sequence number `IndexWriter` assigned to the op `id` once it was applied.
+    Applied { id: u64, seq_no: u64 },
+    /// The op `id` failed to apply and must not be replayed.
+    Failed { id: u64 },
+}
+
+/// An op of the log to replay, `seq_no` is `None` if the process crashed while applying
+/// it.
+#[derive(Clone, Debug)]
+struct WalEntry {
+    id: u64,
+    seq_no: Option<u64>,
+    op: WalOp,
+}
+
+/// An append-only log of the indexing operations since the last commit.
+///
+/// Each frame is written as json, prefixed with its length and crc32. An operation is
+/// logged before it is applied, so every operation `IndexWriter` acknowledged can be
+/// replayed, and once applied it is followed by the sequence number it was assigned, so
+/// ops logged concurrently by several threads are replayed in the order they were
+/// applied. Ops still being applied when the process crashed are replayed after all
+/// others. The log only has to survive a crash of the process, it is not fsync'ed per
+/// operation.
+pub(crate) struct WriteAheadLog {
+    path: PathBuf,
+    // indexing ops hold the read side while logging and applying, commit, rollback
+    // and recovery hold the write side so that no op is half way when the log is cut
+    lock: RwLock<()>,
+    file: Mutex<File>,
+    // the id of the next logged op
+    next_id: AtomicU64,
+    // entries left by a previous writer, they must be recovered before new ops are logged
+    pending: Mutex<Vec<WalEntry>>,
+}
+
+impl WriteAheadLog {
+    pub fn open(dir: &Path) -> Result<WriteAheadLog> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(WAL_FILE_NAME);
+        let mut frames = vec![];
+        if path.exists() {
+            let mut data = vec![];
+            File::open(&path)?.read_to_end(&mut data)?;
+            let valid_len = Self::parse(&data, &mut frames)?;
+            if valid_len < data.len() {
+                // drop the partial frame of a write interrupted by the crash
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)?
+                    .set_len(valid_len as u64)?;
+            }
+        }
+        let next_id = frames
+            .iter()
+            .map(|f| match *f {
+                WalFrame::Op { id, .. } => id + 1,
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0);
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(WriteAheadLog {
+            path,
+            lock: RwLock::new(()),
+            file: Mutex::new(file),
+            next_id: AtomicU64::new(next_id),
+            pending: Mutex::new(Self::reconcile(frames)),
+        })
+    }
+
+    /// Parses the complete frames of `data` and returns the number of bytes they span.
+    ///
+    /// A frame that runs past the end of `data`, or the last frame failing its checksum,
+    /// is the remains of a write interrupted by a crash and ends the log. A checksum
+    /// mismatch before that is corruption.
+    fn parse(data: &[u8], frames: &mut Vec<WalFrame>) -> Result<usize> {
+        let mut valid_len = 0;
+        while data.len() - valid_len >= FRAME_HEADER_LEN {
+            let header = &data[valid_len..valid_len + FRAME_HEADER_LEN];
+            let len = BigEndian::read_u32(&header[..4]) as usize;
+            let checksum = BigEndian::read_u32(&header[4..]);
+            let start = valid_len + FRAME_HEADER_LEN;
+            if data.len() - start < len {
+                break;
+            }
+            let payload = &data[start..start + len];
+            if crc32::checksum_ieee(payload) != checksum {
+                if start + len == data.len() {
+                    break;
+                }
+                bail!(CorruptIndex(format!(
+                    "write-ahead log checksum mismatch for the frame at offset {}",
+                    valid_len
+                )));
+            }
+            match serde_json::from_slice(payload) {
+                Ok(frame) => frames.push(frame),
+                Err(e) => bail!(CorruptIndex(format!(
+                    "invalid write-ahead log frame at offset {}: {}",
+                    valid_len, e
+                ))),
+            }
+            valid_len = start + len;
+        }
+        Ok(valid_len)
+    }
+
+    /// Returns the ops of `frames` to replay: the applied ones in sequence number order,
+    /// then the ones the crash interrupted in the order they were logged.
+    fn reconcile(frames: Vec<WalFrame>) -> Vec<WalEntry> {
+        let mut entries: Vec<WalEntry> = vec![];
+        for frame in frames {
+            match frame {
+                WalFrame::Op { id, op } => entries.push(WalEntry {
+                    id,
+                    seq_no: None,
+                    op,
+                }),
+                WalFrame::Applied { id, seq_no } => {
+                    if let Some(entry) = entries.iter_mut().rev().find(|e| e.id == id) {
+                        entry.seq_no = Some(seq_no);
+                    }
+                }
+                WalFrame::Failed { id } => entries.retain(|e| e.id != id),
+            }
+        }
+        entries.sort_by_key(|e| (e.seq_no.is_none(), e.seq_no, e.id));
+        entries
+    }
+
+    fn append(file: &mut File, frame: &WalFrame) -> Result<()> {
+        let payload = serde_json::to_vec(frame)?;
+        if payload.len() > u32::max_value() as usize {
+            bail!(IllegalArgument(format!(
+                "write-ahead log entry of {} bytes is too large",
+                payload.len()
+            )));
+        }
+        let mut data = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+        data.write_u32::<BigEndian>(payload.len() as u32)?;
+        data.write_u32::<BigEndian>(crc32::checksum_ieee(&payload))?;
+        data.extend_from_slice(&payload);
+        // a single write, so a crash leaves at most one partial frame at the end
+        file.write_all(&data)?;
+        Ok(())
+    }
+
+    /// Logs an indexing op, applies it and then logs the sequence number it was assigned.
+    pub fn log<F: FnOnce() -> Result<u64>>(&self, op: WalOp, apply: F) -> Result<u64> {
+        let _l = self.lock.read()?;
+        if !self.pending.lock()?.is_empty() {
+            bail!(IllegalState(
+                "the write-ahead log has entries of a previous writer, call recover_from_wal \
+                 before indexing"
+                    .into()
+            ));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::AcqRel);
+        Self::append(&mut *self.file.lock()?, &WalFrame::Op { id, op })?;
+        match apply() {
+            Ok(seq_no) => {
+                Self::append(&mut *self.file.lock()?, &WalFrame::Applied { id, seq_no })?;
+                Ok(seq_no)
+            }
+            Err(e) => {
+                // the op is replayed after a crash if this fails, as if it was interrupted
+                let failed = Self::append(&mut *self.file.lock()?, &WalFrame::Failed { id });
+                if let Err(log_err) = failed {
+                    warn!("failed to log the failure of op {}: {:?}", id, log_err);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs a commit or rollback and empties the log once it succeeded.
+    pub fn truncate_after<T, F: FnOnce() -> Result<T>>(&self, apply: F) -> Result<T> {
+        let _l = self.lock.write()?;
+        let res = apply()?;
+        if self.pending.lock()?.is_empty() {
+            self.file.lock()?.set_len(0)?;
+        }
+        Ok(res)
+    }
+
+    /// Replays the entries left by a previous writer in sequence number order and
+    /// rewrites the log with the sequence numbers of this writer.
+    pub fn recover<F>(&self, mut replay: F) -> Result<usize>
+    where
+        F: FnMut(ReplayOp) -> Result<u64>,
+    {
+        let _l = self.lock.write()?;
+        let mut pending = self.pending.lock()?;
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        let mut frames = Vec::with_capacity(pending.len() * 2);
+        for entry in pending.iter() {
+            let seq_no = replay(entry.op.clone().into())?;
+            let id = self.next_id.fetch_add(1, Ordering::AcqRel);
+            frames.push(WalFrame::Op {
+                id,
+                op: entry.op.clone(),
+            });
+            frames.push(WalFrame::Applied { id, seq_no });
+        }
+
+        // keep the old log until the replayed one is complete
+        let tmp_path = self.path.with_extension("log.tmp");
+        {
+            let mut tmp = File::create(&tmp_path)?;
+            for frame in &frames {
+                Self::append(&mut tmp, frame)?;
+            }
+            tmp.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        *self.file.lock()? = OpenOptions::new().append(true).open(&self.path)?;
+
+        let count = pending.len();
+        pending.clear();
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::analysis::StringTokenStream;
+    use core::codec::CodecEnum;
+    use core::search::query::PhraseQuery;
+
+    fn string_field(name: &str, value: &str) -> Field {
+        let mut field_type = FieldType::default();
+        field_type.index_options = IndexOptions::Docs;
+        field_type.tokenized = false;
+        Field::new(
+            name.to_string(),
+            field_type,
+            Some(VariantValue::VString(value.to_string())),
+            None,
+        )
+    }
+
+    fn entries(path: &Path) -> Vec<WalEntry> {
+        let mut frames = vec![];
+        WriteAheadLog::parse(&fs::read(path).unwrap(), &mut frames).unwrap();
+        WriteAheadLog::reconcile(frames)
+    }
+
+    // the offsets at which the frames of the log at `path` end
+    fn frame_ends(path: &Path) -> Vec<usize> {
+        let data = fs::read(path).unwrap();
+        let mut ends = vec![];
+        let mut end = 0;
+        while end < data.len() {
+            end += FRAME_HEADER_LEN + BigEndian::read_u32(&data[end..end + 4]) as usize;
+            ends.push(end);
+        }
+        ends
+    }
+
+    #[test]
+    fn test_reopen_keeps_complete_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let wal = WriteAheadLog::open(dir.path()).unwrap();
+            for i in 0..3 {
+                let id = format!("{}", i);
+                let op = WalOp::update_document(&[string_field("id", &id)], None).unwrap();
+                // log out of order, as concurrent writer threads would
+                wal.log(op, || Ok(10 - i)).unwrap();
+            }
+            let term = Term::new("id".into(), b"1".to_vec());
+            wal.log(WalOp::delete_terms(&[term]), || Ok(11)).unwrap();
+        }
+        // simulate a torn write of the last entry
+        let path = dir.path().join(WAL_FILE_NAME);
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"\0\0\0\x40\x12\x34\x56\x78{\"seq_no\":12,")
+            .unwrap();
+
+        let wal = WriteAheadLog::open(dir.path()).unwrap();
+        let op = WalOp::delete_terms(&[]);
+        assert!(wal.log(op, || Ok(1)).is_err());
+
+        let mut ids = vec![];
+        let count = wal
+            .recover(|op| {
+                match op {
+                    ReplayOp::UpdateDocument(doc, None) => {
+                        ids.push(doc[0].string_value().unwrap().to_string())
+                    }
+                    ReplayOp::DeleteTerms(terms) => ids.push(format!("-{}", terms[0].text()?)),
+                    _ => unreachable!(),
+                }
+                Ok(ids.len() as u64)
+            })
+            .unwrap();
+        assert_eq!(count, 4);
+        assert_eq!(ids, vec!["2", "1", "0", "-1"]);
+
+        // the log now holds the replayed entries and accepts new ones
+        wal.log(WalOp::delete_terms(&[]), || Ok(5)).unwrap();
+        let seq_nos: Vec<_> = entries(&path).iter().map(|e| e.seq_no).collect();
+        assert_eq!(seq_nos, (1..6).map(Some).collect::<Vec<_>>());
+
+        wal.truncate_after(|| Ok(())).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_logs_analyzed_fields() {
+        let field = string_field("body", "Some Text");
+        let mut field_type = field.field_type().clone();
+        field_type.tokenized = true;
+        let value = field.field_data().cloned();
+        // the string value is analyzed again on replay
+        let analyzed = Field::new("body".into(), field_type.clone(), value.clone(), None);
+        match WalOp::update_document(&[analyzed], None).unwrap().into() {
+            ReplayOp::UpdateDocument(doc, None) => {
+                assert!(doc[0].field_type().tokenized);
+                assert_eq!(doc[0].analyzed_value(), Some("Some Text"));
+            }
+            _ => unreachable!(),
+        }
+        // while the tokens of a token stream would be lost
+        let tokens = Box::new(StringTokenStream::new("Some Text".into()));
+        let field = Field::new("body".into(), field_type, value, Some(tokens));
+        assert!(WalOp::update_document(&[field], None).is_err());
+    }
+
+    #[test]
+    fn test_logs_delete_queries() {
+        let term = |id: &str| Term::new("id".into(), id.as_bytes().to_vec());
+        let query = |id: &str| -> Box<dyn Query<CodecEnum>> {
+            Box::new(TermQuery::new(term(id), 1.0, None))
+        };
+        let queries: Vec<Arc<dyn Query<CodecEnum>>> = vec![
+            Arc::new(MatchAllDocsQuery),
+            Arc::from(query("a")),
+            Arc::from(
+                BooleanQuery::build(vec![query("b")], vec![], vec![], vec![query("c")], 0).unwrap(),
+            ),
+        ];
+        let queries = match WalOp::delete_queries(&queries).unwrap().into() {
+            ReplayOp::DeleteQueries(queries) => queries,
+            _ => unreachable!(),
+        };
+        let queries: Vec<Box<dyn Query<CodecEnum>>> = queries
+            .into_iter()
+            .map(|q| q.into_query().unwrap())
+            .collect();
+        assert!(queries[0].as_any().is::<MatchAllDocsQuery>());
+        let term_query = queries[1].as_any().downcast_ref::<TermQuery>().unwrap();
+        assert_eq!(term_query.term(), &term("a"));
+        let boolean = queries[2]
+            .as_any()
+            .downcast_ref::<BooleanQuery<CodecEnum>>()
+            .unwrap();
+        assert_eq!(boolean.must_queries().len(), 1);
+        assert_eq!(boolean.must_not_queries().len(), 1);
+
+        // other queries can't be replayed
+        let phrase: Arc<dyn Query<CodecEnum>> = Arc::new(
+            PhraseQuery::new(vec![term("a"), term("b")], vec![0, 1], 0, None, None).unwrap(),
+        );
+        assert!(WalOp::delete_queries(&[phrase]).is_err());
+    }
+
+    #[test]
+    fn test_ops_are_logged_before_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        let crashed_dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(WAL_FILE_NAME);
+        let term = |id: &str| Term::new("id".into(), id.as_bytes().to_vec());
+        {
+            let wal = WriteAheadLog::open(dir.path()).unwrap();
+            wal.log(WalOp::delete_terms(&[term("a")]), || Ok(1))
+                .unwrap();
+            // a failed op is not replayed
+            let failed = wal.log(WalOp::delete_terms(&[term("b")]), || {
+                bail!(IllegalState("failed".into()))
+            });
+            assert!(failed.is_err());
+            // crash while the op is applied
+            wal.log(WalOp::delete_terms(&[term("c")]), || {
+                fs::copy(&path, crashed_dir.path().join(WAL_FILE_NAME))?;
+                Ok(2)
+            })
+            .unwrap();
+        }
+
+        let wal = WriteAheadLog::open(crashed_dir.path()).unwrap();
+        let mut replayed = vec![];
+        wal.recover(|op| {
+            if let ReplayOp::DeleteTerms(terms) = op {
+                replayed.push(terms[0].text()?);
+            }
+            Ok(replayed.len() as u64 + 10)
+        })
+        .unwrap();
+        assert_eq!(replayed, vec!["a", "c"]);
+        let seq_nos: Vec<_> = entries(&crashed_dir.path().join(WAL_FILE_NAME))
+            .iter()
+            .map(|e| e.seq_no)
+            .collect();
+        assert_eq!(seq_nos, vec![Some(11), Some(12)]);
+        // new ops don't reuse the ids of the recovered ones
+        wal.log(WalOp::delete_terms(&[]), || Ok(13)).unwrap();
+        assert_eq!(entries(&crashed_dir.path().join(WAL_FILE_NAME)).len(), 3);
+    }
+
+    #[test]
+    fn test_checksum_mismatch_is_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let wal = WriteAheadLog::open(dir.path()).unwrap();
+            for i in 0..2 {
+                let term = Term::new("id".into(), format!("{}", i).into_bytes());
+                wal.log(WalOp::delete_terms(&[term]), || Ok(i + 1)).unwrap();
+            }
+        }
+        let path = dir.path().join(WAL_FILE_NAME);
+        let data = fs::read(&path).unwrap();
+        let ends = frame_ends(&path);
+        assert_eq!(ends.len(), 4);
+
+        // a flipped payload byte in the last frame is a torn write and gets dropped, the
+        // op it confirmed is replayed as one interrupted by the crash
+        let mut torn = data.clone();
+        let last = torn.len() - 2;
+        torn[last] ^= 0xff;
+        fs::write(&path, &torn).unwrap();
+        let wal = WriteAheadLog::open(dir.path()).unwrap();
+        let seq_nos: Vec<_> = wal
+            .pending
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|e| e.seq_no)
+            .collect();
+        assert_eq!(seq_nos, vec![Some(1), None]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), ends[2] as u64);
+        drop(wal);
+
+        // while one before it means the log can't be trusted
+        let mut corrupt = data;
+        corrupt[ends[0] - 2] ^= 0xff;
+        fs::write(&path, &corrupt).unwrap();
+        assert!(WriteAheadLog::open(dir.path()).is_err());
+    }
+}
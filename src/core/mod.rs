@@ -19,3 +19,6 @@ pub mod index;
 pub mod search;
 pub mod store;
 pub mod util;
+
+#[cfg(test)]
+pub mod test_util;
@@ -59,6 +59,7 @@ const SENTINEL: u32 = u32::max_value();
 /// filters in order to decide on which filters are worth caching.
 pub struct UsageTrackingQueryCachingPolicy {
     recently_used_filters: Mutex<FrequencyTrackingRingBuffer>,
+    min_frequency: Option<u32>,
 }
 
 impl UsageTrackingQueryCachingPolicy {
@@ -67,9 +68,21 @@ impl UsageTrackingQueryCachingPolicy {
             Mutex::new(FrequencyTrackingRingBuffer::new(history_size, SENTINEL));
         UsageTrackingQueryCachingPolicy {
             recently_used_filters,
+            min_frequency: None,
         }
     }
 
+    /// Caches any query used at least `min_frequency` times among the last
+    /// `history_size` uses, instead of the thresholds derived from the query type.
+    pub fn with_min_frequency(
+        history_size: usize,
+        min_frequency: u32,
+    ) -> UsageTrackingQueryCachingPolicy {
+        let mut policy = Self::new(history_size);
+        policy.min_frequency = Some(min_frequency);
+        policy
+    }
+
     fn is_costly<C: Codec>(w: &dyn Weight<C>) -> bool {
         // TODO currently only PointRangeQuery is costly
         w.actual_query_type() == POINT_RANGE
@@ -80,7 +93,9 @@ impl UsageTrackingQueryCachingPolicy {
     }
 
     fn cache_min_frequency<C: Codec>(&self, w: &dyn Weight<C>) -> u32 {
-        if Self::is_costly(w) {
+        if let Some(min_frequency) = self.min_frequency {
+            min_frequency
+        } else if Self::is_costly(w) {
             2
         } else if Self::is_cheap(w) {
             20
@@ -15,6 +15,7 @@ use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::mem;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
 
@@ -22,10 +23,10 @@ use core::index::reader::LeafReaderContext;
 use core::search::cache::{LRUCache, QueryCachingPolicy};
 use core::search::collector::Collector;
 use core::search::scorer::{BulkScorer, ConstantScoreScorer};
-use core::search::Explanation;
 use core::search::{
     query::Weight, scorer::two_phase_next, scorer::Scorer, DocIdSet, DocIterator, NO_MORE_DOCS,
 };
+use core::search::{Explanation, MatchesIterator};
 use core::util::external::Deferred;
 use core::util::UnsignedShift;
 use core::util::{
@@ -93,14 +94,26 @@ impl LeafCache {
         }
     }
 
-    pub fn put_if_absent(&mut self, query_key: &str, set: CacheDocIdSetEnum) {
+    /// returns whether `set` was added
+    pub fn put_if_absent(&mut self, query_key: &str, set: CacheDocIdSetEnum) -> bool {
         if !self.leaf_cache.contains_key(query_key) {
             self.leaf_cache.insert(query_key.to_string(), set);
+            true
+        } else {
+            false
         }
     }
 
-    pub fn remove(&mut self, query_key: &str) {
-        self.leaf_cache.remove(query_key);
+    /// returns the removed entry, if any
+    pub fn remove(&mut self, query_key: &str) -> Option<CacheDocIdSetEnum> {
+        self.leaf_cache.remove(query_key)
+    }
+
+    fn ram_bytes_used(&self) -> usize {
+        self.leaf_cache
+            .values()
+            .map(|set| set.ram_bytes_used_estimate())
+            .sum()
     }
 }
 
@@ -115,8 +128,15 @@ struct CacheData {
     pub cache: HashMap<String, LeafCache>,
 
     max_size: usize,
+    max_ram_bytes: usize,
+    // the bytes used by the cached sets of all the leaves, never more than `max_ram_bytes`
+    ram_bytes_used: usize,
     min_size: i32,
     min_size_ratio: f32,
+
+    hit_count: u64,
+    miss_count: u64,
+    eviction_count: u64,
 }
 
 impl CacheData {
@@ -131,7 +151,7 @@ impl CacheData {
         }
     }
 
-    /// Whether evictions are required.
+    /// Whether evictions are required before adding a query.
     fn requires_eviction(&self) -> Result<bool> {
         Ok(self.unique_queries.len() >= self.max_size)
     }
@@ -141,13 +161,20 @@ impl CacheData {
         query_key: &str,
         leaf_reader: &LeafReaderContext<'_, C>,
     ) -> Result<Option<CachedDocIdSetIterEnum>> {
-        if let Some(leaf_cache) = self.cache.get(leaf_reader.reader.core_cache_key()) {
-            if let Some(singleton) = self.unique_queries.get(&query_key.to_string()) {
+        let cached = match self.cache.get(leaf_reader.reader.core_cache_key()) {
+            Some(leaf_cache) => match self.unique_queries.get(&query_key.to_string()) {
                 // this get call moves the query to the most-recently-used position
-                return leaf_cache.get(singleton);
-            }
+                Some(singleton) => leaf_cache.get(singleton)?,
+                None => None,
+            },
+            None => None,
+        };
+        if cached.is_some() {
+            self.hit_count += 1;
+        } else {
+            self.miss_count += 1;
         }
-        Ok(None)
+        Ok(cached)
     }
 
     // return true if new LeafCache is added to process core reader drop listener
@@ -157,6 +184,11 @@ impl CacheData {
         leaf_reader: &LeafReaderContext<'_, C>,
         set: CacheDocIdSetEnum,
     ) -> Result<bool> {
+        let set_bytes = set.ram_bytes_used_estimate();
+        if set_bytes > self.max_ram_bytes {
+            // could only be cached by evicting everything else, and still exceed the budget
+            return Ok(false);
+        }
         self.evict_if_necessary()?;
 
         let query_key = if self.unique_queries.contains_key(&query_key.to_string()) {
@@ -181,45 +213,77 @@ impl CacheData {
 
         {
             let leaf_cache = self.cache.get_mut(key).unwrap();
-            leaf_cache.put_if_absent(&query_key, set);
+            if leaf_cache.put_if_absent(&query_key, set) {
+                self.ram_bytes_used += set_bytes;
+            }
+        }
+        // the query was just used, so the least recently used ones go first
+        while self.ram_bytes_used > self.max_ram_bytes {
+            self.evict_last()?;
         }
 
         Ok(new_entry)
     }
 
     fn evict_if_necessary(&mut self) -> Result<()> {
-        if self.requires_eviction()? {
-            loop {
-                if !self.requires_eviction()? {
-                    break;
-                }
-
-                if let Some(key) = self.unique_queries.remove_last() {
-                    self.on_eviction(&key);
-                } else {
-                    bail!(
-                        "Removal from the cache failed! This is probably due to a query which has \
-                         been modified after having been put into the cache or a badly \
-                         implemented clone()."
-                    );
-                }
-            }
+        while self.requires_eviction()? {
+            self.evict_last()?;
         }
 
         Ok(())
     }
 
+    /// Evicts the least recently used query.
+    fn evict_last(&mut self) -> Result<()> {
+        if let Some(key) = self.unique_queries.remove_last() {
+            self.on_eviction(&key);
+            Ok(())
+        } else {
+            bail!(
+                "Removal from the cache failed! This is probably due to a query which has been \
+                 modified after having been put into the cache or a badly implemented clone()."
+            );
+        }
+    }
+
     fn on_eviction(&mut self, query_key: &str) {
         for leaf_cache in self.cache.values_mut() {
-            leaf_cache.remove(query_key);
+            if let Some(set) = leaf_cache.remove(query_key) {
+                self.ram_bytes_used -= set.ram_bytes_used_estimate();
+                self.eviction_count += 1;
+            }
         }
     }
+
+    fn on_core_closed(&mut self, core_key: &str) {
+        if let Some(leaf_cache) = self.cache.remove(core_key) {
+            self.ram_bytes_used -= leaf_cache.ram_bytes_used();
+        }
+    }
+
+    fn cache_count(&self) -> usize {
+        self.cache.values().map(|c| c.leaf_cache.len()).sum()
+    }
+
+    fn cache_size(&self) -> usize {
+        debug_assert_eq!(
+            self.ram_bytes_used,
+            self.cache
+                .values()
+                .map(|c| c.ram_bytes_used())
+                .sum::<usize>()
+        );
+        self.ram_bytes_used
+    }
 }
 
 /// A [`QueryCache`] that evicts queries using a LRU (least-recently-used)
 /// eviction policy in order to remain under a given maximum size and number of
 /// bytes used.
 ///
+/// The bytes used are the estimated sizes of the cached `DocIdSet`s, they never exceed
+/// `max_ram_bytes`: a set larger than the whole budget is not cached at all.
+///
 /// This class is thread-safe.
 ///
 /// Note that query eviction runs in linear time with the total number of
@@ -231,20 +295,61 @@ pub struct LRUQueryCache {
 }
 
 impl LRUQueryCache {
-    pub fn new(max_size: usize) -> LRUQueryCache {
-        // let max_size = 10;
+    /// Caches the sets of at most `max_entries` queries, using at most `max_ram_bytes`.
+    pub fn new(max_ram_bytes: usize, max_entries: usize) -> LRUQueryCache {
+        Self::with_leaf_limits(max_ram_bytes, max_entries, 10000, 0.03f32)
+    }
+
+    /// Only segments with at least `min_size` docs and at least `min_size_ratio` of
+    /// the docs of the index get cached.
+    pub fn with_leaf_limits(
+        max_ram_bytes: usize,
+        max_entries: usize,
+        min_size: i32,
+        min_size_ratio: f32,
+    ) -> LRUQueryCache {
         let cache_data = CacheData {
-            unique_queries: LRUCache::with_capacity(max_size),
+            unique_queries: LRUCache::with_capacity(max_entries),
             cache: HashMap::new(),
-            max_size,
-            min_size: 10000,
-            min_size_ratio: 0.03f32,
+            max_size: max_entries,
+            max_ram_bytes,
+            ram_bytes_used: 0,
+            min_size,
+            min_size_ratio,
+            hit_count: 0,
+            miss_count: 0,
+            eviction_count: 0,
         };
 
         LRUQueryCache {
             cache_data: Arc::new(RwLock::new(cache_data)),
         }
     }
+
+    /// The number of lookups of a segment's entry that found a cached `DocIdSet`.
+    pub fn hit_count(&self) -> u64 {
+        self.cache_data.read().unwrap().hit_count
+    }
+
+    /// The number of lookups of a segment's entry that found nothing cached.
+    pub fn miss_count(&self) -> u64 {
+        self.cache_data.read().unwrap().miss_count
+    }
+
+    /// The number of `DocIdSet`s currently cached, summed over all segments.
+    pub fn cache_count(&self) -> usize {
+        self.cache_data.read().unwrap().cache_count()
+    }
+
+    /// An estimate of the bytes used by the cached `DocIdSet`s.
+    pub fn cache_size(&self) -> usize {
+        self.cache_data.read().unwrap().cache_size()
+    }
+
+    /// The number of `DocIdSet`s removed because their query was evicted.
+    pub fn eviction_count(&self) -> u64 {
+        self.cache_data.read().unwrap().eviction_count
+    }
 }
 
 impl<C: Codec> QueryCache<C> for LRUQueryCache {
@@ -399,7 +504,7 @@ impl<C: Codec> CachingWrapperWeight<C> {
                         .reader
                         .add_core_drop_listener(Deferred::new(move || {
                             let core_key = key;
-                            cache_data.write().unwrap().cache.remove(&core_key);
+                            cache_data.write().unwrap().on_core_closed(&core_key);
                         }))
                 }
 
@@ -509,6 +614,14 @@ impl<C: Codec> Weight<C> for CachingWrapperWeight<C> {
             ))
         }
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.weight.matches(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for CachingWrapperWeight<C> {
@@ -577,6 +690,16 @@ impl RoaringDocIdSet {
             cardinality,
         }
     }
+
+    fn ram_bytes_used_estimate(&self) -> usize {
+        let blocks = self.doc_id_sets.len() * mem::size_of::<Option<DocIdSetEnum>>();
+        self.doc_id_sets
+            .iter()
+            .flatten()
+            .map(|set| set.ram_bytes_used_estimate())
+            .sum::<usize>()
+            + blocks
+    }
 }
 
 struct RoaringDocIdSetBuilder {
@@ -852,6 +975,15 @@ enum CacheDocIdSetEnum {
     Roaring(RoaringDocIdSet),
 }
 
+impl CacheDocIdSetEnum {
+    fn ram_bytes_used_estimate(&self) -> usize {
+        match self {
+            CacheDocIdSetEnum::Bit(s) => s.ram_bytes_used_estimate(),
+            CacheDocIdSetEnum::Roaring(s) => s.ram_bytes_used_estimate(),
+        }
+    }
+}
+
 impl DocIdSet for CacheDocIdSetEnum {
     type Iter = CachedDocIdSetIterEnum;
 
@@ -873,6 +1005,13 @@ impl DocIdSet for CacheDocIdSetEnum {
             }
         }
     }
+
+    fn bits(&self) -> Option<&dyn Bits> {
+        match self {
+            CacheDocIdSetEnum::Bit(i) => i.bits(),
+            CacheDocIdSetEnum::Roaring(_) => None,
+        }
+    }
 }
 
 enum CachedDocIdSetIterEnum {
@@ -944,3 +1083,133 @@ impl DocIterator for CachedDocIdSetIterEnum {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::doc::Term;
+    use core::index::reader::IndexReader;
+    use core::search::cache::UsageTrackingQueryCachingPolicy;
+    use core::search::query::{Query, TermQuery};
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::{keyword_field, new_fs_writer};
+
+    fn term_query(value: &str) -> TermQuery {
+        TermQuery::new(
+            Term::new("tag".into(), value.as_bytes().to_vec()),
+            1.0,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_cache_statistics() {
+        let (_dir, writer) = new_fs_writer();
+        for v in &["a", "b", "a", "c", "a"] {
+            writer.add_document(vec![keyword_field("tag", v)]).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let leaves = searcher.reader().leaves();
+
+        // cache every segment, but only once a query has been used 3 times
+        let cache = LRUQueryCache::with_leaf_limits(usize::max_value(), 100, 0, 0.0);
+        let policy = Arc::new(UsageTrackingQueryCachingPolicy::with_min_frequency(256, 3));
+
+        let query = term_query("a");
+        let mut results = vec![];
+        for _ in 0..5 {
+            let weight = query.create_weight(&searcher, false).unwrap();
+            let weight = cache.do_cache(weight, Arc::clone(&policy));
+            let mut scorer = weight.create_scorer(&leaves[0]).unwrap().unwrap();
+            let mut docs = vec![];
+            loop {
+                let doc = scorer.next().unwrap();
+                if doc == NO_MORE_DOCS {
+                    break;
+                }
+                docs.push(doc);
+            }
+            results.push(docs);
+        }
+
+        assert!(results.iter().all(|docs| docs == &vec![0, 2, 4]));
+        // the first three uses miss, the third one populates the cache
+        assert_eq!(cache.miss_count(), 3);
+        assert_eq!(cache.hit_count(), 2);
+        assert_eq!(cache.cache_count(), 1);
+        assert!(cache.cache_size() > 0);
+        assert_eq!(cache.eviction_count(), 0);
+
+        // a cache holding a single query evicts the previous one
+        let cache = LRUQueryCache::with_leaf_limits(usize::max_value(), 1, 0, 0.0);
+        let policy = Arc::new(UsageTrackingQueryCachingPolicy::with_min_frequency(256, 1));
+        for value in &["a", "b"] {
+            let weight = term_query(value).create_weight(&searcher, false).unwrap();
+            let weight = cache.do_cache(weight, Arc::clone(&policy));
+            weight.create_scorer(&leaves[0]).unwrap();
+        }
+        assert_eq!(cache.cache_count(), 1);
+        assert_eq!(cache.eviction_count(), 1);
+    }
+
+    #[test]
+    fn test_cache_ram_budget() {
+        let (_dir, writer) = new_fs_writer();
+        for v in &["a", "b", "c", "d", "a", "b", "c", "d"] {
+            writer.add_document(vec![keyword_field("tag", v)]).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let leaves = searcher.reader().leaves();
+        // a query is cached on its first use, or never with a `min_frequency` of 1000
+        let search_with = |cache: &LRUQueryCache, value: &str, min_frequency: u32| {
+            let policy = UsageTrackingQueryCachingPolicy::with_min_frequency(256, min_frequency);
+            let weight = term_query(value).create_weight(&searcher, false).unwrap();
+            let weight = cache.do_cache(weight, Arc::new(policy));
+            weight.create_scorer(&leaves[0]).unwrap();
+        };
+        let search = |cache: &LRUQueryCache, value: &str| search_with(cache, value, 1);
+        // returns whether the set of `value` is cached, which makes it the most recently used
+        let is_cached = |cache: &LRUQueryCache, value: &str| {
+            let hits = cache.hit_count();
+            search_with(cache, value, 1000);
+            cache.hit_count() > hits
+        };
+
+        // every query matches 2 docs of the segment, so their sets have the same size
+        let unbounded = LRUQueryCache::with_leaf_limits(usize::max_value(), 100, 0, 0.0);
+        search(&unbounded, "a");
+        let set_bytes = unbounded.cache_size();
+        assert!(set_bytes > 0);
+
+        // a set larger than the budget is not cached
+        let cache = LRUQueryCache::with_leaf_limits(set_bytes - 1, 100, 0, 0.0);
+        search(&cache, "a");
+        assert_eq!(cache.cache_count(), 0);
+        assert_eq!(cache.cache_size(), 0);
+
+        let max_ram_bytes = set_bytes * 2 + set_bytes / 2;
+        let cache = LRUQueryCache::with_leaf_limits(max_ram_bytes, 100, 0, 0.0);
+        for value in &["a", "b", "c"] {
+            search(&cache, value);
+            assert!(cache.cache_size() <= max_ram_bytes);
+        }
+        assert_eq!(cache.cache_count(), 2);
+        assert_eq!(cache.eviction_count(), 1);
+        assert!(!is_cached(&cache, "a"));
+        // "b" is used again, so "c" is the least recently used when "d" is added
+        assert!(is_cached(&cache, "b"));
+        search(&cache, "d");
+        assert!(cache.cache_size() <= max_ram_bytes);
+        assert!(is_cached(&cache, "b"));
+        assert!(is_cached(&cache, "d"));
+        assert!(!is_cached(&cache, "c"));
+    }
+}
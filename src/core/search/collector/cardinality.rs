@@ -0,0 +1,304 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use core::codec::doc_values::{
+    NumericDocValues, SortedDocValues, SortedNumericDocValues, SortedSetDocValues, NO_MORE_ORDS,
+};
+use core::codec::Codec;
+use core::doc::DocValuesType;
+use core::index::reader::{LeafReader, LeafReaderContext};
+use core::search::collector::{
+    Collector, MergeableCollector, ParallelLeafCollector, SearchCollector,
+};
+use core::search::scorer::Scorer;
+use core::util::{BitsMut, DocId, HyperLogLogPlusPlus};
+use error::{
+    ErrorKind::{IllegalArgument, IllegalState},
+    Result, ResultExt,
+};
+
+/// The doc values of the cardinality field in the current leaf.
+enum LeafValues {
+    Numeric(Box<dyn NumericDocValues>, Box<dyn BitsMut>),
+    SortedNumeric(Box<dyn SortedNumericDocValues>),
+    // the ords of the leaf whose value was already added
+    Sorted(Box<dyn SortedDocValues>, Vec<bool>),
+    SortedSet(Box<dyn SortedSetDocValues>, Vec<bool>),
+}
+
+impl LeafValues {
+    fn new<C: Codec>(reader: &LeafReaderContext<'_, C>, field: &str) -> Result<Option<Self>> {
+        let doc_values_type = match reader.reader.field_info(field) {
+            Some(info) => info.doc_values_type,
+            None => {
+                return Ok(None);
+            }
+        };
+        let values = match doc_values_type {
+            DocValuesType::Null => {
+                return Ok(None);
+            }
+            DocValuesType::Numeric => LeafValues::Numeric(
+                reader.reader.get_numeric_doc_values(field)?,
+                reader.reader.get_docs_with_field(field)?,
+            ),
+            DocValuesType::SortedNumeric => {
+                LeafValues::SortedNumeric(reader.reader.get_sorted_numeric_doc_values(field)?)
+            }
+            DocValuesType::Sorted => {
+                let values = reader.reader.get_sorted_doc_values(field)?;
+                let seen = vec![false; values.value_count()];
+                LeafValues::Sorted(values, seen)
+            }
+            DocValuesType::SortedSet => {
+                let values = reader.reader.get_sorted_set_doc_values(field)?;
+                let seen = vec![false; values.get_value_count()];
+                LeafValues::SortedSet(values, seen)
+            }
+            DocValuesType::Binary => bail!(IllegalArgument(format!(
+                "can't count the distinct values of binary doc values field '{}'",
+                field
+            ))),
+        };
+        Ok(Some(values))
+    }
+
+    fn add(&mut self, doc: DocId, hll: &mut HyperLogLogPlusPlus) -> Result<()> {
+        match self {
+            LeafValues::Numeric(values, docs_with_field) => {
+                if docs_with_field.get(doc as usize)? {
+                    hll.add_long(values.get_mut(doc)?);
+                }
+            }
+            LeafValues::SortedNumeric(values) => {
+                values.set_document(doc)?;
+                for i in 0..values.count() {
+                    hll.add_long(values.value_at(i)?);
+                }
+            }
+            LeafValues::Sorted(values, seen) => {
+                let ord = values.get_ord(doc)?;
+                if ord >= 0 && !seen[ord as usize] {
+                    seen[ord as usize] = true;
+                    hll.add_bytes(&values.lookup_ord(ord)?);
+                }
+            }
+            LeafValues::SortedSet(values, seen) => {
+                values.set_document(doc)?;
+                loop {
+                    let ord = values.next_ord()?;
+                    if ord == NO_MORE_ORDS {
+                        break;
+                    }
+                    if !seen[ord as usize] {
+                        seen[ord as usize] = true;
+                        hll.add_bytes(&values.lookup_ord(ord)?);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A `Collector` estimating the number of distinct doc values of `field` among the
+/// matching docs, numeric values are counted by value and sorted values by their bytes.
+///
+/// Docs without a value are not counted, the estimate is accurate to the relative error
+/// of `HyperLogLogPlusPlus` for the given precision.
+pub struct CardinalityCollector {
+    field: String,
+    hll: HyperLogLogPlusPlus,
+    values: Option<LeafValues>,
+    channel: Option<(Sender<HyperLogLogPlusPlus>, Receiver<HyperLogLogPlusPlus>)>,
+}
+
+impl CardinalityCollector {
+    pub fn new(field: &str, precision: u8) -> Result<Self> {
+        Ok(CardinalityCollector {
+            field: field.to_string(),
+            hll: HyperLogLogPlusPlus::new(precision)?,
+            values: None,
+            channel: None,
+        })
+    }
+
+    /// Returns the estimated number of distinct values collected.
+    pub fn cardinality(&self) -> u64 {
+        self.hll.cardinality()
+    }
+
+    pub fn hll(&self) -> &HyperLogLogPlusPlus {
+        &self.hll
+    }
+}
+
+impl SearchCollector for CardinalityCollector {
+    type LC = CardinalityLeafCollector;
+
+    fn set_next_reader<C: Codec>(&mut self, reader: &LeafReaderContext<'_, C>) -> Result<()> {
+        self.values = LeafValues::new(reader, &self.field)?;
+        Ok(())
+    }
+
+    fn support_parallel(&self) -> bool {
+        true
+    }
+
+    fn init_parallel(&mut self) {
+        if self.channel.is_none() {
+            self.channel = Some(channel());
+        }
+    }
+
+    fn leaf_collector<C: Codec>(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<CardinalityLeafCollector> {
+        Ok(CardinalityLeafCollector {
+            hll: HyperLogLogPlusPlus::new(self.hll.precision())?,
+            values: LeafValues::new(reader, &self.field)?,
+            channel: self.channel.as_ref().unwrap().0.clone(),
+        })
+    }
+
+    fn finish_parallel(&mut self) -> Result<()> {
+        if let Some((sender, receiver)) = self.channel.take() {
+            drop(sender);
+            while let Ok(hll) = receiver.recv() {
+                self.hll.merge(&hll)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Collector for CardinalityCollector {
+    fn needs_scores(&self) -> bool {
+        false
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, doc: DocId, _scorer: &mut S) -> Result<()> {
+        if let Some(ref mut values) = self.values {
+            values.add(doc, &mut self.hll)?;
+        }
+        Ok(())
+    }
+}
+
+impl MergeableCollector for CardinalityCollector {
+    type Merged = u64;
+
+    fn merge_collectors(collectors: Vec<Self>) -> Result<u64> {
+        let mut collectors = collectors.into_iter();
+        if let Some(mut merged) = collectors.next() {
+            for collector in collectors {
+                merged.hll.merge(&collector.hll)?;
+            }
+            Ok(merged.cardinality())
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+pub struct CardinalityLeafCollector {
+    hll: HyperLogLogPlusPlus,
+    values: Option<LeafValues>,
+    channel: Sender<HyperLogLogPlusPlus>,
+}
+
+impl Collector for CardinalityLeafCollector {
+    fn needs_scores(&self) -> bool {
+        false
+    }
+
+    fn collect<S: Scorer + ?Sized>(&mut self, doc: DocId, _scorer: &mut S) -> Result<()> {
+        if let Some(ref mut values) = self.values {
+            values.add(doc, &mut self.hll)?;
+        }
+        Ok(())
+    }
+}
+
+impl ParallelLeafCollector for CardinalityLeafCollector {
+    fn finish_leaf(&mut self) -> Result<()> {
+        self.channel
+            .send(self.hll.clone())
+            .chain_err(|| IllegalState("channel unexpected closed before search complete".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use core::doc::{Fieldable, NumericDocValuesField, SortedSetDocValuesField};
+    use core::index::reader::IndexReader;
+    use core::search::query::MatchAllDocsQuery;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::new_fs_writer;
+    use core::util::DEFAULT_HLL_PRECISION;
+
+    use std::sync::Arc;
+
+    #[test]
+    fn test_cardinality_of_doc_values() {
+        const NUM_DOCS: i64 = 20_000;
+        const DISTINCT: i64 = 5_000;
+
+        let (_dir, writer) = new_fs_writer();
+        for i in 0..NUM_DOCS {
+            let mut doc: Vec<Box<dyn Fieldable>> =
+                vec![Box::new(NumericDocValuesField::new("id", i))];
+            // every other doc has no value
+            if i % 2 == 0 {
+                let value = (i / 2) % DISTINCT * 1_000;
+                doc.push(Box::new(NumericDocValuesField::new("num", value)));
+                let tag = format!("tag{}", value);
+                doc.push(Box::new(SortedSetDocValuesField::new(
+                    "tag",
+                    tag.as_bytes(),
+                )));
+            }
+            writer.add_document(doc).unwrap();
+            if i % 7_000 == 6_999 {
+                writer.commit().unwrap();
+            }
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        assert!(reader.leaves().len() > 1);
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        for field in &["num", "tag"] {
+            let mut collector = CardinalityCollector::new(field, DEFAULT_HLL_PRECISION).unwrap();
+            searcher.search(&MatchAllDocsQuery, &mut collector).unwrap();
+            let error = (collector.cardinality() as f64 - DISTINCT as f64).abs() / DISTINCT as f64;
+            // three standard errors
+            assert!(
+                error <= 3.0 * collector.hll().relative_error(),
+                "estimated {} distinct values of {}",
+                collector.cardinality(),
+                field
+            );
+        }
+
+        let mut collector = CardinalityCollector::new("missing", DEFAULT_HLL_PRECISION).unwrap();
+        searcher.search(&MatchAllDocsQuery, &mut collector).unwrap();
+        assert_eq!(collector.cardinality(), 0);
+        assert!(CardinalityCollector::new("num", 30).is_err());
+    }
+}
@@ -27,6 +27,10 @@ mod chain;
 
 pub use self::chain::*;
 
+mod cardinality;
+
+pub use self::cardinality::*;
+
 use error::Result;
 
 use core::codec::Codec;
@@ -148,3 +152,12 @@ impl<'a, T: Collector + 'a> Collector for &'a mut T {
 pub trait ParallelLeafCollector: Collector + Send + 'static {
     fn finish_leaf(&mut self) -> Result<()>;
 }
+
+/// A collector whose results can be merged with the ones of other collectors of the
+/// same kind, used when each leaf is collected by its own collector.
+pub trait MergeableCollector: SearchCollector + Sized {
+    type Merged;
+
+    /// Merges the collectors of all the leaves into the final result.
+    fn merge_collectors(collectors: Vec<Self>) -> Result<Self::Merged>;
+}
@@ -19,7 +19,9 @@ use std::usize;
 
 use core::codec::Codec;
 use core::index::reader::LeafReaderContext;
-use core::search::collector::{Collector, ParallelLeafCollector, SearchCollector};
+use core::search::collector::{
+    Collector, MergeableCollector, ParallelLeafCollector, SearchCollector,
+};
 use core::search::scorer::Scorer;
 use core::search::sort_field::{ScoreDoc, ScoreDocHit, TopDocs, TopScoreDocs};
 use core::util::DocId;
@@ -182,6 +184,26 @@ impl Collector for TopDocsCollector {
     }
 }
 
+impl MergeableCollector for TopDocsCollector {
+    type Merged = TopDocs;
+
+    fn merge_collectors(collectors: Vec<Self>) -> Result<TopDocs> {
+        let estimated_hits = collectors
+            .iter()
+            .map(|c| c.base.estimated_hits)
+            .max()
+            .unwrap_or(0);
+        let mut merged = TopDocsCollector::new(estimated_hits);
+        for collector in collectors {
+            merged.base.total_hits += collector.base.total_hits;
+            for doc in collector.base.pq {
+                merged.add_doc(doc.doc, doc.score);
+            }
+        }
+        Ok(merged.top_docs())
+    }
+}
+
 struct LeafTopDocs {
     docs: Vec<ScoreDoc>,
     total_hits: usize,
@@ -0,0 +1,403 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Reverse;
+
+use core::codec::PostingIterator;
+use core::search::intervals::{IntervalIterator, NO_MORE_INTERVALS};
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::Result;
+
+/// The intervals of a single term, one per position.
+pub(crate) struct TermIntervalIterator<T: PostingIterator> {
+    postings: T,
+    freq: i32,
+    upto: i32,
+    pos: i32,
+}
+
+impl<T: PostingIterator> TermIntervalIterator<T> {
+    pub fn new(postings: T) -> Self {
+        TermIntervalIterator {
+            postings,
+            freq: 0,
+            upto: 0,
+            pos: -1,
+        }
+    }
+
+    fn reset(&mut self, doc: DocId) -> Result<DocId> {
+        if doc != NO_MORE_DOCS {
+            self.freq = self.postings.freq()?;
+        }
+        self.upto = 0;
+        self.pos = -1;
+        Ok(doc)
+    }
+}
+
+impl<T: PostingIterator> IntervalIterator for TermIntervalIterator<T> {
+    fn start(&self) -> i32 {
+        self.pos
+    }
+
+    fn end(&self) -> i32 {
+        self.pos
+    }
+
+    fn next_interval(&mut self) -> Result<i32> {
+        if self.upto >= self.freq {
+            self.pos = NO_MORE_INTERVALS;
+        } else {
+            self.pos = self.postings.next_position()?;
+            self.upto += 1;
+        }
+        Ok(self.pos)
+    }
+}
+
+impl<T: PostingIterator> DocIterator for TermIntervalIterator<T> {
+    fn doc_id(&self) -> DocId {
+        self.postings.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.postings.next()?;
+        self.reset(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.postings.advance(target)?;
+        self.reset(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.postings.cost()
+    }
+}
+
+/// How a `ConjunctionIntervalIterator` combines the intervals of its sub iterators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum IntervalFunction {
+    /// The sub intervals follow each other without any gap.
+    Block,
+    /// The sub intervals appear in order without overlapping.
+    Ordered,
+    /// The sub intervals appear in any order, possibly overlapping.
+    Unordered,
+    /// The intervals of the first sub iterator containing an interval of the second.
+    Containing,
+}
+
+/// Combines the intervals of sub iterators on the docs they all match.
+pub(crate) struct ConjunctionIntervalIterator {
+    subs: Vec<Box<dyn IntervalIterator>>,
+    function: IntervalFunction,
+    doc: DocId,
+    start: i32,
+    end: i32,
+    // a sub iterator has no interval left in the current doc
+    exhausted: bool,
+}
+
+impl ConjunctionIntervalIterator {
+    pub fn new(subs: Vec<Box<dyn IntervalIterator>>, function: IntervalFunction) -> Self {
+        debug_assert!(!subs.is_empty());
+        debug_assert!(function != IntervalFunction::Containing || subs.len() == 2);
+        ConjunctionIntervalIterator {
+            subs,
+            function,
+            doc: -1,
+            start: -1,
+            end: -1,
+            exhausted: false,
+        }
+    }
+
+    fn do_advance(&mut self, mut target: DocId) -> Result<DocId> {
+        'agree: loop {
+            for sub in &mut self.subs {
+                let doc = if sub.doc_id() < target {
+                    sub.advance(target)?
+                } else {
+                    sub.doc_id()
+                };
+                if doc == NO_MORE_DOCS {
+                    self.doc = NO_MORE_DOCS;
+                    return Ok(NO_MORE_DOCS);
+                }
+                if doc > target {
+                    target = doc;
+                    continue 'agree;
+                }
+            }
+            break;
+        }
+        self.doc = target;
+        self.reset()?;
+        Ok(target)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.start = -1;
+        self.end = -1;
+        self.exhausted = false;
+        match self.function {
+            IntervalFunction::Block => {}
+            IntervalFunction::Ordered => {
+                self.subs[0].next_interval()?;
+            }
+            IntervalFunction::Unordered => {
+                for sub in &mut self.subs {
+                    if sub.next_interval()? == NO_MORE_INTERVALS {
+                        self.exhausted = true;
+                    }
+                }
+            }
+            IntervalFunction::Containing => {
+                self.exhausted = self.subs[1].next_interval()? == NO_MORE_INTERVALS;
+            }
+        }
+        Ok(())
+    }
+
+    fn no_more_intervals(&mut self) -> Result<i32> {
+        self.start = NO_MORE_INTERVALS;
+        self.end = NO_MORE_INTERVALS;
+        Ok(NO_MORE_INTERVALS)
+    }
+
+    fn next_block(&mut self) -> Result<i32> {
+        if self.subs[0].next_interval()? == NO_MORE_INTERVALS {
+            return self.no_more_intervals();
+        }
+        let mut i = 1;
+        while i < self.subs.len() {
+            while self.subs[i].start() <= self.subs[i - 1].end() {
+                if self.subs[i].next_interval()? == NO_MORE_INTERVALS {
+                    return self.no_more_intervals();
+                }
+            }
+            if self.subs[i].start() == self.subs[i - 1].end() + 1 {
+                i += 1;
+            } else {
+                // a gap, start over from the next interval of the first sub iterator
+                if self.subs[0].next_interval()? == NO_MORE_INTERVALS {
+                    return self.no_more_intervals();
+                }
+                i = 1;
+            }
+        }
+        self.start = self.subs[0].start();
+        self.end = self.subs[self.subs.len() - 1].end();
+        Ok(self.start)
+    }
+
+    fn next_ordered(&mut self) -> Result<i32> {
+        let n = self.subs.len();
+        self.start = NO_MORE_INTERVALS;
+        self.end = NO_MORE_INTERVALS;
+        // the start of the last sub interval of the latest match, a smaller match must
+        // end before it
+        let mut bound = i32::max_value();
+        let mut i = 1;
+        loop {
+            loop {
+                if self.subs[i - 1].end() >= bound {
+                    return Ok(self.start);
+                }
+                if i == n || self.subs[i].start() > self.subs[i - 1].end() {
+                    break;
+                }
+                loop {
+                    if self.subs[i].end() >= bound
+                        || self.subs[i].next_interval()? == NO_MORE_INTERVALS
+                    {
+                        return Ok(self.start);
+                    }
+                    if self.subs[i].start() > self.subs[i - 1].end() {
+                        break;
+                    }
+                }
+                i += 1;
+            }
+            self.start = self.subs[0].start();
+            self.end = self.subs[n - 1].end();
+            bound = self.subs[n - 1].start();
+            i = 1;
+            if self.subs[0].next_interval()? == NO_MORE_INTERVALS {
+                return Ok(self.start);
+            }
+        }
+    }
+
+    // the sub iterator whose interval starts first, the widest one on ties
+    fn first_sub(&self) -> usize {
+        (0..self.subs.len())
+            .min_by_key(|&i| (self.subs[i].start(), Reverse(self.subs[i].end())))
+            .unwrap()
+    }
+
+    fn max_end(&self) -> i32 {
+        self.subs.iter().map(|s| s.end()).max().unwrap()
+    }
+
+    fn next_unordered(&mut self) -> Result<i32> {
+        if self.exhausted {
+            return self.no_more_intervals();
+        }
+        // move on from the sub intervals the previous match starts with
+        loop {
+            let first = self.first_sub();
+            if self.subs[first].start() != self.start {
+                break;
+            }
+            if self.subs[first].next_interval()? == NO_MORE_INTERVALS {
+                self.exhausted = true;
+                return self.no_more_intervals();
+            }
+        }
+        // then shrink the match from the left as long as its end doesn't move
+        loop {
+            let first = self.first_sub();
+            self.start = self.subs[first].start();
+            self.end = self.max_end();
+            if self.subs[first].end() == self.end {
+                return Ok(self.start);
+            }
+            if self.subs[first].next_interval()? == NO_MORE_INTERVALS {
+                self.exhausted = true;
+                return Ok(self.start);
+            }
+            if self.max_end() != self.end {
+                return Ok(self.start);
+            }
+        }
+    }
+
+    fn next_containing(&mut self) -> Result<i32> {
+        if self.exhausted {
+            return self.no_more_intervals();
+        }
+        while self.subs[0].next_interval()? != NO_MORE_INTERVALS {
+            let (big_start, big_end) = (self.subs[0].start(), self.subs[0].end());
+            while self.subs[1].start() < big_start && self.subs[1].end() < big_end {
+                if self.subs[1].next_interval()? == NO_MORE_INTERVALS {
+                    self.exhausted = true;
+                    return self.no_more_intervals();
+                }
+            }
+            if big_start <= self.subs[1].start() && big_end >= self.subs[1].end() {
+                self.start = big_start;
+                self.end = big_end;
+                return Ok(self.start);
+            }
+        }
+        self.no_more_intervals()
+    }
+}
+
+impl IntervalIterator for ConjunctionIntervalIterator {
+    fn start(&self) -> i32 {
+        self.start
+    }
+
+    fn end(&self) -> i32 {
+        self.end
+    }
+
+    fn next_interval(&mut self) -> Result<i32> {
+        match self.function {
+            IntervalFunction::Block => self.next_block(),
+            IntervalFunction::Ordered => self.next_ordered(),
+            IntervalFunction::Unordered => self.next_unordered(),
+            IntervalFunction::Containing => self.next_containing(),
+        }
+    }
+}
+
+impl DocIterator for ConjunctionIntervalIterator {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.doc == NO_MORE_DOCS {
+            return Ok(NO_MORE_DOCS);
+        }
+        let target = self.doc + 1;
+        self.do_advance(target)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.do_advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.subs.iter().map(|s| s.cost()).min().unwrap()
+    }
+}
+
+/// Only keeps the intervals whose last position is at most `max_distance` after the
+/// first one.
+pub(crate) struct WithinIntervalIterator {
+    inner: Box<dyn IntervalIterator>,
+    max_distance: i32,
+}
+
+impl WithinIntervalIterator {
+    pub fn new(inner: Box<dyn IntervalIterator>, max_distance: i32) -> Self {
+        WithinIntervalIterator {
+            inner,
+            max_distance,
+        }
+    }
+}
+
+impl IntervalIterator for WithinIntervalIterator {
+    fn start(&self) -> i32 {
+        self.inner.start()
+    }
+
+    fn end(&self) -> i32 {
+        self.inner.end()
+    }
+
+    fn next_interval(&mut self) -> Result<i32> {
+        loop {
+            let start = self.inner.next_interval()?;
+            if start == NO_MORE_INTERVALS || self.inner.end() - start <= self.max_distance {
+                return Ok(start);
+            }
+        }
+    }
+}
+
+impl DocIterator for WithinIntervalIterator {
+    fn doc_id(&self) -> DocId {
+        self.inner.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.inner.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.inner.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.inner.cost()
+    }
+}
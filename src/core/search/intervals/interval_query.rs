@@ -0,0 +1,388 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use core::codec::Codec;
+use core::doc::Term;
+use core::index::reader::LeafReaderContext;
+use core::search::explanation::Explanation;
+use core::search::intervals::{IntervalIterator, IntervalsSource, NO_MORE_INTERVALS};
+use core::search::query::{Query, TermQuery, Weight, TERM_OPS_PER_POS};
+use core::search::scorer::Scorer;
+use core::search::searcher::SearchPlanBuilder;
+use core::search::similarity::{SimScorer, SimWeight, Similarity};
+use core::search::statistics::{CollectionStatistics, TermStatistics};
+use core::search::{DocIterator, MatchesIterator};
+use core::util::DocId;
+use error::Result;
+
+const INTERVAL: &str = "interval";
+
+/// A query matching the docs where an `IntervalsSource` has at least one interval in
+/// `field`, scored by the number of intervals.
+pub struct IntervalQuery<C: Codec> {
+    field: String,
+    source: Arc<dyn IntervalsSource<C>>,
+}
+
+impl<C: Codec> IntervalQuery<C> {
+    pub fn new(field: String, source: Arc<dyn IntervalsSource<C>>) -> Self {
+        IntervalQuery { field, source }
+    }
+
+    fn terms(&self) -> Vec<Term> {
+        let mut terms = vec![];
+        self.source.extract_terms(&self.field, &mut terms);
+        terms
+    }
+}
+
+impl<C: Codec> Query<C> for IntervalQuery<C> {
+    fn create_weight(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        let terms = self.terms();
+        let max_doc = i64::from(searcher.max_doc());
+        let mut term_stats = Vec::with_capacity(terms.len());
+        for term in &terms {
+            if needs_scores {
+                term_stats.push(searcher.term_statistics(term)?);
+            } else {
+                term_stats.push(TermStatistics::new(term.bytes.clone(), max_doc, -1));
+            }
+        }
+        let collection_stats = match searcher.collections_statistics(&self.field) {
+            Some(stat) if needs_scores => stat.clone(),
+            _ => CollectionStatistics::new(self.field.clone(), 0, max_doc, -1, -1, -1),
+        };
+
+        let similarity = searcher.similarity(&self.field, needs_scores);
+        let sim_weight = similarity.compute_weight(&collection_stats, &term_stats, None, 1.0f32);
+
+        Ok(Box::new(IntervalWeight {
+            field: self.field.clone(),
+            source: Arc::clone(&self.source),
+            match_cost: terms.len() as f32 * TERM_OPS_PER_POS as f32,
+            similarity,
+            sim_weight,
+            needs_scores,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        self.terms()
+            .into_iter()
+            .map(|term| TermQuery::new(term, 1.0f32, None))
+            .collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<C: Codec> fmt::Display for IntervalQuery<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "IntervalQuery(field: {}, source: {})",
+            &self.field, &self.source
+        )
+    }
+}
+
+struct IntervalWeight<C: Codec> {
+    field: String,
+    source: Arc<dyn IntervalsSource<C>>,
+    match_cost: f32,
+    similarity: Box<dyn Similarity<C>>,
+    sim_weight: Box<dyn SimWeight<C>>,
+    needs_scores: bool,
+}
+
+impl<C: Codec> IntervalWeight<C> {
+    fn scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<IntervalScorer>> {
+        let intervals = match self.source.intervals(&self.field, reader)? {
+            Some(intervals) => intervals,
+            None => return Ok(None),
+        };
+        Ok(Some(IntervalScorer {
+            intervals,
+            freq: 0,
+            match_cost: self.match_cost,
+            doc_scorer: self.sim_weight.sim_scorer(reader.reader)?,
+            needs_scores: self.needs_scores,
+        }))
+    }
+}
+
+impl<C: Codec> Weight<C> for IntervalWeight<C> {
+    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        Ok(self
+            .scorer(reader)?
+            .map(|scorer| Box::new(scorer) as Box<dyn Scorer>))
+    }
+
+    fn query_type(&self) -> &'static str {
+        INTERVAL
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.sim_weight.normalize(norm, boost)
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.sim_weight.get_value_for_normalization()
+    }
+
+    fn needs_scores(&self) -> bool {
+        self.needs_scores
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        if let Some(mut scorer) = self.scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                let freq = scorer.freq as f32;
+                let freq_expl = Explanation::new(true, freq, format!("freq={}", freq), vec![]);
+                let score_expl = self.sim_weight.explain(reader.reader, doc, freq_expl)?;
+
+                return Ok(Explanation::new(
+                    true,
+                    score_expl.value(),
+                    format!("weight({} in {}), result of:", self, doc),
+                    vec![score_expl],
+                ));
+            }
+        }
+
+        Ok(Explanation::new(
+            false,
+            0.0f32,
+            "no matching intervals".to_string(),
+            vec![],
+        ))
+    }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        if let Some(mut intervals) = self.source.intervals(&self.field, reader)? {
+            if intervals.advance(doc)? == doc && intervals.next_interval()? != NO_MORE_INTERVALS {
+                return Ok(Some(Box::new(IntervalMatchesIterator {
+                    intervals,
+                    started: false,
+                })));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<C: Codec> fmt::Display for IntervalWeight<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "IntervalWeight(field: {}, source: {}, similarity: {}, need_score: {})",
+            &self.field, &self.source, &self.similarity, self.needs_scores
+        )
+    }
+}
+
+/// The intervals of a doc as matches, starting on its first interval.
+struct IntervalMatchesIterator {
+    intervals: Box<dyn IntervalIterator>,
+    started: bool,
+}
+
+impl MatchesIterator for IntervalMatchesIterator {
+    fn next_match(&mut self) -> Result<bool> {
+        if !self.started {
+            self.started = true;
+            return Ok(true);
+        }
+        Ok(self.intervals.next_interval()? != NO_MORE_INTERVALS)
+    }
+
+    fn start_position(&self) -> i32 {
+        self.intervals.start()
+    }
+
+    fn end_position(&self) -> i32 {
+        self.intervals.end()
+    }
+
+    fn start_offset(&self) -> i32 {
+        -1
+    }
+
+    fn end_offset(&self) -> i32 {
+        -1
+    }
+}
+
+/// Approximates the matches by the docs the intervals iterator moves to, a doc matches
+/// if it has an interval.
+struct IntervalScorer {
+    intervals: Box<dyn IntervalIterator>,
+    freq: i32,
+    match_cost: f32,
+    doc_scorer: Box<dyn SimScorer>,
+    needs_scores: bool,
+}
+
+impl Scorer for IntervalScorer {
+    fn score(&mut self) -> Result<f32> {
+        self.doc_scorer
+            .score(self.intervals.doc_id(), self.freq as f32)
+    }
+}
+
+impl DocIterator for IntervalScorer {
+    fn doc_id(&self) -> DocId {
+        self.intervals.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.approximate_next()?;
+        self.confirm_match(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.approximate_advance(target)?;
+        self.confirm_match(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.intervals.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.freq = 0;
+        while self.intervals.next_interval()? != NO_MORE_INTERVALS {
+            self.freq += 1;
+            if !self.needs_scores {
+                break;
+            }
+        }
+        Ok(self.freq > 0)
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.match_cost
+    }
+
+    fn support_two_phase(&self) -> bool {
+        true
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        self.intervals.next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        self.intervals.advance(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::doc::IndexOptions;
+    use core::search::collector::TopDocsCollector;
+    use core::search::intervals::Intervals;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::{new_fs_writer, text_doc};
+
+    fn search<IS: IndexSearcher<C>, C: Codec>(
+        searcher: &IS,
+        source: Arc<dyn IntervalsSource<C>>,
+    ) -> Vec<DocId> {
+        let query = IntervalQuery::new("body".into(), source);
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(&query, &mut collector).unwrap();
+        let mut docs: Vec<DocId> = collector
+            .top_docs()
+            .score_docs()
+            .iter()
+            .map(|d| d.doc_id())
+            .collect();
+        docs.sort();
+        docs
+    }
+
+    #[test]
+    fn test_interval_functions() {
+        let (_dir, writer) = new_fs_writer();
+        for text in &[
+            "the quick brown fox jumps",
+            "the quick fox jumps",
+            "fox quick brown",
+            "quick red fox and quick old fox",
+            "a brown dog",
+        ] {
+            writer
+                .add_document(text_doc(
+                    "body",
+                    text,
+                    IndexOptions::DocsAndFreqsAndPositions,
+                ))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        let term = |t: &str| Intervals::term(t.as_bytes());
+        assert_eq!(search(&searcher, term("fox")), vec![0, 1, 2, 3]);
+        assert_eq!(search(&searcher, term("cat")), Vec::<DocId>::new());
+
+        let phrase = Intervals::phrase(&[&b"quick"[..], &b"brown"[..]]);
+        assert_eq!(phrase.to_string(), "PHRASE(quick, brown)");
+        assert_eq!(search(&searcher, Arc::clone(&phrase)), vec![0, 2]);
+
+        let ordered = Intervals::ordered(vec![term("quick"), term("fox")]);
+        assert_eq!(search(&searcher, Arc::clone(&ordered)), vec![0, 1, 3]);
+        let unordered = Intervals::unordered(vec![term("quick"), term("fox")]);
+        assert_eq!(search(&searcher, unordered), vec![0, 1, 2, 3]);
+        let missing = Intervals::ordered(vec![term("quick"), term("cat")]);
+        assert_eq!(search(&searcher, missing), Vec::<DocId>::new());
+
+        let within = Intervals::within(Arc::clone(&ordered), 1);
+        assert_eq!(within.to_string(), "WITHIN/1(ORDERED(quick, fox))");
+        assert_eq!(search(&searcher, within), vec![1]);
+        let within = Intervals::within(Arc::clone(&ordered), 2);
+        assert_eq!(search(&searcher, within), vec![0, 1, 3]);
+
+        let sentence = Intervals::ordered(vec![term("the"), term("jumps")]);
+        let containing = Intervals::containing(sentence, term("quick"));
+        assert_eq!(search(&searcher, containing), vec![0, 1]);
+        let containing = Intervals::containing(phrase, term("brown"));
+        assert_eq!(search(&searcher, containing), vec![0, 2]);
+
+        // both minimal intervals of the doc count
+        let query = IntervalQuery::new("body".into(), ordered);
+        let explanation = searcher.explain(&query, 3).unwrap();
+        assert!(explanation.is_match());
+        assert!(explanation.to_string(0).contains("freq=2"));
+        assert!(!searcher.explain(&query, 2).unwrap().is_match());
+    }
+}
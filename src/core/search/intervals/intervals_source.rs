@@ -0,0 +1,303 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::sync::Arc;
+
+use core::codec::{Codec, PostingIteratorFlags};
+use core::codec::{TermIterator, Terms};
+use core::doc::{IndexOptions, Term};
+use core::index::reader::LeafReaderContext;
+use core::search::intervals::{
+    ConjunctionIntervalIterator, IntervalFunction, IntervalIterator, IntervalsSource,
+    TermIntervalIterator, WithinIntervalIterator,
+};
+use error::{ErrorKind::IllegalState, Result};
+
+/// The factories of the `IntervalsSource`s.
+pub struct Intervals;
+
+impl Intervals {
+    /// The positions of `term`, each one an interval.
+    pub fn term<C: Codec>(term: &[u8]) -> Arc<dyn IntervalsSource<C>> {
+        Arc::new(TermIntervalsSource {
+            term: term.to_vec(),
+        })
+    }
+
+    /// The intervals where `terms` appear one right after the other.
+    pub fn phrase<C: Codec>(terms: &[&[u8]]) -> Arc<dyn IntervalsSource<C>> {
+        let sources = terms.iter().map(|t| Self::term(t)).collect();
+        Self::conjunction(sources, IntervalFunction::Block)
+    }
+
+    /// The minimal intervals where the intervals of `sources` appear in order without
+    /// overlapping.
+    pub fn ordered<C: Codec>(
+        sources: Vec<Arc<dyn IntervalsSource<C>>>,
+    ) -> Arc<dyn IntervalsSource<C>> {
+        Self::conjunction(sources, IntervalFunction::Ordered)
+    }
+
+    /// The minimal intervals containing an interval of each of `sources`, in any order.
+    pub fn unordered<C: Codec>(
+        sources: Vec<Arc<dyn IntervalsSource<C>>>,
+    ) -> Arc<dyn IntervalsSource<C>> {
+        Self::conjunction(sources, IntervalFunction::Unordered)
+    }
+
+    /// The intervals of `source` spanning at most `distance` positions after their start.
+    pub fn within<C: Codec>(
+        source: Arc<dyn IntervalsSource<C>>,
+        distance: i32,
+    ) -> Arc<dyn IntervalsSource<C>> {
+        assert!(distance >= 0, "distance must be >= 0, got {}", distance);
+        Arc::new(WithinIntervalsSource { source, distance })
+    }
+
+    /// The intervals of `big` containing an interval of `small`.
+    pub fn containing<C: Codec>(
+        big: Arc<dyn IntervalsSource<C>>,
+        small: Arc<dyn IntervalsSource<C>>,
+    ) -> Arc<dyn IntervalsSource<C>> {
+        Arc::new(ConjunctionIntervalsSource {
+            sources: vec![big, small],
+            function: IntervalFunction::Containing,
+        })
+    }
+
+    fn conjunction<C: Codec>(
+        mut sources: Vec<Arc<dyn IntervalsSource<C>>>,
+        function: IntervalFunction,
+    ) -> Arc<dyn IntervalsSource<C>> {
+        assert!(!sources.is_empty(), "at least one source is required");
+        if sources.len() == 1 {
+            return sources.remove(0);
+        }
+        Arc::new(ConjunctionIntervalsSource { sources, function })
+    }
+}
+
+struct TermIntervalsSource {
+    term: Vec<u8>,
+}
+
+impl<C: Codec> IntervalsSource<C> for TermIntervalsSource {
+    fn intervals(
+        &self,
+        field: &str,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn IntervalIterator>>> {
+        let field_terms = match reader.reader.terms(field)? {
+            Some(field_terms) => field_terms,
+            None => return Ok(None),
+        };
+        if !field_terms.has_positions()? {
+            bail!(IllegalState(format!(
+                "field {} was indexed with {:?}, without position data; cannot compute intervals \
+                 of {}",
+                field,
+                reader
+                    .reader
+                    .field_info(field)
+                    .map_or(IndexOptions::Null, |f| f.index_options),
+                String::from_utf8_lossy(&self.term)
+            )));
+        }
+        let mut term_iter = field_terms.iterator()?;
+        if !term_iter.seek_exact(&self.term)? {
+            return Ok(None);
+        }
+        let postings = term_iter.postings_with_flags(PostingIteratorFlags::POSITIONS)?;
+        Ok(Some(Box::new(TermIntervalIterator::new(postings))))
+    }
+
+    fn extract_terms(&self, field: &str, terms: &mut Vec<Term>) {
+        terms.push(Term::new(field.to_string(), self.term.clone()));
+    }
+}
+
+impl fmt::Display for TermIntervalsSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.term))
+    }
+}
+
+struct ConjunctionIntervalsSource<C: Codec> {
+    sources: Vec<Arc<dyn IntervalsSource<C>>>,
+    function: IntervalFunction,
+}
+
+impl<C: Codec> IntervalsSource<C> for ConjunctionIntervalsSource<C> {
+    fn intervals(
+        &self,
+        field: &str,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn IntervalIterator>>> {
+        let mut subs = Vec::with_capacity(self.sources.len());
+        for source in &self.sources {
+            match source.intervals(field, reader)? {
+                Some(sub) => subs.push(sub),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(Box::new(ConjunctionIntervalIterator::new(
+            subs,
+            self.function,
+        ))))
+    }
+
+    fn extract_terms(&self, field: &str, terms: &mut Vec<Term>) {
+        for source in &self.sources {
+            source.extract_terms(field, terms);
+        }
+    }
+}
+
+impl<C: Codec> fmt::Display for ConjunctionIntervalsSource<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.function {
+            IntervalFunction::Block => "PHRASE",
+            IntervalFunction::Ordered => "ORDERED",
+            IntervalFunction::Unordered => "UNORDERED",
+            IntervalFunction::Containing => "CONTAINING",
+        };
+        write!(f, "{}(", name)?;
+        for (i, source) in self.sources.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", source)?;
+        }
+        write!(f, ")")
+    }
+}
+
+struct WithinIntervalsSource<C: Codec> {
+    source: Arc<dyn IntervalsSource<C>>,
+    distance: i32,
+}
+
+impl<C: Codec> IntervalsSource<C> for WithinIntervalsSource<C> {
+    fn intervals(
+        &self,
+        field: &str,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn IntervalIterator>>> {
+        Ok(self.source.intervals(field, reader)?.map(|inner| {
+            Box::new(WithinIntervalIterator::new(inner, self.distance)) as Box<dyn IntervalIterator>
+        }))
+    }
+
+    fn extract_terms(&self, field: &str, terms: &mut Vec<Term>) {
+        self.source.extract_terms(field, terms);
+    }
+}
+
+impl<C: Codec> fmt::Display for WithinIntervalsSource<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "WITHIN/{}({})", self.distance, &self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::index::reader::IndexReader;
+    use core::search::intervals::NO_MORE_INTERVALS;
+    use core::search::{DocIterator, NO_MORE_DOCS};
+    use core::test_util::{new_fs_writer, text_doc};
+    use core::util::DocId;
+
+    // the (doc, start, end) of every interval of `source` in the leaf
+    fn intervals<C: Codec>(
+        source: &Arc<dyn IntervalsSource<C>>,
+        leaf: &LeafReaderContext<'_, C>,
+    ) -> Vec<(DocId, i32, i32)> {
+        let mut iter = match source.intervals("body", leaf).unwrap() {
+            Some(iter) => iter,
+            None => return vec![],
+        };
+        let mut intervals = vec![];
+        while iter.next().unwrap() != NO_MORE_DOCS {
+            while iter.next_interval().unwrap() != NO_MORE_INTERVALS {
+                intervals.push((iter.doc_id(), iter.start(), iter.end()));
+            }
+        }
+        intervals
+    }
+
+    #[test]
+    fn test_interval_positions() {
+        let (_dir, writer) = new_fs_writer();
+        for text in &[
+            "the quick brown fox jumps",
+            "quick red fox and quick old fox",
+            "fox quick brown",
+        ] {
+            writer
+                .add_document(text_doc(
+                    "body",
+                    text,
+                    IndexOptions::DocsAndFreqsAndPositions,
+                ))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        assert_eq!(leaves.len(), 1);
+        let leaf = &leaves[0];
+
+        let term = |t: &str| Intervals::term(t.as_bytes());
+        assert_eq!(
+            intervals(&term("fox"), leaf),
+            vec![(0, 3, 3), (1, 2, 2), (1, 6, 6), (2, 0, 0)]
+        );
+        assert!(intervals(&term("cat"), leaf).is_empty());
+
+        let phrase = Intervals::phrase(&[&b"quick"[..], &b"brown"[..]]);
+        assert_eq!(intervals(&phrase, leaf), vec![(0, 1, 2), (2, 1, 2)]);
+
+        // the minimal intervals only, "quick ... quick ... fox" matches from the second
+        // "quick"
+        let ordered = Intervals::ordered(vec![term("quick"), term("fox")]);
+        assert_eq!(
+            intervals(&ordered, leaf),
+            vec![(0, 1, 3), (1, 0, 2), (1, 4, 6)]
+        );
+        let unordered = Intervals::unordered(vec![term("quick"), term("fox")]);
+        assert_eq!(
+            intervals(&unordered, leaf),
+            vec![(0, 1, 3), (1, 0, 2), (1, 2, 4), (1, 4, 6), (2, 0, 1)]
+        );
+
+        let within = Intervals::within(Arc::clone(&ordered), 1);
+        assert!(intervals(&within, leaf).is_empty());
+        let unordered_within = Intervals::within(Arc::clone(&unordered), 1);
+        assert_eq!(intervals(&unordered_within, leaf), vec![(2, 0, 1)]);
+        let within = Intervals::within(ordered, 2);
+        assert_eq!(
+            intervals(&within, leaf),
+            vec![(0, 1, 3), (1, 0, 2), (1, 4, 6)]
+        );
+
+        let sentence = Intervals::ordered(vec![term("the"), term("jumps")]);
+        let containing = Intervals::containing(sentence, term("fox"));
+        assert_eq!(intervals(&containing, leaf), vec![(0, 0, 4)]);
+        let containing = Intervals::containing(unordered, term("red"));
+        assert_eq!(intervals(&containing, leaf), vec![(1, 0, 2)]);
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Positional queries built from intervals, the ports of Lucene's intervals API.
+//!
+//! An `IntervalsSource` produces, for every document of a leaf, the minimal intervals of
+//! positions its terms match at. Sources are combined with the functions of `Intervals`
+//! and searched with an `IntervalQuery`.
+
+mod interval_iterator;
+
+pub use self::interval_iterator::*;
+
+mod intervals_source;
+
+pub use self::intervals_source::*;
+
+mod interval_query;
+
+pub use self::interval_query::*;
+
+use std::fmt;
+
+use core::codec::Codec;
+use core::doc::Term;
+use core::index::reader::LeafReaderContext;
+use core::search::DocIterator;
+use error::Result;
+
+/// Returned by `IntervalIterator::next_interval` once the intervals of the current doc
+/// are exhausted.
+pub const NO_MORE_INTERVALS: i32 = i32::max_value();
+
+/// Iterates over the docs of a source and, for each of them, over its intervals in
+/// increasing start order.
+///
+/// Moving to a doc only makes sure the terms of the source occur in it, whether it has
+/// any interval is only known after calling `next_interval`.
+pub trait IntervalIterator: DocIterator {
+    /// The start position of the current interval, -1 before `next_interval` was called
+    /// on the current doc and `NO_MORE_INTERVALS` once the intervals are exhausted.
+    fn start(&self) -> i32;
+
+    /// The end position of the current interval, inclusive.
+    fn end(&self) -> i32;
+
+    /// Moves to the next interval of the current doc and returns its start, or
+    /// `NO_MORE_INTERVALS` if there is none left.
+    fn next_interval(&mut self) -> Result<i32>;
+}
+
+/// A source of intervals over the positions of a field.
+pub trait IntervalsSource<C: Codec>: fmt::Display + Send + Sync {
+    /// Returns the intervals of `field` in the leaf, or `None` if they can't match in it.
+    fn intervals(
+        &self,
+        field: &str,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn IntervalIterator>>>;
+
+    /// Adds the terms of this source in `field` to `terms`.
+    fn extract_terms(&self, field: &str, terms: &mut Vec<Term>);
+}
@@ -0,0 +1,369 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::PostingIterator;
+use error::Result;
+
+/// Iterates over the positions a query matches at in a single document, in increasing
+/// start position order.
+///
+/// The positions and offsets are only valid after `next_match` returned `true`, offsets
+/// are `-1` if they were not indexed.
+pub trait MatchesIterator: Send {
+    /// Moves to the next match, returns `false` once the matches are exhausted.
+    fn next_match(&mut self) -> Result<bool>;
+
+    /// The start position of the current match.
+    fn start_position(&self) -> i32;
+
+    /// The end position of the current match, inclusive.
+    fn end_position(&self) -> i32;
+
+    /// The start offset of the current match.
+    fn start_offset(&self) -> i32;
+
+    /// The end offset of the current match.
+    fn end_offset(&self) -> i32;
+}
+
+/// The matches of a document matching without any position, e.g. by a `MatchAllDocsQuery`
+/// or over a field indexed without positions.
+pub struct EmptyMatchesIterator;
+
+impl MatchesIterator for EmptyMatchesIterator {
+    fn next_match(&mut self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn start_position(&self) -> i32 {
+        -1
+    }
+
+    fn end_position(&self) -> i32 {
+        -1
+    }
+
+    fn start_offset(&self) -> i32 {
+        -1
+    }
+
+    fn end_offset(&self) -> i32 {
+        -1
+    }
+}
+
+/// The positions of a term in the document its postings are on.
+pub struct TermMatchesIterator<T: PostingIterator> {
+    postings: T,
+    upto: i32,
+    freq: i32,
+    pos: i32,
+    start_offset: i32,
+    end_offset: i32,
+}
+
+impl<T: PostingIterator> TermMatchesIterator<T> {
+    /// Creates the iterator of a postings already positioned on the document.
+    pub fn new(postings: T) -> Result<Self> {
+        let freq = postings.freq()?;
+        Ok(TermMatchesIterator {
+            postings,
+            upto: 0,
+            freq,
+            pos: -1,
+            start_offset: -1,
+            end_offset: -1,
+        })
+    }
+}
+
+impl<T: PostingIterator> MatchesIterator for TermMatchesIterator<T> {
+    fn next_match(&mut self) -> Result<bool> {
+        if self.upto >= self.freq {
+            return Ok(false);
+        }
+        self.pos = self.postings.next_position()?;
+        self.start_offset = self.postings.start_offset()?;
+        self.end_offset = self.postings.end_offset()?;
+        self.upto += 1;
+        Ok(true)
+    }
+
+    fn start_position(&self) -> i32 {
+        self.pos
+    }
+
+    fn end_position(&self) -> i32 {
+        self.pos
+    }
+
+    fn start_offset(&self) -> i32 {
+        self.start_offset
+    }
+
+    fn end_offset(&self) -> i32 {
+        self.end_offset
+    }
+}
+
+/// The matches of a document collected beforehand, as `(start_position, end_position,
+/// start_offset, end_offset)` in increasing start position order.
+pub struct PositionsMatchesIterator {
+    matches: Vec<(i32, i32, i32, i32)>,
+    upto: usize,
+}
+
+impl PositionsMatchesIterator {
+    pub fn new(mut matches: Vec<(i32, i32, i32, i32)>) -> Self {
+        matches.sort();
+        matches.dedup();
+        PositionsMatchesIterator { matches, upto: 0 }
+    }
+
+    fn current(&self) -> (i32, i32, i32, i32) {
+        self.matches[self.upto - 1]
+    }
+}
+
+impl MatchesIterator for PositionsMatchesIterator {
+    fn next_match(&mut self) -> Result<bool> {
+        if self.upto < self.matches.len() {
+            self.upto += 1;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn start_position(&self) -> i32 {
+        self.current().0
+    }
+
+    fn end_position(&self) -> i32 {
+        self.current().1
+    }
+
+    fn start_offset(&self) -> i32 {
+        self.current().2
+    }
+
+    fn end_offset(&self) -> i32 {
+        self.current().3
+    }
+}
+
+/// Merges the matches of several iterators over the same document.
+pub struct DisjunctionMatchesIterator {
+    subs: Vec<Box<dyn MatchesIterator>>,
+    current: usize,
+    started: bool,
+}
+
+impl DisjunctionMatchesIterator {
+    /// Merges `subs`, which are returned as is if there are less than two of them.
+    pub fn from_subs(mut subs: Vec<Box<dyn MatchesIterator>>) -> Box<dyn MatchesIterator> {
+        match subs.len() {
+            0 => Box::new(EmptyMatchesIterator),
+            1 => subs.remove(0),
+            _ => Box::new(DisjunctionMatchesIterator {
+                subs,
+                current: 0,
+                started: false,
+            }),
+        }
+    }
+}
+
+impl MatchesIterator for DisjunctionMatchesIterator {
+    fn next_match(&mut self) -> Result<bool> {
+        if !self.started {
+            self.started = true;
+            let mut subs = Vec::with_capacity(self.subs.len());
+            for mut sub in self.subs.drain(..) {
+                if sub.next_match()? {
+                    subs.push(sub);
+                }
+            }
+            self.subs = subs;
+        } else if !self.subs.is_empty() && !self.subs[self.current].next_match()? {
+            self.subs.remove(self.current);
+        }
+
+        let subs = &self.subs;
+        match (0..subs.len()).min_by_key(|&i| (subs[i].start_position(), subs[i].end_position())) {
+            Some(current) => {
+                self.current = current;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn start_position(&self) -> i32 {
+        self.subs[self.current].start_position()
+    }
+
+    fn end_position(&self) -> i32 {
+        self.subs[self.current].end_position()
+    }
+
+    fn start_offset(&self) -> i32 {
+        self.subs[self.current].start_offset()
+    }
+
+    fn end_offset(&self) -> i32 {
+        self.subs[self.current].end_offset()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::codec::CodecEnum;
+    use core::doc::{IndexOptions, Term};
+    use core::index::reader::IndexReader;
+    use core::search::query::spans::{SpanNearQuery, SpanQueryEnum, SpanTermQuery};
+    use core::search::query::TermAutomatonQuery;
+    use core::search::query::{BooleanQuery, MatchAllDocsQuery, PhraseQuery, Query, TermQuery};
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::{new_fs_writer, text_doc};
+    use core::util::DocId;
+
+    use std::sync::Arc;
+
+    fn collect(mut matches: Box<dyn MatchesIterator>) -> Vec<(i32, i32, i32, i32)> {
+        let mut res = vec![];
+        while matches.next_match().unwrap() {
+            res.push((
+                matches.start_position(),
+                matches.end_position(),
+                matches.start_offset(),
+                matches.end_offset(),
+            ));
+        }
+        res
+    }
+
+    #[test]
+    fn test_term_and_boolean_matches() {
+        let (_dir, writer) = new_fs_writer();
+        writer
+            .add_document(text_doc(
+                "body",
+                "the quick brown fox and the quick dog",
+                IndexOptions::DocsAndFreqsAndPositionsAndOffsets,
+            ))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "body",
+                "a lazy dog",
+                IndexOptions::DocsAndFreqsAndPositionsAndOffsets,
+            ))
+            .unwrap();
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let leaves = searcher.reader().leaves();
+
+        let term = |t: &str| -> Box<dyn Query<_>> {
+            Box::new(TermQuery::new(
+                Term::new("body".into(), t.as_bytes().to_vec()),
+                1.0f32,
+                None,
+            ))
+        };
+        let weight = term("quick").create_weight(&searcher, true).unwrap();
+        let matches = weight.matches(&leaves[0], 0).unwrap().unwrap();
+        assert_eq!(collect(matches), vec![(1, 1, 4, 9), (6, 6, 28, 33)]);
+        assert!(weight.matches(&leaves[0], 1).unwrap().is_none());
+
+        // the matches of the clauses are merged in position order
+        let query =
+            BooleanQuery::build(vec![], vec![term("quick"), term("fox")], vec![], vec![], 0)
+                .unwrap();
+        let weight = query.create_weight(&searcher, true).unwrap();
+        let matches = weight.matches(&leaves[0], 0).unwrap().unwrap();
+        let expected = vec![(1, 1, 4, 9), (3, 3, 16, 19), (6, 6, 28, 33)];
+        assert_eq!(collect(matches), expected);
+        // the prohibited clause rejects the first doc
+        let query =
+            BooleanQuery::build(vec![term("dog")], vec![], vec![], vec![term("fox")], 0).unwrap();
+        let weight = query.create_weight(&searcher, true).unwrap();
+        assert!(weight.matches(&leaves[0], 0).unwrap().is_none());
+        let matches = weight.matches(&leaves[0], 1).unwrap().unwrap();
+        assert_eq!(collect(matches), vec![(2, 2, 7, 10)]);
+
+        // a doc can match without positions
+        let weight = MatchAllDocsQuery.create_weight(&searcher, false).unwrap();
+        let matches = weight.matches(&leaves[0], 1).unwrap().unwrap();
+        assert!(collect(matches).is_empty());
+    }
+
+    #[test]
+    fn test_positional_matches() {
+        let (_dir, writer) = new_fs_writer();
+        writer
+            .add_document(text_doc(
+                "body",
+                "the quick brown fox and the quick dog",
+                IndexOptions::DocsAndFreqsAndPositionsAndOffsets,
+            ))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "body",
+                "a lazy dog",
+                IndexOptions::DocsAndFreqsAndPositionsAndOffsets,
+            ))
+            .unwrap();
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let leaves = searcher.reader().leaves();
+        let term = |t: &str| Term::new("body".into(), t.as_bytes().to_vec());
+        let matches = |query: &dyn Query<CodecEnum>, doc: DocId| {
+            let weight = query.create_weight(&searcher, true).unwrap();
+            weight.matches(&leaves[0], doc).unwrap().map(collect)
+        };
+
+        // a phrase matches from its first term to its last one
+        let query =
+            PhraseQuery::new(vec![term("the"), term("quick")], vec![0, 1], 0, None, None).unwrap();
+        let expected = vec![(0, 1, 0, 9), (5, 6, 24, 33)];
+        assert_eq!(matches(&query, 0), Some(expected));
+        assert_eq!(matches(&query, 1), None);
+        let query =
+            PhraseQuery::new(vec![term("quick"), term("fox")], vec![0, 1], 1, None, None).unwrap();
+        assert_eq!(matches(&query, 0), Some(vec![(1, 3, 4, 19)]));
+        let query =
+            PhraseQuery::new(vec![term("quick"), term("fox")], vec![0, 1], 0, None, None).unwrap();
+        assert_eq!(matches(&query, 0), None);
+
+        // the end of a span is exclusive, the one of a match is not
+        let clauses = vec![
+            SpanQueryEnum::Term(SpanTermQuery::new(term("quick"), None)),
+            SpanQueryEnum::Term(SpanTermQuery::new(term("dog"), None)),
+        ];
+        let query = SpanNearQuery::new(clauses, 0, true).unwrap();
+        assert_eq!(matches(&query, 0), Some(vec![(6, 7, -1, -1)]));
+        assert_eq!(matches(&query, 1), None);
+
+        let sequence = [Some(&b"quick"[..]), None, Some(&b"fox"[..])];
+        let query = TermAutomatonQuery::sequence("body".into(), &sequence).unwrap();
+        assert_eq!(matches(&query, 0), Some(vec![(1, 3, -1, -1)]));
+        assert_eq!(matches(&query, 1), None);
+    }
+}
@@ -13,10 +13,12 @@
 
 pub mod cache;
 pub mod collector;
+pub mod intervals;
 pub mod query;
 pub mod scorer;
 pub mod similarity;
 pub mod sort_field;
+pub mod suggest;
 
 mod searcher;
 
@@ -26,6 +28,10 @@ mod explanation;
 
 pub use self::explanation::*;
 
+mod matches_iterator;
+
+pub use self::matches_iterator::*;
+
 mod statistics;
 
 pub use self::statistics::*;
@@ -34,9 +40,13 @@ mod search_manager;
 
 pub use self::search_manager::*;
 
+mod searcher_lifetime_manager;
+
+pub use self::searcher_lifetime_manager::*;
+
 use std::i32;
 
-use core::util::DocId;
+use core::util::{Bits, DocId};
 
 use error::Result;
 
@@ -49,6 +59,10 @@ error_chain! {
         SearchFailed {
             description("Search failed")
         }
+        TooManyClauses(count: usize, max: usize) {
+            description("Too many boolean clauses")
+            display("Too many boolean clauses: {} exceeds the max clause count {}", count, max)
+        }
     }
 }
 
@@ -153,6 +167,15 @@ pub trait DocIterator: Send {
     fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
         self.advance(target)
     }
+
+    /// Moves on from the approximate match `doc` to the first doc `matches()` confirms,
+    /// which is how a two phase iterator implements `next()` and `advance(DocId)`.
+    fn confirm_match(&mut self, mut doc: DocId) -> Result<DocId> {
+        while doc != NO_MORE_DOCS && !self.matches()? {
+            doc = self.approximate_next()?;
+        }
+        Ok(doc)
+    }
 }
 
 impl Eq for dyn DocIterator {}
@@ -203,6 +226,13 @@ pub trait DocIdSet: Send + Sync {
     /// This implementation can return None if there
     /// are no docs that match.
     fn iterator(&self) -> Result<Option<Self::Iter>>;
+
+    /// Optionally provides random access to the set, e.g. to check single docs without
+    /// iterating. Returns `None` if the set has no efficient random access, which is
+    /// the default.
+    fn bits(&self) -> Option<&dyn Bits> {
+        None
+    }
 }
 
 #[cfg(test)]
@@ -301,6 +331,10 @@ pub mod tests {
             self.iterator.match_cost()
         }
 
+        fn support_two_phase(&self) -> bool {
+            self.iterator.support_two_phase()
+        }
+
         fn approximate_next(&mut self) -> Result<DocId> {
             self.iterator.approximate_next()
         }
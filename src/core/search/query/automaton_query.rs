@@ -0,0 +1,491 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::cmp::max;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::Arc;
+
+use core::codec::{Codec, PostingIteratorFlags, SeekStatus, TermIterator, Terms};
+use core::doc::Term;
+use core::index::reader::{IndexReader, LeafReader, LeafReaderContext};
+use core::search::explanation::Explanation;
+use core::search::query::{BooleanQuery, Query, TermQuery, Weight};
+use core::search::scorer::{ConstantScoreScorer, Scorer};
+use core::search::searcher::SearchPlanBuilder;
+use core::search::ErrorKind::TooManyClauses;
+use core::search::{DocIdSet, DocIterator, NO_MORE_DOCS};
+use core::util::automaton::{Automaton, Transition};
+use core::util::{DocId, DocIdSetBuilder};
+use error::Result;
+
+const AUTOMATON_QUERY: &str = "automaton";
+
+/// A query that matches the documents containing a term accepted by a
+/// deterministic `Automaton`.
+///
+/// This is the shared core of pattern based queries like wildcard, regexp
+/// and fuzzy matching; any automaton may be supplied directly as well.
+pub struct AutomatonQuery {
+    field: String,
+    automaton: Arc<Automaton>,
+}
+
+impl AutomatonQuery {
+    pub fn new(field: String, automaton: Automaton) -> Result<AutomatonQuery> {
+        automaton.check_deterministic()?;
+        Ok(AutomatonQuery {
+            field,
+            automaton: Arc::new(automaton),
+        })
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    pub fn automaton(&self) -> &Automaton {
+        &self.automaton
+    }
+
+    /// Rewrites the query into a boolean query with a SHOULD term clause per term of
+    /// `reader` it accepts, so that the terms are scored instead of matching at a constant
+    /// score. Fails with `TooManyClauses` as soon as more terms than
+    /// `BooleanQuery::max_clause_count` are found, the query itself is returned if none is.
+    pub fn rewrite<C: Codec>(
+        &self,
+        reader: &dyn IndexReader<Codec = C>,
+    ) -> Result<Box<dyn Query<C>>> {
+        let max_clause_count = BooleanQuery::<C>::max_clause_count();
+        let intersection = TermsIntersection::new(Arc::clone(&self.automaton));
+        // the leaves may share terms
+        let mut terms = BTreeSet::new();
+        for leaf in reader.leaves() {
+            if let Some(leaf_terms) = leaf.reader.terms(&self.field)? {
+                let mut iter = leaf_terms.iterator()?;
+                intersection.intersect(&mut iter, |iter| {
+                    terms.insert(iter.term()?.to_vec());
+                    Ok(terms.len() <= max_clause_count)
+                })?;
+            }
+            if terms.len() > max_clause_count {
+                bail!(TooManyClauses(terms.len(), max_clause_count));
+            }
+        }
+
+        if terms.is_empty() {
+            return Ok(Box::new(AutomatonQuery {
+                field: self.field.clone(),
+                automaton: Arc::clone(&self.automaton),
+            }));
+        }
+        let shoulds = terms
+            .into_iter()
+            .map(|term| {
+                let term = Term::new(self.field.clone(), term);
+                Box::new(TermQuery::new(term, 1.0, None)) as Box<dyn Query<C>>
+            })
+            .collect();
+        BooleanQuery::build(vec![], shoulds, vec![], vec![], 0)
+    }
+}
+
+impl<C: Codec> Query<C> for AutomatonQuery {
+    fn create_weight(
+        &self,
+        _searcher: &dyn SearchPlanBuilder<C>,
+        _needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        Ok(Box::new(AutomatonWeight {
+            field: self.field.clone(),
+            intersection: TermsIntersection::new(Arc::clone(&self.automaton)),
+            weight: 0f32,
+            norm: 0f32,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        vec![]
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl fmt::Display for AutomatonQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "AutomatonQuery(field={}, automaton={})",
+            &self.field, &self.automaton
+        )
+    }
+}
+
+// Finds the terms of a terms dictionary accepted by an automaton, seeking over the terms
+// the automaton can't accept instead of running every term through it.
+struct TermsIntersection {
+    automaton: Arc<Automaton>,
+    live: Vec<bool>,
+}
+
+impl TermsIntersection {
+    fn new(automaton: Arc<Automaton>) -> TermsIntersection {
+        let live = automaton.live_states();
+        TermsIntersection { automaton, live }
+    }
+
+    // positions `iter` on every accepted term in order and calls `visit`, until it
+    // returns false
+    fn intersect<T, F>(&self, iter: &mut T, mut visit: F) -> Result<()>
+    where
+        T: TermIterator,
+        F: FnMut(&mut T) -> Result<bool>,
+    {
+        let mut seek_term = self.first_seek_term();
+        while let Some(target) = seek_term.take() {
+            if iter.seek_ceil(&target)? == SeekStatus::End {
+                break;
+            }
+            let mut term = Some(iter.term()?.to_vec());
+            // the terms following an accepted one are likely accepted as well, they are
+            // walked until one is rejected and a term past it is sought
+            while let Some(t) = term {
+                if !self.automaton.run(&t) {
+                    seek_term = self.next_seek_term(&t);
+                    break;
+                }
+                if !visit(iter)? {
+                    return Ok(());
+                }
+                term = iter.next()?;
+            }
+        }
+        Ok(())
+    }
+
+    // the term to seek to first, `None` if no term is accepted
+    fn first_seek_term(&self) -> Option<Vec<u8>> {
+        if self.automaton.num_states() == 0 || !self.live[0] {
+            return None;
+        }
+        let mut term = vec![];
+        self.extend(0, &mut term);
+        Some(term)
+    }
+
+    // a term after `term` that no accepted term greater than `term` is less than, `None`
+    // if there is no accepted term greater than `term`
+    fn next_seek_term(&self, term: &[u8]) -> Option<Vec<u8>> {
+        // states[i] is the state reached by term[..i], as long as it's live
+        let mut states = vec![0];
+        for &b in term {
+            match self.automaton.step(states[states.len() - 1], b) {
+                Some(next) if self.live[next] => states.push(next),
+                _ => break,
+            }
+        }
+        let live_prefix = states.len() - 1;
+        if live_prefix == term.len() {
+            // the smallest terms after `term` start with it
+            if let Some(t) = self.min_live_transition(states[live_prefix], None) {
+                let mut next = term.to_vec();
+                next.push(t.min);
+                self.extend(t.dest, &mut next);
+                return Some(next);
+            }
+            if term.is_empty() {
+                return None;
+            }
+        }
+        // otherwise the next term replaces the byte of `term` at the longest possible
+        // prefix by a greater one
+        for i in (0..=live_prefix.min(term.len() - 1)).rev() {
+            if let Some(t) = self.min_live_transition(states[i], Some(term[i])) {
+                let mut next = term[..i].to_vec();
+                next.push(max(t.min, term[i] + 1));
+                self.extend(t.dest, &mut next);
+                return Some(next);
+            }
+        }
+        None
+    }
+
+    // the transition of the smallest label greater than `after` leading to a live state
+    fn min_live_transition(&self, state: usize, after: Option<u8>) -> Option<Transition> {
+        self.automaton
+            .transitions(state)
+            .iter()
+            .filter(|t| self.live[t.dest] && after.map_or(true, |b| t.max > b))
+            .min_by_key(|t| t.min)
+            .cloned()
+    }
+
+    // appends the smallest labels to `term` up to an accept state, or until a state is
+    // reached again. Every accepted term starting with `term` is at least the result.
+    fn extend(&self, mut state: usize, term: &mut Vec<u8>) {
+        let mut visited = vec![false; self.automaton.num_states()];
+        while !self.automaton.is_accept(state) && !visited[state] {
+            visited[state] = true;
+            match self.min_live_transition(state, None) {
+                Some(t) => {
+                    term.push(t.min);
+                    state = t.dest;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+struct AutomatonWeight {
+    field: String,
+    intersection: TermsIntersection,
+    weight: f32,
+    norm: f32,
+}
+
+impl<C: Codec> Weight<C> for AutomatonWeight {
+    fn create_scorer(
+        &self,
+        leaf_reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn Scorer>>> {
+        let terms = match leaf_reader.reader.terms(&self.field)? {
+            Some(terms) => terms,
+            None => return Ok(None),
+        };
+
+        let mut builder = DocIdSetBuilder::from_terms(leaf_reader.reader.max_doc(), &terms)?;
+        let mut matched = false;
+        let mut postings = None;
+        let mut iter = terms.iterator()?;
+        self.intersection.intersect(&mut iter, |iter| {
+            // hand the previous term's iterator back so it can be reset in place
+            let flags = PostingIteratorFlags::NONE;
+            let mut p = iter.postings_with_reuse(postings.take(), flags)?;
+            builder.add(&mut p)?;
+            postings = Some(p);
+            matched = true;
+            Ok(true)
+        })?;
+
+        if !matched {
+            return Ok(None);
+        }
+        if let Some(iterator) = builder.build().iterator()? {
+            let cost = iterator.cost();
+            Ok(Some(Box::new(ConstantScoreScorer::new(
+                self.weight,
+                iterator,
+                cost,
+            ))))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn query_type(&self) -> &'static str {
+        AUTOMATON_QUERY
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.norm = norm;
+        self.weight = norm * boost;
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.weight * self.weight
+    }
+
+    fn needs_scores(&self) -> bool {
+        false
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        let matched = match self.create_scorer(reader)? {
+            Some(mut scorer) => scorer.advance(doc)? == doc && doc != NO_MORE_DOCS,
+            None => false,
+        };
+        if matched {
+            Ok(Explanation::new(
+                true,
+                self.weight,
+                format!("{}, product of:", self),
+                vec![Explanation::new(true, self.weight, "boost".into(), vec![])],
+            ))
+        } else {
+            Ok(Explanation::new(
+                false,
+                0f32,
+                "no matching term".to_string(),
+                vec![],
+            ))
+        }
+    }
+}
+
+impl fmt::Display for AutomatonWeight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "AutomatonWeight(field={}, automaton={}, weight={}, norm={})",
+            &self.field, &self.intersection.automaton, self.weight, self.norm
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+
+    use core::codec::CodecEnum;
+    use core::index::merge::{SerialMergeScheduler, TieredMergePolicy};
+    use core::index::reader::StandardDirectoryReader;
+    use core::index::writer::{IndexWriter, IndexWriterConfig};
+    use core::search::collector::TopDocsCollector;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::store::directory::{FSDirectory, MockDirectory};
+    use core::test_util::{keyword_field, new_fs_writer};
+
+    /// colou?r
+    fn colour_automaton() -> Automaton {
+        let mut automaton = Automaton::new();
+        let mut state = automaton.create_state();
+        for &b in b"colo" {
+            let next = automaton.create_state();
+            automaton.add_transition(state, next, b, b);
+            state = next;
+        }
+        let u = automaton.create_state();
+        let end = automaton.create_state();
+        automaton.add_transition(state, u, b'u', b'u');
+        automaton.add_transition(state, end, b'r', b'r');
+        automaton.add_transition(u, end, b'r', b'r');
+        automaton.set_accept(end, true);
+        automaton
+    }
+
+    #[test]
+    fn test_custom_automaton_matches_both_spellings() {
+        let (_dir, writer) = new_fs_writer();
+        for v in &["color", "colour", "colouur", "colors", "cooler", "flavour"] {
+            writer.add_document(vec![keyword_field("word", v)]).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        let query = AutomatonQuery::new("word".into(), colour_automaton()).unwrap();
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(&query, &mut collector).unwrap();
+        let mut docs: Vec<DocId> = collector
+            .top_docs()
+            .score_docs()
+            .iter()
+            .map(|d| d.doc_id())
+            .collect();
+        docs.sort();
+        assert_eq!(docs, vec![0, 1]);
+
+        let query = AutomatonQuery::new("word".into(), Automaton::make_string(b"colr")).unwrap();
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(&query, &mut collector).unwrap();
+        assert_eq!(collector.top_docs().total_hits(), 0);
+    }
+
+    #[test]
+    fn test_rejects_non_deterministic_automaton() {
+        let mut automaton = Automaton::make_string(b"ab");
+        automaton.add_transition(0, 0, b'a', b'a');
+        assert!(AutomatonQuery::new("word".into(), automaton).is_err());
+    }
+
+    #[test]
+    fn test_next_seek_term() {
+        let intersection = TermsIntersection::new(Arc::new(colour_automaton()));
+        let next = |term: &str| {
+            intersection
+                .next_seek_term(term.as_bytes())
+                .map(|t| String::from_utf8(t).unwrap())
+        };
+        assert_eq!(intersection.first_seek_term(), Some(b"color".to_vec()));
+        assert_eq!(next("abc"), Some("color".into()));
+        assert_eq!(next("colo"), Some("color".into()));
+        assert_eq!(next("color"), Some("colour".into()));
+        assert_eq!(next("colors"), Some("colour".into()));
+        assert_eq!(next("colour"), None);
+        assert_eq!(next("cooler"), None);
+
+        // the smallest labels loop on `*`, the seek term stops there
+        let intersection = TermsIntersection::new(Arc::new(Automaton::make_wildcard(b"?y*")));
+        assert_eq!(next_of(&intersection, "t0042"), Some(b"ty".to_vec()));
+        assert_eq!(next_of(&intersection, "ty"), Some(b"ty\x00".to_vec()));
+        assert_eq!(next_of(&intersection, "\x7f\x00"), Some(b"\x7fy".to_vec()));
+    }
+
+    fn next_of(intersection: &TermsIntersection, term: &str) -> Option<Vec<u8>> {
+        intersection.next_seek_term(term.as_bytes())
+    }
+
+    #[test]
+    fn test_seeks_over_rejected_terms() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(IndexWriterConfig::default());
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let writer = IndexWriter::new(Arc::clone(&directory), config).unwrap();
+        for i in 0..2000 {
+            let word = format!("t{:04}", i);
+            writer
+                .add_document(vec![keyword_field("word", &word)])
+                .unwrap();
+        }
+        writer
+            .add_document(vec![keyword_field("word", "xylophone")])
+            .unwrap();
+        writer.commit().unwrap();
+
+        type MockReader = StandardDirectoryReader<
+            MockDirectory<FSDirectory>,
+            CodecEnum,
+            SerialMergeScheduler,
+            TieredMergePolicy,
+        >;
+        let mock_dir = Arc::new(MockDirectory::new(directory));
+        let reader = MockReader::open(Arc::clone(&mock_dir)).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let search = |pattern: &[u8]| {
+            let automaton = Automaton::make_wildcard(pattern);
+            let query = AutomatonQuery::new("word".into(), automaton).unwrap();
+            let mut collector = TopDocsCollector::new(10);
+            let before = mock_dir.bytes_read("tim");
+            searcher.search(&query, &mut collector).unwrap();
+            let hits = collector.top_docs().total_hits();
+            (hits, mock_dir.bytes_read("tim") - before)
+        };
+
+        let (hits, scan_read) = search(b"*");
+        assert_eq!(hits, 2001);
+        // only the block of `xylophone` is read, the `t` terms sought over
+        let (hits, read) = search(b"?y*");
+        assert_eq!(hits, 1);
+        assert!(read * 4 < scan_read, "{} vs {}", read, scan_read);
+        let (hits, read) = search(b"t123?");
+        assert_eq!(hits, 10);
+        assert!(read * 4 < scan_read, "{} vs {}", read, scan_read);
+    }
+}
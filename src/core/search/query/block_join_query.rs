@@ -0,0 +1,768 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::f32;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use core::codec::Codec;
+use core::index::reader::LeafReaderContext;
+use core::search::explanation::Explanation;
+use core::search::query::{Query, TermQuery, Weight};
+use core::search::scorer::Scorer;
+use core::search::searcher::SearchPlanBuilder;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::external::Deferred;
+use core::util::{BitSet, Bits, DocId, FixedBitSet, ImmutableBitSet};
+use error::{ErrorKind::IllegalState, Result};
+
+const TO_PARENT_BLOCK_JOIN_QUERY: &str = "to_parent_block_join";
+const TO_CHILD_BLOCK_JOIN_QUERY: &str = "to_child_block_join";
+
+/// How the scores of the children matching a `ToParentBlockJoinQuery` make the score of
+/// their parent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockJoinScoreMode {
+    /// Parents are not scored, the child query doesn't need scores.
+    None,
+    /// The average score of the matching children.
+    Avg,
+    /// The score of the best matching child.
+    Max,
+    /// The score of the worst matching child.
+    Min,
+    /// The sum of the scores of the matching children.
+    Total,
+}
+
+type BitSetCache = Arc<RwLock<HashMap<String, Option<Arc<FixedBitSet>>>>>;
+
+/// Produces the bit sets of the docs matching a query, deleted docs included.
+///
+/// The bit sets are cached per segment core, so that they are only built once for all
+/// the searches of a block join query, and dropped with the core of their segment.
+pub struct QueryBitSetProducer<C: Codec> {
+    query: Box<dyn Query<C>>,
+    cache: BitSetCache,
+}
+
+impl<C: Codec> QueryBitSetProducer<C> {
+    pub fn new(query: Box<dyn Query<C>>) -> Self {
+        QueryBitSetProducer {
+            query,
+            cache: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    pub fn query(&self) -> &dyn Query<C> {
+        self.query.as_ref()
+    }
+
+    fn create_weight(&self, searcher: &dyn SearchPlanBuilder<C>) -> Result<BitSetWeight<C>> {
+        Ok(BitSetWeight {
+            weight: self.query.create_weight(searcher, false)?,
+            cache: Arc::clone(&self.cache),
+        })
+    }
+}
+
+impl<C: Codec> fmt::Display for QueryBitSetProducer<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", &self.query)
+    }
+}
+
+struct BitSetWeight<C: Codec> {
+    weight: Box<dyn Weight<C>>,
+    cache: BitSetCache,
+}
+
+impl<C: Codec> BitSetWeight<C> {
+    /// Returns the bit set of the docs of the segment of `reader` matching the query, or
+    /// `None` if no doc matches.
+    fn bit_set(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Arc<FixedBitSet>>> {
+        let key = reader.reader.core_cache_key();
+        if let Some(bits) = self.cache.read()?.get(key) {
+            return Ok(bits.clone());
+        }
+
+        // built without holding the lock, a concurrent build of the same segment is dropped
+        let bits = match self.weight.create_scorer(reader)? {
+            Some(mut scorer) => {
+                let mut bits = FixedBitSet::new(reader.reader.max_doc() as usize);
+                bits.or(&mut scorer)?;
+                Some(Arc::new(bits))
+            }
+            None => None,
+        };
+        {
+            let mut cache = self.cache.write()?;
+            if let Some(cached) = cache.get(key) {
+                return Ok(cached.clone());
+            }
+            cache.insert(key.to_string(), bits.clone());
+        }
+        let key = key.to_string();
+        let cache = Arc::clone(&self.cache);
+        reader.reader.add_core_drop_listener(Deferred::new(move || {
+            cache.write().unwrap().remove(&key);
+        }));
+        Ok(bits)
+    }
+}
+
+impl<C: Codec> fmt::Display for BitSetWeight<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", &self.weight)
+    }
+}
+
+/// The first child of `parent`, which is `parent` itself if it has no child.
+fn first_child(parent_bits: &FixedBitSet, parent: DocId) -> DocId {
+    if parent == 0 {
+        0
+    } else {
+        parent_bits.prev_set_bit(parent as usize - 1) + 1
+    }
+}
+
+/// Matches the parent docs of the children matching `child_query`.
+///
+/// Each parent must be indexed right after its children in a single block, e.g. by
+/// `IndexWriter::add_documents`, and `parents_filter` must match all the parent docs and
+/// only them. The child query must not match any parent doc.
+pub struct ToParentBlockJoinQuery<C: Codec> {
+    child_query: Box<dyn Query<C>>,
+    parents_filter: QueryBitSetProducer<C>,
+    score_mode: BlockJoinScoreMode,
+}
+
+impl<C: Codec> ToParentBlockJoinQuery<C> {
+    pub fn new(
+        child_query: Box<dyn Query<C>>,
+        parents_filter: Box<dyn Query<C>>,
+        score_mode: BlockJoinScoreMode,
+    ) -> Self {
+        ToParentBlockJoinQuery {
+            child_query,
+            parents_filter: QueryBitSetProducer::new(parents_filter),
+            score_mode,
+        }
+    }
+}
+
+impl<C: Codec> Query<C> for ToParentBlockJoinQuery<C> {
+    fn create_weight(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        let needs_scores = needs_scores && self.score_mode != BlockJoinScoreMode::None;
+        Ok(Box::new(ToParentBlockJoinWeight {
+            child_weight: self.child_query.create_weight(searcher, needs_scores)?,
+            parents_weight: self.parents_filter.create_weight(searcher)?,
+            score_mode: self.score_mode,
+            needs_scores,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        self.child_query.extract_terms()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<C: Codec> fmt::Display for ToParentBlockJoinQuery<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ToParentBlockJoinQuery(child: {}, parents: {}, score_mode: {:?})",
+            &self.child_query, &self.parents_filter, self.score_mode
+        )
+    }
+}
+
+struct ToParentBlockJoinWeight<C: Codec> {
+    child_weight: Box<dyn Weight<C>>,
+    parents_weight: BitSetWeight<C>,
+    score_mode: BlockJoinScoreMode,
+    needs_scores: bool,
+}
+
+impl<C: Codec> ToParentBlockJoinWeight<C> {
+    fn scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<ToParentBlockJoinScorer>> {
+        let mut child_scorer = match self.child_weight.create_scorer(reader)? {
+            Some(scorer) => scorer,
+            None => return Ok(None),
+        };
+        let next_child_doc = child_scorer.next()?;
+        if next_child_doc == NO_MORE_DOCS {
+            return Ok(None);
+        }
+        let parent_bits = match self.parents_weight.bit_set(reader)? {
+            Some(bits) => bits,
+            None => return Ok(None),
+        };
+        Ok(Some(ToParentBlockJoinScorer {
+            child_scorer,
+            parent_bits,
+            score_mode: self.score_mode,
+            needs_scores: self.needs_scores,
+            parent_doc: -1,
+            next_child_doc,
+            child_count: 0,
+            score: 0.0,
+        }))
+    }
+}
+
+impl<C: Codec> Weight<C> for ToParentBlockJoinWeight<C> {
+    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        Ok(self
+            .scorer(reader)?
+            .map(|scorer| Box::new(scorer) as Box<dyn Scorer>))
+    }
+
+    fn query_type(&self) -> &'static str {
+        TO_PARENT_BLOCK_JOIN_QUERY
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.child_weight.normalize(norm, boost)
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.child_weight.value_for_normalization()
+    }
+
+    fn needs_scores(&self) -> bool {
+        self.needs_scores
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        if let Some(mut scorer) = self.scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                let score = scorer.score()?;
+                let start = first_child(&scorer.parent_bits, doc);
+                let mut best: Option<Explanation> = None;
+                for child in start..doc {
+                    let child_explain = self.child_weight.explain(reader, child)?;
+                    if child_explain.is_match()
+                        && best
+                            .as_ref()
+                            .map_or(true, |b| child_explain.value() > b.value())
+                    {
+                        best = Some(child_explain);
+                    }
+                }
+                return Ok(Explanation::new(
+                    true,
+                    score,
+                    format!(
+                        "Score based on {} child docs in range from {} to {}, best match:",
+                        scorer.child_count,
+                        start + reader.doc_base,
+                        doc - 1 + reader.doc_base
+                    ),
+                    best.into_iter().collect(),
+                ));
+            }
+        }
+        Ok(Explanation::new(
+            false,
+            0.0,
+            "Not a match".to_string(),
+            vec![],
+        ))
+    }
+}
+
+impl<C: Codec> fmt::Display for ToParentBlockJoinWeight<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ToParentBlockJoinWeight(child: {}, parents: {}, score_mode: {:?})",
+            &self.child_weight, &self.parents_weight, self.score_mode
+        )
+    }
+}
+
+struct ToParentBlockJoinScorer {
+    child_scorer: Box<dyn Scorer>,
+    parent_bits: Arc<FixedBitSet>,
+    score_mode: BlockJoinScoreMode,
+    needs_scores: bool,
+    parent_doc: DocId,
+    // the first child doc not collected into a parent yet
+    next_child_doc: DocId,
+    child_count: i32,
+    score: f32,
+}
+
+impl ToParentBlockJoinScorer {
+    fn next_parent(&mut self) -> Result<DocId> {
+        if self.next_child_doc == NO_MORE_DOCS {
+            self.parent_doc = NO_MORE_DOCS;
+            return Ok(NO_MORE_DOCS);
+        }
+        self.parent_doc = self.parent_bits.next_set_bit(self.next_child_doc as usize);
+        if self.parent_doc == self.next_child_doc {
+            bail!(IllegalState(format!(
+                "child query must not match the same docs as the parents filter, doc {} matches \
+                 both",
+                self.parent_doc
+            )));
+        }
+        if self.parent_doc == NO_MORE_DOCS {
+            // the children after the last parent have no parent
+            self.next_child_doc = NO_MORE_DOCS;
+            return Ok(NO_MORE_DOCS);
+        }
+
+        let mut total = 0.0f32;
+        let mut max = f32::NEG_INFINITY;
+        let mut min = f32::INFINITY;
+        self.child_count = 0;
+        while self.next_child_doc < self.parent_doc {
+            if self.needs_scores {
+                let score = self.child_scorer.score()?;
+                total += score;
+                max = max.max(score);
+                min = min.min(score);
+            }
+            self.child_count += 1;
+            self.next_child_doc = self.child_scorer.next()?;
+        }
+        self.score = match self.score_mode {
+            BlockJoinScoreMode::None => 0.0,
+            BlockJoinScoreMode::Avg => total / self.child_count as f32,
+            BlockJoinScoreMode::Max => max,
+            BlockJoinScoreMode::Min => min,
+            BlockJoinScoreMode::Total => total,
+        };
+        if !self.needs_scores {
+            self.score = 1.0;
+        }
+        Ok(self.parent_doc)
+    }
+}
+
+impl Scorer for ToParentBlockJoinScorer {
+    fn score(&mut self) -> Result<f32> {
+        Ok(self.score)
+    }
+}
+
+impl DocIterator for ToParentBlockJoinScorer {
+    fn doc_id(&self) -> DocId {
+        self.parent_doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.next_parent()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        if target == NO_MORE_DOCS {
+            self.parent_doc = NO_MORE_DOCS;
+            return Ok(NO_MORE_DOCS);
+        }
+        if target > 0 {
+            // skip the children of the parents before target
+            let prev_parent = self.parent_bits.prev_set_bit(target as usize - 1);
+            if prev_parent > self.next_child_doc {
+                self.next_child_doc = self.child_scorer.advance(prev_parent)?;
+            }
+        }
+        self.next_parent()
+    }
+
+    fn cost(&self) -> usize {
+        self.child_scorer.cost()
+    }
+}
+
+/// Matches the child docs of the parents matching `parent_query`, each child is scored
+/// with the score of its parent.
+///
+/// The docs are expected in the blocks described by `ToParentBlockJoinQuery`, the
+/// parent query must only match docs of `parents_filter`.
+pub struct ToChildBlockJoinQuery<C: Codec> {
+    parent_query: Box<dyn Query<C>>,
+    parents_filter: QueryBitSetProducer<C>,
+}
+
+impl<C: Codec> ToChildBlockJoinQuery<C> {
+    pub fn new(parent_query: Box<dyn Query<C>>, parents_filter: Box<dyn Query<C>>) -> Self {
+        ToChildBlockJoinQuery {
+            parent_query,
+            parents_filter: QueryBitSetProducer::new(parents_filter),
+        }
+    }
+}
+
+impl<C: Codec> Query<C> for ToChildBlockJoinQuery<C> {
+    fn create_weight(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        Ok(Box::new(ToChildBlockJoinWeight {
+            parent_weight: self.parent_query.create_weight(searcher, needs_scores)?,
+            parents_weight: self.parents_filter.create_weight(searcher)?,
+            needs_scores,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        self.parent_query.extract_terms()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<C: Codec> fmt::Display for ToChildBlockJoinQuery<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ToChildBlockJoinQuery(parent: {}, parents: {})",
+            &self.parent_query, &self.parents_filter
+        )
+    }
+}
+
+struct ToChildBlockJoinWeight<C: Codec> {
+    parent_weight: Box<dyn Weight<C>>,
+    parents_weight: BitSetWeight<C>,
+    needs_scores: bool,
+}
+
+impl<C: Codec> Weight<C> for ToChildBlockJoinWeight<C> {
+    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        let parent_scorer = match self.parent_weight.create_scorer(reader)? {
+            Some(scorer) => scorer,
+            None => return Ok(None),
+        };
+        let parent_bits = match self.parents_weight.bit_set(reader)? {
+            Some(bits) => bits,
+            None => return Ok(None),
+        };
+        Ok(Some(Box::new(ToChildBlockJoinScorer {
+            parent_scorer,
+            parent_bits,
+            needs_scores: self.needs_scores,
+            child_doc: -1,
+            parent_doc: 0,
+            parent_score: 1.0,
+        })))
+    }
+
+    fn query_type(&self) -> &'static str {
+        TO_CHILD_BLOCK_JOIN_QUERY
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.parent_weight.normalize(norm, boost)
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.parent_weight.value_for_normalization()
+    }
+
+    fn needs_scores(&self) -> bool {
+        self.needs_scores
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        if let Some(mut scorer) = self.create_scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                let bits = self.parents_weight.bit_set(reader)?.unwrap();
+                let parent = bits.next_set_bit(doc as usize);
+                let parent_explain = self.parent_weight.explain(reader, parent)?;
+                return Ok(Explanation::new(
+                    true,
+                    parent_explain.value(),
+                    format!(
+                        "Score based on parent document {}",
+                        parent + reader.doc_base
+                    ),
+                    vec![parent_explain],
+                ));
+            }
+        }
+        Ok(Explanation::new(
+            false,
+            0.0,
+            "Not a match".to_string(),
+            vec![],
+        ))
+    }
+}
+
+impl<C: Codec> fmt::Display for ToChildBlockJoinWeight<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ToChildBlockJoinWeight(parent: {}, parents: {})",
+            &self.parent_weight, &self.parents_weight
+        )
+    }
+}
+
+struct ToChildBlockJoinScorer {
+    parent_scorer: Box<dyn Scorer>,
+    parent_bits: Arc<FixedBitSet>,
+    needs_scores: bool,
+    child_doc: DocId,
+    parent_doc: DocId,
+    parent_score: f32,
+}
+
+impl ToChildBlockJoinScorer {
+    fn validate_parent_doc(&self) -> Result<()> {
+        if self.parent_doc != NO_MORE_DOCS && !self.parent_bits.get(self.parent_doc as usize)? {
+            bail!(IllegalState(format!(
+                "parent query must only match docs of the parents filter, doc {} is not a parent",
+                self.parent_doc
+            )));
+        }
+        Ok(())
+    }
+
+    fn next_parent(&mut self) -> Result<()> {
+        self.parent_doc = self.parent_scorer.next()?;
+        self.validate_parent_doc()
+    }
+
+    fn end(&mut self) -> Result<DocId> {
+        self.parent_doc = NO_MORE_DOCS;
+        self.child_doc = NO_MORE_DOCS;
+        Ok(NO_MORE_DOCS)
+    }
+
+    fn score_parent(&mut self) -> Result<()> {
+        if self.needs_scores {
+            self.parent_score = self.parent_scorer.score()?;
+        }
+        Ok(())
+    }
+}
+
+impl Scorer for ToChildBlockJoinScorer {
+    fn score(&mut self) -> Result<f32> {
+        Ok(self.parent_score)
+    }
+}
+
+impl DocIterator for ToChildBlockJoinScorer {
+    fn doc_id(&self) -> DocId {
+        self.child_doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        if self.child_doc + 1 < self.parent_doc {
+            self.child_doc += 1;
+            return Ok(self.child_doc);
+        }
+        // the children of the current parent are exhausted, move to the next parent
+        // having children
+        loop {
+            self.next_parent()?;
+            if self.parent_doc == NO_MORE_DOCS {
+                return self.end();
+            }
+            let first = first_child(&self.parent_bits, self.parent_doc);
+            if first < self.parent_doc {
+                self.child_doc = first;
+                self.score_parent()?;
+                return Ok(self.child_doc);
+            }
+        }
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let mut target = target;
+        if target >= self.parent_doc {
+            if target == NO_MORE_DOCS {
+                return self.end();
+            }
+            self.parent_doc = self.parent_scorer.advance(target + 1)?;
+            self.validate_parent_doc()?;
+            loop {
+                if self.parent_doc == NO_MORE_DOCS {
+                    return self.end();
+                }
+                let first = first_child(&self.parent_bits, self.parent_doc);
+                if first < self.parent_doc {
+                    target = target.max(first);
+                    break;
+                }
+                self.next_parent()?;
+            }
+            self.score_parent()?;
+        }
+        debug_assert!(target < self.parent_doc);
+        self.child_doc = target;
+        Ok(self.child_doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.parent_scorer.cost()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::codec::CodecEnum;
+    use core::doc::{Fieldable, IndexOptions, Term};
+    use core::index::reader::IndexReader;
+    use core::search::collector::TopDocsCollector;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::{new_fs_writer, text_field};
+
+    use std::sync::Arc;
+
+    fn term<C: Codec>(field: &str, text: &str) -> Box<dyn Query<C>> {
+        Box::new(TermQuery::new(
+            Term::new(field.into(), text.as_bytes().to_vec()),
+            1.0f32,
+            None,
+        ))
+    }
+
+    fn search<IS: IndexSearcher<C>, C: Codec>(
+        searcher: &IS,
+        query: &dyn Query<C>,
+    ) -> Vec<(DocId, f32)> {
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(query, &mut collector).unwrap();
+        let mut docs: Vec<(DocId, f32)> = collector
+            .top_docs()
+            .score_docs()
+            .iter()
+            .map(|d| (d.doc_id(), d.score()))
+            .collect();
+        docs.sort_by_key(|d| d.0);
+        docs
+    }
+
+    #[test]
+    fn test_product_variants() {
+        let (_dir, writer) = new_fs_writer();
+        // each product is indexed right after its variants
+        let products: &[(&str, &[&str])] = &[
+            ("shirt", &["red small", "blue large", "red red large"]),
+            ("shoes", &[]),
+            ("hat", &["blue small"]),
+            ("scarf", &["green large"]),
+        ];
+        for (name, variants) in products {
+            let mut block: Vec<Vec<Box<dyn Fieldable>>> = variants
+                .iter()
+                .map(|v| vec![text_field("variant", v, IndexOptions::DocsAndFreqs)])
+                .collect();
+            block.push(vec![
+                text_field("type", "product", IndexOptions::DocsAndFreqs),
+                text_field("name", name, IndexOptions::DocsAndFreqs),
+            ]);
+            writer.add_documents(block).unwrap();
+        }
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        // the docs: shirt variants 0..3, shirt 3, shoes 4, hat variant 5, hat 6, scarf
+        // variant 7, scarf 8
+        let child_scores = search(&searcher, term("variant", "red").as_ref());
+        assert_eq!(child_scores.len(), 2);
+        let best_red = child_scores[0].1.max(child_scores[1].1);
+        let query = ToParentBlockJoinQuery::new(
+            term("variant", "red"),
+            term("type", "product"),
+            BlockJoinScoreMode::Max,
+        );
+        assert_eq!(search(&searcher, &query), vec![(3, best_red)]);
+
+        let query = ToParentBlockJoinQuery::new(
+            term("variant", "large"),
+            term("type", "product"),
+            BlockJoinScoreMode::Total,
+        );
+        let parents: Vec<DocId> = search(&searcher, &query).iter().map(|d| d.0).collect();
+        assert_eq!(parents, vec![3, 8]);
+
+        // advancing skips the children of the parents before the target
+        let weight = query.create_weight(&searcher, true).unwrap();
+        let leaves = searcher.reader().leaves();
+        let mut scorer = weight.create_scorer(&leaves[0]).unwrap().unwrap();
+        assert_eq!(scorer.advance(4).unwrap(), 8);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+
+        let explanation = searcher.explain(&query, 3).unwrap();
+        assert!(explanation.is_match());
+        assert!(explanation
+            .description()
+            .starts_with("Score based on 2 child docs"));
+        assert!(!searcher.explain(&query, 4).unwrap().is_match());
+
+        // the children of the matching parents, scored with their parent score
+        let parent_query = term("name", "hat");
+        let parent_score = search(&searcher, parent_query.as_ref())[0].1;
+        let query = ToChildBlockJoinQuery::new(parent_query, term("type", "product"));
+        assert_eq!(search(&searcher, &query), vec![(5, parent_score)]);
+
+        let query = ToChildBlockJoinQuery::new(term("type", "product"), term("type", "product"));
+        let children: Vec<DocId> = search(&searcher, &query).iter().map(|d| d.0).collect();
+        assert_eq!(children, vec![0, 1, 2, 5, 7]);
+        let weight = query.create_weight(&searcher, false).unwrap();
+        let mut scorer = weight.create_scorer(&leaves[0]).unwrap().unwrap();
+        assert_eq!(scorer.advance(3).unwrap(), 5);
+        assert_eq!(scorer.advance(6).unwrap(), 7);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+
+        // a parent query matching children is rejected
+        let query = ToChildBlockJoinQuery::new(term("variant", "blue"), term("type", "product"));
+        let mut collector = TopDocsCollector::new(10);
+        assert!(searcher.search(&query, &mut collector).is_err());
+
+        // the parent bits of a segment are built once for all the weights of a query
+        let query = ToParentBlockJoinQuery::new(
+            term("variant", "red"),
+            term("type", "product"),
+            BlockJoinScoreMode::Max,
+        );
+        let parent_bits = || {
+            let weight = query.create_weight(&searcher, true).unwrap();
+            let weight = weight
+                .as_any()
+                .downcast_ref::<ToParentBlockJoinWeight<CodecEnum>>()
+                .unwrap();
+            weight.parents_weight.bit_set(&leaves[0]).unwrap().unwrap()
+        };
+        let first = parent_bits();
+        assert_eq!(search(&searcher, &query), vec![(3, best_red)]);
+        assert!(Arc::ptr_eq(&first, &parent_bits()));
+        assert_eq!(query.parents_filter.cache.read().unwrap().len(), 1);
+        assert_eq!(first.next_set_bit(0), 3);
+    }
+}
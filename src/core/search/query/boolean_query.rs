@@ -13,6 +13,7 @@
 
 use std::any::Any;
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use core::codec::Codec;
 use core::index::reader::LeafReaderContext;
@@ -22,6 +23,8 @@ use core::search::scorer::{
     ConjunctionScorer, DisjunctionSumScorer, ReqNotScorer, ReqOptScorer, Scorer,
 };
 use core::search::searcher::SearchPlanBuilder;
+use core::search::ErrorKind::TooManyClauses;
+use core::search::{DisjunctionMatchesIterator, MatchesIterator};
 use core::util::DocId;
 use error::{ErrorKind::IllegalArgument, Result};
 
@@ -32,11 +35,19 @@ pub struct BooleanQuery<C: Codec> {
     filter_queries: Vec<Box<dyn Query<C>>>,
     must_not_queries: Vec<Box<dyn Query<C>>>,
     min_should_match: i32,
+    coord_enabled: bool,
 }
 
 pub const BOOLEAN: &str = "boolean";
 
+/// The default for `BooleanQuery::max_clause_count`.
+pub const DEFAULT_MAX_CLAUSE_COUNT: usize = 1024;
+
+static MAX_CLAUSE_COUNT: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_CLAUSE_COUNT);
+
 impl<C: Codec> BooleanQuery<C> {
+    /// Creates the query, or the only clause itself if there is a single non-negated one.
+    /// Fails with `TooManyClauses` if there are more than `max_clause_count()` clauses.
     pub fn build(
         musts: Vec<Box<dyn Query<C>>>,
         shoulds: Vec<Box<dyn Query<C>>>,
@@ -44,25 +55,34 @@ impl<C: Codec> BooleanQuery<C> {
         must_nots: Vec<Box<dyn Query<C>>>,
         min_should_match: i32,
     ) -> Result<Box<dyn Query<C>>> {
-        let min_should_match = if min_should_match > 0 {
-            min_should_match
-        } else {
-            if musts.is_empty() {
-                1
-            } else {
-                0
-            }
-        };
+        let max_clause_count = Self::max_clause_count();
+        Self::build_with_max_clause_count(
+            musts,
+            shoulds,
+            filters,
+            must_nots,
+            min_should_match,
+            max_clause_count,
+        )
+    }
 
+    /// Like `build`, but with a limit of `max_clause_count` clauses instead of the global
+    /// one, e.g. for a query parser allowing fewer clauses than the rest of the process.
+    pub fn build_with_max_clause_count(
+        musts: Vec<Box<dyn Query<C>>>,
+        shoulds: Vec<Box<dyn Query<C>>>,
+        filters: Vec<Box<dyn Query<C>>>,
+        must_nots: Vec<Box<dyn Query<C>>>,
+        min_should_match: i32,
+        max_clause_count: usize,
+    ) -> Result<Box<dyn Query<C>>> {
+        let clause_count = musts.len() + shoulds.len() + filters.len() + must_nots.len();
+        if clause_count > max_clause_count {
+            bail!(TooManyClauses(clause_count, max_clause_count));
+        }
         let mut musts = musts;
         let mut shoulds = shoulds;
         let mut filters = filters;
-        let must_nots = must_nots;
-        if musts.len() + shoulds.len() + filters.len() + must_nots.len() == 0 {
-            bail!(IllegalArgument(
-                "boolean query should at least contain one inner query!".into()
-            ));
-        }
         if must_nots.len() == 0 && musts.len() + shoulds.len() + filters.len() == 1 {
             let query = if musts.len() == 1 {
                 musts.remove(0)
@@ -73,17 +93,119 @@ impl<C: Codec> BooleanQuery<C> {
             };
             return Ok(query);
         }
+        Ok(Box::new(Self::with_clauses(
+            musts,
+            shoulds,
+            filters,
+            must_nots,
+            min_should_match,
+        )?))
+    }
+
+    /// Creates a `BooleanQuery` without rewriting single clause queries, for callers
+    /// that need to configure the query before boxing it. Fails with `TooManyClauses`
+    /// like `build`.
+    pub fn new(
+        musts: Vec<Box<dyn Query<C>>>,
+        shoulds: Vec<Box<dyn Query<C>>>,
+        filters: Vec<Box<dyn Query<C>>>,
+        must_nots: Vec<Box<dyn Query<C>>>,
+        min_should_match: i32,
+    ) -> Result<BooleanQuery<C>> {
+        let clause_count = musts.len() + shoulds.len() + filters.len() + must_nots.len();
+        let max_clause_count = Self::max_clause_count();
+        if clause_count > max_clause_count {
+            bail!(TooManyClauses(clause_count, max_clause_count));
+        }
+        Self::with_clauses(musts, shoulds, filters, must_nots, min_should_match)
+    }
+
+    fn with_clauses(
+        musts: Vec<Box<dyn Query<C>>>,
+        shoulds: Vec<Box<dyn Query<C>>>,
+        filters: Vec<Box<dyn Query<C>>>,
+        must_nots: Vec<Box<dyn Query<C>>>,
+        min_should_match: i32,
+    ) -> Result<BooleanQuery<C>> {
+        let min_should_match = if min_should_match > 0 {
+            min_should_match
+        } else if musts.is_empty() {
+            1
+        } else {
+            0
+        };
+
+        let mut musts = musts;
+        if musts.len() + shoulds.len() + filters.len() + must_nots.len() == 0 {
+            bail!(IllegalArgument(
+                "boolean query should at least contain one inner query!".into()
+            ));
+        }
         if musts.len() + shoulds.len() + filters.len() == 0 {
             // only must_not exists
             musts.push(Box::new(MatchAllDocsQuery {}));
         }
-        Ok(Box::new(BooleanQuery {
+        Ok(BooleanQuery {
             must_queries: musts,
             should_queries: shoulds,
             filter_queries: filters,
             must_not_queries: must_nots,
             min_should_match,
-        }))
+            coord_enabled: false,
+        })
+    }
+
+    /// Returns the maximum number of clauses of a `BooleanQuery`, shared by all queries
+    /// and the rewrites of multi-term queries. Defaults to `DEFAULT_MAX_CLAUSE_COUNT`.
+    pub fn max_clause_count() -> usize {
+        MAX_CLAUSE_COUNT.load(Ordering::Acquire)
+    }
+
+    /// Sets the maximum number of clauses, creating a query or rewriting a multi-term
+    /// query into more fails with `TooManyClauses`. This guards against expanding a
+    /// wildcard into a huge number of term clauses.
+    pub fn set_max_clause_count(max_clause_count: usize) -> Result<()> {
+        if max_clause_count == 0 {
+            bail!(IllegalArgument(
+                "max clause count must be at least 1".into()
+            ));
+        }
+        MAX_CLAUSE_COUNT.store(max_clause_count, Ordering::Release);
+        Ok(())
+    }
+
+    /// Enables the classic coordination factor, which rewards docs matching more of
+    /// the optional clauses.
+    ///
+    /// When enabled, the summed score of the SHOULD clauses is multiplied by
+    /// `matched SHOULD clauses / total SHOULD clauses`. Required clauses are not part of
+    /// the coord denominator and their scores are left as is.
+    pub fn set_coord_enabled(&mut self, enabled: bool) {
+        self.coord_enabled = enabled;
+    }
+
+    pub fn is_coord_enabled(&self) -> bool {
+        self.coord_enabled
+    }
+
+    pub fn must_queries(&self) -> &[Box<dyn Query<C>>] {
+        &self.must_queries
+    }
+
+    pub fn should_queries(&self) -> &[Box<dyn Query<C>>] {
+        &self.should_queries
+    }
+
+    pub fn filter_queries(&self) -> &[Box<dyn Query<C>>] {
+        &self.filter_queries
+    }
+
+    pub fn must_not_queries(&self) -> &[Box<dyn Query<C>>] {
+        &self.must_not_queries
+    }
+
+    pub fn min_should_match(&self) -> i32 {
+        self.min_should_match
     }
 
     fn queries_to_str(&self, queries: &[Box<dyn Query<C>>]) -> String {
@@ -121,6 +243,7 @@ impl<C: Codec> Query<C> for BooleanQuery<C> {
             must_not_weights,
             needs_scores,
             self.min_should_match,
+            self.coord_enabled,
         )))
     }
 
@@ -155,8 +278,14 @@ impl<C: Codec> fmt::Display for BooleanQuery<C> {
         let must_not_str = self.queries_to_str(&self.must_not_queries);
         write!(
             f,
-            "BooleanQuery(must: [{}], should: [{}], filters: [{}], must_not: [{}], match: {})",
-            must_str, should_str, filters_str, must_not_str, self.min_should_match
+            "BooleanQuery(must: [{}], should: [{}], filters: [{}], must_not: [{}], match: {}, \
+             coord: {})",
+            must_str,
+            should_str,
+            filters_str,
+            must_not_str,
+            self.min_should_match,
+            self.coord_enabled
         )
     }
 }
@@ -167,6 +296,7 @@ struct BooleanWeight<C: Codec> {
     must_not_weights: Vec<Box<dyn Weight<C>>>,
     min_should_match: i32,
     needs_scores: bool,
+    coord_enabled: bool,
 }
 
 impl<C: Codec> BooleanWeight<C> {
@@ -176,6 +306,7 @@ impl<C: Codec> BooleanWeight<C> {
         must_nots: Vec<Box<dyn Weight<C>>>,
         needs_scores: bool,
         min_should_match: i32,
+        coord_enabled: bool,
     ) -> BooleanWeight<C> {
         BooleanWeight {
             must_weights: musts,
@@ -183,9 +314,16 @@ impl<C: Codec> BooleanWeight<C> {
             must_not_weights: must_nots,
             min_should_match,
             needs_scores,
+            coord_enabled,
         }
     }
 
+    /// Whether the coord factor is applied to the SHOULD clauses, it has no effect
+    /// with a single optional clause.
+    fn use_coord(&self) -> bool {
+        self.coord_enabled && self.needs_scores && self.should_weights.len() > 1
+    }
+
     fn weights_to_str(&self, weights: &[Box<dyn Weight<C>>]) -> String {
         let weight_strs: Vec<String> = weights.iter().map(|q| format!("{}", q)).collect();
         weight_strs.join(", ")
@@ -223,6 +361,12 @@ impl<C: Codec> Weight<C> for BooleanWeight<C> {
             }
             match scorers.len() {
                 0 => None,
+                _ if self.use_coord() => Some(Box::new(DisjunctionSumScorer::with_coord(
+                    scorers,
+                    self.needs_scores,
+                    self.min_should_match,
+                    self.should_weights.len(),
+                ))),
                 1 => Some(scorers.remove(0)),
                 _ => Some(Box::new(DisjunctionSumScorer::new(
                     scorers,
@@ -311,6 +455,7 @@ impl<C: Codec> Weight<C> for BooleanWeight<C> {
         let mut coord = 0;
         let mut max_coord = 0;
         let mut sum = 0.0f32;
+        let mut should_sum = 0.0f32;
         let mut fail = false;
         let mut match_count = 0;
         let mut should_match_count = 0;
@@ -342,6 +487,7 @@ impl<C: Codec> Weight<C> for BooleanWeight<C> {
 
             if e.is_match() {
                 sum += e.value();
+                should_sum += e.value();
                 coord += 1;
                 match_count += 1;
                 should_match_count += 1;
@@ -377,8 +523,25 @@ impl<C: Codec> Weight<C> for BooleanWeight<C> {
             // we have a match
             let result = Explanation::new(true, sum, "sum of:".to_string(), subs);
 
-            let coord_factor = 1.0f32;
-            if (coord_factor - 1.0).abs() < ::std::f32::EPSILON {
+            if self.use_coord() {
+                let max_should = self.should_weights.len();
+                let coord_factor = should_match_count as f32 / max_should as f32;
+                Ok(Explanation::new(
+                    true,
+                    sum - should_sum + should_sum * coord_factor,
+                    "sum of required clauses and optional clauses times coord:".to_string(),
+                    vec![
+                        result,
+                        Explanation::new(
+                            true,
+                            coord_factor,
+                            format!("coord({}/{})", should_match_count, max_should),
+                            vec![],
+                        ),
+                    ],
+                ))
+            } else {
+                let coord_factor = 1.0f32;
                 Ok(Explanation::new(
                     true,
                     sum * coord_factor,
@@ -393,11 +556,42 @@ impl<C: Codec> Weight<C> for BooleanWeight<C> {
                         ),
                     ],
                 ))
-            } else {
-                Ok(result)
             }
         }
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        for w in &self.must_not_weights {
+            if w.matches(reader, doc)?.is_some() {
+                return Ok(None);
+            }
+        }
+
+        let mut subs = vec![];
+        for w in &self.must_weights {
+            match w.matches(reader, doc)? {
+                Some(sub) => subs.push(sub),
+                None => return Ok(None),
+            }
+        }
+        let mut should_match_count = 0;
+        for w in &self.should_weights {
+            if let Some(sub) = w.matches(reader, doc)? {
+                should_match_count += 1;
+                subs.push(sub);
+            }
+        }
+
+        if subs.is_empty() || should_match_count < self.min_should_match {
+            Ok(None)
+        } else {
+            Ok(Some(DisjunctionMatchesIterator::from_subs(subs)))
+        }
+    }
 }
 
 impl<C: Codec> fmt::Display for BooleanWeight<C> {
@@ -413,3 +607,164 @@ impl<C: Codec> fmt::Display for BooleanWeight<C> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::codec::tests::TestCodec;
+    use core::codec::CodecEnum;
+    use core::doc::Term;
+    use core::index::reader::IndexReader;
+    use core::index::tests::*;
+    use core::search::query::AutomatonQuery;
+    use core::search::tests::*;
+    use core::search::{self, NO_MORE_DOCS};
+    use core::test_util::{keyword_field, new_fs_writer};
+    use core::util::automaton::Automaton;
+    use error::{Error, ErrorKind};
+
+    use std::collections::HashMap;
+
+    fn collect_scores(weight: &BooleanWeight<TestCodec>) -> HashMap<DocId, f32> {
+        let leaf_reader = MockLeafReader::new(0);
+        let index_reader = MockIndexReader::new(vec![leaf_reader]);
+        let leaves = index_reader.leaves();
+        let mut scorer = weight.create_scorer(&leaves[0]).unwrap().unwrap();
+
+        let mut scores = HashMap::new();
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            scores.insert(doc, scorer.score().unwrap());
+        }
+        scores
+    }
+
+    fn should_weight(coord_enabled: bool) -> BooleanWeight<TestCodec> {
+        // doc 3 matches all three clauses, doc 1 matches only one of them
+        let shoulds: Vec<Box<dyn Weight<TestCodec>>> = vec![
+            Box::new(create_mock_weight(vec![1, 3])),
+            Box::new(create_mock_weight(vec![3])),
+            Box::new(create_mock_weight(vec![3])),
+        ];
+        BooleanWeight::new(vec![], shoulds, vec![], true, 1, coord_enabled)
+    }
+
+    #[test]
+    fn test_coord_rewards_more_matching_clauses() {
+        // mock scorers score each doc with its doc id
+        let plain = collect_scores(&should_weight(false));
+        assert!((plain[&1] - 1.0).abs() < ::std::f32::EPSILON);
+        assert!((plain[&3] - 9.0).abs() < ::std::f32::EPSILON);
+
+        let coord = collect_scores(&should_weight(true));
+        assert!((coord[&1] - 1.0 / 3.0).abs() < ::std::f32::EPSILON);
+        assert!((coord[&3] - 9.0).abs() < ::std::f32::EPSILON);
+        assert!(coord[&3] / coord[&1] > plain[&3] / plain[&1]);
+    }
+
+    #[test]
+    fn test_coord_excludes_required_clauses() {
+        let musts: Vec<Box<dyn Weight<TestCodec>>> =
+            vec![Box::new(create_mock_weight(vec![1, 2, 3]))];
+        let shoulds: Vec<Box<dyn Weight<TestCodec>>> = vec![
+            Box::new(create_mock_weight(vec![2, 3])),
+            Box::new(create_mock_weight(vec![3])),
+        ];
+        let weight = BooleanWeight::new(musts, shoulds, vec![], true, 0, true);
+        let scores = collect_scores(&weight);
+        // required score only
+        assert!((scores[&1] - 1.0).abs() < ::std::f32::EPSILON);
+        // 2 + 2 * 1/2
+        assert!((scores[&2] - 3.0).abs() < ::std::f32::EPSILON);
+        // 3 + 6 * 2/2
+        assert!((scores[&3] - 9.0).abs() < ::std::f32::EPSILON);
+    }
+
+    fn prefix_clauses(n: usize) -> Vec<Box<dyn Query<TestCodec>>> {
+        (0..n)
+            .map(|i| {
+                let term = Term::new("title".into(), format!("p{:05}", i).into_bytes());
+                Box::new(TermQuery::new(term, 1.0, None)) as Box<dyn Query<TestCodec>>
+            })
+            .collect()
+    }
+
+    fn assert_too_many_clauses<T>(res: Result<T>, expected_count: usize, expected_max: usize) {
+        match res {
+            Err(Error(ErrorKind::Search(search::ErrorKind::TooManyClauses(count, max)), _)) => {
+                assert_eq!(count, expected_count);
+                assert_eq!(max, expected_max);
+            }
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("expected too many clauses"),
+        }
+    }
+
+    // a single test as the limit is global
+    #[test]
+    fn test_max_clause_count() {
+        assert_eq!(
+            BooleanQuery::<TestCodec>::max_clause_count(),
+            DEFAULT_MAX_CLAUSE_COUNT
+        );
+        assert!(BooleanQuery::<TestCodec>::set_max_clause_count(0).is_err());
+
+        let at_cap = prefix_clauses(DEFAULT_MAX_CLAUSE_COUNT);
+        assert!(BooleanQuery::build(vec![], at_cap, vec![], vec![], 0).is_ok());
+        let over_cap = prefix_clauses(DEFAULT_MAX_CLAUSE_COUNT + 76);
+        assert_too_many_clauses(
+            BooleanQuery::build(vec![], over_cap, vec![], vec![], 0),
+            1100,
+            DEFAULT_MAX_CLAUSE_COUNT,
+        );
+        let over_cap = prefix_clauses(DEFAULT_MAX_CLAUSE_COUNT + 76);
+        assert_too_many_clauses(
+            BooleanQuery::new(vec![], over_cap, vec![], vec![], 0),
+            1100,
+            DEFAULT_MAX_CLAUSE_COUNT,
+        );
+        // an explicit limit replaces the global one
+        let clauses = prefix_clauses(3);
+        assert_too_many_clauses(
+            BooleanQuery::build_with_max_clause_count(vec![], clauses, vec![], vec![], 0, 2),
+            3,
+            2,
+        );
+
+        // rewriting a prefix matching more terms than the cap
+        let (_dir, writer) = new_fs_writer();
+        for i in 0..DEFAULT_MAX_CLAUSE_COUNT + 76 {
+            let value = format!("p{:05}", i);
+            writer
+                .add_document(vec![keyword_field("title", &value)])
+                .unwrap();
+        }
+        writer
+            .add_document(vec![keyword_field("title", "q")])
+            .unwrap();
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let prefix = AutomatonQuery::new("title".into(), Automaton::make_wildcard(b"p*")).unwrap();
+        // the terms are counted up to the first one over the cap
+        assert_too_many_clauses(
+            prefix.rewrite::<CodecEnum>(&reader),
+            DEFAULT_MAX_CLAUSE_COUNT + 1,
+            DEFAULT_MAX_CLAUSE_COUNT,
+        );
+
+        BooleanQuery::<CodecEnum>::set_max_clause_count(2048).unwrap();
+        let rewritten = prefix.rewrite::<CodecEnum>(&reader);
+        BooleanQuery::<CodecEnum>::set_max_clause_count(DEFAULT_MAX_CLAUSE_COUNT).unwrap();
+        let rewritten = rewritten.unwrap();
+        let boolean = rewritten
+            .as_any()
+            .downcast_ref::<BooleanQuery<CodecEnum>>()
+            .unwrap();
+        assert_eq!(boolean.should_queries().len(), 1100);
+        assert!(boolean.must_queries().is_empty());
+    }
+}
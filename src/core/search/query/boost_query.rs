@@ -21,6 +21,7 @@ use core::search::explanation::Explanation;
 use core::search::query::{Query, TermQuery, Weight};
 use core::search::scorer::Scorer;
 use core::search::searcher::SearchPlanBuilder;
+use core::search::MatchesIterator;
 use core::util::DocId;
 
 use error::Result;
@@ -122,6 +123,14 @@ impl<C: Codec> Weight<C> for BoostWeight<C> {
     fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
         self.weight.explain(reader, doc)
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.weight.matches(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for BoostWeight<C> {
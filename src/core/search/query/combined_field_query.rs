@@ -0,0 +1,543 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use core::codec::doc_values::NumericDocValues;
+use core::codec::{Codec, CodecPostingIterator, PostingIterator, PostingIteratorFlags};
+use core::codec::{TermIterator, Terms};
+use core::doc::Term;
+use core::index::reader::LeafReaderContext;
+use core::search::explanation::Explanation;
+use core::search::query::{Query, TermQuery, Weight};
+use core::search::scorer::Scorer;
+use core::search::searcher::SearchPlanBuilder;
+use core::search::similarity::BM25Similarity;
+use core::search::statistics::{CollectionStatistics, TermStatistics};
+use core::search::{DocIterator, MatchesIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::{ErrorKind::IllegalArgument, Result};
+
+const COMBINED_FIELD: &str = "combined_field";
+
+/// A query scoring its terms over several fields with BM25 as if the fields were
+/// concatenated into a single one, a.k.a. BM25F.
+///
+/// Summing the scores of a `TermQuery` per field inflates the scores of the docs that
+/// contain a term in several fields, as every field adds the whole idf of the term.
+/// Here the frequency of a term in a doc, the length of the doc and the collection
+/// statistics are the sums over the fields, each field counting `weight` times, and each
+/// term is scored once.
+///
+/// The fields must be indexed with norms computed by `BM25Similarity`, the similarity of
+/// the searcher is not used. A field weight must be at least 1.
+pub struct CombinedFieldQuery {
+    fields: Vec<(String, f32)>,
+    terms: Vec<Vec<u8>>,
+    similarity: Arc<BM25Similarity>,
+}
+
+impl CombinedFieldQuery {
+    pub fn new(fields: Vec<(String, f32)>, terms: Vec<Vec<u8>>) -> Result<CombinedFieldQuery> {
+        if fields.is_empty() || terms.is_empty() {
+            bail!(IllegalArgument(
+                "combined field query needs at least one field and one term".into()
+            ));
+        }
+        for (field, weight) in &fields {
+            if weight.is_nan() || *weight < 1.0 {
+                bail!(IllegalArgument(format!(
+                    "weight of field {} must be at least 1, got {}",
+                    field, weight
+                )));
+            }
+        }
+        Ok(CombinedFieldQuery {
+            fields,
+            terms,
+            similarity: Arc::new(BM25Similarity::default()),
+        })
+    }
+
+    /// Scores with the given BM25 parameters instead of the default ones.
+    pub fn set_similarity(&mut self, similarity: BM25Similarity) {
+        self.similarity = Arc::new(similarity);
+    }
+
+    fn term(&self, field: usize, term: usize) -> Term {
+        Term::new(self.fields[field].0.clone(), self.terms[term].clone())
+    }
+}
+
+impl<C: Codec> Query<C> for CombinedFieldQuery {
+    fn create_weight(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        _needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        // the statistics of the concatenated field
+        let max_doc = i64::from(searcher.max_doc());
+        let mut doc_count = 0;
+        let mut sum_total_term_freq = 0f64;
+        for (field, weight) in &self.fields {
+            if let Some(stat) = searcher.collections_statistics(field) {
+                let count = if stat.doc_count == -1 {
+                    stat.max_doc
+                } else {
+                    stat.doc_count
+                };
+                doc_count = doc_count.max(count);
+                if stat.sum_total_term_freq > 0 {
+                    sum_total_term_freq += f64::from(*weight) * stat.sum_total_term_freq as f64;
+                }
+            }
+        }
+        let collection_stats = CollectionStatistics::new(
+            COMBINED_FIELD.into(),
+            0,
+            max_doc,
+            doc_count,
+            sum_total_term_freq.ceil() as i64,
+            -1,
+        );
+        let avg_length = BM25Similarity::avg_field_length(&collection_stats);
+
+        let mut idfs = Vec::with_capacity(self.terms.len());
+        for (i, bytes) in self.terms.iter().enumerate() {
+            // a doc counts once however many fields it has the term in
+            let mut doc_freq = 0;
+            for field in 0..self.fields.len() {
+                doc_freq = doc_freq.max(searcher.term_statistics(&self.term(field, i))?.doc_freq);
+            }
+            let term_stats = TermStatistics::new(bytes.clone(), doc_freq, -1);
+            idfs.push(BM25Similarity::idf(&[term_stats], &collection_stats));
+        }
+
+        Ok(Box::new(CombinedFieldWeight {
+            fields: self.fields.clone(),
+            terms: self.terms.clone(),
+            similarity: Arc::clone(&self.similarity),
+            idfs,
+            avg_length,
+            boost: 1.0f32,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        let mut terms = Vec::with_capacity(self.fields.len() * self.terms.len());
+        for field in 0..self.fields.len() {
+            for term in 0..self.terms.len() {
+                terms.push(TermQuery::new(self.term(field, term), 1.0f32, None));
+            }
+        }
+        terms
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl fmt::Display for CombinedFieldQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms: Vec<_> = self
+            .terms
+            .iter()
+            .map(|t| String::from_utf8_lossy(t))
+            .collect();
+        write!(
+            f,
+            "CombinedFieldQuery(fields: {:?}, terms: {:?}, similarity: {})",
+            &self.fields, terms, &self.similarity
+        )
+    }
+}
+
+struct CombinedFieldWeight {
+    fields: Vec<(String, f32)>,
+    terms: Vec<Vec<u8>>,
+    similarity: Arc<BM25Similarity>,
+    idfs: Vec<f32>,
+    avg_length: f32,
+    boost: f32,
+}
+
+impl CombinedFieldWeight {
+    fn scorer<C: Codec>(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<CombinedFieldScorer<CodecPostingIterator<C>>>> {
+        let mut postings = vec![];
+        let mut norms = Vec::with_capacity(self.fields.len());
+        for (field, weight) in &self.fields {
+            // the length counts the field even if it has none of the terms
+            if let Some(field_norms) = reader.reader.norm_values(field)? {
+                norms.push((*weight, field_norms));
+            }
+            let field_terms = match reader.reader.terms(field)? {
+                Some(field_terms) => field_terms,
+                None => continue,
+            };
+            let mut term_iter = field_terms.iterator()?;
+            for (term, bytes) in self.terms.iter().enumerate() {
+                if term_iter.seek_exact(bytes)? {
+                    postings.push(FieldPostings {
+                        term,
+                        weight: *weight,
+                        postings: term_iter.postings_with_flags(PostingIteratorFlags::FREQS)?,
+                    });
+                }
+            }
+        }
+        if postings.is_empty() {
+            return Ok(None);
+        }
+
+        let weights = self.idfs.iter().map(|idf| idf * self.boost).collect();
+        Ok(Some(CombinedFieldScorer::new(
+            postings,
+            norms,
+            Arc::clone(&self.similarity),
+            weights,
+            self.avg_length,
+        )))
+    }
+}
+
+impl<C: Codec> Weight<C> for CombinedFieldWeight {
+    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        Ok(self
+            .scorer(reader)?
+            .map(|scorer| Box::new(scorer) as Box<dyn Scorer>))
+    }
+
+    fn query_type(&self) -> &'static str {
+        COMBINED_FIELD
+    }
+
+    fn normalize(&mut self, _norm: f32, boost: f32) {
+        self.boost = boost;
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.idfs
+            .iter()
+            .map(|idf| idf * self.boost * idf * self.boost)
+            .sum()
+    }
+
+    fn needs_scores(&self) -> bool {
+        true
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        if let Some(mut scorer) = self.scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                let length = scorer.length()?;
+                let mut subs = vec![];
+                for (term, &freq) in scorer.freqs.iter().enumerate() {
+                    if freq > 0.0 {
+                        let weight = scorer.weights[term];
+                        let score = self.similarity.score_with_length(
+                            weight,
+                            freq,
+                            length,
+                            self.avg_length,
+                        );
+                        subs.push(Explanation::new(
+                            true,
+                            score,
+                            format!(
+                                "score(term={}), computed from:",
+                                String::from_utf8_lossy(&self.terms[term])
+                            ),
+                            vec![
+                                Explanation::new(true, weight, "idf * boost".into(), vec![]),
+                                Explanation::new(true, freq, "combined freq".into(), vec![]),
+                                Explanation::new(true, length, "combined length".into(), vec![]),
+                                Explanation::new(
+                                    true,
+                                    self.avg_length,
+                                    "avg combined length".into(),
+                                    vec![],
+                                ),
+                            ],
+                        ));
+                    }
+                }
+                let score = subs.iter().map(|e| e.value()).sum();
+                return Ok(Explanation::new(
+                    true,
+                    score,
+                    format!("weight({} in {}), sum of:", self, doc),
+                    subs,
+                ));
+            }
+        }
+
+        Ok(Explanation::new(
+            false,
+            0.0f32,
+            "no matching term".to_string(),
+            vec![],
+        ))
+    }
+
+    // the terms match in several fields, which the positions of a `MatchesIterator` can't
+    // tell apart
+    fn matches(
+        &self,
+        _reader: &LeafReaderContext<'_, C>,
+        _doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        Ok(None)
+    }
+}
+
+impl fmt::Display for CombinedFieldWeight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "CombinedFieldWeight(fields: {:?}, terms: {}, similarity: {})",
+            &self.fields,
+            self.terms.len(),
+            &self.similarity
+        )
+    }
+}
+
+struct FieldPostings<T: PostingIterator> {
+    term: usize,
+    weight: f32,
+    postings: T,
+}
+
+/// Matches the docs containing any of the terms in any of the fields, the frequencies
+/// of a term in the fields of the current doc are summed up by field weight.
+struct CombinedFieldScorer<T: PostingIterator> {
+    postings: Vec<FieldPostings<T>>,
+    norms: Vec<(f32, Box<dyn NumericDocValues>)>,
+    similarity: Arc<BM25Similarity>,
+    weights: Vec<f32>,
+    avg_length: f32,
+    doc: DocId,
+    cost: usize,
+    freqs: Vec<f32>,
+}
+
+impl<T: PostingIterator> CombinedFieldScorer<T> {
+    fn new(
+        postings: Vec<FieldPostings<T>>,
+        norms: Vec<(f32, Box<dyn NumericDocValues>)>,
+        similarity: Arc<BM25Similarity>,
+        weights: Vec<f32>,
+        avg_length: f32,
+    ) -> Self {
+        let cost = postings.iter().map(|p| p.postings.cost()).sum();
+        let num_terms = weights.len();
+        CombinedFieldScorer {
+            postings,
+            norms,
+            similarity,
+            weights,
+            avg_length,
+            doc: -1,
+            cost,
+            freqs: vec![0.0; num_terms],
+        }
+    }
+
+    fn update_doc(&mut self) -> Result<DocId> {
+        self.doc = self
+            .postings
+            .iter()
+            .map(|p| p.postings.doc_id())
+            .min()
+            .unwrap_or(NO_MORE_DOCS);
+        for freq in &mut self.freqs {
+            *freq = 0.0;
+        }
+        if self.doc != NO_MORE_DOCS {
+            for p in &self.postings {
+                if p.postings.doc_id() == self.doc {
+                    self.freqs[p.term] += p.weight * p.postings.freq()? as f32;
+                }
+            }
+        }
+        Ok(self.doc)
+    }
+
+    /// The length of the current doc over all the fields with norms.
+    fn length(&self) -> Result<f32> {
+        let mut length = 0.0f32;
+        for (weight, norms) in &self.norms {
+            length += weight * BM25Similarity::decode_field_length(norms.get(self.doc)?);
+        }
+        Ok(length)
+    }
+}
+
+impl<T: PostingIterator> Scorer for CombinedFieldScorer<T> {
+    fn score(&mut self) -> Result<f32> {
+        let length = self.length()?;
+        let mut score = 0.0f32;
+        for (&freq, &weight) in self.freqs.iter().zip(&self.weights) {
+            if freq > 0.0 {
+                score += self
+                    .similarity
+                    .score_with_length(weight, freq, length, self.avg_length);
+            }
+        }
+        Ok(score)
+    }
+}
+
+impl<T: PostingIterator> DocIterator for CombinedFieldScorer<T> {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.doc;
+        for p in &mut self.postings {
+            if p.postings.doc_id() == doc {
+                p.postings.next()?;
+            }
+        }
+        self.update_doc()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        for p in &mut self.postings {
+            if p.postings.doc_id() < target {
+                p.postings.advance(target)?;
+            }
+        }
+        self.update_doc()
+    }
+
+    fn cost(&self) -> usize {
+        self.cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::doc::IndexOptions;
+    use core::index::reader::IndexReader;
+    use core::search::collector::TopDocsCollector;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::{new_fs_writer, text_field};
+
+    fn search<IS: IndexSearcher<C>, C: Codec>(
+        searcher: &IS,
+        query: &dyn Query<C>,
+    ) -> Vec<(DocId, f32)> {
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(query, &mut collector).unwrap();
+        let mut docs: Vec<(DocId, f32)> = collector
+            .top_docs()
+            .score_docs()
+            .iter()
+            .map(|d| (d.doc_id(), d.score()))
+            .collect();
+        docs.sort_by_key(|d| d.0);
+        docs
+    }
+
+    fn query(fields: &[(&str, f32)], terms: &[&str]) -> CombinedFieldQuery {
+        CombinedFieldQuery::new(
+            fields.iter().map(|(f, w)| (f.to_string(), *w)).collect(),
+            terms.iter().map(|t| t.as_bytes().to_vec()).collect(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_combined_bm25_scores() {
+        let (_dir, writer) = new_fs_writer();
+        for (title, body) in &[
+            ("fox", "quick brown fox jumps"),
+            ("dog", "quick brown fox jumps"),
+            ("fox", "lazy dog sleeps here"),
+            ("cat", "cat"),
+        ] {
+            let doc = vec![
+                text_field("title", title, IndexOptions::DocsAndFreqs),
+                text_field("body", body, IndexOptions::DocsAndFreqs),
+            ];
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let lengths: Vec<f32> = {
+            let leaves = reader.leaves();
+            let norm = |field: &str, doc: DocId| {
+                let norms = leaves[0].reader.norm_values(field).unwrap().unwrap();
+                BM25Similarity::decode_field_length(norms.get(doc).unwrap())
+            };
+            (0..3)
+                .map(|doc| norm("title", doc) + norm("body", doc))
+                .collect()
+        };
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        let fields = [("title", 1.0), ("body", 1.0)];
+        let docs = search(&searcher, &query(&fields, &["fox"]));
+        assert_eq!(docs.iter().map(|d| d.0).collect::<Vec<_>>(), vec![0, 1, 2]);
+
+        // 4 + 13 terms over 4 docs, fox is in at most 2 docs of a field
+        let similarity = BM25Similarity::default();
+        let avg_length = 17.0 / 4.0;
+        let idf = (1.0f32 + 2.5 / 2.5).ln();
+        for &(doc, score) in &docs {
+            let freq = if doc == 0 { 2.0 } else { 1.0 };
+            let expected =
+                similarity.score_with_length(idf, freq, lengths[doc as usize], avg_length);
+            assert!((score - expected).abs() < 1e-5, "doc {}: {}", doc, score);
+        }
+
+        // a doc with the term in both fields doesn't get both idfs
+        let title = TermQuery::new(Term::new("title".into(), b"fox".to_vec()), 1.0, None);
+        let body = TermQuery::new(Term::new("body".into(), b"fox".to_vec()), 1.0, None);
+        let summed = search(&searcher, &title)[0].1 + search(&searcher, &body)[0].1;
+        assert!(docs[0].1 > docs[1].1);
+        assert!(docs[0].1 < summed);
+
+        // the terms are scored independently, a heavier title counts its terms twice
+        let docs = search(&searcher, &query(&fields, &["fox", "dog"]));
+        assert_eq!(docs.iter().map(|d| d.0).collect::<Vec<_>>(), vec![0, 1, 2]);
+        let weighted = search(
+            &searcher,
+            &query(&[("title", 2.0), ("body", 1.0)], &["dog"]),
+        );
+        assert_eq!(weighted.iter().map(|d| d.0).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(weighted[0].1 > weighted[1].1);
+
+        let explanation = searcher
+            .explain(&query(&fields, &["fox", "dog"]), 1)
+            .unwrap();
+        assert!(explanation.is_match());
+        assert_eq!(explanation.details().len(), 2);
+        assert!((explanation.value() - docs[1].1).abs() < 1e-6);
+
+        let fox = vec![b"fox".to_vec()];
+        assert!(CombinedFieldQuery::new(vec![("title".into(), 0.5)], fox.clone()).is_err());
+        assert!(CombinedFieldQuery::new(vec![], fox).is_err());
+    }
+}
@@ -17,6 +17,7 @@ use core::search::explanation::Explanation;
 use core::search::query::{Query, TermQuery, Weight};
 use core::search::scorer::{DisjunctionMaxScorer, Scorer};
 use core::search::searcher::SearchPlanBuilder;
+use core::search::{DisjunctionMatchesIterator, MatchesIterator};
 use core::util::DocId;
 
 use error::ErrorKind::IllegalArgument;
@@ -224,6 +225,24 @@ impl<C: Codec> Weight<C> for DisjunctionMaxWeight<C> {
             ))
         }
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        let mut subs = vec![];
+        for w in &self.weights {
+            if let Some(sub) = w.matches(reader, doc)? {
+                subs.push(sub);
+            }
+        }
+        if subs.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(DisjunctionMatchesIterator::from_subs(subs)))
+        }
+    }
 }
 
 impl<C: Codec> fmt::Display for DisjunctionMaxWeight<C> {
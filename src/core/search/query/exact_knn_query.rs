@@ -0,0 +1,232 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::fmt;
+
+use core::codec::Codec;
+use core::index::reader::LeafReaderContext;
+use core::index::{compare_scores, exact_leaf_search};
+use core::search::explanation::Explanation;
+use core::search::query::{Query, TermQuery, Weight};
+use core::search::scorer::Scorer;
+use core::search::searcher::SearchPlanBuilder;
+use core::search::{DocIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::Result;
+
+const EXACT_KNN_QUERY: &str = "exact_knn";
+
+/// A query matching the `k` docs whose `DenseVectorField` in `field` is the nearest to
+/// `query_vector`, scored with the similarity of the field.
+///
+/// Unlike `IndexSearcher::knn_search` no HNSW graph is used, the query vector is compared
+/// to every vector, so this costs a scan of the vectors but always finds the true nearest
+/// neighbors. The `k` nearest docs of the index are found once when the weight is
+/// created, each leaf then matches its share of them.
+pub struct ExactKnnQuery {
+    field: String,
+    query_vector: Vec<f32>,
+    k: usize,
+}
+
+impl ExactKnnQuery {
+    pub fn new(field: String, query_vector: Vec<f32>, k: usize) -> ExactKnnQuery {
+        ExactKnnQuery {
+            field,
+            query_vector,
+            k,
+        }
+    }
+}
+
+impl<C: Codec> Query<C> for ExactKnnQuery {
+    fn create_weight(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        _needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        let mut hits = vec![];
+        for leaf in searcher.leaves() {
+            for (doc, score) in exact_leaf_search(&leaf, &self.field, &self.query_vector, self.k)? {
+                hits.push((leaf.doc_base + doc, score));
+            }
+        }
+        hits.sort_by(|a, b| compare_scores(b.1, a.1).then_with(|| a.0.cmp(&b.0)));
+        hits.truncate(self.k);
+        hits.sort_by_key(|h| h.0);
+        Ok(Box::new(ExactKnnWeight {
+            field: self.field.clone(),
+            k: self.k,
+            hits,
+            boost: 1f32,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        vec![]
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl fmt::Display for ExactKnnQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ExactKnnQuery(field: {}, dimension: {}, k: {})",
+            &self.field,
+            self.query_vector.len(),
+            self.k
+        )
+    }
+}
+
+struct ExactKnnWeight {
+    field: String,
+    k: usize,
+    // the nearest docs of the index by their doc id in the top reader, sorted by doc id
+    hits: Vec<(DocId, f32)>,
+    boost: f32,
+}
+
+impl ExactKnnWeight {
+    // the hits of `leaf_reader`, by their doc id in the leaf
+    fn leaf_hits<C: Codec>(&self, leaf_reader: &LeafReaderContext<'_, C>) -> &[(DocId, f32)] {
+        let start = leaf_reader.doc_base;
+        let end = start + leaf_reader.reader.max_doc();
+        // the position of the first hit not before `doc`, the doc ids are distinct
+        let position = |doc: DocId| match self.hits.binary_search_by_key(&doc, |h| h.0) {
+            Ok(i) | Err(i) => i,
+        };
+        &self.hits[position(start)..position(end)]
+    }
+}
+
+impl<C: Codec> Weight<C> for ExactKnnWeight {
+    fn create_scorer(
+        &self,
+        leaf_reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn Scorer>>> {
+        let hits = self.leaf_hits(leaf_reader);
+        if hits.is_empty() {
+            return Ok(None);
+        }
+        let hits = hits
+            .iter()
+            .map(|&(doc, score)| (doc - leaf_reader.doc_base, score))
+            .collect();
+        Ok(Some(Box::new(KnnHitsScorer {
+            hits,
+            upto: 0,
+            doc: -1,
+            boost: self.boost,
+        })))
+    }
+
+    fn query_type(&self) -> &'static str {
+        EXACT_KNN_QUERY
+    }
+
+    fn normalize(&mut self, _norm: f32, boost: f32) {
+        // the vector similarities are already normalized
+        self.boost = boost;
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.boost * self.boost
+    }
+
+    fn needs_scores(&self) -> bool {
+        true
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        let doc = reader.doc_base + doc;
+        Ok(match self.leaf_hits(reader).iter().find(|h| h.0 == doc) {
+            Some(&(_, score)) => Explanation::new(
+                true,
+                score * self.boost,
+                format!(
+                    "within the {} nearest vectors of field {}",
+                    self.k, self.field
+                ),
+                vec![],
+            ),
+            None => Explanation::new(
+                false,
+                0f32,
+                format!(
+                    "not within the {} nearest vectors of field {}",
+                    self.k, self.field
+                ),
+                vec![],
+            ),
+        })
+    }
+}
+
+impl fmt::Display for ExactKnnWeight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "ExactKnnWeight(field: {}, k: {}, boost: {})",
+            &self.field, self.k, self.boost
+        )
+    }
+}
+
+// iterates the nearest docs of a leaf, sorted by doc id
+struct KnnHitsScorer {
+    hits: Vec<(DocId, f32)>,
+    // the position of the next hit
+    upto: usize,
+    doc: DocId,
+    boost: f32,
+}
+
+impl Scorer for KnnHitsScorer {
+    fn score(&mut self) -> Result<f32> {
+        debug_assert!(self.upto > 0 && self.doc != NO_MORE_DOCS);
+        Ok(self.hits[self.upto - 1].1 * self.boost)
+    }
+}
+
+impl DocIterator for KnnHitsScorer {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.doc = if self.upto < self.hits.len() {
+            self.upto += 1;
+            self.hits[self.upto - 1].0
+        } else {
+            NO_MORE_DOCS
+        };
+        Ok(self.doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        while self.doc < target {
+            self.next()?;
+        }
+        Ok(self.doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.hits.len()
+    }
+}
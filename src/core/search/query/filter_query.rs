@@ -17,7 +17,7 @@ use core::search::explanation::Explanation;
 use core::search::query::{Query, TermQuery, Weight};
 use core::search::scorer::{FeatureResult, Scorer};
 use core::search::searcher::SearchPlanBuilder;
-use core::search::{DocIterator, NO_MORE_DOCS};
+use core::search::{DocIterator, MatchesIterator, NO_MORE_DOCS};
 use core::util::DocId;
 use core::util::IndexedContext;
 use error::Result;
@@ -133,6 +133,20 @@ impl<C: Codec> Weight<C> for FilterWeight<C> {
     fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
         self.weight.explain(reader, doc)
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        // the filters may reject the doc the wrapped weight matches
+        if let Some(mut scorer) = self.create_scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                return self.weight.matches(reader, doc);
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl<C: Codec> fmt::Display for FilterWeight<C> {
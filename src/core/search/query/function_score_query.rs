@@ -0,0 +1,295 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::fmt;
+
+use core::codec::doc_values::NumericDocValues;
+use core::codec::Codec;
+use core::doc::DocValuesType;
+use core::index::reader::{LeafReader, LeafReaderContext};
+use core::search::explanation::Explanation;
+use core::search::query::{Query, TermQuery, Weight};
+use core::search::scorer::Scorer;
+use core::search::searcher::SearchPlanBuilder;
+use core::search::DocIterator;
+use core::util::{BitsMut, DocId};
+use error::Result;
+
+const FUNCTION_SCORE_QUERY: &str = "function_score";
+
+/// A query matching the docs of `query`, with their score multiplied by the value of
+/// `boost_field`.
+///
+/// This is how docs are boosted at index time, regardless of the query: the boost of each
+/// doc is indexed as a `FloatDocValuesField`. Docs without a value keep their score, as if
+/// their boost were 1.
+pub struct FunctionScoreQuery<C: Codec> {
+    query: Box<dyn Query<C>>,
+    boost_field: String,
+}
+
+impl<C: Codec> FunctionScoreQuery<C> {
+    pub fn build(query: Box<dyn Query<C>>, boost_field: String) -> Box<dyn Query<C>> {
+        Box::new(FunctionScoreQuery { query, boost_field })
+    }
+}
+
+impl<C: Codec> Query<C> for FunctionScoreQuery<C> {
+    fn create_weight(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        Ok(Box::new(FunctionScoreWeight {
+            weight: self.query.create_weight(searcher, needs_scores)?,
+            boost_field: self.boost_field.clone(),
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        self.query.extract_terms()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl<C: Codec> fmt::Display for FunctionScoreQuery<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "FunctionScoreQuery(query: {}, boost_field: {})",
+            &self.query, &self.boost_field
+        )
+    }
+}
+
+struct FunctionScoreWeight<C: Codec> {
+    weight: Box<dyn Weight<C>>,
+    boost_field: String,
+}
+
+impl<C: Codec> Weight<C> for FunctionScoreWeight<C> {
+    fn create_scorer(
+        &self,
+        leaf_reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<Box<dyn Scorer>>> {
+        if let Some(scorer) = self.weight.create_scorer(leaf_reader)? {
+            Ok(Some(Box::new(FunctionScoreScorer {
+                scorer,
+                boosts: DocBoosts::new(leaf_reader, &self.boost_field)?,
+            })))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn query_type(&self) -> &'static str {
+        FUNCTION_SCORE_QUERY
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.weight.normalize(norm, boost)
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.weight.value_for_normalization()
+    }
+
+    fn needs_scores(&self) -> bool {
+        self.weight.needs_scores()
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        let explanation = self.weight.explain(reader, doc)?;
+        if !explanation.is_match() {
+            return Ok(explanation);
+        }
+        let boost = match DocBoosts::new(reader, &self.boost_field)? {
+            Some(mut boosts) => boosts.boost(doc)?,
+            None => 1f32,
+        };
+        Ok(Explanation::new(
+            true,
+            explanation.value() * boost,
+            "product of:".to_string(),
+            vec![
+                explanation,
+                Explanation::new(
+                    true,
+                    boost,
+                    format!("boost of field {}", self.boost_field),
+                    vec![],
+                ),
+            ],
+        ))
+    }
+}
+
+impl<C: Codec> fmt::Display for FunctionScoreWeight<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "FunctionScoreWeight(weight: {}, boost_field: {})",
+            &self.weight, &self.boost_field
+        )
+    }
+}
+
+// the float values of the boost field in a leaf
+struct DocBoosts {
+    values: Box<dyn NumericDocValues>,
+    docs_with_field: Box<dyn BitsMut>,
+}
+
+impl DocBoosts {
+    fn new<C: Codec>(reader: &LeafReaderContext<'_, C>, field: &str) -> Result<Option<Self>> {
+        match reader.reader.field_info(field) {
+            Some(info) if info.doc_values_type == DocValuesType::Numeric => Ok(Some(DocBoosts {
+                values: reader.reader.get_numeric_doc_values(field)?,
+                docs_with_field: reader.reader.get_docs_with_field(field)?,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    fn boost(&mut self, doc: DocId) -> Result<f32> {
+        if self.docs_with_field.get(doc as usize)? {
+            // a `FloatDocValuesField` keeps the bits of the float
+            Ok(f32::from_bits(self.values.get_mut(doc)? as u32))
+        } else {
+            Ok(1f32)
+        }
+    }
+}
+
+struct FunctionScoreScorer {
+    scorer: Box<dyn Scorer>,
+    boosts: Option<DocBoosts>,
+}
+
+impl Scorer for FunctionScoreScorer {
+    fn score(&mut self) -> Result<f32> {
+        let score = self.scorer.score()?;
+        match self.boosts {
+            Some(ref mut boosts) => Ok(score * boosts.boost(self.scorer.doc_id())?),
+            None => Ok(score),
+        }
+    }
+}
+
+impl DocIterator for FunctionScoreScorer {
+    fn doc_id(&self) -> DocId {
+        self.scorer.doc_id()
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        self.scorer.next()
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.scorer.cost()
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        self.scorer.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.scorer.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.scorer.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        self.scorer.approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        self.scorer.approximate_advance(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::doc::{FloatDocValuesField, IndexOptions, Term};
+    use core::search::collector::TopDocsCollector;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::{new_fs_writer, text_field};
+
+    use std::sync::Arc;
+
+    fn search<IS: IndexSearcher<C>, C: Codec>(
+        searcher: &IS,
+        query: &dyn Query<C>,
+    ) -> Vec<(DocId, f32)> {
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(query, &mut collector).unwrap();
+        collector
+            .top_docs()
+            .score_docs()
+            .iter()
+            .map(|d| (d.doc_id(), d.score()))
+            .collect()
+    }
+
+    #[test]
+    fn test_boost_by_field() {
+        let (_dir, writer) = new_fs_writer();
+        // the same text, so the docs are tied without the boosts
+        for boost in &[None, Some(2f32), Some(0.5f32)] {
+            let mut doc = vec![text_field(
+                "body",
+                "quick brown fox",
+                IndexOptions::DocsAndFreqs,
+            )];
+            if let Some(boost) = *boost {
+                doc.push(Box::new(FloatDocValuesField::new("boost", boost)));
+            }
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let term = || TermQuery::new(Term::new("body".into(), b"fox".to_vec()), 1.0, None);
+
+        let tied = search(&searcher, &term());
+        assert_eq!(tied.len(), 3);
+        assert!(tied.iter().all(|d| (d.1 - tied[0].1).abs() < 1e-6));
+        let score = tied[0].1;
+
+        let query = FunctionScoreQuery::build(Box::new(term()), "boost".into());
+        let boosted = search(&searcher, query.as_ref());
+        let docs: Vec<DocId> = boosted.iter().map(|d| d.0).collect();
+        // the doc without a boost keeps its score
+        assert_eq!(docs, vec![1, 0, 2]);
+        assert!((boosted[0].1 - score * 2.0).abs() < 1e-5);
+        assert!((boosted[1].1 - score).abs() < 1e-5);
+        assert!((boosted[2].1 - score * 0.5).abs() < 1e-5);
+
+        let missing = FunctionScoreQuery::build(Box::new(term()), "no_boost".into());
+        assert_eq!(search(&searcher, missing.as_ref()), tied);
+    }
+}
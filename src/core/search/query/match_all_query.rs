@@ -16,8 +16,8 @@ use core::index::reader::LeafReaderContext;
 use core::search::explanation::Explanation;
 use core::search::query::{Query, TermQuery, Weight};
 use core::search::scorer::{two_phase_next, ConstantScoreScorer, Scorer};
-use core::search::searcher::SearchPlanBuilder;
-use core::search::{DocIterator, NO_MORE_DOCS};
+use core::search::searcher::{ScoreMode, SearchPlanBuilder};
+use core::search::{DocIterator, MatchesIterator, NO_MORE_DOCS};
 use core::util::DocId;
 use error::Result;
 use std::fmt;
@@ -198,7 +198,8 @@ impl<C: Codec> Query<C> for ConstantScoreQuery<C> {
         searcher: &dyn SearchPlanBuilder<C>,
         needs_scores: bool,
     ) -> Result<Box<dyn Weight<C>>> {
-        let weight = searcher.create_weight(self.query.as_ref(), false)?;
+        let needs_sub_scores = Query::<C>::score_mode(self).needs_scores();
+        let weight = searcher.create_weight(self.query.as_ref(), needs_sub_scores)?;
         if needs_scores {
             Ok(Box::new(ConstantScoreWeight::new(weight, self.boost)))
         } else {
@@ -210,6 +211,10 @@ impl<C: Codec> Query<C> for ConstantScoreQuery<C> {
         vec![]
     }
 
+    fn score_mode(&self) -> ScoreMode {
+        ScoreMode::CompleteNoScores
+    }
+
     fn as_any(&self) -> &dyn (::std::any::Any) {
         self
     }
@@ -292,6 +297,14 @@ impl<C: Codec> Weight<C> for ConstantScoreWeight<C> {
             ))
         }
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.sub_weight.matches(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for ConstantScoreWeight<C> {
@@ -58,11 +58,36 @@ mod exists_query;
 
 pub use self::exists_query::*;
 
+mod automaton_query;
+
+pub use self::automaton_query::*;
+
+mod term_automaton_query;
+
+pub use self::term_automaton_query::*;
+
+mod combined_field_query;
+
+pub use self::combined_field_query::*;
+
+mod block_join_query;
+
+pub use self::block_join_query::*;
+
+mod exact_knn_query;
+
+pub use self::exact_knn_query::*;
+
+mod function_score_query;
+
+pub use self::function_score_query::*;
+
 use core::codec::Codec;
 use core::index::reader::LeafReaderContext;
 use core::search::explanation::Explanation;
 use core::search::scorer::{BatchScorer, Scorer};
-use core::search::searcher::SearchPlanBuilder;
+use core::search::searcher::{ScoreMode, SearchPlanBuilder};
+use core::search::{DocIterator, EmptyMatchesIterator, MatchesIterator};
 use core::util::DocId;
 
 use error::Result;
@@ -111,6 +136,13 @@ pub trait Query<C: Codec>: Display {
     /// For highlight use.
     fn extract_terms(&self) -> Vec<TermQuery>;
 
+    /// How this query uses the scores of the docs matching its sub queries, queries with
+    /// the same score for all matching docs return `ScoreMode::CompleteNoScores` and
+    /// create the weights of their sub queries without scores.
+    fn score_mode(&self) -> ScoreMode {
+        ScoreMode::Complete
+    }
+
     fn as_any(&self) -> &dyn Any;
 }
 
@@ -165,6 +197,33 @@ pub trait Weight<C: Codec>: Display {
         None
     }
 
+    /// Returns this weight if it scores the docs of a single term, so that the search
+    /// loop can run over its concrete `TermScorer` rather than a boxed one.
+    ///
+    /// Wrapping weights must not forward this, their scorers may differ from the
+    /// `TermScorer` of the weight they wrap.
+    fn as_term_weight(&self) -> Option<&TermWeight<C>> {
+        None
+    }
+
     /// An explanation of the score computation for the named document.
     fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation>;
+
+    /// Returns the positions the query matches at in the named document, or `None` if it
+    /// doesn't match it.
+    ///
+    /// The default implementation only tells whether the document matches, weights over
+    /// positions must return their actual matches, or `None` if they can't tell them.
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        if let Some(mut scorer) = self.create_scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                return Ok(Some(Box::new(EmptyMatchesIterator)));
+            }
+        }
+        Ok(None)
+    }
 }
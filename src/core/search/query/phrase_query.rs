@@ -14,10 +14,10 @@
 use std::boxed::Box;
 use std::fmt;
 
-use core::codec::Codec;
-use core::codec::PostingIteratorFlags;
+use core::codec::{Codec, CodecTermIterator};
+use core::codec::{PostingIterator, PostingIteratorFlags};
 use core::codec::{TermIterator, Terms};
-use core::doc::Term;
+use core::doc::{IndexOptions, Term};
 use core::index::reader::LeafReaderContext;
 use core::search::explanation::Explanation;
 use core::search::query::{Query, TermQuery, Weight};
@@ -25,7 +25,7 @@ use core::search::scorer::{ExactPhraseScorer, PostingsAndFreq, Scorer, SloppyPhr
 use core::search::searcher::SearchPlanBuilder;
 use core::search::similarity::{SimWeight, Similarity};
 use core::search::statistics::{CollectionStatistics, TermStatistics};
-use core::search::DocIterator;
+use core::search::{DocIterator, MatchesIterator, PositionsMatchesIterator};
 use core::util::{DocId, KeyedContext};
 use error::{ErrorKind, Result};
 
@@ -255,6 +255,30 @@ impl<C: Codec> PhraseWeight<C> {
         }
     }
 
+    /// Returns the terms of the field, or fails if the field was indexed without positions.
+    fn term_iterator(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<CodecTermIterator<C>>> {
+        if let Some(field_terms) = reader.reader.terms(&self.field)? {
+            if !field_terms.has_positions()? {
+                bail!(ErrorKind::IllegalState(format!(
+                    "field {} was indexed with {:?}, without position data; cannot run \
+                     PhraseQuery (phrase={:?})",
+                    self.field,
+                    reader
+                        .reader
+                        .field_info(&self.field)
+                        .map_or(IndexOptions::Null, |f| f.index_options),
+                    self.terms
+                )));
+            }
+            Ok(Some(field_terms.iterator()?))
+        } else {
+            Ok(None)
+        }
+    }
+
     fn term_positions_cost(&self, term_iter: &mut impl TermIterator) -> Result<f32> {
         let doc_freq = term_iter.doc_freq()?;
         debug_assert!(doc_freq > 0);
@@ -275,18 +299,9 @@ impl<C: Codec> Weight<C> for PhraseWeight<C> {
         debug_assert!(!self.terms.len() >= 2);
 
         let mut postings_freqs = Vec::with_capacity(self.terms.len());
-        let mut term_iter = if let Some(field_terms) = reader.reader.terms(&self.field)? {
-            debug_assert!(
-                field_terms.has_positions()?,
-                format!(
-                    "field {} was indexed without position data; cannot run PhraseQuery \
-                     (phrase={:?})",
-                    self.field, self.terms
-                )
-            );
-            field_terms.iterator()?
-        } else {
-            return Ok(None);
+        let mut term_iter = match self.term_iterator(reader)? {
+            Some(term_iter) => term_iter,
+            None => return Ok(None),
         };
 
         let mut total_match_cost = 0f32;
@@ -346,20 +361,10 @@ impl<C: Codec> Weight<C> for PhraseWeight<C> {
 
         let mut matched = true;
         let mut postings_freqs = Vec::with_capacity(self.terms.len());
-        let mut term_iter = if let Some(field_terms) = reader.reader.terms(&self.field)? {
-            debug_assert!(
-                field_terms.has_positions()?,
-                format!(
-                    "field {} was indexed without position data; cannot run PhraseQuery \
-                     (phrase={:?})",
-                    self.field, self.terms
-                )
-            );
-            Some(field_terms.iterator()?)
-        } else {
+        let mut term_iter = self.term_iterator(reader)?;
+        if term_iter.is_none() {
             matched = false;
-            None
-        };
+        }
 
         let mut total_match_cost = 0f32;
         for i in 0..self.terms.len() {
@@ -431,6 +436,84 @@ impl<C: Codec> Weight<C> for PhraseWeight<C> {
             vec![],
         ))
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        let mut term_iter = match self.term_iterator(reader)? {
+            Some(term_iter) => term_iter,
+            None => return Ok(None),
+        };
+        let mut term_positions = Vec::with_capacity(self.terms.len());
+        for term in &self.terms {
+            if !term_iter.seek_exact(term.bytes.as_ref())? {
+                return Ok(None);
+            }
+            let mut postings = term_iter.postings_with_flags(PostingIteratorFlags::OFFSETS)?;
+            if postings.advance(doc)? != doc {
+                return Ok(None);
+            }
+            let freq = postings.freq()?;
+            let mut positions = Vec::with_capacity(freq as usize);
+            for _ in 0..freq {
+                let position = postings.next_position()?;
+                positions.push((position, postings.start_offset()?, postings.end_offset()?));
+            }
+            term_positions.push(positions);
+        }
+
+        let matches = phrase_matches(&term_positions, &self.positions, self.slop);
+        if matches.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(Box::new(PositionsMatchesIterator::new(matches))))
+        }
+    }
+}
+
+/// Returns the matches of a phrase in a doc, from the `(position, start_offset, end_offset)`
+/// of each term of the phrase in the doc, `offsets` being their positions in the phrase.
+///
+/// Like `SloppyPhraseScorer`, a match takes one position per term, and its positions move
+/// away from the phrase by at most `slop`; the least position is moved forward each time.
+fn phrase_matches(
+    term_positions: &[Vec<(i32, i32, i32)>],
+    offsets: &[i32],
+    slop: i32,
+) -> Vec<(i32, i32, i32, i32)> {
+    let mut matches = vec![];
+    let mut upto = vec![0usize; term_positions.len()];
+    let phrase_position = |i: usize, upto: &[usize]| term_positions[i][upto[i]].0 - offsets[i];
+    loop {
+        let mut least = 0;
+        let mut end = i32::min_value();
+        for i in 0..term_positions.len() {
+            let position = phrase_position(i, &upto);
+            if position < phrase_position(least, &upto) {
+                least = i;
+            }
+            end = end.max(position);
+        }
+
+        if end - phrase_position(least, &upto) <= slop {
+            let mut picked: Vec<(i32, i32, i32)> = (0..term_positions.len())
+                .map(|i| term_positions[i][upto[i]])
+                .collect();
+            picked.sort();
+            // repeated terms of the phrase can't match at the same position
+            if picked.windows(2).all(|w| w[0].0 != w[1].0) {
+                let (first, last) = (picked[0], picked[picked.len() - 1]);
+                matches.push((first.0, last.0, first.1, last.2));
+            }
+        }
+
+        upto[least] += 1;
+        if upto[least] == term_positions[least].len() {
+            return matches;
+        }
+    }
 }
 
 impl<C: Codec> fmt::Display for PhraseWeight<C> {
@@ -445,6 +528,8 @@ impl<C: Codec> fmt::Display for PhraseWeight<C> {
 
 #[cfg(test)]
 mod tests {
+    extern crate tempfile;
+
     use super::*;
 
     use core::analysis::WhitespaceTokenizer;
@@ -453,6 +538,8 @@ mod tests {
     use core::search::collector::TopDocsCollector;
     use core::search::{DefaultIndexSearcher, IndexSearcher};
     use core::store::directory::FSDirectory;
+    use core::test_util::new_fs_writer;
+    use error::Error;
 
     use std::fs;
     use std::io;
@@ -625,4 +712,134 @@ mod tests {
             assert_eq!(top_docs.total_hits(), 3);
         }
     }
+    #[test]
+    fn test_phrase_query_requires_positions() {
+        let (_dir, writer) = new_fs_writer();
+
+        let field = |name: &str, index_options| -> Box<dyn Fieldable> {
+            let mut field_type = FieldType::default();
+            field_type.index_options = index_options;
+            let tokens = WhitespaceTokenizer::new(Box::new(StringReader::new("quick fox".into())));
+            Box::new(Field::new(
+                name.into(),
+                field_type,
+                None,
+                Some(Box::new(tokens)),
+            ))
+        };
+        let doc = vec![
+            field("title", IndexOptions::DocsAndFreqs),
+            field("tags", IndexOptions::Docs),
+        ];
+        writer.add_document(doc).unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let index_searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        for &name in &["title", "tags"] {
+            let query: PhraseQuery = PhraseQuery::new(
+                vec![
+                    Term::new(name.into(), b"quick".to_vec()),
+                    Term::new(name.into(), b"fox".to_vec()),
+                ],
+                vec![0, 1],
+                0,
+                None,
+                None,
+            )
+            .unwrap();
+            let mut collector = TopDocsCollector::new(10);
+            match index_searcher.search(&query, &mut collector) {
+                Err(Error(ErrorKind::IllegalState(msg), _)) => {
+                    assert!(msg.contains(&format!("field {} ", name)), "{}", msg);
+                }
+                Err(e) => panic!("unexpected error: {:?}", e),
+                Ok(_) => panic!("phrase query on a field without positions must fail"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_phrase_query_decodes_positions_of_candidates_only() {
+        use core::codec::postings::BLOCK_SIZE;
+        use core::codec::CodecEnum;
+        use core::index::merge::{SerialMergeScheduler, TieredMergePolicy};
+        use core::index::reader::StandardDirectoryReader;
+        use core::store::directory::{Directory, MockDirectory};
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(IndexWriterConfig::default());
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let writer = IndexWriter::new(Arc::clone(&directory), config).unwrap();
+
+        // every doc holds a block's quarter of "a" positions, only a few also hold "b"
+        let num_docs = BLOCK_SIZE * 4;
+        let candidates = [100, 300, 500];
+        let mut text = vec!["a"; BLOCK_SIZE as usize / 4].join(" ");
+        for i in 0..num_docs {
+            let mut field_type = FieldType::default();
+            field_type.index_options = IndexOptions::DocsAndFreqsAndPositions;
+            if candidates.contains(&i) {
+                text.push_str(" b");
+            }
+            let tokens = WhitespaceTokenizer::new(Box::new(StringReader::new(text.clone())));
+            let doc: Vec<Box<dyn Fieldable>> = vec![Box::new(Field::new(
+                "body".into(),
+                field_type,
+                None,
+                Some(Box::new(tokens)),
+            ))];
+            writer.add_document(doc).unwrap();
+            if candidates.contains(&i) {
+                let len = text.len() - 2;
+                text.truncate(len);
+            }
+        }
+        writer.commit().unwrap();
+
+        type MockReader = StandardDirectoryReader<
+            MockDirectory<FSDirectory>,
+            CodecEnum,
+            SerialMergeScheduler,
+            TieredMergePolicy,
+        >;
+        let mock_dir = Arc::new(MockDirectory::new(directory));
+        let reader = MockReader::open(Arc::clone(&mock_dir)).unwrap();
+        let index_searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let query: PhraseQuery = PhraseQuery::new(
+            vec![
+                Term::new("body".into(), b"a".to_vec()),
+                Term::new("body".into(), b"b".to_vec()),
+            ],
+            vec![0, 1],
+            0,
+            None,
+            None,
+        )
+        .unwrap();
+        let before = mock_dir.bytes_read("pos");
+        let mut collector = TopDocsCollector::new(10);
+        index_searcher.search(&query, &mut collector).unwrap();
+        let mut docs: Vec<DocId> = collector
+            .top_docs()
+            .score_docs()
+            .iter()
+            .map(|d| d.doc_id())
+            .collect();
+        docs.sort();
+        assert_eq!(docs, candidates.to_vec());
+
+        // the positions of "a" fill num_docs / 4 packed blocks, but only the block holding
+        // those of each candidate is read, plus the single vInt block of "b"
+        let read = mock_dir.bytes_read("pos") - before;
+        let pos_file = mock_dir
+            .list_all()
+            .unwrap()
+            .into_iter()
+            .find(|f| f.ends_with(".pos"))
+            .unwrap();
+        let pos_len = mock_dir.file_length(&pos_file).unwrap() as u64;
+        assert!(read > 0);
+        assert!(read * 8 < pos_len, "read {} of {} bytes", read, pos_len);
+    }
 }
@@ -12,14 +12,35 @@
 // limitations under the License.
 
 use error::{ErrorKind::IllegalArgument, Result};
+use std::io::Cursor;
 use std::option::Option::{None, Some};
 use std::result::Result::Ok;
 use std::str::Chars;
+use std::sync::Arc;
 use std::vec::Vec;
 
+use core::analysis::Analyzer;
 use core::codec::Codec;
 use core::doc::Term;
-use core::search::query::{BooleanQuery, BoostQuery, PhraseQuery, Query, TermQuery};
+use core::search::query::{
+    AutomatonQuery, BooleanQuery, BoostQuery, PhraseQuery, Query, TermQuery,
+    DEFAULT_MAX_CLAUSE_COUNT,
+};
+use core::search::ErrorKind::TooManyClauses;
+use core::util::automaton::{Automaton, MAX_FUZZY_EDITS};
+
+// a term of the token graph, with the position it starts at and the number of positions
+// it spans
+type GraphToken = (Vec<u8>, usize, usize);
+
+/// How clauses without a `+` or `|` operator are combined by `QueryStringQueryBuilder`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// The clause is optional, `a b` means `a | b`.
+    Or,
+    /// The clause is required, `a b` means `+a +b`.
+    And,
+}
 
 /// A query that parses a query string and runs it.
 ///
@@ -32,6 +53,11 @@ pub struct QueryStringQueryBuilder {
     min_should_match: i32,
     #[allow(dead_code)]
     boost: f32,
+    default_operator: Operator,
+    phrase_slop: Option<i32>,
+    allow_leading_wildcard: bool,
+    max_clause_count: usize,
+    analyzer: Option<Arc<dyn Analyzer>>,
 }
 
 impl QueryStringQueryBuilder {
@@ -46,9 +72,55 @@ impl QueryStringQueryBuilder {
             fields,
             min_should_match,
             boost,
+            default_operator: Operator::Or,
+            phrase_slop: None,
+            allow_leading_wildcard: false,
+            max_clause_count: DEFAULT_MAX_CLAUSE_COUNT,
+            analyzer: None,
         }
     }
 
+    /// Sets how clauses without an explicit operator are combined, `Operator::Or` by
+    /// default.
+    pub fn set_default_operator(&mut self, operator: Operator) {
+        self.default_operator = operator;
+    }
+
+    /// Sets the slop of the quoted text made of several whitespace separated terms which
+    /// doesn't give its own `~slop`, making it a phrase query. Without a phrase slop such
+    /// text is searched as a single term, e.g. `"quick fox"` as the term `quick fox`.
+    /// Quoted text of a single term is a term query either way.
+    pub fn set_phrase_slop(&mut self, slop: i32) {
+        self.phrase_slop = Some(slop);
+    }
+
+    /// Whether wildcard terms may start with `*` or `?`, which has to check every term of
+    /// the field. Disallowed by default.
+    pub fn set_allow_leading_wildcard(&mut self, allow: bool) {
+        self.allow_leading_wildcard = allow;
+    }
+
+    /// Sets the maximum number of clauses of every boolean query the parser builds,
+    /// `DEFAULT_MAX_CLAUSE_COUNT` by default. Building a query with more clauses fails
+    /// with `TooManyClauses`.
+    pub fn set_max_clause_count(&mut self, max_clause_count: usize) {
+        self.max_clause_count = max_clause_count;
+    }
+
+    /// Sets the analyzer of the query text. Plain and quoted terms are searched as the
+    /// token graph its token stream emits, while wildcard, prefix and fuzzy terms are only
+    /// normalized, e.g. lower cased like the indexed terms. Without it terms are searched
+    /// as given.
+    ///
+    /// Every path through the token graph is searched, so with a synonym filter a term
+    /// matches either itself or any of its synonyms, a multi-term synonym matching as a
+    /// phrase, and `"ny times"~0` with `ny -> new york` also matches "new york times".
+    /// Building a query of more paths than the max clause count fails with
+    /// `TooManyClauses`.
+    pub fn set_analyzer(&mut self, analyzer: Arc<dyn Analyzer>) {
+        self.analyzer = Some(analyzer);
+    }
+
     pub fn build<C: Codec>(&self) -> Result<Box<dyn Query<C>>> {
         match self.parse_query(&mut self.query_string.chars(), None) {
             Ok(Some(q)) => Ok(q),
@@ -64,7 +136,8 @@ impl QueryStringQueryBuilder {
     ) -> Result<Option<Box<dyn Query<C>>>> {
         let mut musts = Vec::new();
         let mut shoulds = Vec::new();
-        let mut is_option = true;
+        let default_is_option = self.default_operator == Operator::Or;
+        let mut is_option = default_is_option;
         while let Some(ch) = chars.next() {
             match ch {
                 '+' => is_option = false,
@@ -87,8 +160,10 @@ impl QueryStringQueryBuilder {
                         term_chars.push(ch);
                     }
 
+                    let mut has_suffix = false;
                     if let Some(ch) = chars.next() {
                         if ch == '^' || ch == '~' {
+                            has_suffix = true;
                             term_chars.push(ch);
                             while let Some(ch) = chars.next() {
                                 if ch == ' ' {
@@ -99,9 +174,15 @@ impl QueryStringQueryBuilder {
                         }
                     }
 
+                    if let Some(slop) = self.phrase_slop {
+                        if !has_suffix && term_chars.iter().any(|c| c.is_whitespace()) {
+                            term_chars.extend(format!("~{}", slop).chars());
+                        }
+                    }
+
                     if !term_chars.is_empty() {
                         let term: String = term_chars.iter().cloned().collect();
-                        let query = self.build_field_query(term);
+                        let query = self.build_field_query(term, true);
                         match query {
                             Ok(q) => {
                                 if is_option {
@@ -115,9 +196,9 @@ impl QueryStringQueryBuilder {
                             }
                         }
                     }
-                    is_option = true;
+                    is_option = default_is_option;
                 }
-                ' ' => is_option = true,
+                ' ' => is_option = default_is_option,
                 ')' => {
                     if end_char.is_none() || end_char.unwrap() != ')' {
                         bail!(IllegalArgument("parenthesis not match!".into()));
@@ -143,7 +224,7 @@ impl QueryStringQueryBuilder {
                     }
                     if !term_chars.is_empty() {
                         let term: String = term_chars.iter().cloned().collect();
-                        let query_res = self.build_field_query(term);
+                        let query_res = self.build_field_query(term, false);
                         match query_res {
                             Ok(q) => {
                                 if is_option {
@@ -157,7 +238,7 @@ impl QueryStringQueryBuilder {
                             }
                         }
                     }
-                    is_option = true;
+                    is_option = default_is_option;
                     if should_return {
                         break;
                     }
@@ -171,31 +252,60 @@ impl QueryStringQueryBuilder {
                 shoulds.remove(0)
             }
         } else {
-            BooleanQuery::build(musts, shoulds, vec![], vec![], self.min_should_match)?
+            self.boolean_query(musts, shoulds, self.min_should_match)?
         };
         Ok(Some(query))
     }
 
+    fn boolean_query<C: Codec>(
+        &self,
+        musts: Vec<Box<dyn Query<C>>>,
+        shoulds: Vec<Box<dyn Query<C>>>,
+        min_should_match: i32,
+    ) -> Result<Box<dyn Query<C>>> {
+        BooleanQuery::build_with_max_clause_count(
+            musts,
+            shoulds,
+            vec![],
+            vec![],
+            min_should_match,
+            self.max_clause_count,
+        )
+    }
+
     fn term_query<C: Codec>(&self, term: String, field: String, boost: f32) -> Box<dyn Query<C>> {
         Box::new(TermQuery::new(Term::new(field, term.into()), boost, None))
     }
 
-    fn build_field_query<C: Codec>(&self, term_boost: String) -> Result<Box<dyn Query<C>>> {
+    fn build_field_query<C: Codec>(
+        &self,
+        term_boost: String,
+        quoted: bool,
+    ) -> Result<Box<dyn Query<C>>> {
+        // `term~edits` is a fuzzy term, `"some terms"~slop` a phrase
         let mut queries = if term_boost.find('~').is_some() {
-            self.field_phrase_query(&term_boost)?
+            if quoted {
+                self.field_phrase_query(&term_boost)?
+            } else {
+                self.field_fuzzy_query(&term_boost)?
+            }
         } else {
-            self.field_term_query(term_boost)?
+            self.field_term_query(term_boost, quoted)?
         };
 
         let res = if queries.len() == 1 {
             queries.remove(0)
         } else {
-            BooleanQuery::build(Vec::new(), queries, vec![], vec![], self.min_should_match)?
+            self.boolean_query(Vec::new(), queries, self.min_should_match)?
         };
         Ok(res)
     }
 
-    fn field_term_query<C: Codec>(&self, query: String) -> Result<Vec<Box<dyn Query<C>>>> {
+    fn field_term_query<C: Codec>(
+        &self,
+        query: String,
+        quoted: bool,
+    ) -> Result<Vec<Box<dyn Query<C>>>> {
         let (term, boost) = if let Some(i) = query.find('^') {
             let (t, b) = query.split_at(i as usize);
             let boost_str: String = b.chars().skip(1).collect();
@@ -209,13 +319,207 @@ impl QueryStringQueryBuilder {
         } else {
             term
         };
+        if !quoted && term.contains(|c: char| c == '*' || c == '?') {
+            return self.field_wildcard_query(&term, boost);
+        }
         let mut queries = Vec::new();
         for fb in &self.fields {
-            queries.push(self.term_query(term.clone(), fb.0.clone(), fb.1 * boost));
+            if self.analyzer.is_none() {
+                queries.push(self.term_query(term.clone(), fb.0.clone(), fb.1 * boost));
+                continue;
+            }
+            let tokens = self.token_graph(&fb.0, &term)?;
+            queries.push(self.graph_query(&fb.0, &tokens, 0, fb.1 * boost)?);
+        }
+        Ok(queries)
+    }
+
+    fn field_wildcard_query<C: Codec>(
+        &self,
+        term: &str,
+        boost: f32,
+    ) -> Result<Vec<Box<dyn Query<C>>>> {
+        if !self.allow_leading_wildcard && term.starts_with(|c: char| c == '*' || c == '?') {
+            bail!(IllegalArgument(format!(
+                "leading wildcard is not allowed: '{}'",
+                term
+            )));
+        }
+        let mut queries = Vec::with_capacity(self.fields.len());
+        for fb in &self.fields {
+            let pattern = self.normalize_wildcard(&fb.0, term)?;
+            let automaton = Automaton::make_wildcard(&pattern);
+            let query = AutomatonQuery::new(fb.0.clone(), automaton)?;
+            queries.push(BoostQuery::build(Box::new(query), fb.1 * boost));
+        }
+        Ok(queries)
+    }
+
+    fn field_fuzzy_query<C: Codec>(&self, query: &str) -> Result<Vec<Box<dyn Query<C>>>> {
+        // `term~edits^boost`
+        let (query, boost) = if let Some(i) = query.find('^') {
+            (&query[..i], query[i + 1..].parse::<f32>()?)
+        } else {
+            (query, 1f32)
+        };
+        let idx = match query.find('~') {
+            Some(idx) => idx,
+            None => bail!(IllegalArgument(format!(
+                "invalid query string '{}' for fuzzy query",
+                query
+            ))),
+        };
+        let (term, edits) = query.split_at(idx);
+        let max_edits = if edits.len() > 1 {
+            edits[1..].parse::<u32>()?
+        } else {
+            MAX_FUZZY_EDITS
+        };
+        let mut queries = Vec::with_capacity(self.fields.len());
+        for fb in &self.fields {
+            let term = self.normalize(&fb.0, term)?;
+            let automaton = Automaton::make_fuzzy(&term, max_edits, 0)?;
+            let query = AutomatonQuery::new(fb.0.clone(), automaton)?;
+            queries.push(BoostQuery::build(Box::new(query), fb.1 * boost));
         }
         Ok(queries)
     }
 
+    fn normalize(&self, field: &str, text: &str) -> Result<Vec<u8>> {
+        match self.analyzer {
+            Some(ref analyzer) => analyzer.normalize(field, text),
+            None => Ok(text.as_bytes().to_vec()),
+        }
+    }
+
+    // normalizes the text between the wildcards, which are kept from the analyzer
+    fn normalize_wildcard(&self, field: &str, pattern: &str) -> Result<Vec<u8>> {
+        let mut normalized = Vec::with_capacity(pattern.len());
+        let mut start = 0;
+        for (i, c) in pattern.char_indices() {
+            if c == '*' || c == '?' {
+                normalized.extend(self.normalize(field, &pattern[start..i])?);
+                normalized.push(c as u8);
+                start = i + 1;
+            }
+        }
+        normalized.extend(self.normalize(field, &pattern[start..])?);
+        Ok(normalized)
+    }
+
+    // the token graph of `text`, the whitespace separated terms without an analyzer
+    fn token_graph(&self, field: &str, text: &str) -> Result<Vec<GraphToken>> {
+        let analyzer = match self.analyzer {
+            Some(ref analyzer) => analyzer,
+            None => {
+                return Ok(text
+                    .split_whitespace()
+                    .enumerate()
+                    .map(|(i, term)| (term.as_bytes().to_vec(), i, 1))
+                    .collect());
+            }
+        };
+        let reader = Box::new(Cursor::new(text.as_bytes().to_vec()));
+        let mut stream = analyzer.token_stream(field, reader)?;
+        stream.reset()?;
+        let mut tokens: Vec<GraphToken> = vec![];
+        let mut position = 0;
+        while stream.next_token()? {
+            let token = stream.token();
+            position += token.position;
+            tokens.push((token.term.clone(), position, token.position_length));
+        }
+        stream.end()?;
+        if tokens.is_empty() {
+            bail!(IllegalArgument(format!(
+                "'{}' is analyzed to no terms",
+                text
+            )));
+        }
+        // the increment of the first token is relative to nothing
+        let first = tokens[0].1;
+        for token in &mut tokens {
+            token.1 -= first;
+        }
+        Ok(tokens)
+    }
+
+    // every sequence of terms from the start to the end of the token graph, counted before
+    // they are enumerated not to build more than `max_clause_count` of them
+    fn graph_paths<'a>(&self, tokens: &'a [GraphToken]) -> Result<Vec<Vec<&'a [u8]>>> {
+        let end = tokens.iter().map(|t| t.1 + t.2).max().unwrap_or(0);
+        let mut counts = vec![0usize; end + 1];
+        counts[end] = 1;
+        for position in (0..end).rev() {
+            let mut starting = tokens.iter().filter(|t| t.1 == position).peekable();
+            let count = if starting.peek().is_none() {
+                // a hole left by a removed term
+                counts[position + 1]
+            } else {
+                starting.fold(0usize, |count, t| count.saturating_add(counts[t.1 + t.2]))
+            };
+            counts[position] = count;
+        }
+        if counts[0] > self.max_clause_count {
+            bail!(TooManyClauses(counts[0], self.max_clause_count));
+        }
+        let mut paths = Vec::with_capacity(counts[0]);
+        Self::collect_paths(tokens, 0, end, &mut vec![], &mut paths);
+        Ok(paths)
+    }
+
+    fn collect_paths<'a>(
+        tokens: &'a [GraphToken],
+        position: usize,
+        end: usize,
+        path: &mut Vec<&'a [u8]>,
+        paths: &mut Vec<Vec<&'a [u8]>>,
+    ) {
+        if position == end {
+            paths.push(path.clone());
+            return;
+        }
+        let mut starting = tokens.iter().filter(|t| t.1 == position).peekable();
+        if starting.peek().is_none() {
+            Self::collect_paths(tokens, position + 1, end, path, paths);
+        }
+        for token in starting {
+            path.push(&token.0);
+            Self::collect_paths(tokens, token.1 + token.2, end, path, paths);
+            path.pop();
+        }
+    }
+
+    // a query matching any path through the token graph, single terms as term queries and
+    // the longer paths as phrases with `slop`
+    fn graph_query<C: Codec>(
+        &self,
+        field: &str,
+        tokens: &[GraphToken],
+        slop: i32,
+        boost: f32,
+    ) -> Result<Box<dyn Query<C>>> {
+        let mut queries = vec![];
+        for path in self.graph_paths(tokens)? {
+            let mut terms: Vec<Term> = path
+                .iter()
+                .map(|term| Term::new(field.to_string(), term.to_vec()))
+                .collect();
+            let query: Box<dyn Query<C>> = if terms.len() == 1 {
+                Box::new(TermQuery::new(terms.remove(0), boost, None))
+            } else {
+                let phrase = PhraseQuery::build(terms, slop, None, None)?;
+                BoostQuery::build(Box::new(phrase), boost)
+            };
+            queries.push(query);
+        }
+        if queries.len() == 1 {
+            Ok(queries.remove(0))
+        } else {
+            self.boolean_query(vec![], queries, 1)
+        }
+    }
+
     fn field_phrase_query<C: Codec>(&self, query: &str) -> Result<Vec<Box<dyn Query<C>>>> {
         if let Some(idx) = query.find('~') {
             let (t, s) = query.split_at(idx);
@@ -229,14 +533,8 @@ impl QueryStringQueryBuilder {
             }
             let mut queries = Vec::with_capacity(self.fields.len());
             for fb in &self.fields {
-                let terms: Vec<Term> = term_strs
-                    .iter()
-                    .map(|term| Term::new(fb.0.clone(), term.as_bytes().to_vec()))
-                    .collect();
-                queries.push(BoostQuery::build(
-                    Box::new(PhraseQuery::build(terms, slop, None, None)?),
-                    fb.1,
-                ))
+                let tokens = self.token_graph(&fb.0, t)?;
+                queries.push(self.graph_query(&fb.0, &tokens, slop, fb.1)?);
             }
 
             Ok(queries)
@@ -251,8 +549,22 @@ impl QueryStringQueryBuilder {
 
 #[cfg(test)]
 mod tests {
+
     use super::*;
+    use core::analysis::{
+        ASCIIFoldingFilterFactory, CustomAnalyzer, LowerCaseFilterFactory,
+        SynonymGraphFilterFactory, SynonymMap, WhitespaceTokenizerFactory,
+    };
     use core::codec::tests::TestCodec;
+    use core::codec::CodecEnum;
+    use core::doc::IndexOptions;
+    use core::search::collector::TopDocsCollector;
+    use core::search::{self, DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::{new_fs_writer, text_doc};
+    use core::util::DocId;
+    use error::{Error, ErrorKind};
+
+    use std::sync::Arc;
 
     #[test]
     fn test_query_string_query() {
@@ -410,4 +722,322 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_synonym_expansion() {
+        let mut synonyms = SynonymMap::new();
+        synonyms.add("ny", "new york");
+        synonyms.add("car", "automobile");
+        let analyzer: Arc<dyn Analyzer> = Arc::new(CustomAnalyzer::new(
+            Box::new(WhitespaceTokenizerFactory),
+            vec![Box::new(SynonymGraphFilterFactory::new(Arc::new(synonyms)))],
+        ));
+
+        let mut builder =
+            QueryStringQueryBuilder::new("car".into(), vec![("title".into(), 1.0)], 0, 1.0);
+        builder.set_analyzer(Arc::clone(&analyzer));
+        let q: Box<dyn Query<TestCodec>> = builder.build().unwrap();
+        assert_eq!(
+            q.to_string(),
+            String::from(
+                "BooleanQuery(must: [], should: [TermQuery(field: title, term: car, boost: 1), \
+                 TermQuery(field: title, term: automobile, boost: 1)], filters: [], must_not: [], \
+                 match: 1)",
+            )
+        );
+
+        let (_dir, writer) = new_fs_writer();
+        writer
+            .add_document(text_doc(
+                "title",
+                "new york times",
+                IndexOptions::DocsAndFreqsAndPositions,
+            ))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "title",
+                "ny times",
+                IndexOptions::DocsAndFreqsAndPositions,
+            ))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "title",
+                "times of new york",
+                IndexOptions::DocsAndFreqsAndPositions,
+            ))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "title",
+                "new times",
+                IndexOptions::DocsAndFreqsAndPositions,
+            ))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "title",
+                "automobile",
+                IndexOptions::DocsAndFreqsAndPositions,
+            ))
+            .unwrap();
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        let hits = |query_string: &str| {
+            let mut builder = QueryStringQueryBuilder::new(
+                query_string.into(),
+                vec![("title".into(), 1.0)],
+                0,
+                1.0,
+            );
+            builder.set_analyzer(Arc::clone(&analyzer));
+            let query: Box<dyn Query<CodecEnum>> = builder.build().unwrap();
+            let mut collector = TopDocsCollector::new(10);
+            searcher.search(query.as_ref(), &mut collector).unwrap();
+            let mut docs: Vec<DocId> = collector
+                .top_docs()
+                .score_docs()
+                .iter()
+                .map(|d| d.doc_id())
+                .collect();
+            docs.sort();
+            docs
+        };
+
+        // the multi-term synonym must match as a whole phrase
+        assert_eq!(hits("\"ny times\"~0"), vec![0, 1]);
+        assert_eq!(hits("+ny"), vec![0, 1, 2]);
+        assert_eq!(hits("car"), vec![4]);
+    }
+
+    #[test]
+    fn test_synonym_paths_are_capped() {
+        let mut synonyms = SynonymMap::new();
+        for synonym in &["b", "c", "d e"] {
+            synonyms.add("a", synonym);
+        }
+        let analyzer: Arc<dyn Analyzer> = Arc::new(CustomAnalyzer::new(
+            Box::new(WhitespaceTokenizerFactory),
+            vec![Box::new(SynonymGraphFilterFactory::new(Arc::new(synonyms)))],
+        ));
+        // every `a` is read four ways, the phrase 4^4 ways
+        let mut builder = QueryStringQueryBuilder::new(
+            "\"a a a a\"~0".into(),
+            vec![("title".into(), 1.0)],
+            0,
+            1.0,
+        );
+        builder.set_analyzer(analyzer);
+        let q: Box<dyn Query<TestCodec>> = builder.build().unwrap();
+        assert_eq!(q.to_string().matches("PhraseQuery").count(), 256);
+
+        builder.set_max_clause_count(100);
+        let q: Result<Box<dyn Query<TestCodec>>> = builder.build();
+        match q {
+            Err(Error(ErrorKind::Search(search::ErrorKind::TooManyClauses(count, max)), _)) => {
+                assert_eq!(count, 256);
+                assert_eq!(max, 100);
+            }
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("expected too many clauses"),
+        }
+    }
+
+    #[test]
+    fn test_default_operator() {
+        let parse = |query_string: &str, operator: Operator| {
+            let mut builder = QueryStringQueryBuilder::new(
+                query_string.into(),
+                vec![("title".into(), 1.0)],
+                0,
+                1.0,
+            );
+            builder.set_default_operator(operator);
+            let q: Box<dyn Query<TestCodec>> = builder.build().unwrap();
+            q.to_string()
+        };
+
+        assert_eq!(
+            parse("a b", Operator::Or),
+            String::from(
+                "BooleanQuery(must: [], should: [TermQuery(field: title, term: a, boost: 1), \
+                 TermQuery(field: title, term: b, boost: 1)], filters: [], must_not: [], match: 1)",
+            )
+        );
+        assert_eq!(
+            parse("a b", Operator::And),
+            String::from(
+                "BooleanQuery(must: [TermQuery(field: title, term: a, boost: 1), TermQuery(field: \
+                 title, term: b, boost: 1)], should: [], filters: [], must_not: [], match: 0)",
+            )
+        );
+        // an explicit `|` still makes the clause optional
+        assert_eq!(
+            parse("a |b", Operator::And),
+            String::from(
+                "BooleanQuery(must: [TermQuery(field: title, term: a, boost: 1)], should: \
+                 [TermQuery(field: title, term: b, boost: 1)], filters: [], must_not: [], match: \
+                 0)",
+            )
+        );
+    }
+
+    #[test]
+    fn test_max_clause_count() {
+        let mut builder =
+            QueryStringQueryBuilder::new("a b c".into(), vec![("title".into(), 1.0)], 0, 1.0);
+        let q: Result<Box<dyn Query<TestCodec>>> = builder.build();
+        assert!(q.is_ok());
+
+        builder.set_max_clause_count(2);
+        let q: Result<Box<dyn Query<TestCodec>>> = builder.build();
+        match q {
+            Err(Error(ErrorKind::Search(search::ErrorKind::TooManyClauses(count, max)), _)) => {
+                assert_eq!(count, 3);
+                assert_eq!(max, 2);
+            }
+            Err(e) => panic!("unexpected error: {:?}", e),
+            Ok(_) => panic!("expected too many clauses"),
+        }
+    }
+
+    #[test]
+    fn test_phrase_slop() {
+        let mut builder = QueryStringQueryBuilder::new(
+            "\"quick fox\" \"lazy dog\"~3".into(),
+            vec![("title".into(), 1.0)],
+            0,
+            1.0,
+        );
+        let q: Box<dyn Query<TestCodec>> = builder.build().unwrap();
+        assert!(q.to_string().contains("term: quick fox"));
+
+        builder.set_phrase_slop(2);
+        let q: Box<dyn Query<TestCodec>> = builder.build().unwrap();
+        let q = q.to_string();
+        assert!(!q.contains("TermQuery"));
+        assert!(q.contains("positions: [0, 1], slop: 2)"));
+        assert!(q.contains("positions: [0, 1], slop: 3)"));
+    }
+
+    #[test]
+    fn test_wildcard() {
+        let mut builder =
+            QueryStringQueryBuilder::new("qu?ck *ing".into(), vec![("title".into(), 1.0)], 0, 1.0);
+        let res: Result<Box<dyn Query<TestCodec>>> = builder.build();
+        assert!(res.is_err());
+
+        builder.set_allow_leading_wildcard(true);
+        let q: Box<dyn Query<TestCodec>> = builder.build().unwrap();
+        let q = q.to_string();
+        assert!(q.starts_with("BooleanQuery(must: [], should: [AutomatonQuery(field=title"));
+        assert_eq!(q.matches("AutomatonQuery").count(), 2);
+
+        // quoted terms are never wildcards
+        let builder =
+            QueryStringQueryBuilder::new("\"*ing\"".into(), vec![("title".into(), 1.0)], 0, 1.0);
+        let q: Box<dyn Query<TestCodec>> = builder.build().unwrap();
+        assert_eq!(
+            q.to_string(),
+            String::from("TermQuery(field: title, term: *ing, boost: 1)")
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_boost() {
+        let fuzzy = |query_string: &str| {
+            let builder = QueryStringQueryBuilder::new(
+                query_string.into(),
+                vec![("title".into(), 3.0)],
+                0,
+                1.0,
+            );
+            let q: Box<dyn Query<TestCodec>> = builder.build().unwrap();
+            q.to_string()
+        };
+        // the boost of the term applies on top of the one of the field
+        let q = fuzzy("quick~1^2");
+        assert!(q.starts_with("BoostQuery(query: AutomatonQuery(field=title"));
+        assert!(q.ends_with("boost: 6)"));
+        assert!(fuzzy("quick~1").ends_with("boost: 3)"));
+    }
+
+    #[test]
+    fn test_normalize_multi_term_queries() {
+        let (_dir, writer) = new_fs_writer();
+        writer
+            .add_document(text_doc(
+                "title",
+                "cafe latte",
+                IndexOptions::DocsAndFreqsAndPositions,
+            ))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "title",
+                "cafeteria",
+                IndexOptions::DocsAndFreqsAndPositions,
+            ))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "title",
+                "cafes",
+                IndexOptions::DocsAndFreqsAndPositions,
+            ))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "title",
+                "tea",
+                IndexOptions::DocsAndFreqsAndPositions,
+            ))
+            .unwrap();
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        let analyzer: Arc<dyn Analyzer> = Arc::new(CustomAnalyzer::new(
+            Box::new(WhitespaceTokenizerFactory),
+            vec![
+                Box::new(LowerCaseFilterFactory),
+                Box::new(ASCIIFoldingFilterFactory),
+            ],
+        ));
+        let hits = |query_string: &str, analyzer: Option<&Arc<dyn Analyzer>>| {
+            let mut builder = QueryStringQueryBuilder::new(
+                query_string.into(),
+                vec![("title".into(), 1.0)],
+                0,
+                1.0,
+            );
+            if let Some(analyzer) = analyzer {
+                builder.set_analyzer(Arc::clone(analyzer));
+            }
+            let query: Box<dyn Query<CodecEnum>> = builder.build().unwrap();
+            let mut collector = TopDocsCollector::new(10);
+            searcher.search(query.as_ref(), &mut collector).unwrap();
+            let mut docs: Vec<DocId> = collector
+                .top_docs()
+                .score_docs()
+                .iter()
+                .map(|d| d.doc_id())
+                .collect();
+            docs.sort();
+            docs
+        };
+
+        assert!(hits("CAFÉ*", None).is_empty());
+        assert_eq!(hits("CAFÉ*", Some(&analyzer)), vec![0, 1, 2]);
+        assert_eq!(hits("CAF?S", Some(&analyzer)), vec![2]);
+        assert!(hits("CAFÉ~1", None).is_empty());
+        assert_eq!(hits("CAFÉ~1", Some(&analyzer)), vec![0, 2]);
+        assert_eq!(hits("Tae~1", Some(&analyzer)), vec![3]);
+        assert_eq!(hits("CAFÉ~1^2", Some(&analyzer)), vec![0, 2]);
+        // the quoted term is a phrase, not a fuzzy term
+        assert_eq!(hits("\"cafe latte\"~0", Some(&analyzer)), vec![0]);
+    }
 }
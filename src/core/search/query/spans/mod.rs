@@ -16,6 +16,10 @@ mod span;
 
 pub use self::span::*;
 
+mod payload_score;
+
+pub use self::payload_score::*;
+
 mod span_boost;
 
 pub use self::span_boost::*;
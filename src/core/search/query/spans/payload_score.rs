@@ -0,0 +1,668 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::{Codec, CodecPostingIterator, PostingIterator};
+use core::doc::Term;
+use core::index::reader::LeafReaderContext;
+use core::search::explanation::Explanation;
+use core::search::query::spans::{
+    PostingsFlag, SpanCollector, SpanQuery, SpanQueryEnum, SpanWeight, SpanWeightEnum, Spans,
+    SpansEnum, NO_MORE_POSITIONS,
+};
+use core::search::searcher::SearchPlanBuilder;
+use core::search::{
+    query::Query, query::TermQuery, query::Weight, scorer::Scorer, similarity::SimScorer,
+    DocIterator, MatchesIterator, Payload,
+};
+use core::util::DocId;
+
+use error::Result;
+
+use std::fmt;
+use std::sync::Arc;
+
+const PAYLOAD_SCORE_QUERY: &str = "payload_score";
+
+/// Encodes a float as a 4 bytes big endian payload, see `decode_float_payload`
+pub fn encode_float_payload(value: f32) -> Payload {
+    value.to_bits().to_be_bytes().to_vec()
+}
+
+/// Decodes a payload written by `encode_float_payload`, payloads of any other
+/// length score as `1.0`
+pub fn decode_float_payload(payload: &[u8]) -> f32 {
+    if payload.len() != 4 {
+        return 1.0;
+    }
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(payload);
+    f32::from_bits(u32::from_be_bytes(bytes))
+}
+
+/// Reduces the payloads seen in the matching spans of a doc into one factor
+/// which is multiplied with the span score.
+pub trait PayloadFunction: fmt::Display + Send + Sync {
+    /// Combines the payload score accumulated so far with the score of one more payload.
+    ///
+    /// `num_payloads_seen` doesn't include the current payload.
+    #[allow(clippy::too_many_arguments)]
+    fn current_score(
+        &self,
+        doc: DocId,
+        field: &str,
+        start: i32,
+        end: i32,
+        num_payloads_seen: i32,
+        current_score: f32,
+        current_payload_score: f32,
+    ) -> f32;
+
+    /// Calculates the final payload factor of a doc.
+    fn doc_score(&self, doc: DocId, field: &str, num_payloads_seen: i32, payload_score: f32)
+        -> f32;
+}
+
+/// Averages the payloads, docs without payloads get a factor of `1.0`
+pub struct AveragePayloadFunction;
+
+impl PayloadFunction for AveragePayloadFunction {
+    fn current_score(
+        &self,
+        _doc: DocId,
+        _field: &str,
+        _start: i32,
+        _end: i32,
+        _num_payloads_seen: i32,
+        current_score: f32,
+        current_payload_score: f32,
+    ) -> f32 {
+        current_score + current_payload_score
+    }
+
+    fn doc_score(
+        &self,
+        _doc: DocId,
+        _field: &str,
+        num_payloads_seen: i32,
+        payload_score: f32,
+    ) -> f32 {
+        if num_payloads_seen > 0 {
+            payload_score / num_payloads_seen as f32
+        } else {
+            1.0
+        }
+    }
+}
+
+impl fmt::Display for AveragePayloadFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AveragePayloadFunction")
+    }
+}
+
+/// Takes the largest payload, docs without payloads get a factor of `1.0`
+pub struct MaxPayloadFunction;
+
+impl PayloadFunction for MaxPayloadFunction {
+    fn current_score(
+        &self,
+        _doc: DocId,
+        _field: &str,
+        _start: i32,
+        _end: i32,
+        num_payloads_seen: i32,
+        current_score: f32,
+        current_payload_score: f32,
+    ) -> f32 {
+        if num_payloads_seen == 0 {
+            current_payload_score
+        } else {
+            current_score.max(current_payload_score)
+        }
+    }
+
+    fn doc_score(
+        &self,
+        _doc: DocId,
+        _field: &str,
+        num_payloads_seen: i32,
+        payload_score: f32,
+    ) -> f32 {
+        if num_payloads_seen > 0 {
+            payload_score
+        } else {
+            1.0
+        }
+    }
+}
+
+impl fmt::Display for MaxPayloadFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MaxPayloadFunction")
+    }
+}
+
+/// Takes the smallest payload, docs without payloads get a factor of `1.0`
+pub struct MinPayloadFunction;
+
+impl PayloadFunction for MinPayloadFunction {
+    fn current_score(
+        &self,
+        _doc: DocId,
+        _field: &str,
+        _start: i32,
+        _end: i32,
+        num_payloads_seen: i32,
+        current_score: f32,
+        current_payload_score: f32,
+    ) -> f32 {
+        if num_payloads_seen == 0 {
+            current_payload_score
+        } else {
+            current_score.min(current_payload_score)
+        }
+    }
+
+    fn doc_score(
+        &self,
+        _doc: DocId,
+        _field: &str,
+        num_payloads_seen: i32,
+        payload_score: f32,
+    ) -> f32 {
+        if num_payloads_seen > 0 {
+            payload_score
+        } else {
+            1.0
+        }
+    }
+}
+
+impl fmt::Display for MinPayloadFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MinPayloadFunction")
+    }
+}
+
+/// A query that wraps a span query and multiplies the span score with a factor
+/// computed from the payloads of the matching spans, e.g. per-term boosts stored at
+/// index time.
+///
+/// Payloads are decoded with `decode_float_payload`.
+pub struct PayloadScoreQuery {
+    wrapped_span: SpanQueryEnum,
+    payload_function: Arc<dyn PayloadFunction>,
+    include_span_score: bool,
+}
+
+impl PayloadScoreQuery {
+    /// if `include_span_score` is false, the score is the payload factor only.
+    pub fn new(
+        wrapped_span: SpanQueryEnum,
+        payload_function: Arc<dyn PayloadFunction>,
+        include_span_score: bool,
+    ) -> Self {
+        PayloadScoreQuery {
+            wrapped_span,
+            payload_function,
+            include_span_score,
+        }
+    }
+
+    pub fn wrapped_span(&self) -> &SpanQueryEnum {
+        &self.wrapped_span
+    }
+}
+
+impl<C: Codec> Query<C> for PayloadScoreQuery {
+    fn create_weight(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        let inner = self.wrapped_span.span_weight(searcher, needs_scores)?;
+        if !needs_scores {
+            return Ok(Box::new(inner));
+        }
+        Ok(Box::new(PayloadScoreWeight {
+            inner,
+            field: SpanQuery::<C>::field(&self.wrapped_span).to_string(),
+            payload_function: Arc::clone(&self.payload_function),
+            include_span_score: self.include_span_score,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        Query::<C>::extract_terms(&self.wrapped_span)
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self
+    }
+}
+
+impl fmt::Display for PayloadScoreQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PayloadScoreQuery(query: {}, function: {}, include_span_score: {})",
+            &self.wrapped_span, &self.payload_function, self.include_span_score
+        )
+    }
+}
+
+pub struct PayloadScoreWeight<C: Codec> {
+    inner: SpanWeightEnum<C>,
+    field: String,
+    payload_function: Arc<dyn PayloadFunction>,
+    include_span_score: bool,
+}
+
+impl<C: Codec> PayloadScoreWeight<C> {
+    fn payload_scorer(
+        &self,
+        ctx: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<PayloadSpanScorer<SpansEnum<CodecPostingIterator<C>>>>> {
+        if let Some(spans) = self.inner.get_spans(ctx, &PostingsFlag::Payloads)? {
+            let doc_scorer = self.inner.sim_scorer(ctx.reader)?;
+            Ok(Some(PayloadSpanScorer::new(
+                spans,
+                doc_scorer,
+                Arc::clone(&self.payload_function),
+                self.field.clone(),
+                self.include_span_score,
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<C: Codec> Weight<C> for PayloadScoreWeight<C> {
+    fn create_scorer(&self, ctx: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        if let Some(scorer) = self.payload_scorer(ctx)? {
+            Ok(Some(Box::new(scorer)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn query_type(&self) -> &'static str {
+        PAYLOAD_SCORE_QUERY
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.inner.normalize(norm, boost)
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.inner.value_for_normalization()
+    }
+
+    fn needs_scores(&self) -> bool {
+        true
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        if let Some(mut scorer) = self.payload_scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                scorer.ensure_freq()?;
+                let factor = scorer.payload_factor();
+                let payload_expl = Explanation::new(
+                    true,
+                    factor,
+                    format!(
+                        "{} over {} payloads",
+                        &self.payload_function, scorer.num_payloads_seen
+                    ),
+                    vec![],
+                );
+                if !self.include_span_score {
+                    return Ok(Explanation::new(
+                        true,
+                        factor,
+                        format!("weight({} in {}), result of:", self, doc),
+                        vec![payload_expl],
+                    ));
+                }
+                let span_expl = self.inner.explain(reader, doc)?;
+                return Ok(Explanation::new(
+                    true,
+                    span_expl.value() * factor,
+                    format!("weight({} in {}), product of:", self, doc),
+                    vec![span_expl, payload_expl],
+                ));
+            }
+        }
+
+        Ok(Explanation::new(
+            false,
+            0.0f32,
+            "no matching term".to_string(),
+            vec![],
+        ))
+    }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.inner.matches_span(reader, doc)
+    }
+}
+
+impl<C: Codec> fmt::Display for PayloadScoreWeight<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "PayloadScoreWeight(weight: {}, function: {})",
+            &self.inner, &self.payload_function
+        )
+    }
+}
+
+/// collects the payloads of the current span
+#[derive(Default)]
+struct PayloadSpanCollector {
+    payloads: Vec<Payload>,
+}
+
+impl SpanCollector for PayloadSpanCollector {
+    fn collect_leaf(
+        &mut self,
+        postings: &impl PostingIterator,
+        _position: i32,
+        _term: &Term,
+    ) -> Result<()> {
+        let payload = postings.payload()?;
+        if !payload.is_empty() {
+            self.payloads.push(payload);
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.payloads.clear();
+    }
+}
+
+/// a `SpanScorer` variant that also reduces the payloads of each match
+pub struct PayloadSpanScorer<S: Spans> {
+    spans: S,
+    doc_scorer: Option<Box<dyn SimScorer>>,
+    payload_function: Arc<dyn PayloadFunction>,
+    field: String,
+    include_span_score: bool,
+    collector: PayloadSpanCollector,
+    /// accumulated sloppy freq (computed in set_freq_current_doc)
+    freq: f32,
+    num_payloads_seen: i32,
+    payload_score: f32,
+    /// last doc we called set_freq_current_doc() for
+    last_scored_doc: DocId,
+}
+
+impl<S: Spans> PayloadSpanScorer<S> {
+    fn new(
+        spans: S,
+        doc_scorer: Option<Box<dyn SimScorer>>,
+        payload_function: Arc<dyn PayloadFunction>,
+        field: String,
+        include_span_score: bool,
+    ) -> Self {
+        PayloadSpanScorer {
+            spans,
+            doc_scorer,
+            payload_function,
+            field,
+            include_span_score,
+            collector: PayloadSpanCollector::default(),
+            freq: 0.0,
+            num_payloads_seen: 0,
+            payload_score: 0.0,
+            last_scored_doc: -1,
+        }
+    }
+
+    fn ensure_freq(&mut self) -> Result<()> {
+        let current_doc = self.doc_id();
+        if self.last_scored_doc != current_doc {
+            self.set_freq_current_doc()?;
+            self.last_scored_doc = current_doc;
+        }
+        Ok(())
+    }
+
+    fn set_freq_current_doc(&mut self) -> Result<()> {
+        self.freq = 0.0;
+        self.num_payloads_seen = 0;
+        self.payload_score = 0.0;
+        self.spans.do_start_current_doc()?;
+
+        let doc = self.spans.doc_id();
+        let mut start_pos = self.spans.next_start_position()?;
+        debug_assert_ne!(start_pos, NO_MORE_POSITIONS);
+        while start_pos != NO_MORE_POSITIONS {
+            if let Some(ref mut doc_scorer) = self.doc_scorer {
+                self.freq += doc_scorer.compute_slop_factor(self.spans.width());
+            }
+            self.spans.do_current_spans()?;
+
+            self.collector.reset();
+            self.spans.collect(&mut self.collector)?;
+            let end_pos = self.spans.end_position();
+            for payload in &self.collector.payloads {
+                self.payload_score = self.payload_function.current_score(
+                    doc,
+                    &self.field,
+                    start_pos,
+                    end_pos,
+                    self.num_payloads_seen,
+                    self.payload_score,
+                    decode_float_payload(payload),
+                );
+                self.num_payloads_seen += 1;
+            }
+            start_pos = self.spans.next_start_position()?;
+        }
+
+        Ok(())
+    }
+
+    fn payload_factor(&self) -> f32 {
+        self.payload_function.doc_score(
+            self.doc_id(),
+            &self.field,
+            self.num_payloads_seen,
+            self.payload_score,
+        )
+    }
+}
+
+impl<S: Spans> Scorer for PayloadSpanScorer<S> {
+    fn score(&mut self) -> Result<f32> {
+        self.ensure_freq()?;
+        let factor = self.payload_factor();
+        if !self.include_span_score {
+            return Ok(factor);
+        }
+        let doc = self.doc_id();
+        let span_score = match self.doc_scorer {
+            Some(ref mut doc_scorer) => doc_scorer.score(doc, self.freq)?,
+            None => 1.0,
+        };
+        Ok(span_score * factor)
+    }
+}
+
+impl<S: Spans> DocIterator for PayloadSpanScorer<S> {
+    fn doc_id(&self) -> i32 {
+        self.spans.doc_id()
+    }
+
+    fn next(&mut self) -> Result<i32> {
+        self.spans.next()
+    }
+
+    fn advance(&mut self, target: i32) -> Result<i32> {
+        self.spans.advance(target)
+    }
+
+    fn cost(&self) -> usize {
+        self.spans.cost()
+    }
+    fn matches(&mut self) -> Result<bool> {
+        self.spans.matches()
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.spans.match_cost()
+    }
+
+    fn support_two_phase(&self) -> bool {
+        self.spans.support_two_phase()
+    }
+
+    fn approximate_next(&mut self) -> Result<i32> {
+        self.spans.approximate_next()
+    }
+
+    fn approximate_advance(&mut self, target: i32) -> Result<i32> {
+        self.spans.approximate_advance(target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use core::analysis::{Token, TokenStream};
+    use core::doc::{Field, FieldType, Fieldable, IndexOptions};
+    use core::search::collector::TopDocsCollector;
+    use core::search::query::spans::SpanTermQuery;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::new_fs_writer;
+
+    /// emits the given terms, each carrying its payload
+    #[derive(Debug)]
+    struct PayloadTokenStream {
+        token: Token,
+        tokens: Vec<(&'static str, Payload)>,
+        index: usize,
+    }
+
+    impl PayloadTokenStream {
+        fn new(tokens: Vec<(&'static str, Payload)>) -> Self {
+            PayloadTokenStream {
+                token: Token::new(),
+                tokens,
+                index: 0,
+            }
+        }
+    }
+
+    impl TokenStream for PayloadTokenStream {
+        fn next_token(&mut self) -> Result<bool> {
+            if self.index >= self.tokens.len() {
+                return Ok(false);
+            }
+            self.clear_token();
+            self.token.term = self.tokens[self.index].0.as_bytes().to_vec();
+            self.token.payload = self.tokens[self.index].1.clone();
+            self.index += 1;
+            Ok(true)
+        }
+
+        fn end(&mut self) -> Result<()> {
+            self.end_token();
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<()> {
+            self.index = 0;
+            Ok(())
+        }
+
+        fn token(&self) -> &Token {
+            &self.token
+        }
+
+        fn token_mut(&mut self) -> &mut Token {
+            &mut self.token
+        }
+    }
+
+    #[test]
+    fn test_payload_float_codec() {
+        let decoded = decode_float_payload(&encode_float_payload(2.5));
+        assert!((decoded - 2.5).abs() < ::std::f32::EPSILON);
+        assert!((decode_float_payload(&[]) - 1.0).abs() < ::std::f32::EPSILON);
+    }
+
+    #[test]
+    fn test_higher_payload_scores_higher() {
+        let (_dir, writer) = new_fs_writer();
+
+        let mut field_type = FieldType::default();
+        field_type.index_options = IndexOptions::DocsAndFreqsAndPositions;
+        // same text in every doc, only the payload of "rust" differs
+        for boost in &[1.0f32, 4.0, 2.0] {
+            let tokens = PayloadTokenStream::new(vec![
+                ("rust", encode_float_payload(*boost)),
+                ("search", encode_float_payload(1.0)),
+            ]);
+            let field = Field::new(
+                "body".into(),
+                field_type.clone(),
+                None,
+                Some(Box::new(tokens)),
+            );
+            let doc: Vec<Box<dyn Fieldable>> = vec![Box::new(field)];
+            writer.add_document(doc).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let span = SpanQueryEnum::Term(SpanTermQuery::new(
+            Term::new("body".into(), b"rust".to_vec()),
+            None,
+        ));
+        let query = PayloadScoreQuery::new(span, Arc::new(AveragePayloadFunction), true);
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(&query, &mut collector).unwrap();
+        let top_docs = collector.top_docs();
+        let hits = top_docs.score_docs();
+
+        let docs: Vec<DocId> = hits.iter().map(|d| d.doc_id()).collect();
+        assert_eq!(docs, vec![1, 2, 0]);
+        // the span score is the same for all docs, so scores scale with the payload
+        let (high, low) = (hits[0].score(), hits[2].score());
+        assert!(low > 0.0);
+        assert!((high / low - 4.0).abs() < 1e-4);
+
+        let query = PayloadScoreQuery::new(
+            SpanQueryEnum::Term(SpanTermQuery::new(
+                Term::new("body".into(), b"rust".to_vec()),
+                None,
+            )),
+            Arc::new(MaxPayloadFunction),
+            false,
+        );
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(&query, &mut collector).unwrap();
+        let top_docs = collector.top_docs();
+        assert!((top_docs.score_docs()[0].score() - 4.0).abs() < ::std::f32::EPSILON);
+    }
+}
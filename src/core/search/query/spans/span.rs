@@ -27,7 +27,7 @@ use core::search::query::{Query, TermQuery, Weight};
 use core::search::scorer::{ConjunctionScorer, Scorer};
 use core::search::searcher::SearchPlanBuilder;
 use core::search::similarity::{SimScorer, SimWeight};
-use core::search::{DocIterator, NO_MORE_DOCS};
+use core::search::{DocIterator, MatchesIterator, PositionsMatchesIterator, NO_MORE_DOCS};
 use core::util::{DocId, KeyedContext};
 
 use error::{ErrorKind, Result};
@@ -649,6 +649,27 @@ pub trait SpanWeight<C: Codec>: Weight<C> {
         Ok(sim_scorer)
     }
 
+    /// The spans of the named document, see `Weight::matches`.
+    fn matches_span(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        if let Some(mut spans) = self.get_spans(reader, &PostingsFlag::Positions)? {
+            if spans.advance(doc)? == doc {
+                let mut matches = vec![];
+                while spans.next_start_position()? != NO_MORE_POSITIONS {
+                    // the end of a match is inclusive, not the one of a span
+                    matches.push((spans.start_position(), spans.end_position() - 1, -1, -1));
+                }
+                if !matches.is_empty() {
+                    return Ok(Some(Box::new(PositionsMatchesIterator::new(matches))));
+                }
+            }
+        }
+        Ok(None)
+    }
+
     fn explain_span(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
         if let Some(spans) = self.get_spans(reader, &PostingsFlag::Positions)? {
             let mut scorer = SpanScorer::new(spans, self.sim_scorer(reader.reader)?);
@@ -862,6 +883,14 @@ impl<C: Codec> Weight<C> for SpanWeightEnum<C> {
             SpanWeightEnum::Boost(w) => w.explain(reader, doc),
         }
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.matches_span(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for SpanWeightEnum<C> {
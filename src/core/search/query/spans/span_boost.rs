@@ -23,7 +23,7 @@ use core::search::query::spans::{
 use core::search::searcher::SearchPlanBuilder;
 use core::search::{
     query::Query, query::TermQuery, query::Weight, scorer::Scorer, similarity::SimScorer,
-    similarity::SimWeight,
+    similarity::SimWeight, MatchesIterator,
 };
 use core::util::{DocId, KeyedContext};
 
@@ -303,6 +303,14 @@ impl<C: Codec> Weight<C> for SpanBoostWeight<C> {
     fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
         self.weight.explain_span(reader, doc)
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.matches_span(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for SpanBoostWeight<C> {
@@ -484,6 +492,14 @@ impl<C: Codec> Weight<C> for SpanBoostWeightEnum<C> {
             SpanBoostWeightEnum::Near(w) => w.explain(reader, doc),
         }
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.matches_span(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for SpanBoostWeightEnum<C> {
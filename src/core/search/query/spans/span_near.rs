@@ -23,7 +23,7 @@ use core::search::query::spans::{SpanCollector, SpanQuery, SpanWeight, Spans};
 use core::search::searcher::SearchPlanBuilder;
 use core::search::{
     query::Query, query::TermQuery, query::Weight, scorer::Scorer, similarity::SimWeight,
-    DocIterator, NO_MORE_DOCS,
+    DocIterator, MatchesIterator, NO_MORE_DOCS,
 };
 use core::util::{DocId, KeyedContext, BM25_SIMILARITY_IDF};
 
@@ -313,6 +313,14 @@ impl<C: Codec> Weight<C> for SpanNearWeight<C> {
     fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
         self.explain_span(reader, doc)
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.matches_span(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for SpanNearWeight<C> {
@@ -984,6 +992,14 @@ impl<C: Codec> Weight<C> for SpanGapWeight<C> {
     fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
         self.explain_span(reader, doc)
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.matches_span(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for SpanGapWeight<C> {
@@ -22,7 +22,7 @@ use core::search::query::spans::{SpanCollector, SpanQuery, SpanWeight, Spans};
 use core::search::searcher::SearchPlanBuilder;
 use core::search::{
     query::Query, query::TermQuery, query::Weight, scorer::Scorer, similarity::SimWeight,
-    DocIterator,
+    DocIterator, MatchesIterator,
 };
 use core::util::DisiPriorityQueue;
 use core::util::DocId;
@@ -220,6 +220,14 @@ impl<C: Codec> Weight<C> for SpanOrWeight<C> {
     fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
         self.explain_span(reader, doc)
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.matches_span(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for SpanOrWeight<C> {
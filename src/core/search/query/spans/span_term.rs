@@ -11,10 +11,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use core::analysis::TokenStream;
 use core::codec::PostingIterator;
 use core::codec::{Codec, CodecPostingIterator};
 use core::codec::{TermIterator, Terms};
-use core::doc::Term;
+use core::doc::{IndexOptions, Term};
 use core::index::reader::LeafReaderContext;
 use core::search::explanation::Explanation;
 use core::search::query::spans::{build_sim_weight, PostingsFlag, SpansEnum, NO_MORE_POSITIONS};
@@ -22,7 +23,7 @@ use core::search::query::spans::{SpanCollector, SpanQuery, SpanWeight, Spans};
 use core::search::searcher::SearchPlanBuilder;
 use core::search::{
     query::Query, query::TermQuery, query::Weight, scorer::Scorer, similarity::SimWeight,
-    DocIterator, NO_MORE_DOCS,
+    DocIterator, MatchesIterator, NO_MORE_DOCS,
 };
 use core::util::{DocId, KeyedContext};
 
@@ -37,12 +38,51 @@ const SPAN_TERM_QUERY: &str = "span_term";
 pub struct SpanTermQuery {
     pub term: Term,
     pub ctx: Option<KeyedContext>,
+    /// number of positions each match covers, see `with_position_length`
+    pub position_length: i32,
 }
 
 impl SpanTermQuery {
     pub fn new<T: Into<Option<KeyedContext>>>(term: Term, ctx: T) -> Self {
+        Self::with_position_length(term, ctx, 1)
+    }
+
+    /// Creates a query whose matches span `position_length` positions instead of one.
+    ///
+    /// The index only records the start position of a token, so a token of a graph token
+    /// stream that stands for several positions (e.g. the synonym `ny` injected over
+    /// `new york`) needs its position length at query time to cover the original span,
+    /// see `from_token_graph`.
+    pub fn with_position_length<T: Into<Option<KeyedContext>>>(
+        term: Term,
+        ctx: T,
+        position_length: i32,
+    ) -> Self {
+        assert!(position_length >= 1);
         let ctx = ctx.into();
-        SpanTermQuery { term, ctx }
+        SpanTermQuery {
+            term,
+            ctx,
+            position_length,
+        }
+    }
+
+    /// Creates a query per token of the graph `stream` emits, in the order of the
+    /// stream, each covering the positions spanned by its token.
+    ///
+    /// Analyzing the query text with the token graph the field was indexed with resolves
+    /// the position lengths the index doesn't record.
+    pub fn from_token_graph(field: &str, stream: &mut dyn TokenStream) -> Result<Vec<Self>> {
+        let mut queries = vec![];
+        stream.reset()?;
+        while stream.next_token()? {
+            let token = stream.token();
+            let term = Term::new(field.to_string(), token.term.clone());
+            let position_length = token.position_length.max(1) as i32;
+            queries.push(Self::with_position_length(term, None, position_length));
+        }
+        stream.end()?;
+        Ok(queries)
     }
 }
 
@@ -102,10 +142,14 @@ impl fmt::Display for SpanTermQuery {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "SpanTermQuery(field: {}, term: {})",
+            "SpanTermQuery(field: {}, term: {}",
             self.term.field,
             self.term.text().unwrap()
-        )
+        )?;
+        if self.position_length != 1 {
+            write!(f, ", position_length: {}", self.position_length)?;
+        }
+        write!(f, ")")
     }
 }
 
@@ -116,11 +160,22 @@ pub struct TermSpans<T: PostingIterator> {
     freq: i32,
     count: i32,
     position: i32,
+    position_length: i32,
     positions_cost: f32,
 }
 
 impl<T: PostingIterator> TermSpans<T> {
     pub fn new(postings: T, term: Term, positions_cost: f32) -> Self {
+        Self::with_position_length(postings, term, positions_cost, 1)
+    }
+
+    /// Creates spans each covering `position_length` positions from their start.
+    pub fn with_position_length(
+        postings: T,
+        term: Term,
+        positions_cost: f32,
+        position_length: i32,
+    ) -> Self {
         TermSpans {
             postings,
             term,
@@ -128,6 +183,7 @@ impl<T: PostingIterator> TermSpans<T> {
             freq: 0,
             count: 0,
             position: -1,
+            position_length,
             positions_cost,
         }
     }
@@ -189,7 +245,7 @@ impl<T: PostingIterator> Spans for TermSpans<T> {
         if self.position == -1 {
             -1
         } else if self.position != NO_MORE_POSITIONS {
-            self.position + 1
+            self.position + self.position_length
         } else {
             NO_MORE_POSITIONS
         }
@@ -216,6 +272,7 @@ const TERM_OPS_PER_POS: i32 = 7;
 
 pub struct SpanTermWeight<C: Codec> {
     term: Term,
+    position_length: i32,
     sim_weight: Option<Box<dyn SimWeight<C>>>,
 }
 
@@ -230,6 +287,7 @@ impl<C: Codec> SpanTermWeight<C> {
             build_sim_weight(query.term.field(), searcher, vec![query.term.clone()], ctx)?;
         Ok(SpanTermWeight {
             term: query.term.clone(),
+            position_length: query.position_length,
             sim_weight,
         })
     }
@@ -269,9 +327,13 @@ impl<C: Codec> SpanWeight<C> for SpanTermWeight<C> {
         if let Some(terms) = reader.reader.terms(self.term.field())? {
             if !terms.has_positions()? {
                 bail!(ErrorKind::IllegalState(format!(
-                    "field '{}' was indexed without position data; cannot run SpanTermQuery \
-                     (term={:?})",
+                    "field '{}' was indexed with {:?}, without position data; cannot run \
+                     SpanTermQuery (term={:?})",
                     &self.term.field,
+                    reader
+                        .reader
+                        .field_info(self.term.field())
+                        .map_or(IndexOptions::Null, |f| f.index_options),
                     &self.term.text()
                 )));
             }
@@ -280,11 +342,13 @@ impl<C: Codec> SpanWeight<C> for SpanTermWeight<C> {
             let postings = terms_iter.postings_with_flags(required_postings.required_postings())?;
             let positions_cost =
                 Self::term_positions_cost(&mut terms_iter)? + PHRASE_TO_SPAN_TERM_POSITIONS_COST;
-            return Ok(Some(SpansEnum::Term(TermSpans::new(
+            let spans = TermSpans::with_position_length(
                 postings,
                 self.term.clone(),
                 positions_cost,
-            ))));
+                self.position_length,
+            );
+            return Ok(Some(SpansEnum::Term(spans)));
         }
         Ok(None)
     }
@@ -318,6 +382,14 @@ impl<C: Codec> Weight<C> for SpanTermWeight<C> {
     fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
         self.explain_span(reader, doc)
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        self.matches_span(reader, doc)
+    }
 }
 
 impl<C: Codec> fmt::Display for SpanTermWeight<C> {
@@ -330,3 +402,90 @@ impl<C: Codec> fmt::Display for SpanTermWeight<C> {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::analysis::{SynonymGraphFilter, SynonymMap, WhitespaceTokenizer};
+    use core::doc::{Field, FieldType, Fieldable, IndexOptions};
+    use core::search::collector::TopDocsCollector;
+    use core::search::query::spans::{SpanNearQuery, SpanQueryEnum};
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::new_fs_writer;
+
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    fn span_term(text: &str) -> SpanQueryEnum {
+        let term = Term::new("body".into(), text.as_bytes().to_vec());
+        SpanQueryEnum::Term(SpanTermQuery::new(term, None))
+    }
+
+    fn graph(text: &str, synonyms: &Arc<SynonymMap>) -> Box<dyn TokenStream> {
+        let reader = Box::new(Cursor::new(text.as_bytes().to_vec()));
+        let tokenizer = Box::new(WhitespaceTokenizer::new(reader));
+        Box::new(SynonymGraphFilter::new(tokenizer, Arc::clone(synonyms)))
+    }
+
+    fn body(text: &str, synonyms: &Arc<SynonymMap>) -> Vec<Box<dyn Fieldable>> {
+        let mut field_type = FieldType::default();
+        field_type.index_options = IndexOptions::DocsAndFreqsAndPositions;
+        let field = Field::new("body".into(), field_type, None, Some(graph(text, synonyms)));
+        vec![Box::new(field)]
+    }
+
+    #[test]
+    fn test_position_length_covers_original_span() {
+        let (_dir, writer) = new_fs_writer();
+
+        let mut synonyms = SynonymMap::new();
+        synonyms.add("ny", "new york");
+        let synonyms = Arc::new(synonyms);
+        // doc 0 is indexed as ny@0, new@0, york@1 and times@2
+        writer.add_document(body("ny times", &synonyms)).unwrap();
+        writer
+            .add_document(body("new york times", &synonyms))
+            .unwrap();
+        writer.add_document(body("ny", &synonyms)).unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let search = |clauses: Vec<SpanQueryEnum>| {
+            let query = SpanNearQuery::new(clauses, 0, true).unwrap();
+            let mut collector = TopDocsCollector::new(10);
+            searcher.search(&query, &mut collector).unwrap();
+            let mut docs: Vec<DocId> = collector
+                .top_docs()
+                .score_docs()
+                .iter()
+                .map(|d| d.doc_id())
+                .collect();
+            docs.sort();
+            docs
+        };
+
+        let new_york_times = vec![span_term("new"), span_term("york"), span_term("times")];
+        assert_eq!(search(new_york_times), vec![0, 1]);
+        // without its position length `ny` ends at position 1, leaving a gap before `times`
+        assert!(search(vec![span_term("ny"), span_term("times")]).is_empty());
+
+        // the token graph of the query resolves the position length of `ny`
+        let mut stream = graph("ny", &synonyms);
+        let graph_terms = SpanTermQuery::from_token_graph("body", stream.as_mut()).unwrap();
+        let ny = &graph_terms[0];
+        assert_eq!(ny.term.text().unwrap(), "ny");
+        assert_eq!(ny.position_length, 2);
+        let ny_span = || {
+            SpanQueryEnum::Term(SpanTermQuery::with_position_length(
+                ny.term.clone(),
+                None,
+                2,
+            ))
+        };
+        // `ny` covers `new york`, `times` directly follows
+        assert_eq!(search(vec![ny_span(), span_term("times")]), vec![0]);
+        // while `york` starts within it
+        assert!(search(vec![ny_span(), span_term("york")]).is_empty());
+    }
+}
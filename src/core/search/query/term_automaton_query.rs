@@ -0,0 +1,749 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+use core::codec::{Codec, CodecPostingIterator, PostingIterator, PostingIteratorFlags};
+use core::codec::{TermIterator, Terms};
+use core::doc::{IndexOptions, Term};
+use core::index::reader::LeafReaderContext;
+use core::search::explanation::Explanation;
+use core::search::query::{Query, TermQuery, Weight, TERM_OPS_PER_POS};
+use core::search::scorer::Scorer;
+use core::search::searcher::SearchPlanBuilder;
+use core::search::similarity::{SimScorer, SimWeight, Similarity};
+use core::search::statistics::{CollectionStatistics, TermStatistics};
+use core::search::{DocIterator, MatchesIterator, PositionsMatchesIterator, NO_MORE_DOCS};
+use core::util::DocId;
+use error::{ErrorKind::IllegalState, Result};
+
+const TERM_AUTOMATON: &str = "term_automaton";
+
+/// A transition on a query term, or on any term if `term` is `None`.
+#[derive(Clone, Debug)]
+struct TermTransition {
+    term: Option<usize>,
+    dest: usize,
+}
+
+/// The determinized automaton of a `TermAutomatonQuery`, over the ids of its terms.
+#[derive(Debug, Default)]
+struct TermDfa {
+    term_steps: Vec<HashMap<usize, usize>>,
+    any_steps: Vec<Option<usize>>,
+    accept: Vec<bool>,
+}
+
+impl TermDfa {
+    /// Steps from `state` on the query term `term`, or on a term that is not part of
+    /// the query if `term` is `None`.
+    fn step(&self, state: usize, term: Option<usize>) -> Option<usize> {
+        if let Some(term) = term {
+            if let Some(&dest) = self.term_steps[state].get(&term) {
+                return Some(dest);
+            }
+        }
+        self.any_steps[state]
+    }
+}
+
+/// A query matching the documents where a sequence of terms accepted by an automaton
+/// occurs at consecutive positions.
+///
+/// The automaton is built like `Automaton`, state 0 being the initial state, except its
+/// transitions are labeled with whole terms, or with any term to allow gaps. This is more
+/// flexible than `PhraseQuery` and cheaper than the span queries it would take to express
+/// the same alternatives. The automaton doesn't need to be deterministic, `finish` must be
+/// called to determinize it once all the transitions are added.
+///
+/// The automaton may neither start nor end with an any term transition, and must not
+/// accept the empty sequence. The score uses the number of positions a match starts at
+/// as the frequency.
+pub struct TermAutomatonQuery {
+    field: String,
+    // the query terms by their id
+    terms: Vec<Vec<u8>>,
+    term_ids: HashMap<Vec<u8>, usize>,
+    transitions: Vec<Vec<TermTransition>>,
+    accept: Vec<bool>,
+    dfa: Option<Arc<TermDfa>>,
+}
+
+impl TermAutomatonQuery {
+    pub fn new(field: String) -> TermAutomatonQuery {
+        TermAutomatonQuery {
+            field,
+            terms: vec![],
+            term_ids: HashMap::new(),
+            transitions: vec![],
+            accept: vec![],
+            dfa: None,
+        }
+    }
+
+    /// Returns a finished query matching the terms of `sequence` at consecutive
+    /// positions, `None` matching any single term.
+    pub fn sequence(field: String, sequence: &[Option<&[u8]>]) -> Result<TermAutomatonQuery> {
+        let mut query = TermAutomatonQuery::new(field);
+        let mut state = query.create_state();
+        for term in sequence {
+            let next = query.create_state();
+            match term {
+                Some(term) => query.add_transition(state, next, term),
+                None => query.add_any_transition(state, next),
+            }
+            state = next;
+        }
+        query.set_accept(state, true);
+        query.finish()?;
+        Ok(query)
+    }
+
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    pub fn create_state(&mut self) -> usize {
+        self.dfa = None;
+        self.transitions.push(vec![]);
+        self.accept.push(false);
+        self.transitions.len() - 1
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn set_accept(&mut self, state: usize, accept: bool) {
+        self.dfa = None;
+        self.accept[state] = accept;
+    }
+
+    /// Adds a transition from `source` to `dest` on `term`.
+    pub fn add_transition(&mut self, source: usize, dest: usize, term: &[u8]) {
+        debug_assert!(dest < self.num_states());
+        let next_id = self.terms.len();
+        let id = *self.term_ids.entry(term.to_vec()).or_insert(next_id);
+        if id == next_id {
+            self.terms.push(term.to_vec());
+        }
+        self.add(source, dest, Some(id));
+    }
+
+    /// Adds a transition from `source` to `dest` on any single term.
+    pub fn add_any_transition(&mut self, source: usize, dest: usize) {
+        debug_assert!(dest < self.num_states());
+        self.add(source, dest, None);
+    }
+
+    fn add(&mut self, source: usize, dest: usize, term: Option<usize>) {
+        self.dfa = None;
+        self.transitions[source].push(TermTransition { term, dest });
+    }
+
+    /// Determinizes the automaton, this must be called before searching and again after
+    /// any later change.
+    pub fn finish(&mut self) -> Result<()> {
+        if self.transitions.is_empty() {
+            bail!(IllegalState("the automaton has no states".into()));
+        }
+        let dfa = self.determinize();
+        if dfa.accept[0] {
+            bail!(IllegalState(
+                "the automaton must not accept the empty sequence".into()
+            ));
+        }
+        if dfa.any_steps[0].is_some() {
+            bail!(IllegalState(
+                "the automaton cannot lead with an any term transition".into()
+            ));
+        }
+        if dfa
+            .any_steps
+            .iter()
+            .any(|dest| dest.map_or(false, |d| dfa.accept[d]))
+        {
+            bail!(IllegalState(
+                "the automaton cannot end with an any term transition".into()
+            ));
+        }
+        self.dfa = Some(Arc::new(dfa));
+        Ok(())
+    }
+
+    // every DFA state is a set of NFA states, built by subset construction
+    fn determinize(&self) -> TermDfa {
+        let mut sets: Vec<Vec<usize>> = vec![vec![0]];
+        let mut set_ids: HashMap<Vec<usize>, usize> = HashMap::new();
+        set_ids.insert(vec![0], 0);
+        let mut dfa = TermDfa::default();
+
+        let mut upto = 0;
+        while upto < sets.len() {
+            let set = sets[upto].clone();
+            let mut labels: Vec<usize> = set
+                .iter()
+                .flat_map(|&s| self.transitions[s].iter().filter_map(|t| t.term))
+                .collect();
+            labels.sort();
+            labels.dedup();
+
+            let mut term_steps = HashMap::with_capacity(labels.len());
+            for label in labels {
+                let dests = self.dests(&set, Some(label));
+                term_steps.insert(label, Self::set_id(dests, &mut sets, &mut set_ids));
+            }
+            let any_dests = self.dests(&set, None);
+            let any_step = if any_dests.is_empty() {
+                None
+            } else {
+                Some(Self::set_id(any_dests, &mut sets, &mut set_ids))
+            };
+
+            dfa.term_steps.push(term_steps);
+            dfa.any_steps.push(any_step);
+            dfa.accept.push(set.iter().any(|&s| self.accept[s]));
+            upto += 1;
+        }
+        dfa
+    }
+
+    // the states reached from `set` on the query term `label`, or on another term
+    fn dests(&self, set: &[usize], label: Option<usize>) -> Vec<usize> {
+        let mut dests: Vec<usize> = set
+            .iter()
+            .flat_map(|&s| self.transitions[s].iter())
+            .filter(|t| t.term.is_none() || t.term == label)
+            .map(|t| t.dest)
+            .collect();
+        dests.sort();
+        dests.dedup();
+        dests
+    }
+
+    fn set_id(
+        set: Vec<usize>,
+        sets: &mut Vec<Vec<usize>>,
+        set_ids: &mut HashMap<Vec<usize>, usize>,
+    ) -> usize {
+        if let Some(&id) = set_ids.get(&set) {
+            return id;
+        }
+        sets.push(set.clone());
+        set_ids.insert(set, sets.len() - 1);
+        sets.len() - 1
+    }
+
+    fn term(&self, id: usize) -> Term {
+        Term::new(self.field.clone(), self.terms[id].clone())
+    }
+}
+
+impl<C: Codec> Query<C> for TermAutomatonQuery {
+    fn create_weight(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        needs_scores: bool,
+    ) -> Result<Box<dyn Weight<C>>> {
+        let dfa = match self.dfa {
+            Some(ref dfa) => Arc::clone(dfa),
+            None => bail!(IllegalState(
+                "call finish before searching a TermAutomatonQuery".into()
+            )),
+        };
+
+        let max_doc = i64::from(searcher.max_doc());
+        let mut term_stats = Vec::with_capacity(self.terms.len());
+        for (i, bytes) in self.terms.iter().enumerate() {
+            if needs_scores {
+                term_stats.push(searcher.term_statistics(&self.term(i))?);
+            } else {
+                term_stats.push(TermStatistics::new(bytes.clone(), max_doc, -1));
+            }
+        }
+        let collection_stats = match searcher.collections_statistics(&self.field) {
+            Some(stat) if needs_scores => stat.clone(),
+            _ => CollectionStatistics::new(self.field.clone(), 0, max_doc, -1, -1, -1),
+        };
+
+        let similarity = searcher.similarity(&self.field, needs_scores);
+        let sim_weight = similarity.compute_weight(&collection_stats, &term_stats, None, 1.0f32);
+
+        Ok(Box::new(TermAutomatonWeight {
+            field: self.field.clone(),
+            terms: self.terms.clone(),
+            dfa,
+            similarity,
+            sim_weight,
+            needs_scores,
+        }))
+    }
+
+    fn extract_terms(&self) -> Vec<TermQuery> {
+        (0..self.terms.len())
+            .map(|i| TermQuery::new(self.term(i), 1.0f32, None))
+            .collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+impl fmt::Display for TermAutomatonQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms: Vec<_> = self
+            .terms
+            .iter()
+            .map(|t| String::from_utf8_lossy(t))
+            .collect();
+        write!(
+            f,
+            "TermAutomatonQuery(field: {}, terms: {:?}, states: {})",
+            &self.field,
+            terms,
+            self.num_states()
+        )
+    }
+}
+
+struct TermAutomatonWeight<C: Codec> {
+    field: String,
+    terms: Vec<Vec<u8>>,
+    dfa: Arc<TermDfa>,
+    similarity: Box<dyn Similarity<C>>,
+    sim_weight: Box<dyn SimWeight<C>>,
+    needs_scores: bool,
+}
+
+impl<C: Codec> TermAutomatonWeight<C> {
+    fn scorer(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<TermAutomatonScorer<CodecPostingIterator<C>>>> {
+        let field_terms = match reader.reader.terms(&self.field)? {
+            Some(field_terms) => field_terms,
+            None => return Ok(None),
+        };
+        if !field_terms.has_positions()? {
+            bail!(IllegalState(format!(
+                "field {} was indexed with {:?}, without position data; cannot run \
+                 TermAutomatonQuery",
+                self.field,
+                reader
+                    .reader
+                    .field_info(&self.field)
+                    .map_or(IndexOptions::Null, |f| f.index_options),
+            )));
+        }
+
+        let mut term_iter = field_terms.iterator()?;
+        let mut postings = Vec::with_capacity(self.terms.len());
+        let mut match_cost = 0f32;
+        for (id, term) in self.terms.iter().enumerate() {
+            if !term_iter.seek_exact(term)? {
+                continue;
+            }
+            let doc_freq = term_iter.doc_freq()?;
+            let total_term_freq = term_iter.total_term_freq()?; // -1 when not available
+            let exp_occurrences_in_matching_doc = if total_term_freq < i64::from(doc_freq) {
+                1.0f32
+            } else {
+                total_term_freq as f32 / doc_freq as f32
+            };
+            match_cost += exp_occurrences_in_matching_doc * TERM_OPS_PER_POS as f32;
+            postings.push(TermPostings {
+                id,
+                postings: term_iter.postings_with_flags(PostingIteratorFlags::POSITIONS)?,
+            });
+        }
+        if postings.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(TermAutomatonScorer::new(
+            postings,
+            Arc::clone(&self.dfa),
+            self.sim_weight.sim_scorer(reader.reader)?,
+            self.needs_scores,
+            match_cost,
+        )))
+    }
+}
+
+impl<C: Codec> Weight<C> for TermAutomatonWeight<C> {
+    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        Ok(self
+            .scorer(reader)?
+            .map(|scorer| Box::new(scorer) as Box<dyn Scorer>))
+    }
+
+    fn query_type(&self) -> &'static str {
+        TERM_AUTOMATON
+    }
+
+    fn normalize(&mut self, norm: f32, boost: f32) {
+        self.sim_weight.normalize(norm, boost)
+    }
+
+    fn value_for_normalization(&self) -> f32 {
+        self.sim_weight.get_value_for_normalization()
+    }
+
+    fn needs_scores(&self) -> bool {
+        self.needs_scores
+    }
+
+    fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+        if let Some(mut scorer) = self.scorer(reader)? {
+            if scorer.advance(doc)? == doc {
+                let freq = scorer.freq as f32;
+                let freq_expl = Explanation::new(true, freq, format!("freq={}", freq), vec![]);
+                let score_expl = self.sim_weight.explain(reader.reader, doc, freq_expl)?;
+
+                return Ok(Explanation::new(
+                    true,
+                    score_expl.value(),
+                    format!("weight({} in {}), result of:", self, doc),
+                    vec![score_expl],
+                ));
+            }
+        }
+
+        Ok(Explanation::new(
+            false,
+            0.0f32,
+            "no matching term sequence".to_string(),
+            vec![],
+        ))
+    }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        if let Some(mut scorer) = self.scorer(reader)? {
+            // so that the scorer doesn't stop at the first match
+            scorer.needs_scores = true;
+            if scorer.advance(doc)? == doc {
+                let matches = scorer
+                    .match_positions
+                    .iter()
+                    .map(|&(start, end)| (start, end, -1, -1))
+                    .collect();
+                return Ok(Some(Box::new(PositionsMatchesIterator::new(matches))));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<C: Codec> fmt::Display for TermAutomatonWeight<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "TermAutomatonWeight(field: {}, terms: {}, similarity: {}, need_score: {})",
+            &self.field,
+            self.terms.len(),
+            &self.similarity,
+            self.needs_scores
+        )
+    }
+}
+
+struct TermPostings<T: PostingIterator> {
+    id: usize,
+    postings: T,
+}
+
+/// Approximates the matches by the docs containing any of the query terms, then runs
+/// the automaton over the positions of the terms in the doc.
+struct TermAutomatonScorer<T: PostingIterator> {
+    postings: Vec<TermPostings<T>>,
+    dfa: Arc<TermDfa>,
+    doc: DocId,
+    freq: i32,
+    cost: usize,
+    match_cost: f32,
+    doc_scorer: Box<dyn SimScorer>,
+    needs_scores: bool,
+    // the (position, term id) pairs of the current doc
+    positions: Vec<(i32, usize)>,
+    // the (start, end) positions of the matches of the current doc
+    match_positions: Vec<(i32, i32)>,
+}
+
+impl<T: PostingIterator> TermAutomatonScorer<T> {
+    fn new(
+        postings: Vec<TermPostings<T>>,
+        dfa: Arc<TermDfa>,
+        doc_scorer: Box<dyn SimScorer>,
+        needs_scores: bool,
+        match_cost: f32,
+    ) -> Self {
+        let cost = postings.iter().map(|p| p.postings.cost()).sum();
+        TermAutomatonScorer {
+            postings,
+            dfa,
+            doc: -1,
+            freq: 0,
+            cost,
+            match_cost,
+            doc_scorer,
+            needs_scores,
+            positions: vec![],
+            match_positions: vec![],
+        }
+    }
+
+    fn min_doc(&self) -> DocId {
+        self.postings
+            .iter()
+            .map(|p| p.postings.doc_id())
+            .min()
+            .unwrap_or(NO_MORE_DOCS)
+    }
+
+    /// Returns the number of positions of the current doc a match starts at.
+    fn automaton_freq(&mut self) -> Result<i32> {
+        self.positions.clear();
+        for p in &mut self.postings {
+            if p.postings.doc_id() == self.doc {
+                for _ in 0..p.postings.freq()? {
+                    self.positions.push((p.postings.next_position()?, p.id));
+                }
+            }
+        }
+        self.positions.sort();
+        self.match_positions.clear();
+
+        let positions = &self.positions;
+        let dfa = &self.dfa;
+        let mut freq = 0;
+        let mut states = vec![];
+        let mut next_states = vec![];
+        let mut start = 0;
+        // the automaton can't lead with any term, so matches start on a query term
+        while start < positions.len() {
+            let start_pos = positions[start].0;
+            let mut pos = start_pos;
+            let mut upto = start;
+            states.clear();
+            states.push(0);
+            loop {
+                next_states.clear();
+                if upto < positions.len() && positions[upto].0 == pos {
+                    while upto < positions.len() && positions[upto].0 == pos {
+                        let term = positions[upto].1;
+                        for &state in &states {
+                            if let Some(dest) = dfa.step(state, Some(term)) {
+                                if !next_states.contains(&dest) {
+                                    next_states.push(dest);
+                                }
+                            }
+                        }
+                        upto += 1;
+                    }
+                } else {
+                    // a gap between the query terms, only any term goes on
+                    for &state in &states {
+                        if let Some(dest) = dfa.step(state, None) {
+                            if !next_states.contains(&dest) {
+                                next_states.push(dest);
+                            }
+                        }
+                    }
+                }
+
+                if next_states.iter().any(|&s| dfa.accept[s]) {
+                    freq += 1;
+                    self.match_positions.push((start_pos, pos));
+                    break;
+                }
+                // the automaton can't end with any term either, so there is nothing left
+                // to match after the last query term
+                if next_states.is_empty() || upto == positions.len() {
+                    break;
+                }
+                mem::swap(&mut states, &mut next_states);
+                pos += 1;
+            }
+
+            if freq > 0 && !self.needs_scores {
+                break;
+            }
+            while start < positions.len() && positions[start].0 == start_pos {
+                start += 1;
+            }
+        }
+
+        self.freq = freq;
+        Ok(freq)
+    }
+}
+
+impl<T: PostingIterator> Scorer for TermAutomatonScorer<T> {
+    fn score(&mut self) -> Result<f32> {
+        self.doc_scorer.score(self.doc, self.freq as f32)
+    }
+}
+
+impl<T: PostingIterator> DocIterator for TermAutomatonScorer<T> {
+    fn doc_id(&self) -> DocId {
+        self.doc
+    }
+
+    fn next(&mut self) -> Result<DocId> {
+        let doc = self.approximate_next()?;
+        self.confirm_match(doc)
+    }
+
+    fn advance(&mut self, target: DocId) -> Result<DocId> {
+        let doc = self.approximate_advance(target)?;
+        self.confirm_match(doc)
+    }
+
+    fn cost(&self) -> usize {
+        self.cost
+    }
+
+    fn matches(&mut self) -> Result<bool> {
+        Ok(self.automaton_freq()? > 0)
+    }
+
+    fn match_cost(&self) -> f32 {
+        self.match_cost
+    }
+
+    fn support_two_phase(&self) -> bool {
+        true
+    }
+
+    fn approximate_next(&mut self) -> Result<DocId> {
+        let doc = self.doc;
+        for p in &mut self.postings {
+            if p.postings.doc_id() == doc {
+                p.postings.next()?;
+            }
+        }
+        self.doc = self.min_doc();
+        Ok(self.doc)
+    }
+
+    fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+        for p in &mut self.postings {
+            if p.postings.doc_id() < target {
+                p.postings.advance(target)?;
+            }
+        }
+        self.doc = self.min_doc();
+        Ok(self.doc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::search::collector::TopDocsCollector;
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::{new_fs_writer, text_doc};
+
+    fn search<IS: IndexSearcher<C>, C: Codec>(
+        searcher: &IS,
+        query: &TermAutomatonQuery,
+    ) -> Vec<DocId> {
+        let mut collector = TopDocsCollector::new(10);
+        searcher.search(query, &mut collector).unwrap();
+        let mut docs: Vec<DocId> = collector
+            .top_docs()
+            .score_docs()
+            .iter()
+            .map(|d| d.doc_id())
+            .collect();
+        docs.sort();
+        docs
+    }
+
+    #[test]
+    fn test_term_sequences_with_gaps() {
+        let (_dir, writer) = new_fs_writer();
+        for text in &[
+            "the quick brown fox jumps",
+            "the quick fox jumps",
+            "quick red fox and quick old fox",
+            "the fox quick brown",
+            "a quick brown dog",
+        ] {
+            writer
+                .add_document(text_doc(
+                    "body",
+                    text,
+                    IndexOptions::DocsAndFreqsAndPositions,
+                ))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+        let reader = writer.get_reader(true, false).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        let query = TermAutomatonQuery::sequence(
+            "body".into(),
+            &[Some(&b"quick"[..]), None, Some(&b"fox"[..])],
+        )
+        .unwrap();
+        assert_eq!(search(&searcher, &query), vec![0, 2]);
+
+        // quick, any number of terms, fox
+        let mut query = TermAutomatonQuery::new("body".into());
+        let start = query.create_state();
+        let gap = query.create_state();
+        let end = query.create_state();
+        query.add_transition(start, gap, b"quick");
+        query.add_any_transition(gap, gap);
+        query.add_transition(gap, end, b"fox");
+        query.set_accept(end, true);
+        // searching an unfinished automaton fails
+        let mut collector = TopDocsCollector::new(10);
+        assert!(searcher.search(&query, &mut collector).is_err());
+        query.finish().unwrap();
+        assert_eq!(search(&searcher, &query), vec![0, 1, 2]);
+
+        // alternatives at the same position: quick (brown | red) (fox | dog)
+        let mut query = TermAutomatonQuery::new("body".into());
+        let states: Vec<usize> = (0..4).map(|_| query.create_state()).collect();
+        query.add_transition(states[0], states[1], b"quick");
+        query.add_transition(states[1], states[2], b"brown");
+        query.add_transition(states[1], states[2], b"red");
+        query.add_transition(states[2], states[3], b"fox");
+        query.add_transition(states[2], states[3], b"dog");
+        query.set_accept(states[3], true);
+        query.finish().unwrap();
+        assert_eq!(search(&searcher, &query), vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn test_rejects_unbounded_automata() {
+        let fox = Some(&b"fox"[..]);
+        for sequence in &[vec![None, fox], vec![fox, None], vec![]] {
+            assert!(TermAutomatonQuery::sequence("body".into(), sequence).is_err());
+        }
+        assert!(TermAutomatonQuery::new("body".into()).finish().is_err());
+    }
+}
@@ -15,9 +15,9 @@ use error::Result;
 
 use std::fmt;
 
-use core::codec::Codec;
+use core::codec::{Codec, CodecPostingIterator};
 use core::codec::{PostingIterator, PostingIteratorFlags};
-use core::doc::Term;
+use core::doc::{IndexOptions, Term};
 use core::index::reader::LeafReaderContext;
 use core::search::explanation::Explanation;
 use core::search::query::{Query, Weight};
@@ -25,7 +25,7 @@ use core::search::scorer::{Scorer, TermScorer};
 use core::search::searcher::SearchPlanBuilder;
 use core::search::similarity::{SimWeight, Similarity};
 use core::search::statistics::{CollectionStatistics, TermStatistics};
-use core::search::DocIterator;
+use core::search::{DocIterator, EmptyMatchesIterator, MatchesIterator, TermMatchesIterator};
 
 use core::util::{DocId, KeyedContext};
 
@@ -115,7 +115,9 @@ impl fmt::Display for TermQuery {
     }
 }
 
-struct TermWeight<C: Codec> {
+/// The weight of a `TermQuery`, whose `TermScorer` the searcher uses directly instead
+/// of through a `Box<dyn Scorer>`, see `Weight::as_term_weight`.
+pub struct TermWeight<C: Codec> {
     term: Term,
     boost: f32,
     similarity: Box<dyn Similarity<C>>,
@@ -139,10 +141,12 @@ impl<C: Codec> TermWeight<C> {
             needs_scores,
         }
     }
-}
 
-impl<C: Codec> Weight<C> for TermWeight<C> {
-    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+    /// Creates the scorer of the term in `reader`, `None` if the term doesn't occur in it.
+    pub fn create_term_scorer(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+    ) -> Result<Option<TermScorer<CodecPostingIterator<C>>>> {
         let _norms = reader.reader.norm_values(&self.term.field);
         let sim_scorer = self.sim_weight.sim_scorer(reader.reader)?;
 
@@ -153,14 +157,24 @@ impl<C: Codec> Weight<C> for TermWeight<C> {
         };
 
         if let Some(postings_iterator) = reader.reader.postings(&self.term, flags as i32)? {
-            Ok(Some(Box::new(TermScorer::new(
-                sim_scorer,
-                postings_iterator,
-            ))))
+            Ok(Some(TermScorer::new(sim_scorer, postings_iterator)))
         } else {
             Ok(None)
         }
     }
+}
+
+impl<C: Codec> Weight<C> for TermWeight<C> {
+    fn create_scorer(&self, reader: &LeafReaderContext<'_, C>) -> Result<Option<Box<dyn Scorer>>> {
+        match self.create_term_scorer(reader)? {
+            Some(scorer) => Ok(Some(Box::new(scorer))),
+            None => Ok(None),
+        }
+    }
+
+    fn as_term_weight(&self) -> Option<&TermWeight<C>> {
+        Some(self)
+    }
 
     fn query_type(&self) -> &'static str {
         TERM
@@ -211,6 +225,34 @@ impl<C: Codec> Weight<C> for TermWeight<C> {
             vec![],
         ))
     }
+
+    fn matches(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        doc: DocId,
+    ) -> Result<Option<Box<dyn MatchesIterator>>> {
+        let index_options = reader
+            .reader
+            .field_info(&self.term.field)
+            .map_or(IndexOptions::Null, |f| f.index_options);
+        let flags = if index_options.has_offsets() {
+            PostingIteratorFlags::OFFSETS
+        } else if index_options.has_positions() {
+            PostingIteratorFlags::POSITIONS
+        } else {
+            PostingIteratorFlags::NONE
+        };
+
+        if let Some(mut postings_iterator) = reader.reader.postings(&self.term, flags as i32)? {
+            if postings_iterator.advance(doc)? == doc {
+                if !index_options.has_positions() {
+                    return Ok(Some(Box::new(EmptyMatchesIterator)));
+                }
+                return Ok(Some(Box::new(TermMatchesIterator::new(postings_iterator)?)));
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl<C: Codec> fmt::Display for TermWeight<C> {
@@ -157,6 +157,7 @@ impl<'a, S: Scorer + ?Sized + 'a> BulkScorer<'a, S> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use core::search::scorer::ConstantScoreScorer;
     use core::search::tests::*;
 
     use core::index::reader::IndexReader;
@@ -198,4 +199,43 @@ mod tests {
         assert_eq!(score_docs[1].doc_id(), 4);
         assert_eq!(score_docs[2].doc_id(), 3);
     }
+
+    #[test]
+    fn test_score_constant_range_with_live_docs() {
+        let iterator = MockDocIterator::new(vec![1, 3, 5, 7, 9]);
+        let mut scorer = ConstantScoreScorer::new(2.0, iterator, 5);
+        let mut live_docs = FixedBitSet::new(10);
+        for doc in 0..10 {
+            if doc != 5 {
+                live_docs.set(doc);
+            }
+        }
+        let leaf_reader = MockLeafReader::new(0);
+        let index_reader = MockIndexReader::new(vec![leaf_reader]);
+        let leaf_reader_context = index_reader.leaves();
+        let mut top_collector = TopDocsCollector::new(10);
+        top_collector
+            .set_next_reader(&leaf_reader_context[0])
+            .unwrap();
+        let next = BulkScorer::new(&mut scorer)
+            .score(
+                &mut top_collector,
+                Some(&live_docs),
+                2,
+                8,
+                NO_MORE_DOCS as usize,
+            )
+            .unwrap();
+        // the first match at or after the range end
+        assert_eq!(next, 9);
+
+        let top_docs = top_collector.top_docs();
+        assert_eq!(top_docs.total_hits(), 2);
+        let mut docs: Vec<DocId> = top_docs.score_docs().iter().map(|d| d.doc_id()).collect();
+        docs.sort();
+        assert_eq!(docs, vec![3, 7]);
+        for doc in top_docs.score_docs() {
+            assert!((doc.score() - 2.0).abs() < ::std::f32::EPSILON);
+        }
+    }
 }
@@ -16,11 +16,17 @@ use core::search::{DocIterator, NO_MORE_DOCS};
 use core::util::DocId;
 use error::Result;
 
+use std::cmp::Ordering;
+
 /// Scorer for conjunctions, sets of queries, all of which are required.
 pub struct ConjunctionScorer<T: Scorer> {
     lead1: T,
     lead2: T,
     others: Vec<T>,
+    // the children supporting two phase iteration, by their position in
+    // `[lead1, lead2, others..]`, cheapest to confirm first
+    two_phase: Vec<usize>,
+    match_cost: f32,
 }
 
 impl<T: Scorer> ConjunctionScorer<T> {
@@ -34,11 +40,51 @@ impl<T: Scorer> ConjunctionScorer<T> {
         let lead2 = children.remove(1);
         let lead1 = children.remove(0);
 
-        ConjunctionScorer {
+        let mut scorer = ConjunctionScorer {
             lead1,
             lead2,
             others,
+            two_phase: vec![],
+            match_cost: 0f32,
+        };
+        let mut two_phase: Vec<usize> = (0..scorer.others.len() + 2)
+            .filter(|&i| scorer.child(i).support_two_phase())
+            .collect();
+        two_phase.sort_by(|&a, &b| {
+            let (a, b) = (scorer.child(a).match_cost(), scorer.child(b).match_cost());
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        });
+        scorer.match_cost = two_phase
+            .iter()
+            .map(|&i| scorer.child(i).match_cost())
+            .sum();
+        scorer.two_phase = two_phase;
+        scorer
+    }
+
+    fn child(&self, i: usize) -> &T {
+        match i {
+            0 => &self.lead1,
+            1 => &self.lead2,
+            _ => &self.others[i - 2],
+        }
+    }
+
+    fn child_mut(&mut self, i: usize) -> &mut T {
+        match i {
+            0 => &mut self.lead1,
+            1 => &mut self.lead2,
+            _ => &mut self.others[i - 2],
+        }
+    }
+
+    /// Moves on from the approximate match `doc` until all the two phase children
+    /// confirm the match.
+    fn confirm(&mut self, doc: DocId) -> Result<DocId> {
+        if self.two_phase.is_empty() {
+            return Ok(doc);
         }
+        self.confirm_match(doc)
     }
 
     fn skip_to_approx(&mut self, target: DocId) -> Result<DocId> {
@@ -101,11 +147,13 @@ impl<T: Scorer> DocIterator for ConjunctionScorer<T> {
     }
 
     fn next(&mut self) -> Result<DocId> {
-        self.approximate_next()
+        let doc = self.approximate_next()?;
+        self.confirm(doc)
     }
 
     fn advance(&mut self, target: DocId) -> Result<DocId> {
-        self.approximate_advance(target)
+        let doc = self.approximate_advance(target)?;
+        self.confirm(doc)
     }
 
     fn cost(&self) -> usize {
@@ -113,9 +161,25 @@ impl<T: Scorer> DocIterator for ConjunctionScorer<T> {
     }
 
     fn matches(&mut self) -> Result<bool> {
+        // the approximations agree on the current doc, only the expensive
+        // checks are left
+        for i in 0..self.two_phase.len() {
+            let child = self.two_phase[i];
+            if !self.child_mut(child).matches()? {
+                return Ok(false);
+            }
+        }
         Ok(true)
     }
 
+    fn match_cost(&self) -> f32 {
+        self.match_cost
+    }
+
+    fn support_two_phase(&self) -> bool {
+        !self.two_phase.is_empty()
+    }
+
     fn approximate_next(&mut self) -> Result<DocId> {
         let doc = self.lead1.approximate_next()?;
         self.skip_to_approx(doc)
@@ -132,6 +196,128 @@ mod tests {
     use super::*;
     use core::search::tests::*;
 
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Counts the calls that move the wrapped iterator.
+    struct CountingIterator {
+        inner: MockDocIterator,
+        moves: Arc<AtomicUsize>,
+    }
+
+    impl DocIterator for CountingIterator {
+        fn doc_id(&self) -> DocId {
+            self.inner.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            self.moves.fetch_add(1, Ordering::Relaxed);
+            self.inner.next()
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            self.moves.fetch_add(1, Ordering::Relaxed);
+            self.inner.advance(target)
+        }
+
+        fn cost(&self) -> usize {
+            self.inner.cost()
+        }
+    }
+
+    /// A two phase iterator whose approximation also matches the docs in `rejected`.
+    struct TwoPhaseMockIterator {
+        approximation: MockDocIterator,
+        rejected: Vec<DocId>,
+        match_cost: f32,
+        checked: Arc<Mutex<Vec<DocId>>>,
+    }
+
+    impl DocIterator for TwoPhaseMockIterator {
+        fn doc_id(&self) -> DocId {
+            self.approximation.doc_id()
+        }
+
+        fn next(&mut self) -> Result<DocId> {
+            loop {
+                let doc = self.approximation.next()?;
+                if doc == NO_MORE_DOCS || self.matches()? {
+                    return Ok(doc);
+                }
+            }
+        }
+
+        fn advance(&mut self, target: DocId) -> Result<DocId> {
+            let doc = self.approximation.advance(target)?;
+            if doc == NO_MORE_DOCS || self.matches()? {
+                return Ok(doc);
+            }
+            self.next()
+        }
+
+        fn cost(&self) -> usize {
+            self.approximation.cost()
+        }
+
+        fn matches(&mut self) -> Result<bool> {
+            let doc = self.doc_id();
+            self.checked.lock().unwrap().push(doc);
+            Ok(!self.rejected.contains(&doc))
+        }
+
+        fn match_cost(&self) -> f32 {
+            self.match_cost
+        }
+
+        fn support_two_phase(&self) -> bool {
+            true
+        }
+
+        fn approximate_next(&mut self) -> Result<DocId> {
+            self.approximation.next()
+        }
+
+        fn approximate_advance(&mut self, target: DocId) -> Result<DocId> {
+            self.approximation.advance(target)
+        }
+    }
+
+    #[test]
+    fn test_conjunction_confirms_two_phase_children() {
+        let checked = Arc::new(Mutex::new(vec![]));
+        let two_phase = |docs: Vec<DocId>, rejected: Vec<DocId>, match_cost: f32| {
+            MockSimpleScorer::new(TwoPhaseMockIterator {
+                approximation: MockDocIterator::new(docs),
+                rejected,
+                match_cost,
+                checked: Arc::clone(&checked),
+            })
+        };
+        // the phrase like children approximately match more docs than they really do
+        let expensive = two_phase(vec![1, 2, 3, 4, 6, 8], vec![2], 10.0);
+        let cheap = two_phase(vec![2, 3, 4, 5, 6, 7, 8], vec![4, 8], 1.0);
+        let term = two_phase(vec![0, 2, 4, 6, 8], vec![], 0.0);
+
+        let mut scorer = ConjunctionScorer::new(vec![expensive, cheap, term]);
+        assert!(scorer.support_two_phase());
+        assert!((scorer.match_cost() - 11.0).abs() < ::std::f32::EPSILON);
+
+        assert_eq!(scorer.next().unwrap(), 6);
+        assert_eq!(scorer.next().unwrap(), NO_MORE_DOCS);
+        // only the docs all the approximations agree on are confirmed, cheapest
+        // first and stopping at the first rejection
+        assert_eq!(*checked.lock().unwrap(), vec![2, 2, 2, 4, 4, 6, 6, 6, 8, 8]);
+
+        let mut scorer = ConjunctionScorer::new(vec![
+            two_phase(vec![1, 2, 3, 4, 6, 8], vec![2], 10.0),
+            two_phase(vec![2, 3, 4, 5, 6, 7, 8], vec![4, 8], 1.0),
+        ]);
+        assert_eq!(scorer.advance(1).unwrap(), 3);
+        assert_eq!(scorer.advance(4).unwrap(), 6);
+        assert_eq!(scorer.approximate_next().unwrap(), 8);
+        assert!(!scorer.matches().unwrap());
+    }
+
     #[test]
     fn test_mock_doc_iterator_next() {
         let mut it = MockDocIterator::new(vec![1, 2, 3, 4, 5]);
@@ -220,4 +406,35 @@ mod tests {
 
         ConjunctionScorer::new(vec![s1, s2, s3])
     }
+
+    #[test]
+    fn test_conjunction_leads_with_cheapest() {
+        let common_moves = Arc::new(AtomicUsize::new(0));
+        let rare_moves = Arc::new(AtomicUsize::new(0));
+        let common = MockSimpleScorer::new(CountingIterator {
+            inner: MockDocIterator::new((0..1000).collect()),
+            moves: Arc::clone(&common_moves),
+        });
+        let rare = MockSimpleScorer::new(CountingIterator {
+            inner: MockDocIterator::new(vec![10, 500, 900]),
+            moves: Arc::clone(&rare_moves),
+        });
+
+        // the common term comes first, but the rare one has to lead
+        let mut scorer = ConjunctionScorer::new(vec![common, rare]);
+        assert_eq!(scorer.cost(), 3);
+        let mut docs = vec![];
+        loop {
+            let doc = scorer.next().unwrap();
+            if doc == NO_MORE_DOCS {
+                break;
+            }
+            docs.push(doc);
+        }
+        assert_eq!(docs, vec![10, 500, 900]);
+        // one advance on the common term per candidate of the rare one, plus the
+        // final one to NO_MORE_DOCS
+        assert_eq!(common_moves.load(Ordering::Relaxed), 4);
+        assert_eq!(rare_moves.load(Ordering::Relaxed), 4);
+    }
 }
@@ -24,6 +24,7 @@ pub struct DisjunctionSumScorer<T: Scorer> {
     needs_scores: bool,
     cost: usize,
     min_should_match: i32,
+    max_coord: usize,
 }
 
 impl<T: Scorer> DisjunctionSumScorer<T> {
@@ -33,7 +34,32 @@ impl<T: Scorer> DisjunctionSumScorer<T> {
         min_should_match: i32,
     ) -> DisjunctionSumScorer<T> {
         assert!(children.len() > 1);
+        Self::build(children, needs_scores, min_should_match, 0)
+    }
+
+    /// Creates a scorer whose summed score is multiplied by the coordination factor
+    /// `matching sub scorers / max_coord`.
+    ///
+    /// `max_coord` is the total number of optional clauses, which may be larger than
+    /// `children.len()` if some clauses have no scorer for the current segment, so a
+    /// single child is allowed here.
+    pub fn with_coord(
+        children: Vec<T>,
+        needs_scores: bool,
+        min_should_match: i32,
+        max_coord: usize,
+    ) -> DisjunctionSumScorer<T> {
+        assert!(!children.is_empty());
+        assert!(max_coord >= children.len());
+        Self::build(children, needs_scores, min_should_match, max_coord)
+    }
 
+    fn build(
+        children: Vec<T>,
+        needs_scores: bool,
+        min_should_match: i32,
+        max_coord: usize,
+    ) -> DisjunctionSumScorer<T> {
         let cost = children.iter().map(|w| w.cost()).sum();
 
         let sub_scorers = if children.len() < 10 || min_should_match > 1 {
@@ -47,6 +73,7 @@ impl<T: Scorer> DisjunctionSumScorer<T> {
             needs_scores,
             cost,
             min_should_match,
+            max_coord,
         }
     }
 }
@@ -57,7 +84,12 @@ impl<T: Scorer> Scorer for DisjunctionSumScorer<T> {
             return Ok(0.0f32);
         }
 
-        self.sub_scorers.score_sum()
+        let (score, matched) = self.sub_scorers.score_sum()?;
+        if self.max_coord > 0 {
+            Ok(score * matched as f32 / self.max_coord as f32)
+        } else {
+            Ok(score)
+        }
     }
 }
 
@@ -201,28 +233,34 @@ pub enum SubScorers<T: Scorer> {
 }
 
 impl<T: Scorer> SubScorers<T> {
-    fn score_sum(&mut self) -> Result<f32> {
+    /// Returns the summed score of the sub scorers on the current doc and how many
+    /// of them matched it.
+    fn score_sum(&mut self) -> Result<(f32, usize)> {
         match self {
             SubScorers::SQ(sq) => {
                 let mut score: f32 = 0.0f32;
+                let mut matched = 0;
 
                 let doc_id = sq.curr_doc;
                 for s in sq.scorers.iter_mut() {
                     if s.doc_id() == doc_id {
                         let sub_score = s.score()?;
                         score += sub_score;
+                        matched += 1;
                     }
                 }
 
-                Ok(score)
+                Ok((score, matched))
             }
             SubScorers::DPQ(dpq) => {
                 let mut score: f32 = 0.0f32;
+                let mut matched = 0;
                 let mut disi = dpq.top_list();
 
                 loop {
                     let sub_score = disi.inner_mut().score()?;
                     score += sub_score;
+                    matched += 1;
 
                     if disi.next.is_null() {
                         break;
@@ -231,7 +269,7 @@ impl<T: Scorer> SubScorers<T> {
                     }
                 }
 
-                Ok(score)
+                Ok((score, matched))
             }
         }
     }
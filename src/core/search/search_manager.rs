@@ -14,12 +14,15 @@
 use core::{
     codec::Codec,
     index::merge::{MergePolicy, MergeScheduler},
-    index::reader::{IndexReader, StandardDirectoryReader},
+    index::reader::{IndexReader, LeafReader, LeafReaderContext, StandardDirectoryReader},
     index::writer::IndexWriter,
-    search::searcher::IndexSearcher,
+    search::query::Query,
+    search::searcher::{IndexSearcher, SearchPlanBuilder},
+    search::NO_MORE_DOCS,
     store::directory::Directory,
 };
 
+use std::collections::{HashSet, VecDeque};
 use std::ops::Deref;
 use std::{
     mem,
@@ -45,6 +48,7 @@ pub struct SearcherManager<C: Codec, T, SF: SearcherFactory<C>> {
     searcher_factory: SF,
     pub manager_base: ReferenceManagerBase<SF::Searcher>,
     refresh_listener: Option<T>,
+    warmer: Option<Arc<dyn IndexReaderWarmer<C>>>,
 }
 
 impl<C: Codec, T, SF: SearcherFactory<C>> SearcherManager<C, T, SF> {
@@ -80,8 +84,15 @@ impl<C: Codec, T, SF: SearcherFactory<C>> SearcherManager<C, T, SF> {
             searcher_factory,
             manager_base,
             refresh_listener,
+            warmer: None,
         })
     }
+
+    /// Registers the warmer run on the segments that become visible by a refresh, before
+    /// the refreshed searcher is handed out.
+    pub fn set_warmer(&mut self, warmer: Arc<dyn IndexReaderWarmer<C>>) {
+        self.warmer = Some(warmer);
+    }
 }
 
 impl<C, T, SF, RL> ReferenceManager<SF::Searcher, RL> for SearcherManager<C, T, SF>
@@ -109,9 +120,21 @@ where
         reference_to_refresh: &Arc<SF::Searcher>,
     ) -> Result<Option<Arc<SF::Searcher>>> {
         if let Some(reader) = reference_to_refresh.reader().refresh()? {
-            self.searcher_factory
-                .new_searcher(Arc::from(reader))
-                .map(|s| Some(Arc::new(s)))
+            let searcher = self.searcher_factory.new_searcher(Arc::from(reader))?;
+            if let Some(ref warmer) = self.warmer {
+                let old_segments: HashSet<String> = reference_to_refresh
+                    .reader()
+                    .leaves()
+                    .iter()
+                    .map(|leaf| leaf.reader.name().to_string())
+                    .collect();
+                for leaf in searcher.reader().leaves() {
+                    if !old_segments.contains(leaf.reader.name()) {
+                        warmer.warm(&searcher, &leaf)?;
+                    }
+                }
+            }
+            Ok(Some(Arc::new(searcher)))
         } else {
             Ok(None)
         }
@@ -135,6 +158,83 @@ pub trait SearcherFactory<C: Codec> {
     fn new_searcher(&self, reader: Arc<dyn IndexReader<Codec = C>>) -> Result<Self::Searcher>;
 }
 
+/// Prepares a newly visible segment, e.g. by running common queries on it, before it
+/// serves traffic.
+pub trait IndexReaderWarmer<C: Codec>: Send + Sync {
+    fn warm(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        leaf: &LeafReaderContext<'_, C>,
+    ) -> Result<()>;
+}
+
+/// An `IndexReaderWarmer` that does nothing.
+pub struct NoOpWarmer;
+
+impl<C: Codec> IndexReaderWarmer<C> for NoOpWarmer {
+    fn warm(
+        &self,
+        _searcher: &dyn SearchPlanBuilder<C>,
+        _leaf: &LeafReaderContext<'_, C>,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// An `IndexReaderWarmer` replaying the last `capacity` recorded queries on new segments.
+pub struct TopQueriesWarmer<C: Codec> {
+    capacity: usize,
+    queries: Mutex<VecDeque<Arc<dyn Query<C> + Send + Sync>>>,
+}
+
+impl<C: Codec> TopQueriesWarmer<C> {
+    pub fn new(capacity: usize) -> Self {
+        TopQueriesWarmer {
+            capacity,
+            queries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Records a served query, dropping the oldest one once `capacity` queries are kept.
+    pub fn record(&self, query: Arc<dyn Query<C> + Send + Sync>) -> Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut queries = self.queries.lock()?;
+        if queries.len() == self.capacity {
+            queries.pop_front();
+        }
+        queries.push_back(query);
+        Ok(())
+    }
+
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.queries.lock()?.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+}
+
+impl<C: Codec> IndexReaderWarmer<C> for TopQueriesWarmer<C> {
+    fn warm(
+        &self,
+        searcher: &dyn SearchPlanBuilder<C>,
+        leaf: &LeafReaderContext<'_, C>,
+    ) -> Result<()> {
+        // don't hold the lock while searching
+        let queries: Vec<_> = self.queries.lock()?.iter().cloned().collect();
+        for query in queries {
+            let weight = searcher.create_normalized_weight(query.as_ref(), false)?;
+            if let Some(mut scorer) = weight.create_scorer(leaf)? {
+                while scorer.next()? != NO_MORE_DOCS {}
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct ReferenceManagerBase<T: ?Sized> {
     lock: Mutex<()>,
     refresh_lock: Mutex<()>,
@@ -351,3 +451,136 @@ pub trait RefreshListener {
     /// to return the new reference
     fn after_refresh(&self, refreshed: bool) -> Result<()>;
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use core::codec::CodecEnum;
+    use core::doc::Term;
+    use core::search::query::{TermQuery, Weight};
+    use core::search::{DefaultIndexSearcher, DefaultSimilarityProducer};
+    use core::test_util::{keyword_doc, new_fs_writer, NoListener};
+
+    use std::any::Any;
+    use std::fmt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    type Reader = dyn IndexReader<Codec = CodecEnum>;
+    type Searcher = DefaultIndexSearcher<CodecEnum, Reader, Arc<Reader>, DefaultSimilarityProducer>;
+
+    struct Factory;
+
+    impl SearcherFactory<CodecEnum> for Factory {
+        type Searcher = Searcher;
+
+        fn new_searcher(&self, reader: Arc<Reader>) -> Result<Searcher> {
+            Ok(DefaultIndexSearcher::new(reader, None))
+        }
+    }
+
+    struct RecordingWarmer {
+        warmed: Mutex<Vec<String>>,
+        replay: TopQueriesWarmer<CodecEnum>,
+    }
+
+    impl IndexReaderWarmer<CodecEnum> for RecordingWarmer {
+        fn warm(
+            &self,
+            searcher: &dyn SearchPlanBuilder<CodecEnum>,
+            leaf: &LeafReaderContext<'_, CodecEnum>,
+        ) -> Result<()> {
+            self.warmed.lock()?.push(leaf.reader.name().to_string());
+            self.replay.warm(searcher, leaf)
+        }
+    }
+
+    // a term query counting the weights created for it
+    struct ReplayedQuery {
+        query: TermQuery,
+        replays: Arc<AtomicUsize>,
+    }
+
+    impl Query<CodecEnum> for ReplayedQuery {
+        fn create_weight(
+            &self,
+            searcher: &dyn SearchPlanBuilder<CodecEnum>,
+            needs_scores: bool,
+        ) -> Result<Box<dyn Weight<CodecEnum>>> {
+            self.replays.fetch_add(1, Ordering::SeqCst);
+            self.query.create_weight(searcher, needs_scores)
+        }
+
+        fn extract_terms(&self) -> Vec<TermQuery> {
+            self.query.extract_terms()
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    impl fmt::Display for ReplayedQuery {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{}", &self.query)
+        }
+    }
+
+    fn term_query(
+        value: &str,
+        replays: &Arc<AtomicUsize>,
+    ) -> Arc<dyn Query<CodecEnum> + Send + Sync> {
+        Arc::new(ReplayedQuery {
+            query: TermQuery::new(
+                Term::new("tag".into(), value.as_bytes().to_vec()),
+                1.0,
+                None,
+            ),
+            replays: Arc::clone(replays),
+        })
+    }
+
+    #[test]
+    fn test_warm_new_segments_on_refresh() {
+        let (_dir, writer) = new_fs_writer();
+        writer.add_document(keyword_doc("a")).unwrap();
+        writer.commit().unwrap();
+
+        let mut manager: SearcherManager<CodecEnum, Arc<NoListener>, Factory> =
+            SearcherManager::from_writer(&writer, true, false, Factory, None).unwrap();
+        let warmer = Arc::new(RecordingWarmer {
+            warmed: Mutex::new(Vec::new()),
+            replay: TopQueriesWarmer::new(2),
+        });
+        let replays = Arc::new(AtomicUsize::new(0));
+        for value in &["a", "b", "c"] {
+            warmer.replay.record(term_query(value, &replays)).unwrap();
+        }
+        // only the last two queries are kept
+        assert_eq!(warmer.replay.len().unwrap(), 2);
+        manager.set_warmer(Arc::clone(&warmer) as Arc<dyn IndexReaderWarmer<CodecEnum>>);
+
+        writer.add_document(keyword_doc("b")).unwrap();
+        writer.commit().unwrap();
+        assert!(manager.maybe_refresh().unwrap());
+        // the segment that was already visible isn't warmed again
+        assert_eq!(*warmer.warmed.lock().unwrap(), vec!["_1".to_string()]);
+        // the kept queries were replayed on the new segment
+        assert_eq!(replays.load(Ordering::SeqCst), 2);
+
+        let searcher = manager.acquire().unwrap();
+        assert_eq!(searcher.reader().num_docs(), 2);
+        manager.release(&searcher).unwrap();
+
+        // nothing changed, nothing to warm
+        assert!(manager.maybe_refresh().unwrap());
+        assert_eq!(warmer.warmed.lock().unwrap().len(), 1);
+        assert_eq!(replays.load(Ordering::SeqCst), 2);
+
+        let noop: &dyn IndexReaderWarmer<CodecEnum> = &NoOpWarmer;
+        let searcher = manager.acquire().unwrap();
+        for leaf in searcher.reader().leaves() {
+            noop.warm(&*searcher, &leaf).unwrap();
+        }
+    }
+}
@@ -23,16 +23,20 @@ use core::codec::{Codec, Terms};
 use core::codec::{TermIterator, TermState};
 use core::doc::{IndexOptions, Term};
 use core::index::reader::{IndexReader, LeafReaderContext, LeafReaderContextPtr, SearchLeafReader};
+use core::index::{compare_scores, leaf_knn_search};
 use core::search::cache::{
     LRUQueryCache, QueryCache, QueryCachingPolicy, UsageTrackingQueryCachingPolicy,
 };
-use core::search::collector::{self, Collector, ParallelLeafCollector, SearchCollector};
+use core::search::collector::{
+    self, Collector, MergeableCollector, ParallelLeafCollector, SearchCollector,
+};
 use core::search::explanation::Explanation;
 use core::search::query::{ConstantScoreQuery, MatchAllDocsQuery, Query, TermQuery, Weight};
 use core::search::scorer::{BulkScorer, Scorer};
 use core::search::similarity::{
     BM25Similarity, SimScorer, SimWeight, Similarity, SimilarityProducer,
 };
+use core::search::sort_field::{ScoreDoc, ScoreDocHit, TopDocs, TopScoreDocs};
 use core::search::statistics::{CollectionStatistics, TermStatistics};
 use core::search::NO_MORE_DOCS;
 use core::util::external::{DefaultContext, ThreadPool, ThreadPoolBuilder};
@@ -201,6 +205,68 @@ impl SimScorer for NonScoringSimScorer {
     }
 }
 
+/// Tells how the scores of the matching docs are used by a search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreMode {
+    /// Matching docs are collected along with their scores.
+    Complete,
+    /// Matching docs are collected without scores, so that scorers skip reading freqs and
+    /// norms. Collected scores are meaningless.
+    CompleteNoScores,
+    /// Only the scores of the top hits are needed. This is scored as `Complete` for now.
+    TopScores,
+}
+
+impl ScoreMode {
+    pub fn needs_scores(self) -> bool {
+        match self {
+            ScoreMode::Complete | ScoreMode::TopScores => true,
+            ScoreMode::CompleteNoScores => false,
+        }
+    }
+}
+
+// overrides whether the wrapped collector needs scores, see
+// `IndexSearcher::search_with_score_mode`
+struct ScoreModeCollector<'a, S: SearchCollector> {
+    collector: &'a mut S,
+    needs_scores: bool,
+}
+
+impl<'a, S: SearchCollector> Collector for ScoreModeCollector<'a, S> {
+    fn needs_scores(&self) -> bool {
+        self.needs_scores
+    }
+
+    fn collect<SC: Scorer + ?Sized>(&mut self, doc: DocId, scorer: &mut SC) -> Result<()> {
+        self.collector.collect(doc, scorer)
+    }
+}
+
+impl<'a, S: SearchCollector> SearchCollector for ScoreModeCollector<'a, S> {
+    type LC = S::LC;
+
+    fn set_next_reader<C: Codec>(&mut self, reader: &LeafReaderContext<'_, C>) -> Result<()> {
+        self.collector.set_next_reader(reader)
+    }
+
+    fn support_parallel(&self) -> bool {
+        self.collector.support_parallel()
+    }
+
+    fn init_parallel(&mut self) {
+        self.collector.init_parallel()
+    }
+
+    fn leaf_collector<C: Codec>(&self, reader: &LeafReaderContext<'_, C>) -> Result<Self::LC> {
+        self.collector.leaf_collector(reader)
+    }
+
+    fn finish_parallel(&mut self) -> Result<()> {
+        self.collector.finish_parallel()
+    }
+}
+
 /// trait that used for build `Weight` and `Similarity` for `Query`.
 pub trait SearchPlanBuilder<C: Codec> {
     /// num docs of the reader in searcher, same as IndexSearcher::reader()::num_docs()
@@ -209,6 +275,9 @@ pub trait SearchPlanBuilder<C: Codec> {
     /// max doc of the reader in searcher, same as IndexSearcher::reader()::max_doc()
     fn max_doc(&self) -> i32;
 
+    /// leaves of the reader in searcher, same as IndexSearcher::reader()::leaves()
+    fn leaves(&self) -> Vec<LeafReaderContext<'_, C>>;
+
     /// Creates a `Weight` for the given query, potentially adding caching
     /// if possible and configured.
     fn create_weight(&self, query: &dyn Query<C>, needs_scores: bool)
@@ -239,13 +308,100 @@ pub trait IndexSearcher<C: Codec>: SearchPlanBuilder<C> {
     where
         S: SearchCollector;
 
+    /// Like `search`, but with the scores computed as told by `score_mode` instead of as
+    /// asked for by the collector.
+    fn search_with_score_mode<S>(
+        &self,
+        query: &dyn Query<C>,
+        collector: &mut S,
+        score_mode: ScoreMode,
+    ) -> Result<()>
+    where
+        S: SearchCollector,
+    {
+        let mut collector = ScoreModeCollector {
+            collector,
+            needs_scores: score_mode.needs_scores(),
+        };
+        self.search(query, &mut collector)
+    }
+
     fn search_parallel<S>(&self, query: &dyn Query<C>, collector: &mut S) -> Result<()>
     where
         S: SearchCollector;
 
+    /// Searches every leaf as its own task of `pool`, each collecting into a new collector
+    /// from `collector_supplier`, and merges the collectors once all leaves are done.
+    ///
+    /// Unlike `search_parallel`, leaves are not assigned to slices up front, idle threads
+    /// of the pool pick up the next waiting leaf, so a few large leaves don't hold back
+    /// the search.
+    fn search_leaves_parallel<S, F>(
+        &self,
+        query: &dyn Query<C>,
+        collector_supplier: F,
+        pool: &ThreadPool<DefaultContext>,
+    ) -> Result<S::Merged>
+    where
+        S: MergeableCollector + Send + 'static,
+        F: Fn() -> S;
+
     fn count(&self, query: &dyn Query<C>) -> Result<i32>;
 
     fn explain(&self, query: &dyn Query<C>, doc: DocId) -> Result<Explanation>;
+
+    /// Returns the `k` docs whose `DenseVectorField` in `field` is the nearest to
+    /// `query_vector`, searching the HNSW graph of each leaf with a beam of `ef`
+    /// candidates. The leaves with no more than `ef` docs are searched exactly like with
+    /// an `ExactKnnQuery`, so are the leaves written before the graphs were part of the
+    /// vectors format.
+    fn knn_search(
+        &self,
+        field: &str,
+        query_vector: &[f32],
+        k: usize,
+        ef: usize,
+    ) -> Result<TopDocs> {
+        knn_search_leaves(&self.reader().leaves(), field, query_vector, k, ef, None)
+    }
+
+    /// Like `knn_search`, among the docs matching `filter`: the graph walk goes through
+    /// the other docs but skips them as hits, so the more selective the filter the more
+    /// of the graph is explored.
+    fn knn_search_with_filter(
+        &self,
+        field: &str,
+        query_vector: &[f32],
+        k: usize,
+        ef: usize,
+        filter: &dyn Query<C>,
+    ) -> Result<TopDocs> {
+        let weight = self.create_normalized_weight(filter, false)?;
+        let leaves = self.reader().leaves();
+        knn_search_leaves(&leaves, field, query_vector, k, ef, Some(weight.as_ref()))
+    }
+}
+
+// the `k` nearest docs of all the leaves, see `IndexSearcher::knn_search`
+fn knn_search_leaves<C: Codec>(
+    leaves: &[LeafReaderContext<'_, C>],
+    field: &str,
+    query_vector: &[f32],
+    k: usize,
+    ef: usize,
+    filter: Option<&dyn Weight<C>>,
+) -> Result<TopDocs> {
+    let mut hits = vec![];
+    for leaf in leaves {
+        for (doc, score) in leaf_knn_search(leaf, field, query_vector, k, ef, filter)? {
+            hits.push(ScoreDoc::new(doc + leaf.doc_base, score));
+        }
+    }
+    hits.sort_by(|a, b| compare_scores(b.score, a.score).then_with(|| a.doc.cmp(&b.doc)));
+    hits.truncate(k);
+    let total_hits = hits.len();
+    let score_docs = hits.into_iter().map(ScoreDocHit::Score).collect();
+    Ok(TopDocs::Score(TopScoreDocs::new(total_hits, score_docs)))
 }
 
 ///  Implements search over a single IndexReader.
@@ -283,6 +439,9 @@ pub struct DefaultIndexSearcher<
 
     // dismatch next limit to break.
     next_limit: usize,
+
+    // whether to hint the directory to load the query terms' postings before searching.
+    prefetch_postings: bool,
 }
 
 impl<C: Codec, R: IndexReader<Codec = C> + ?Sized, IR: Deref<Target = R>>
@@ -353,15 +512,34 @@ where
         DefaultIndexSearcher {
             reader,
             sim_producer,
-            query_cache: Arc::new(LRUQueryCache::new(1000)),
+            query_cache: Arc::new(LRUQueryCache::new(32 * 1024 * 1024, 1000)),
             cache_policy: Arc::new(UsageTrackingQueryCachingPolicy::default()),
             collection_statistics,
             thread_pool: None,
             leaf_ord_slices: vec![],
             next_limit: next_limit.unwrap_or(DEFAULT_DISMATCH_NEXT_LIMIT),
+            prefetch_postings: false,
         }
     }
 
+    /// Replaces the similarity used to score queries.
+    ///
+    /// Only field lengths are encoded into the norms at index time, the searcher's
+    /// similarity decodes them and applies its own parameters, so e.g. BM25's `k1`
+    /// and `b` can be re-tuned without reindexing.
+    pub fn set_similarity(&mut self, sim_producer: SP) {
+        self.sim_producer = sim_producer;
+    }
+
+    /// Enables prefetching the postings of the query's terms before a search starts.
+    ///
+    /// This issues read-ahead hints (e.g. `madvise(WILLNEED)` for mmap directories) so that
+    /// a cold search doesn't fault in the postings page by page. The query must support
+    /// `Query::extract_terms`.
+    pub fn set_prefetch_postings(&mut self, prefetch_postings: bool) {
+        self.prefetch_postings = prefetch_postings;
+    }
+
     pub fn with_thread_pool(&mut self, num_threads: usize) {
         // at least 2 thread to support parallel
         if num_threads > 1 {
@@ -390,6 +568,51 @@ where
         self.cache_policy = cache_policy;
     }
 
+    fn prefetch_query_postings(&self, query: &dyn Query<C>) -> Result<()> {
+        let term_queries = query.extract_terms();
+        for reader in self.reader.leaves() {
+            for term_query in &term_queries {
+                let term = &term_query.term;
+                if let Some(terms) = reader.reader.terms(&term.field)? {
+                    let mut terms_iter = terms.iterator()?;
+                    if terms_iter.seek_exact(&term.bytes)? {
+                        terms_iter.prefetch_postings()?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // collects the docs of `scorer` in the leaf `reader`, returning whether the
+    // collection timed out
+    fn search_leaf<S: Scorer + ?Sized, T: SearchCollector>(
+        &self,
+        reader: &LeafReaderContext<'_, C>,
+        scorer: &mut S,
+        collector: &mut T,
+    ) -> Result<bool> {
+        // some in running segment maybe wrong, just skip it!
+        // TODO maybe we should matching more specific error type
+        if let Err(e) = collector.set_next_reader(reader) {
+            error!(
+                "set next reader for leaf {} failed!, {:?}",
+                reader.reader.name(),
+                e
+            );
+            return Ok(false);
+        }
+        let live_docs = reader.reader.live_docs();
+
+        match Self::do_search(scorer, collector, live_docs.as_ref(), self.next_limit) {
+            Ok(()) => Ok(false),
+            Err(Error(ErrorKind::Collector(collector::ErrorKind::CollectionTimeout), _)) => {
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     fn do_search<S: Scorer + ?Sized, T: Collector, B: Bits + ?Sized>(
         scorer: &mut S,
         collector: &mut T,
@@ -488,36 +711,29 @@ where
     where
         S: SearchCollector,
     {
+        if self.prefetch_postings {
+            self.prefetch_query_postings(query)?;
+        }
         let weight = self.create_weight(query, collector.needs_scores())?;
+        // a single term is scored through its concrete scorer, without a virtual call
+        // per doc
+        let term_weight = weight.as_term_weight();
 
         for reader in self.reader.leaves() {
-            if let Some(mut scorer) = weight.create_scorer(&reader)? {
-                // some in running segment maybe wrong, just skip it!
-                // TODO maybe we should matching more specific error type
-                if let Err(e) = collector.set_next_reader(&reader) {
-                    error!(
-                        "set next reader for leaf {} failed!, {:?}",
-                        reader.reader.name(),
-                        e
-                    );
-                    continue;
+            let timeout = if let Some(term_weight) = term_weight {
+                match term_weight.create_term_scorer(&reader)? {
+                    Some(mut scorer) => self.search_leaf(&reader, &mut scorer, collector)?,
+                    None => false,
                 }
-                let live_docs = reader.reader.live_docs();
-
-                match Self::do_search(&mut *scorer, collector, live_docs.as_ref(), self.next_limit)
-                {
-                    Ok(()) => {}
-                    Err(Error(
-                        ErrorKind::Collector(collector::ErrorKind::CollectionTimeout),
-                        _,
-                    )) => {
-                        // Collection timeout, we must terminate the search
-                        break;
-                    }
-                    Err(e) => {
-                        return Err(e);
-                    }
+            } else {
+                match weight.create_scorer(&reader)? {
+                    Some(mut scorer) => self.search_leaf(&reader, &mut *scorer, collector)?,
+                    None => false,
                 }
+            };
+            if timeout {
+                // Collection timeout, we must terminate the search
+                break;
             }
         }
 
@@ -531,6 +747,9 @@ where
         if collector.support_parallel() && self.leaf_ord_slices.len() > 1 {
             debug_assert!(self.thread_pool.is_some());
             let thread_pool = self.thread_pool.as_ref().unwrap();
+            if self.prefetch_postings {
+                self.prefetch_query_postings(query)?;
+            }
             let weight = self.create_weight(query, collector.needs_scores())?;
             let leaf_readers = self.reader.leaves();
 
@@ -629,6 +848,87 @@ where
         self.search(query, collector)
     }
 
+    fn search_leaves_parallel<S, F>(
+        &self,
+        query: &dyn Query<C>,
+        collector_supplier: F,
+        pool: &ThreadPool<DefaultContext>,
+    ) -> Result<S::Merged>
+    where
+        S: MergeableCollector + Send + 'static,
+        F: Fn() -> S,
+    {
+        let leaf_readers = self.reader.leaves();
+        let mut collectors: Vec<S> = leaf_readers.iter().map(|_| collector_supplier()).collect();
+        if collectors.is_empty() {
+            return S::merge_collectors(collectors);
+        }
+        if self.prefetch_postings {
+            self.prefetch_query_postings(query)?;
+        }
+        let needs_scores = collectors.iter().any(|c| c.needs_scores());
+        let weight = self.create_weight(query, needs_scores)?;
+
+        let (sender, receiver) = unbounded();
+        let num_leaves = leaf_readers.len();
+        for (leaf_ctx, mut collector) in leaf_readers.iter().zip(collectors.drain(..)) {
+            let w = &weight as *const Box<dyn Weight<C>> as u64;
+            let reader = unsafe { ::std::mem::transmute(leaf_ctx.reader) };
+            let parent = unsafe { ::std::mem::transmute(leaf_ctx.parent) };
+            let leaf_ctx_ptr =
+                LeafReaderContextPtr::new(leaf_ctx.ord, leaf_ctx.doc_base, reader, parent);
+            let sender: Sender<(usize, Result<S>)> = sender.clone();
+            let next_limit = self.next_limit;
+
+            pool.execute(move |_| {
+                let weight = unsafe { &*(w as *const Box<dyn Weight<C>>) };
+                let reader = unsafe { &(*leaf_ctx_ptr.reader) };
+                let parent = unsafe { &(*leaf_ctx_ptr.parent) };
+                let leaf_ctx =
+                    LeafReaderContext::new(parent, reader, leaf_ctx_ptr.ord, leaf_ctx_ptr.doc_base);
+
+                let res: Result<()> = (|| {
+                    if let Some(mut scorer) = weight.create_scorer(&leaf_ctx)? {
+                        collector.set_next_reader(&leaf_ctx)?;
+                        let live_docs = leaf_ctx.reader.live_docs();
+                        match Self::do_search(
+                            scorer.as_mut(),
+                            &mut collector,
+                            live_docs.as_ref(),
+                            next_limit,
+                        ) {
+                            Ok(())
+                            | Err(Error(
+                                ErrorKind::Collector(collector::ErrorKind::CollectionTimeout),
+                                _,
+                            )) => {}
+                            Err(e) => return Err(e),
+                        }
+                    }
+                    Ok(())
+                })();
+                // the receiver only goes away once every task is done
+                let _ = sender.send((leaf_ctx.ord, res.map(|()| collector)));
+            });
+        }
+        drop(sender);
+
+        // wait for all the tasks, they borrow the weight and the leaves
+        let mut results: Vec<(usize, Result<S>)> = receiver.iter().collect();
+        if results.len() != num_leaves {
+            bail!(ErrorKind::IllegalState(format!(
+                "only {} of {} leaves were searched",
+                results.len(),
+                num_leaves
+            )));
+        }
+        results.sort_by_key(|r| r.0);
+        for (_, res) in results {
+            collectors.push(res?);
+        }
+        S::merge_collectors(collectors)
+    }
+
     fn count(&self, query: &dyn Query<C>) -> Result<i32> {
         let mut query = query;
         while let Some(constant_query) = query.as_any().downcast_ref::<ConstantScoreQuery<C>>() {
@@ -639,12 +939,7 @@ where
             return Ok(self.reader().num_docs());
         } else if let Some(term_query) = query.as_any().downcast_ref::<TermQuery>() {
             if !self.reader().has_deletions() {
-                let term = &term_query.term;
-                let mut count = 0;
-                for leaf in self.reader().leaves() {
-                    count += leaf.reader.doc_freq(term)?;
-                }
-                return Ok(count);
+                return self.reader().doc_freq(&term_query.term);
             }
         }
 
@@ -685,6 +980,10 @@ where
         self.reader.max_doc()
     }
 
+    fn leaves(&self) -> Vec<LeafReaderContext<'_, C>> {
+        self.reader.leaves()
+    }
+
     /// Creates a {@link Weight} for the given query, potentially adding caching
     /// if possible and configured.
     fn create_weight(
@@ -871,14 +1170,25 @@ impl ParallelLeafCollector for TotalHitsCountLeafCollector {
 
 #[cfg(test)]
 mod tests {
+    extern crate tempfile;
+
     use super::*;
     use core::codec::tests::TestCodec;
+    use core::codec::CodecEnum;
+    use core::index::merge::{SerialMergeScheduler, TieredMergePolicy};
+    use core::index::reader::StandardDirectoryReader;
     use core::index::tests::*;
+    use core::index::writer::{IndexWriter, IndexWriterConfig};
     use core::search::collector::*;
     use core::search::query::TermQuery;
+    use core::search::sort_field::TopDocs;
     use core::search::tests::*;
+    use core::store::directory::{FSDirectory, MmapDirectory};
+    use core::test_util::{fs_writer, new_fs_writer, text_doc};
     use core::util::DocId;
 
+    use std::path::Path;
+
     struct MockQuery {
         docs: Vec<DocId>,
     }
@@ -950,4 +1260,330 @@ mod tests {
         assert!((score_docs[1].score() - 5f32) < ::std::f32::EPSILON);
         assert!((score_docs[2].score() - 5f32) < ::std::f32::EPSILON);
     }
+
+    struct BM25Producer {
+        k1: f32,
+        b: f32,
+    }
+
+    impl<C: Codec> SimilarityProducer<C> for BM25Producer {
+        fn create(&self, _field: &str) -> Box<dyn Similarity<C>> {
+            Box::new(BM25Similarity::new(self.k1, self.b))
+        }
+    }
+
+    #[test]
+    fn test_set_similarity_rescores_without_reindexing() {
+        let (_dir, writer) = new_fs_writer();
+        // a short doc matching once and a long doc matching three times
+        writer
+            .add_document(text_doc("body", "a", IndexOptions::DocsAndFreqs))
+            .unwrap();
+        writer
+            .add_document(text_doc(
+                "body",
+                "a a a x x x x x x x",
+                IndexOptions::DocsAndFreqs,
+            ))
+            .unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let producer = BM25Producer { k1: 1.2, b: 0.0 };
+        let mut searcher = DefaultIndexSearcher::with_similarity(Arc::new(reader), producer, None);
+        let query = TermQuery::new(Term::new("body".into(), b"a".to_vec()), 1.0, None);
+        let ranking = |searcher: &DefaultIndexSearcher<_, _, _, BM25Producer>| {
+            let mut collector = TopDocsCollector::new(10);
+            searcher.search(&query, &mut collector).unwrap();
+            let top_docs = collector.top_docs();
+            let docs: Vec<DocId> = top_docs.score_docs().iter().map(|d| d.doc_id()).collect();
+            docs
+        };
+
+        // without length normalization the higher term frequency wins
+        assert_eq!(ranking(&searcher), vec![1, 0]);
+
+        // full length normalization on the same index favors the short doc
+        searcher.set_similarity(BM25Producer { k1: 1.2, b: 1.0 });
+        assert_eq!(ranking(&searcher), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_search_without_scores_skips_freqs() {
+        use core::codec::postings::BLOCK_SIZE;
+        use core::store::directory::MockDirectory;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = Arc::new(IndexWriterConfig::default());
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let writer = IndexWriter::new(Arc::clone(&directory), config).unwrap();
+        // two full packed blocks of matching docs, then a doc not matching
+        for _ in 0..BLOCK_SIZE * 2 {
+            writer
+                .add_document(text_doc("body", "a a b", IndexOptions::DocsAndFreqs))
+                .unwrap();
+        }
+        writer
+            .add_document(text_doc("body", "b", IndexOptions::DocsAndFreqs))
+            .unwrap();
+        writer.commit().unwrap();
+
+        type MockReader = StandardDirectoryReader<
+            MockDirectory<FSDirectory>,
+            CodecEnum,
+            SerialMergeScheduler,
+            TieredMergePolicy,
+        >;
+        let mock_dir = Arc::new(MockDirectory::new(directory));
+        let reader = MockReader::open(Arc::clone(&mock_dir)).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let term_query = || TermQuery::new(Term::new("body".into(), b"a".to_vec()), 1.0, None);
+        // the docs found and the bytes of the postings file read to find them
+        let search = |query: &dyn Query<CodecEnum>, score_mode: ScoreMode| {
+            let before = mock_dir.bytes_read("doc");
+            let mut collector = TopDocsCollector::new(1000);
+            searcher
+                .search_with_score_mode(query, &mut collector, score_mode)
+                .unwrap();
+            let mut docs: Vec<DocId> = collector
+                .top_docs()
+                .score_docs()
+                .iter()
+                .map(|d| d.doc_id())
+                .collect();
+            docs.sort();
+            (docs, mock_dir.bytes_read("doc") - before)
+        };
+
+        let expected: Vec<DocId> = (0..BLOCK_SIZE * 2).collect();
+        let (docs, no_scores) = search(&term_query(), ScoreMode::CompleteNoScores);
+        assert_eq!(docs, expected);
+        let (docs, scores) = search(&term_query(), ScoreMode::Complete);
+        assert_eq!(docs, expected);
+        // the packed freq blocks are skipped without scores
+        assert!(no_scores < scores, "{} >= {}", no_scores, scores);
+
+        // a constant score query never reads the freqs of its sub query
+        let query = ConstantScoreQuery::new(Box::new(term_query()));
+        let (docs, constant) = search(&query, ScoreMode::Complete);
+        assert_eq!(docs, expected);
+        assert_eq!(constant, no_scores);
+
+        assert!(!ScoreMode::CompleteNoScores.needs_scores());
+        assert!(ScoreMode::TopScores.needs_scores());
+    }
+
+    fn build_segmented_index(path: &Path, num_segments: usize, docs_per_segment: usize) {
+        let mut config = IndexWriterConfig::default();
+        // keep all the segments apart
+        config.merge_policy.set_segs_per_tier(1000.0).unwrap();
+        let writer = fs_writer(path, Arc::new(config));
+        for segment in 0..num_segments {
+            for i in 0..docs_per_segment {
+                let text = format!("common w{} s{}", i % 3, segment % 4);
+                writer
+                    .add_document(text_doc("body", &text, IndexOptions::DocsAndFreqs))
+                    .unwrap();
+            }
+            writer.commit().unwrap();
+        }
+    }
+
+    fn search_pool(num_threads: usize) -> ThreadPool<DefaultContext> {
+        ThreadPoolBuilder::with_default_factory("search".into())
+            .thread_count(num_threads)
+            .build()
+    }
+
+    #[test]
+    fn test_search_leaves_parallel() {
+        let dir = tempfile::tempdir().unwrap();
+        build_segmented_index(dir.path(), 6, 20);
+        let reader = MmapReader::open(Arc::new(MmapDirectory::new(&dir.path()).unwrap())).unwrap();
+        assert_eq!(reader.leaves().len(), 6);
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let pool = search_pool(3);
+
+        for text in &[&b"w1"[..], &b"s2"[..], &b"missing"[..]] {
+            let query = TermQuery::new(Term::new("body".into(), text.to_vec()), 1.0, None);
+            // room for all the hits, so docs with the same score can't be picked differently
+            let mut collector = TopDocsCollector::new(200);
+            searcher.search(&query, &mut collector).unwrap();
+            let expected = collector.top_docs();
+
+            let merged = searcher
+                .search_leaves_parallel(&query, || TopDocsCollector::new(200), &pool)
+                .unwrap();
+            assert_eq!(merged.total_hits(), expected.total_hits());
+            let docs = |top_docs: &TopDocs| -> Vec<(DocId, u32)> {
+                let mut docs: Vec<(DocId, u32)> = top_docs
+                    .score_docs()
+                    .iter()
+                    .map(|d| (d.doc_id(), d.score().to_bits()))
+                    .collect();
+                docs.sort();
+                docs
+            };
+            assert_eq!(docs(&merged), docs(&expected));
+        }
+    }
+
+    // hides the `TermWeight` of a `TermQuery`, so that it is scored through a boxed scorer
+    struct BoxedScorerQuery(TermQuery);
+
+    impl<C: Codec> Query<C> for BoxedScorerQuery {
+        fn create_weight(
+            &self,
+            searcher: &dyn SearchPlanBuilder<C>,
+            needs_scores: bool,
+        ) -> Result<Box<dyn Weight<C>>> {
+            let weight = self.0.create_weight(searcher, needs_scores)?;
+            Ok(Box::new(BoxedScorerWeight(weight)))
+        }
+
+        fn extract_terms(&self) -> Vec<TermQuery> {
+            Query::<C>::extract_terms(&self.0)
+        }
+
+        fn as_any(&self) -> &dyn (::std::any::Any) {
+            self
+        }
+    }
+
+    impl fmt::Display for BoxedScorerQuery {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "BoxedScorerQuery({})", self.0)
+        }
+    }
+
+    struct BoxedScorerWeight<C: Codec>(Box<dyn Weight<C>>);
+
+    impl<C: Codec> Weight<C> for BoxedScorerWeight<C> {
+        fn create_scorer(
+            &self,
+            reader: &LeafReaderContext<'_, C>,
+        ) -> Result<Option<Box<dyn Scorer>>> {
+            self.0.create_scorer(reader)
+        }
+
+        fn query_type(&self) -> &'static str {
+            self.0.query_type()
+        }
+
+        fn normalize(&mut self, norm: f32, boost: f32) {
+            self.0.normalize(norm, boost)
+        }
+
+        fn value_for_normalization(&self) -> f32 {
+            self.0.value_for_normalization()
+        }
+
+        fn needs_scores(&self) -> bool {
+            self.0.needs_scores()
+        }
+
+        fn explain(&self, reader: &LeafReaderContext<'_, C>, doc: DocId) -> Result<Explanation> {
+            self.0.explain(reader, doc)
+        }
+    }
+
+    impl<C: Codec> fmt::Display for BoxedScorerWeight<C> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "BoxedScorerWeight({})", self.0)
+        }
+    }
+
+    fn score_docs(top_docs: &TopDocs) -> Vec<(DocId, u32)> {
+        top_docs
+            .score_docs()
+            .iter()
+            .map(|d| (d.doc_id(), d.score().to_bits()))
+            .collect()
+    }
+
+    #[test]
+    fn test_term_scorer_fast_path() {
+        let dir = tempfile::tempdir().unwrap();
+        build_segmented_index(dir.path(), 4, 300);
+        let reader = MmapReader::open(Arc::new(MmapDirectory::new(&dir.path()).unwrap())).unwrap();
+        let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+
+        for text in &[&b"common"[..], &b"w1"[..], &b"s2"[..], &b"missing"[..]] {
+            let term_query = TermQuery::new(Term::new("body".into(), text.to_vec()), 1.0, None);
+            let weight = searcher.create_weight(&term_query, true).unwrap();
+            assert!(weight.as_term_weight().is_some());
+            let mut collector = TopDocsCollector::new(50);
+            searcher.search(&term_query, &mut collector).unwrap();
+            let fast = collector.top_docs();
+
+            let boxed_query = BoxedScorerQuery(term_query);
+            let weight = searcher.create_weight(&boxed_query, true).unwrap();
+            assert!(weight.as_term_weight().is_none());
+            let mut collector = TopDocsCollector::new(50);
+            searcher.search(&boxed_query, &mut collector).unwrap();
+            let boxed = collector.top_docs();
+
+            assert_eq!(fast.total_hits(), boxed.total_hits());
+            assert_eq!(score_docs(&fast), score_docs(&boxed));
+        }
+    }
+
+    type MmapReader =
+        StandardDirectoryReader<MmapDirectory, CodecEnum, SerialMergeScheduler, TieredMergePolicy>;
+
+    fn build_text_index(path: &Path, docs: impl Iterator<Item = String>) {
+        let config = Arc::new(IndexWriterConfig::default());
+        let directory = Arc::new(MmapDirectory::new(&path).unwrap());
+        let writer = IndexWriter::new(directory, config).unwrap();
+        for text in docs {
+            writer
+                .add_document(text_doc("body", &text, IndexOptions::DocsAndFreqs))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+    }
+
+    fn hits<C: Codec, S: IndexSearcher<C>>(searcher: &S, text: &[u8]) -> Vec<DocId> {
+        let query = TermQuery::new(Term::new("body".into(), text.to_vec()), 1.0, None);
+        let mut collector = TopDocsCollector::new(100);
+        searcher.search(&query, &mut collector).unwrap();
+        let mut docs: Vec<DocId> = collector
+            .top_docs()
+            .score_docs()
+            .iter()
+            .map(|d| d.doc_id())
+            .collect();
+        docs.sort();
+        docs
+    }
+
+    #[test]
+    fn test_prefetch_postings_keeps_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = (0..50).map(|i| {
+            let extra = if i == 7 { "once" } else { "" };
+            format!("common w{} {}", i % 5, extra)
+        });
+        build_text_index(dir.path(), docs);
+
+        let directory = Arc::new(MmapDirectory::new(&dir.path()).unwrap());
+        let reader = MmapReader::open(directory).unwrap();
+        let mut searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+        let expected: Vec<Vec<DocId>> = [&b"common"[..], b"w3", b"once", b"missing"]
+            .iter()
+            .map(|t| hits(&searcher, t))
+            .collect();
+        assert_eq!(expected[0].len(), 50);
+        assert_eq!(expected[1].len(), 10);
+        // a singleton term is inlined into the terms dict and has nothing to prefetch
+        assert_eq!(expected[2], vec![7]);
+        assert!(expected[3].is_empty());
+
+        searcher.set_prefetch_postings(true);
+        let prefetched: Vec<Vec<DocId>> = [&b"common"[..], b"w3", b"once", b"missing"]
+            .iter()
+            .map(|t| hits(&searcher, t))
+            .collect();
+        assert_eq!(prefetched, expected);
+    }
 }
@@ -0,0 +1,191 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use error::{ErrorKind::AlreadyClosed, Result};
+
+/// A searcher handed out by `SearcherLifetimeManager`, along with the version of the
+/// reader it searches.
+pub struct ManagedSearcher<S> {
+    pub searcher: Arc<S>,
+    pub version: i64,
+}
+
+struct TrackedSearcher<S> {
+    searcher: Arc<S>,
+    record_time: Instant,
+}
+
+/// Keeps track of the searchers of several reader generations, so that follow up
+/// requests of a user (e.g. the next page of results) keep searching the same point in
+/// time while newer searchers serve new requests.
+///
+/// Searchers are recorded with the version of their reader and can then be acquired back
+/// by version until they are pruned. A searcher is reference counted, pruning only drops
+/// the reference held by the manager, so searches still using it are not affected.
+pub struct SearcherLifetimeManager<S> {
+    searchers: RwLock<HashMap<i64, TrackedSearcher<S>>>,
+    closed: AtomicBool,
+}
+
+impl<S> Default for SearcherLifetimeManager<S> {
+    fn default() -> Self {
+        SearcherLifetimeManager {
+            searchers: RwLock::new(HashMap::new()),
+            closed: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<S> SearcherLifetimeManager<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn ensure_open(&self) -> Result<()> {
+        if self.closed.load(Ordering::Acquire) {
+            bail!(AlreadyClosed(
+                "this SearcherLifetimeManager is closed".into()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Records a searcher over the reader of `version` and returns the version. Recording
+    /// the same version again keeps the searcher recorded first.
+    pub fn record(&self, searcher: Arc<S>, version: i64) -> Result<i64> {
+        self.ensure_open()?;
+        self.searchers
+            .write()?
+            .entry(version)
+            .or_insert_with(|| TrackedSearcher {
+                searcher,
+                record_time: Instant::now(),
+            });
+        Ok(version)
+    }
+
+    /// Returns the searcher recorded for `version`, or `None` if it was pruned or never
+    /// recorded, in which case the request should be replayed on a current searcher.
+    pub fn acquire(&self, version: i64) -> Result<Option<ManagedSearcher<S>>> {
+        self.ensure_open()?;
+        Ok(self
+            .searchers
+            .read()?
+            .get(&version)
+            .map(|tracked| ManagedSearcher {
+                searcher: Arc::clone(&tracked.searcher),
+                version,
+            }))
+    }
+
+    /// The number of searchers currently tracked.
+    pub fn len(&self) -> Result<usize> {
+        Ok(self.searchers.read()?.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Drops the searchers that were replaced by a newer one more than `max_stale_sec`
+    /// seconds ago, the newest searcher is always kept. Returns the number of searchers
+    /// pruned.
+    pub fn prune(&self, max_stale_sec: u64) -> Result<usize> {
+        self.prune_at(Instant::now(), Duration::from_secs(max_stale_sec))
+    }
+
+    fn prune_at(&self, now: Instant, max_stale: Duration) -> Result<usize> {
+        let mut searchers = self.searchers.write()?;
+        let mut versions: Vec<(i64, Instant)> = searchers
+            .iter()
+            .map(|(version, tracked)| (*version, tracked.record_time))
+            .collect();
+        versions.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let mut pruned = 0;
+        // a searcher is stale since the next newer one was recorded
+        for pair in versions.windows(2) {
+            let (newer, stale) = (pair[0], pair[1]);
+            if now.duration_since(newer.1) > max_stale {
+                searchers.remove(&stale.0);
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Drops all tracked searchers, any later `record` or `acquire` fails.
+    pub fn close(&self) -> Result<()> {
+        self.closed.store(true, Ordering::Release);
+        self.searchers.write()?.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_acquire() {
+        let manager = SearcherLifetimeManager::new();
+        let first = Arc::new("first");
+        assert_eq!(manager.record(Arc::clone(&first), 1).unwrap(), 1);
+        manager.record(Arc::new("second"), 2).unwrap();
+        // recording a version twice keeps the first searcher
+        manager.record(Arc::new("again"), 1).unwrap();
+        assert_eq!(manager.len().unwrap(), 2);
+
+        let managed = manager.acquire(1).unwrap().unwrap();
+        assert_eq!(managed.version, 1);
+        assert!(Arc::ptr_eq(&managed.searcher, &first));
+        assert_eq!(*manager.acquire(2).unwrap().unwrap().searcher, "second");
+        assert!(manager.acquire(3).unwrap().is_none());
+
+        manager.close().unwrap();
+        assert!(manager.acquire(1).is_err());
+        assert!(manager.record(Arc::new("third"), 3).is_err());
+        // searchers in use outlive the manager
+        assert_eq!(*managed.searcher, "first");
+    }
+
+    #[test]
+    fn test_prune_stale_searchers() {
+        let manager = SearcherLifetimeManager::new();
+        for version in 1..=3 {
+            manager.record(Arc::new(version), version).unwrap();
+        }
+        let in_use = manager.acquire(1).unwrap().unwrap();
+
+        // nothing has been replaced for long enough
+        assert_eq!(manager.prune(60).unwrap(), 0);
+        assert_eq!(manager.len().unwrap(), 3);
+
+        // everything but the newest searcher was replaced more than a minute ago
+        let later = Instant::now() + Duration::from_secs(61);
+        assert_eq!(manager.prune_at(later, Duration::from_secs(60)).unwrap(), 2);
+        assert_eq!(manager.len().unwrap(), 1);
+        assert!(manager.acquire(1).unwrap().is_none());
+        assert!(manager.acquire(2).unwrap().is_none());
+        assert_eq!(*manager.acquire(3).unwrap().unwrap().searcher, 3);
+
+        // the pruned searcher is still usable by its holder
+        assert_eq!(Arc::strong_count(&in_use.searcher), 1);
+        assert_eq!(*in_use.searcher, 1);
+    }
+}
@@ -69,7 +69,7 @@ impl BM25Similarity {
     /// The default implementation computes the average as sumTotalTermFreq / docCount,
     /// or returns 1 if the index does not store sumTotalTermFreq:
     /// any field that omits frequency information).
-    fn avg_field_length(collection_stats: &CollectionStatistics) -> f32 {
+    pub(crate) fn avg_field_length(collection_stats: &CollectionStatistics) -> f32 {
         let sum_total_term_freq = collection_stats.sum_total_term_freq;
         if sum_total_term_freq <= 0 {
             1f32
@@ -83,8 +83,7 @@ impl BM25Similarity {
     }
 
     pub fn compute_norm(state: &FieldInvertState) -> i64 {
-        let num_terms = state.length - state.num_overlap;
-        BM25Similarity::encode_norm_value(state.boost, num_terms) as i64
+        BM25Similarity::encode_norm_value(state.boost, state.field_length()) as i64
     }
 
     pub fn encode_norm_value(boost: f32, field_length: i32) -> u8 {
@@ -96,7 +95,23 @@ impl BM25Similarity {
         NORM_TABLE[b]
     }
 
-    fn idf(term_stats: &[TermStatistics], collection_stats: &CollectionStatistics) -> f32 {
+    /// Decodes the field length of a norm written by `compute_norm`.
+    pub fn decode_field_length(norm: i64) -> f32 {
+        BM25Similarity::decode_norm_value((norm & 0xFF) as usize)
+    }
+
+    /// The score of a term given its frequency and the length of the doc, for the callers
+    /// computing them themselves, e.g. over several fields. `weight` is the idf of the
+    /// term times its boost.
+    pub fn score_with_length(&self, weight: f32, freq: f32, length: f32, avg_length: f32) -> f32 {
+        weight * (self.k1 + 1.0) * freq
+            / (freq + self.k1 * ((1.0 - self.b) + self.b * length / avg_length))
+    }
+
+    pub(crate) fn idf(
+        term_stats: &[TermStatistics],
+        collection_stats: &CollectionStatistics,
+    ) -> f32 {
         let mut idf = 0.0f32;
         let doc_count = if collection_stats.doc_count == -1 {
             collection_stats.max_doc
@@ -393,8 +408,98 @@ impl<C: Codec> SimWeight<C> for BM25SimWeight {
 
 #[cfg(test)]
 mod tests {
+
     use super::*;
+    use core::analysis::{Token, TokenStream};
+    use core::doc::{Field, FieldType, Fieldable, IndexOptions};
+    use core::index::reader::{IndexReader, LeafReader};
     use core::index::tests::MockLeafReader;
+    use core::test_util::new_fs_writer;
+
+    /// emits the given `(term, position_increment)` pairs
+    #[derive(Debug)]
+    struct OverlapTokenStream {
+        token: Token,
+        tokens: Vec<(&'static str, usize)>,
+        index: usize,
+    }
+
+    impl OverlapTokenStream {
+        fn new(tokens: Vec<(&'static str, usize)>) -> Self {
+            OverlapTokenStream {
+                token: Token::new(),
+                tokens,
+                index: 0,
+            }
+        }
+    }
+
+    impl TokenStream for OverlapTokenStream {
+        fn next_token(&mut self) -> Result<bool> {
+            if self.index >= self.tokens.len() {
+                return Ok(false);
+            }
+            self.clear_token();
+            let (term, position) = self.tokens[self.index];
+            self.token.term = term.as_bytes().to_vec();
+            self.token.position = position;
+            self.index += 1;
+            Ok(true)
+        }
+
+        fn end(&mut self) -> Result<()> {
+            self.end_token();
+            Ok(())
+        }
+
+        fn reset(&mut self) -> Result<()> {
+            self.index = 0;
+            Ok(())
+        }
+
+        fn token(&self) -> &Token {
+            &self.token
+        }
+
+        fn token_mut(&mut self) -> &mut Token {
+            &mut self.token
+        }
+    }
+
+    fn index_norm_with_synonym(discount_overlaps: bool) -> i64 {
+        let (_dir, writer) = new_fs_writer();
+
+        let mut field_type = FieldType::default();
+        field_type.index_options = IndexOptions::DocsAndFreqsAndPositions;
+        field_type.set_discount_overlaps(discount_overlaps);
+        // the synonym "newyork" is injected at the same position as "ny"
+        let tokens = OverlapTokenStream::new(vec![("ny", 1), ("newyork", 0), ("times", 1)]);
+        let field = Field::new("body".into(), field_type, None, Some(Box::new(tokens)));
+        let doc: Vec<Box<dyn Fieldable>> = vec![Box::new(field)];
+        writer.add_document(doc).unwrap();
+        writer.commit().unwrap();
+
+        let reader = writer.get_reader(true, false).unwrap();
+        let leaves = reader.leaves();
+        let norms = leaves[0].reader.norm_values("body").unwrap().unwrap();
+        norms.get(0).unwrap()
+    }
+
+    #[test]
+    fn test_field_length_with_overlap_tokens() {
+        let discounted = index_norm_with_synonym(true);
+        assert_eq!(
+            discounted,
+            i64::from(BM25Similarity::encode_norm_value(1.0, 2))
+        );
+
+        let counted = index_norm_with_synonym(false);
+        assert_eq!(
+            counted,
+            i64::from(BM25Similarity::encode_norm_value(1.0, 3))
+        );
+        assert_ne!(discounted, counted);
+    }
 
     // copy from Lucene TestBM25Similarity
     #[test]
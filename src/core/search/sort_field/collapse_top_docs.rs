@@ -11,22 +11,55 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::search::sort_field::SortField;
+use core::search::sort_field::{Sort, SortField, SortFieldType};
 use core::util::DocId;
-use core::util::VariantValue;
+use core::util::{tagged_variant_values, VariantValue};
+use error::{ErrorKind::IllegalArgument, Result};
 use std::cmp::{Ord, Ordering};
 use std::f32;
 
+/// `max_score` is `NaN` when scores were not tracked, which most formats (e.g. JSON)
+/// cannot represent, so it is (de)serialized as an optional value.
+mod max_score_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::f32;
+
+    pub fn serialize<S: Serializer>(
+        max_score: &f32,
+        serializer: S,
+    ) -> ::std::result::Result<S::Ok, S::Error> {
+        let value = if max_score.is_nan() {
+            None
+        } else {
+            Some(*max_score)
+        };
+        value.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> ::std::result::Result<f32, D::Error> {
+        Ok(Option::deserialize(deserializer)?.unwrap_or(f32::NAN))
+    }
+}
+
 /// Holds one hit in `TopDocs`
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ScoreDoc {
     pub doc: DocId,
     pub score: f32,
+    /// The shard this hit comes from, set by `TopDocs::merge`.
+    #[serde(default)]
+    pub shard_index: usize,
 }
 
 impl ScoreDoc {
     pub fn new(doc: DocId, score: f32) -> ScoreDoc {
-        ScoreDoc { doc, score }
+        ScoreDoc {
+            doc,
+            score,
+            shard_index: 0,
+        }
     }
 
     pub fn reset(&mut self, doc: DocId, score: f32) {
@@ -81,7 +114,7 @@ impl Eq for ScoreDoc {}
 ///
 /// @see ScoreDoc
 /// @see TopFieldDocs
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FieldDoc {
     pub doc: DocId,
     pub score: f32,
@@ -93,6 +126,7 @@ pub struct FieldDoc {
     /// FieldComparator used to sort this field.
     /// @see Sort
     /// @see IndexSearcher#search(Query,int,Sort)
+    #[serde(with = "tagged_variant_values")]
     pub fields: Vec<VariantValue>,
 }
 
@@ -130,7 +164,7 @@ impl PartialOrd for FieldDoc {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ScoreDocHit {
     Score(ScoreDoc),
     Field(FieldDoc),
@@ -158,6 +192,20 @@ impl ScoreDocHit {
         }
     }
 
+    pub fn shard_index(&self) -> usize {
+        match *self {
+            ScoreDocHit::Score(ref s) => s.shard_index,
+            ScoreDocHit::Field(ref f) => f.shard_index,
+        }
+    }
+
+    pub fn set_shard_index(&mut self, shard_index: usize) {
+        match *self {
+            ScoreDocHit::Score(ref mut s) => s.shard_index = shard_index,
+            ScoreDocHit::Field(ref mut f) => f.shard_index = shard_index,
+        }
+    }
+
     pub fn order_by_doc(d1: &ScoreDocHit, d2: &ScoreDocHit) -> Ordering {
         if d1.doc_id() < d2.doc_id() {
             Ordering::Less
@@ -202,7 +250,7 @@ impl PartialOrd for ScoreDocHit {
 }
 
 /// Represents hits returned by `IndexSearcher::search`
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TopScoreDocs {
     /// The total number of hits for the query.
     pub total_hits: usize,
@@ -211,6 +259,7 @@ pub struct TopScoreDocs {
     pub score_docs: Vec<ScoreDocHit>,
 
     /// Stores the maximum score value encountered, needed for normalizing.
+    #[serde(with = "max_score_serde")]
     max_score: f32,
 }
 
@@ -228,14 +277,16 @@ impl TopScoreDocs {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct TopFieldDocs {
     pub total_hits: usize,
     pub score_docs: Vec<ScoreDocHit>,
+    #[serde(with = "max_score_serde")]
     pub max_score: f32,
     pub fields: Vec<SortField>,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct CollapseTopFieldDocs {
     /// The total number of hits for the query.
     pub total_hits: usize,
@@ -247,6 +298,7 @@ pub struct CollapseTopFieldDocs {
     pub score_docs: Vec<ScoreDocHit>,
 
     /// Stores the maximum score value encountered, needed for normalizing.
+    #[serde(with = "max_score_serde")]
     max_score: f32,
 
     /// The fields which were used to sort results by.
@@ -256,6 +308,7 @@ pub struct CollapseTopFieldDocs {
     pub field: String,
 
     /// The collapse value for each top doc
+    #[serde(with = "tagged_variant_values")]
     pub collapse_values: Vec<VariantValue>,
 }
 
@@ -285,6 +338,7 @@ impl CollapseTopFieldDocs {
     }
 }
 
+#[derive(Serialize, Deserialize)]
 pub enum TopDocs {
     Score(TopScoreDocs),
     Field(TopFieldDocs),
@@ -323,4 +377,349 @@ impl TopDocs {
             TopDocs::Collapse(ref mut c) => &mut c.score_docs,
         }
     }
+
+    pub fn max_score(&self) -> f32 {
+        match *self {
+            TopDocs::Score(ref s) => s.max_score,
+            TopDocs::Field(ref f) => f.max_score,
+            TopDocs::Collapse(ref c) => c.max_score,
+        }
+    }
+
+    /// Merges the top hits of queries that were run separately against several shards
+    /// into the global `top_n` hits.
+    ///
+    /// Hits are ordered by descending score when `sort` is `None`, otherwise by the sort
+    /// values of their `FieldDoc`, which must have been collected with the same `sort`.
+    /// Every merged hit is tagged with the index of its shard in `shard_results`. Ties
+    /// are broken by shard index and then by the hit's rank within its shard, so the
+    /// merge is stable.
+    pub fn merge(sort: Option<&Sort>, top_n: usize, shard_results: &[TopDocs]) -> Result<TopDocs> {
+        let mut total_hits = 0;
+        let mut max_score = f32::NAN;
+        for shard in shard_results {
+            if let TopDocs::Collapse(_) = *shard {
+                bail!(IllegalArgument("collapsed top docs can't be merged".into()));
+            }
+            if let Some(sort) = sort {
+                for hit in shard.score_docs() {
+                    match *hit {
+                        ScoreDocHit::Field(ref f) if f.fields.len() == sort.get_sort().len() => {}
+                        _ => bail!(IllegalArgument(
+                            "hits to merge by sort must all hold its sort values".into()
+                        )),
+                    }
+                }
+            }
+            total_hits += shard.total_hits();
+            let shard_max = shard.max_score();
+            if max_score.is_nan() || shard_max > max_score {
+                max_score = shard_max;
+            }
+        }
+
+        let mut score_docs = Vec::with_capacity(top_n);
+        // the next hit to take from every shard
+        let mut heads = vec![0usize; shard_results.len()];
+        while score_docs.len() < top_n {
+            let mut best: Option<usize> = None;
+            for (i, shard) in shard_results.iter().enumerate() {
+                if heads[i] >= shard.score_docs().len() {
+                    continue;
+                }
+                let hit = &shard.score_docs()[heads[i]];
+                let better = match best {
+                    Some(b) => {
+                        let best_hit = &shard_results[b].score_docs()[heads[b]];
+                        Self::compare_hits(sort, hit, best_hit) == Ordering::Less
+                    }
+                    None => true,
+                };
+                if better {
+                    best = Some(i);
+                }
+            }
+            match best {
+                Some(b) => {
+                    let mut hit = shard_results[b].score_docs()[heads[b]].clone();
+                    hit.set_shard_index(b);
+                    score_docs.push(hit);
+                    heads[b] += 1;
+                }
+                None => break,
+            }
+        }
+
+        Ok(match sort {
+            Some(sort) => TopDocs::Field(TopFieldDocs {
+                total_hits,
+                score_docs,
+                max_score,
+                fields: sort.get_sort().to_vec(),
+            }),
+            None => {
+                let mut top_docs = TopScoreDocs::new(total_hits, score_docs);
+                top_docs.max_score = max_score;
+                TopDocs::Score(top_docs)
+            }
+        })
+    }
+
+    // `Less` means `h1` ranks before `h2`
+    fn compare_hits(sort: Option<&Sort>, h1: &ScoreDocHit, h2: &ScoreDocHit) -> Ordering {
+        match (sort, h1, h2) {
+            (Some(sort), &ScoreDocHit::Field(ref f1), &ScoreDocHit::Field(ref f2)) => {
+                for (i, sort_field) in sort.get_sort().iter().enumerate() {
+                    let ord = if sort_field.field_type() == SortFieldType::Score {
+                        // relevance sorts by descending score
+                        f2.fields[i].cmp(&f1.fields[i])
+                    } else {
+                        f1.fields[i].cmp(&f2.fields[i])
+                    };
+                    let ord = if sort_field.is_reverse() {
+                        ord.reverse()
+                    } else {
+                        ord
+                    };
+                    if ord != Ordering::Equal {
+                        return ord;
+                    }
+                }
+                Ordering::Equal
+            }
+            _ => h2
+                .score()
+                .partial_cmp(&h1.score())
+                .unwrap_or(Ordering::Equal),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::codec::Codec;
+    use core::doc::{Fieldable, FloatDocValuesField};
+    use core::index::writer::IndexWriterConfig;
+    use core::search::collector::TopDocsCollector;
+    use core::search::query::{FunctionScoreQuery, MatchAllDocsQuery, Query};
+    use core::search::sort_field::{SimpleSortField, Sort, SortFieldType};
+    use core::search::{DefaultIndexSearcher, IndexSearcher};
+    use core::test_util::fs_writer;
+
+    use serde_json;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_top_docs_serde_round_trip() {
+        let hits = vec![
+            ScoreDocHit::Score(ScoreDoc::new(3, 1.5)),
+            ScoreDocHit::Score(ScoreDoc::new(7, 0.5)),
+        ];
+        let top_docs = TopDocs::Score(TopScoreDocs::new(10, hits));
+        let json = serde_json::to_string(&top_docs).unwrap();
+        let decoded: TopDocs = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.total_hits(), 10);
+        assert_eq!(decoded.score_docs().len(), 2);
+        assert_eq!(decoded.score_docs()[0].doc_id(), 3);
+        assert!((decoded.score_docs()[0].score() - 1.5).abs() < f32::EPSILON);
+        assert_eq!(decoded.score_docs()[1].doc_id(), 7);
+        // scores were not tracked for max_score, it must survive as NaN
+        if let TopDocs::Score(ref s) = decoded {
+            assert!(s.max_score.is_nan());
+        }
+
+        let sort_field = SortField::Simple(SimpleSortField::new(
+            "price".into(),
+            SortFieldType::Long,
+            true,
+        ));
+        let hits = vec![ScoreDocHit::Field(FieldDoc::new(
+            2,
+            0.25,
+            vec![VariantValue::Long(42), VariantValue::VString("a".into())],
+        ))];
+        let top_docs = TopDocs::Field(TopFieldDocs {
+            total_hits: 1,
+            score_docs: hits,
+            max_score: 0.25,
+            fields: vec![sort_field.clone()],
+        });
+        let json = serde_json::to_string(&top_docs).unwrap();
+        let decoded: TopDocs = serde_json::from_str(&json).unwrap();
+        match decoded {
+            TopDocs::Field(ref f) => {
+                assert_eq!(f.fields, vec![sort_field]);
+                assert!((f.max_score - 0.25).abs() < f32::EPSILON);
+                match f.score_docs[0] {
+                    ScoreDocHit::Field(ref d) => {
+                        assert_eq!(d.doc, 2);
+                        assert_eq!(
+                            format!("{:?}", d.fields),
+                            format!(
+                                "{:?}",
+                                vec![VariantValue::Long(42), VariantValue::VString("a".into())]
+                            )
+                        );
+                    }
+                    _ => panic!("expected a field doc"),
+                }
+            }
+            _ => panic!("expected field top docs"),
+        }
+    }
+
+    fn shard_hits(hits: &[(DocId, f32)]) -> TopDocs {
+        let score_docs = hits
+            .iter()
+            .map(|&(doc, score)| ScoreDocHit::Score(ScoreDoc::new(doc, score)))
+            .collect();
+        TopDocs::Score(TopScoreDocs::new(hits.len() * 2, score_docs))
+    }
+
+    #[test]
+    fn test_merge_by_score() {
+        // all hits of the "union", as (shard, doc, score)
+        let shards: Vec<Vec<(DocId, f32)>> = vec![
+            vec![(0, 9.0), (1, 7.5), (2, 3.0), (3, 2.0), (4, 1.0), (5, 0.5)],
+            vec![(0, 8.0), (1, 7.5), (2, 7.0), (3, 6.0), (4, 0.1)],
+            vec![(0, 7.5), (1, 4.0), (2, 3.5), (3, 3.0), (4, 2.5), (5, 2.0)],
+        ];
+        let mut union = Vec::new();
+        for (shard, hits) in shards.iter().enumerate() {
+            for &(doc, score) in hits {
+                union.push((shard, doc, score));
+            }
+        }
+        union.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap().then(a.0.cmp(&b.0)));
+        let expected: Vec<(usize, DocId)> = union.iter().take(5).map(|h| (h.0, h.1)).collect();
+
+        // each shard only reports its own top 5
+        let shard_results: Vec<TopDocs> = shards.iter().map(|h| shard_hits(&h[..5])).collect();
+        let merged = TopDocs::merge(None, 5, &shard_results).unwrap();
+        let hits: Vec<(usize, DocId)> = merged
+            .score_docs()
+            .iter()
+            .map(|h| (h.shard_index(), h.doc_id()))
+            .collect();
+        assert_eq!(hits, expected);
+        // the 7.5 ties are broken by shard
+        assert_eq!(hits, vec![(0, 0), (1, 0), (0, 1), (1, 1), (2, 0)]);
+        assert_eq!(merged.total_hits(), 30);
+
+        let merged = TopDocs::merge(None, 100, &shard_results).unwrap();
+        assert_eq!(merged.score_docs().len(), 15);
+    }
+
+    #[test]
+    fn test_merge_by_sort_field() {
+        let sort = Sort::new(vec![SortField::Simple(SimpleSortField::new(
+            "price".into(),
+            SortFieldType::Long,
+            true,
+        ))]);
+        let shard = |prices: &[i64]| {
+            let score_docs = prices
+                .iter()
+                .enumerate()
+                .map(|(doc, &p)| {
+                    ScoreDocHit::Field(FieldDoc::new(doc as DocId, 1.0, vec![p.into()]))
+                })
+                .collect();
+            TopDocs::Field(TopFieldDocs {
+                total_hits: prices.len(),
+                score_docs,
+                max_score: 1.0,
+                fields: sort.get_sort().to_vec(),
+            })
+        };
+        let shard_results = vec![shard(&[9, 5, 1]), shard(&[8, 5, 4])];
+        let merged = TopDocs::merge(Some(&sort), 4, &shard_results).unwrap();
+        let hits: Vec<(usize, DocId)> = merged
+            .score_docs()
+            .iter()
+            .map(|h| (h.shard_index(), h.doc_id()))
+            .collect();
+        assert_eq!(hits, vec![(0, 0), (1, 0), (0, 1), (1, 1)]);
+        assert_eq!(merged.total_hits(), 6);
+
+        // hits collected without sort values can't be merged by sort
+        let shard_results = vec![shard_hits(&[(0, 1.0)])];
+        assert!(TopDocs::merge(Some(&sort), 4, &shard_results).is_err());
+    }
+
+    fn search<IS: IndexSearcher<C>, C: Codec>(searcher: &IS, query: &dyn Query<C>) -> TopDocs {
+        let mut collector = TopDocsCollector::new(5);
+        searcher.search(query, &mut collector).unwrap();
+        collector.top_docs()
+    }
+
+    #[test]
+    fn test_merge_shard_searches() {
+        // the boosts of the docs of each shard, the union index holds the docs of all
+        // the shards one after the other
+        let shards: Vec<Vec<f32>> = vec![
+            vec![3.0, 9.0, 1.0, 7.5, 2.0, 0.5],
+            vec![7.5, 8.0, 0.1, 6.0, 7.0],
+            vec![2.5, 7.5, 4.0, 3.5, 3.0, 2.0, 9.0],
+        ];
+        let dirs: Vec<_> = (0..=shards.len())
+            .map(|_| tempfile::tempdir().unwrap())
+            .collect();
+        let writers: Vec<_> = dirs
+            .iter()
+            .map(|dir| fs_writer(dir.path(), Arc::new(IndexWriterConfig::default())))
+            .collect();
+        let union = &writers[shards.len()];
+        let mut offsets = vec![];
+        let mut num_docs = 0;
+        for (shard, boosts) in shards.iter().enumerate() {
+            offsets.push(num_docs);
+            for &boost in boosts {
+                let doc = || -> Vec<Box<dyn Fieldable>> {
+                    vec![Box::new(FloatDocValuesField::new("boost", boost))]
+                };
+                writers[shard].add_document(doc()).unwrap();
+                union.add_document(doc()).unwrap();
+                num_docs += 1;
+            }
+        }
+        // the score of a doc is its boost, whatever the statistics of its index
+        let query = FunctionScoreQuery::build(Box::new(MatchAllDocsQuery), "boost".into());
+        let mut shard_results = vec![];
+        let mut expected = None;
+        for (i, writer) in writers.iter().enumerate() {
+            writer.commit().unwrap();
+            let reader = writer.get_reader(true, false).unwrap();
+            let searcher = DefaultIndexSearcher::new(Arc::new(reader), None);
+            let top_docs = search(&searcher, query.as_ref());
+            if i < shards.len() {
+                shard_results.push(top_docs);
+            } else {
+                expected = Some(top_docs);
+            }
+        }
+        let expected = expected.unwrap();
+
+        let merged = TopDocs::merge(None, 5, &shard_results).unwrap();
+        let hits: Vec<(DocId, f32)> = merged
+            .score_docs()
+            .iter()
+            .map(|h| (offsets[h.shard_index()] + h.doc_id(), h.score()))
+            .collect();
+        let union_hits: Vec<(DocId, f32)> = expected
+            .score_docs()
+            .iter()
+            .map(|h| (h.doc_id(), h.score()))
+            .collect();
+        assert_eq!(hits, union_hits);
+        // the 7.5 ties come in the order of the shards
+        assert_eq!(
+            hits.iter().map(|h| h.0).collect::<Vec<_>>(),
+            vec![1, 17, 7, 3, 6]
+        );
+        assert_eq!(merged.total_hits(), expected.total_hits());
+    }
 }
@@ -19,12 +19,12 @@ use core::search::sort_field::{
     NumericDocValuesComparator, RelevanceComparator,
 };
 use core::util::{sortable_double_bits, sortable_float_bits};
-use core::util::{BitsMut, DocId, VariantValue};
+use core::util::{tagged_variant_value_option, BitsMut, DocId, VariantValue};
 
 use error::ErrorKind::IllegalArgument;
 use error::Result;
 
-#[derive(PartialEq, Debug, Clone, Copy, Eq)]
+#[derive(PartialEq, Debug, Clone, Copy, Eq, Serialize, Deserialize)]
 pub enum SortFieldType {
     String,
     Score,
@@ -46,7 +46,7 @@ pub enum SortedSetSelectorType {
     MiddleMax,
 }
 
-#[derive(PartialEq, Debug, Clone, Copy, Eq)]
+#[derive(PartialEq, Debug, Clone, Copy, Eq, Serialize, Deserialize)]
 pub enum SortedNumericSelectorType {
     Min,
     Max,
@@ -58,7 +58,7 @@ pub enum SortFieldMissingValue {
     StringFirst,
 }
 
-#[derive(Clone, Eq, PartialEq, Debug)]
+#[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum SortField {
     Simple(SimpleSortField),
     SortedNumeric(SortedNumericSortField),
@@ -128,11 +128,12 @@ impl SortField {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct SimpleSortField {
     field: String,
     field_type: SortFieldType,
     is_reverse: bool,
+    #[serde(with = "tagged_variant_value_option")]
     missing_value: Option<VariantValue>,
 }
 
@@ -210,7 +211,7 @@ impl SimpleSortField {
 ///
 /// Like sorting by string, this also supports sorting missing values as first or last,
 /// via {@link #setMissingValue(Object)}.
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub struct SortedNumericSortField {
     selector: SortedNumericSelectorType,
     real_type: SortFieldType,
@@ -0,0 +1,140 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use core::search::suggest::{Suggester, Suggestion};
+use core::util::automaton::Automaton;
+use error::Result;
+
+pub const DEFAULT_FUZZY_MAX_EDITS: u32 = 1;
+pub const DEFAULT_FUZZY_PREFIX_LENGTH: usize = 1;
+pub const DEFAULT_MIN_FUZZY_LENGTH: usize = 3;
+
+/// Suggests the keys of a `Suggester` starting with a string within `max_edits` edits of
+/// `prefix`, the heaviest first.
+///
+/// The Levenshtein automaton of the prefix is intersected with the FST of the suggester,
+/// so only the keys within the edit budget are visited. Edits are counted in characters,
+/// the first `prefix_length` characters must match exactly, and a prefix shorter than
+/// `min_fuzzy_length` characters is matched exactly, as nearly every key is a few edits
+/// away from a short prefix.
+pub struct FuzzyCompletionQuery {
+    prefix: String,
+    max_edits: u32,
+    prefix_length: usize,
+    min_fuzzy_length: usize,
+    automaton: Automaton,
+}
+
+impl FuzzyCompletionQuery {
+    pub fn new(
+        prefix: String,
+        max_edits: u32,
+        prefix_length: usize,
+        min_fuzzy_length: usize,
+    ) -> Result<FuzzyCompletionQuery> {
+        let edits = if prefix.chars().count() < min_fuzzy_length {
+            0
+        } else {
+            max_edits
+        };
+        let automaton = Automaton::make_fuzzy_prefix(prefix.as_bytes(), edits, prefix_length)?;
+        Ok(FuzzyCompletionQuery {
+            prefix,
+            max_edits,
+            prefix_length,
+            min_fuzzy_length,
+            automaton,
+        })
+    }
+
+    /// Returns the `num` heaviest keys of `suggester` matching the query.
+    pub fn suggest(&self, suggester: &Suggester, num: usize) -> Result<Vec<Suggestion>> {
+        suggester.lookup(&self.automaton, num)
+    }
+}
+
+impl fmt::Display for FuzzyCompletionQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "FuzzyCompletionQuery(prefix: {}, max_edits: {}, prefix_length: {}, min_fuzzy_length: \
+             {})",
+            &self.prefix, self.max_edits, self.prefix_length, self.min_fuzzy_length
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggest(query: &FuzzyCompletionQuery, suggester: &Suggester, num: usize) -> Vec<String> {
+        let suggestions = query.suggest(suggester, num).unwrap();
+        suggestions.into_iter().map(|s| s.key).collect()
+    }
+
+    #[test]
+    fn test_fuzzy_completion_query() {
+        let entries = vec![
+            ("receive".to_string(), 5),
+            ("received".to_string(), 10),
+            ("receiver".to_string(), 3),
+            ("recipe".to_string(), 7),
+            ("deceive".to_string(), 8),
+        ];
+        let suggester = Suggester::build(entries).unwrap();
+
+        // "ie" for "ei" is a single transposition
+        let query = FuzzyCompletionQuery::new("recieve".into(), 1, 1, 3).unwrap();
+        assert_eq!(
+            suggest(&query, &suggester, 5),
+            vec!["received", "receive", "receiver"]
+        );
+        assert_eq!(suggest(&query, &suggester, 2), vec!["received", "receive"]);
+        let exact = FuzzyCompletionQuery::new("recieve".into(), 0, 1, 3).unwrap();
+        assert!(suggest(&exact, &suggester, 5).is_empty());
+
+        // the substitution of the first byte is only allowed without an exact prefix
+        let query = FuzzyCompletionQuery::new("receive".into(), 1, 0, 3).unwrap();
+        assert_eq!(
+            suggest(&query, &suggester, 5),
+            vec!["received", "deceive", "receive", "receiver"]
+        );
+        let query = FuzzyCompletionQuery::new("receive".into(), 1, 1, 3).unwrap();
+        assert_eq!(
+            suggest(&query, &suggester, 5),
+            vec!["received", "receive", "receiver"]
+        );
+
+        // prefixes shorter than min_fuzzy_length are matched exactly
+        let query = FuzzyCompletionQuery::new("dec".into(), 1, 0, 4).unwrap();
+        assert_eq!(suggest(&query, &suggester, 5), vec!["deceive"]);
+        let query = FuzzyCompletionQuery::new("dec".into(), 1, 0, 3).unwrap();
+        assert_eq!(
+            suggest(&query, &suggester, 5),
+            vec!["received", "deceive", "recipe", "receive", "receiver"]
+        );
+
+        assert!(FuzzyCompletionQuery::new("recieve".into(), 3, 1, 3).is_err());
+
+        // a misspelt accented character is a single edit
+        let suggester =
+            Suggester::build(vec![("café".to_string(), 2), ("cafés".to_string(), 4)]).unwrap();
+        let query = FuzzyCompletionQuery::new("cafe".into(), 1, 1, 3).unwrap();
+        assert_eq!(suggest(&query, &suggester, 5), vec!["cafés", "café"]);
+        let query = FuzzyCompletionQuery::new("cfé".into(), 1, 1, 3).unwrap();
+        assert_eq!(suggest(&query, &suggester, 5), vec!["cafés", "café"]);
+    }
+}
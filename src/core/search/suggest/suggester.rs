@@ -0,0 +1,238 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::util::automaton::Automaton;
+use core::util::fst::Arc as FstArc;
+use core::util::fst::{ByteSequenceOutput, ByteSequenceOutputFactory};
+use core::util::fst::{FstBuilder, InputType, Output, END_LABEL, FST};
+use core::util::{to_ints_ref, IntsRefBuilder};
+
+use error::{ErrorKind::IllegalArgument, Result};
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// A key completing the prefix looked up in a `Suggester`, with its weight.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Suggestion {
+    pub key: String,
+    pub weight: u64,
+}
+
+/// Suggests the keys starting with a prefix, the heaviest first.
+///
+/// The keys are held in an FST mapping each of them to its weight, the completion
+/// queries intersect it with an automaton of the prefixes they accept.
+pub struct Suggester {
+    fst: Option<FST<ByteSequenceOutputFactory>>,
+}
+
+impl Suggester {
+    /// Builds the suggester of `entries`, the keys with their weights. A key given more
+    /// than once keeps its highest weight.
+    pub fn build(mut entries: Vec<(String, u64)>) -> Result<Suggester> {
+        if entries.iter().any(|e| e.0.is_empty()) {
+            bail!(IllegalArgument("suggester keys must not be empty".into()));
+        }
+        if entries.is_empty() {
+            return Ok(Suggester { fst: None });
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        entries.dedup_by(|a, b| a.0 == b.0);
+
+        let mut builder = FstBuilder::new(InputType::Byte1, ByteSequenceOutputFactory::new());
+        builder.init();
+        let mut scratch = IntsRefBuilder::new();
+        for (key, weight) in &entries {
+            let output = ByteSequenceOutput::new(weight.to_be_bytes().to_vec());
+            builder.add(to_ints_ref(key.as_bytes(), &mut scratch), output)?;
+        }
+        Ok(Suggester {
+            fst: builder.finish()?,
+        })
+    }
+
+    /// Returns the `num` heaviest keys accepted by `automaton`, the smaller keys first
+    /// on equal weights.
+    ///
+    /// The paths of the FST the automaton accepts are walked best first, by the highest
+    /// weight the keys they lead to may have, so the walk stops as soon as the `num`
+    /// heaviest keys are found.
+    pub fn lookup(&self, automaton: &Automaton, num: usize) -> Result<Vec<Suggestion>> {
+        let fst = match self.fst {
+            Some(ref fst) if automaton.num_states() > 0 && num > 0 => fst,
+            _ => return Ok(vec![]),
+        };
+        let mut reader = fst.bytes_reader();
+        let mut queue = BinaryHeap::new();
+        queue.push(TopNPath {
+            max_weight: u64::max_value(),
+            key: vec![],
+            state: 0,
+            arc: Some(fst.root_arc()),
+            output: ByteSequenceOutput::new(vec![]),
+        });
+        let mut suggestions = Vec::with_capacity(num);
+        while let Some(path) = queue.pop() {
+            let follow = match path.arc {
+                Some(arc) => arc,
+                None => {
+                    // a complete key is heavier than all the keys left to find
+                    suggestions.push(Suggestion {
+                        key: String::from_utf8(path.key)?,
+                        weight: path.max_weight,
+                    });
+                    if suggestions.len() == num {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let mut arc = fst.read_first_target_arc(&follow, &mut reader)?;
+            loop {
+                let output = match arc.output {
+                    Some(ref o) => path.output.cat(o),
+                    None => path.output.clone(),
+                };
+                if arc.label == END_LABEL {
+                    if automaton.is_accept(path.state) {
+                        queue.push(TopNPath {
+                            max_weight: max_weight(&output),
+                            key: path.key.clone(),
+                            state: path.state,
+                            arc: None,
+                            output,
+                        });
+                    }
+                } else if let Some(next) = automaton.step(path.state, arc.label as u8) {
+                    let mut key = path.key.clone();
+                    key.push(arc.label as u8);
+                    queue.push(TopNPath {
+                        max_weight: max_weight(&output),
+                        key,
+                        state: next,
+                        arc: Some(arc.clone()),
+                        output,
+                    });
+                }
+                if arc.is_last() {
+                    break;
+                }
+                fst.read_next_arc(&mut arc, &mut reader)?;
+            }
+        }
+        Ok(suggestions)
+    }
+}
+
+// the highest weight of the keys whose output starts with `output`, the weights being
+// stored big endian
+fn max_weight(output: &ByteSequenceOutput) -> u64 {
+    let bytes = output.inner();
+    (0..8).fold(0u64, |w, i| {
+        w << 8 | u64::from(*bytes.get(i).unwrap_or(&0xFF))
+    })
+}
+
+// a path of the FST accepted by the automaton, ordered by the highest weight of the keys
+// it leads to then by key. The path is a complete key when there is no arc to follow.
+struct TopNPath {
+    max_weight: u64,
+    key: Vec<u8>,
+    state: usize,
+    arc: Option<FstArc<ByteSequenceOutput>>,
+    output: ByteSequenceOutput,
+}
+
+impl Ord for TopNPath {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // the queue pops the greatest path first: the heaviest, then the smallest key,
+        // then a complete key before the keys it is a prefix of
+        self.max_weight
+            .cmp(&other.max_weight)
+            .then_with(|| other.key.cmp(&self.key))
+            .then_with(|| self.arc.is_none().cmp(&other.arc.is_none()))
+    }
+}
+
+impl PartialOrd for TopNPath {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for TopNPath {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for TopNPath {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggester_lookup() {
+        let entries = vec![
+            ("banana".to_string(), 3),
+            ("apple".to_string(), 4),
+            ("band".to_string(), 9),
+            ("ban".to_string(), 1),
+            ("band".to_string(), 2),
+        ];
+        let suggester = Suggester::build(entries).unwrap();
+
+        let automaton = Automaton::make_fuzzy_prefix(b"ban", 0, 0).unwrap();
+        let suggestions = suggester.lookup(&automaton, 10).unwrap();
+        let keys: Vec<(&str, u64)> = suggestions
+            .iter()
+            .map(|s| (s.key.as_str(), s.weight))
+            .collect();
+        // "band" keeps the highest of its weights
+        assert_eq!(keys, vec![("band", 9), ("banana", 3), ("ban", 1)]);
+        assert_eq!(suggester.lookup(&automaton, 1).unwrap()[0].key, "band");
+
+        let automaton = Automaton::make_fuzzy_prefix(b"c", 0, 0).unwrap();
+        assert!(suggester.lookup(&automaton, 10).unwrap().is_empty());
+        let empty = Suggester::build(vec![]).unwrap();
+        assert!(empty.lookup(&automaton, 10).unwrap().is_empty());
+
+        assert!(Suggester::build(vec![(String::new(), 1)]).is_err());
+    }
+
+    #[test]
+    fn test_suggester_lookup_top_n() {
+        let entries: Vec<(String, u64)> = (0..1000u64)
+            .map(|i| (format!("k{:03}", i), i * 7919 % 100 * 1000))
+            .chain(vec![("zz".to_string(), 1 << 40)])
+            .collect();
+        let suggester = Suggester::build(entries.clone()).unwrap();
+
+        let mut expected = entries.clone();
+        expected.retain(|e| e.0.starts_with('k'));
+        expected.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let automaton = Automaton::make_fuzzy_prefix(b"k", 0, 0).unwrap();
+        for &num in &[1, 5, 25, 2000] {
+            let suggestions = suggester.lookup(&automaton, num).unwrap();
+            let keys: Vec<(String, u64)> =
+                suggestions.into_iter().map(|s| (s.key, s.weight)).collect();
+            assert_eq!(&keys[..], &expected[..num.min(expected.len())]);
+        }
+
+        let automaton = Automaton::make_fuzzy_prefix(b"", 0, 0).unwrap();
+        let suggestions = suggester.lookup(&automaton, 1).unwrap();
+        assert_eq!(suggestions[0].key, "zz");
+    }
+}
@@ -0,0 +1,302 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::store::directory::{Directory, Lock};
+use core::store::io::{DataOutput, IndexInput, IndexOutput};
+use core::store::IOContext;
+
+use error::Result;
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// A `Directory` mirroring all writes to a number of replica directories.
+///
+/// Reads are only served by the primary directory. Failures of the primary are returned
+/// as usual, while failures of a replica are logged and otherwise ignored, a replica
+/// failing to write a file stops receiving the writes of that file.
+pub struct CompositeDirectory<P: Directory, R: Directory> {
+    primary: Arc<P>,
+    replicas: Vec<Arc<R>>,
+}
+
+impl<P: Directory, R: Directory> CompositeDirectory<P, R> {
+    pub fn new(primary: Arc<P>, replicas: Vec<Arc<R>>) -> Self {
+        CompositeDirectory { primary, replicas }
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn replicas(&self) -> &[Arc<R>] {
+        &self.replicas
+    }
+
+    fn replicate<F: Fn(&R) -> Result<()>>(&self, op: &str, f: F) {
+        for replica in &self.replicas {
+            if let Err(e) = f(replica) {
+                warn!("replica {} failed to {}: {:?}", replica, op, e);
+            }
+        }
+    }
+
+    fn replica_outputs(&self, name: &str, ctx: &IOContext) -> Vec<Option<R::IndexOutput>> {
+        self.replicas
+            .iter()
+            .map(|replica| match replica.create_output(name, ctx) {
+                Ok(output) => Some(output),
+                Err(e) => {
+                    warn!("replica {} failed to create {}: {:?}", replica, name, e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+impl<P: Directory, R: Directory> Directory for CompositeDirectory<P, R> {
+    type IndexOutput = CompositeIndexOutput<P::IndexOutput, R::IndexOutput>;
+    type TempOutput = CompositeIndexOutput<P::TempOutput, R::IndexOutput>;
+
+    fn list_all(&self) -> Result<Vec<String>> {
+        self.primary.list_all()
+    }
+
+    fn file_length(&self, name: &str) -> Result<i64> {
+        self.primary.file_length(name)
+    }
+
+    fn create_output(&self, name: &str, context: &IOContext) -> Result<Self::IndexOutput> {
+        let primary = self.primary.create_output(name, context)?;
+        Ok(CompositeIndexOutput::new(
+            primary,
+            self.replica_outputs(name, context),
+        ))
+    }
+
+    fn open_input(&self, name: &str, ctx: &IOContext) -> Result<Box<dyn IndexInput>> {
+        self.primary.open_input(name, ctx)
+    }
+
+    fn create_temp_output(
+        &self,
+        prefix: &str,
+        suffix: &str,
+        ctx: &IOContext,
+    ) -> Result<Self::TempOutput> {
+        let primary = self.primary.create_temp_output(prefix, suffix, ctx)?;
+        // replicas use the name picked by the primary, so that later renames apply to all
+        let replicas = self.replica_outputs(primary.name(), ctx);
+        Ok(CompositeIndexOutput::new(primary, replicas))
+    }
+
+    fn delete_file(&self, name: &str) -> Result<()> {
+        self.primary.delete_file(name)?;
+        self.replicate("delete", |r| r.delete_file(name));
+        Ok(())
+    }
+
+    fn sync(&self, name: &HashSet<String>) -> Result<()> {
+        self.primary.sync(name)?;
+        self.replicate("sync", |r| r.sync(name));
+        Ok(())
+    }
+
+    fn sync_meta_data(&self) -> Result<()> {
+        self.primary.sync_meta_data()?;
+        self.replicate("sync meta data", |r| r.sync_meta_data());
+        Ok(())
+    }
+
+    fn rename(&self, source: &str, dest: &str) -> Result<()> {
+        self.primary.rename(source, dest)?;
+        self.replicate("rename", |r| r.rename(source, dest));
+        Ok(())
+    }
+
+    fn obtain_lock(&self, name: &str) -> Result<Box<dyn Lock>> {
+        self.primary.obtain_lock(name)
+    }
+}
+
+impl<P: Directory, R: Directory> fmt::Display for CompositeDirectory<P, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CompositeDirectory({}", self.primary)?;
+        for replica in &self.replicas {
+            write!(f, ", {}", replica)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// The `IndexOutput` of a `CompositeDirectory`, writing the same bytes to the primary
+/// and to every replica that hasn't failed yet.
+pub struct CompositeIndexOutput<O: IndexOutput, RO: IndexOutput> {
+    primary: O,
+    replicas: Vec<Option<RO>>,
+}
+
+impl<O: IndexOutput, RO: IndexOutput> CompositeIndexOutput<O, RO> {
+    fn new(primary: O, replicas: Vec<Option<RO>>) -> Self {
+        CompositeIndexOutput { primary, replicas }
+    }
+
+    /// The number of replicas still receiving the writes.
+    pub fn live_replicas(&self) -> usize {
+        self.replicas.iter().filter(|r| r.is_some()).count()
+    }
+
+    fn replicate<F: Fn(&mut RO) -> io::Result<()>>(&mut self, op: &str, f: F) {
+        for replica in &mut self.replicas {
+            let failed = match *replica {
+                Some(ref mut output) => match f(output) {
+                    Ok(()) => false,
+                    Err(e) => {
+                        warn!("replica failed to {} {}: {:?}", op, output.name(), e);
+                        true
+                    }
+                },
+                None => false,
+            };
+            if failed {
+                *replica = None;
+            }
+        }
+    }
+}
+
+impl<O: IndexOutput, RO: IndexOutput> io::Write for CompositeIndexOutput<O, RO> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let count = self.primary.write(buf)?;
+        self.replicate("write", |r| r.write_all(&buf[..count]));
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.primary.flush()?;
+        self.replicate("flush", |r| r.flush());
+        Ok(())
+    }
+}
+
+impl<O: IndexOutput, RO: IndexOutput> DataOutput for CompositeIndexOutput<O, RO> {}
+
+impl<O: IndexOutput, RO: IndexOutput> IndexOutput for CompositeIndexOutput<O, RO> {
+    fn name(&self) -> &str {
+        self.primary.name()
+    }
+
+    fn file_pointer(&self) -> i64 {
+        self.primary.file_pointer()
+    }
+
+    fn checksum(&self) -> Result<i64> {
+        self.primary.checksum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::store::directory::FSDirectory;
+    use core::store::io::DataInput;
+
+    use std::fs;
+
+    fn sorted_files<D: Directory>(dir: &D) -> Vec<String> {
+        let mut files = dir.list_all().unwrap();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_mirror_writes() {
+        let primary_dir = tempfile::tempdir().unwrap();
+        let replica_dirs = vec![tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let primary = Arc::new(FSDirectory::with_path(primary_dir.path()).unwrap());
+        let replicas = replica_dirs
+            .iter()
+            .map(|d| Arc::new(FSDirectory::with_path(d.path()).unwrap()))
+            .collect();
+        let dir = CompositeDirectory::new(primary, replicas);
+        let ctx = IOContext::Default;
+
+        {
+            let mut output = dir.create_output("a", &ctx).unwrap();
+            output.write_int(42).unwrap();
+            output.write_string("mirrored").unwrap();
+            assert_eq!(output.live_replicas(), 2);
+        }
+        {
+            let mut output = dir.create_temp_output("b", "x", &ctx).unwrap();
+            output.write_int(7).unwrap();
+        }
+        let temp_name = dir
+            .list_all()
+            .unwrap()
+            .into_iter()
+            .find(|n| n.ends_with(".tmp"))
+            .unwrap();
+        dir.rename(&temp_name, "b").unwrap();
+        dir.sync(&["a".to_string(), "b".to_string()].iter().cloned().collect())
+            .unwrap();
+        dir.sync_meta_data().unwrap();
+
+        let directories: Vec<&FSDirectory> = Some(dir.primary())
+            .into_iter()
+            .chain(dir.replicas().iter().map(|r| r.as_ref()))
+            .collect();
+        for d in &directories {
+            assert_eq!(sorted_files(*d), vec!["a".to_string(), "b".to_string()]);
+            let mut input = d.open_input("a", &ctx).unwrap();
+            assert_eq!(input.read_int().unwrap(), 42);
+            assert_eq!(input.read_string().unwrap(), "mirrored");
+            assert_eq!(d.open_input("b", &ctx).unwrap().read_int().unwrap(), 7);
+        }
+
+        dir.delete_file("a").unwrap();
+        for d in &directories {
+            assert_eq!(sorted_files(*d), vec!["b".to_string()]);
+        }
+    }
+
+    #[test]
+    fn test_replica_failures_are_ignored() {
+        let primary_dir = tempfile::tempdir().unwrap();
+        let replica_dir = tempfile::tempdir().unwrap();
+        let primary = Arc::new(FSDirectory::with_path(primary_dir.path()).unwrap());
+        let replica = Arc::new(FSDirectory::with_path(replica_dir.path()).unwrap());
+        let dir = CompositeDirectory::new(primary, vec![replica]);
+        let ctx = IOContext::Default;
+
+        // the replica is gone, writes still succeed on the primary
+        fs::remove_dir_all(replica_dir.path()).unwrap();
+        {
+            let mut output = dir.create_output("a", &ctx).unwrap();
+            assert_eq!(output.live_replicas(), 0);
+            output.write_int(42).unwrap();
+        }
+        dir.sync(&Some("a".to_string()).into_iter().collect())
+            .unwrap();
+        dir.rename("a", "b").unwrap();
+        assert_eq!(dir.list_all().unwrap(), vec!["b".to_string()]);
+        let mut input = dir.open_input("b", &ctx).unwrap();
+        assert_eq!(input.read_int().unwrap(), 42);
+        dir.delete_file("b").unwrap();
+    }
+}
@@ -14,10 +14,12 @@
 use std::collections::HashSet;
 use std::fmt;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 use core::store::io::{BufferedChecksumIndexInput, DataOutput, IndexInput, IndexOutput};
 use core::store::IOContext;
+use error::ErrorKind::{AlreadyClosed, IllegalState};
 use error::Result;
 
 /// A Directory is a flat list of files.
@@ -75,6 +77,25 @@ pub trait Directory: fmt::Display {
 
     fn rename(&self, source: &str, dest: &str) -> Result<()>;
 
+    /// Acquires and returns the named lock, failing if it is already held.
+    ///
+    /// By default the lock only excludes the other holders in this process of the same
+    /// lock of this directory instance, directories shared across processes must
+    /// override it.
+    fn obtain_lock(&self, name: &str) -> Result<Box<dyn Lock>> {
+        let key = (self as *const Self as *const u8 as usize, name.to_string());
+        if !IN_PROCESS_LOCKS.lock()?.insert(key.clone()) {
+            bail!(IllegalState(format!(
+                "lock held by this process: {} in {}",
+                name, self
+            )));
+        }
+        Ok(Box::new(InProcessLock {
+            key,
+            released: AtomicBool::new(false),
+        }))
+    }
+
     fn copy_from<D: Directory>(
         &self,
         from: Arc<D>,
@@ -100,15 +121,69 @@ pub trait Directory: fmt::Display {
     }
 }
 
+/// An interprocess mutex lock, see `Directory::obtain_lock`.
+pub trait Lock: Send + Sync {
+    /// Best effort check that this lock is still valid, returns an error if it was
+    /// released or taken away.
+    fn ensure_valid(&self) -> Result<()>;
+
+    /// Releases the lock, closing an already released lock is a no-op.
+    fn close(&self) -> Result<()>;
+}
+
+lazy_static! {
+    // the locks obtained through the default `Directory::obtain_lock`, keyed by the address
+    // of the directory and the lock name
+    static ref IN_PROCESS_LOCKS: Mutex<HashSet<(usize, String)>> = Mutex::new(HashSet::new());
+}
+
+/// The `Lock` obtained by the default `Directory::obtain_lock`.
+struct InProcessLock {
+    key: (usize, String),
+    released: AtomicBool,
+}
+
+impl Lock for InProcessLock {
+    fn ensure_valid(&self) -> Result<()> {
+        if self.released.load(Ordering::Acquire) {
+            bail!(AlreadyClosed(format!(
+                "lock instance already released: {}",
+                self.key.1
+            )));
+        }
+        Ok(())
+    }
+
+    fn close(&self) -> Result<()> {
+        if !self.released.swap(true, Ordering::AcqRel) {
+            IN_PROCESS_LOCKS.lock()?.remove(&self.key);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for InProcessLock {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            warn!("release lock {} failed: {:?}", self.key.1, e);
+        }
+    }
+}
+
 /// This struct makes a best-effort check that a provided
 /// `Lock` is valid before any destructive filesystem operation.
 pub struct LockValidatingDirectoryWrapper<D: Directory> {
     dir: Arc<D>,
+    write_lock: Arc<dyn Lock>,
 }
 
 impl<D: Directory> LockValidatingDirectoryWrapper<D> {
-    pub fn new(dir: Arc<D>) -> Self {
-        LockValidatingDirectoryWrapper { dir }
+    pub fn new(dir: Arc<D>, write_lock: Arc<dyn Lock>) -> Self {
+        LockValidatingDirectoryWrapper { dir, write_lock }
+    }
+
+    fn ensure_valid(&self) -> Result<()> {
+        self.write_lock.ensure_valid()
     }
 }
 
@@ -126,6 +201,7 @@ impl<D: Directory> Directory for LockValidatingDirectoryWrapper<D> {
     type TempOutput = D::TempOutput;
 
     fn create_output(&self, name: &str, context: &IOContext) -> Result<Self::IndexOutput> {
+        self.ensure_valid()?;
         self.dir.create_output(name, context)
     }
 
@@ -143,18 +219,22 @@ impl<D: Directory> Directory for LockValidatingDirectoryWrapper<D> {
     }
 
     fn delete_file(&self, name: &str) -> Result<()> {
+        self.ensure_valid()?;
         self.dir.delete_file(name)
     }
 
     fn sync(&self, name: &HashSet<String>) -> Result<()> {
+        self.ensure_valid()?;
         self.dir.sync(name)
     }
 
     fn sync_meta_data(&self) -> Result<()> {
+        self.ensure_valid()?;
         self.dir.sync_meta_data()
     }
 
     fn rename(&self, source: &str, dest: &str) -> Result<()> {
+        self.ensure_valid()?;
         self.dir.rename(source, dest)
     }
 
@@ -165,6 +245,7 @@ impl<D: Directory> Directory for LockValidatingDirectoryWrapper<D> {
         dest: &str,
         ctx: &IOContext,
     ) -> Result<()> {
+        self.ensure_valid()?;
         self.dir.copy_from(from, src, dest, ctx)
     }
 }
@@ -213,6 +294,10 @@ default impl<T: FilterDirectory> Directory for T {
         self.dir().rename(source, dest)
     }
 
+    fn obtain_lock(&self, name: &str) -> Result<Box<dyn Lock>> {
+        self.dir().obtain_lock(name)
+    }
+
     fn copy_from<D: Directory>(
         &self,
         from: Arc<D>,
@@ -231,3 +316,83 @@ default impl<T: FilterDirectory> Directory for T {
         self.dir().resolve(name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::store::directory::FSDirectory;
+
+    // only the lock methods of `Directory` are used
+    struct NoLockDirectory;
+
+    impl Directory for NoLockDirectory {
+        type IndexOutput = <FSDirectory as Directory>::IndexOutput;
+        type TempOutput = <FSDirectory as Directory>::TempOutput;
+
+        fn list_all(&self) -> Result<Vec<String>> {
+            unimplemented!()
+        }
+
+        fn file_length(&self, _name: &str) -> Result<i64> {
+            unimplemented!()
+        }
+
+        fn create_output(&self, _name: &str, _ctx: &IOContext) -> Result<Self::IndexOutput> {
+            unimplemented!()
+        }
+
+        fn open_input(&self, _name: &str, _ctx: &IOContext) -> Result<Box<dyn IndexInput>> {
+            unimplemented!()
+        }
+
+        fn create_temp_output(
+            &self,
+            _prefix: &str,
+            _suffix: &str,
+            _ctx: &IOContext,
+        ) -> Result<Self::TempOutput> {
+            unimplemented!()
+        }
+
+        fn delete_file(&self, _name: &str) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn sync(&self, _name: &HashSet<String>) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn sync_meta_data(&self) -> Result<()> {
+            unimplemented!()
+        }
+
+        fn rename(&self, _source: &str, _dest: &str) -> Result<()> {
+            unimplemented!()
+        }
+    }
+
+    impl fmt::Display for NoLockDirectory {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "NoLockDirectory")
+        }
+    }
+
+    #[test]
+    fn test_default_lock_excludes_holders_in_process() {
+        let (dir1, dir2) = (Arc::new(NoLockDirectory), Arc::new(NoLockDirectory));
+        let lock = dir1.obtain_lock("write.lock").unwrap();
+        assert!(dir1.obtain_lock("write.lock").is_err());
+        // other locks and other directories are independent
+        let other = dir1.obtain_lock("other.lock").unwrap();
+        let lock2 = dir2.obtain_lock("write.lock").unwrap();
+
+        lock.ensure_valid().unwrap();
+        lock.close().unwrap();
+        assert!(lock.ensure_valid().is_err());
+        let lock = dir1.obtain_lock("write.lock").unwrap();
+        drop(lock);
+        dir1.obtain_lock("write.lock").unwrap();
+        other.close().unwrap();
+        lock2.close().unwrap();
+    }
+}
@@ -16,31 +16,55 @@ use std::fmt;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 
 use core::codec::segment_infos::segment_file_name;
-use core::store::directory::Directory;
-use core::store::io::{FSIndexOutput, IndexInput, MmapIndexInput};
+use core::store::directory::{Directory, Lock};
+use core::store::io::{
+    FSIndexInput, FSIndexOutput, IndexInput, MmapIndexInput, DEFAULT_OUTPUT_BUFFER_SIZE,
+};
 use core::store::IOContext;
 use core::util::to_base36;
-use error::ErrorKind::IllegalState;
+use error::ErrorKind::{AlreadyClosed, IllegalArgument, IllegalState};
 use error::Result;
 
+#[cfg(unix)]
+use libc;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+
+lazy_static! {
+    // canonical paths of the locks held by this process, os file locks may be
+    // granted again to the same process
+    static ref LOCK_HELD: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
 /// a straightforward `Directory` implementations use std::fs::File.
 ///
-/// However, it has poor concurrent performance (multiple threads will bottleneck)
-/// as it synchronizes when multiple threads read from the same file.
+/// The files are read through memory mapped inputs, unless the directory is opened with
+/// `with_buffered_input`, which reads them with buffered positional reads instead, for
+/// platforms or file systems where mapping the files isn't wanted.
 pub struct FSDirectory {
     pub directory: PathBuf,
     pending_deletes: RwLock<BTreeSet<String>>,
     pub ops_since_last_delete: AtomicUsize,
     pub next_temp_file_counter: AtomicUsize,
+    output_buffer_size: AtomicUsize,
+    buffered_input: bool,
 }
 
 impl FSDirectory {
     pub fn with_path<T: AsRef<Path> + ?Sized>(directory: &T) -> Result<Self> {
         Self::new(directory)
     }
+
+    /// Opens the directory at `directory` whose inputs are `FSIndexInput`s, read into a
+    /// buffer on the heap rather than memory mapped.
+    pub fn with_buffered_input<T: AsRef<Path> + ?Sized>(directory: &T) -> Result<Self> {
+        let mut dir = Self::new(directory)?;
+        dir.buffered_input = true;
+        Ok(dir)
+    }
 }
 
 impl FSDirectory {
@@ -60,9 +84,28 @@ impl FSDirectory {
             pending_deletes: RwLock::new(BTreeSet::new()),
             ops_since_last_delete: AtomicUsize::new(0),
             next_temp_file_counter: AtomicUsize::new(0),
+            output_buffer_size: AtomicUsize::new(DEFAULT_OUTPUT_BUFFER_SIZE),
+            buffered_input: false,
         })
     }
 
+    /// Sets the write buffer size of the outputs created from now on, a larger buffer
+    /// reduces the number of syscalls of big sequential writes such as merges.
+    pub fn set_output_buffer_size(&self, buffer_size: usize) -> Result<()> {
+        if buffer_size == 0 {
+            bail!(IllegalArgument(
+                "output buffer size must be positive".into()
+            ));
+        }
+        self.output_buffer_size
+            .store(buffer_size, Ordering::Release);
+        Ok(())
+    }
+
+    pub fn output_buffer_size(&self) -> usize {
+        self.output_buffer_size.load(Ordering::Acquire)
+    }
+
     fn delete_pending_files(pending_deletes: &mut BTreeSet<String>, dir: &PathBuf) -> Result<()> {
         let mut deleted_set = BTreeSet::new();
         for name in pending_deletes.iter() {
@@ -156,14 +199,17 @@ impl Directory for FSDirectory {
         self.pending_deletes.write()?.remove(name);
         self.maybe_delete_pending_files()?;
         let path = self.resolve(name);
-        FSIndexOutput::new(name.to_string(), &path)
+        FSIndexOutput::with_buffer_size(name.to_string(), &path, self.output_buffer_size())
     }
 
     fn open_input(&self, name: &str, _ctx: &IOContext) -> Result<Box<dyn IndexInput>> {
         self.ensure_can_read(name)?;
-        let path = self.directory.as_path().join(name);
-        // hack logic, we don'e implement FsIndexInput yes, so just us MmapIndexInput instead
-        Ok(Box::new(MmapIndexInput::new(path)?))
+        let path = self.resolve(name);
+        if self.buffered_input {
+            Ok(Box::new(FSIndexInput::new(name, path)?))
+        } else {
+            Ok(Box::new(MmapIndexInput::new(path)?))
+        }
     }
 
     fn create_temp_output(
@@ -190,7 +236,7 @@ impl Directory for FSDirectory {
             }
 
             let path = self.resolve(&name);
-            return FSIndexOutput::new(name, &path);
+            return FSIndexOutput::with_buffer_size(name, &path, self.output_buffer_size());
         }
     }
 
@@ -235,11 +281,95 @@ impl Directory for FSDirectory {
         self.maybe_delete_pending_files()
     }
 
+    fn obtain_lock(&self, name: &str) -> Result<Box<dyn Lock>> {
+        let path = self.resolve(name);
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&path)?;
+        let path = path.canonicalize()?;
+
+        if !LOCK_HELD.lock()?.insert(path.clone()) {
+            bail!(IllegalState(format!(
+                "lock held by this process: {}",
+                path.display()
+            )));
+        }
+        if let Err(e) = FSLock::try_lock(&file) {
+            LOCK_HELD.lock()?.remove(&path);
+            bail!(IllegalState(format!(
+                "lock held by another program: {}: {}",
+                path.display(),
+                e
+            )));
+        }
+        Ok(Box::new(FSLock {
+            path,
+            file: Mutex::new(Some(file)),
+        }))
+    }
+
     fn resolve(&self, name: &str) -> PathBuf {
         self.directory.join(name)
     }
 }
 
+/// A `Lock` obtained by `FSDirectory`, backed by an exclusive os lock on the lock file.
+pub struct FSLock {
+    path: PathBuf,
+    // `None` once released
+    file: Mutex<Option<fs::File>>,
+}
+
+impl FSLock {
+    #[cfg(unix)]
+    fn try_lock(file: &fs::File) -> ::std::io::Result<()> {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            return Err(::std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn try_lock(_file: &fs::File) -> ::std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Lock for FSLock {
+    fn ensure_valid(&self) -> Result<()> {
+        if self.file.lock()?.is_none() {
+            bail!(AlreadyClosed(format!(
+                "lock instance already released: {}",
+                self.path.display()
+            )));
+        }
+        if !self.path.exists() {
+            bail!(AlreadyClosed(format!(
+                "lock file deleted by an external force: {}",
+                self.path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn close(&self) -> Result<()> {
+        // dropping the file releases the os lock
+        if self.file.lock()?.take().is_some() {
+            LOCK_HELD.lock()?.remove(&self.path);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for FSLock {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            warn!("release lock {} failed: {:?}", self.path.display(), e);
+        }
+    }
+}
+
 impl fmt::Display for FSDirectory {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "FSDirectory({})", self.directory.display())
@@ -0,0 +1,402 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::store::directory::{Directory, FilterDirectory};
+use core::store::io::{DataInput, DataOutput, IndexInput, IndexOutput, RandomAccessInput};
+use core::store::IOContext;
+
+use error::Result;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+fn fake_failure(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("MockDirectory: {}", msg))
+}
+
+/// A `Directory` wrapper for tests that fails with `io::Error`s on demand, to check
+/// that callers cope with partial writes and failed operations.
+///
+/// Failures can be injected after a number of directory operations (creating,
+/// opening, deleting, renaming and syncing files) or at a byte offset of every
+/// output created afterwards.
+///
+/// It also counts the bytes read from its inputs per file extension, to check that
+/// readers skip what they don't need.
+pub struct MockDirectory<D: Directory> {
+    dir: Arc<D>,
+    op_count: AtomicUsize,
+    // operations at index >= this fail, `None` if disabled
+    fail_after_ops: Mutex<Option<usize>>,
+    // outputs created while set fail to write at or beyond this offset
+    fail_at_offset: Mutex<Option<u64>>,
+    bytes_read: Mutex<HashMap<String, Arc<AtomicU64>>>,
+}
+
+impl<D: Directory> MockDirectory<D> {
+    pub fn new(dir: Arc<D>) -> Self {
+        MockDirectory {
+            dir,
+            op_count: AtomicUsize::new(0),
+            fail_after_ops: Mutex::new(None),
+            fail_at_offset: Mutex::new(None),
+            bytes_read: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Lets the next `ops` operations succeed and fails all operations after them.
+    pub fn fail_after_ops(&self, ops: usize) {
+        let count = self.op_count.load(Ordering::Acquire);
+        *self.fail_after_ops.lock().unwrap() = Some(count + ops);
+    }
+
+    /// Makes outputs created from now on fail once they get to `offset` bytes.
+    pub fn fail_at_offset(&self, offset: u64) {
+        *self.fail_at_offset.lock().unwrap() = Some(offset);
+    }
+
+    /// Stops injecting failures, outputs that were already created keep failing.
+    pub fn clear_failures(&self) {
+        *self.fail_after_ops.lock().unwrap() = None;
+        *self.fail_at_offset.lock().unwrap() = None;
+    }
+
+    /// The number of operations done through this directory so far.
+    pub fn op_count(&self) -> usize {
+        self.op_count.load(Ordering::Acquire)
+    }
+
+    /// The number of bytes read so far from the files with the extension `extension`,
+    /// bytes skipped by seeking are not counted.
+    pub fn bytes_read(&self, extension: &str) -> u64 {
+        self.bytes_read
+            .lock()
+            .unwrap()
+            .get(extension)
+            .map_or(0, |count| count.load(Ordering::Acquire))
+    }
+
+    fn maybe_fail(&self, op: &str, name: &str) -> Result<()> {
+        let idx = self.op_count.fetch_add(1, Ordering::AcqRel);
+        if let Some(limit) = *self.fail_after_ops.lock()? {
+            if idx >= limit {
+                let msg = format!("{} '{}' failed after {} ops", op, name, limit);
+                return Err(fake_failure(msg).into());
+            }
+        }
+        Ok(())
+    }
+
+    fn wrap_output<O: IndexOutput>(&self, output: O) -> Result<MockIndexOutput<O>> {
+        Ok(MockIndexOutput {
+            output,
+            fail_at_offset: *self.fail_at_offset.lock()?,
+        })
+    }
+}
+
+impl<D: Directory> FilterDirectory for MockDirectory<D> {
+    type Dir = D;
+
+    #[inline]
+    fn dir(&self) -> &Self::Dir {
+        &*self.dir
+    }
+}
+
+impl<D: Directory> Directory for MockDirectory<D> {
+    type IndexOutput = MockIndexOutput<D::IndexOutput>;
+    type TempOutput = MockIndexOutput<D::TempOutput>;
+
+    fn create_output(&self, name: &str, context: &IOContext) -> Result<Self::IndexOutput> {
+        self.maybe_fail("create_output", name)?;
+        let output = self.dir.create_output(name, context)?;
+        self.wrap_output(output)
+    }
+
+    fn open_input(&self, name: &str, ctx: &IOContext) -> Result<Box<dyn IndexInput>> {
+        self.maybe_fail("open_input", name)?;
+        let input = self.dir.open_input(name, ctx)?;
+        let extension = name.rsplit('.').next().unwrap_or("");
+        let bytes_read = Arc::clone(
+            self.bytes_read
+                .lock()?
+                .entry(extension.to_string())
+                .or_insert_with(|| Arc::new(AtomicU64::new(0))),
+        );
+        Ok(Box::new(MockIndexInput { input, bytes_read }))
+    }
+
+    fn create_temp_output(
+        &self,
+        prefix: &str,
+        suffix: &str,
+        ctx: &IOContext,
+    ) -> Result<Self::TempOutput> {
+        self.maybe_fail("create_temp_output", prefix)?;
+        let output = self.dir.create_temp_output(prefix, suffix, ctx)?;
+        self.wrap_output(output)
+    }
+
+    fn delete_file(&self, name: &str) -> Result<()> {
+        self.maybe_fail("delete_file", name)?;
+        self.dir.delete_file(name)
+    }
+
+    fn sync(&self, name: &HashSet<String>) -> Result<()> {
+        self.maybe_fail("sync", &format!("{:?}", name))?;
+        self.dir.sync(name)
+    }
+
+    fn sync_meta_data(&self) -> Result<()> {
+        self.maybe_fail("sync_meta_data", "")?;
+        self.dir.sync_meta_data()
+    }
+
+    fn rename(&self, source: &str, dest: &str) -> Result<()> {
+        self.maybe_fail("rename", source)?;
+        self.dir.rename(source, dest)
+    }
+}
+
+impl<D: Directory> fmt::Display for MockDirectory<D> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MockDirectory({})", self.dir.as_ref())
+    }
+}
+
+/// An `IndexOutput` of `MockDirectory` that writes up to the failure offset and
+/// then fails, like a disk that filled up.
+pub struct MockIndexOutput<O: IndexOutput> {
+    output: O,
+    fail_at_offset: Option<u64>,
+}
+
+impl<O: IndexOutput> DataOutput for MockIndexOutput<O> {}
+
+impl<O: IndexOutput> Write for MockIndexOutput<O> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(offset) = self.fail_at_offset {
+            let pos = self.output.file_pointer() as u64;
+            if pos >= offset {
+                return Err(fake_failure(format!(
+                    "write to '{}' failed at offset {}",
+                    self.output.name(),
+                    offset
+                )));
+            }
+            let len = buf.len().min((offset - pos) as usize);
+            return self.output.write(&buf[..len]);
+        }
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<O: IndexOutput> IndexOutput for MockIndexOutput<O> {
+    fn name(&self) -> &str {
+        self.output.name()
+    }
+
+    fn file_pointer(&self) -> i64 {
+        self.output.file_pointer()
+    }
+
+    fn checksum(&self) -> Result<i64> {
+        self.output.checksum()
+    }
+}
+
+/// An `IndexInput` of `MockDirectory` that counts the bytes read through it.
+pub struct MockIndexInput {
+    input: Box<dyn IndexInput>,
+    bytes_read: Arc<AtomicU64>,
+}
+
+impl MockIndexInput {
+    fn wrap(&self, input: Box<dyn IndexInput>) -> Box<dyn IndexInput> {
+        Box::new(MockIndexInput {
+            input,
+            bytes_read: Arc::clone(&self.bytes_read),
+        })
+    }
+}
+
+impl DataInput for MockIndexInput {
+    fn read_byte(&mut self) -> Result<u8> {
+        self.bytes_read.fetch_add(1, Ordering::AcqRel);
+        self.input.read_byte()
+    }
+
+    fn skip_bytes(&mut self, count: usize) -> Result<()> {
+        self.input.skip_bytes(count)
+    }
+}
+
+impl Read for MockIndexInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = self.input.read(buf)?;
+        self.bytes_read.fetch_add(count as u64, Ordering::AcqRel);
+        Ok(count)
+    }
+}
+
+impl IndexInput for MockIndexInput {
+    fn clone(&self) -> Result<Box<dyn IndexInput>> {
+        Ok(self.wrap(self.input.clone()?))
+    }
+
+    fn file_pointer(&self) -> i64 {
+        self.input.file_pointer()
+    }
+
+    fn seek(&mut self, pos: i64) -> Result<()> {
+        self.input.seek(pos)
+    }
+
+    fn len(&self) -> u64 {
+        self.input.len()
+    }
+
+    fn name(&self) -> &str {
+        self.input.name()
+    }
+
+    fn random_access_slice(&self, offset: i64, length: i64) -> Result<Box<dyn RandomAccessInput>> {
+        self.input.random_access_slice(offset, length)
+    }
+
+    fn slice(&self, description: &str, offset: i64, length: i64) -> Result<Box<dyn IndexInput>> {
+        Ok(self.wrap(self.input.slice(description, offset, length)?))
+    }
+
+    unsafe fn get_and_advance(&mut self, length: usize) -> Result<*const u8> {
+        self.bytes_read.fetch_add(length as u64, Ordering::AcqRel);
+        self.input.get_and_advance(length)
+    }
+
+    fn is_buffered(&self) -> bool {
+        self.input.is_buffered()
+    }
+
+    fn mapped_bytes(&self) -> Option<&[u8]> {
+        self.input.mapped_bytes()
+    }
+
+    fn prefetch(&self, offset: i64, length: i64) -> Result<()> {
+        self.input.prefetch(offset, length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::index::writer::{IndexWriter, IndexWriterConfig};
+    use core::store::directory::FSDirectory;
+    use core::test_util::keyword_doc;
+
+    fn mock_fs_directory(dir: &tempfile::TempDir) -> Arc<MockDirectory<FSDirectory>> {
+        let fs_dir = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        Arc::new(MockDirectory::new(fs_dir))
+    }
+
+    #[test]
+    fn test_fail_at_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = mock_fs_directory(&dir);
+        directory.fail_at_offset(4);
+        let mut output = directory.create_output("a", &IOContext::Default).unwrap();
+        output.write_bytes(b"ab", 0, 2).unwrap();
+        assert!(output.write_bytes(b"cdef", 0, 4).is_err());
+        // the bytes before the failure offset were still written
+        assert_eq!(output.file_pointer(), 4);
+        assert!(output.write_byte(b'g').is_err());
+
+        directory.clear_failures();
+        let mut output = directory.create_output("b", &IOContext::Default).unwrap();
+        output.write_bytes(b"abcdef", 0, 6).unwrap();
+    }
+
+    #[test]
+    fn test_fail_after_ops() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = mock_fs_directory(&dir);
+        directory.create_output("a", &IOContext::Default).unwrap();
+        assert_eq!(directory.op_count(), 1);
+
+        directory.fail_after_ops(1);
+        directory.rename("a", "b").unwrap();
+        assert!(directory.delete_file("b").is_err());
+        assert!(directory.create_output("c", &IOContext::Default).is_err());
+
+        directory.clear_failures();
+        directory.delete_file("b").unwrap();
+    }
+
+    #[test]
+    fn test_bytes_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = mock_fs_directory(&dir);
+        let mut output = directory
+            .create_output("a.bin", &IOContext::Default)
+            .unwrap();
+        output.write_bytes(b"abcdefgh", 0, 8).unwrap();
+        drop(output);
+
+        let mut input = directory.open_input("a.bin", &IOContext::READ).unwrap();
+        input.read_byte().unwrap();
+        input.skip_bytes(3).unwrap();
+        let mut buf = [0u8; 2];
+        input.read_bytes(&mut buf, 0, 2).unwrap();
+        assert_eq!(&buf, b"ef");
+        let mut slice = input.slice("slice", 6, 2).unwrap();
+        slice.read_byte().unwrap();
+        assert_eq!(directory.bytes_read("bin"), 4);
+        assert_eq!(directory.bytes_read("doc"), 0);
+    }
+
+    #[test]
+    fn test_index_writer_survives_failed_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = mock_fs_directory(&dir);
+        let config = Arc::new(IndexWriterConfig::default());
+
+        let writer = IndexWriter::new(Arc::clone(&directory), Arc::clone(&config)).unwrap();
+        for v in &["a", "b", "c"] {
+            writer.add_document(keyword_doc(v)).unwrap();
+        }
+        writer.commit().unwrap();
+
+        writer.add_document(keyword_doc("d")).unwrap();
+        directory.fail_at_offset(16);
+        assert!(writer.commit().is_err());
+        directory.clear_failures();
+        writer.rollback().unwrap();
+        drop(writer);
+
+        // only the first commit is visible and the index still takes new commits
+        let writer = IndexWriter::new(Arc::clone(&directory), config).unwrap();
+        assert_eq!(writer.num_docs(), 3);
+        writer.add_document(keyword_doc("e")).unwrap();
+        writer.commit().unwrap();
+        assert_eq!(writer.num_docs(), 4);
+    }
+}
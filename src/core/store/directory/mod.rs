@@ -15,6 +15,10 @@ mod directory;
 
 pub use self::directory::*;
 
+mod composite_directory;
+
+pub use self::composite_directory::*;
+
 mod fs_directory;
 
 pub use self::fs_directory::*;
@@ -26,3 +30,9 @@ pub use self::mmap_directory::*;
 mod tracking_directory_wrapper;
 
 pub use self::tracking_directory_wrapper::*;
+
+#[cfg(any(test, feature = "test-utils"))]
+mod mock_directory;
+
+#[cfg(any(test, feature = "test-utils"))]
+pub use self::mock_directory::*;
@@ -39,6 +39,16 @@ impl<D: Directory, T: Deref<Target = D>> TrackingDirectoryWrapper<D, T> {
     pub fn get_create_files(&self) -> HashSet<String> {
         self.create_file_names.lock().unwrap().clone()
     }
+
+    /// The names of the files created (and not deleted since) after the last `reset`.
+    pub fn created_files(&self) -> HashSet<String> {
+        self.get_create_files()
+    }
+
+    /// Forgets the files created so far, e.g. before tracking the next flush or merge.
+    pub fn reset(&self) {
+        self.create_file_names.lock().unwrap().clear();
+    }
 }
 
 impl<D, T> FilterDirectory for TrackingDirectoryWrapper<D, T>
@@ -113,3 +123,40 @@ where
         write!(f, "TrackingDirectoryWrapper({})", &*self.directory)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::store::directory::FSDirectory;
+
+    use std::sync::Arc;
+
+    fn names(names: &[&str]) -> HashSet<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_tracks_created_files_since_reset() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = Arc::new(FSDirectory::with_path(dir.path()).unwrap());
+        let tracking = TrackingDirectoryWrapper::new(Arc::clone(&directory));
+        let ctx = IOContext::Default;
+
+        tracking.create_output("a", &ctx).unwrap();
+        tracking.create_output("b", &ctx).unwrap();
+        assert_eq!(tracking.created_files(), names(&["a", "b"]));
+
+        tracking.reset();
+        assert!(tracking.created_files().is_empty());
+
+        tracking.create_output("c", &ctx).unwrap();
+        tracking.create_output("d", &ctx).unwrap();
+        tracking.rename("c", "e").unwrap();
+        tracking.delete_file("d").unwrap();
+        // files written directly to the wrapped directory are not tracked
+        directory.create_output("f", &ctx).unwrap();
+        assert_eq!(tracking.created_files(), names(&["e"]));
+    }
+}
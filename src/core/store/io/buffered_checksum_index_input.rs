@@ -89,6 +89,10 @@ impl IndexInput for BufferedChecksumIndexInput {
         &self.name
     }
 
+    fn prefetch(&self, offset: i64, length: i64) -> Result<()> {
+        self.index_input.prefetch(offset, length)
+    }
+
     fn random_access_slice(
         &self,
         _offset: i64,
@@ -11,13 +11,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::store::io::{DataInput, DataOutput};
+use core::store::io::{DataInput, DataOutput, IndexInput, IndexOutput, RandomAccessInput};
 
-use error::Result;
+use error::{ErrorKind::IllegalArgument, Result};
 use std::cmp::min;
 use std::io::{self, Read, Write};
 use std::sync::Arc;
 
+use flate2::Crc;
+
 pub struct ByteArrayRef(Arc<Vec<u8>>);
 
 impl ByteArrayRef {
@@ -104,6 +106,94 @@ impl<T: AsRef<[u8]>> DataInput for ByteArrayDataInput<T> {
     }
 }
 
+impl<T> IndexInput for ByteArrayDataInput<T>
+where
+    T: AsRef<[u8]> + Clone + Send + Sync + 'static,
+{
+    fn clone(&self) -> Result<Box<dyn IndexInput>> {
+        Ok(Box::new(ByteArrayDataInput {
+            bytes: self.bytes.clone(),
+            pos: self.pos,
+        }))
+    }
+
+    fn file_pointer(&self) -> i64 {
+        self.pos as i64
+    }
+
+    fn seek(&mut self, pos: i64) -> Result<()> {
+        if pos < 0 || pos as usize > self.length() {
+            bail!(IllegalArgument(format!(
+                "seek to {} is beyond the length {} of ByteArrayDataInput",
+                pos,
+                self.length()
+            )));
+        }
+        self.pos = pos as usize;
+        Ok(())
+    }
+
+    fn len(&self) -> u64 {
+        self.length() as u64
+    }
+
+    fn name(&self) -> &str {
+        "ByteArrayDataInput"
+    }
+
+    fn random_access_slice(&self, offset: i64, length: i64) -> Result<Box<dyn RandomAccessInput>> {
+        if offset < 0 || length < 0 || (offset + length) as usize > self.length() {
+            bail!(IllegalArgument(format!(
+                "slice ({}, {}) is beyond the length {} of ByteArrayDataInput",
+                offset,
+                length,
+                self.length()
+            )));
+        }
+        let start = offset as usize;
+        let bytes = self.bytes.as_ref()[start..start + length as usize].to_vec();
+        Ok(Box::new(ByteArrayRandomAccessInput { bytes }))
+    }
+}
+
+/// `RandomAccessInput` over a copy of a part of a `ByteArrayDataInput`.
+struct ByteArrayRandomAccessInput {
+    bytes: Vec<u8>,
+}
+
+impl ByteArrayRandomAccessInput {
+    fn slice_at(&self, pos: u64, len: usize) -> Result<&[u8]> {
+        let pos = pos as usize;
+        if pos + len > self.bytes.len() {
+            bail!(IllegalArgument(format!(
+                "read ({}, {}) is beyond the length {} of the slice",
+                pos,
+                len,
+                self.bytes.len()
+            )));
+        }
+        Ok(&self.bytes[pos..pos + len])
+    }
+}
+
+impl RandomAccessInput for ByteArrayRandomAccessInput {
+    fn read_byte(&self, pos: u64) -> Result<u8> {
+        Ok(self.slice_at(pos, 1)?[0])
+    }
+
+    fn read_short(&self, pos: u64) -> Result<i16> {
+        self.slice_at(pos, 2)?.read_short()
+    }
+
+    fn read_int(&self, pos: u64) -> Result<i32> {
+        self.slice_at(pos, 4)?.read_int()
+    }
+
+    fn read_long(&self, pos: u64) -> Result<i64> {
+        self.slice_at(pos, 8)?.read_long()
+    }
+}
+
 impl<T: AsRef<[u8]>> Read for ByteArrayDataInput<T> {
     fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
         let size = ::std::cmp::min(buf.len(), self.length() - self.pos);
@@ -114,10 +204,13 @@ impl<T: AsRef<[u8]>> Read for ByteArrayDataInput<T> {
 }
 
 /// DataOutput backed by a byte array.
+///
+/// Writes past the end of the given range fail instead of growing the array.
 pub struct ByteArrayDataOutput<T> {
     bytes: T,
     pub pos: usize,
     limit: usize,
+    crc: Crc,
 }
 
 impl<T> ByteArrayDataOutput<T>
@@ -129,6 +222,7 @@ where
             bytes,
             pos: offset,
             limit: offset + len,
+            crc: Crc::new(),
         }
     }
 
@@ -138,6 +232,19 @@ where
     }
 }
 
+impl ByteArrayDataOutput<Vec<u8>> {
+    /// Creates an output writing at most `capacity` bytes into a new `Vec<u8>`.
+    pub fn with_capacity(capacity: usize) -> ByteArrayDataOutput<Vec<u8>> {
+        ByteArrayDataOutput::new(vec![0u8; capacity], 0, capacity)
+    }
+
+    /// Returns the underlying buffer, truncated to the current position.
+    pub fn into_inner(mut self) -> Vec<u8> {
+        self.bytes.truncate(self.pos);
+        self.bytes
+    }
+}
+
 impl<T> Write for ByteArrayDataOutput<T>
 where
     T: AsMut<[u8]>,
@@ -146,6 +253,7 @@ where
         let length = min(self.limit - self.pos, buf.len());
         let pos = self.pos;
         self.bytes_slice()[pos..pos + length].copy_from_slice(&buf[..length]);
+        self.crc.update(&buf[..length]);
         self.pos += length;
         Ok(length)
     }
@@ -156,3 +264,70 @@ where
 }
 
 impl<T> DataOutput for ByteArrayDataOutput<T> where T: AsMut<[u8]> {}
+
+impl<T> IndexOutput for ByteArrayDataOutput<T>
+where
+    T: AsMut<[u8]>,
+{
+    fn name(&self) -> &str {
+        "ByteArrayDataOutput"
+    }
+
+    fn file_pointer(&self) -> i64 {
+        self.pos as i64
+    }
+
+    fn checksum(&self) -> Result<i64> {
+        Ok((self.crc.sum() as i64) & 0xffff_ffffi64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_array_round_trip() {
+        let mut output = ByteArrayDataOutput::with_capacity(64);
+        output.write_vint(300).unwrap();
+        output.write_long(-42).unwrap();
+        output.write_string("rucene").unwrap();
+        output.write_int(0x0102_0304).unwrap();
+        assert_eq!(output.file_pointer(), 21);
+
+        let checksum = output.checksum().unwrap();
+        let bytes = output.into_inner();
+        assert_eq!(bytes.len(), 21);
+        let mut crc = Crc::new();
+        crc.update(&bytes);
+        assert_eq!(checksum, i64::from(crc.sum()));
+
+        let mut input = ByteArrayDataInput::new(bytes);
+        assert_eq!(IndexInput::len(&input), 21);
+        assert_eq!(input.read_vint().unwrap(), 300);
+        assert_eq!(input.read_long().unwrap(), -42);
+
+        let mut cloned = IndexInput::clone(&input).unwrap();
+        assert_eq!(cloned.file_pointer(), 10);
+        assert_eq!(cloned.read_string().unwrap(), "rucene");
+        // the clone has its own position
+        assert_eq!(input.file_pointer(), 10);
+
+        let random_access = input.random_access_slice(17, 4).unwrap();
+        assert_eq!(random_access.read_int(0).unwrap(), 0x0102_0304);
+        assert_eq!(random_access.read_byte(3).unwrap(), 4);
+        assert!(random_access.read_short(3).is_err());
+
+        input.seek(17).unwrap();
+        assert_eq!(input.read_int().unwrap(), 0x0102_0304);
+        assert!(input.seek(22).is_err());
+    }
+
+    #[test]
+    fn test_byte_array_output_is_bounded() {
+        let mut output = ByteArrayDataOutput::with_capacity(2);
+        output.write_short(7).unwrap();
+        assert!(output.write_byte(1).is_err());
+        assert_eq!(output.into_inner(), vec![0, 7]);
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::codec::codec_util;
+use core::store::io::{DataOutput, IndexOutput};
+
+use error::Result;
+
+use crc::{crc32, Hasher32};
+use std::io::{self, Write};
+
+/// Wraps another `IndexOutput` and computes the CRC32 of all bytes written
+/// through it, so that `close` can append a codec footer to any output.
+pub struct ChecksumIndexOutput<O: IndexOutput> {
+    output: O,
+    digest: crc32::Digest,
+}
+
+impl<O: IndexOutput> ChecksumIndexOutput<O> {
+    pub fn new(output: O) -> ChecksumIndexOutput<O> {
+        ChecksumIndexOutput {
+            output,
+            digest: crc32::Digest::new_with_initial(crc32::IEEE, 0u32),
+        }
+    }
+
+    /// Writes the codec footer with the checksum of everything written so far,
+    /// flushes and returns the wrapped output.
+    pub fn close(mut self) -> Result<O> {
+        codec_util::write_footer(&mut self)?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+impl<O: IndexOutput> Write for ChecksumIndexOutput<O> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.output.write(buf)?;
+        self.digest.write(&buf[..size]);
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<O: IndexOutput> DataOutput for ChecksumIndexOutput<O> {}
+
+impl<O: IndexOutput> IndexOutput for ChecksumIndexOutput<O> {
+    fn name(&self) -> &str {
+        self.output.name()
+    }
+
+    fn file_pointer(&self) -> i64 {
+        self.output.file_pointer()
+    }
+
+    fn checksum(&self) -> Result<i64> {
+        Ok(i64::from(self.digest.sum32()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::store::io::DataInput;
+    use core::store::io::{BufferedChecksumIndexInput, ByteArrayDataInput, ByteArrayDataOutput};
+
+    fn write_with_footer() -> Vec<u8> {
+        let mut output = ChecksumIndexOutput::new(ByteArrayDataOutput::with_capacity(64));
+        output.write_vint(12_345).unwrap();
+        output.write_string("footer").unwrap();
+        // agrees with the checksum the wrapped output computes on its own
+        assert_eq!(
+            output.checksum().unwrap(),
+            output.output.checksum().unwrap()
+        );
+        output.close().unwrap().into_inner()
+    }
+
+    fn verify(bytes: Vec<u8>) -> Result<i64> {
+        let input = ByteArrayDataInput::new(bytes);
+        let mut input = BufferedChecksumIndexInput::new(Box::new(input));
+        assert_eq!(input.read_vint()?, 12_345);
+        input.read_string()?;
+        codec_util::check_footer(&mut input)
+    }
+
+    #[test]
+    fn test_checksum_footer() {
+        let bytes = write_with_footer();
+        assert_eq!(bytes.len(), 2 + 7 + codec_util::footer_length());
+        assert!(verify(bytes.clone()).is_ok());
+
+        let mut corrupted = bytes;
+        corrupted[4] ^= 0x01;
+        assert!(verify(corrupted).is_err());
+    }
+}
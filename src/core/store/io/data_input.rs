@@ -209,15 +209,9 @@ pub trait DataInput: Read {
             bail!(IllegalState(ERR_MSG.to_owned()));
         }
 
-        let length = length as usize;
-
-        let mut buffer = Vec::with_capacity(length);
-
-        unsafe {
-            buffer.set_len(length);
-        };
-
+        let mut buffer = vec![0u8; length as usize];
         self.read_exact(&mut buffer)?;
+        // invalid UTF-8 is an error rather than lossily replaced
         Ok(String::from_utf8(buffer)?)
     }
 
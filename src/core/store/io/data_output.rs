@@ -89,6 +89,12 @@ pub trait DataOutput: Write {
 
     fn write_string(&mut self, s: &str) -> Result<()> {
         let s = s.as_bytes();
+        if s.len() > i32::max_value() as usize {
+            bail!(IllegalArgument(format!(
+                "string of {} bytes is too long to be written",
+                s.len()
+            )));
+        }
         self.write_vint(s.len() as i32)?;
         self.write_all(s)?;
         Ok(())
@@ -137,3 +143,165 @@ pub trait DataOutput: Write {
 
 // a implement that can use Vec<u8> as a data output
 impl DataOutput for Vec<u8> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::store::io::DataInput;
+
+    fn zint_round_trip(values: &[i32]) {
+        let mut out = Vec::new();
+        for v in values {
+            out.write_zint(*v).unwrap();
+        }
+        let mut input: &[u8] = &out;
+        for v in values {
+            assert_eq!(input.read_zint().unwrap(), *v);
+        }
+        assert!(input.is_empty());
+    }
+
+    fn zlong_round_trip(values: &[i64]) {
+        let mut out = Vec::new();
+        for v in values {
+            out.write_zlong(*v).unwrap();
+        }
+        let mut input: &[u8] = &out;
+        for v in values {
+            assert_eq!(input.read_zlong().unwrap(), *v);
+        }
+        assert!(input.is_empty());
+    }
+
+    #[test]
+    fn test_zint_round_trip() {
+        // every small value, then the whole range with a prime stride
+        let small: Vec<i32> = (-70_000..70_000).collect();
+        zint_round_trip(&small);
+        let mut strided = Vec::new();
+        let mut v = i64::from(i32::min_value());
+        while v <= i64::from(i32::max_value()) {
+            strided.push(v as i32);
+            v += 65_521;
+        }
+        strided.push(i32::min_value());
+        strided.push(i32::max_value());
+        zint_round_trip(&strided);
+
+        // small negatives pack into a single byte
+        let mut out = Vec::new();
+        out.write_zint(-64).unwrap();
+        assert_eq!(out, vec![127u8]);
+        let mut out = Vec::new();
+        out.write_zint(i32::min_value()).unwrap();
+        assert_eq!(out.len(), 5);
+    }
+
+    #[test]
+    fn test_zlong_round_trip() {
+        let mut values: Vec<i64> = (-70_000..70_000).collect();
+        for shift in 0..63 {
+            let v = 1i64 << shift;
+            values.extend_from_slice(&[v, v - 1, v + 1, -v, -v - 1, -v + 1]);
+        }
+        values.push(i64::min_value());
+        values.push(i64::max_value());
+        zlong_round_trip(&values);
+
+        let mut out = Vec::new();
+        out.write_zlong(-1).unwrap();
+        assert_eq!(out, vec![1u8]);
+    }
+
+    // the VInt examples of Lucene's file format documentation
+    const VINT_VECTORS: &[(i64, &[u8])] = &[
+        (0, &[0x00]),
+        (1, &[0x01]),
+        (2, &[0x02]),
+        (127, &[0x7f]),
+        (128, &[0x80, 0x01]),
+        (129, &[0x81, 0x01]),
+        (130, &[0x82, 0x01]),
+        (16_383, &[0xff, 0x7f]),
+        (16_384, &[0x80, 0x80, 0x01]),
+        (16_385, &[0x81, 0x80, 0x01]),
+    ];
+
+    #[test]
+    fn test_vint_vlong_lucene_layout() {
+        for &(value, bytes) in VINT_VECTORS {
+            let mut out = Vec::new();
+            out.write_vint(value as i32).unwrap();
+            assert_eq!(out.as_slice(), bytes);
+            let mut out = Vec::new();
+            out.write_vlong(value).unwrap();
+            assert_eq!(out.as_slice(), bytes);
+
+            let mut input = bytes;
+            assert_eq!(i64::from(input.read_vint().unwrap()), value);
+            let mut input = bytes;
+            assert_eq!(input.read_vlong().unwrap(), value);
+        }
+
+        // negative ints take the full 5 bytes
+        let mut out = Vec::new();
+        out.write_vint(-1).unwrap();
+        assert_eq!(out, vec![0xff, 0xff, 0xff, 0xff, 0x0f]);
+
+        // vLongs are at most 9 bytes long, negative ones can't be written
+        let mut out = Vec::new();
+        out.write_vlong(i64::max_value()).unwrap();
+        assert_eq!(
+            out,
+            vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]
+        );
+        let mut input: &[u8] = &out;
+        assert_eq!(input.read_vlong().unwrap(), i64::max_value());
+        assert!(Vec::<u8>::new().write_vlong(-1).is_err());
+
+        let mut too_long: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert!(too_long.read_vlong().is_err());
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let values = [
+            "",
+            "a",
+            "caf\u{e9}",
+            "\u{4e2d}\u{6587}",
+            "\u{1f600} emoji",
+            "\0",
+        ];
+        let mut out = Vec::new();
+        for v in &values {
+            out.write_string(v).unwrap();
+        }
+        // the length prefix counts UTF-8 bytes, not chars
+        assert_eq!(out[0], 0);
+        let mut input: &[u8] = &out;
+        for v in &values {
+            assert_eq!(input.read_string().unwrap(), *v);
+        }
+        assert!(input.is_empty());
+
+        let mut out = Vec::new();
+        out.write_string("\u{1f600}").unwrap();
+        assert_eq!(out, vec![4, 0xf0, 0x9f, 0x98, 0x80]);
+    }
+
+    #[test]
+    fn test_read_invalid_string() {
+        // a truncated 4-byte sequence and a lone continuation byte
+        let mut input: &[u8] = &[3, 0xf0, 0x9f, 0x98];
+        assert!(input.read_string().is_err());
+        let mut input: &[u8] = &[2, b'a', 0x80];
+        assert!(input.read_string().is_err());
+        // negative length
+        let mut input: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0x0f];
+        assert!(input.read_string().is_err());
+        // fewer bytes than the length says
+        let mut input: &[u8] = &[5, b'a', b'b'];
+        assert!(input.read_string().is_err());
+    }
+}
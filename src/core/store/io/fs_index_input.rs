@@ -0,0 +1,392 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::store::io::{DataInput, IndexInput, RandomAccessInput};
+
+use error::ErrorKind::{IllegalArgument, UnexpectedEOF};
+use error::Result;
+
+use std::cmp::{max, min};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+const BUFFER_SIZE: usize = 8192;
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, offset)
+}
+
+/// Fills `buf` from the file starting at `offset`, without moving a shared cursor,
+/// so clones of the same file can read concurrently.
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match read_at(file, buf, offset) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
+                ));
+            }
+            Ok(n) => {
+                let rest = buf;
+                buf = &mut rest[n..];
+                offset += n as u64;
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// A buffered `IndexInput` reading a file with positional reads through `std::fs`.
+///
+/// Clones and slices share the open file, each with its own position and buffer.
+pub struct FSIndexInput {
+    name: String,
+    file: Arc<File>,
+    // start of this input in the file
+    offset: u64,
+    length: u64,
+    buffer: Vec<u8>,
+    // position of `buffer[0]` in this input
+    buffer_start: u64,
+    buffer_pos: usize,
+    buffer_len: usize,
+}
+
+impl FSIndexInput {
+    pub fn new<P: AsRef<Path>>(name: &str, path: P) -> Result<FSIndexInput> {
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        Ok(Self::with_file(name.to_string(), Arc::new(file), 0, length))
+    }
+
+    fn with_file(name: String, file: Arc<File>, offset: u64, length: u64) -> FSIndexInput {
+        FSIndexInput {
+            name,
+            file,
+            offset,
+            length,
+            buffer: vec![],
+            buffer_start: 0,
+            buffer_pos: 0,
+            buffer_len: 0,
+        }
+    }
+
+    #[inline]
+    fn position(&self) -> u64 {
+        self.buffer_start + self.buffer_pos as u64
+    }
+
+    /// Refills the buffer from the current position with at least `min_len` bytes.
+    fn refill(&mut self, min_len: usize) -> Result<()> {
+        let start = self.position();
+        let remaining = self.length.saturating_sub(start);
+        if remaining < min_len as u64 {
+            bail!(UnexpectedEOF(format!(
+                "read past EOF: {} (position {} + {} > length {})",
+                self.name, start, min_len, self.length
+            )));
+        }
+        let len = min(remaining, max(BUFFER_SIZE, min_len) as u64) as usize;
+        if self.buffer.len() < len {
+            self.buffer.resize(len, 0u8);
+        }
+        read_exact_at(&self.file, &mut self.buffer[..len], self.offset + start)?;
+        self.buffer_start = start;
+        self.buffer_pos = 0;
+        self.buffer_len = len;
+        Ok(())
+    }
+
+    fn check_range(&self, offset: i64, length: i64) -> Result<()> {
+        if offset < 0 || length < 0 || (offset + length) as u64 > self.length {
+            bail!(IllegalArgument(format!(
+                "Illegal (offset, length) slice: ({}, {}) for file of length: {}",
+                offset, length, self.length
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl IndexInput for FSIndexInput {
+    fn clone(&self) -> Result<Box<dyn IndexInput>> {
+        let mut input = Self::with_file(
+            self.name.clone(),
+            Arc::clone(&self.file),
+            self.offset,
+            self.length,
+        );
+        input.buffer_start = self.position();
+        Ok(Box::new(input))
+    }
+
+    fn file_pointer(&self) -> i64 {
+        self.position() as i64
+    }
+
+    fn seek(&mut self, pos: i64) -> Result<()> {
+        if pos < 0 {
+            bail!(IllegalArgument(format!("negative position: {}", pos)));
+        }
+        let pos = pos as u64;
+        if pos >= self.buffer_start && pos <= self.buffer_start + self.buffer_len as u64 {
+            self.buffer_pos = (pos - self.buffer_start) as usize;
+        } else {
+            self.buffer_start = pos;
+            self.buffer_pos = 0;
+            self.buffer_len = 0;
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> u64 {
+        self.length
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn random_access_slice(&self, offset: i64, length: i64) -> Result<Box<dyn RandomAccessInput>> {
+        self.check_range(offset, length)?;
+        Ok(Box::new(FSRandomAccessInput {
+            file: Arc::clone(&self.file),
+            offset: self.offset + offset as u64,
+            length: length as u64,
+        }))
+    }
+
+    fn slice(&self, description: &str, offset: i64, length: i64) -> Result<Box<dyn IndexInput>> {
+        self.check_range(offset, length)?;
+        Ok(Box::new(Self::with_file(
+            description.to_string(),
+            Arc::clone(&self.file),
+            self.offset + offset as u64,
+            length as u64,
+        )))
+    }
+
+    /// Makes sure the next `length` bytes are buffered and returns a pointer to them,
+    /// valid until the next read from this input.
+    unsafe fn get_and_advance(&mut self, length: usize) -> Result<*const u8> {
+        if self.buffer_pos + length > self.buffer_len {
+            self.refill(length)?;
+        }
+        let ptr = self.buffer.as_ptr().add(self.buffer_pos);
+        self.buffer_pos += length;
+        Ok(ptr)
+    }
+
+    fn is_buffered(&self) -> bool {
+        true
+    }
+}
+
+impl DataInput for FSIndexInput {
+    fn read_byte(&mut self) -> Result<u8> {
+        if self.buffer_pos >= self.buffer_len {
+            self.refill(1)?;
+        }
+        let b = self.buffer[self.buffer_pos];
+        self.buffer_pos += 1;
+        Ok(b)
+    }
+
+    fn skip_bytes(&mut self, count: usize) -> Result<()> {
+        let pos = self.position() + count as u64;
+        if pos > self.length {
+            bail!(UnexpectedEOF(format!(
+                "skip past EOF: {} (position {} > length {})",
+                self.name, pos, self.length
+            )));
+        }
+        self.seek(pos as i64)
+    }
+}
+
+impl Read for FSIndexInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let total = min(
+            buf.len() as u64,
+            self.length.saturating_sub(self.position()),
+        ) as usize;
+        let mut read = 0;
+        while read < total {
+            if self.buffer_pos >= self.buffer_len {
+                let left = total - read;
+                if left >= BUFFER_SIZE {
+                    // large reads bypass the buffer
+                    let start = self.position();
+                    read_exact_at(&self.file, &mut buf[read..total], self.offset + start)?;
+                    self.buffer_start = start + left as u64;
+                    self.buffer_pos = 0;
+                    self.buffer_len = 0;
+                    return Ok(total);
+                }
+                self.refill(1)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+            }
+            let count = min(total - read, self.buffer_len - self.buffer_pos);
+            buf[read..read + count]
+                .copy_from_slice(&self.buffer[self.buffer_pos..self.buffer_pos + count]);
+            self.buffer_pos += count;
+            read += count;
+        }
+        Ok(total)
+    }
+}
+
+/// `RandomAccessInput` over a range of a file, reading each value with a positional read.
+struct FSRandomAccessInput {
+    file: Arc<File>,
+    offset: u64,
+    length: u64,
+}
+
+impl FSRandomAccessInput {
+    fn read_fully(&self, pos: u64, buf: &mut [u8]) -> Result<()> {
+        if pos + buf.len() as u64 > self.length {
+            bail!(IllegalArgument(format!(
+                "invalid position, expecting 0 < pos < {}, got: {}",
+                self.length, pos
+            )));
+        }
+        read_exact_at(&self.file, buf, self.offset + pos)?;
+        Ok(())
+    }
+}
+
+impl RandomAccessInput for FSRandomAccessInput {
+    fn read_byte(&self, pos: u64) -> Result<u8> {
+        let mut buf = [0u8; 1];
+        self.read_fully(pos, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    fn read_short(&self, pos: u64) -> Result<i16> {
+        let mut buf = [0u8; 2];
+        self.read_fully(pos, &mut buf)?;
+        Ok(i16::from_be_bytes(buf))
+    }
+
+    fn read_int(&self, pos: u64) -> Result<i32> {
+        let mut buf = [0u8; 4];
+        self.read_fully(pos, &mut buf)?;
+        Ok(i32::from_be_bytes(buf))
+    }
+
+    fn read_long(&self, pos: u64) -> Result<i64> {
+        let mut buf = [0u8; 8];
+        self.read_fully(pos, &mut buf)?;
+        Ok(i64::from_be_bytes(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::store::directory::{Directory, FSDirectory};
+    use core::store::io::{DataOutput, FSIndexOutput};
+    use core::store::IOContext;
+
+    #[test]
+    fn test_fs_index_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test");
+        {
+            let mut output = FSIndexOutput::new("test".into(), &path).unwrap();
+            output.write_vint(300).unwrap();
+            output.write_string("rucene").unwrap();
+            // spans several buffers
+            for i in 0..5000 {
+                output.write_int(i).unwrap();
+            }
+        }
+
+        let mut input = FSIndexInput::new("test", &path).unwrap();
+        assert_eq!(input.len(), 2 + 7 + 5000 * 4);
+        assert_eq!(input.read_vint().unwrap(), 300);
+        assert_eq!(input.read_string().unwrap(), "rucene");
+        for i in 0..5000 {
+            assert_eq!(input.read_int().unwrap(), i);
+        }
+        assert!(input.read_byte().is_err());
+
+        input.seek(9 + 4 * 4000).unwrap();
+        let mut bytes = vec![0u8; 4 * 1000];
+        input.read_bytes(&mut bytes, 0, 4 * 1000).unwrap();
+        assert_eq!(&bytes[..4], &4000i32.to_be_bytes());
+        assert_eq!(input.file_pointer(), input.len() as i64);
+
+        input.seek(9).unwrap();
+        let mut cloned = IndexInput::clone(&input).unwrap();
+        assert_eq!(cloned.read_int().unwrap(), 0);
+        // the clone doesn't move this input
+        assert_eq!(input.file_pointer(), 9);
+
+        let mut slice = input.slice("ints", 9 + 4 * 10, 8).unwrap();
+        assert_eq!(slice.len(), 8);
+        assert_eq!(slice.read_int().unwrap(), 10);
+        assert_eq!(slice.read_int().unwrap(), 11);
+        assert!(slice.read_byte().is_err());
+
+        let random_access = input.random_access_slice(9, 4 * 5000).unwrap();
+        assert_eq!(random_access.read_int(4 * 4999).unwrap(), 4999);
+        assert_eq!(random_access.read_long(0).unwrap(), 1);
+        assert!(random_access.read_int(4 * 5000).is_err());
+
+        input.seek(9).unwrap();
+        let ptr = unsafe { input.get_and_advance(BUFFER_SIZE + 4).unwrap() };
+        let block = unsafe { ::std::slice::from_raw_parts(ptr, BUFFER_SIZE + 4) };
+        assert_eq!(&block[BUFFER_SIZE..], &2048i32.to_be_bytes());
+        assert_eq!(input.read_int().unwrap(), 2049);
+
+        // a short read is an error rather than a dangling pointer
+        input.seek(input.len() as i64 - 2).unwrap();
+        assert!(unsafe { input.get_and_advance(4) }.is_err());
+    }
+
+    #[test]
+    fn test_buffered_input_is_opt_in() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let mut output = FSIndexOutput::new("test".into(), &dir.path().join("test")).unwrap();
+            output.write_int(7).unwrap();
+        }
+
+        let mapped = FSDirectory::with_path(dir.path()).unwrap();
+        let input = mapped.open_input("test", &IOContext::READ).unwrap();
+        assert!(input.mapped_bytes().is_some());
+
+        let buffered = FSDirectory::with_buffered_input(dir.path()).unwrap();
+        let mut input = buffered.open_input("test", &IOContext::READ).unwrap();
+        assert!(input.mapped_bytes().is_none());
+        assert_eq!(input.read_int().unwrap(), 7);
+    }
+}
@@ -22,27 +22,53 @@ use std::path::Path;
 
 use flate2::CrcWriter;
 
-const CHUNK_SIZE: usize = 8192;
+/// The default size of the write buffer of a `FSIndexOutput`.
+pub const DEFAULT_OUTPUT_BUFFER_SIZE: usize = 8192;
 
 /// `IndexOutput` implement for `FsDirectory`
-pub struct FSIndexOutput {
+///
+/// Writes are buffered and only reach the file once the buffer is full (or on `flush`),
+/// a bigger buffer means less syscalls for large sequential writes such as merges.
+pub struct FSIndexOutput<W: Write = File> {
     name: String,
-    writer: CrcWriter<BufWriter<File>>,
+    writer: CrcWriter<BufWriter<W>>,
+    buffer_size: usize,
     bytes_written: usize,
 }
 
 impl FSIndexOutput {
     pub fn new<P: AsRef<Path>>(name: String, path: P) -> Result<FSIndexOutput> {
+        Self::with_buffer_size(name, path, DEFAULT_OUTPUT_BUFFER_SIZE)
+    }
+
+    pub fn with_buffer_size<P: AsRef<Path>>(
+        name: String,
+        path: P,
+        buffer_size: usize,
+    ) -> Result<FSIndexOutput> {
         let file = OpenOptions::new().write(true).create(true).open(path)?;
-        Ok(FSIndexOutput {
+        Ok(FSIndexOutput::with_writer(name, file, buffer_size))
+    }
+}
+
+impl<W: Write> FSIndexOutput<W> {
+    /// Creates an output writing to `writer` through a buffer of `buffer_size` bytes.
+    pub fn with_writer(name: String, writer: W, buffer_size: usize) -> FSIndexOutput<W> {
+        debug_assert!(buffer_size > 0);
+        FSIndexOutput {
             name,
-            writer: CrcWriter::new(BufWriter::with_capacity(CHUNK_SIZE, file)),
+            writer: CrcWriter::new(BufWriter::with_capacity(buffer_size, writer)),
+            buffer_size,
             bytes_written: 0,
-        })
+        }
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
     }
 }
 
-impl Drop for FSIndexOutput {
+impl<W: Write> Drop for FSIndexOutput<W> {
     fn drop(&mut self) {
         if let Err(ref desc) = self.writer.flush() {
             error!("Oops, failed to flush {}, errmsg: {}", self.name, desc);
@@ -51,9 +77,9 @@ impl Drop for FSIndexOutput {
     }
 }
 
-impl DataOutput for FSIndexOutput {}
+impl<W: Write> DataOutput for FSIndexOutput<W> {}
 
-impl Write for FSIndexOutput {
+impl<W: Write> Write for FSIndexOutput<W> {
     fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
         let count = self.writer.write(buf)?;
         self.bytes_written += count;
@@ -65,7 +91,7 @@ impl Write for FSIndexOutput {
     }
 }
 
-impl IndexOutput for FSIndexOutput {
+impl<W: Write> IndexOutput for FSIndexOutput<W> {
     fn name(&self) -> &str {
         &self.name
     }
@@ -94,4 +120,54 @@ mod tests {
         assert_eq!(fsout.file_pointer(), 1);
         ::std::fs::remove_file("hello.txt").unwrap();
     }
+
+    // a sink counting how often the buffer actually got written out
+    struct CountingWriter {
+        bytes: Vec<u8>,
+        writes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+            self.writes += 1;
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> ::std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn write_stream(buffer_size: usize) -> (Vec<u8>, usize) {
+        let sink = CountingWriter {
+            bytes: Vec::new(),
+            writes: 0,
+        };
+        let mut output = FSIndexOutput::with_writer("test".into(), sink, buffer_size);
+        assert_eq!(output.buffer_size(), buffer_size);
+        // 4MB made of small writes, the way codecs write
+        for i in 0..(1 << 20) {
+            output.write_int(i).unwrap();
+        }
+        assert_eq!(output.file_pointer(), 4 << 20);
+        output.flush().unwrap();
+        let sink = output.writer.get_ref().get_ref();
+        (sink.bytes.clone(), sink.writes)
+    }
+
+    #[test]
+    fn test_buffer_size() {
+        let (small_bytes, small_writes) = write_stream(DEFAULT_OUTPUT_BUFFER_SIZE);
+        let (large_bytes, large_writes) = write_stream(DEFAULT_OUTPUT_BUFFER_SIZE * 16);
+
+        assert_eq!(small_writes, (4 << 20) / DEFAULT_OUTPUT_BUFFER_SIZE);
+        assert_eq!(large_writes, small_writes / 16);
+
+        assert_eq!(small_bytes.len(), 4 << 20);
+        assert_eq!(small_bytes, large_bytes);
+        for (i, chunk) in small_bytes.chunks(4).enumerate() {
+            assert_eq!(chunk, &(i as u32).to_be_bytes());
+        }
+    }
 }
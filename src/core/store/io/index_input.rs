@@ -11,7 +11,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use core::store::io::{DataInput, RandomAccessInput};
+use core::store::io::{DataInput, RandomAccessInput, SlicedIndexInput};
 
 use error::Result;
 
@@ -29,15 +29,34 @@ pub trait IndexInput: DataInput + Send + Sync {
     fn random_access_slice(&self, _offset: i64, _length: i64)
         -> Result<Box<dyn RandomAccessInput>>;
 
-    fn slice(&self, _description: &str, _offset: i64, _length: i64) -> Result<Box<dyn IndexInput>> {
-        unimplemented!();
+    /// Creates a slice of this input, with the given description, offset, and length.
+    ///
+    /// The slice has its own position and shares the underlying file with this input.
+    /// The default wraps a clone of this input in a `SlicedIndexInput`.
+    fn slice(&self, description: &str, offset: i64, length: i64) -> Result<Box<dyn IndexInput>> {
+        let slice = SlicedIndexInput::new(description, self.clone()?, offset, length)?;
+        Ok(Box::new(slice))
     }
 
-    unsafe fn get_and_advance(&mut self, _length: usize) -> *const u8 {
+    unsafe fn get_and_advance(&mut self, _length: usize) -> Result<*const u8> {
         unimplemented!()
     }
 
     fn is_buffered(&self) -> bool {
         false
     }
+
+    /// Returns all the bytes of this input if they are memory mapped, so that they can
+    /// be used in place instead of being copied out.
+    fn mapped_bytes(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Hints that the bytes in `[offset, offset + length)` will be read soon, so the
+    /// implementation may ask the OS to read them ahead (e.g. before a merge scans a file).
+    ///
+    /// This is only advisory and does not change what is read; the default is a no-op.
+    fn prefetch(&self, _offset: i64, _length: i64) -> Result<()> {
+        Ok(())
+    }
 }
@@ -13,8 +13,10 @@
 
 use core::store::io::{DataInput, IndexInput, RandomAccessInput};
 
-use error::ErrorKind::{IllegalArgument, IllegalState};
+use error::ErrorKind::{IllegalArgument, IllegalState, UnexpectedEOF};
 use error::Result;
+#[cfg(unix)]
+use libc;
 use memmap::{Mmap, MmapOptions};
 use std::fmt::Debug;
 use std::fs::File;
@@ -204,6 +206,30 @@ impl MmapIndexInput {
         })
     }
 
+    /// Asks the OS to page in the mapped range `[offset, offset + length)`.
+    ///
+    /// `madvise` requires a page aligned address, so the start is rounded down to the
+    /// page boundary. The advice is best effort, its failure is not an error.
+    #[cfg(unix)]
+    fn advise_will_need(&self, offset: usize, length: usize) {
+        let page_size = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+            size if size > 0 => size as usize,
+            _ => 4096,
+        };
+        let addr = self.slice.as_ptr() as usize + offset;
+        let aligned = addr - addr % page_size;
+        unsafe {
+            libc::madvise(
+                aligned as *mut libc::c_void,
+                length + (addr - aligned),
+                libc::MADV_WILLNEED,
+            );
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn advise_will_need(&self, _offset: usize, _length: usize) {}
+
     #[inline]
     fn check_random_access(&self, from: u64, len: u64) -> Result<()> {
         if from + len > self.len() {
@@ -243,11 +269,19 @@ impl IndexInput for MmapIndexInput {
     }
 
     #[inline(always)]
-    unsafe fn get_and_advance(&mut self, length: usize) -> *const u8 {
-        debug_assert!(self.position + length <= self.slice.len());
+    unsafe fn get_and_advance(&mut self, length: usize) -> Result<*const u8> {
+        if self.position + length > self.slice.len() {
+            bail!(UnexpectedEOF(format!(
+                "read past EOF: {} (position {} + {} > length {})",
+                self.description,
+                self.position,
+                length,
+                self.slice.len()
+            )));
+        }
         let ptr = self.slice.as_ptr().add(self.position);
         self.position += length;
-        ptr
+        Ok(ptr)
     }
 
     fn slice(&self, description: &str, offset: i64, length: i64) -> Result<Box<dyn IndexInput>> {
@@ -255,9 +289,27 @@ impl IndexInput for MmapIndexInput {
         Ok(Box::new(boxed))
     }
 
+    fn mapped_bytes(&self) -> Option<&[u8]> {
+        Some(self.slice)
+    }
+
     fn name(&self) -> &str {
         "MmapIndexInput" // hard-coded
     }
+
+    fn prefetch(&self, offset: i64, length: i64) -> Result<()> {
+        let total_len = self.len() as i64;
+        if offset < 0 || length < 0 || offset + length > total_len {
+            bail!(IllegalArgument(format!(
+                "Illegal (offset, length) prefetch: ({}, {}) for file of length: {}",
+                offset, length, total_len
+            )));
+        }
+        if length > 0 {
+            self.advise_will_need(offset as usize, length as usize);
+        }
+        Ok(())
+    }
 }
 
 impl DataInput for MmapIndexInput {
@@ -353,9 +405,19 @@ mod tests {
 
         let mmap_input = MmapIndexInput::new(&path).unwrap();
         let mut slice = mmap_input.slice("from3", 3, 13).unwrap();
+        assert_eq!(
+            slice.mapped_bytes().unwrap()[..8],
+            567_890_i64.to_be_bytes()
+        );
         assert_eq!(slice.read_long().unwrap(), 567_890_i64);
         assert_eq!(slice.read_int().unwrap(), 1_234_567_i32);
         assert!(slice.read_int().is_err());
+
+        let mut slice = mmap_input.slice("from3", 3, 13).unwrap();
+        let ptr = unsafe { slice.get_and_advance(12).unwrap() };
+        assert_eq!(unsafe { *ptr.add(7) }, 567_890_i64.to_be_bytes()[7]);
+        assert!(unsafe { slice.get_and_advance(2) }.is_err());
+        assert!(unsafe { slice.get_and_advance(1) }.is_ok());
     }
 
     #[test]
@@ -383,4 +445,35 @@ mod tests {
 
         ::std::fs::remove_file(name).unwrap();
     }
+
+    #[test]
+    fn test_mmap_prefetch() {
+        let name = "prefetch.txt";
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = temp_dir.path().join(name);
+
+        let mut fsout = FSIndexOutput::new(name.to_string(), &path).unwrap();
+        for i in 0..10_000 {
+            fsout.write_int(i).unwrap();
+        }
+        fsout.flush().unwrap();
+
+        let mut mmap_input = MmapIndexInput::new(&path).unwrap();
+        let len = mmap_input.len() as i64;
+        mmap_input.prefetch(0, len).unwrap();
+        mmap_input.prefetch(4_001, 1_234).unwrap();
+        mmap_input.prefetch(len, 0).unwrap();
+        assert!(mmap_input.prefetch(-1, 10).is_err());
+        assert!(mmap_input.prefetch(len - 4, 8).is_err());
+        for i in 0..10_000 {
+            assert_eq!(mmap_input.read_int().unwrap(), i);
+        }
+
+        let mut slice = mmap_input.slice("from_100", 400, 400).unwrap();
+        slice.prefetch(3, 200).unwrap();
+        assert!(slice.prefetch(0, 401).is_err());
+        for i in 100..200 {
+            assert_eq!(slice.read_int().unwrap(), i);
+        }
+    }
 }
@@ -35,6 +35,14 @@ mod mmap_index_input;
 
 pub use self::mmap_index_input::*;
 
+mod fs_index_input;
+
+pub use self::fs_index_input::*;
+
+mod sliced_index_input;
+
+pub use self::sliced_index_input::*;
+
 mod data_output;
 
 pub use self::data_output::*;
@@ -43,6 +51,10 @@ mod index_output;
 
 pub use self::index_output::*;
 
+mod checksum_index_output;
+
+pub use self::checksum_index_output::*;
+
 mod fs_index_output;
 
 pub use self::fs_index_output::*;
@@ -55,6 +67,10 @@ mod growable_byte_array_output;
 
 pub use self::growable_byte_array_output::*;
 
+mod output_stream_data_output;
+
+pub use self::output_stream_data_output::*;
+
 mod ram_output;
 
 pub use self::ram_output::*;
@@ -0,0 +1,97 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::store::io::DataOutput;
+
+use std::io::{self, Write};
+
+/// A `DataOutput` writing straight through to any `Write`, e.g. a `TcpStream`
+/// or a compressing encoder, so the output doesn't need to be buffered in memory.
+pub struct OutputStreamDataOutput<W: Write> {
+    output: W,
+    bytes_written: u64,
+}
+
+impl<W: Write> OutputStreamDataOutput<W> {
+    pub fn new(output: W) -> OutputStreamDataOutput<W> {
+        OutputStreamDataOutput {
+            output,
+            bytes_written: 0,
+        }
+    }
+
+    /// The number of bytes written so far.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Returns the wrapped `Write`, without flushing it.
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+}
+
+impl<W: Write> Write for OutputStreamDataOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.output.write(buf)?;
+        self.bytes_written += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+impl<W: Write> DataOutput for OutputStreamDataOutput<W> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::store::io::DataInput;
+    use std::io::BufWriter;
+
+    #[test]
+    fn test_output_stream_data_output() {
+        let mut output = OutputStreamDataOutput::new(BufWriter::new(Vec::<u8>::new()));
+        output.write_byte(0xab).unwrap();
+        output.write_bytes(b"xyz", 1, 2).unwrap();
+        output.write_vint(16_384).unwrap();
+        output.write_vlong(i64::max_value()).unwrap();
+        output.write_zint(-3).unwrap();
+        output.write_string("stream").unwrap();
+        assert_eq!(output.bytes_written(), 1 + 2 + 3 + 9 + 1 + 7);
+
+        output.flush().unwrap();
+        let bytes = output.into_inner().into_inner().unwrap();
+        assert_eq!(bytes.len(), 23);
+
+        let mut input = bytes.as_slice();
+        assert_eq!(input.read_byte().unwrap(), 0xab);
+        assert_eq!(input.read_byte().unwrap(), b'y');
+        assert_eq!(input.read_byte().unwrap(), b'z');
+        assert_eq!(input.read_vint().unwrap(), 16_384);
+        assert_eq!(input.read_vlong().unwrap(), i64::max_value());
+        assert_eq!(input.read_zint().unwrap(), -3);
+        assert_eq!(input.read_string().unwrap(), "stream");
+        assert!(input.is_empty());
+    }
+}
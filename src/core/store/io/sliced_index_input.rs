@@ -0,0 +1,190 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::store::io::{DataInput, IndexInput, RandomAccessInput};
+
+use error::ErrorKind::IllegalArgument;
+use error::Result;
+
+use std::io::{self, Read};
+
+/// A view over the sub-range `[offset, offset + length)` of another `IndexInput`.
+///
+/// The view owns a clone of the base input, so it has its own position but shares
+/// the underlying file (e.g. the mmap of a compound file) with the base.
+pub struct SlicedIndexInput {
+    base: Box<dyn IndexInput>,
+    offset: i64,
+    length: i64,
+    description: String,
+}
+
+impl SlicedIndexInput {
+    pub fn new(
+        description: &str,
+        mut base: Box<dyn IndexInput>,
+        offset: i64,
+        length: i64,
+    ) -> Result<SlicedIndexInput> {
+        let base_len = base.len() as i64;
+        if offset < 0 || length < 0 || offset + length > base_len {
+            bail!(IllegalArgument(format!(
+                "Illegal (offset, length) slice: ({}, {}) for file of length: {}",
+                offset, length, base_len
+            )));
+        }
+        base.seek(offset)?;
+        Ok(SlicedIndexInput {
+            base,
+            offset,
+            length,
+            description: description.to_string(),
+        })
+    }
+}
+
+impl IndexInput for SlicedIndexInput {
+    fn clone(&self) -> Result<Box<dyn IndexInput>> {
+        Ok(Box::new(SlicedIndexInput {
+            base: self.base.clone()?,
+            offset: self.offset,
+            length: self.length,
+            description: self.description.clone(),
+        }))
+    }
+
+    fn file_pointer(&self) -> i64 {
+        self.base.file_pointer() - self.offset
+    }
+
+    fn seek(&mut self, pos: i64) -> Result<()> {
+        if pos < 0 || pos > self.length {
+            bail!(IllegalArgument(format!(
+                "seek position {} out of bounds of slice '{}' with length: {}",
+                pos, self.description, self.length
+            )));
+        }
+        self.base.seek(self.offset + pos)
+    }
+
+    fn len(&self) -> u64 {
+        self.length as u64
+    }
+
+    fn name(&self) -> &str {
+        &self.description
+    }
+
+    fn random_access_slice(&self, offset: i64, length: i64) -> Result<Box<dyn RandomAccessInput>> {
+        if offset < 0 || length < 0 || offset + length > self.length {
+            bail!(IllegalArgument(format!(
+                "Illegal (offset, length) slice: ({}, {}) for file of length: {}",
+                offset, length, self.length
+            )));
+        }
+        self.base.random_access_slice(self.offset + offset, length)
+    }
+
+    fn slice(&self, description: &str, offset: i64, length: i64) -> Result<Box<dyn IndexInput>> {
+        if offset < 0 || length < 0 || offset + length > self.length {
+            bail!(IllegalArgument(format!(
+                "Illegal (offset, length) slice: ({}, {}) for file of length: {}",
+                offset, length, self.length
+            )));
+        }
+        self.base.slice(description, self.offset + offset, length)
+    }
+
+    fn prefetch(&self, offset: i64, length: i64) -> Result<()> {
+        if offset < 0 || length < 0 || offset + length > self.length {
+            bail!(IllegalArgument(format!(
+                "Illegal (offset, length) prefetch: ({}, {}) for file of length: {}",
+                offset, length, self.length
+            )));
+        }
+        self.base.prefetch(self.offset + offset, length)
+    }
+
+    fn mapped_bytes(&self) -> Option<&[u8]> {
+        self.base
+            .mapped_bytes()
+            .map(|bytes| &bytes[self.offset as usize..(self.offset + self.length) as usize])
+    }
+}
+
+impl DataInput for SlicedIndexInput {}
+
+impl Read for SlicedIndexInput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = (self.length - self.file_pointer()) as usize;
+        let count = buf.len().min(remaining);
+        self.base.read(&mut buf[..count])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate tempfile;
+
+    use super::*;
+    use core::store::io::{DataOutput, FSIndexOutput, MmapIndexInput};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_sliced_index_input() {
+        let name = "sliced.bin";
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = temp_dir.path().join(name);
+
+        let mut fsout = FSIndexOutput::new(name.to_string(), &path).unwrap();
+        for i in 0..8 {
+            fsout.write_int(i).unwrap();
+        }
+        fsout.flush().unwrap();
+
+        let base: Box<dyn IndexInput> = Box::new(MmapIndexInput::new(&path).unwrap());
+        let mut first = SlicedIndexInput::new("first", base.clone().unwrap(), 4, 12).unwrap();
+        let mut second = SlicedIndexInput::new("second", base.clone().unwrap(), 16, 16).unwrap();
+        assert_eq!(first.len(), 12);
+        assert_eq!(first.name(), "first");
+
+        // the views have independent positions
+        assert_eq!(first.read_int().unwrap(), 1);
+        assert_eq!(second.read_int().unwrap(), 4);
+        assert_eq!(first.read_int().unwrap(), 2);
+        assert_eq!(first.file_pointer(), 8);
+        assert_eq!(first.read_int().unwrap(), 3);
+        // reads stop at the end of the slice
+        assert!(first.read_int().is_err());
+
+        first.seek(4).unwrap();
+        assert_eq!(first.read_int().unwrap(), 2);
+        assert!(first.seek(13).is_err());
+
+        let mut cloned = IndexInput::clone(&second).unwrap();
+        assert_eq!(cloned.file_pointer(), 4);
+        assert_eq!(cloned.read_int().unwrap(), 5);
+        assert_eq!(second.read_int().unwrap(), 5);
+
+        let mut nested = second.slice("nested", 8, 8).unwrap();
+        assert_eq!(nested.read_int().unwrap(), 6);
+        assert_eq!(nested.read_int().unwrap(), 7);
+        assert!(second.slice("nested", 8, 12).is_err());
+
+        let random = first.random_access_slice(4, 8).unwrap();
+        assert_eq!(random.read_int(4).unwrap(), 3);
+
+        assert!(SlicedIndexInput::new("too_long", base, 16, 20).is_err());
+    }
+}
@@ -59,7 +59,7 @@ impl FlushInfo {
 /// It is used as part of an `IOContext` in case of MERGE context.
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
 pub struct MergeInfo {
-    total_max_doc: u32,
+    total_max_doc: u64,
     estimated_merge_bytes: u64,
     is_external: bool,
     merge_max_num_segments: Option<u32>,
@@ -67,7 +67,7 @@ pub struct MergeInfo {
 
 impl MergeInfo {
     pub fn new(
-        total_max_doc: u32,
+        total_max_doc: u64,
         estimated_merge_bytes: u64,
         is_external: bool,
         merge_max_num_segments: Option<u32>,
@@ -0,0 +1,136 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fixtures shared by the tests of the crate.
+
+extern crate tempfile;
+
+use core::analysis::{StringTokenStream, WhitespaceTokenizer};
+use core::codec::vectors::KnnVectorSimilarityFunction;
+use core::codec::{Codec, CodecEnum};
+use core::doc::{DenseVectorField, Field, FieldType, Fieldable, IndexOptions};
+use core::index::merge::{MergePolicy, MergeScheduler, SerialMergeScheduler, TieredMergePolicy};
+use core::index::writer::{IndexWriter, IndexWriterConfig};
+use core::search::RefreshListener;
+use core::store::directory::FSDirectory;
+
+use error::Result;
+
+use std::io::Cursor;
+use std::path::Path;
+use std::sync::Arc;
+
+pub use self::tempfile::TempDir;
+
+/// The writer of `new_fs_writer`.
+pub type FSIndexWriter =
+    IndexWriter<FSDirectory, CodecEnum, SerialMergeScheduler, TieredMergePolicy>;
+
+/// Opens an `IndexWriter` with `config` over a `FSDirectory` at `path`.
+pub fn fs_writer<C: Codec, MS: MergeScheduler, MP: MergePolicy>(
+    path: &Path,
+    config: Arc<IndexWriterConfig<C, MS, MP>>,
+) -> IndexWriter<FSDirectory, C, MS, MP> {
+    let directory = Arc::new(FSDirectory::with_path(path).unwrap());
+    IndexWriter::new(directory, config).unwrap()
+}
+
+/// Opens an `IndexWriter` with the default config over a new temporary directory, which
+/// is deleted when the returned `TempDir` is dropped.
+pub fn new_fs_writer() -> (TempDir, FSIndexWriter) {
+    let dir = tempfile::tempdir().unwrap();
+    let writer = fs_writer(dir.path(), Arc::new(IndexWriterConfig::default()));
+    (dir, writer)
+}
+
+/// A field of the whitespace separated terms of `text`.
+pub fn text_field(name: &str, text: &str, index_options: IndexOptions) -> Box<dyn Fieldable> {
+    let mut field_type = FieldType::default();
+    field_type.index_options = index_options;
+    let tokens = WhitespaceTokenizer::new(Box::new(Cursor::new(text.as_bytes().to_vec())));
+    Box::new(Field::new(
+        name.into(),
+        field_type,
+        None,
+        Some(Box::new(tokens)),
+    ))
+}
+
+/// A doc of a single `text_field`.
+pub fn text_doc(name: &str, text: &str, index_options: IndexOptions) -> Vec<Box<dyn Fieldable>> {
+    vec![text_field(name, text, index_options)]
+}
+
+/// A field indexing `value` as a single term, without frequencies.
+pub fn keyword_field(name: &str, value: &str) -> Box<dyn Fieldable> {
+    let mut field_type = FieldType::default();
+    field_type.index_options = IndexOptions::Docs;
+    let tokens = StringTokenStream::new(value.to_string());
+    Box::new(Field::new(
+        name.into(),
+        field_type,
+        None,
+        Some(Box::new(tokens)),
+    ))
+}
+
+/// A doc of a single `keyword_field` named `tag`.
+pub fn keyword_doc(value: &str) -> Vec<Box<dyn Fieldable>> {
+    vec![keyword_field("tag", value)]
+}
+
+/// A `DenseVectorField` of `vector`.
+pub fn vector_field(
+    name: &str,
+    vector: &[f32],
+    similarity: KnnVectorSimilarityFunction,
+) -> Box<dyn Fieldable> {
+    Box::new(DenseVectorField::new(name, vector, similarity).unwrap())
+}
+
+/// A `RefreshListener` doing nothing.
+pub struct NoListener;
+
+impl RefreshListener for NoListener {
+    fn before_refresh(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn after_refresh(&self, _refreshed: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns a xorshift generator of pseudo random `u64`s, the same ones for the same
+/// `seed`, which must not be 0.
+pub fn xorshift(mut seed: u64) -> impl FnMut() -> u64 {
+    move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    }
+}
+
+/// Returns `count` pseudo random vectors of `dimension` values in `[-0.5, 0.5)`, see
+/// `xorshift`.
+pub fn random_vectors(count: usize, dimension: usize, seed: u64) -> Vec<Vec<f32>> {
+    let mut next = xorshift(seed);
+    (0..count)
+        .map(|_| {
+            (0..dimension)
+                .map(|_| (next() >> 40) as f32 / (1u64 << 24) as f32 - 0.5)
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,701 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use error::ErrorKind::IllegalArgument;
+use error::Result;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::str;
+
+/// The most edits `Automaton::make_fuzzy_prefix` supports.
+pub const MAX_FUZZY_EDITS: u32 = 2;
+
+// a position in the fuzzy matched term: the characters of the term consumed, whether the
+// next character must be the one before them to complete a transposition, and the edits
+// made
+type FuzzyPosition = (usize, bool, u32);
+
+// a state of the fuzzy DFA: the positions reached, then the bytes read and the number of
+// continuation bytes still expected of the character being decoded. The bytes read are
+// only kept while they may be those of a character of the term, any other character is
+// matched the same way.
+type FuzzyState = (Vec<FuzzyPosition>, Vec<u8>, u8);
+
+// a position in the wildcard pattern and the continuation bytes still expected to
+// complete the code point a `?` there started to match
+type WildcardPosition = (usize, u8);
+
+/// A transition from a state on any byte in `[min, max]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Transition {
+    pub min: u8,
+    pub max: u8,
+    pub dest: usize,
+}
+
+/// A finite state automaton over the bytes of a term.
+///
+/// State 0 is the initial state. States are created with `create_state` and
+/// connected with `add_transition`; queries require the automaton to be
+/// deterministic, i.e. the transitions leaving a state must not overlap.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Automaton {
+    transitions: Vec<Vec<Transition>>,
+    accept: Vec<bool>,
+}
+
+impl Automaton {
+    pub fn new() -> Automaton {
+        Automaton::default()
+    }
+
+    /// Returns an automaton accepting exactly `s`.
+    pub fn make_string(s: &[u8]) -> Automaton {
+        let mut automaton = Automaton::new();
+        let mut state = automaton.create_state();
+        for &b in s {
+            let next = automaton.create_state();
+            automaton.add_transition(state, next, b, b);
+            state = next;
+        }
+        automaton.set_accept(state, true);
+        automaton
+    }
+
+    /// Returns a deterministic automaton accepting the UTF-8 terms that match the wildcard
+    /// `pattern`, where `*` matches any sequence of characters and `?` any single
+    /// character, whatever the number of bytes it is encoded with.
+    pub fn make_wildcard(pattern: &[u8]) -> Automaton {
+        // every DFA state is the set of pattern positions the input may have reached
+        let mut automaton = Automaton::new();
+        let mut states: HashMap<Vec<WildcardPosition>, usize> = HashMap::new();
+        let mut pending = vec![Self::wildcard_closure(pattern, vec![(0, 0)])];
+        states.insert(pending[0].clone(), automaton.create_state());
+        while let Some(positions) = pending.pop() {
+            let source = states[&positions];
+            automaton.set_accept(source, positions.contains(&(pattern.len(), 0)));
+            let mut dests = Vec::with_capacity(256);
+            for b in 0..=255u8 {
+                let mut next = vec![];
+                for &(p, remaining) in &positions {
+                    if remaining > 0 {
+                        if b & 0xC0 == 0x80 {
+                            next.push(if remaining > 1 {
+                                (p, remaining - 1)
+                            } else {
+                                (p + 1, 0)
+                            });
+                        }
+                        continue;
+                    }
+                    match pattern.get(p) {
+                        // `*` steps over single bytes, but the rest of the pattern can't
+                        // match from within a code point
+                        Some(&b'*') => next.push((p, 0)),
+                        Some(&b'?') => match b {
+                            0x00..=0x7F => next.push((p + 1, 0)),
+                            0xC2..=0xDF => next.push((p, 1)),
+                            0xE0..=0xEF => next.push((p, 2)),
+                            0xF0..=0xF4 => next.push((p, 3)),
+                            _ => {}
+                        },
+                        Some(&c) if c == b => next.push((p + 1, 0)),
+                        _ => {}
+                    }
+                }
+                dests.push(if next.is_empty() {
+                    None
+                } else {
+                    let next = Self::wildcard_closure(pattern, next);
+                    Some(Self::dfa_state(
+                        &mut automaton,
+                        &mut states,
+                        &mut pending,
+                        next,
+                    ))
+                });
+            }
+            automaton.add_byte_transitions(source, &dests);
+        }
+        automaton
+    }
+
+    /// Returns a deterministic automaton accepting the UTF-8 terms that start with a string
+    /// within `max_edits` edits of `prefix`. An edit is the insertion, deletion or
+    /// substitution of a character, or the transposition of two adjacent characters,
+    /// whatever the number of bytes they are encoded with. The first `exact_length`
+    /// characters of `prefix` must match without edits.
+    pub fn make_fuzzy_prefix(
+        prefix: &[u8],
+        max_edits: u32,
+        exact_length: usize,
+    ) -> Result<Automaton> {
+        Self::make_fuzzy_automaton(prefix, max_edits, exact_length, true)
+    }
+
+    /// Returns a deterministic automaton accepting the terms within `max_edits` edits of
+    /// `term`, where edits are counted like `make_fuzzy_prefix` does.
+    pub fn make_fuzzy(term: &[u8], max_edits: u32, exact_length: usize) -> Result<Automaton> {
+        Self::make_fuzzy_automaton(term, max_edits, exact_length, false)
+    }
+
+    fn make_fuzzy_automaton(
+        term: &[u8],
+        max_edits: u32,
+        exact_length: usize,
+        match_prefix: bool,
+    ) -> Result<Automaton> {
+        if max_edits > MAX_FUZZY_EDITS {
+            bail!(IllegalArgument(format!(
+                "max_edits must be between 0 and {}, got {}",
+                MAX_FUZZY_EDITS, max_edits
+            )));
+        }
+        let term: Vec<char> = match str::from_utf8(term) {
+            Ok(term) => term.chars().collect(),
+            Err(e) => bail!(IllegalArgument(format!("fuzzy term is not UTF-8: {}", e))),
+        };
+        let fuzzy = FuzzyTerm {
+            encoded: term
+                .iter()
+                .map(|c| c.encode_utf8(&mut [0u8; 4]).as_bytes().to_vec())
+                .collect(),
+            term,
+            max_edits,
+            exact_length,
+        };
+
+        let mut automaton = Automaton::new();
+        let mut states: HashMap<FuzzyState, usize> = HashMap::new();
+        let start = fuzzy.closure(vec![(0, false, 0)]);
+        let mut pending = vec![];
+        Self::dfa_state(
+            &mut automaton,
+            &mut states,
+            &mut pending,
+            (start, vec![], 0),
+        );
+        while let Some(state) = pending.pop() {
+            let source = states[&state];
+            let (positions, partial, remaining) = state;
+            if remaining == 0 && fuzzy.accepts(&positions) {
+                automaton.set_accept(source, true);
+                if match_prefix {
+                    // the prefix is matched, any suffix is accepted
+                    automaton.add_transition(source, source, 0, 255);
+                    continue;
+                }
+            }
+            let mut dests = Vec::with_capacity(256);
+            for b in 0..=255u8 {
+                let next = if remaining == 0 {
+                    match b {
+                        0x00..=0x7F => fuzzy.step(&positions, Some(char::from(b))),
+                        0xC2..=0xF4 => {
+                            let remaining = if b < 0xE0 {
+                                1
+                            } else if b < 0xF0 {
+                                2
+                            } else {
+                                3
+                            };
+                            fuzzy.decoding(&positions, vec![b], remaining)
+                        }
+                        _ => None,
+                    }
+                } else if b & 0xC0 == 0x80 {
+                    let mut partial = partial.clone();
+                    if !partial.is_empty() {
+                        partial.push(b);
+                    }
+                    if remaining == 1 {
+                        let c = str::from_utf8(&partial).ok().and_then(|s| s.chars().next());
+                        fuzzy.step(&positions, c)
+                    } else {
+                        fuzzy.decoding(&positions, partial, remaining - 1)
+                    }
+                } else {
+                    None
+                };
+                dests.push(
+                    next.map(|next| {
+                        Self::dfa_state(&mut automaton, &mut states, &mut pending, next)
+                    }),
+                );
+            }
+            automaton.add_byte_transitions(source, &dests);
+        }
+        Ok(automaton)
+    }
+
+    // returns the state of the DFA standing for `key`, created if it is new
+    fn dfa_state<K: Clone + Eq + Hash>(
+        automaton: &mut Automaton,
+        states: &mut HashMap<K, usize>,
+        pending: &mut Vec<K>,
+        key: K,
+    ) -> usize {
+        match states.get(&key) {
+            Some(&state) => state,
+            None => {
+                let state = automaton.create_state();
+                states.insert(key.clone(), state);
+                pending.push(key);
+                state
+            }
+        }
+    }
+
+    // adds the transitions from `source` to `dests[b]` on every byte `b`, merging runs
+    // of bytes going to the same state into one transition
+    fn add_byte_transitions(&mut self, source: usize, dests: &[Option<usize>]) {
+        let mut current: Option<(usize, u8, u8)> = None;
+        for (b, &dest) in dests.iter().enumerate() {
+            let b = b as u8;
+            current = match (current, dest) {
+                (Some((d, min, _)), Some(dest)) if d == dest => Some((d, min, b)),
+                (current, dest) => {
+                    if let Some((d, min, max)) = current {
+                        self.add_transition(source, d, min, max);
+                    }
+                    dest.map(|d| (d, b, b))
+                }
+            };
+        }
+        if let Some((d, min, max)) = current {
+            self.add_transition(source, d, min, max);
+        }
+    }
+
+    // adds the positions following a `*`, which may match nothing
+    fn wildcard_closure(
+        pattern: &[u8],
+        mut positions: Vec<WildcardPosition>,
+    ) -> Vec<WildcardPosition> {
+        let mut i = 0;
+        while i < positions.len() {
+            let (p, remaining) = positions[i];
+            if remaining == 0 && pattern.get(p) == Some(&b'*') && !positions.contains(&(p + 1, 0)) {
+                positions.push((p + 1, 0));
+            }
+            i += 1;
+        }
+        positions.sort();
+        positions.dedup();
+        positions
+    }
+
+    /// Creates a new, non accepting state and returns its number.
+    pub fn create_state(&mut self) -> usize {
+        self.transitions.push(vec![]);
+        self.accept.push(false);
+        self.transitions.len() - 1
+    }
+
+    pub fn num_states(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn set_accept(&mut self, state: usize, accept: bool) {
+        self.accept[state] = accept;
+    }
+
+    pub fn is_accept(&self, state: usize) -> bool {
+        self.accept[state]
+    }
+
+    /// Adds a transition from `source` to `dest` on all bytes in `[min, max]`.
+    pub fn add_transition(&mut self, source: usize, dest: usize, min: u8, max: u8) {
+        debug_assert!(dest < self.num_states());
+        debug_assert!(min <= max);
+        self.transitions[source].push(Transition { min, max, dest });
+    }
+
+    pub fn transitions(&self, state: usize) -> &[Transition] {
+        &self.transitions[state]
+    }
+
+    /// Returns the state reached from `state` on `label`, if any.
+    pub fn step(&self, state: usize, label: u8) -> Option<usize> {
+        self.transitions[state]
+            .iter()
+            .find(|t| t.min <= label && label <= t.max)
+            .map(|t| t.dest)
+    }
+
+    /// Returns true if the automaton accepts `term`.
+    pub fn run(&self, term: &[u8]) -> bool {
+        if self.transitions.is_empty() {
+            return false;
+        }
+        let mut state = 0;
+        for &b in term {
+            match self.step(state, b) {
+                Some(next) => state = next,
+                None => return false,
+            }
+        }
+        self.accept[state]
+    }
+
+    /// Returns true if no two transitions leaving the same state overlap.
+    pub fn is_deterministic(&self) -> bool {
+        self.transitions.iter().all(|transitions| {
+            let mut sorted = transitions.clone();
+            sorted.sort_by_key(|t| t.min);
+            sorted.windows(2).all(|w| w[0].max < w[1].min)
+        })
+    }
+
+    /// Returns for every state whether an accept state can be reached from it.
+    pub fn live_states(&self) -> Vec<bool> {
+        let mut sources = vec![vec![]; self.num_states()];
+        for (state, transitions) in self.transitions.iter().enumerate() {
+            for t in transitions {
+                sources[t.dest].push(state);
+            }
+        }
+        let mut live = self.accept.clone();
+        let mut pending: Vec<usize> = (0..live.len()).filter(|&s| live[s]).collect();
+        while let Some(state) = pending.pop() {
+            for &source in &sources[state] {
+                if !live[source] {
+                    live[source] = true;
+                    pending.push(source);
+                }
+            }
+        }
+        live
+    }
+
+    /// Returns the prefix that every accepted term starts with.
+    pub fn common_prefix(&self) -> Vec<u8> {
+        let mut prefix = vec![];
+        if self.transitions.is_empty() {
+            return prefix;
+        }
+        let mut visited = HashSet::new();
+        let mut state = 0;
+        while visited.insert(state) && !self.accept[state] {
+            match self.transitions[state].as_slice() {
+                [t] if t.min == t.max => {
+                    prefix.push(t.min);
+                    state = t.dest;
+                }
+                _ => break,
+            }
+        }
+        prefix
+    }
+
+    /// Returns an error unless the automaton is deterministic.
+    pub fn check_deterministic(&self) -> Result<()> {
+        if !self.is_deterministic() {
+            bail!(IllegalArgument("automaton must be deterministic".into()));
+        }
+        Ok(())
+    }
+}
+
+// the term of a fuzzy automaton, stepping the positions of its states on the characters
+// of the input
+struct FuzzyTerm {
+    term: Vec<char>,
+    // the UTF-8 encoding of every character of the term
+    encoded: Vec<Vec<u8>>,
+    max_edits: u32,
+    exact_length: usize,
+}
+
+impl FuzzyTerm {
+    fn accepts(&self, positions: &[FuzzyPosition]) -> bool {
+        positions
+            .iter()
+            .any(|&(p, transposing, _)| p == self.term.len() && !transposing)
+    }
+
+    // returns the state reached from `positions` on the character `c`, `None` standing
+    // for a character not in the term
+    fn step(&self, positions: &[FuzzyPosition], c: Option<char>) -> Option<FuzzyState> {
+        let term = &self.term;
+        let is = |p: usize| c.is_some() && term.get(p).cloned() == c;
+        let mut next = vec![];
+        for &(p, transposing, edits) in positions {
+            if transposing {
+                if is(p) {
+                    next.push((p + 2, false, edits));
+                }
+                continue;
+            }
+            if is(p) {
+                next.push((p + 1, false, edits));
+            }
+            if edits < self.max_edits && p >= self.exact_length {
+                // an inserted character
+                next.push((p, false, edits + 1));
+                if p < term.len() {
+                    // a substituted character
+                    next.push((p + 1, false, edits + 1));
+                }
+                if is(p + 1) && !is(p) {
+                    next.push((p, true, edits + 1));
+                }
+            }
+        }
+        if next.is_empty() {
+            None
+        } else {
+            Some((self.closure(next), vec![], 0))
+        }
+    }
+
+    // returns the state reading a character whose first bytes are `partial`, if the
+    // character may still lead somewhere
+    fn decoding(
+        &self,
+        positions: &[FuzzyPosition],
+        mut partial: Vec<u8>,
+        remaining: u8,
+    ) -> Option<FuzzyState> {
+        if !partial.is_empty() && !self.encoded.iter().any(|e| e.starts_with(&partial)) {
+            partial.clear();
+        }
+        if partial.is_empty() && self.step(positions, None).is_none() {
+            return None;
+        }
+        Some((positions.to_vec(), partial, remaining))
+    }
+
+    // adds the positions reached by deleting characters of the term, and only keeps the
+    // fewest edits of each position as they accept all that more edits do
+    fn closure(&self, positions: Vec<FuzzyPosition>) -> Vec<FuzzyPosition> {
+        let mut fewest_edits = BTreeMap::new();
+        let mut pending = positions;
+        while let Some((p, transposing, edits)) = pending.pop() {
+            if fewest_edits
+                .get(&(p, transposing))
+                .map_or(false, |&e| e <= edits)
+            {
+                continue;
+            }
+            fewest_edits.insert((p, transposing), edits);
+            if !transposing
+                && edits < self.max_edits
+                && p >= self.exact_length
+                && p < self.term.len()
+            {
+                pending.push((p + 1, false, edits + 1));
+            }
+        }
+        fewest_edits
+            .into_iter()
+            .map(|((p, transposing), edits)| (p, transposing, edits))
+            .collect()
+    }
+}
+
+impl fmt::Display for Automaton {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (state, transitions) in self.transitions.iter().enumerate() {
+            write!(f, "{}{}", if self.accept[state] { "*" } else { "" }, state)?;
+            for t in transitions {
+                write!(f, " [{}-{}]->{}", t.min, t.max, t.dest)?;
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make_string() {
+        let automaton = Automaton::make_string(b"abc");
+        assert!(automaton.run(b"abc"));
+        assert!(!automaton.run(b"ab"));
+        assert!(!automaton.run(b"abcd"));
+        assert_eq!(automaton.common_prefix(), b"abc".to_vec());
+        assert!(automaton.is_deterministic());
+    }
+
+    #[test]
+    fn test_ranges_and_determinism() {
+        // a[0-9]+
+        let mut automaton = Automaton::new();
+        let s0 = automaton.create_state();
+        let s1 = automaton.create_state();
+        let s2 = automaton.create_state();
+        automaton.add_transition(s0, s1, b'a', b'a');
+        automaton.add_transition(s1, s2, b'0', b'9');
+        automaton.add_transition(s2, s2, b'0', b'9');
+        automaton.set_accept(s2, true);
+
+        assert!(automaton.run(b"a0"));
+        assert!(automaton.run(b"a123"));
+        assert!(!automaton.run(b"a"));
+        assert!(!automaton.run(b"a1b"));
+        assert_eq!(automaton.common_prefix(), b"a".to_vec());
+        assert!(automaton.check_deterministic().is_ok());
+
+        automaton.add_transition(s1, s1, b'5', b'7');
+        assert!(!automaton.is_deterministic());
+        assert!(automaton.check_deterministic().is_err());
+    }
+
+    #[test]
+    fn test_live_states() {
+        // `a?` and a dead end after `b`
+        let mut automaton = Automaton::make_wildcard(b"a?");
+        let dead = automaton.create_state();
+        automaton.add_transition(0, dead, b'b', b'b');
+        let live = automaton.live_states();
+        assert_eq!(
+            live.iter().filter(|&&l| l).count(),
+            automaton.num_states() - 1
+        );
+        assert!(live[0]);
+        assert!(!live[dead]);
+    }
+
+    #[test]
+    fn test_make_wildcard() {
+        let automaton = Automaton::make_wildcard(b"ab*c?");
+        assert!(automaton.check_deterministic().is_ok());
+        assert!(automaton.run(b"abcd"));
+        assert!(automaton.run(b"abxxcd"));
+        assert!(automaton.run(b"abcccd"));
+        assert!(!automaton.run(b"abc"));
+        assert!(!automaton.run(b"abxxc"));
+        assert!(!automaton.run(b"xabcd"));
+        assert_eq!(automaton.common_prefix(), b"ab".to_vec());
+
+        let automaton = Automaton::make_wildcard(b"*ing");
+        assert!(automaton.check_deterministic().is_ok());
+        assert!(automaton.run(b"ing"));
+        assert!(automaton.run(b"singing"));
+        assert!(!automaton.run(b"singer"));
+
+        let automaton = Automaton::make_wildcard(b"*");
+        assert!(automaton.run(b""));
+        assert!(automaton.run(b"anything"));
+        assert_eq!(automaton.num_states(), 1);
+    }
+
+    #[test]
+    fn test_make_wildcard_matches_code_points() {
+        let automaton = Automaton::make_wildcard("caf?".as_bytes());
+        assert!(automaton.check_deterministic().is_ok());
+        assert!(automaton.run("cafe".as_bytes()));
+        assert!(automaton.run("café".as_bytes()));
+        assert!(!automaton.run("caf".as_bytes()));
+        assert!(!automaton.run("cafés".as_bytes()));
+
+        // one, two, three and four byte characters
+        let automaton = Automaton::make_wildcard("?".as_bytes());
+        for c in &["a", "é", "€", "𝄞"] {
+            assert!(automaton.run(c.as_bytes()), "{}", c);
+        }
+        assert!(!automaton.run("ab".as_bytes()));
+        assert!(!automaton.run("éa".as_bytes()));
+        assert!(!automaton.run(&"é".as_bytes()[..1]));
+
+        let automaton = Automaton::make_wildcard("*?é?".as_bytes());
+        assert!(automaton.run("€éa".as_bytes()));
+        assert!(automaton.run("x€é𝄞".as_bytes()));
+        assert!(!automaton.run("éé".as_bytes()));
+    }
+
+    #[test]
+    fn test_make_fuzzy_prefix() {
+        let automaton = Automaton::make_fuzzy_prefix(b"recieve", 1, 1).unwrap();
+        assert!(automaton.check_deterministic().is_ok());
+        assert!(automaton.run(b"recieve"));
+        assert!(automaton.run(b"recieved"));
+        // a transposition, a substitution, an insertion and a deletion
+        assert!(automaton.run(b"receive"));
+        assert!(automaton.run(b"receiver"));
+        assert!(automaton.run(b"recxeve"));
+        assert!(automaton.run(b"reciieve"));
+        assert!(automaton.run(b"recive"));
+        assert!(!automaton.run(b"rexxeve"));
+        assert!(!automaton.run(b"rec"));
+        // the first byte must match exactly
+        assert!(!automaton.run(b"xecieve"));
+        let automaton = Automaton::make_fuzzy_prefix(b"recieve", 1, 0).unwrap();
+        assert!(automaton.run(b"xecieve"));
+
+        let automaton = Automaton::make_fuzzy_prefix(b"ab", 0, 0).unwrap();
+        assert!(automaton.run(b"ab"));
+        assert!(automaton.run(b"abc"));
+        assert!(!automaton.run(b"ba"));
+        assert!(!automaton.run(b"a"));
+
+        let automaton = Automaton::make_fuzzy_prefix(b"abcd", 2, 0).unwrap();
+        assert!(automaton.run(b"bacdx"));
+        assert!(automaton.run(b"cd"));
+        assert!(!automaton.run(b"d"));
+        assert!(Automaton::make_fuzzy_prefix(b"abcd", 3, 0).is_err());
+    }
+
+    #[test]
+    fn test_make_fuzzy() {
+        let automaton = Automaton::make_fuzzy(b"recieve", 1, 0).unwrap();
+        assert!(automaton.check_deterministic().is_ok());
+        assert!(automaton.run(b"recieve"));
+        assert!(automaton.run(b"receive"));
+        assert!(automaton.run(b"recieved"));
+        assert!(automaton.run(b"recive"));
+        assert!(automaton.run(b"xecieve"));
+        // unlike a fuzzy prefix the whole term is matched
+        assert!(!automaton.run(b"receiver"));
+        assert!(!automaton.run(b"rec"));
+
+        let automaton = Automaton::make_fuzzy(b"ab", 1, 0).unwrap();
+        assert!(!automaton.run(b""));
+        assert!(automaton.run(b"a"));
+        assert!(automaton.run(b"abc"));
+        assert!(!automaton.run(b"abcd"));
+    }
+
+    #[test]
+    fn test_make_fuzzy_matches_code_points() {
+        // replacing `é` by `e` is a single edit though their encodings differ by two bytes
+        let automaton = Automaton::make_fuzzy("café".as_bytes(), 1, 0).unwrap();
+        assert!(automaton.check_deterministic().is_ok());
+        for term in &["café", "cafe", "cafè", "caf", "cafés", "cfé", "acfé"] {
+            assert!(automaton.run(term.as_bytes()), "{}", term);
+        }
+        for term in &["cfe", "cafeè", "ca"] {
+            assert!(!automaton.run(term.as_bytes()), "{}", term);
+        }
+        // nor is a part of a character matched
+        assert!(!automaton.run(&"cafè".as_bytes()[..4]));
+
+        let automaton = Automaton::make_fuzzy_prefix("日本".as_bytes(), 1, 1).unwrap();
+        assert!(automaton.check_deterministic().is_ok());
+        for term in &["日本語", "日本", "日", "日x本", "日语"] {
+            assert!(automaton.run(term.as_bytes()), "{}", term);
+        }
+        assert!(!automaton.run("本日".as_bytes()));
+        let automaton = Automaton::make_fuzzy_prefix("日本".as_bytes(), 1, 0).unwrap();
+        assert!(automaton.run("本日".as_bytes()));
+        assert!(automaton.run("𝄞本".as_bytes()));
+
+        assert!(Automaton::make_fuzzy(&[0xC3], 1, 0).is_err());
+    }
+}
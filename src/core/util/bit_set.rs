@@ -341,6 +341,48 @@ impl FixedBitSet {
             this_arr[i] |= other_arr[i];
         }
     }
+
+    /// this = this AND other, bits beyond the length of `other` are cleared
+    pub fn set_and(&mut self, other: &FixedBitSet) {
+        let pos = self.num_words.min(other.num_words);
+        for i in 0..pos {
+            self.bits[i] &= other.bits[i];
+        }
+        for word in &mut self.bits[pos..self.num_words] {
+            *word = 0;
+        }
+    }
+
+    /// this = this AND NOT other
+    pub fn set_and_not(&mut self, other: &FixedBitSet) {
+        let pos = self.num_words.min(other.num_words);
+        for i in 0..pos {
+            self.bits[i] &= !other.bits[i];
+        }
+    }
+
+    /// Returns the index of the last set bit before or on the index specified,
+    /// -1 is returned if there are no more set bits.
+    pub fn prev_set_bit(&self, index: usize) -> i32 {
+        debug_assert!(index < self.num_bits);
+        let mut i = (index >> 6) as i32;
+        let sub_index = (index & 0x3f) as u32;
+        // skip all the bits to the left of index
+        let word = self.bits[i as usize] << (63 - sub_index);
+        if word != 0 {
+            return (i << 6) + sub_index as i32 - word.leading_zeros() as i32;
+        }
+        loop {
+            i -= 1;
+            if i < 0 {
+                return -1;
+            }
+            let word = self.bits[i as usize];
+            if word != 0 {
+                return (i << 6) + 63 - word.leading_zeros() as i32;
+            }
+        }
+    }
 }
 
 impl ImmutableBitSet for FixedBitSet {
@@ -482,3 +524,59 @@ pub fn bits2words(num_bits: usize) -> usize {
     // I.e.: get the word-offset of the last bit and add one (make sure to use >> so 0 returns 0!)
     (((num_bits - 1) >> 6) + 1) as usize
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bit_set(num_bits: usize, bits: &[usize]) -> FixedBitSet {
+        let mut set = FixedBitSet::new(num_bits);
+        for &bit in bits {
+            set.set(bit);
+        }
+        set
+    }
+
+    #[test]
+    fn test_next_and_prev_set_bit() {
+        let set = bit_set(200, &[0, 63, 64, 130, 199]);
+        assert_eq!(set.cardinality(), 5);
+        assert_eq!(set.next_set_bit(0), 0);
+        assert_eq!(set.next_set_bit(1), 63);
+        assert_eq!(set.next_set_bit(65), 130);
+        assert_eq!(set.next_set_bit(131), 199);
+        assert_eq!(bit_set(200, &[10]).next_set_bit(11), NO_MORE_DOCS);
+
+        assert_eq!(set.prev_set_bit(199), 199);
+        assert_eq!(set.prev_set_bit(198), 130);
+        assert_eq!(set.prev_set_bit(129), 64);
+        assert_eq!(set.prev_set_bit(63), 63);
+        assert_eq!(set.prev_set_bit(62), 0);
+        assert_eq!(bit_set(200, &[150]).prev_set_bit(149), -1);
+    }
+
+    #[test]
+    fn test_set_operations() {
+        let other = bit_set(100, &[1, 3, 70]);
+
+        let mut and = bit_set(200, &[1, 2, 70, 150]);
+        and.set_and(&other);
+        let bits: Vec<i32> = BitSetIterator::new(&and).collect();
+        assert_eq!(bits, vec![1, 70]);
+
+        let mut and_not = bit_set(200, &[1, 2, 70, 150]);
+        and_not.set_and_not(&other);
+        let bits: Vec<i32> = BitSetIterator::new(&and_not).collect();
+        assert_eq!(bits, vec![2, 150]);
+
+        let mut or = bit_set(200, &[2, 150]);
+        or.set_or(&other);
+        let bits: Vec<i32> = BitSetIterator::new(&or).collect();
+        assert_eq!(bits, vec![1, 2, 3, 70, 150]);
+
+        or.clear(3);
+        assert!(!or.get(3).unwrap());
+        assert!(or.get(2).unwrap());
+        assert_eq!(or.cardinality(), 4);
+    }
+}
@@ -108,15 +108,41 @@ impl BytesRefBuilder {
         &mut self.buffer
     }
 
+    /// Ensures the buffer holds at least `size` bytes, it's never shrunk. The allocation
+    /// grows geometrically so appending byte by byte is amortized O(1).
     pub fn grow(&mut self, size: usize) {
-        self.buffer.resize(size, 0u8);
+        if self.buffer.len() < size {
+            self.buffer.resize(size, 0u8);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.length
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Sets the length of the built bytes, growing the buffer with zeros if needed.
+    pub fn set_length(&mut self, length: usize) {
+        self.grow(self.offset + length);
+        self.length = length;
+    }
+
+    /// Resets the length to 0 and keeps the buffer for reuse.
+    pub fn clear(&mut self) {
+        self.offset = 0;
+        self.length = 0;
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.buffer[self.offset..self.offset + self.length]
     }
 
     pub fn append(&mut self, b: u8) {
         let pos = self.offset + self.length;
-        if pos >= self.buffer.len() {
-            self.buffer.resize(pos + 1, 0u8);
-        }
+        self.grow(pos + 1);
         self.buffer[pos] = b;
         self.length += 1;
     }
@@ -124,23 +150,67 @@ impl BytesRefBuilder {
     pub fn appends(&mut self, bytes: &[u8]) {
         let start = self.offset + self.length;
         let end = start + bytes.len();
-        if end >= self.buffer.len() {
-            self.buffer.resize(end, 0u8);
-        }
+        self.grow(end);
         self.buffer[start..end].copy_from_slice(bytes);
         self.length += bytes.len();
     }
 
     pub fn get(&self) -> BytesRef {
-        BytesRef::new(&self.buffer[self.offset..self.length])
+        BytesRef::new(self.bytes())
     }
 
     pub fn copy_from(&mut self, bytes: &[u8]) {
-        if self.buffer.len() < bytes.len() {
-            self.buffer.resize(bytes.len(), 0u8);
-        }
+        self.grow(bytes.len());
         self.buffer[0..bytes.len()].copy_from_slice(bytes);
         self.offset = 0;
         self.length = bytes.len();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_term_incrementally() {
+        let mut builder = BytesRefBuilder::new();
+        assert!(builder.is_empty());
+        builder.appends(b"foo");
+        builder.append(b'_');
+        builder.appends(b"bar");
+        assert_eq!(builder.get().bytes(), b"foo_bar");
+
+        builder.set_length(3);
+        assert_eq!(builder.bytes(), b"foo");
+        builder.appends(b"d");
+        assert_eq!(builder.get().bytes(), b"food");
+        builder.set_length(6);
+        assert_eq!(builder.bytes(), b"food_b");
+
+        builder.clear();
+        assert!(builder.is_empty());
+        builder.copy_from(b"xy");
+        assert_eq!(builder.get().bytes(), b"xy");
+        // a shorter length never shrinks the buffer
+        builder.grow(1);
+        assert!(builder.buffer.len() >= 7);
+    }
+
+    #[test]
+    fn test_capacity_grows_geometrically() {
+        let mut builder = BytesRefBuilder::new();
+        let mut reallocations = 0;
+        let mut capacity = builder.buffer.capacity();
+        for i in 0..100_000 {
+            builder.append((i % 256) as u8);
+            if builder.buffer.capacity() != capacity {
+                reallocations += 1;
+                capacity = builder.buffer.capacity();
+            }
+        }
+        assert_eq!(builder.len(), 100_000);
+        assert_eq!(builder.bytes()[257], 1);
+        // about log2(100_000) reallocations, not one per byte
+        assert!(reallocations <= 20, "{} reallocations", reallocations);
+    }
+}
@@ -16,6 +16,7 @@ use error::Result;
 use core::search::{DocIdSet, DocIterator, NO_MORE_DOCS};
 use core::util::bit_set::{FixedBitSet, ImmutableBitSet};
 use core::util::packed::{EliasFanoDecoder, EliasFanoEncoder, NO_MORE_VALUES};
+use core::util::Bits;
 use core::util::DocId;
 use error::ErrorKind::*;
 use std::borrow::Cow;
@@ -37,6 +38,12 @@ impl<T: ImmutableBitSet> BitDocIdSet<T> {
     }
 }
 
+impl BitDocIdSet<FixedBitSet> {
+    pub fn ram_bytes_used_estimate(&self) -> usize {
+        self.set.bits.capacity() * 8
+    }
+}
+
 impl<T: ImmutableBitSet + 'static> DocIdSet for BitDocIdSet<T> {
     type Iter = BitSetDocIterator<T>;
     fn iterator(&self) -> Result<Option<Self::Iter>> {
@@ -46,9 +53,9 @@ impl<T: ImmutableBitSet + 'static> DocIdSet for BitDocIdSet<T> {
         )?))
     }
 
-    //    fn bits(&self) -> Result<Option<ImmutableBitSetRef>> {
-    //        Ok(Some(Arc::clone(&self.set)))
-    //    }
+    fn bits(&self) -> Option<&dyn Bits> {
+        Some(self.set.as_ref())
+    }
 }
 
 pub struct BitSetDocIterator<T: ImmutableBitSet> {
@@ -112,6 +119,10 @@ impl IntArrayDocIdSet {
             length,
         }
     }
+
+    pub fn ram_bytes_used_estimate(&self) -> usize {
+        self.docs.capacity() * 4
+    }
 }
 
 impl DocIdSet for IntArrayDocIdSet {
@@ -184,6 +195,17 @@ pub enum DocIdSetEnum {
     BitDocId(BitDocIdSet<FixedBitSet>),
 }
 
+impl DocIdSetEnum {
+    pub fn ram_bytes_used_estimate(&self) -> usize {
+        match self {
+            DocIdSetEnum::ShortArray(s) => s.ram_bytes_used_estimate(),
+            DocIdSetEnum::IntArray(s) => s.ram_bytes_used_estimate(),
+            DocIdSetEnum::NotDocId(s) => s.set.ram_bytes_used_estimate(),
+            DocIdSetEnum::BitDocId(s) => s.ram_bytes_used_estimate(),
+        }
+    }
+}
+
 impl DocIdSet for DocIdSetEnum {
     type Iter = DocIdSetDocIterEnum;
     fn iterator(&self) -> Result<Option<Self::Iter>> {
@@ -194,6 +216,13 @@ impl DocIdSet for DocIdSetEnum {
             DocIdSetEnum::BitDocId(s) => Ok(s.iterator()?.map(DocIdSetDocIterEnum::BitDocId)),
         }
     }
+
+    fn bits(&self) -> Option<&dyn Bits> {
+        match self {
+            DocIdSetEnum::BitDocId(s) => s.bits(),
+            _ => None,
+        }
+    }
 }
 
 pub struct ShortArrayDocIdSet {
@@ -208,6 +237,10 @@ impl ShortArrayDocIdSet {
             length,
         }
     }
+
+    pub fn ram_bytes_used_estimate(&self) -> usize {
+        self.docs.capacity() * 2
+    }
 }
 
 impl DocIdSet for ShortArrayDocIdSet {
@@ -558,7 +591,31 @@ impl DocIterator for DocIdSetDocIterEnum {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use core::util::bit_set::BitSet;
     use error::Result;
+
+    #[test]
+    fn test_bit_doc_id_set_bits() -> Result<()> {
+        let mut set = FixedBitSet::new(10);
+        set.set(1);
+        set.set(4);
+        let doc_id_set = DocIdSetEnum::BitDocId(BitDocIdSet::with_bits(Arc::new(set)));
+        {
+            let bits = doc_id_set.bits().unwrap();
+            assert!(bits.get(1)?);
+            assert!(!bits.get(2)?);
+            assert!(bits.get(4)?);
+        }
+        let mut docs = doc_id_set.iterator()?.unwrap();
+        assert_eq!(docs.next()?, 1);
+        assert_eq!(docs.next()?, 4);
+        assert_eq!(docs.next()?, NO_MORE_DOCS);
+
+        // array backed sets have no random access
+        let doc_id_set = IntArrayDocIdSet::new(vec![1, 4, NO_MORE_DOCS], 2);
+        assert!(doc_id_set.bits().is_none());
+        Ok(())
+    }
     #[test]
     fn ef_doc_id_set() -> Result<()> {
         let doc_ids = IntArrayDocIdSet::new(vec![2, 3, 5, 7, 11, 13, 24, NO_MORE_DOCS], 7)
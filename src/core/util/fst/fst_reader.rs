@@ -15,7 +15,7 @@ use std::cmp::max;
 use std::io;
 
 use core::codec::{check_header, write_header};
-use core::store::io::{ByteArrayDataOutput, DataInput, DataOutput};
+use core::store::io::{ByteArrayDataOutput, DataInput, DataOutput, IndexInput, RandomAccessInput};
 use core::util::fst::bytes_store::{BytesStore, StoreBytesReader};
 use core::util::fst::fst_builder::{FstBuilder, Node};
 use core::util::fst::DirectionalBytesReader;
@@ -180,6 +180,8 @@ pub struct FST<F: OutputFactory> {
     bytes_array: Vec<u8>,
     // flag of whether use bytes_store or bytes_array
     use_bytes_array: bool,
+    // Used at read time instead of the above when the FST was loaded off heap.
+    off_heap_bytes: Option<OffHeapBytes>,
     start_node: CompiledAddress,
     version: i32,
     output_factory: F,
@@ -196,6 +198,7 @@ impl<F: OutputFactory> FST<F> {
             bytes_store,
             bytes_array: Vec::with_capacity(0),
             use_bytes_array: false,
+            off_heap_bytes: None,
             start_node: -1,
             version: VERSION_CURRENT,
             output_factory,
@@ -204,9 +207,39 @@ impl<F: OutputFactory> FST<F> {
     }
 
     pub fn from_input<I: DataInput + ?Sized>(data_in: &mut I, output_factory: F) -> Result<Self> {
-        let output_factory = output_factory;
         let max_block_bits = DEFAULT_MAX_BLOCK_BITS;
+        let (mut fst, num_bytes) = Self::read_meta(data_in, output_factory)?;
 
+        if num_bytes > (1 << max_block_bits as i64) {
+            // FST is big: we need multiple pages
+            fst.bytes_store = BytesStore::new(data_in, num_bytes as usize, 1 << max_block_bits)?;
+            fst.use_bytes_array = false;
+        } else {
+            fst.bytes_array = vec![0u8; num_bytes as usize];
+            data_in.read_exact(&mut fst.bytes_array)?;
+        }
+        Ok(fst)
+    }
+
+    /// Load a FST without copying its arcs onto the heap: they are read on demand
+    /// from a random access slice of `data_in`, which is cheap when the file is mmapped.
+    /// `data_in` is left positioned right after the FST, as with `from_input`.
+    pub fn from_input_off_heap(data_in: &mut dyn IndexInput, output_factory: F) -> Result<Self> {
+        let (mut fst, num_bytes) = Self::read_meta(data_in, output_factory)?;
+
+        let start = data_in.file_pointer();
+        let input = data_in.random_access_slice(start, num_bytes)?;
+        data_in.seek(start + num_bytes)?;
+        fst.off_heap_bytes = Some(OffHeapBytes {
+            input: ::std::sync::Arc::from(input),
+            len: num_bytes as usize,
+        });
+        Ok(fst)
+    }
+
+    /// Reads everything but the arcs, returning a FST with no bytes loaded yet
+    /// together with the number of arc bytes that follow in `data_in`.
+    fn read_meta<I: DataInput + ?Sized>(data_in: &mut I, output_factory: F) -> Result<(Self, i64)> {
         // Only reads most recent format; we don't have
         // back-compat promise for FSTs (they are experimental):
         let version = check_header(data_in, FILE_FORMAT_NAME, VERSION_PACKED, VERSION_CURRENT)?;
@@ -249,35 +282,27 @@ impl<F: OutputFactory> FST<F> {
         }
 
         let num_bytes = data_in.read_vlong()?;
-        let bytes_store: BytesStore;
-        let mut bytes_array: Vec<u8>;
-        let use_bytes_array: bool;
-
-        if num_bytes > (1 << max_block_bits as i64) {
-            // FST is big: we need multiple pages
-            bytes_store = BytesStore::new(data_in, num_bytes as usize, 1 << max_block_bits)?;
-            bytes_array = Vec::with_capacity(0);
-            use_bytes_array = false;
-        } else {
-            let len = num_bytes as usize;
-            bytes_array = vec![0u8; len];
-            data_in.read_exact(&mut bytes_array)?;
-            // a dummy struct
-            bytes_store = BytesStore::with_block_bits(8);
-            use_bytes_array = true;
-        };
+        if num_bytes < 0 {
+            bail!(ErrorKind::CorruptIndex(format!(
+                "invalid FST num_bytes: {}",
+                num_bytes
+            )));
+        }
 
-        Ok(FST {
+        let fst = FST {
             input_type,
             start_node,
             version,
             output_factory,
-            bytes_store,
-            use_bytes_array,
+            // a dummy struct
+            bytes_store: BytesStore::with_block_bits(8),
+            use_bytes_array: true,
             empty_output,
-            bytes_array,
+            bytes_array: Vec::with_capacity(0),
+            off_heap_bytes: None,
             cached_root_arcs: Vec::with_capacity(0),
-        })
+        };
+        Ok((fst, num_bytes))
     }
 
     pub fn outputs(&self) -> &F {
@@ -380,7 +405,11 @@ impl<F: OutputFactory> FST<F> {
     }
 
     pub fn bytes_reader(&self) -> FSTBytesReader {
-        if self.use_bytes_array {
+        if let Some(ref bytes) = self.off_heap_bytes {
+            FSTBytesReader::OffHeap(OffHeapBytesReader::new(::std::sync::Arc::clone(
+                &bytes.input,
+            )))
+        } else if self.use_bytes_array {
             FSTBytesReader::Directional(DirectionalBytesReader::new(&self.bytes_array, true))
         } else {
             FSTBytesReader::BytesStore(self.bytes_store.get_reverse_reader())
@@ -978,7 +1007,12 @@ impl<F: OutputFactory> FST<F> {
         };
         out.write_byte(t)?;
         out.write_vlong(self.start_node)?;
-        if self.bytes_store.get_position() > 0 {
+        if let Some(ref bytes) = self.off_heap_bytes {
+            out.write_vlong(bytes.len as i64)?;
+            for pos in 0..bytes.len {
+                out.write_byte(bytes.input.read_byte(pos as u64)?)?;
+            }
+        } else if self.bytes_store.get_position() > 0 {
             debug_assert!(!self.use_bytes_array);
             let num_bytes = self.bytes_store.get_position();
             out.write_vlong(num_bytes as i64)?;
@@ -1002,6 +1036,7 @@ impl<F: OutputFactory + Default> Default for FST<F> {
             bytes_store: BytesStore::with_block_bits(1),
             bytes_array: Vec::with_capacity(0),
             use_bytes_array: true,
+            off_heap_bytes: None,
             start_node: 0,
             version: VERSION_CURRENT,
             output_factory: F::default(),
@@ -1010,9 +1045,68 @@ impl<F: OutputFactory + Default> Default for FST<F> {
     }
 }
 
+struct OffHeapBytes {
+    input: ::std::sync::Arc<dyn RandomAccessInput>,
+    len: usize,
+}
+
+/// Reversed `BytesReader` over the arcs of an off heap FST, see `FST::from_input_off_heap`.
+pub struct OffHeapBytesReader {
+    input: ::std::sync::Arc<dyn RandomAccessInput>,
+    pos: usize,
+}
+
+impl OffHeapBytesReader {
+    fn new(input: ::std::sync::Arc<dyn RandomAccessInput>) -> OffHeapBytesReader {
+        OffHeapBytesReader { input, pos: 0 }
+    }
+}
+
+impl BytesReader for OffHeapBytesReader {
+    fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn set_position(&mut self, pos: usize) {
+        self.pos = pos
+    }
+
+    fn reversed(&self) -> bool {
+        true
+    }
+}
+
+impl io::Read for OffHeapBytesReader {
+    fn read(&mut self, b: &mut [u8]) -> io::Result<usize> {
+        let len = b.len().min(self.pos);
+        for v in b.iter_mut().take(len) {
+            *v = self
+                .input
+                .read_byte(self.pos as u64)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+            self.pos -= 1;
+        }
+        Ok(len)
+    }
+}
+
+impl DataInput for OffHeapBytesReader {
+    fn read_byte(&mut self) -> Result<u8> {
+        let b = self.input.read_byte(self.pos as u64)?;
+        self.pos -= 1;
+        Ok(b)
+    }
+
+    fn skip_bytes(&mut self, count: usize) -> Result<()> {
+        self.pos -= count;
+        Ok(())
+    }
+}
+
 pub enum FSTBytesReader {
     Directional(DirectionalBytesReader),
     BytesStore(StoreBytesReader),
+    OffHeap(OffHeapBytesReader),
 }
 
 impl BytesReader for FSTBytesReader {
@@ -1020,6 +1114,7 @@ impl BytesReader for FSTBytesReader {
         match *self {
             FSTBytesReader::Directional(ref d) => d.position(),
             FSTBytesReader::BytesStore(ref b) => b.position(),
+            FSTBytesReader::OffHeap(ref o) => o.position(),
         }
     }
 
@@ -1027,6 +1122,7 @@ impl BytesReader for FSTBytesReader {
         match *self {
             FSTBytesReader::Directional(ref mut d) => d.set_position(pos),
             FSTBytesReader::BytesStore(ref mut b) => b.set_position(pos),
+            FSTBytesReader::OffHeap(ref mut o) => o.set_position(pos),
         }
     }
 
@@ -1034,6 +1130,7 @@ impl BytesReader for FSTBytesReader {
         match *self {
             FSTBytesReader::Directional(ref d) => d.reversed(),
             FSTBytesReader::BytesStore(ref b) => b.reversed(),
+            FSTBytesReader::OffHeap(ref o) => o.reversed(),
         }
     }
 }
@@ -1043,6 +1140,7 @@ impl io::Read for FSTBytesReader {
         match *self {
             FSTBytesReader::Directional(ref mut d) => d.read(buf),
             FSTBytesReader::BytesStore(ref mut b) => b.read(buf),
+            FSTBytesReader::OffHeap(ref mut o) => o.read(buf),
         }
     }
 }
@@ -1052,6 +1150,7 @@ impl DataInput for FSTBytesReader {
         match *self {
             FSTBytesReader::Directional(ref mut d) => d.read_byte(),
             FSTBytesReader::BytesStore(ref mut b) => b.read_byte(),
+            FSTBytesReader::OffHeap(ref mut o) => o.read_byte(),
         }
     }
 
@@ -1059,6 +1158,7 @@ impl DataInput for FSTBytesReader {
         match *self {
             FSTBytesReader::Directional(ref mut d) => d.read_bytes(b, offset, length),
             FSTBytesReader::BytesStore(ref mut r) => r.read_bytes(b, offset, length),
+            FSTBytesReader::OffHeap(ref mut o) => o.read_bytes(b, offset, length),
         }
     }
 
@@ -1066,6 +1166,7 @@ impl DataInput for FSTBytesReader {
         match *self {
             FSTBytesReader::Directional(ref mut d) => d.skip_bytes(count),
             FSTBytesReader::BytesStore(ref mut b) => b.skip_bytes(count),
+            FSTBytesReader::OffHeap(ref mut o) => o.skip_bytes(count),
         }
     }
 }
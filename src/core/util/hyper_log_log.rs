@@ -0,0 +1,201 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use error::{ErrorKind::IllegalArgument, Result};
+
+use fasthash::murmur3;
+
+pub const MIN_HLL_PRECISION: u8 = 4;
+pub const MAX_HLL_PRECISION: u8 = 18;
+pub const DEFAULT_HLL_PRECISION: u8 = 14;
+
+// the cardinalities up to which linear counting is more accurate than the raw estimate,
+// indexed by `precision - MIN_HLL_PRECISION`, as measured by the HyperLogLog++ paper
+const LINEAR_COUNTING_THRESHOLDS: [f64; 15] = [
+    10.0, 20.0, 40.0, 80.0, 220.0, 400.0, 900.0, 1800.0, 3100.0, 6500.0, 11500.0, 20000.0, 50000.0,
+    120_000.0, 350_000.0,
+];
+
+/// Estimates the number of distinct values added to it, using `2^precision` one byte
+/// registers whatever the number of values.
+///
+/// This is HyperLogLog with the 64 bits hashes and the linear counting thresholds of
+/// HyperLogLog++. The sparse representation and the empirical bias correction are not
+/// implemented, so the estimate is slightly biased for cardinalities between the
+/// linear counting threshold and `5 * 2^precision`. The relative standard error is
+/// about `1.04 / sqrt(2^precision)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HyperLogLogPlusPlus {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLogPlusPlus {
+    pub fn new(precision: u8) -> Result<Self> {
+        if precision < MIN_HLL_PRECISION || precision > MAX_HLL_PRECISION {
+            bail!(IllegalArgument(format!(
+                "precision must be in [{}, {}], got {}",
+                MIN_HLL_PRECISION, MAX_HLL_PRECISION, precision
+            )));
+        }
+        Ok(HyperLogLogPlusPlus {
+            precision,
+            registers: vec![0; 1 << precision],
+        })
+    }
+
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// The relative standard error of the estimates for this precision.
+    pub fn relative_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+
+    pub fn add_long(&mut self, value: i64) {
+        self.add_hash(mix64(value as u64));
+    }
+
+    pub fn add_bytes(&mut self, bytes: &[u8]) {
+        self.add_hash(murmur3::hash128(bytes) as u64);
+    }
+
+    /// Adds a value by its well distributed 64 bits hash.
+    pub fn add_hash(&mut self, hash: u64) {
+        let index = (hash >> (64 - self.precision)) as usize;
+        // the sentinel bit bounds the rank when the remaining bits are all zero
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Adds the values of `other` to this estimator, as if they had been added here.
+    pub fn merge(&mut self, other: &HyperLogLogPlusPlus) -> Result<()> {
+        if other.precision != self.precision {
+            bail!(IllegalArgument(format!(
+                "can't merge precision {} into precision {}",
+                other.precision, self.precision
+            )));
+        }
+        for (register, &rank) in self.registers.iter_mut().zip(&other.registers) {
+            if rank > *register {
+                *register = rank;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the estimated number of distinct values added.
+    pub fn cardinality(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let mut sum = 0f64;
+        let mut zeros = 0usize;
+        for &rank in &self.registers {
+            sum += 1.0 / (1u64 << rank) as f64;
+            if rank == 0 {
+                zeros += 1;
+            }
+        }
+
+        if zeros > 0 {
+            let linear_count = m * (m / zeros as f64).ln();
+            let threshold =
+                LINEAR_COUNTING_THRESHOLDS[(self.precision - MIN_HLL_PRECISION) as usize];
+            if linear_count <= threshold {
+                return linear_count.round() as u64;
+            }
+        }
+        (alpha(self.registers.len()) * m * m / sum).round() as u64
+    }
+}
+
+fn alpha(m: usize) -> f64 {
+    match m {
+        16 => 0.673,
+        32 => 0.697,
+        64 => 0.709,
+        _ => 0.7213 / (1.0 + 1.079 / m as f64),
+    }
+}
+
+// the finalizer of murmur3, spreads the bits of a long over the whole hash
+fn mix64(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    k ^= k >> 33;
+    k
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_estimate(hll: &HyperLogLogPlusPlus, expected: u64) {
+        let error = (hll.cardinality() as f64 - expected as f64).abs() / expected as f64;
+        // three standard errors
+        assert!(
+            error <= 3.0 * hll.relative_error(),
+            "estimated {} for {} distinct values",
+            hll.cardinality(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_cardinality() {
+        assert!(HyperLogLogPlusPlus::new(MIN_HLL_PRECISION - 1).is_err());
+        assert!(HyperLogLogPlusPlus::new(MAX_HLL_PRECISION + 1).is_err());
+
+        let mut hll = HyperLogLogPlusPlus::new(DEFAULT_HLL_PRECISION).unwrap();
+        assert_eq!(hll.cardinality(), 0);
+        // duplicates don't count
+        for _ in 0..3 {
+            for v in 0..1000 {
+                hll.add_long(v * 7);
+            }
+        }
+        assert_estimate(&hll, 1000);
+
+        // past the linear counting range
+        let mut hll = HyperLogLogPlusPlus::new(10).unwrap();
+        for v in 0..100_000 {
+            hll.add_bytes(format!("value{}", v).as_bytes());
+        }
+        assert_estimate(&hll, 100_000);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut a = HyperLogLogPlusPlus::new(12).unwrap();
+        let mut b = HyperLogLogPlusPlus::new(12).unwrap();
+        let mut all = HyperLogLogPlusPlus::new(12).unwrap();
+        for v in 0..30_000 {
+            if v % 2 == 0 {
+                a.add_long(v);
+            } else {
+                b.add_long(v);
+            }
+            all.add_long(v);
+        }
+        a.merge(&b).unwrap();
+        assert_eq!(a, all);
+        assert_estimate(&a, 30_000);
+
+        assert!(a.merge(&HyperLogLogPlusPlus::new(13).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Receives the diagnostic messages of an `IndexWriter` and its components, to debug
+/// how the index is flushed, merged and committed.
+///
+/// Each message is tagged with the component emitting it: "IW" for the `IndexWriter`,
+/// "DWPT" for the per thread documents writers, "CMS" for the `ConcurrentMergeScheduler`
+/// and "MP" for the merge policy.
+pub trait InfoStream: Send + Sync {
+    /// Handles a message of `component`, only called if `is_enabled(component)`.
+    fn message(&self, component: &str, message: &str);
+
+    /// Returns true if the messages of `component` should be emitted.
+    fn is_enabled(&self, component: &str) -> bool;
+}
+
+impl dyn InfoStream {
+    /// Sends the message built by `msg` as a message of `component` if its messages are
+    /// enabled, so that a message is only formatted when it is emitted.
+    pub fn log<F: FnOnce() -> String>(&self, component: &str, msg: F) {
+        if self.is_enabled(component) {
+            self.message(component, &msg());
+        }
+    }
+}
+
+/// An `InfoStream` dropping every message, the default of `IndexWriterConfig`.
+pub struct NoOutputInfoStream;
+
+impl InfoStream for NoOutputInfoStream {
+    fn message(&self, _component: &str, _message: &str) {
+        debug_assert!(
+            false,
+            "message() should not be called when is_enabled is false"
+        );
+    }
+
+    fn is_enabled(&self, _component: &str) -> bool {
+        false
+    }
+}
@@ -13,6 +13,7 @@
 
 pub type DocId = i32;
 
+pub mod automaton;
 pub mod bkd;
 pub mod external;
 pub mod fst;
@@ -58,6 +59,10 @@ mod bit_set;
 
 pub use self::bit_set::*;
 
+mod sparse_fixed_bit_set;
+
+pub use self::sparse_fixed_bit_set::*;
+
 mod bit_util;
 
 pub use self::bit_util::*;
@@ -78,6 +83,10 @@ mod doc_id_set;
 
 pub use self::doc_id_set::*;
 
+mod info_stream;
+
+pub use self::info_stream::*;
+
 mod int_block_pool;
 
 pub use self::int_block_pool::*;
@@ -114,6 +123,10 @@ mod disi;
 
 pub use self::disi::*;
 
+mod hyper_log_log;
+
+pub use self::hyper_log_log::*;
+
 use std::ops::Deref;
 
 use core::codec::doc_values::NumericDocValues;
@@ -12,14 +12,17 @@
 // limitations under the License.
 
 use core::codec::doc_values::NumericDocValues;
+use core::codec::{check_header, write_header};
+use core::store::io::{DataInput, DataOutput};
 use core::util::bit_util::BitsRequired;
 use core::util::packed::MonotonicBlockPackedReader;
 use core::util::packed::{
-    check_block_size, get_mutable_by_ratio, Mutable, MutableEnum, PackedIntsNullMutable, Reader,
+    check_block_size, get_mutable_by_ratio, get_reader, Mutable, MutableEnum,
+    PackedIntsNullMutable, Reader,
 };
 use core::util::{DocId, LongValues, ReusableIterator};
 
-use error::Result;
+use error::{ErrorKind::CorruptIndex, Result};
 use std::mem;
 
 pub const DEFAULT_PAGE_SIZE: usize = 1024;
@@ -28,6 +31,12 @@ pub const MIN_PAGE_SIZE: usize = 64;
 // since their goal is to try to have small numbers of bits per value
 pub const MAX_PAGE_SIZE: usize = 1 << 20;
 
+// the header of the format written by `PackedLongValues::save`, which is kept readable by
+// later versions. Any change of the values it stores needs a new version.
+const CODEC_NAME: &str = "PackedLongValues";
+const VERSION_START: i32 = 0;
+const VERSION_CURRENT: i32 = VERSION_START;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
 pub enum PackedLongValuesBuilderType {
     Default,
@@ -35,6 +44,28 @@ pub enum PackedLongValuesBuilderType {
     Monotonic,
 }
 
+impl PackedLongValuesBuilderType {
+    fn id(self) -> u8 {
+        match self {
+            PackedLongValuesBuilderType::Default => 0,
+            PackedLongValuesBuilderType::Delta => 1,
+            PackedLongValuesBuilderType::Monotonic => 2,
+        }
+    }
+
+    fn with_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(PackedLongValuesBuilderType::Default),
+            1 => Ok(PackedLongValuesBuilderType::Delta),
+            2 => Ok(PackedLongValuesBuilderType::Monotonic),
+            _ => bail!(CorruptIndex(format!(
+                "invalid packed long values type: {}",
+                id
+            ))),
+        }
+    }
+}
+
 /// Utility class to compress integers into a `LongValues` instance.
 pub struct PackedLongValues {
     page_shift: usize,
@@ -140,6 +171,108 @@ impl PackedLongValues {
     pub fn iterator(&self) -> LongValuesIterator {
         LongValuesIterator::new(self)
     }
+
+    /// Writes the values behind a versioned header, so that they can be cached outside of
+    /// the process and read back with `from_input`, even by later versions.
+    pub fn save(&self, out: &mut impl DataOutput) -> Result<()> {
+        write_header(out, CODEC_NAME, VERSION_CURRENT)?;
+        out.write_byte(self.builder_type.id())?;
+        out.write_vint(self.page_shift as i32)?;
+        out.write_vlong(self.size)?;
+        out.write_vint(self.values.len() as i32)?;
+        for (block, values) in self.values.iter().enumerate() {
+            if let MutableEnum::PackedIntsNull(ref values) = *values {
+                // a block of zeros has nothing to pack
+                out.write_byte(0)?;
+                out.write_vint(values.size() as i32)?;
+            } else {
+                out.write_byte(1)?;
+                values.save(out)?;
+            }
+            match self.builder_type {
+                PackedLongValuesBuilderType::Delta => out.write_zlong(self.mins[block])?,
+                PackedLongValuesBuilderType::Monotonic => {
+                    out.write_zlong(self.mins[block])?;
+                    out.write_int(self.averages[block].to_bits() as i32)?;
+                }
+                PackedLongValuesBuilderType::Default => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads values written by `save`.
+    pub fn from_input<T: DataInput + ?Sized>(input: &mut T) -> Result<PackedLongValues> {
+        check_header(input, CODEC_NAME, VERSION_START, VERSION_CURRENT)?;
+        let builder_type = PackedLongValuesBuilderType::with_id(input.read_byte()?)?;
+        let page_shift = input.read_vint()?;
+        if page_shift < MIN_PAGE_SIZE.trailing_zeros() as i32
+            || page_shift > MAX_PAGE_SIZE.trailing_zeros() as i32
+        {
+            bail!(CorruptIndex(format!("invalid page shift: {}", page_shift)));
+        }
+        let page_shift = page_shift as usize;
+        let page_size = 1usize << page_shift;
+        let size = input.read_vlong()?;
+        let block_count = input.read_vint()?;
+        // every block but the last one is full
+        let fits = block_count >= 0
+            && size >= 0
+            && size <= i64::from(block_count) << page_shift
+            && (block_count == 0 || size > i64::from(block_count - 1) << page_shift);
+        if !fits {
+            bail!(CorruptIndex(format!(
+                "{} blocks of {} values can't hold {} values",
+                block_count, page_size, size
+            )));
+        }
+
+        let block_count = block_count as usize;
+        let mut values = Vec::with_capacity(block_count);
+        let mut mins = vec![];
+        let mut averages = vec![];
+        for block in 0..block_count {
+            let block_values = match input.read_byte()? {
+                0 => MutableEnum::PackedIntsNull(PackedIntsNullMutable::new(
+                    input.read_vint()? as usize
+                )),
+                1 => get_reader(input)?.into_mutable(),
+                b => bail!(CorruptIndex(format!("invalid block marker: {}", b))),
+            };
+            let expected = if block + 1 < block_count {
+                page_size
+            } else {
+                size as usize - (block << page_shift)
+            };
+            if block_values.size() != expected {
+                bail!(CorruptIndex(format!(
+                    "block {} has {} values instead of {}",
+                    block,
+                    block_values.size(),
+                    expected
+                )));
+            }
+            values.push(block_values);
+            match builder_type {
+                PackedLongValuesBuilderType::Delta => mins.push(input.read_zlong()?),
+                PackedLongValuesBuilderType::Monotonic => {
+                    mins.push(input.read_zlong()?);
+                    averages.push(f32::from_bits(input.read_int()? as u32));
+                }
+                PackedLongValuesBuilderType::Default => {}
+            }
+        }
+
+        Ok(PackedLongValues::new(
+            page_shift,
+            page_size - 1,
+            size,
+            values,
+            mins,
+            averages,
+            builder_type,
+        ))
+    }
 }
 
 impl LongValues for PackedLongValues {
@@ -417,3 +550,76 @@ impl PackedLongValuesBuilder {
         size
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::store::io::{ByteArrayDataInput, GrowableByteArrayDataOutput};
+    use core::util::packed::COMPACT;
+
+    fn build(builder_type: PackedLongValuesBuilderType, values: &[i64]) -> PackedLongValues {
+        let mut builder = PackedLongValuesBuilder::new(MIN_PAGE_SIZE, COMPACT, builder_type);
+        for &v in values {
+            builder.add(v);
+        }
+        builder.build()
+    }
+
+    fn save(values: &PackedLongValues) -> Vec<u8> {
+        let mut out = GrowableByteArrayDataOutput::new(0);
+        values.save(&mut out).unwrap();
+        out.bytes[..out.position()].to_vec()
+    }
+
+    #[test]
+    fn test_save_and_read() {
+        // a block of zeros, one with negative values and a partial last block
+        let values: Vec<i64> = (0..200)
+            .map(|i| match i {
+                0..=63 => 0,
+                64..=127 => i * 3 - 250,
+                _ => i * i,
+            })
+            .collect();
+        for &builder_type in &[
+            PackedLongValuesBuilderType::Default,
+            PackedLongValuesBuilderType::Delta,
+            PackedLongValuesBuilderType::Monotonic,
+        ] {
+            let bytes = save(&build(builder_type, &values));
+            let mut input = ByteArrayDataInput::new(&bytes);
+            let read = PackedLongValues::from_input(&mut input).unwrap();
+            assert!(input.eof());
+            assert_eq!(read.size(), 200);
+            for (i, &v) in values.iter().enumerate() {
+                assert_eq!(read.get64(i as i64).unwrap(), v);
+            }
+            assert_eq!(read.iterator().collect::<Vec<i64>>(), values);
+        }
+
+        let bytes = save(&build(PackedLongValuesBuilderType::Delta, &[]));
+        let read = PackedLongValues::from_input(&mut ByteArrayDataInput::new(&bytes)).unwrap();
+        assert_eq!(read.size(), 0);
+    }
+
+    #[test]
+    fn test_read_checks_header() {
+        let bytes = save(&build(PackedLongValuesBuilderType::Delta, &[3, 1, 4]));
+        // the magic, the codec name as a vint length and its bytes, then the version
+        let version_end = 4 + 1 + CODEC_NAME.len() + 4;
+        assert_eq!(bytes[version_end - 1], VERSION_CURRENT as u8);
+
+        let mut newer = bytes.clone();
+        newer[version_end - 1] += 1;
+        assert!(PackedLongValues::from_input(&mut ByteArrayDataInput::new(&newer)).is_err());
+
+        let mut bad_type = bytes.clone();
+        bad_type[version_end] = 3;
+        assert!(PackedLongValues::from_input(&mut ByteArrayDataInput::new(&bad_type)).is_err());
+
+        let mut other_codec = bytes;
+        other_codec[5] = b'X';
+        assert!(PackedLongValues::from_input(&mut ByteArrayDataInput::new(&other_codec)).is_err());
+    }
+}
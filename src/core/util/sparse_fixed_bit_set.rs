@@ -0,0 +1,269 @@
+// Copyright 2019 Zhizhesihai (Beijing) Technology Limited.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::mem;
+
+use core::search::NO_MORE_DOCS;
+use core::util::{BitSet, Bits, ImmutableBitSet};
+
+use error::Result;
+
+/// A bit set that only allocates memory for the 64 bit words that have a bit set.
+///
+/// Bits are grouped in blocks of 4096. For every block, `indices` has one bit per 64 bit
+/// word telling whether that word is non zero, and `bits` only holds these non zero words,
+/// in order. This is much smaller than a `FixedBitSet` when less than about 1/8 of the
+/// bits are set, at the cost of slower updates.
+pub struct SparseFixedBitSet {
+    indices: Vec<u64>,
+    bits: Vec<Vec<u64>>,
+    length: usize,
+    non_zero_long_count: usize,
+}
+
+fn block_count(length: usize) -> usize {
+    let count = length >> 12;
+    if (count << 12) < length {
+        count + 1
+    } else {
+        count
+    }
+}
+
+impl SparseFixedBitSet {
+    /// Create a `SparseFixedBitSet` that can contain bits between `0` included and
+    /// `length` excluded.
+    pub fn new(length: usize) -> SparseFixedBitSet {
+        let block_count = block_count(length);
+        SparseFixedBitSet {
+            indices: vec![0; block_count],
+            bits: vec![Vec::new(); block_count],
+            length,
+            non_zero_long_count: 0,
+        }
+    }
+
+    /// The number of 64 bit words that have at least one bit set.
+    pub fn non_zero_long_count(&self) -> usize {
+        self.non_zero_long_count
+    }
+
+    /// Returns the memory held by this bit set, including the words it allocated space
+    /// for but doesn't use yet.
+    pub fn approximate_ram_bytes_used(&self) -> usize {
+        let words: usize = self.bits.iter().map(Vec::capacity).sum();
+        mem::size_of::<Self>()
+            + self.indices.capacity() * mem::size_of::<u64>()
+            + self.bits.capacity() * mem::size_of::<Vec<u64>>()
+            + words * mem::size_of::<u64>()
+    }
+
+    // the position of word `word_num` of a block in its list of non zero words
+    #[inline]
+    fn word_offset(index: u64, word_num: usize) -> usize {
+        (index & ((1u64 << word_num) - 1)).count_ones() as usize
+    }
+
+    fn first_doc_from_block(&self, first_block: usize) -> i32 {
+        for i4096 in first_block..self.indices.len() {
+            let index = self.indices[i4096];
+            if index != 0 {
+                let word_num = index.trailing_zeros() as usize;
+                let word = self.bits[i4096][0];
+                return ((i4096 << 12) | (word_num << 6)) as i32 + word.trailing_zeros() as i32;
+            }
+        }
+        NO_MORE_DOCS
+    }
+}
+
+impl ImmutableBitSet for SparseFixedBitSet {
+    fn cardinality(&self) -> usize {
+        self.bits
+            .iter()
+            .flat_map(|words| words.iter())
+            .map(|w| w.count_ones() as usize)
+            .sum()
+    }
+
+    fn next_set_bit(&self, i: usize) -> i32 {
+        debug_assert!(i < self.length);
+        let i4096 = i >> 12;
+        let index = self.indices[i4096];
+        let words = &self.bits[i4096];
+        let mut word_num = (i >> 6) & 0x3f;
+        let mut o = Self::word_offset(index, word_num);
+        if index & (1u64 << word_num) != 0 {
+            // the word that contains `i` is not empty, check the bits after it
+            let word = words[o] >> (i & 0x3f);
+            if word != 0 {
+                return (i + word.trailing_zeros() as usize) as i32;
+            }
+            o += 1;
+        }
+        // the remaining non zero words of the block
+        let index_bits = (index >> word_num) >> 1;
+        if index_bits != 0 {
+            word_num += 1 + index_bits.trailing_zeros() as usize;
+            let word = words[o];
+            return ((i4096 << 12) | (word_num << 6)) as i32 + word.trailing_zeros() as i32;
+        }
+        self.first_doc_from_block(i4096 + 1)
+    }
+}
+
+impl BitSet for SparseFixedBitSet {
+    fn set(&mut self, i: usize) {
+        debug_assert!(i < self.length);
+        let i4096 = i >> 12;
+        let word_num = (i >> 6) & 0x3f;
+        let mask = 1u64 << (i & 0x3f);
+        let index = self.indices[i4096];
+        let o = Self::word_offset(index, word_num);
+        if index & (1u64 << word_num) != 0 {
+            self.bits[i4096][o] |= mask;
+        } else {
+            self.bits[i4096].insert(o, mask);
+            self.indices[i4096] = index | (1u64 << word_num);
+            self.non_zero_long_count += 1;
+        }
+    }
+
+    fn clear(&mut self, i: usize) {
+        debug_assert!(i < self.length);
+        let i4096 = i >> 12;
+        let word_num = (i >> 6) & 0x3f;
+        let index = self.indices[i4096];
+        if index & (1u64 << word_num) == 0 {
+            return;
+        }
+        let o = Self::word_offset(index, word_num);
+        let word = self.bits[i4096][o] & !(1u64 << (i & 0x3f));
+        if word != 0 {
+            self.bits[i4096][o] = word;
+        } else {
+            // keep only non zero words
+            self.bits[i4096].remove(o);
+            self.indices[i4096] = index & !(1u64 << word_num);
+            self.non_zero_long_count -= 1;
+        }
+    }
+
+    fn clear_batch(&mut self, start_index: usize, end_index: usize) {
+        debug_assert!(end_index <= self.length);
+        if start_index >= end_index {
+            return;
+        }
+        let mut doc = self.next_set_bit(start_index);
+        while doc != NO_MORE_DOCS && (doc as usize) < end_index {
+            self.clear(doc as usize);
+            if doc as usize + 1 >= self.length {
+                break;
+            }
+            doc = self.next_set_bit(doc as usize + 1);
+        }
+    }
+}
+
+impl Bits for SparseFixedBitSet {
+    fn get(&self, i: usize) -> Result<bool> {
+        debug_assert!(i < self.length);
+        let i4096 = i >> 12;
+        let word_num = (i >> 6) & 0x3f;
+        let index = self.indices[i4096];
+        if index & (1u64 << word_num) == 0 {
+            return Ok(false);
+        }
+        let word = self.bits[i4096][Self::word_offset(index, word_num)];
+        Ok(word & (1u64 << (i & 0x3f)) != 0)
+    }
+
+    fn len(&self) -> usize {
+        self.length
+    }
+
+    fn as_bit_set(&self) -> &dyn BitSet {
+        self
+    }
+
+    fn as_bit_set_mut(&mut self) -> &mut dyn BitSet {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::util::{BitSetIterator, FixedBitSet};
+
+    #[test]
+    fn test_matches_fixed_bit_set() {
+        let length = 20_000;
+        let mut sparse = SparseFixedBitSet::new(length);
+        let mut fixed = FixedBitSet::new(length);
+        // a few dense words, a run across a block boundary and scattered bits
+        let mut docs: Vec<usize> = (0..70).collect();
+        docs.extend(4090..4100);
+        docs.extend((5000..length).step_by(997));
+        docs.push(length - 1);
+        for &doc in &docs {
+            sparse.set(doc);
+            fixed.set(doc);
+        }
+        assert_eq!(sparse.cardinality(), fixed.cardinality());
+        for i in 0..length {
+            assert_eq!(sparse.get(i).unwrap(), fixed.get(i).unwrap(), "bit {}", i);
+            assert_eq!(sparse.next_set_bit(i), fixed.next_set_bit(i), "from {}", i);
+        }
+        let all: Vec<i32> = BitSetIterator::new(&sparse).collect();
+        assert_eq!(all, BitSetIterator::new(&fixed).collect::<Vec<i32>>());
+
+        sparse.clear(65);
+        fixed.clear(65);
+        sparse.clear_batch(0, 64);
+        fixed.clear_batch(0, 64);
+        sparse.clear_batch(4095, 4097);
+        fixed.clear_batch(4095, 4097);
+        // clearing an unset bit is a no-op
+        sparse.clear(3000);
+        assert_eq!(sparse.cardinality(), fixed.cardinality());
+        let all: Vec<i32> = BitSetIterator::new(&sparse).collect();
+        assert_eq!(all, BitSetIterator::new(&fixed).collect::<Vec<i32>>());
+        assert_eq!(sparse.next_set_bit(0), 64);
+    }
+
+    #[test]
+    fn test_empty_words_are_not_allocated() {
+        let length = 1 << 20;
+        let mut sparse = SparseFixedBitSet::new(length);
+        assert_eq!(sparse.next_set_bit(0), NO_MORE_DOCS);
+        let empty = sparse.approximate_ram_bytes_used();
+
+        // one bit every 1000, far below the 1/8 density a fixed bit set pays off at
+        for doc in (0..length).step_by(1000) {
+            sparse.set(doc);
+        }
+        assert_eq!(sparse.non_zero_long_count(), sparse.cardinality());
+        let used = sparse.approximate_ram_bytes_used();
+        assert!(used > empty);
+        let fixed_bytes = FixedBitSet::new(length).bits.capacity() * 8;
+        assert!(used < fixed_bytes / 2, "{} vs {}", used, fixed_bytes);
+
+        for doc in (0..length).step_by(1000) {
+            sparse.clear(doc);
+        }
+        assert_eq!(sparse.non_zero_long_count(), 0);
+        assert_eq!(sparse.cardinality(), 0);
+        assert_eq!(sparse.next_set_bit(0), NO_MORE_DOCS);
+    }
+}
@@ -197,6 +197,113 @@ impl serde::Serialize for VariantValue {
     }
 }
 
+/// Serializes a `VariantValue` in the externally tagged form (e.g. `{"Long": 1}`)
+/// that the derived `Deserialize` reads back, so the exact variant survives a round
+/// trip. The plain `Serialize` impl above writes bare values meant for display.
+pub struct TaggedVariantValue<'a>(pub &'a VariantValue);
+
+impl<'a> serde::Serialize for TaggedVariantValue<'a> {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        const NAME: &str = "VariantValue";
+        match *self.0 {
+            VariantValue::Bool(ref b) => serializer.serialize_newtype_variant(NAME, 0, "Bool", b),
+            VariantValue::Char(ref c) => serializer.serialize_newtype_variant(NAME, 1, "Char", c),
+            VariantValue::Short(ref s) => serializer.serialize_newtype_variant(NAME, 2, "Short", s),
+            VariantValue::Int(ref i) => serializer.serialize_newtype_variant(NAME, 3, "Int", i),
+            VariantValue::Long(ref l) => serializer.serialize_newtype_variant(NAME, 4, "Long", l),
+            VariantValue::Float(ref f) => serializer.serialize_newtype_variant(NAME, 5, "Float", f),
+            VariantValue::Double(ref d) => {
+                serializer.serialize_newtype_variant(NAME, 6, "Double", d)
+            }
+            VariantValue::VString(ref s) => {
+                serializer.serialize_newtype_variant(NAME, 7, "VString", s)
+            }
+            VariantValue::Binary(ref b) => {
+                serializer.serialize_newtype_variant(NAME, 8, "Binary", b)
+            }
+            VariantValue::Vec(ref v) => {
+                serializer.serialize_newtype_variant(NAME, 9, "Vec", &TaggedVariantValues(v))
+            }
+            VariantValue::Map(ref m) => {
+                let map = TaggedVariantValueMap(m);
+                serializer.serialize_newtype_variant(NAME, 10, "Map", &map)
+            }
+        }
+    }
+}
+
+/// Serializes a slice of `VariantValue`s, see `TaggedVariantValue`.
+pub struct TaggedVariantValues<'a>(pub &'a [VariantValue]);
+
+impl<'a> serde::Serialize for TaggedVariantValues<'a> {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for v in self.0 {
+            seq.serialize_element(&TaggedVariantValue(v))?;
+        }
+        seq.end()
+    }
+}
+
+struct TaggedVariantValueMap<'a>(&'a HashMap<String, VariantValue>);
+
+impl<'a> serde::Serialize for TaggedVariantValueMap<'a> {
+    fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (k, v) in self.0 {
+            map.serialize_entry(k, &TaggedVariantValue(v))?;
+        }
+        map.end()
+    }
+}
+
+/// Helpers for `#[serde(with = "...")]` on `Vec<VariantValue>` fields.
+pub mod tagged_variant_values {
+    use super::{TaggedVariantValues, VariantValue};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        values: &[VariantValue],
+        serializer: S,
+    ) -> ::std::result::Result<S::Ok, S::Error> {
+        TaggedVariantValues(values).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> ::std::result::Result<Vec<VariantValue>, D::Error> {
+        Vec::deserialize(deserializer)
+    }
+}
+
+/// Helpers for `#[serde(with = "...")]` on `Option<VariantValue>` fields.
+pub mod tagged_variant_value_option {
+    use super::{TaggedVariantValue, VariantValue};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        value: &Option<VariantValue>,
+        serializer: S,
+    ) -> ::std::result::Result<S::Ok, S::Error> {
+        value.as_ref().map(TaggedVariantValue).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> ::std::result::Result<Option<VariantValue>, D::Error> {
+        Option::deserialize(deserializer)
+    }
+}
+
 impl Hash for VariantValue {
     fn hash<H: Hasher>(&self, state: &mut H) {
         match *self {